@@ -0,0 +1,218 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Rendering of every scene of a directory, with an HTML page showing the results.
+
+use crate::utils::canvas::file::FileCanvas;
+use crate::utils::canvas::DrawCanvas;
+use crate::utils::result::{AppError, VoidAppResult};
+use log::{info, warn};
+use raytracer::ray_algorithm::strategy::StandardRenderStrategy;
+use raytracer::renderer::{render_scene, RenderConfiguration};
+use raytracer::scene::{Scene, SceneFormat};
+use raytracer::thumbnail::render_thumbnail;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+pub const INDEX_FILE: &str = "index.html";
+
+pub struct GalleryConfiguration {
+    pub scene_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub thumbnail_width: u32,
+    pub full_width: u32,
+}
+
+/// Result of the rendering of one scene file
+struct GalleryEntry {
+    name: String,
+    outcome: Result<f32, String>,
+}
+
+pub fn generate_gallery(config: &GalleryConfiguration) -> VoidAppResult {
+    let mut scene_files: Vec<PathBuf> = fs::read_dir(&config.scene_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| SceneFormat::from_path(path).is_some())
+        .collect();
+    if scene_files.is_empty() {
+        return Err(AppError::BadArgument(format!(
+            "No TOML, JSON or YAML scene in {}",
+            config.scene_dir.display()
+        )));
+    }
+    scene_files.sort();
+    fs::create_dir_all(&config.output_dir)?;
+
+    let mut entries = Vec::new();
+    for scene_file in &scene_files {
+        let name = scene_file
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        info!("Gallery: rendering {}", scene_file.display());
+        let instant_start = Instant::now();
+        let outcome = render_entry(config, scene_file, &name)
+            .map(|_| instant_start.elapsed().as_secs_f32())
+            .map_err(|err| err.to_string());
+        if let Err(err) = &outcome {
+            warn!("Gallery: {} not rendered: {}", scene_file.display(), err);
+        }
+        entries.push(GalleryEntry { name, outcome });
+    }
+
+    let index_path = config.output_dir.join(INDEX_FILE);
+    fs::write(&index_path, index_page(&entries))?;
+    let failures = entries.iter().filter(|e| e.outcome.is_err()).count();
+    info!(
+        "Gallery of {} scenes written to {}, {} failed",
+        entries.len(),
+        index_path.display(),
+        failures
+    );
+    Ok(())
+}
+
+fn render_entry(config: &GalleryConfiguration, scene_file: &Path, name: &str) -> VoidAppResult {
+    let scene_content = fs::read_to_string(scene_file)?;
+    let format = SceneFormat::from_path(scene_file).unwrap_or_default();
+    thumbnail_to_file(
+        &scene_content,
+        format,
+        config.thumbnail_width,
+        &config.output_dir.join(thumbnail_file(name)),
+    )?;
+    render_to_file(
+        &scene_content,
+        format,
+        config.full_width,
+        &config.output_dir.join(full_file(name)),
+    )
+}
+
+/// The scene is parsed for each size, rendering consuming it
+fn render_to_file(
+    scene_content: &str,
+    format: SceneFormat,
+    width: u32,
+    path: &Path,
+) -> VoidAppResult {
+    let scene = Scene::from_description(scene_content, format, None)?;
+    let height = (width as f64 / scene.camera.size_ratio()) as u32;
+    let mut canvas = FileCanvas::new(width, height, path, scene.config.srgb_output)?;
+    let config = RenderConfiguration {
+        canvas_width: width,
+        canvas_height: height,
+        render_strategy: Box::new(StandardRenderStrategy),
+        ..Default::default()
+    };
+    for pixel in render_scene(scene, config)? {
+        canvas.draw(pixel?)?;
+    }
+    canvas.save()?;
+    Ok(())
+}
+
+/// Thumbnail rendered with the fast settings of `render_thumbnail`, `width` pixels wide
+fn thumbnail_to_file(
+    scene_content: &str,
+    format: SceneFormat,
+    width: u32,
+    path: &Path,
+) -> VoidAppResult {
+    let scene = Scene::from_description(scene_content, format, None)?;
+    let size_ratio = scene.camera.size_ratio();
+    let size = match size_ratio >= 1.0 {
+        true => width,
+        false => (width as f64 / size_ratio) as u32,
+    };
+    let srgb_output = scene.config.srgb_output;
+    let frame = render_thumbnail(scene, size)?;
+    let mut canvas = FileCanvas::new(frame.width(), frame.height(), path, srgb_output)?;
+    for pixel in frame.pixels() {
+        canvas.draw(pixel.clone())?;
+    }
+    canvas.save()?;
+    Ok(())
+}
+
+fn thumbnail_file(name: &str) -> String {
+    format!("{}_thumbnail.png", name)
+}
+
+fn full_file(name: &str) -> String {
+    format!("{}.png", name)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn index_page(entries: &[GalleryEntry]) -> String {
+    let mut page = String::from(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>raytracer-rust gallery</title>
+<style>
+body { font-family: sans-serif; background: #222; color: #ddd; }
+figure { display: inline-block; margin: 1em; vertical-align: top; }
+figcaption { text-align: center; }
+.error { color: #e66; max-width: 20em; }
+</style>
+</head>
+<body>
+<h1>raytracer-rust gallery</h1>
+"#,
+    );
+    // Writing to a String cannot fail
+    for entry in entries {
+        let name = escape_html(&entry.name);
+        match &entry.outcome {
+            Ok(duration) => writeln!(
+                page,
+                r#"<figure><a href="{}"><img src="{}" alt="{}"></a><figcaption>{} ({:.1} s)</figcaption></figure>"#,
+                escape_html(&full_file(&entry.name)),
+                escape_html(&thumbnail_file(&entry.name)),
+                name,
+                name,
+                duration
+            )
+            .unwrap(),
+            Err(err) => writeln!(
+                page,
+                r#"<figure class="error"><figcaption>{}: {}</figcaption></figure>"#,
+                name,
+                escape_html(err)
+            )
+            .unwrap(),
+        }
+    }
+    page.push_str("</body>\n</html>\n");
+    page
+}
@@ -22,29 +22,55 @@ OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE.
 */
 
+mod gallery;
+mod serve;
+mod stitch;
 mod utils;
 
+use crate::gallery::GalleryConfiguration;
+use crate::serve::ServeConfiguration;
+use crate::utils::canvas::checkpoint::CheckpointCanvas;
+use crate::utils::canvas::file::FileCanvas;
 use crate::utils::canvas::none::NoCanvas;
 use crate::utils::canvas::sdl::WrapperCanvas;
 use crate::utils::canvas::DrawCanvas;
 use crate::utils::monitor::ProgressionMonitor;
-use crate::utils::monitor::{NoMonitor, TermMonitor};
-use crate::utils::result::{AppError, VoidAppResult};
-use log::info;
+use crate::utils::monitor::{JsonMonitor, LineMonitor, NoMonitor, TermMonitor};
+use crate::utils::notify::{CommandHook, Notifier, RenderSummary, WebhookHook};
+use crate::utils::result::{AppError, AppResult, VoidAppResult};
+use log::{info, warn};
+use raytracer::accumulation::{camera_axes, TemporalAccumulator};
+use raytracer::animation::AnimationFrame;
+use raytracer::cameras::{MovedCamera, OrthogonalCamera};
+use raytracer::checkpoint::Checkpoint;
+use raytracer::depth_map::{DepthMap, LightProjection};
+use raytracer::interpolation::{interpolate_frame, Frame, MotionVectors};
+use raytracer::memory::format_bytes;
+use raytracer::panorama::PanoramaGrid;
 use raytracer::ray_algorithm::strategy::{
     RandomAntiAliasingRenderStrategy, StandardRenderStrategy,
 };
 use raytracer::ray_algorithm::AnyPixelRenderStrategy;
-use raytracer::renderer::{render_scene_with_finally, Pixel, RenderConfiguration};
-use raytracer::result::Result;
+use raytracer::renderer::{
+    render_environment, render_linear_pass, render_motion_vectors, render_scene,
+    render_scene_with_finally, resume_scene_with_finally, CancellationToken, Pixel,
+    RenderConfiguration, RendererKind, ToneMappingOperator, DEFAULT_TILE_SIZE,
+};
+use raytracer::result::{RaytracerError, Result};
+use raytracer::tiles::TileOrder;
+use raytracer::vector::Vec3;
 use sdl2::event::{Event, WindowEvent};
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Scancode};
 use sdl2::pixels::PixelFormatEnum;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use raytracer::scene::Scene;
+use raytracer::scene::{
+    dump_scene_description, scene_cache, RayEmitter, Scene, SceneDumpFormat, SceneFormat,
+};
 use simplelog::{Config, LevelFilter, TermLogger, TerminalMode};
 use std::fs;
+use std::path::Path;
 use std::str::FromStr;
 
 const APP_AUTHOR: &str = "Vincent Hiribarren";
@@ -54,16 +80,85 @@ const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 const ARG_FILE_INPUT: &str = "INPUT_FILE";
 const ARG_NO_STATUS: &str = "no-status";
+const ARG_PROGRESS: &str = "progress";
 const ARG_NO_GUI: &str = "no-gui";
 const ARG_NO_PROGRESSIVE: &str = "no-progressive";
 const ARG_NO_PARALLEL: &str = "no-parallel";
+const ARG_RENDERER: &str = "renderer";
+const ARG_TILE_SIZE: &str = "tile-size";
+const ARG_TILE_ORDER: &str = "tile-order";
 const ARG_STRATEGY_RANDOM: &str = "strategy-random";
 const ARG_WIDTH: &str = "width";
 const ARG_HEIGHT: &str = "height";
 const ARG_VERBOSE: &str = "verbose";
+const ARG_TRACE_PIXEL: &str = "trace-pixel";
+const ARG_TRACE_OUTPUT: &str = "trace-output";
+const ARG_OUTPUT: &str = "output";
+const ARG_NOTIFY_COMMAND: &str = "notify-command";
+const ARG_NOTIFY_URL: &str = "notify-url";
+const ARG_TONE_MAPPING: &str = "tone-mapping";
+const ARG_EXPOSURE: &str = "exposure";
+const ARG_FRAME: &str = "frame";
+const ARG_FRAMES: &str = "frames";
+const ARG_FPS: &str = "fps";
+const ARG_TIME: &str = "time";
+const ARG_SEED: &str = "seed";
+const ARG_HALF_RATE: &str = "half-rate";
+const ARG_INTERACTIVE: &str = "interactive";
+const ARG_HALF_PRECISION: &str = "half-precision";
+const ARG_MOTION_VECTORS: &str = "motion-vectors";
+const ARG_LIGHT_DEPTH_MAP: &str = "light-depth-map";
+const ARG_LIGHT: &str = "light";
+const ARG_LIGHT_PROJECTION: &str = "light-projection";
+const ARG_ENVIRONMENT_CAPTURE: &str = "environment-capture";
+const ARG_CAPTURE_POINT: &str = "capture-point";
+const ARG_GAMMA: &str = "gamma";
+const ARG_SAVE_INTERVAL: &str = "save-interval";
+const ARG_CACHE: &str = "cache";
+const ARG_CHECKPOINT: &str = "checkpoint";
+const ARG_RESUME: &str = "resume";
+const ARG_PANORAMA_GRID: &str = "panorama-grid";
+const ARG_PANORAMA_PART: &str = "panorama-part";
+
+const ARG_ERROR_FORMAT: &str = "error-format";
+const ARG_DUMP_SCENE: &str = "dump-scene";
+const ARG_VALIDATE: &str = "validate";
+const ARG_STATS: &str = "stats";
+
+const CMD_GALLERY: &str = "gallery";
+const ARG_GALLERY_SCENE_DIR: &str = "SCENE_DIR";
+const ARG_GALLERY_OUTPUT_DIR: &str = "OUTPUT_DIR";
+const ARG_GALLERY_THUMBNAIL_WIDTH: &str = "thumbnail-width";
+const ARG_GALLERY_FULL_WIDTH: &str = "full-width";
+const GALLERY_THUMBNAIL_WIDTH: u32 = 256;
+
+const CMD_STITCH: &str = "stitch";
+const ARG_STITCH_OUTPUT: &str = "OUTPUT";
+
+const CMD_SERVE: &str = "serve";
+const ARG_SERVE_QUEUE_DIR: &str = "QUEUE_DIR";
+const ARG_SERVE_JOBS: &str = "jobs";
+const SERVE_CONCURRENT_JOBS: usize = 2;
 
 const WINDOW_WIDTH: u32 = 800;
+/// Camera move of each key press in interactive mode, relative to the camera width
+const INTERACTIVE_STEP_RATIO: f64 = 0.05;
+/// Resolution of the interactive preview divided while the camera moves
+const INTERACTIVE_MOVING_DIVIDER: u32 = 4;
+/// Keys moving the camera in interactive mode, the preview staying at a low resolution
+/// while one of them is held
+const INTERACTIVE_MOVING_KEYS: [Scancode; 8] = [
+    Scancode::Up,
+    Scancode::W,
+    Scancode::Down,
+    Scancode::S,
+    Scancode::Right,
+    Scancode::D,
+    Scancode::Left,
+    Scancode::A,
+];
 const CANVAS_WIDTH: u32 = 1024;
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
 const SDL_WINDOW_CLEAR_COLOR: sdl2::pixels::Color = sdl2::pixels::Color {
     r: 77,
     g: 77,
@@ -71,15 +166,82 @@ const SDL_WINDOW_CLEAR_COLOR: sdl2::pixels::Color = sdl2::pixels::Color {
     a: 255,
 };
 
-fn main() -> VoidAppResult {
+fn main() {
     let matches = clap::App::new(APP_NAME)
         .author(APP_AUTHOR)
         .about(APP_ABOUT)
         .version(APP_VERSION)
+        .setting(clap::AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            clap::SubCommand::with_name(CMD_GALLERY)
+                .about("Render every scene of a directory, with an HTML index page of the images.")
+                .arg(
+                    clap::Arg::with_name(ARG_GALLERY_SCENE_DIR)
+                        .required(true)
+                        .help("Directory of the TOML scene files."),
+                )
+                .arg(
+                    clap::Arg::with_name(ARG_GALLERY_OUTPUT_DIR)
+                        .required(true)
+                        .help("Directory receiving the images and the index page."),
+                )
+                .arg(
+                    clap::Arg::with_name(ARG_GALLERY_THUMBNAIL_WIDTH)
+                        .long("thumbnail-width")
+                        .takes_value(true)
+                        .help(
+                            format!("Thumbnail width, default: {}.", GALLERY_THUMBNAIL_WIDTH)
+                                .as_str(),
+                        ),
+                )
+                .arg(
+                    clap::Arg::with_name(ARG_GALLERY_FULL_WIDTH)
+                        .long("full-width")
+                        .takes_value(true)
+                        .help(format!("Full image width, default: {}.", CANVAS_WIDTH).as_str()),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name(CMD_STITCH)
+                .about("Assemble the parts of a panorama rendered apart into the whole image.")
+                .arg(
+                    clap::Arg::with_name(ARG_STITCH_OUTPUT)
+                        .required(true)
+                        .help("PNG image the parts were rendered for, they being read next to it."),
+                )
+                .arg(
+                    clap::Arg::with_name(ARG_PANORAMA_GRID)
+                        .long("panorama-grid")
+                        .value_name("COLUMNSxROWS")
+                        .required(true)
+                        .help("Columns and rows of parts the panorama was cut into."),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name(CMD_SERVE)
+                .about("Render the jobs dropped into a queue directory by priority, until stopped.")
+                .arg(
+                    clap::Arg::with_name(ARG_SERVE_QUEUE_DIR)
+                        .required(true)
+                        .help("Directory of the .job.json files, receiving their status.json."),
+                )
+                .arg(
+                    clap::Arg::with_name(ARG_SERVE_JOBS)
+                        .long("jobs")
+                        .takes_value(true)
+                        .help(
+                            format!(
+                                "Jobs rendered at the same time, default: {}.",
+                                SERVE_CONCURRENT_JOBS
+                            )
+                            .as_str(),
+                        ),
+                ),
+        )
         .arg(
             clap::Arg::with_name(ARG_FILE_INPUT)
                 .required(true)
-                .help("TOML file describing the scene."),
+                .help("TOML, JSON or YAML file describing the scene, told by its extension."),
         )
         .arg(
             clap::Arg::with_name(ARG_VERBOSE)
@@ -93,6 +255,14 @@ fn main() -> VoidAppResult {
                 .long("no-status")
                 .help("Do not display textual progressive bar (quicker)."),
         )
+        .arg(
+            clap::Arg::with_name(ARG_PROGRESS)
+                .long(ARG_PROGRESS)
+                .value_name("MODE")
+                .possible_values(&["bar", "lines", "json"])
+                .default_value("bar")
+                .help("Progress shown as a bar, or as text or JSON lines on stderr without TTY."),
+        )
         .arg(
             clap::Arg::with_name(ARG_NO_GUI)
                 .long("no-gui")
@@ -109,6 +279,31 @@ fn main() -> VoidAppResult {
                 .long("no-parallel")
                 .help("Do not use multithreading for parallel computation (slower)."),
         )
+        .arg(
+            clap::Arg::with_name(ARG_RENDERER)
+                .long(ARG_RENDERER)
+                .value_name("KIND")
+                .possible_values(&["sequential", "threaded", "tiled", "progressive"])
+                .default_value("tiled")
+                .help("Pixels rendered in order, by rows or tiles, or coarse first then refined."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_TILE_SIZE)
+                .long(ARG_TILE_SIZE)
+                .value_name("SIZE")
+                .help(&format!(
+                    "Side of the tiles in pixels, default: {}.",
+                    DEFAULT_TILE_SIZE
+                )),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_TILE_ORDER)
+                .long(ARG_TILE_ORDER)
+                .value_name("ORDER")
+                .possible_values(&["scanline", "spiral", "center-out", "hilbert"])
+                .default_value("center-out")
+                .help("Order the tiles are rendered in, from the top row or from the center."),
+        )
         .arg(
             clap::Arg::with_name(ARG_WIDTH)
                 .short("w")
@@ -131,8 +326,283 @@ fn main() -> VoidAppResult {
                 .value_name("RAY_COUNT")
                 .help("Average of RAY_COUNT random rays sent."),
         )
-        .get_matches();
+        .arg(
+            clap::Arg::with_name(ARG_TRACE_PIXEL)
+                .long("trace-pixel")
+                .value_name("X,Y")
+                .requires(ARG_TRACE_OUTPUT)
+                .help("Do not render, but export the rays launched for the canvas pixel X,Y."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_TRACE_OUTPUT)
+                .long("trace-output")
+                .value_name("FILE")
+                .requires(ARG_TRACE_PIXEL)
+                .help("File receiving the traced rays, as OBJ or JSON depending on its extension."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_OUTPUT)
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .conflicts_with(ARG_TRACE_PIXEL)
+                .help("Save the rendered image to FILE, as PNG or JPEG by extension."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_SAVE_INTERVAL)
+                .long("save-interval")
+                .value_name("DURATION")
+                .help("Also save the partial image or checkpoint every DURATION, like 90s or 5m."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_CHECKPOINT)
+                .long("checkpoint")
+                .value_name("FILE")
+                .conflicts_with_all(&[ARG_FRAMES, ARG_INTERACTIVE, ARG_TRACE_PIXEL, ARG_RESUME])
+                .help("Save the completed tiles to FILE each minute, to resume the rendering."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_RESUME)
+                .long("resume")
+                .value_name("FILE")
+                .conflicts_with_all(&[ARG_FRAMES, ARG_INTERACTIVE, ARG_TRACE_PIXEL])
+                .help("Continue the rendering saved in the checkpoint FILE, still saving to it."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_NOTIFY_COMMAND)
+                .long("notify-command")
+                .value_name("COMMAND")
+                .conflicts_with(ARG_TRACE_PIXEL)
+                .help("Run COMMAND when rendering ends, with RAYTRACER_* environment variables."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_NOTIFY_URL)
+                .long("notify-url")
+                .value_name("URL")
+                .conflicts_with(ARG_TRACE_PIXEL)
+                .help("POST a JSON summary to URL when rendering ends."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_TONE_MAPPING)
+                .long("tone-mapping")
+                .value_name("OPERATOR")
+                .possible_values(&["clamp", "reinhard", "aces"])
+                .help("Tone mapping operator, replacing the one of the scene."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_EXPOSURE)
+                .long("exposure")
+                .value_name("STOPS")
+                .allow_hyphen_values(true)
+                .help("Exposure before tone mapping, replacing the one of the scene."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_FRAME)
+                .long("frame")
+                .value_name("FRAME")
+                .help("Animation frame to render, replacing the one of the scene."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_FRAMES)
+                .long("frames")
+                .value_name("COUNT")
+                .requires(ARG_OUTPUT)
+                .conflicts_with(ARG_SAVE_INTERVAL)
+                .help("Render COUNT animation frames from the first one, numbering the output."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_FPS)
+                .long(ARG_FPS)
+                .value_name("FPS")
+                .help("Frames per second of the animation, replacing those of the scene."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_TIME)
+                .long(ARG_TIME)
+                .value_name("SECONDS")
+                .allow_hyphen_values(true)
+                .conflicts_with(ARG_FRAMES)
+                .help("Time of the scene in seconds, replacing the one of the frame."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_SEED)
+                .long(ARG_SEED)
+                .value_name("SEED")
+                .help("Seed of the random samples, replacing the one of the scene."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_HALF_RATE)
+                .long("half-rate")
+                .requires(ARG_FRAMES)
+                .help("Only render every other frame, interpolating the frames in between."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_INTERACTIVE)
+                .long(ARG_INTERACTIVE)
+                .conflicts_with_all(&[ARG_NO_GUI, ARG_FRAMES, ARG_OUTPUT, ARG_TRACE_PIXEL])
+                .help("Preview moving the camera with the arrow keys, the samples reused"),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_HALF_PRECISION)
+                .long(ARG_HALF_PRECISION)
+                .requires(ARG_INTERACTIVE)
+                .help("Keep the samples of the preview as half-precision floats, halving their memory"),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_MOTION_VECTORS)
+                .long("motion-vectors")
+                .value_name("FILE")
+                .conflicts_with(ARG_TRACE_PIXEL)
+                .help("Save the motion of the pixels since the last frame to FILE, as PFM."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_LIGHT_DEPTH_MAP)
+                .long("light-depth-map")
+                .value_name("FILE")
+                .conflicts_with_all(&[ARG_FRAMES, ARG_INTERACTIVE, ARG_TRACE_PIXEL, ARG_OUTPUT])
+                .help("Do not render, but save the depth seen from a light to FILE, as EXR."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_LIGHT)
+                .long("light")
+                .value_name("INDEX")
+                .default_value("0")
+                .requires(ARG_LIGHT_DEPTH_MAP)
+                .help("Light of the depth map, by its position among the lights of the scene."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_LIGHT_PROJECTION)
+                .long("light-projection")
+                .value_name("PROJECTION")
+                .possible_values(&["orthographic", "perspective"])
+                .default_value("orthographic")
+                .requires(ARG_LIGHT_DEPTH_MAP)
+                .help("Parallel rays of the depth map, or rays spreading from the light."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_ENVIRONMENT_CAPTURE)
+                .long("environment-capture")
+                .value_name("FILE")
+                .requires(ARG_CAPTURE_POINT)
+                .conflicts_with_all(&[
+                    ARG_FRAMES,
+                    ARG_INTERACTIVE,
+                    ARG_TRACE_PIXEL,
+                    ARG_OUTPUT,
+                    ARG_LIGHT_DEPTH_MAP,
+                ])
+                .help("Do not render, but save the scene seen all around a point to FILE, as HDR."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_CAPTURE_POINT)
+                .long("capture-point")
+                .value_name("X,Y,Z")
+                .allow_hyphen_values(true)
+                .requires(ARG_ENVIRONMENT_CAPTURE)
+                .help("Point the environment is captured from."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_CACHE)
+                .long("cache")
+                .value_name("FILE")
+                .help("Load the scene from the cache FILE, writing it first if missing or stale."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_GAMMA)
+                .long("gamma")
+                .value_name("GAMMA")
+                .help("Encoding gamma after tone mapping, replacing the one of the scene."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_PANORAMA_GRID)
+                .long("panorama-grid")
+                .value_name("COLUMNSxROWS")
+                .requires(ARG_PANORAMA_PART)
+                .help("Cut the canvas into COLUMNSxROWS parts, rendered apart then stitched."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_PANORAMA_PART)
+                .long("panorama-part")
+                .value_name("COLUMN,ROW")
+                .requires_all(&[ARG_PANORAMA_GRID, ARG_OUTPUT])
+                .conflicts_with_all(&[
+                    ARG_FRAMES,
+                    ARG_INTERACTIVE,
+                    ARG_TRACE_PIXEL,
+                    ARG_MOTION_VECTORS,
+                    ARG_LIGHT_DEPTH_MAP,
+                    ARG_ENVIRONMENT_CAPTURE,
+                ])
+                .help("Only render this part of the grid, to the output file numbered by it."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_ERROR_FORMAT)
+                .long("error-format")
+                .value_name("FORMAT")
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .help("Format of the error written on stderr, json giving one object."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_DUMP_SCENE)
+                .long("dump-scene")
+                .value_name("FORMAT")
+                .possible_values(&["toml", "json"])
+                .help(
+                    "Print the scene with its default values filled in, instead of rendering it.",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_VALIDATE)
+                .long("validate")
+                .help("Print every error of the scene description, instead of rendering it."),
+        )
+        .arg(
+            clap::Arg::with_name(ARG_STATS)
+                .long("stats")
+                .help("Print the rays, BVH node visits and triangle tests of the rendering."),
+        )
+        .get_matches_safe();
+
+    let matches = match matches {
+        Ok(matches) => matches,
+        // Help and version are not errors
+        Err(err) if !err.use_stderr() => err.exit(),
+        Err(err) if json_errors_requested() => {
+            let summary = err.message.lines().next().unwrap_or_default();
+            let summary = summary.trim_start_matches("error: ").to_string();
+            exit_with_error(&AppError::BadArgument(summary), true)
+        }
+        Err(err) => {
+            eprintln!("{}", err.message);
+            std::process::exit(AppError::BadArgument(String::new()).exit_code())
+        }
+    };
+    let json_errors = matches.value_of(ARG_ERROR_FORMAT) == Some("json");
+    if let Err(err) = run(&matches) {
+        exit_with_error(&err, json_errors);
+    }
+}
+
+/// Arguments are checked without clap, which could not parse them
+fn json_errors_requested() -> bool {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter().any(|arg| arg == "--error-format=json")
+        || args
+            .windows(2)
+            .any(|pair| pair[0] == "--error-format" && pair[1] == "json")
+}
 
+fn exit_with_error(err: &AppError, json: bool) -> ! {
+    if json {
+        eprintln!("{}", err.to_json());
+    } else {
+        eprintln!("Error: {}", err);
+    }
+    std::process::exit(err.exit_code())
+}
+
+fn run(matches: &clap::ArgMatches) -> VoidAppResult {
     // Log level
     let log_level = match matches.occurrences_of(ARG_VERBOSE) {
         0 => LevelFilter::Info,
@@ -142,11 +612,120 @@ fn main() -> VoidAppResult {
     TermLogger::init(log_level, Config::default(), TerminalMode::Mixed)
         .expect("Error while initializing logger");
 
+    // Golden scenes rendered one after the other
+    if let Some(gallery_matches) = matches.subcommand_matches(CMD_GALLERY) {
+        let parse_width = |arg: &str, default: u32| match gallery_matches.value_of(arg) {
+            Some(width) => width.parse::<u32>().map_err(|e| {
+                AppError::BadArgument(format!("Error when parsing {} value: {}", arg, e))
+            }),
+            None => Ok(default),
+        };
+        let config = GalleryConfiguration {
+            scene_dir: gallery_matches
+                .value_of(ARG_GALLERY_SCENE_DIR)
+                .unwrap()
+                .into(),
+            output_dir: gallery_matches
+                .value_of(ARG_GALLERY_OUTPUT_DIR)
+                .unwrap()
+                .into(),
+            thumbnail_width: parse_width(ARG_GALLERY_THUMBNAIL_WIDTH, GALLERY_THUMBNAIL_WIDTH)?,
+            full_width: parse_width(ARG_GALLERY_FULL_WIDTH, CANVAS_WIDTH)?,
+        };
+        return gallery::generate_gallery(&config);
+    }
+
+    // Panorama parts assembled once they are all rendered
+    if let Some(stitch_matches) = matches.subcommand_matches(CMD_STITCH) {
+        let output = stitch_matches.value_of(ARG_STITCH_OUTPUT).unwrap();
+        let (columns, rows) = panorama_grid(stitch_matches.value_of(ARG_PANORAMA_GRID).unwrap())?;
+        return stitch::stitch_panorama(output, columns, rows);
+    }
+
+    // Jobs of a team rendered as they come, until the process is stopped
+    if let Some(serve_matches) = matches.subcommand_matches(CMD_SERVE) {
+        let concurrent_jobs = match serve_matches.value_of(ARG_SERVE_JOBS) {
+            Some(jobs) => jobs.parse::<usize>().map_err(|e| {
+                AppError::BadArgument(format!(
+                    "Error when parsing {} value: {}",
+                    ARG_SERVE_JOBS, e
+                ))
+            })?,
+            None => SERVE_CONCURRENT_JOBS,
+        };
+        let config = ServeConfiguration {
+            queue_dir: serve_matches.value_of(ARG_SERVE_QUEUE_DIR).unwrap().into(),
+            concurrent_jobs,
+        };
+        return serve::serve(&config);
+    }
+
+    // Scene description written back instead of being rendered
+    if let Some(format) = matches.value_of(ARG_DUMP_SCENE) {
+        let format = SceneDumpFormat::from_str(format).map_err(AppError::BadArgument)?;
+        let scene_file = matches.value_of(ARG_FILE_INPUT).unwrap();
+        let scene_content = fs::read_to_string(scene_file)?;
+        let scene_format = scene_format(scene_file);
+        println!(
+            "{}",
+            dump_scene_description(&scene_content, scene_format, format)?
+        );
+        return Ok(());
+    }
+
+    // Scene description checked instead of being rendered
+    if matches.is_present(ARG_VALIDATE) {
+        let scene_file = matches.value_of(ARG_FILE_INPUT).unwrap();
+        let scene_content = fs::read_to_string(scene_file)?;
+        let errors = Scene::validate(&scene_content, scene_format(scene_file));
+        for error in &errors {
+            println!("{}", error);
+        }
+        return match errors.len() {
+            0 => Ok(()),
+            count => Err(AppError::EngineError(RaytracerError::ParsingError(
+                format!("{} errors in the scene description", count),
+            ))),
+        };
+    }
+
+    // Hooks called when the rendering is done or has failed
+    let mut notifier = Notifier::default();
+    if let Some(command) = matches.value_of(ARG_NOTIFY_COMMAND) {
+        notifier.add_hook(Box::new(CommandHook(command.to_string())));
+    }
+    if let Some(url) = matches.value_of(ARG_NOTIFY_URL) {
+        notifier.add_hook(Box::new(WebhookHook(url.to_string())));
+    }
+
+    let instant_start = Instant::now();
+    let result = render(matches, &notifier, instant_start);
+    if let Err(err) = &result {
+        notifier.notify(&RenderSummary {
+            scene_file: matches.value_of(ARG_FILE_INPUT).unwrap().to_string(),
+            canvas_size: None,
+            duration: instant_start.elapsed(),
+            error: Some(err.to_string()),
+        });
+    }
+    result
+}
+
+fn render(
+    matches: &clap::ArgMatches,
+    notifier: &Notifier,
+    instant_start: Instant,
+) -> VoidAppResult {
     // Generate scene to render
-    let scene = {
-        let scene_content = fs::read_to_string(matches.value_of(ARG_FILE_INPUT).unwrap())?;
-        Scene::from_str(&scene_content)?
+    let scene_file = matches.value_of(ARG_FILE_INPUT).unwrap();
+    let frame = match matches.value_of(ARG_FRAME) {
+        Some(frame) => Some(frame.parse().map_err(|e| {
+            AppError::BadArgument(format!("Error when parsing frame value: {}", e))
+        })?),
+        None => None,
     };
+    let scene = load_scene(matches, scene_file, frame)?;
+    let loading_duration = instant_start.elapsed();
 
     // Camera ratio
     let camera_ratio = scene.camera.size_ratio();
@@ -171,80 +750,868 @@ fn main() -> VoidAppResult {
             }
         };
 
+    // Debug mode, only tracing the rays of one pixel
+    if let Some(pixel) = matches.value_of(ARG_TRACE_PIXEL) {
+        let output = matches.value_of(ARG_TRACE_OUTPUT).unwrap();
+        return trace_pixel(&scene, pixel, output, canvas_width, canvas_height);
+    }
+
+    // Animation, only written to image files
+    if let Some(frame_count) = matches.value_of(ARG_FRAMES) {
+        let frame_count = frame_count
+            .parse()
+            .map_err(|e| AppError::BadArgument(format!("Error when parsing frame count: {}", e)))?;
+        let canvas_size = (canvas_width, canvas_height);
+        return render_sequence(
+            matches,
+            scene,
+            frame_count,
+            canvas_size,
+            notifier,
+            instant_start,
+        );
+    }
+
+    // Shadow baking, only the depth seen from a light
+    if let Some(depth_file) = matches.value_of(ARG_LIGHT_DEPTH_MAP) {
+        return save_light_depth_map(matches, &scene, depth_file, canvas_width);
+    }
+
+    // HDRI of the scene, only the light seen all around a point
+    if let Some(capture_file) = matches.value_of(ARG_ENVIRONMENT_CAPTURE) {
+        let point = matches.value_of(ARG_CAPTURE_POINT).unwrap();
+        return save_environment_capture(matches, scene, point, capture_file, canvas_width);
+    }
+
+    // Preview with a moving camera
+    if matches.is_present(ARG_INTERACTIVE) {
+        let half_precision = matches.is_present(ARG_HALF_PRECISION);
+        return render_interactive(scene, canvas_width, canvas_height, half_precision);
+    }
+
+    // Part of a panorama too large to be rendered at once, written to its own file
+    let (scene, canvas_width, canvas_height, output) = match matches.value_of(ARG_PANORAMA_PART) {
+        Some(part) => {
+            let (columns, rows) = panorama_grid(matches.value_of(ARG_PANORAMA_GRID).unwrap())?;
+            let grid = PanoramaGrid::new(canvas_width, canvas_height, columns, rows)?;
+            let (column, row) = panorama_part(part)?;
+            let tile = grid.part(column, row)?;
+            info!(
+                "Part {},{} of the {}x{} panorama, at {},{}",
+                column, row, canvas_width, canvas_height, tile.x, tile.y
+            );
+            let output = stitch::part_file(matches.value_of(ARG_OUTPUT).unwrap(), column, row);
+            let scene = grid.part_scene(scene, column, row)?;
+            (scene, tile.width, tile.height, Some(output))
+        }
+        None => {
+            let output = matches.value_of(ARG_OUTPUT).map(String::from);
+            (scene, canvas_width, canvas_height, output)
+        }
+    };
+
+    // Computed before the rendering takes the scene
+    if let Some(motion_file) = matches.value_of(ARG_MOTION_VECTORS) {
+        let canvas_size = (canvas_width, canvas_height);
+        save_motion_vectors(matches, &scene, motion_file, canvas_size)?;
+    }
+
     // Ray casting strategy
-    let render_strategy: Box<dyn AnyPixelRenderStrategy> =
-        if let Some(strategy) = matches.value_of(ARG_STRATEGY_RANDOM) {
-            let rays_per_pixel: u32 = strategy.parse().map_err(|e| {
-                AppError::BadArgument(format!("Error when parsing strategy value: {}", e))
-            })?;
-            Box::new(RandomAntiAliasingRenderStrategy { rays_per_pixel })
-        } else {
-            Box::new(StandardRenderStrategy)
-        };
+    let render_strategy = render_strategy(matches)?;
 
-    // Terminal progress bar
-    let monitor: Box<dyn ProgressionMonitor> = if matches.is_present(ARG_NO_STATUS) {
-        Box::new(NoMonitor)
-    } else {
-        Box::new(TermMonitor::new((canvas_height * canvas_width) as u64))
+    let srgb_output = scene.config.srgb_output;
+    let save_interval = match matches.value_of(ARG_SAVE_INTERVAL) {
+        Some(interval) => Some(parse_duration(interval)?),
+        None => None,
     };
+    // Image file, checked before rendering so a wrong extension is known early
+    let file_canvas = match output {
+        Some(output) => {
+            let file_canvas = FileCanvas::new(canvas_width, canvas_height, output, srgb_output)?;
+            Some(match save_interval {
+                Some(interval) => file_canvas.with_save_interval(interval),
+                None => file_canvas,
+            })
+        }
+        None => None,
+    };
+
+    // Terminal progress bar, over the pixels given again by the progressive passes too
+    let renderer = renderer_kind(matches)?;
+    let (tile_size, tile_order) = tiling(matches)?;
+    let given_pixels = renderer.renderer().pixel_count(canvas_width, canvas_height);
+    let monitor = progress_monitor(matches, given_pixels);
 
     // Build options
     let config = RenderConfiguration {
         canvas_width,
         canvas_height,
         render_strategy,
+        renderer,
+        tile_size,
+        tile_order,
+        ..Default::default()
     };
+    let stats = config.stats.clone();
+    stats.add_phase("Loading", loading_duration);
+    let cancellation = config.cancellation.clone();
 
     info!("Camera ratio; {:.2}", camera_ratio);
     info!("Canvas size: {}x{}", canvas_width, canvas_height);
 
+    // Tiles of an interrupted rendering, kept with the new ones in the checkpoint file
+    let resumed = matches.is_present(ARG_RESUME);
+    let checkpoint_file = matches
+        .value_of(ARG_RESUME)
+        .or(matches.value_of(ARG_CHECKPOINT));
+    let tile_renderer = matches!(renderer, RendererKind::Sequential | RendererKind::Tiled);
+    if checkpoint_file.is_some() && !tile_renderer {
+        return Err(AppError::BadArgument(String::from(
+            "Checkpoints need the sequential or the tiled renderer",
+        )));
+    }
+    let checkpoint = match checkpoint_file {
+        Some(_) => {
+            let description = checkpoint_description(matches, scene_file)?;
+            Some(match matches.value_of(ARG_RESUME) {
+                Some(resume_file) => load_checkpoint(resume_file, &config, &description)?,
+                None => Checkpoint::new(&config, &description),
+            })
+        }
+        None => None,
+    };
+
     // Sequential or parallel computation
-    let render_iter =
-        render_scene_with_finally(scene, config, !matches.is_present(ARG_NO_PARALLEL), || {
-            monitor.clean()
-        })?;
+    let render_end = || {
+        monitor.clean();
+        notifier.notify(&RenderSummary {
+            scene_file: scene_file.to_string(),
+            canvas_size: Some((canvas_width, canvas_height)),
+            duration: instant_start.elapsed(),
+            error: None,
+        });
+    };
+    let render_iter: Box<dyn Iterator<Item = Result<Pixel>>> = match (&checkpoint, resumed) {
+        (Some(checkpoint), true) => Box::new(resume_scene_with_finally(
+            scene, config, checkpoint, render_end,
+        )?),
+        _ => Box::new(render_scene_with_finally(scene, config, render_end)?),
+    };
+    let render_iter = match (checkpoint, checkpoint_file) {
+        (Some(checkpoint), Some(path)) => {
+            let interval = save_interval.unwrap_or(CHECKPOINT_INTERVAL);
+            let mut canvas = CheckpointCanvas::new(path, checkpoint, interval);
+            Box::new(render_iter.inspect(move |pixel| {
+                if let Ok(pixel) = pixel {
+                    let _ = canvas.draw(pixel.clone());
+                }
+            }))
+        }
+        _ => render_iter,
+    };
 
     // Launch the computation / rendering
     if matches.is_present(ARG_NO_GUI) {
-        render_no_gui(render_iter, &monitor)?;
+        render_no_gui(render_iter, &monitor, file_canvas)?;
     } else {
         let progressive_rendering = !matches.is_present(ARG_NO_PROGRESSIVE);
         render_sdl(
             render_iter,
             &monitor,
+            file_canvas,
             canvas_width,
             canvas_height,
             camera_ratio,
             progressive_rendering,
+            srgb_output,
+            cancellation,
         )?;
     }
 
+    if matches.is_present(ARG_STATS) {
+        print!("{}", stats.snapshot());
+    }
+    Ok(())
+}
+
+/// What the rendered pixels depend on, apart from the canvas size: a checkpoint is only
+/// resumed with the same scene description and command line options
+fn checkpoint_description(matches: &clap::ArgMatches, scene_file: &str) -> AppResult<String> {
+    let mut description = fs::read_to_string(scene_file)?;
+    let options = [
+        ARG_FRAME,
+        ARG_FPS,
+        ARG_TIME,
+        ARG_SEED,
+        ARG_PANORAMA_GRID,
+        ARG_PANORAMA_PART,
+        ARG_STRATEGY_RANDOM,
+        ARG_TONE_MAPPING,
+        ARG_EXPOSURE,
+        ARG_GAMMA,
+    ];
+    for option in options.iter() {
+        if let Some(value) = matches.value_of(option) {
+            description.push_str(&format!("\n--{} {}", option, value));
+        }
+    }
+    Ok(description)
+}
+
+/// Checkpoint of an interrupted rendering, refused if made for another one
+fn load_checkpoint(
+    path: &str,
+    config: &RenderConfiguration,
+    description: &str,
+) -> AppResult<Checkpoint> {
+    let checkpoint = Checkpoint::decode(&fs::read(path)?)?;
+    if !checkpoint.is_for(config, description) {
+        return Err(AppError::BadArgument(format!(
+            "Checkpoint {} was made for another scene, canvas or options",
+            path
+        )));
+    }
+    Ok(checkpoint)
+}
+
+/// Scene of the input file at an animation frame, with the tone mapping given on the
+/// command line
+fn load_scene(
+    matches: &clap::ArgMatches,
+    scene_file: &str,
+    frame: Option<u32>,
+) -> AppResult<Scene> {
+    let scene_content = fs::read_to_string(scene_file)?;
+    let format = scene_format(scene_file);
+    let frame = match matches.value_of(ARG_FPS) {
+        Some(fps) => {
+            let fps: f64 = fps.parse().map_err(|e| {
+                AppError::BadArgument(format!("Error when parsing frames per second: {}", e))
+            })?;
+            if !(fps.is_finite() && fps > 0.0) {
+                return Err(AppError::BadArgument(format!(
+                    "Frames per second must be a positive number, not {}",
+                    fps
+                )));
+            }
+            AnimationFrame::from(frame).with_frames_per_second(fps)
+        }
+        None => AnimationFrame::from(frame),
+    };
+    let frame =
+        match matches.value_of(ARG_TIME) {
+            Some(time) => frame.with_time(time.parse().map_err(|e| {
+                AppError::BadArgument(format!("Error when parsing time value: {}", e))
+            })?),
+            None => frame,
+        };
+    let mut scene = match matches.value_of(ARG_CACHE) {
+        Some(cache_file) => load_cached_scene(cache_file, &scene_content, format, frame)?,
+        None => Scene::from_description(&scene_content, format, frame)?,
+    };
+    if !scene.warnings.is_empty() {
+        warn!("Scene loaded with {} warning(s)", scene.warnings.len());
+    }
+    if let Some(seed) = matches.value_of(ARG_SEED) {
+        scene.config.seed = seed
+            .parse()
+            .map_err(|e| AppError::BadArgument(format!("Error when parsing seed value: {}", e)))?;
+    }
+
+    let tone_mapping = &mut scene.config.tone_mapping;
+    if let Some(operator) = matches.value_of(ARG_TONE_MAPPING) {
+        tone_mapping.operator =
+            ToneMappingOperator::from_str(operator).map_err(AppError::BadArgument)?;
+    }
+    if let Some(exposure) = matches.value_of(ARG_EXPOSURE) {
+        tone_mapping.exposure = exposure.parse().map_err(|e| {
+            AppError::BadArgument(format!("Error when parsing exposure value: {}", e))
+        })?;
+    }
+    if let Some(gamma) = matches.value_of(ARG_GAMMA) {
+        tone_mapping.gamma = parse_gamma(gamma)?;
+    }
+    Ok(scene)
+}
+
+/// Ray casting strategy, built for each rendering as the configuration owns it
+fn render_strategy(matches: &clap::ArgMatches) -> AppResult<Box<dyn AnyPixelRenderStrategy>> {
+    Ok(match matches.value_of(ARG_STRATEGY_RANDOM) {
+        Some(strategy) => {
+            let rays_per_pixel: u32 = strategy.parse().map_err(|e| {
+                AppError::BadArgument(format!("Error when parsing strategy value: {}", e))
+            })?;
+            Box::new(RandomAntiAliasingRenderStrategy { rays_per_pixel })
+        }
+        None => Box::new(StandardRenderStrategy),
+    })
+}
+
+/// Renderer chosen on the command line, sequential without multithreading
+fn renderer_kind(matches: &clap::ArgMatches) -> AppResult<RendererKind> {
+    if matches.is_present(ARG_NO_PARALLEL) {
+        return Ok(RendererKind::Sequential);
+    }
+    matches
+        .value_of(ARG_RENDERER)
+        .unwrap_or_default()
+        .parse()
+        .map_err(AppError::BadArgument)
+}
+
+/// Side and order of the tiles chosen on the command line
+fn tiling(matches: &clap::ArgMatches) -> AppResult<(u32, TileOrder)> {
+    let tile_size = match matches.value_of(ARG_TILE_SIZE) {
+        Some(size) => size
+            .parse()
+            .map_err(|e| AppError::BadArgument(format!("Error when parsing tile size: {}", e)))?,
+        None => DEFAULT_TILE_SIZE,
+    };
+    let tile_order = matches
+        .value_of(ARG_TILE_ORDER)
+        .unwrap_or_default()
+        .parse()
+        .map_err(AppError::BadArgument)?;
+    Ok((tile_size, tile_order))
+}
+
+/// Render the frames of an animation from the one of `first_scene`, each saved to the
+/// output file numbered by its frame. At half rate, every other frame is interpolated
+/// from the two rendered around it, the last one being always rendered.
+fn render_sequence(
+    matches: &clap::ArgMatches,
+    first_scene: Scene,
+    frame_count: u32,
+    (canvas_width, canvas_height): (u32, u32),
+    notifier: &Notifier,
+    instant_start: Instant,
+) -> VoidAppResult {
+    let scene_file = matches.value_of(ARG_FILE_INPUT).unwrap();
+    let output = matches.value_of(ARG_OUTPUT).unwrap();
+    let half_rate = matches.is_present(ARG_HALF_RATE);
+    let renderer = renderer_kind(matches)?;
+    let (tile_size, tile_order) = tiling(matches)?;
+    let first_frame = first_scene.config.frame;
+    let is_rendered = |index: u32| !half_rate || index % 2 != 1 || index + 1 == frame_count;
+    let rendered: Vec<u32> = (0..frame_count)
+        .filter(|&index| is_rendered(index))
+        .collect();
+    let rendered_count = rendered.len();
+    // Checked before rendering so a wrong extension is known early
+    FileCanvas::new(canvas_width, canvas_height, output, false)?;
+
+    let frame_pixels = renderer.renderer().pixel_count(canvas_width, canvas_height);
+    let monitor = progress_monitor(matches, frame_pixels * rendered_count as u64);
+    info!(
+        "Rendering {} of {} frames from frame {}",
+        rendered_count, frame_count, first_frame
+    );
+
+    // The next rendered scene is loaded ahead, to follow the motion toward it of the
+    // pixels of the frame before an interpolated one
+    let mut next_scene = Some(first_scene);
+    let mut before_interpolated: Option<(Frame, MotionVectors)> = None;
+    for (position, &index) in rendered.iter().enumerate() {
+        let frame_number = first_frame + index;
+        let scene = next_scene.take().unwrap();
+        next_scene = match rendered.get(position + 1) {
+            Some(&next_index) => Some(load_scene(
+                matches,
+                scene_file,
+                Some(first_frame + next_index),
+            )?),
+            None => None,
+        };
+        let motion = match &next_scene {
+            Some(next_scene) if !is_rendered(index + 1) => Some(render_motion_vectors(
+                &scene,
+                next_scene,
+                canvas_width,
+                canvas_height,
+            )),
+            _ => None,
+        };
+        if let Some(motion_file) = matches.value_of(ARG_MOTION_VECTORS) {
+            let motion_file = sequence_file(motion_file, frame_number);
+            save_motion_vectors(matches, &scene, &motion_file, (canvas_width, canvas_height))?;
+        }
+
+        let srgb_output = scene.config.srgb_output;
+        let config = RenderConfiguration {
+            canvas_width,
+            canvas_height,
+            render_strategy: render_strategy(matches)?,
+            renderer,
+            tile_size,
+            tile_order,
+            ..Default::default()
+        };
+        let pixels = render_scene(scene, config)?.inspect(|_| monitor.update());
+        let frame = Frame::from_pixels(canvas_width, canvas_height, pixels)?;
+        if let Some((previous, motion)) = before_interpolated.take() {
+            let between = interpolate_frame(&previous, &frame, &motion, 0.5)?;
+            let between_file = sequence_file(output, frame_number - 1);
+            save_frame(&between, &between_file, srgb_output)?;
+        }
+        save_frame(&frame, &sequence_file(output, frame_number), srgb_output)?;
+        before_interpolated = motion.map(|motion| (frame, motion));
+    }
+    monitor.clean();
+
+    notifier.notify(&RenderSummary {
+        scene_file: scene_file.to_string(),
+        canvas_size: Some((canvas_width, canvas_height)),
+        duration: instant_start.elapsed(),
+        error: None,
+    });
+    Ok(())
+}
+
+/// Motion of each pixel of the scene since the previous frame, written as a PFM image
+fn save_motion_vectors(
+    matches: &clap::ArgMatches,
+    scene: &Scene,
+    path: &str,
+    (canvas_width, canvas_height): (u32, u32),
+) -> VoidAppResult {
+    let scene_file = matches.value_of(ARG_FILE_INPUT).unwrap();
+    let previous_frame = scene.config.frame.saturating_sub(1);
+    let previous_scene = load_scene(matches, scene_file, Some(previous_frame))?;
+    let motion = render_motion_vectors(scene, &previous_scene, canvas_width, canvas_height);
+    fs::write(path, motion.to_pfm())?;
+    info!("Motion vectors saved to {}", path);
+    Ok(())
+}
+
+/// Depth of the scene seen from the light chosen on the command line, written as an EXR
+/// image of `size` by `size` pixels
+fn save_light_depth_map(
+    matches: &clap::ArgMatches,
+    scene: &Scene,
+    path: &str,
+    size: u32,
+) -> VoidAppResult {
+    let index: usize = matches
+        .value_of(ARG_LIGHT)
+        .unwrap()
+        .parse()
+        .map_err(|e| AppError::BadArgument(format!("Error when parsing light index: {}", e)))?;
+    let light = scene.lights.get(index).ok_or_else(|| {
+        AppError::BadArgument(format!(
+            "Light {} does not exist, the scene has {} light(s)",
+            index,
+            scene.lights.len()
+        ))
+    })?;
+    let projection = LightProjection::from_str(matches.value_of(ARG_LIGHT_PROJECTION).unwrap())
+        .map_err(AppError::BadArgument)?;
+    let depth_map = DepthMap::from_light(scene, light.as_ref(), projection, size)?;
+    fs::write(path, depth_map.to_exr())?;
+    info!("Depth map of light {} saved to {}", index, path);
+    Ok(())
+}
+
+/// Scene seen all around the point given as X,Y,Z, written as a Radiance HDR environment
+/// map `width` pixels wide
+fn save_environment_capture(
+    matches: &clap::ArgMatches,
+    scene: Scene,
+    point: &str,
+    path: &str,
+    width: u32,
+) -> VoidAppResult {
+    let parse_error =
+        || AppError::BadArgument(format!("Point must be given as X,Y,Z, not: {}", point));
+    let coordinates = point
+        .split(',')
+        .map(|value| value.trim().parse::<f64>().map_err(|_| parse_error()))
+        .collect::<AppResult<Vec<_>>>()?;
+    let eye = match coordinates.as_slice() {
+        &[x, y, z] => Vec3::new(x, y, z),
+        _ => return Err(parse_error()),
+    };
+    let config = RenderConfiguration {
+        canvas_width: width,
+        canvas_height: (width / 2).max(1),
+        render_strategy: render_strategy(matches)?,
+        ..Default::default()
+    };
+    let environment = render_environment(scene, eye, &config)?;
+    fs::write(path, environment.to_hdr())?;
+    info!("Environment seen from {} saved to {}", point, path);
+    Ok(())
+}
+
+/// Columns and rows of a panorama grid, given as COLUMNSxROWS
+fn panorama_grid(grid: &str) -> AppResult<(u32, u32)> {
+    let parse_error = || {
+        AppError::BadArgument(format!(
+            "Panorama grid must be given as COLUMNSxROWS, not: {}",
+            grid
+        ))
+    };
+    match grid.split('x').collect::<Vec<_>>().as_slice() {
+        [columns, rows] => Ok((
+            columns.trim().parse().map_err(|_| parse_error())?,
+            rows.trim().parse().map_err(|_| parse_error())?,
+        )),
+        _ => Err(parse_error()),
+    }
+}
+
+/// Column and row of a panorama part, given as COLUMN,ROW
+fn panorama_part(part: &str) -> AppResult<(u32, u32)> {
+    let parse_error = || {
+        AppError::BadArgument(format!(
+            "Panorama part must be given as COLUMN,ROW, not: {}",
+            part
+        ))
+    };
+    match part.split(',').collect::<Vec<_>>().as_slice() {
+        [column, row] => Ok((
+            column.trim().parse().map_err(|_| parse_error())?,
+            row.trim().parse().map_err(|_| parse_error())?,
+        )),
+        _ => Err(parse_error()),
+    }
+}
+
+/// File of an animation frame, the output file with the frame number before its extension
+fn sequence_file(output: &str, frame: u32) -> String {
+    let path = Path::new(output);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{:04}.{}", stem, frame, extension.to_string_lossy()),
+        None => format!("{}_{:04}", stem, frame),
+    };
+    path.with_file_name(name).display().to_string()
+}
+
+fn save_frame(frame: &Frame, path: &str, srgb_output: bool) -> VoidAppResult {
+    let mut file_canvas = FileCanvas::new(frame.width(), frame.height(), path, srgb_output)?;
+    for pixel in frame.pixels() {
+        file_canvas.draw(pixel.clone())?;
+    }
+    file_canvas.save()?;
+    info!("Image saved to {}", path);
+    Ok(())
+}
+
+/// Format of a scene file given by its extension, TOML if it is not a known one
+fn scene_format(scene_file: &str) -> SceneFormat {
+    SceneFormat::from_path(scene_file).unwrap_or_default()
+}
+
+/// Scene of a cache file, the cache being written again when it cannot be used
+fn load_cached_scene(
+    cache_file: &str,
+    scene_content: &str,
+    format: SceneFormat,
+    frame: AnimationFrame,
+) -> Result<Scene> {
+    if let Ok(cache) = fs::read(cache_file) {
+        match Scene::from_cache(&cache, scene_content, frame) {
+            Ok(Some(scene)) => {
+                info!("Scene loaded from cache {}", cache_file);
+                return Ok(scene);
+            }
+            Ok(None) => info!("Cache {} is stale, writing it again", cache_file),
+            Err(err) => warn!("Cache {} ignored, {}", cache_file, err),
+        }
+    }
+    let cache = scene_cache(scene_content, format)?;
+    if let Err(err) = fs::write(cache_file, &cache) {
+        warn!("Cache {} not written, {}", cache_file, err);
+    }
+    match Scene::from_cache(&cache, scene_content, frame)? {
+        Some(scene) => Ok(scene),
+        // A file changed while the cache was written
+        None => Scene::from_description(scene_content, format, frame),
+    }
+}
+
+/// Monitor of the rendering progress chosen on the command line
+fn progress_monitor(matches: &clap::ArgMatches, total_pixels: u64) -> Box<dyn ProgressionMonitor> {
+    if matches.is_present(ARG_NO_STATUS) {
+        return Box::new(NoMonitor);
+    }
+    match matches.value_of(ARG_PROGRESS) {
+        Some("lines") => Box::new(LineMonitor::new(total_pixels)),
+        Some("json") => Box::new(JsonMonitor::new(total_pixels)),
+        _ => Box::new(TermMonitor::new(total_pixels)),
+    }
+}
+
+/// Duration in seconds, or with a s, m or h unit
+fn parse_duration(duration: &str) -> AppResult<Duration> {
+    let duration = duration.trim();
+    let (value, unit_seconds) = match duration.chars().last() {
+        Some('s') => (&duration[..duration.len() - 1], 1.0),
+        Some('m') => (&duration[..duration.len() - 1], 60.0),
+        Some('h') => (&duration[..duration.len() - 1], 3600.0),
+        _ => (duration, 1.0),
+    };
+    match value.trim().parse::<f64>() {
+        Ok(value) if value > 0.0 => Ok(Duration::from_secs_f64(value * unit_seconds)),
+        _ => Err(AppError::BadArgument(format!(
+            "Duration must be positive, like 90s, 5m or 1h, not: {}",
+            duration
+        ))),
+    }
+}
+
+/// Gamma of the tone mapping, a positive number
+fn parse_gamma(gamma: &str) -> AppResult<f64> {
+    let gamma: f64 = gamma
+        .trim()
+        .parse()
+        .map_err(|e| AppError::BadArgument(format!("Error when parsing gamma value: {}", e)))?;
+    if !(gamma.is_finite() && gamma > 0.0) {
+        return Err(AppError::BadArgument(format!(
+            "Gamma must be a positive number, not: {}",
+            gamma
+        )));
+    }
+    Ok(gamma)
+}
+
+fn trace_pixel(
+    scene: &Scene,
+    pixel: &str,
+    output: &str,
+    canvas_width: u32,
+    canvas_height: u32,
+) -> VoidAppResult {
+    let parse_error =
+        || AppError::BadArgument(format!("Pixel must be given as X,Y, not: {}", pixel));
+    let (x, y) = match pixel.split(',').collect::<Vec<_>>().as_slice() {
+        [x, y] => (
+            x.trim().parse::<u32>().map_err(|_| parse_error())?,
+            y.trim().parse::<u32>().map_err(|_| parse_error())?,
+        ),
+        _ => return Err(parse_error()),
+    };
+    if x >= canvas_width || y >= canvas_height {
+        return Err(AppError::BadArgument(format!(
+            "Pixel {},{} is outside of the {}x{} canvas",
+            x, y, canvas_width, canvas_height
+        )));
+    }
+    let canvas_x = (x as f64 + 0.5) / canvas_width as f64;
+    let canvas_y = (y as f64 + 0.5) / canvas_height as f64;
+    let ray_tree = raytracer::debug::trace_pixel(scene, canvas_x, canvas_y)?;
+    let content = if output.to_lowercase().ends_with(".json") {
+        ray_tree.to_json()
+    } else {
+        ray_tree.to_obj()
+    };
+    fs::write(output, content)?;
+    info!("{} rays exported to {}", ray_tree.rays.len(), output);
+    Ok(())
+}
+
+/// Draw the pixel on the canvas, and also on the image file if there is one
+fn draw_pixel(
+    canvas: &mut dyn DrawCanvas,
+    file_canvas: &mut Option<FileCanvas>,
+    pixel: Pixel,
+) -> VoidAppResult {
+    if let Some(file_canvas) = file_canvas {
+        file_canvas.draw(pixel.clone())?;
+    }
+    canvas.draw(pixel)?;
+    Ok(())
+}
+
+/// Save the image file once, when the rendering is done
+fn save_file_canvas(file_canvas: &mut Option<FileCanvas>) -> VoidAppResult {
+    if let Some(file_canvas) = file_canvas.take() {
+        let path = file_canvas.path().display().to_string();
+        file_canvas.save()?;
+        info!("Image saved to {}", path);
+    }
     Ok(())
 }
 
 fn render_no_gui<M: AsRef<dyn ProgressionMonitor>>(
     render_iter: impl Iterator<Item = Result<Pixel>>,
     monitor: M,
+    mut file_canvas: Option<FileCanvas>,
 ) -> VoidAppResult {
     let monitor = monitor.as_ref();
     let mut canvas = NoCanvas;
     for pixel in render_iter {
-        canvas.draw(pixel?)?;
+        draw_pixel(&mut canvas, &mut file_canvas, pixel?)?;
         monitor.update();
     }
+    save_file_canvas(&mut file_canvas)
+}
+
+/// Preview refined pass after pass, the camera moved with the arrow or WASD keys. The
+/// samples of the previous passes are reprojected after each move, so the preview keeps
+/// converging where the same points are still seen.
+fn render_interactive(
+    mut scene: Scene,
+    canvas_width: u32,
+    canvas_height: u32,
+    half_precision: bool,
+) -> VoidAppResult {
+    let camera_ratio = scene.camera.size_ratio();
+    let srgb_output = scene.config.srgb_output;
+    let config = RenderConfiguration {
+        canvas_width,
+        canvas_height,
+        render_strategy: Box::new(RandomAntiAliasingRenderStrategy { rays_per_pixel: 1 }),
+        ..Default::default()
+    };
+    let moving_width = (canvas_width / INTERACTIVE_MOVING_DIVIDER).max(1);
+    let moving_height = (canvas_height / INTERACTIVE_MOVING_DIVIDER).max(1);
+    let moving_config = RenderConfiguration {
+        canvas_width: moving_width,
+        canvas_height: moving_height,
+        render_strategy: Box::new(RandomAntiAliasingRenderStrategy { rays_per_pixel: 1 }),
+        ..Default::default()
+    };
+    let placeholder: Box<dyn RayEmitter> = Box::new(OrthogonalCamera::default());
+    let camera: Arc<dyn RayEmitter> = Arc::from(std::mem::replace(&mut scene.camera, placeholder));
+    let step = INTERACTIVE_STEP_RATIO * camera.width();
+    let (forward, right) = camera_axes(camera.as_ref());
+    let mut offset = Vec3::default();
+    // Where the camera was when the samples of the accumulator were gathered
+    let mut accumulated_offset = offset;
+    scene.camera = Box::new(MovedCamera::new(Arc::clone(&camera), offset));
+    let mut accumulator = TemporalAccumulator::new(canvas_width, canvas_height);
+    if half_precision {
+        accumulator = accumulator.with_half_precision();
+    }
+    info!(
+        "Preview samples memory: {}",
+        format_bytes(accumulator.memory_size())
+    );
+
+    let mut render_canvas =
+        sdl2::surface::Surface::new(canvas_width, canvas_height, PixelFormatEnum::RGBA32)
+            .map_err(AppError::SdlError)?
+            .into_canvas()
+            .map_err(AppError::SdlError)?;
+    let mut moving_canvas =
+        sdl2::surface::Surface::new(moving_width, moving_height, PixelFormatEnum::RGBA32)
+            .map_err(AppError::SdlError)?
+            .into_canvas()
+            .map_err(AppError::SdlError)?;
+    let sdl_context = sdl2::init().map_err(AppError::SdlError)?;
+    let video_subsystem = sdl_context.video().map_err(AppError::SdlError)?;
+    let window = video_subsystem
+        .window(
+            "RayTracer Test",
+            WINDOW_WIDTH,
+            (WINDOW_WIDTH as f64 / camera_ratio) as u32,
+        )
+        .position_centered()
+        .resizable()
+        .build()?;
+    let mut window_canvas = window.into_canvas().build()?;
+    window_canvas.set_logical_size(canvas_width, canvas_height)?;
+    window_canvas.set_draw_color(SDL_WINDOW_CLEAR_COLOR);
+    let texture_creator = window_canvas.texture_creator();
+
+    let mut event_pump = sdl_context.event_pump().map_err(AppError::SdlError)?;
+    let mut pass = 0;
+    'event_loop: loop {
+        let mut moved = Vec3::default();
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Window {
+                    win_event: WindowEvent::Resized(w, h),
+                    ..
+                } => {
+                    let (new_w, new_h) = if w as f64 / h as f64 > camera_ratio {
+                        (w as u32, (w as f64 / camera_ratio) as u32)
+                    } else {
+                        ((h as f64 * camera_ratio) as u32, h as u32)
+                    };
+                    window_canvas.window_mut().set_size(new_w, new_h)?
+                }
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'event_loop,
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => match keycode {
+                    Keycode::Up | Keycode::W => moved = moved + step * forward,
+                    Keycode::Down | Keycode::S => moved = moved - step * forward,
+                    Keycode::Right | Keycode::D => moved = moved + step * right,
+                    Keycode::Left | Keycode::A => moved = moved - step * right,
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        if moved.norm() > 0.0 {
+            offset = offset + moved;
+            scene.camera = Box::new(MovedCamera::new(Arc::clone(&camera), offset));
+        }
+        let moving = moved.norm() > 0.0
+            || event_pump
+                .keyboard_state()
+                .pressed_scancodes()
+                .any(|key| INTERACTIVE_MOVING_KEYS.contains(&key));
+
+        // While moving, a single pass at a low resolution, stretched to the window, keeps
+        // the navigation responsive. Once stopped, the accumulated samples are reprojected
+        // to the new view only once, then refined at full resolution.
+        let (pixels, canvas) = if moving {
+            let mut preview = TemporalAccumulator::new(moving_width, moving_height);
+            preview.add(&render_linear_pass(&scene, &moving_config, pass)?);
+            (
+                preview.pixels(&scene.config.tone_mapping),
+                &mut moving_canvas,
+            )
+        } else {
+            if offset != accumulated_offset {
+                let previous_camera = MovedCamera::new(Arc::clone(&camera), accumulated_offset);
+                let kept = accumulator.reproject(&scene, &previous_camera);
+                info!("Camera moved, {} pixels kept their samples", kept);
+                accumulated_offset = offset;
+            }
+            accumulator.add(&render_linear_pass(&scene, &config, pass)?);
+            (
+                accumulator.pixels(&scene.config.tone_mapping),
+                &mut render_canvas,
+            )
+        };
+        pass += 1;
+        let mut wrapper_canvas = WrapperCanvas(&mut *canvas, srgb_output);
+        for pixel in pixels {
+            wrapper_canvas.draw(pixel)?;
+        }
+        let texture = texture_creator.create_texture_from_surface(canvas.surface())?;
+        window_canvas.clear();
+        window_canvas
+            .copy(&texture, None, None)
+            .map_err(AppError::SdlError)?;
+        window_canvas.present();
+    }
+
     Ok(())
 }
 
 #[allow(clippy::while_let_on_iterator)]
 #[allow(clippy::collapsible_if)]
+#[allow(clippy::too_many_arguments)]
 fn render_sdl<M: AsRef<dyn ProgressionMonitor>>(
     render_iter: impl Iterator<Item = Result<Pixel>>,
     monitor: M,
+    mut file_canvas: Option<FileCanvas>,
     canvas_width: u32,
     canvas_height: u32,
     camera_ratio: f64,
     progressive_rendering: bool,
+    srgb_output: bool,
+    cancellation: CancellationToken,
 ) -> VoidAppResult {
     let monitor = monitor.as_ref();
 
@@ -256,14 +1623,21 @@ fn render_sdl<M: AsRef<dyn ProgressionMonitor>>(
             .map_err(AppError::SdlError)?;
     render_canvas.set_draw_color(SDL_WINDOW_CLEAR_COLOR);
     render_canvas.clear();
+    // RGBA surface, and its copy as a window texture
+    let framebuffer_size = 2 * 4 * canvas_width as usize * canvas_height as usize;
+    info!(
+        "Window framebuffer memory: {}",
+        format_bytes(framebuffer_size)
+    );
 
     if !progressive_rendering {
         // We prepare immediately the result before displaying it
-        let mut wrapper_canvas = WrapperCanvas(&mut render_canvas);
+        let mut wrapper_canvas = WrapperCanvas(&mut render_canvas, srgb_output);
         while let Some(pixel) = render_iter.next() {
-            wrapper_canvas.draw(pixel?)?;
+            draw_pixel(&mut wrapper_canvas, &mut file_canvas, pixel?)?;
             monitor.update();
         }
+        save_file_canvas(&mut file_canvas)?;
     }
 
     let sdl_context = sdl2::init().map_err(AppError::SdlError)?;
@@ -319,10 +1693,10 @@ fn render_sdl<M: AsRef<dyn ProgressionMonitor>>(
         }
         if render_iter.peek().is_some() {
             let instant = Instant::now();
-            let mut wrapper_canvas = WrapperCanvas(&mut render_canvas);
+            let mut wrapper_canvas = WrapperCanvas(&mut render_canvas, srgb_output);
 
             while let Some(pixel) = render_iter.next() {
-                wrapper_canvas.draw(pixel?)?;
+                draw_pixel(&mut wrapper_canvas, &mut file_canvas, pixel?)?;
                 monitor.update();
                 if progressive_rendering {
                     if instant.elapsed().as_millis() > 20 {
@@ -330,6 +1704,9 @@ fn render_sdl<M: AsRef<dyn ProgressionMonitor>>(
                     }
                 }
             }
+            if render_iter.peek().is_none() {
+                save_file_canvas(&mut file_canvas)?;
+            }
             texture = texture_creator.create_texture_from_surface(render_canvas.surface())?;
             window_canvas.clear();
             window_canvas
@@ -346,5 +1723,36 @@ fn render_sdl<M: AsRef<dyn ProgressionMonitor>>(
         }
     }
 
+    // Window closed while rendering: the pixels already computed are kept
+    if render_iter.peek().is_some() {
+        cancellation.cancel();
+        for pixel in render_iter {
+            match pixel {
+                Ok(pixel) => {
+                    if let Some(file_canvas) = &mut file_canvas {
+                        file_canvas.draw(pixel)?;
+                    }
+                    monitor.update();
+                }
+                Err(RaytracerError::Cancelled) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        warn!("Rendering cancelled, only the pixels rendered so far are saved");
+        save_file_canvas(&mut file_canvas)?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gamma_must_be_positive() {
+        assert_eq!(parse_gamma("2.2").unwrap(), 2.2);
+        for gamma in &["0", "-1", "inf", "NaN", "dark"] {
+            assert!(matches!(parse_gamma(gamma), Err(AppError::BadArgument(_))));
+        }
+    }
+}
@@ -0,0 +1,235 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Render service of the `serve` subcommand, so one machine renders the scenes of a small
+//! team. A job is a JSON file dropped into the queue directory, renamed once it is queued;
+//! a `.cancel` file of the same name stops it. The progress of every job is written to a
+//! status file of the directory at each poll.
+
+use crate::utils::canvas::file::FileCanvas;
+use crate::utils::canvas::DrawCanvas;
+use crate::utils::result::{AppError, VoidAppResult};
+use log::{info, warn};
+use raytracer::renderer::RenderConfiguration;
+use raytracer::scene::{Scene, SceneFormat};
+use raytracer::service::{JobId, JobStatus, RenderJob, RenderService};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const JOB_EXTENSION: &str = ".job.json";
+pub const STATUS_FILE: &str = "status.json";
+const ACCEPTED_SUFFIX: &str = ".accepted";
+const REJECTED_SUFFIX: &str = ".rejected";
+const CANCEL_EXTENSION: &str = ".cancel";
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const JOB_WIDTH: u32 = 1024;
+
+pub struct ServeConfiguration {
+    pub queue_dir: PathBuf,
+    /// Jobs rendered at the same time, sharing the threads of the machine
+    pub concurrent_jobs: usize,
+}
+
+/// Job of the queue directory being rendered or over
+struct ServedJob {
+    name: String,
+    output: PathBuf,
+    srgb_output: bool,
+    saved: bool,
+}
+
+/// Serve the jobs of the queue directory until the process is stopped
+pub fn serve(config: &ServeConfiguration) -> VoidAppResult {
+    if !config.queue_dir.is_dir() {
+        return Err(AppError::BadArgument(format!(
+            "Queue directory {} does not exist",
+            config.queue_dir.display()
+        )));
+    }
+    info!(
+        "Serving the render jobs of {}, {} at a time",
+        config.queue_dir.display(),
+        config.concurrent_jobs
+    );
+    let service = RenderService::new(config.concurrent_jobs);
+    let mut jobs = HashMap::new();
+    loop {
+        for path in new_job_files(&config.queue_dir)? {
+            accept_job(&service, &mut jobs, &path)?;
+        }
+        for (&id, job) in jobs.iter_mut() {
+            if config.queue_dir.join(cancel_file(&job.name)).exists() && service.cancel(id) {
+                info!("Job {} cancelled: {}", id, job.name);
+            }
+            if !job.saved && matches!(service.status(id), Some(JobStatus::Done(_))) {
+                job.saved = true;
+                if let Err(err) = save_frame(&service, id, job) {
+                    warn!("Job {}: {} not saved: {}", id, job.output.display(), err);
+                }
+            }
+        }
+        fs::write(
+            config.queue_dir.join(STATUS_FILE),
+            status_description(&service, &jobs),
+        )?;
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn new_job_files(queue_dir: &Path) -> Result<Vec<PathBuf>, AppError> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(queue_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.to_string_lossy().ends_with(JOB_EXTENSION))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Queue the job of a file, which is renamed so that it is not queued again, with another
+/// suffix when it cannot be queued
+fn accept_job(
+    service: &RenderService,
+    jobs: &mut HashMap<JobId, ServedJob>,
+    path: &Path,
+) -> VoidAppResult {
+    let suffixed = |suffix: &str| PathBuf::from(format!("{}{}", path.display(), suffix));
+    match load_job(path) {
+        Ok((job, output)) => {
+            let name = job.name.clone();
+            let srgb_output = job.scene.config.srgb_output;
+            let id = service.submit(job);
+            let job = ServedJob {
+                name,
+                output,
+                srgb_output,
+                saved: false,
+            };
+            jobs.insert(id, job);
+            fs::rename(path, suffixed(ACCEPTED_SUFFIX))?;
+        }
+        Err(err) => {
+            warn!("Job {} rejected: {}", path.display(), err);
+            fs::rename(path, suffixed(REJECTED_SUFFIX))?;
+        }
+    }
+    Ok(())
+}
+
+/// Job of a file like `{"scene": "room.toml", "width": 800, "priority": 1}`, with the path of
+/// its image, `output` or the name of the job file as PNG. Paths are relative to the queue
+/// directory.
+fn load_job(path: &Path) -> Result<(RenderJob, PathBuf), AppError> {
+    let description: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| AppError::BadArgument(format!("Job is not JSON: {}", e)))?;
+    let queue_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let name = file_name.trim_end_matches(JOB_EXTENSION).to_string();
+    let scene_file = match description["scene"].as_str() {
+        Some(scene) => queue_dir.join(scene),
+        None => return Err(AppError::BadArgument(String::from("Job without scene"))),
+    };
+    let output = match description["output"].as_str() {
+        Some(output) => queue_dir.join(output),
+        None => queue_dir.join(format!("{}.png", name)),
+    };
+    let width = description["width"]
+        .as_u64()
+        .map_or(JOB_WIDTH, |width| width as u32);
+    let priority = description["priority"].as_i64().unwrap_or(0) as i32;
+    let format = SceneFormat::from_path(&scene_file).unwrap_or_default();
+    let scene = Scene::from_description(&fs::read_to_string(&scene_file)?, format, None)?;
+    let config = RenderConfiguration {
+        canvas_width: width,
+        canvas_height: (width as f64 / scene.camera.size_ratio()) as u32,
+        ..Default::default()
+    };
+    let job = RenderJob {
+        name,
+        priority,
+        scene,
+        config,
+    };
+    Ok((job, output))
+}
+
+fn save_frame(service: &RenderService, id: JobId, job: &ServedJob) -> VoidAppResult {
+    let frame = match service.take_frame(id) {
+        Some(frame) => frame,
+        None => return Ok(()),
+    };
+    let mut canvas = FileCanvas::new(frame.width(), frame.height(), &job.output, job.srgb_output)?;
+    for pixel in frame.pixels() {
+        canvas.draw(pixel.clone())?;
+    }
+    canvas.save()?;
+    info!("Job {} saved to {}", id, job.output.display());
+    Ok(())
+}
+
+fn cancel_file(name: &str) -> String {
+    format!("{}{}", name, CANCEL_EXTENSION)
+}
+
+/// Jobs of the service as a JSON array, in the order they were queued
+fn status_description(service: &RenderService, jobs: &HashMap<JobId, ServedJob>) -> String {
+    let reports: Vec<_> = service
+        .reports()
+        .into_iter()
+        .map(|report| {
+            let output = jobs
+                .get(&report.id)
+                .map(|job| job.output.display().to_string());
+            let mut line = serde_json::json!({
+                "id": report.id,
+                "name": report.name,
+                "priority": report.priority,
+                "output": output,
+            });
+            let status = match &report.status {
+                JobStatus::Queued => "queued",
+                JobStatus::Rendering(progress) => {
+                    if let Some(progress) = progress {
+                        line["percent"] = progress.percent().into();
+                        line["eta_seconds"] = progress.eta.map(|eta| eta.as_secs_f64()).into();
+                    }
+                    "rendering"
+                }
+                JobStatus::Done(duration) => {
+                    line["seconds"] = duration.as_secs_f64().into();
+                    "done"
+                }
+                JobStatus::Failed(err) => {
+                    line["error"] = err.as_str().into();
+                    "failed"
+                }
+                JobStatus::Cancelled => "cancelled",
+            };
+            line["status"] = status.into();
+            line
+        })
+        .collect();
+    serde_json::to_string_pretty(&reports).unwrap_or_default()
+}
@@ -0,0 +1,125 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Parts of a panorama rendered apart, each one to its own image next to the output file,
+//! assembled into the whole image. Rows of pixels are read from the parts and written out
+//! one after the other, so the whole image is never held in memory.
+
+use crate::utils::result::{AppError, VoidAppResult};
+use log::info;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// File of the part at a column and a row, the output file with them before its extension
+pub fn part_file(output: &str, column: u32, row: u32) -> String {
+    let path = Path::new(output);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!(
+            "{}_{}_{}.{}",
+            stem,
+            column,
+            row,
+            extension.to_string_lossy()
+        ),
+        None => format!("{}_{}_{}", stem, column, row),
+    };
+    path.with_file_name(name).display().to_string()
+}
+
+/// Reader of the rows of a part, which must be a RGBA PNG image as the renderer writes
+fn open_part(path: &str) -> Result<(png::OutputInfo, png::Reader<File>), AppError> {
+    let decoding_error = |e: png::DecodingError| {
+        AppError::BadArgument(format!("Panorama part {} cannot be read: {}", path, e))
+    };
+    let (info, reader) = png::Decoder::new(File::open(path)?)
+        .read_info()
+        .map_err(decoding_error)?;
+    let rgba8 = info.color_type == png::ColorType::RGBA && info.bit_depth == png::BitDepth::Eight;
+    if !rgba8 || reader.info().interlaced {
+        return Err(AppError::BadArgument(format!(
+            "Panorama part {} is not a non interlaced PNG image of 8 bits RGBA pixels",
+            path
+        )));
+    }
+    Ok((info, reader))
+}
+
+/// Whole image of the parts of a grid of `columns` by `rows`, written to `output` as PNG
+pub fn stitch_panorama(output: &str, columns: u32, rows: u32) -> VoidAppResult {
+    // Parts of a column share their width and parts of a row their height
+    let part_size = |column, row| -> Result<(u32, u32), AppError> {
+        let (info, _) = open_part(&part_file(output, column, row))?;
+        Ok((info.width, info.height))
+    };
+    let widths = (0..columns)
+        .map(|column| part_size(column, 0).map(|(width, _)| width))
+        .collect::<Result<Vec<_>, _>>()?;
+    let heights = (0..rows)
+        .map(|row| part_size(0, row).map(|(_, height)| height))
+        .collect::<Result<Vec<_>, _>>()?;
+    for row in 0..rows {
+        for column in 0..columns {
+            if part_size(column, row)? != (widths[column as usize], heights[row as usize]) {
+                return Err(AppError::BadArgument(format!(
+                    "Panorama part {} does not fit the other parts of its row and column",
+                    part_file(output, column, row)
+                )));
+            }
+        }
+    }
+    let (width, height) = (widths.iter().sum(), heights.iter().sum());
+
+    let encoding_error = |e: png::EncodingError| AppError::DrawError(e.to_string());
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(output)?), width, height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut image = encoder
+        .write_header()
+        .map_err(encoding_error)?
+        .into_stream_writer();
+    for row in 0..rows {
+        let mut readers = (0..columns)
+            .map(|column| open_part(&part_file(output, column, row)).map(|(_, reader)| reader))
+            .collect::<Result<Vec<_>, _>>()?;
+        for _ in 0..heights[row as usize] {
+            for (column, reader) in readers.iter_mut().enumerate() {
+                let pixels = reader.next_row().ok().flatten().ok_or_else(|| {
+                    AppError::BadArgument(format!(
+                        "Panorama part {} is truncated",
+                        part_file(output, column as u32, row)
+                    ))
+                })?;
+                image.write_all(pixels)?;
+            }
+        }
+    }
+    image.finish().map_err(encoding_error)?;
+    info!(
+        "Panorama of {}x{} parts stitched to {}, {}x{} pixels",
+        columns, rows, output, width, height
+    );
+    Ok(())
+}
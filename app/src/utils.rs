@@ -24,6 +24,9 @@ SOFTWARE.
 
 pub mod monitor {
     use log::warn;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::time::Instant;
+
     pub trait ProgressionMonitor: Send + Sync {
         fn update(&self);
         fn clean(&self);
@@ -33,15 +36,15 @@ pub mod monitor {
 
     impl TermMonitor {
         pub fn new(total_pixels: u64) -> TermMonitor {
-            let progress_bar = indicatif::ProgressBar::new(total_pixels as u64);
+            let progress_bar = indicatif::ProgressBar::new(total_pixels);
             progress_bar.set_style(
                 indicatif::ProgressStyle::default_bar()
                     .template("{msg} {bar} {percent}% Elapsed: {elapsed} ETA: {eta}"),
             );
-            progress_bar.set_draw_delta((total_pixels / 100) as u64); // Update every percent
+            progress_bar.set_draw_delta(total_pixels / 100); // Update every percent
             progress_bar.set_message(format!("Processing {} pixels...", total_pixels).as_str());
             if progress_bar.is_hidden() {
-                warn!("Cannot show progress bar, requires TTY");
+                warn!("Cannot show progress bar, requires TTY, see --progress lines or json");
             }
             TermMonitor(progress_bar)
         }
@@ -56,6 +59,125 @@ pub mod monitor {
         }
     }
 
+    /// Pixels done out of the total, counted from several threads
+    struct Progress {
+        total: u64,
+        done: AtomicU64,
+        start: Instant,
+        finished: AtomicBool,
+    }
+
+    impl Progress {
+        fn new(total: u64) -> Progress {
+            Progress {
+                total: total.max(1),
+                done: AtomicU64::new(0),
+                start: Instant::now(),
+                finished: AtomicBool::new(false),
+            }
+        }
+
+        /// Count one more pixel, giving the pixels done when a new percent is reached
+        fn advance(&self) -> Option<u64> {
+            let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+            if self.percent(done) != self.percent(done - 1) {
+                Some(done)
+            } else {
+                None
+            }
+        }
+
+        /// True the first time only, the end being reported once
+        fn finish(&self) -> bool {
+            !self.finished.swap(true, Ordering::Relaxed)
+        }
+
+        fn percent(&self, done: u64) -> u64 {
+            done.min(self.total) * 100 / self.total
+        }
+
+        fn elapsed_seconds(&self) -> f64 {
+            self.start.elapsed().as_secs_f64()
+        }
+
+        /// Remaining time at the mean speed so far
+        fn eta_seconds(&self, done: u64) -> f64 {
+            let remaining = self.total.saturating_sub(done);
+            self.elapsed_seconds() * remaining as f64 / done.max(1) as f64
+        }
+    }
+
+    /// One line on stderr for each percent done, readable in logs without a TTY
+    pub struct LineMonitor(Progress);
+
+    impl LineMonitor {
+        pub fn new(total_pixels: u64) -> LineMonitor {
+            LineMonitor(Progress::new(total_pixels))
+        }
+    }
+
+    impl ProgressionMonitor for LineMonitor {
+        fn update(&self) {
+            let progress = &self.0;
+            if let Some(done) = progress.advance() {
+                eprintln!(
+                    "Progress: {}% ({}/{} pixels), elapsed {:.1}s, ETA {:.1}s",
+                    progress.percent(done),
+                    done,
+                    progress.total,
+                    progress.elapsed_seconds(),
+                    progress.eta_seconds(done)
+                );
+            }
+        }
+
+        fn clean(&self) {
+            if self.0.finish() {
+                eprintln!("Progress: done in {:.1}s", self.0.elapsed_seconds());
+            }
+        }
+    }
+
+    /// One JSON object on stderr for each percent done, then one at the end, for tools
+    /// following the rendering
+    pub struct JsonMonitor(Progress);
+
+    impl JsonMonitor {
+        pub fn new(total_pixels: u64) -> JsonMonitor {
+            JsonMonitor(Progress::new(total_pixels))
+        }
+    }
+
+    impl ProgressionMonitor for JsonMonitor {
+        fn update(&self) {
+            let progress = &self.0;
+            if let Some(done) = progress.advance() {
+                let line = serde_json::json!({
+                    "event": "progress",
+                    "percent": progress.percent(done),
+                    "done_pixels": done,
+                    "total_pixels": progress.total,
+                    "elapsed_seconds": progress.elapsed_seconds(),
+                    "eta_seconds": progress.eta_seconds(done),
+                });
+                eprintln!("{}", line);
+            }
+        }
+
+        fn clean(&self) {
+            let progress = &self.0;
+            if progress.finish() {
+                let line = serde_json::json!({
+                    "event": "done",
+                    "done_pixels": progress.done.load(Ordering::Relaxed),
+                    "total_pixels": progress.total,
+                    "elapsed_seconds": progress.elapsed_seconds(),
+                });
+                eprintln!("{}", line);
+            }
+        }
+    }
+
     pub struct NoMonitor;
 
     impl ProgressionMonitor for NoMonitor {
@@ -65,6 +187,148 @@ pub mod monitor {
     }
 }
 
+pub mod notify {
+    use log::{info, warn};
+    use std::cell::Cell;
+    use std::process::Command;
+    use std::time::Duration;
+
+    /// What is known about a finished or failed rendering
+    pub struct RenderSummary {
+        pub scene_file: String,
+        /// Unknown if the scene could not be loaded
+        pub canvas_size: Option<(u32, u32)>,
+        pub duration: Duration,
+        pub error: Option<String>,
+    }
+
+    impl RenderSummary {
+        pub fn status(&self) -> &'static str {
+            if self.error.is_some() {
+                "failure"
+            } else {
+                "success"
+            }
+        }
+
+        pub fn message(&self) -> String {
+            let duration = self.duration.as_secs_f32();
+            match (&self.error, self.canvas_size) {
+                (Some(error), _) => format!(
+                    "Rendering of {} failed after {:.1} seconds: {}",
+                    self.scene_file, duration, error
+                ),
+                (None, Some((width, height))) => format!(
+                    "Rendering of {} ({}x{}) done in {:.1} seconds",
+                    self.scene_file, width, height, duration
+                ),
+                (None, None) => format!(
+                    "Rendering of {} done in {:.1} seconds",
+                    self.scene_file, duration
+                ),
+            }
+        }
+    }
+
+    pub trait NotificationHook {
+        fn send(&self, summary: &RenderSummary) -> Result<(), String>;
+    }
+
+    /// Shell command, getting the summary from RAYTRACER_* environment variables
+    pub struct CommandHook(pub String);
+
+    impl NotificationHook for CommandHook {
+        fn send(&self, summary: &RenderSummary) -> Result<(), String> {
+            let mut command = if cfg!(windows) {
+                let mut command = Command::new("cmd");
+                command.arg("/C");
+                command
+            } else {
+                let mut command = Command::new("sh");
+                command.arg("-c");
+                command
+            };
+            let (width, height) = summary.canvas_size.unwrap_or_default();
+            let status = command
+                .arg(&self.0)
+                .env("RAYTRACER_STATUS", summary.status())
+                .env("RAYTRACER_MESSAGE", summary.message())
+                .env("RAYTRACER_SCENE", &summary.scene_file)
+                .env("RAYTRACER_WIDTH", width.to_string())
+                .env("RAYTRACER_HEIGHT", height.to_string())
+                .env(
+                    "RAYTRACER_DURATION",
+                    format!("{:.3}", summary.duration.as_secs_f32()),
+                )
+                .env("RAYTRACER_ERROR", summary.error.as_deref().unwrap_or(""))
+                .status()
+                .map_err(|e| format!("Cannot run '{}': {}", self.0, e))?;
+            if !status.success() {
+                return Err(format!("'{}' ended with {}", self.0, status));
+            }
+            Ok(())
+        }
+    }
+
+    /// JSON document posted to a URL, its text field being understood by Slack webhooks
+    pub struct WebhookHook(pub String);
+
+    /// Time after which a webhook not answering is given up
+    const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+    impl NotificationHook for WebhookHook {
+        fn send(&self, summary: &RenderSummary) -> Result<(), String> {
+            let body = serde_json::json!({
+                "text": summary.message(),
+                "status": summary.status(),
+                "scene": summary.scene_file,
+                "width": summary.canvas_size.map(|(width, _)| width),
+                "height": summary.canvas_size.map(|(_, height)| height),
+                "duration_seconds": summary.duration.as_secs_f64(),
+                "error": summary.error,
+            });
+            let response = ureq::post(&self.0).timeout(WEBHOOK_TIMEOUT).send_json(body);
+            if let Some(err) = response.synthetic_error() {
+                return Err(format!("Cannot post to {}: {}", self.0, err));
+            }
+            if !response.ok() {
+                return Err(format!(
+                    "{} answered {} {}",
+                    self.0,
+                    response.status(),
+                    response.status_text()
+                ));
+            }
+            Ok(())
+        }
+    }
+
+    /// Send the summary to all the hooks, only for the first end of rendering
+    #[derive(Default)]
+    pub struct Notifier {
+        hooks: Vec<Box<dyn NotificationHook>>,
+        sent: Cell<bool>,
+    }
+
+    impl Notifier {
+        pub fn add_hook(&mut self, hook: Box<dyn NotificationHook>) {
+            self.hooks.push(hook);
+        }
+
+        pub fn notify(&self, summary: &RenderSummary) {
+            if self.hooks.is_empty() || self.sent.replace(true) {
+                return;
+            }
+            info!("Sending notification: {}", summary.message());
+            for hook in &self.hooks {
+                if let Err(err) = hook.send(summary) {
+                    warn!("Notification not sent: {}", err);
+                }
+            }
+        }
+    }
+}
+
 pub mod result {
     use crate::utils::canvas::DrawCanvasError;
     use crate::utils::result::AppError::*;
@@ -83,7 +347,7 @@ pub mod result {
     #[derive(Debug)]
     pub enum AppError {
         SdlError(String),
-        EngineError(String),
+        EngineError(RaytracerError),
         LoggerError(String),
         BadArgument(String),
         DrawError(String),
@@ -101,6 +365,73 @@ pub mod result {
         }
     }
 
+    /// Process exit code for each category of failure
+    pub const EXIT_CODES: [(&str, i32); 6] = [
+        ("argument", 2),
+        ("scene", 3),
+        ("render", 4),
+        ("output", 5),
+        ("display", 6),
+        ("logger", 7),
+    ];
+
+    impl AppError {
+        /// Class of the failure, for tools reading the errors
+        pub fn category(&self) -> &'static str {
+            match self {
+                BadArgument(_) => "argument",
+                EngineError(RaytracerError::NormalNotFound(_)) => "render",
+                EngineError(RaytracerError::Cancelled) => "render",
+                EngineError(_) => "scene",
+                DrawError(_) => "output",
+                SdlError(_) => "display",
+                LoggerError(_) => "logger",
+            }
+        }
+
+        pub fn exit_code(&self) -> i32 {
+            let category = self.category();
+            EXIT_CODES
+                .iter()
+                .find(|(name, _)| *name == category)
+                .map_or(1, |&(_, code)| code)
+        }
+
+        /// Dotted path of the scene key which could not be parsed, if known
+        pub fn key_path(&self) -> Option<&str> {
+            const KEY_START: &str = "for key `";
+            match self {
+                EngineError(RaytracerError::ParsingError(message)) => {
+                    let start = message.find(KEY_START)? + KEY_START.len();
+                    let length = message[start..].find('`')?;
+                    Some(&message[start..start + length])
+                }
+                EngineError(RaytracerError::InvalidScene(error)) => error.key.as_deref(),
+                _ => None,
+            }
+        }
+
+        /// Line and column of the scene description where the error is, if known
+        pub fn position(&self) -> Option<(usize, usize)> {
+            match self {
+                EngineError(RaytracerError::InvalidScene(error)) => error.position,
+                _ => None,
+            }
+        }
+
+        pub fn to_json(&self) -> serde_json::Value {
+            let position = self.position();
+            serde_json::json!({
+                "category": self.category(),
+                "message": self.to_string(),
+                "key_path": self.key_path(),
+                "line": position.map(|(line, _)| line),
+                "column": position.map(|(_, column)| column),
+                "exit_code": self.exit_code(),
+            })
+        }
+    }
+
     impl From<std::io::Error> for AppError {
         fn from(err: Error) -> Self {
             BadArgument(err.to_string())
@@ -133,7 +464,7 @@ pub mod result {
 
     impl From<RaytracerError> for AppError {
         fn from(err: RaytracerError) -> Self {
-            EngineError(err.to_string())
+            EngineError(err)
         }
     }
 
@@ -144,47 +475,4 @@ pub mod result {
     }
 }
 
-pub mod canvas {
-    use raytracer::renderer::Pixel;
-
-    pub struct DrawCanvasError(pub String);
-
-    pub trait DrawCanvas {
-        fn draw(&mut self, pixel: Pixel) -> Result<(), DrawCanvasError>;
-    }
-    pub mod sdl {
-        use super::*;
-        use raytracer::renderer::Pixel;
-        use sdl2::render::Canvas;
-
-        pub struct WrapperCanvas<'a, T: sdl2::render::RenderTarget>(pub &'a mut Canvas<T>);
-
-        impl<T: sdl2::render::RenderTarget> DrawCanvas for WrapperCanvas<'_, T> {
-            fn draw(&mut self, p: Pixel) -> std::result::Result<(), DrawCanvasError> {
-                let draw_color = sdl2::pixels::Color::RGB(
-                    (255.0 * p.color.red()) as u8,
-                    (255.0 * p.color.green()) as u8,
-                    (255.0 * p.color.blue()) as u8,
-                );
-                self.0.set_draw_color(draw_color);
-                self.0
-                    .draw_point(sdl2::rect::Point::new(p.x as i32, p.y as i32))
-                    .map_err(DrawCanvasError)?;
-                Ok(())
-            }
-        }
-    }
-
-    pub mod none {
-        use super::*;
-        use raytracer::renderer::Pixel;
-
-        pub struct NoCanvas;
-
-        impl DrawCanvas for NoCanvas {
-            fn draw(&mut self, _pixel: Pixel) -> Result<(), DrawCanvasError> {
-                Ok(())
-            }
-        }
-    }
-}
+pub mod canvas;
@@ -0,0 +1,236 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+use raytracer::renderer::Pixel;
+
+pub struct DrawCanvasError(pub String);
+
+pub trait DrawCanvas {
+    fn draw(&mut self, pixel: Pixel) -> Result<(), DrawCanvasError>;
+}
+pub mod sdl {
+    use super::*;
+    use raytracer::renderer::Pixel;
+    use sdl2::render::Canvas;
+
+    /// SDL canvas, and if its colors are sRGB encoded
+    pub struct WrapperCanvas<'a, T: sdl2::render::RenderTarget>(pub &'a mut Canvas<T>, pub bool);
+
+    impl<T: sdl2::render::RenderTarget> DrawCanvas for WrapperCanvas<'_, T> {
+        fn draw(&mut self, p: Pixel) -> std::result::Result<(), DrawCanvasError> {
+            let [red, green, blue] = p.color.to_rgb8(self.1);
+            let draw_color = sdl2::pixels::Color::RGB(red, green, blue);
+            self.0.set_draw_color(draw_color);
+            self.0
+                .draw_point(sdl2::rect::Point::new(p.x as i32, p.y as i32))
+                .map_err(DrawCanvasError)?;
+            Ok(())
+        }
+    }
+}
+
+pub mod none {
+    use super::*;
+    use raytracer::renderer::Pixel;
+
+    pub struct NoCanvas;
+
+    impl DrawCanvas for NoCanvas {
+        fn draw(&mut self, _pixel: Pixel) -> Result<(), DrawCanvasError> {
+            Ok(())
+        }
+    }
+}
+
+pub mod file {
+    use super::*;
+    use image::buffer::ConvertBuffer;
+    use image::{ImageFormat, RgbImage, Rgba, RgbaImage};
+    use log::{info, warn};
+    use raytracer::renderer::Pixel;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+
+    /// Image kept in memory, then saved as PNG or JPEG depending on the file extension
+    pub struct FileCanvas {
+        path: PathBuf,
+        format: ImageFormat,
+        buffer: RgbaImage,
+        srgb: bool,
+        /// Partial image written at each interval, and time of the last write
+        checkpoint: Option<(Duration, Instant)>,
+    }
+
+    impl FileCanvas {
+        pub fn new<P: AsRef<Path>>(
+            width: u32,
+            height: u32,
+            path: P,
+            srgb: bool,
+        ) -> Result<FileCanvas, DrawCanvasError> {
+            let path = path.as_ref();
+            let format = match ImageFormat::from_path(path) {
+                Ok(format @ ImageFormat::Png) | Ok(format @ ImageFormat::Jpeg) => format,
+                _ => {
+                    return Err(DrawCanvasError(format!(
+                        "{} must have a PNG or JPEG extension",
+                        path.display()
+                    )))
+                }
+            };
+            Ok(FileCanvas {
+                path: path.to_path_buf(),
+                format,
+                buffer: RgbaImage::new(width, height),
+                srgb,
+                checkpoint: None,
+            })
+        }
+
+        /// Periodically write the image being drawn, so an interrupted rendering is not lost
+        pub fn with_save_interval(mut self, interval: Duration) -> FileCanvas {
+            self.checkpoint = Some((interval, Instant::now()));
+            self
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+
+        pub fn save(self) -> Result<(), DrawCanvasError> {
+            self.write()
+        }
+
+        fn write(&self) -> Result<(), DrawCanvasError> {
+            write_aside(&self.path, |temporary_path| match self.format {
+                // JPEG has no alpha channel, so holdout areas are only black in it
+                ImageFormat::Jpeg => {
+                    let image: RgbImage = self.buffer.convert();
+                    image.save_with_format(temporary_path, self.format)
+                }
+                _ => self.buffer.save_with_format(temporary_path, self.format),
+            })
+        }
+
+        fn write_checkpoint_if_due(&mut self) {
+            if let Some((interval, last_write)) = self.checkpoint {
+                if last_write.elapsed() < interval {
+                    return;
+                }
+                match self.write() {
+                    Ok(()) => info!("Partial image saved to {}", self.path.display()),
+                    Err(err) => warn!("Partial image not saved: {}", err.0),
+                }
+                self.checkpoint = Some((interval, Instant::now()));
+            }
+        }
+    }
+
+    /// Written aside then renamed, so a crash while writing keeps the previous file
+    pub(super) fn write_aside<F, E>(path: &Path, write: F) -> Result<(), DrawCanvasError>
+    where
+        F: FnOnce(&Path) -> Result<(), E>,
+        E: std::fmt::Display,
+    {
+        let mut temporary_path = path.to_path_buf().into_os_string();
+        temporary_path.push(".tmp");
+        let temporary_path = PathBuf::from(temporary_path);
+        let error =
+            |e: &dyn std::fmt::Display| DrawCanvasError(format!("{}: {}", path.display(), e));
+        write(&temporary_path).map_err(|e| error(&e))?;
+        fs::rename(&temporary_path, path).map_err(|e| error(&e))
+    }
+
+    impl DrawCanvas for FileCanvas {
+        fn draw(&mut self, p: Pixel) -> Result<(), DrawCanvasError> {
+            if p.x >= self.buffer.width() || p.y >= self.buffer.height() {
+                return Err(DrawCanvasError(format!(
+                    "Pixel {},{} is outside of the image",
+                    p.x, p.y
+                )));
+            }
+            let [red, green, blue] = p.color.to_rgb8(self.srgb);
+            let color = Rgba([red, green, blue, (255.0 * p.alpha) as u8]);
+            self.buffer.put_pixel(p.x, p.y, color);
+            self.write_checkpoint_if_due();
+            Ok(())
+        }
+    }
+}
+
+pub mod checkpoint {
+    use super::file::write_aside;
+    use super::*;
+    use log::{info, warn};
+    use raytracer::checkpoint::Checkpoint;
+    use raytracer::renderer::Pixel;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+
+    /// Completed tiles written at each interval, for an interrupted rendering to be resumed
+    pub struct CheckpointCanvas {
+        path: PathBuf,
+        checkpoint: Checkpoint,
+        interval: Duration,
+        last_write: Instant,
+    }
+
+    impl CheckpointCanvas {
+        pub fn new<P: AsRef<Path>>(
+            path: P,
+            checkpoint: Checkpoint,
+            interval: Duration,
+        ) -> CheckpointCanvas {
+            CheckpointCanvas {
+                path: path.as_ref().to_path_buf(),
+                checkpoint,
+                interval,
+                last_write: Instant::now(),
+            }
+        }
+
+        fn write(&self) -> Result<(), DrawCanvasError> {
+            let bytes = self.checkpoint.encode();
+            write_aside(&self.path, |temporary_path| {
+                fs::write(temporary_path, &bytes)
+            })
+        }
+    }
+
+    impl DrawCanvas for CheckpointCanvas {
+        fn draw(&mut self, p: Pixel) -> Result<(), DrawCanvasError> {
+            self.checkpoint.record(&p);
+            if self.last_write.elapsed() >= self.interval {
+                match self.write() {
+                    Ok(()) => info!("Checkpoint saved to {}", self.path.display()),
+                    Err(err) => warn!("Checkpoint not saved: {}", err.0),
+                }
+                self.last_write = Instant::now();
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,411 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Samples of a preview accumulated over its passes, so that it converges instead of
+//! staying noisy. When the camera moves, the samples are reprojected to where they are
+//! now seen: each pixel follows its motion back to the previous view, and keeps the
+//! samples found there when the previous camera sees the same point of the same object
+//! there, the depth not matching where objects were hidden or revealed.
+//!
+//! With `with_half_precision`, the samples are kept as half-precision floats, their
+//! average rather than their sum so that it stays in range, halving the memory of the
+//! buffer for very large previews. The sums are still made in double precision.
+
+use crate::colors::Color;
+use crate::ray_algorithm::camera_ray_hit;
+use crate::renderer::{Pixel, ToneMapping, BACKGROUND_DISTANCE};
+use crate::scene::{RayEmitter, Scene};
+use crate::vector::Vec3;
+
+/// Samples kept by default for a pixel, older ones fading so that the errors of the
+/// reprojection do not stay forever
+pub const DEFAULT_MAX_HISTORY: u32 = 64;
+
+/// Relative distance under which points seen from two cameras are the same
+const DEPTH_TOLERANCE: f64 = 1e-4;
+
+#[derive(Debug, Clone)]
+struct History {
+    color: Color,
+    alpha: f64,
+    samples: f64,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            color: Color::BLACK,
+            alpha: 0.0,
+            samples: 0.0,
+        }
+    }
+}
+
+impl History {
+    /// Average color and alpha, then the samples, as half-precision floats
+    fn to_half(&self) -> [u16; 5] {
+        let average = if self.samples > 0.0 {
+            1.0 / self.samples
+        } else {
+            0.0
+        };
+        [
+            f16_from_f64(self.color.red() * average),
+            f16_from_f64(self.color.green() * average),
+            f16_from_f64(self.color.blue() * average),
+            f16_from_f64(self.alpha * average),
+            f16_from_f64(self.samples),
+        ]
+    }
+
+    fn from_half(half: &[u16; 5]) -> Self {
+        let samples = f64_from_f16(half[4]);
+        History {
+            color: Color::unclamped(
+                f64_from_f16(half[0]) * samples,
+                f64_from_f16(half[1]) * samples,
+                f64_from_f16(half[2]) * samples,
+            ),
+            alpha: f64_from_f16(half[3]) * samples,
+            samples,
+        }
+    }
+}
+
+/// Samples of the pixels, in double or half precision
+#[derive(Clone)]
+enum Buffer {
+    Full(Vec<History>),
+    Half(Vec<[u16; 5]>),
+}
+
+impl Buffer {
+    /// Buffer of the same precision without samples
+    fn emptied(&self) -> Self {
+        match self {
+            Buffer::Full(history) => Buffer::Full(vec![History::default(); history.len()]),
+            Buffer::Half(history) => Buffer::Half(vec![[0; 5]; history.len()]),
+        }
+    }
+
+    fn get(&self, index: usize) -> History {
+        match self {
+            Buffer::Full(history) => history[index].clone(),
+            Buffer::Half(history) => History::from_half(&history[index]),
+        }
+    }
+
+    fn set(&mut self, index: usize, value: &History) {
+        match self {
+            Buffer::Full(history) => history[index] = value.clone(),
+            Buffer::Half(history) => history[index] = value.to_half(),
+        }
+    }
+
+    /// Copy without converting, so that half-precision samples are not rounded again
+    fn copy(&mut self, index: usize, from: &Self, from_index: usize) {
+        match (self, from) {
+            (Buffer::Full(history), Buffer::Full(from)) => {
+                history[index] = from[from_index].clone()
+            }
+            (Buffer::Half(history), Buffer::Half(from)) => history[index] = from[from_index],
+            (buffer, from) => buffer.set(index, &from.get(from_index)),
+        }
+    }
+
+    fn memory_size(&self) -> usize {
+        match self {
+            Buffer::Full(history) => history.len() * std::mem::size_of::<History>(),
+            Buffer::Half(history) => history.len() * std::mem::size_of::<[u16; 5]>(),
+        }
+    }
+}
+
+/// Sum of the samples of each pixel, averaged when the preview is displayed
+pub struct TemporalAccumulator {
+    width: u32,
+    height: u32,
+    history: Buffer,
+    max_history: f64,
+}
+
+impl TemporalAccumulator {
+    pub fn new(width: u32, height: u32) -> Self {
+        TemporalAccumulator {
+            width,
+            height,
+            history: Buffer::Full(vec![History::default(); width as usize * height as usize]),
+            max_history: DEFAULT_MAX_HISTORY as f64,
+        }
+    }
+
+    pub fn with_max_history(self, samples: u32) -> Self {
+        TemporalAccumulator {
+            max_history: samples.max(1) as f64,
+            ..self
+        }
+    }
+
+    /// Keep the samples as half-precision floats, with about three significant digits
+    pub fn with_half_precision(self) -> Self {
+        let pixels = self.width as usize * self.height as usize;
+        TemporalAccumulator {
+            history: Buffer::Half(vec![[0; 5]; pixels]),
+            ..self
+        }
+    }
+
+    /// Memory used by the samples, in bytes
+    pub fn memory_size(&self) -> usize {
+        self.history.memory_size()
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    /// Add a pass of samples before tone mapping, as given by `render_linear_pass`
+    pub fn add(&mut self, pixels: &[Pixel]) {
+        for pixel in pixels {
+            let index = self.index(pixel.x, pixel.y);
+            let mut history = self.history.get(index);
+            // Beyond the history kept, the older samples count less and less
+            if history.samples >= self.max_history {
+                let kept = (self.max_history - 1.0) / history.samples;
+                history.color = &history.color * kept;
+                history.alpha *= kept;
+                history.samples *= kept;
+            }
+            history.color += pixel.color.clone();
+            history.alpha += pixel.alpha;
+            history.samples += 1.0;
+            self.history.set(index, &history);
+        }
+    }
+
+    /// Samples accumulated for a pixel, not a whole number after reprojections
+    pub fn samples_at(&self, x: u32, y: u32) -> f64 {
+        self.history.get(self.index(x, y)).samples
+    }
+
+    /// Average of the samples of each pixel, tone mapped, transparent black where there
+    /// are none
+    pub fn pixels(&self, tone_mapping: &ToneMapping) -> Vec<Pixel> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let history = self.history.get(self.index(x, y));
+                if history.samples <= 0.0 {
+                    return Pixel::with_alpha(x, y, Color::BLACK, 0.0);
+                }
+                let color = &history.color * (1.0 / history.samples);
+                let color = tone_mapping.apply(&color);
+                Pixel::with_alpha(x, y, color, history.alpha / history.samples)
+            })
+            .collect()
+    }
+
+    /// Move the samples seen by `previous_camera` to where the camera of the scene now
+    /// sees them, the objects not having moved. Returns the pixels which kept samples.
+    pub fn reproject(&mut self, scene: &Scene, previous_camera: &dyn RayEmitter) -> usize {
+        let (width, height) = (self.width as f64, self.height as f64);
+        let mut history = self.history.emptied();
+        let mut kept = 0;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let canvas_x = (x as f64 + 0.5) / width;
+                let canvas_y = (y as f64 + 0.5) / height;
+                let ray = scene.camera.generate_ray(canvas_x, canvas_y);
+                let hit = camera_ray_hit(&ray, scene);
+                let point = match hit {
+                    Some((_, point)) => point,
+                    None => ray.source + BACKGROUND_DISTANCE * ray.direction,
+                };
+                let (previous_x, previous_y) = match previous_camera.canvas_position(point) {
+                    Some(position) => position,
+                    None => continue,
+                };
+                if !(previous_x > 0.0 && previous_x < 1.0 && previous_y > 0.0 && previous_y < 1.0) {
+                    continue;
+                }
+                // Depth test, the previous camera seeing the same point there
+                let previous_ray = previous_camera.generate_ray(previous_x, previous_y);
+                let same_point = match (hit, camera_ray_hit(&previous_ray, scene)) {
+                    (Some((index, point)), Some((previous_index, previous_point))) => {
+                        let distance = point.distance(previous_ray.source).max(1.0);
+                        index == previous_index
+                            && point.distance(previous_point) < DEPTH_TOLERANCE * distance
+                    }
+                    (None, None) => true,
+                    _ => false,
+                };
+                if !same_point {
+                    continue;
+                }
+                let from_x = ((previous_x * width) as u32).min(self.width - 1);
+                let from_y = ((previous_y * height) as u32).min(self.height - 1);
+                history.copy(self.index(x, y), &self.history, self.index(from_x, from_y));
+                kept += 1;
+            }
+        }
+        self.history = history;
+        kept
+    }
+}
+
+/// Direction in which the camera of a scene looks, and the one on its right, to move it
+pub fn camera_axes(camera: &dyn RayEmitter) -> (Vec3, Vec3) {
+    let forward = camera.generate_ray(0.5, 0.5).direction;
+    let right = Vec3::new(0.0, 1.0, 0.0).cross_product(forward);
+    let right = if right.norm() > 1e-9 {
+        right.normalize()
+    } else {
+        // Camera looking straight up or down
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    (forward, right)
+}
+
+/// Bits of the nearest half-precision float, rounded to even, the values too large for it
+/// saturated to the largest finite one
+fn f16_from_f64(value: f64) -> u16 {
+    let sign = if value.is_sign_negative() { 0x8000 } else { 0 };
+    let value = value.abs();
+    if value.is_nan() {
+        return 0x7e00;
+    }
+    if value >= 65504.0 {
+        return sign | 0x7bff;
+    }
+    let bits = (value as f32).to_bits();
+    let exponent = (bits >> 23) as i32 - 127 + 15;
+    if exponent < 1 {
+        // Subnormal, in steps of 2^-24
+        return sign | (value * 16_777_216.0).round() as u16;
+    }
+    let mantissa = bits & 0x7f_ffff;
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let rest = mantissa & 0x1fff;
+    let half = if rest > 0x1000 || (rest == 0x1000 && half & 1 == 1) {
+        half + 1
+    } else {
+        half
+    };
+    sign | half.min(0x7bff) as u16
+}
+
+fn f64_from_f16(half: u16) -> f64 {
+    let sign = if half & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exponent = i32::from((half >> 10) & 0x1f);
+    let mantissa = f64::from(half & 0x3ff);
+    sign * match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        0x1f if mantissa == 0.0 => f64::INFINITY,
+        0x1f => f64::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::SceneBuilder;
+    use crate::cameras::{MovedCamera, OrthogonalCamera};
+    use crate::renderer::{render_linear_pass, RenderConfiguration};
+    use std::sync::Arc;
+
+    fn scene_with_camera(camera: MovedCamera) -> Scene {
+        SceneBuilder::new()
+            .camera(camera)
+            .add_sphere(Vec3::new(0.0, 0.0, 0.0), 2.0)
+            .colored(Color::RED)
+            .build()
+    }
+
+    #[test]
+    fn samples_are_averaged_and_fade_beyond_the_history() {
+        let mut accumulator = TemporalAccumulator::new(1, 1).with_max_history(4);
+        for &red in &[1.0, 0.0, 1.0, 0.0] {
+            accumulator.add(&[Pixel::new(0, 0, Color::new(red, 0.0, 0.0))]);
+        }
+        let pixel = &accumulator.pixels(&ToneMapping::default())[0];
+        assert!((pixel.color.red() - 0.5).abs() < 1e-9);
+        accumulator.add(&[Pixel::new(0, 0, Color::WHITE)]);
+        assert!((accumulator.samples_at(0, 0) - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn half_floats_are_rounded_to_even_and_saturated() {
+        for &value in &[0.0, 1.0, -2.5, 0.1, 1000.25, 6e-8, 65504.0] {
+            let rounded = f64_from_f16(f16_from_f64(value));
+            assert!(
+                (rounded - value).abs() <= value.abs() / 1024.0 + 3e-8,
+                "{}",
+                value
+            );
+        }
+        // Halfway between 1 and the next half, 1 + 2^-10
+        assert_eq!(f16_from_f64(1.0 + 2f64.powi(-11)), 0x3c00);
+        assert_eq!(f16_from_f64(1e9), 0x7bff);
+        assert!(f64_from_f16(f16_from_f64(f64::NAN)).is_nan());
+    }
+
+    #[test]
+    fn half_precision_halves_the_memory_and_keeps_the_average() {
+        let mut accumulator = TemporalAccumulator::new(2, 1).with_half_precision();
+        assert!(accumulator.memory_size() * 2 < TemporalAccumulator::new(2, 1).memory_size());
+        for &red in &[1.0, 0.0, 1.0, 0.0, 0.3] {
+            accumulator.add(&[Pixel::new(1, 0, Color::new(red, 0.0, 0.0))]);
+        }
+        let pixel = &accumulator.pixels(&ToneMapping::default())[1];
+        assert!((pixel.color.red() - 0.46).abs() < 1e-3);
+        assert!((accumulator.samples_at(1, 0) - 5.0).abs() < 1e-9);
+        assert_eq!(accumulator.samples_at(0, 0), 0.0);
+    }
+
+    #[test]
+    fn moved_camera_keeps_the_samples_still_seen() {
+        let camera: Arc<dyn RayEmitter> = Arc::new(OrthogonalCamera::default());
+        let previous = MovedCamera::new(Arc::clone(&camera), Vec3::default());
+        let scene = scene_with_camera(previous);
+        let config = RenderConfiguration {
+            canvas_width: 32,
+            canvas_height: 18,
+            ..Default::default()
+        };
+        let mut accumulator = TemporalAccumulator::new(32, 18);
+        accumulator.add(&render_linear_pass(&scene, &config, 0).unwrap());
+
+        // One unit on the right, two pixels of the canvas
+        let moved = scene_with_camera(MovedCamera::new(camera, Vec3::new(1.0, 0.0, 0.0)));
+        let kept = accumulator.reproject(&moved, scene.camera.as_ref());
+        assert!(kept > 0 && kept < 32 * 18);
+        // The right columns were outside of the previous view
+        assert!((0..18).all(|y| accumulator.samples_at(31, y) == 0.0));
+        assert_eq!(accumulator.samples_at(0, 0), 1.0);
+        assert_eq!(accumulator.samples_at(16, 9), 1.0);
+        let pixels = accumulator.pixels(&ToneMapping::default());
+        assert!(pixels[9 * 32 + 13].color.red() > 0.0);
+    }
+}
@@ -0,0 +1,245 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Keyframed transformations of the camera and of the objects of a scene, given in the
+//! `[animation]` table of a description, and the rendering of a sequence of frames
+
+use crate::interpolation::Frame;
+use crate::renderer::{render_linear_pass, Pixel, RenderConfiguration};
+use crate::result::Result;
+use crate::scene::{Scene, SceneFormat};
+use crate::vector::{Mat3, Vec3};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Frame of an animation at which a scene is built, with its frame rate. Those of the
+/// configuration of the description are used when they are not given. A frame gives the
+/// time of the scene, unless a time is given too.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AnimationFrame {
+    pub frame: Option<u32>,
+    pub frames_per_second: Option<f64>,
+    /// Time of the scene in seconds
+    pub time: Option<f64>,
+}
+
+impl AnimationFrame {
+    pub fn with_frames_per_second(self, frames_per_second: f64) -> Self {
+        AnimationFrame {
+            frames_per_second: Some(frames_per_second),
+            ..self
+        }
+    }
+
+    pub fn with_time(self, time: f64) -> Self {
+        AnimationFrame {
+            time: Some(time),
+            ..self
+        }
+    }
+}
+
+impl From<Option<u32>> for AnimationFrame {
+    fn from(frame: Option<u32>) -> Self {
+        AnimationFrame {
+            frame,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<u32> for AnimationFrame {
+    fn from(frame: u32) -> Self {
+        AnimationFrame::from(Some(frame))
+    }
+}
+
+/// Transformation reached at a time, in seconds
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Keyframe {
+    pub time: f64,
+    #[serde(default)]
+    pub position: Vec3,
+    /// Angles of rotation around the x, then y, then z axis
+    #[serde(default)]
+    pub rotation_degree: Vec3,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+impl Keyframe {
+    pub fn rotation(&self) -> Mat3 {
+        Mat3::rotation(Vec3::new(
+            self.rotation_degree.x.to_radians(),
+            self.rotation_degree.y.to_radians(),
+            self.rotation_degree.z.to_radians(),
+        ))
+    }
+
+    /// Transformation at `time` of keys sorted by time, linearly interpolated between them
+    /// and held before the first and after the last one, none if there are no keys
+    pub fn at(keys: &[Keyframe], time: f64) -> Option<Keyframe> {
+        let index = keys.iter().position(|key| key.time > time);
+        let (start, end) = match index {
+            None => return keys.last().cloned(),
+            Some(0) => return keys.first().cloned(),
+            Some(index) => (&keys[index - 1], &keys[index]),
+        };
+        let ratio = (time - start.time) / (end.time - start.time);
+        let between = |start: Vec3, end: Vec3| start + ratio * (end - start);
+        Some(Keyframe {
+            time,
+            position: between(start.position, end.position),
+            rotation_degree: between(start.rotation_degree, end.rotation_degree),
+            scale: start.scale + ratio * (end.scale - start.scale),
+        })
+    }
+
+    /// Mean velocities per second of the position and of the rotation angles, in radians,
+    /// between two times, as while a shutter is open. None if there are no keys or no
+    /// time between the two.
+    pub fn velocities(keys: &[Keyframe], times: Range<f64>) -> Option<(Vec3, Vec3)> {
+        let duration = times.end - times.start;
+        if duration <= 0.0 {
+            return None;
+        }
+        let (start, end) = (Self::at(keys, times.start)?, Self::at(keys, times.end)?);
+        let turn = end.rotation_degree - start.rotation_degree;
+        let angular_velocity = Vec3::new(
+            turn.x.to_radians(),
+            turn.y.to_radians(),
+            turn.z.to_radians(),
+        );
+        Some((
+            (1.0 / duration) * (end.position - start.position),
+            (1.0 / duration) * angular_velocity,
+        ))
+    }
+}
+
+/// Keyframes of an object of the `[[object]]` array of a description, given by its index
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObjectTrack {
+    pub index: usize,
+    pub keys: Vec<Keyframe>,
+}
+
+/// Tracks of an animation. The camera keys move the camera from where it is described
+/// and turn it around the center of its view, while the keys of an object replace its
+/// `position`, `rotation_degree` and `scale`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Animation {
+    pub camera: Vec<Keyframe>,
+    pub object: Vec<ObjectTrack>,
+}
+
+impl Animation {
+    pub fn is_empty(&self) -> bool {
+        self.camera.is_empty() && self.object.is_empty()
+    }
+}
+
+/// Frames of the animation of a description rendered one after another, at the frame rate
+/// of its configuration unless another one is given. Each frame is given to `frame_done`
+/// with its number once rendered, the objects hierarchy being refitted from frame to frame.
+pub fn render_animation<F>(
+    scene_str: &str,
+    format: SceneFormat,
+    frames: Range<u32>,
+    frames_per_second: Option<f64>,
+    config: &RenderConfiguration,
+    mut frame_done: F,
+) -> Result<()>
+where
+    F: FnMut(u32, Frame) -> Result<()>,
+{
+    let mut previous: Option<Scene> = None;
+    for frame in frames {
+        let at = AnimationFrame {
+            frame: Some(frame),
+            frames_per_second,
+            time: None,
+        };
+        let scene = match previous.take() {
+            Some(previous) => previous.next_frame(scene_str, format, at)?,
+            None => Scene::from_description(scene_str, format, at)?,
+        };
+        let tone_mapping = &scene.config.tone_mapping;
+        // Other samples at each frame, or their noise would stay still over the animation
+        let pixels = render_linear_pass(&scene, config, frame)?
+            .into_iter()
+            .map(|pixel| {
+                let color = tone_mapping.apply(&pixel.color);
+                Ok(Pixel::with_alpha(pixel.x, pixel.y, color, pixel.alpha))
+            });
+        frame_done(
+            frame,
+            Frame::from_pixels(config.canvas_width, config.canvas_height, pixels)?,
+        )?;
+        previous = Some(scene);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f64, x: f64) -> Keyframe {
+        Keyframe {
+            time,
+            position: Vec3::new(x, 0.0, 0.0),
+            rotation_degree: Vec3::new(0.0, 10.0 * x, 0.0),
+            scale: 1.0,
+        }
+    }
+
+    #[test]
+    fn keyframes_are_interpolated_and_held() {
+        let keys = [key(1.0, 0.0), key(3.0, 4.0)];
+        assert_eq!(Keyframe::at(&keys, 0.0), Some(keys[0].clone()));
+        let between = Keyframe::at(&keys, 2.0).unwrap();
+        assert_eq!(between.position, Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(between.rotation_degree, Vec3::new(0.0, 20.0, 0.0));
+        assert_eq!(Keyframe::at(&keys, 5.0), Some(keys[1].clone()));
+        assert_eq!(Keyframe::at(&[], 1.0), None);
+    }
+
+    #[test]
+    fn velocities_are_the_mean_ones_over_the_times() {
+        let keys = [key(1.0, 0.0), key(3.0, 4.0)];
+        let (velocity, angular_velocity) = Keyframe::velocities(&keys, 0.0..2.0).unwrap();
+        assert_eq!(velocity, Vec3::new(1.0, 0.0, 0.0));
+        assert!((angular_velocity.y - 10.0_f64.to_radians()).abs() < 1e-12);
+        assert_eq!(Keyframe::velocities(&keys, 2.0..2.0), None);
+        assert_eq!(Keyframe::velocities(&[], 0.0..1.0), None);
+    }
+}
@@ -0,0 +1,277 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Sky of a planet, the sun light being scattered once by the air molecules (Rayleigh
+//! scattering, making the sky blue) and by the aerosols (Mie scattering, making the haze
+//! around the sun), as described by Nishita.
+//!
+//! Distances of the model are in meters, the scene origin being at `altitude` above the
+//! ground, with the Y axis going up.
+
+use crate::colors::Color;
+use crate::vector::Vec3;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Aerosols absorb a part of the light they do not scatter
+const MIE_EXTINCTION_RATIO: f64 = 1.1;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Atmosphere {
+    /// Direction going towards the sun
+    pub sun_direction: Vec3,
+    pub sun_intensity: f64,
+    /// Height of the scene origin above the ground
+    pub altitude: f64,
+    /// Meters in a unit of the scene, for the distances of the height fog
+    pub meters_per_unit: f64,
+    pub planet_radius: f64,
+    pub atmosphere_thickness: f64,
+    /// Scattering coefficients of the air at sea level for red, green and blue, per meter
+    pub rayleigh_scattering: [f64; 3],
+    /// Height at which the air density is divided by e
+    pub rayleigh_scale_height: f64,
+    pub mie_scattering: f64,
+    pub mie_scale_height: f64,
+    /// How much aerosols scatter the light forward, between 0 and 1
+    pub mie_anisotropy: f64,
+    /// Also scatter the light between the eye and the objects, far objects fading in
+    /// the color of the sky
+    pub height_fog: bool,
+    /// Samples along a view ray, and along the ray going from each of them to the sun
+    pub samples: u32,
+    pub light_samples: u32,
+}
+
+impl Default for Atmosphere {
+    fn default() -> Self {
+        Atmosphere {
+            sun_direction: Vec3::new(0.0, 0.3, 1.0),
+            sun_intensity: 20.0,
+            altitude: 1.0,
+            meters_per_unit: 1.0,
+            planet_radius: 6_360e3,
+            atmosphere_thickness: 60e3,
+            rayleigh_scattering: [5.8e-6, 13.5e-6, 33.1e-6],
+            rayleigh_scale_height: 7_994.0,
+            mie_scattering: 21e-6,
+            mie_scale_height: 1_200.0,
+            mie_anisotropy: 0.76,
+            height_fog: true,
+            samples: 16,
+            light_samples: 8,
+        }
+    }
+}
+
+/// Distances along a ray to the two points where it crosses a sphere centered on the
+/// origin, none if it misses it
+fn sphere_intersections(origin: Vec3, direction: Vec3, radius: f64) -> Option<(f64, f64)> {
+    let b = origin.dot_product(direction);
+    let c = origin.dot_product(origin) - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    Some((-b - root, -b + root))
+}
+
+impl Atmosphere {
+    /// Light seen along the ray leaving the scene origin in `direction`, as seen
+    /// in the background of the scene
+    pub fn sky_color(&self, direction: Vec3) -> Color {
+        let direction = direction.normalize();
+        let origin = self.planet_position(Vec3::new(0.0, 0.0, 0.0));
+        let top_radius = self.planet_radius + self.atmosphere_thickness;
+        let mut distance = match sphere_intersections(origin, direction, top_radius) {
+            Some((_, exit)) if exit > 0.0 => exit,
+            _ => return Color::BLACK,
+        };
+        // The ground hides the sky below the horizon
+        if let Some((ground, _)) = sphere_intersections(origin, direction, self.planet_radius) {
+            if ground > 0.0 {
+                distance = ground;
+            }
+        }
+        let (inscattered, _) = self.single_scattering(origin, direction, distance);
+        inscattered
+    }
+
+    /// Color of an object seen through the air between `eye` and the object `point`,
+    /// both in scene coordinates
+    pub fn aerial_perspective(&self, color: Color, eye: Vec3, point: Vec3) -> Color {
+        let origin = self.planet_position(eye);
+        let segment = Vec3::between_points(origin, self.planet_position(point));
+        let distance = segment.norm();
+        if distance <= 0.0 {
+            return color;
+        }
+        let (inscattered, transmittance) =
+            self.single_scattering(origin, segment.normalize(), distance);
+        transmittance * color + inscattered
+    }
+
+    fn planet_position(&self, scene_point: Vec3) -> Vec3 {
+        self.meters_per_unit * scene_point + Vec3::new(0.0, self.planet_radius + self.altitude, 0.0)
+    }
+
+    /// Relative density of the air molecules and aerosols at a position around the planet
+    fn densities(&self, position: Vec3) -> (f64, f64) {
+        let height = (position.norm() - self.planet_radius).max(0.0);
+        (
+            (-height / self.rayleigh_scale_height).exp(),
+            (-height / self.mie_scale_height).exp(),
+        )
+    }
+
+    /// Densities integrated along the ray going from a position to the sun, none if the
+    /// planet hides the sun
+    fn sun_optical_depth(&self, position: Vec3, sun_direction: Vec3) -> Option<(f64, f64)> {
+        if let Some((ground, _)) = sphere_intersections(position, sun_direction, self.planet_radius)
+        {
+            if ground > 0.0 {
+                return None;
+            }
+        }
+        let top_radius = self.planet_radius + self.atmosphere_thickness;
+        let (_, exit) = sphere_intersections(position, sun_direction, top_radius)?;
+        let samples = self.light_samples.max(1);
+        let step = exit.max(0.0) / samples as f64;
+        let mut depth = (0.0, 0.0);
+        for index in 0..samples {
+            let sample = position + ((index as f64 + 0.5) * step) * sun_direction;
+            let (rayleigh, mie) = self.densities(sample);
+            depth = (depth.0 + rayleigh * step, depth.1 + mie * step);
+        }
+        Some(depth)
+    }
+
+    /// Part of each color channel of the light going through air of some optical depth
+    fn transmittance(&self, (rayleigh_depth, mie_depth): (f64, f64)) -> [f64; 3] {
+        let mut transmittance = [0.0; 3];
+        for (channel, value) in transmittance.iter_mut().enumerate() {
+            *value = (-(self.rayleigh_scattering[channel] * rayleigh_depth
+                + MIE_EXTINCTION_RATIO * self.mie_scattering * mie_depth))
+                .exp();
+        }
+        transmittance
+    }
+
+    /// Sun light scattered towards the origin along a ray up to `distance`, with the
+    /// part of the light from the end of the ray still reaching the origin
+    fn single_scattering(&self, origin: Vec3, direction: Vec3, distance: f64) -> (Color, Color) {
+        let sun_direction = self.sun_direction.normalize();
+        let samples = self.samples.max(1);
+        let step = distance / samples as f64;
+        let mut view_depth = (0.0, 0.0);
+        let mut rayleigh_sum = [0.0; 3];
+        let mut mie_sum = [0.0; 3];
+        for index in 0..samples {
+            let sample = origin + ((index as f64 + 0.5) * step) * direction;
+            let (rayleigh, mie) = self.densities(sample);
+            view_depth = (view_depth.0 + rayleigh * step, view_depth.1 + mie * step);
+            let sun_depth = match self.sun_optical_depth(sample, sun_direction) {
+                Some(sun_depth) => sun_depth,
+                None => continue,
+            };
+            let attenuation =
+                self.transmittance((view_depth.0 + sun_depth.0, view_depth.1 + sun_depth.1));
+            for channel in 0..3 {
+                rayleigh_sum[channel] += attenuation[channel] * rayleigh * step;
+                mie_sum[channel] += attenuation[channel] * mie * step;
+            }
+        }
+        let cos_angle = direction.dot_product(sun_direction);
+        let rayleigh_phase = 3.0 / (16.0 * PI) * (1.0 + cos_angle * cos_angle);
+        let g = self.mie_anisotropy;
+        let mie_phase = 3.0 / (8.0 * PI) * ((1.0 - g * g) * (1.0 + cos_angle * cos_angle))
+            / ((2.0 + g * g) * (1.0 + g * g - 2.0 * g * cos_angle).powf(1.5));
+        let mut inscattered = [0.0; 3];
+        for (channel, value) in inscattered.iter_mut().enumerate() {
+            *value = self.sun_intensity
+                * (rayleigh_sum[channel] * self.rayleigh_scattering[channel] * rayleigh_phase
+                    + mie_sum[channel] * self.mie_scattering * mie_phase);
+        }
+        let [red, green, blue] = self.transmittance(view_depth);
+        (
+            Color::unclamped(inscattered[0], inscattered[1], inscattered[2]),
+            Color::new(red, green, blue),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noon_sky_is_blue() {
+        let atmosphere = Atmosphere {
+            sun_direction: Vec3::new(0.0, 1.0, 0.0),
+            ..Default::default()
+        };
+        let zenith = atmosphere.sky_color(Vec3::new(0.0, 1.0, 0.0));
+        assert!(zenith.blue() > zenith.green() && zenith.green() > zenith.red());
+    }
+
+    #[test]
+    fn sunset_horizon_is_red_and_dimmer_than_noon() {
+        let sunset = Atmosphere {
+            sun_direction: Vec3::new(0.0, 0.02, 1.0),
+            ..Default::default()
+        };
+        let noon = Atmosphere {
+            sun_direction: Vec3::new(0.0, 1.0, 0.0),
+            ..Default::default()
+        };
+        let direction = Vec3::new(0.0, 0.05, 1.0);
+        let horizon = sunset.sky_color(direction);
+        assert!(horizon.red() > horizon.blue());
+        assert!(horizon.blue() < noon.sky_color(direction).blue());
+    }
+
+    #[test]
+    fn far_objects_fade_more_in_the_haze() {
+        let atmosphere = Atmosphere {
+            meters_per_unit: 1000.0,
+            ..Default::default()
+        };
+        let eye = Vec3::new(0.0, 0.0, 0.0);
+        let seen_at = |color: Color, distance: f64| {
+            atmosphere.aerial_perspective(color, eye, Vec3::new(0.0, 0.0, distance))
+        };
+        // Less of the object color goes through, more of the sky color is added
+        let transmitted_at =
+            |distance| seen_at(Color::RED, distance).red() - seen_at(Color::BLACK, distance).red();
+        assert!(transmitted_at(1.0) < 1.0);
+        assert!(transmitted_at(50.0) < transmitted_at(1.0));
+        assert!(seen_at(Color::BLACK, 50.0).blue() > seen_at(Color::BLACK, 1.0).blue());
+        assert_eq!(
+            atmosphere.aerial_perspective(Color::RED, eye, eye).red(),
+            1.0
+        );
+    }
+}
@@ -0,0 +1,473 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Scenes built in code without a description, every part having a default. The builder
+//! writes the description of what it is given, so that the scene can be written back with
+//! `Scene::to_toml`, except when a camera, an instance, or a shape, texture or light of a
+//! type it does not know is given:
+//!
+//! ```
+//! use raytracer::builder::SceneBuilder;
+//! use raytracer::colors::Color;
+//! use raytracer::textures::Phong;
+//! use raytracer::vector::Vec3;
+//!
+//! let scene = SceneBuilder::new()
+//!     .add_sphere(Vec3::new(0.0, 0.0, 0.0), 5.0)
+//!     .colored(Color::RED)
+//!     .with_phong(Phong::default())
+//!     .add_plane(Vec3::new(0.0, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0))
+//!     .build();
+//! assert_eq!(scene.objects.len(), 2);
+//! ```
+
+use crate::cameras::PerspectiveCamera;
+use crate::colors::Color;
+use crate::lights::{AnyLightObject, LightDirectional, LightPoint};
+use crate::primitives::{AABox, InfinitePlan, Shape, Sphere, TransformedShape};
+use crate::scene::{RayEmitter, Scene, SceneConfiguration, SceneObject};
+use crate::textures::{
+    CheckedPattern, CloudTexture, GradientColorTexture, Mirror, Phong, PlainColorTexture, Texture,
+    TextureEffects, Transparency,
+};
+use crate::vector::{Mat3, Vec3};
+use serde::Serialize;
+use std::any::Any;
+use std::f64::consts::FRAC_PI_4;
+use std::sync::Arc;
+
+/// Scene being built, seen by the default perspective camera unless another one is given,
+/// and lit by a white light from above when no light is added
+#[derive(Default)]
+pub struct SceneBuilder {
+    camera: Option<Box<dyn RayEmitter>>,
+    lights: Vec<Box<dyn AnyLightObject>>,
+    objects: Vec<SceneObject>,
+    config: SceneConfiguration,
+    /// Description of each light and object, none when it cannot be described
+    light_descriptions: Vec<Option<toml::Value>>,
+    object_descriptions: Vec<Option<toml::Value>>,
+}
+
+impl SceneBuilder {
+    pub fn new() -> SceneBuilder {
+        SceneBuilder::default()
+    }
+
+    pub fn camera<C: RayEmitter + 'static>(mut self, camera: C) -> SceneBuilder {
+        self.camera = Some(Box::new(camera));
+        self
+    }
+
+    pub fn config(mut self, config: SceneConfiguration) -> SceneBuilder {
+        self.config = config;
+        self
+    }
+
+    pub fn light<L: AnyLightObject + 'static>(mut self, light: L) -> SceneBuilder {
+        let light_any: &dyn Any = &light;
+        let description = tagged("point", light_any.downcast_ref::<LightPoint>());
+        self.light_descriptions
+            .push(description.map(toml::Value::Table));
+        self.lights.push(Box::new(light));
+        self
+    }
+
+    /// Object of any shape, white until its texture is given
+    pub fn add_object<S: Shape + 'static>(self, shape: S) -> ObjectBuilder {
+        let shape_any: &dyn Any = &shape;
+        let description = tagged("sphere", shape_any.downcast_ref::<Sphere>());
+        self.add_described_object(shape, description)
+    }
+
+    fn add_described_object<S: Shape + 'static>(
+        self,
+        shape: S,
+        shape_description: Option<toml::value::Table>,
+    ) -> ObjectBuilder {
+        ObjectBuilder {
+            scene: self,
+            shape: Box::new(shape),
+            texture: Box::new(PlainColorTexture::default()),
+            effects: TextureEffects::default(),
+            shape_description,
+            texture_description: tagged("plain", Some(&PlainColorTexture::default())),
+        }
+    }
+
+    pub fn add_sphere(self, center: Vec3, radius: f64) -> ObjectBuilder {
+        self.add_object(Sphere { center, radius })
+    }
+
+    pub fn add_box(self, corner_1: Vec3, corner_2: Vec3) -> ObjectBuilder {
+        let description = [
+            ("type", value("box")),
+            ("min", value(corner_1)),
+            ("max", value(corner_2)),
+        ];
+        self.add_described_object(AABox::new(corner_1, corner_2), Some(table(&description)))
+    }
+
+    pub fn add_plane(self, center: Vec3, normal: Vec3) -> ObjectBuilder {
+        let description = [
+            ("type", value("infinite_plan")),
+            ("center", value(center)),
+            ("normal", value(normal)),
+        ];
+        self.add_described_object(InfinitePlan::new(center, normal), Some(table(&description)))
+    }
+
+    /// Copy of a shape shared with other objects, moved from the origin of the shape to
+    /// `position` after being rotated by angles in degrees and scaled. Each part of a
+    /// composite object is added with its own shared shape.
+    pub fn add_instance(
+        self,
+        shape: &Arc<dyn Shape>,
+        position: Vec3,
+        rotation_degree: Vec3,
+        scale: f64,
+    ) -> ObjectBuilder {
+        let rotation = rotation_of(rotation_degree);
+        let shape = TransformedShape::new(Arc::clone(shape), position, rotation, scale);
+        self.add_described_object(shape, None)
+    }
+
+    pub fn build(mut self) -> Scene {
+        if self.lights.is_empty() {
+            let (direction, color) = (Vec3::new(-1.0, -2.0, 1.0), Color::WHITE);
+            self.lights
+                .push(Box::new(LightDirectional::new(direction, color.clone())));
+            let description = [
+                ("type", value("directional")),
+                ("direction", value(direction)),
+                ("color", value(color)),
+            ];
+            self.light_descriptions
+                .push(Some(toml::Value::Table(table(&description))));
+        }
+        let description = self.description();
+        Scene {
+            camera: self
+                .camera
+                .unwrap_or_else(|| Box::new(PerspectiveCamera::default())),
+            lights: self.lights,
+            objects: self.objects.into(),
+            config: self.config,
+            warnings: Vec::new(),
+            description,
+        }
+    }
+
+    /// Description of the scene, none if one of its parts cannot be described
+    fn description(&self) -> Option<toml::value::Table> {
+        if self.camera.is_some() {
+            return None;
+        }
+        // The angle of the default camera, given to it as it is by the parser
+        let camera = [
+            ("type", value("perspective")),
+            ("screen_center", value(Vec3::new(0.0, 0.0, -50.0))),
+            ("look_at", value(Vec3::new(0.0, 0.0, 50.0))),
+            ("width", value(16.0)),
+            ("height", value(9.0)),
+            ("angle_degree", value(FRAC_PI_4)),
+        ];
+        let lights = self
+            .light_descriptions
+            .iter()
+            .cloned()
+            .collect::<Option<_>>()?;
+        let objects = self
+            .object_descriptions
+            .iter()
+            .cloned()
+            .collect::<Option<_>>()?;
+        Some(table(&[
+            ("camera", toml::Value::Table(table(&camera))),
+            ("light", toml::Value::Array(lights)),
+            ("object", toml::Value::Array(objects)),
+        ]))
+    }
+}
+
+/// Object being added to a scene, added when the next one is started or when the scene
+/// is built
+pub struct ObjectBuilder {
+    scene: SceneBuilder,
+    shape: Box<dyn Shape>,
+    texture: Box<dyn Texture>,
+    effects: TextureEffects,
+    shape_description: Option<toml::value::Table>,
+    texture_description: Option<toml::value::Table>,
+}
+
+impl ObjectBuilder {
+    pub fn textured<T: Texture + 'static>(mut self, texture: T) -> ObjectBuilder {
+        let texture_any: &dyn Any = &texture;
+        self.texture_description = tagged("plain", texture_any.downcast_ref::<PlainColorTexture>())
+            .or_else(|| tagged("checked", texture_any.downcast_ref::<CheckedPattern>()))
+            .or_else(|| {
+                tagged(
+                    "gradient",
+                    texture_any.downcast_ref::<GradientColorTexture>(),
+                )
+            })
+            .or_else(|| tagged("clouds", texture_any.downcast_ref::<CloudTexture>()));
+        self.texture = Box::new(texture);
+        self
+    }
+
+    pub fn colored(self, color: Color) -> ObjectBuilder {
+        self.textured(PlainColorTexture { color })
+    }
+
+    /// Moved from the origin of its shape to `position`, after being rotated by angles in
+    /// degrees around the x, y and z axes and scaled
+    pub fn transformed(mut self, position: Vec3, rotation_degree: Vec3, scale: f64) -> Self {
+        let rotation = rotation_of(rotation_degree);
+        let shape = Arc::from(self.shape);
+        self.shape = Box::new(TransformedShape::new(shape, position, rotation, scale));
+        // A description moves a shape only once
+        self.shape_description = self
+            .shape_description
+            .filter(|description| !description.contains_key("position"))
+            .map(|mut description| {
+                description.insert("position".to_string(), value(position));
+                description.insert("rotation_degree".to_string(), value(rotation_degree));
+                description.insert("scale".to_string(), value(scale));
+                description
+            });
+        self
+    }
+
+    pub fn with_phong(mut self, phong: Phong) -> ObjectBuilder {
+        self.effects.phong = Some(phong);
+        self
+    }
+
+    pub fn with_mirror(mut self, mirror: Mirror) -> ObjectBuilder {
+        self.effects.mirror = Some(mirror);
+        self
+    }
+
+    pub fn with_transparency(mut self, transparency: Transparency) -> ObjectBuilder {
+        self.effects.transparency = Some(transparency);
+        self
+    }
+
+    /// Every effect at once, replacing the ones given before
+    pub fn with_effects(mut self, effects: TextureEffects) -> ObjectBuilder {
+        self.effects = effects;
+        self
+    }
+
+    /// Scene with this object added, to go on with the scene
+    pub fn done(self) -> SceneBuilder {
+        let mut scene = self.scene;
+        let description = match (self.shape_description, self.texture_description) {
+            (Some(mut description), Some(texture)) => {
+                description.insert("texture".to_string(), toml::Value::Table(texture));
+                description.insert("effect".to_string(), value(&self.effects));
+                Some(toml::Value::Table(description))
+            }
+            _ => None,
+        };
+        scene.object_descriptions.push(description);
+        scene.objects.push(SceneObject {
+            shape: self.shape,
+            texture: self.texture,
+            effects: self.effects.with_preset(),
+        });
+        scene
+    }
+
+    pub fn add_object<S: Shape + 'static>(self, shape: S) -> ObjectBuilder {
+        self.done().add_object(shape)
+    }
+
+    pub fn add_sphere(self, center: Vec3, radius: f64) -> ObjectBuilder {
+        self.done().add_sphere(center, radius)
+    }
+
+    pub fn add_box(self, corner_1: Vec3, corner_2: Vec3) -> ObjectBuilder {
+        self.done().add_box(corner_1, corner_2)
+    }
+
+    pub fn add_plane(self, center: Vec3, normal: Vec3) -> ObjectBuilder {
+        self.done().add_plane(center, normal)
+    }
+
+    pub fn add_instance(
+        self,
+        shape: &Arc<dyn Shape>,
+        position: Vec3,
+        rotation_degree: Vec3,
+        scale: f64,
+    ) -> ObjectBuilder {
+        self.done()
+            .add_instance(shape, position, rotation_degree, scale)
+    }
+
+    pub fn light<L: AnyLightObject + 'static>(self, light: L) -> SceneBuilder {
+        self.done().light(light)
+    }
+
+    pub fn build(self) -> Scene {
+        self.done().build()
+    }
+}
+
+/// Part of a description, of the type `kind`, none without a value
+fn tagged<T: Serialize>(kind: &str, part: Option<&T>) -> Option<toml::value::Table> {
+    match value(part?) {
+        toml::Value::Table(mut description) => {
+            description.insert("type".to_string(), value(kind));
+            Some(description)
+        }
+        _ => None,
+    }
+}
+
+fn value<T: Serialize>(part: T) -> toml::Value {
+    toml::Value::try_from(part).expect("Builder parts always serialize")
+}
+
+fn table(entries: &[(&str, toml::Value)]) -> toml::value::Table {
+    entries
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.clone()))
+        .collect()
+}
+
+/// Rotation of angles in degrees around the x, then y, then z axis
+fn rotation_of(rotation_degree: Vec3) -> Mat3 {
+    Mat3::rotation(Vec3::new(
+        rotation_degree.x.to_radians(),
+        rotation_degree.y.to_radians(),
+        rotation_degree.z.to_radians(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lights::LightPoint;
+    use crate::renderer::{render_scene, RenderConfiguration};
+    use std::str::FromStr;
+
+    #[test]
+    fn objects_get_their_effects_and_a_default_light() {
+        let scene = SceneBuilder::new()
+            .add_sphere(Vec3::new(0.0, 0.0, 0.0), 5.0)
+            .with_mirror(Mirror::default())
+            .add_box(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0))
+            .transformed(Vec3::new(8.0, 0.0, 0.0), Vec3::new(0.0, 45.0, 0.0), 2.0)
+            .build();
+        assert_eq!(scene.objects.len(), 2);
+        assert_eq!(scene.lights.len(), 1);
+        assert!(scene.objects[0].effects().mirror.is_some());
+        assert!(scene.objects[1].effects().mirror.is_none());
+        let aabb = scene.objects[1].aabb().unwrap();
+        assert!(aabb.min.x > 5.0 && aabb.max.x < 11.0);
+    }
+
+    #[test]
+    fn instances_share_their_shape() {
+        let shape: Arc<dyn Shape> = Arc::new(Sphere {
+            center: Vec3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+        });
+        let scene = (0..3)
+            .fold(SceneBuilder::new(), |scene, i| {
+                let position = Vec3::new(4.0 * i as f64, 0.0, 0.0);
+                scene
+                    .add_instance(&shape, position, Vec3::default(), 1.0)
+                    .done()
+            })
+            .build();
+        assert_eq!(scene.objects.len(), 3);
+        assert_eq!(Arc::strong_count(&shape), 4);
+        let aabb = scene.objects[2].aabb().unwrap();
+        assert!((aabb.center().x - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn built_scene_is_written_back() {
+        let scene = SceneBuilder::new()
+            .add_sphere(Vec3::new(0.0, 0.0, 0.0), 5.0)
+            .colored(Color::RED)
+            .with_phong(Phong::default())
+            .add_box(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0))
+            .textured(CheckedPattern::default())
+            .transformed(Vec3::new(8.0, 0.0, 0.0), Vec3::new(0.0, 45.0, 0.0), 2.0)
+            .add_plane(Vec3::new(0.0, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0))
+            .light(LightPoint::new(Vec3::new(0.0, 0.0, -40.0)))
+            .build();
+        let written = Scene::from_str(&scene.to_toml().unwrap()).unwrap();
+        assert_eq!(written.objects.len(), 3);
+        assert_eq!(written.lights.len(), 1);
+        assert!(written.objects[0].effects().phong.is_some());
+        let aabb = written.objects[1].aabb().unwrap();
+        assert!(aabb.min.x > 5.0 && aabb.max.x < 11.0);
+        let ray = written.camera.generate_ray(0.2, 0.7);
+        let expected = scene.camera.generate_ray(0.2, 0.7);
+        assert_eq!(ray.source, expected.source);
+        assert_eq!(ray.direction, expected.direction);
+    }
+
+    #[test]
+    fn scene_of_unknown_parts_is_not_written() {
+        let scene = SceneBuilder::new()
+            .camera(PerspectiveCamera::default())
+            .add_sphere(Vec3::new(0.0, 0.0, 0.0), 5.0)
+            .build();
+        assert!(scene.to_toml().is_err());
+        let shape: Arc<dyn Shape> = Arc::new(Sphere::default());
+        let scene = SceneBuilder::new()
+            .add_instance(&shape, Vec3::default(), Vec3::default(), 1.0)
+            .build();
+        assert!(scene.to_toml().is_err());
+    }
+
+    #[test]
+    fn built_scene_is_rendered() {
+        let scene = SceneBuilder::new()
+            .add_sphere(Vec3::new(0.0, 0.0, 0.0), 20.0)
+            .colored(Color::RED)
+            .light(LightPoint::new(Vec3::new(0.0, 0.0, -40.0)))
+            .build();
+        let config = RenderConfiguration {
+            canvas_width: 8,
+            canvas_height: 8,
+            ..Default::default()
+        };
+        let pixels: Vec<_> = render_scene(scene, config).unwrap().collect();
+        let center = pixels
+            .iter()
+            .flatten()
+            .find(|p| (p.x, p.y) == (4, 4))
+            .unwrap();
+        assert!(center.color.red() > 0.5 && center.color.blue() < 1e-9);
+    }
+}
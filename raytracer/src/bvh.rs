@@ -0,0 +1,467 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Bounding volume hierarchies, so a ray is only tested against what it may hit.
+//!
+//! As with the acceleration structures of GPU ray tracing APIs, there are two levels: the
+//! top level one is over the scene objects, and is only refit when they move, while the
+//! bottom level ones are over the parts of a shape, like the triangles of a mesh, in the
+//! space of the shape so they are shared by all its transformed copies.
+
+use crate::primitives::Ray;
+use crate::stats::{count, Event};
+use crate::vector::Vec3;
+use serde::{Deserialize, Serialize};
+
+/// Axis-aligned bounding box
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Margin added around boxes, so flat objects still have a volume
+    const PADDING: f64 = 1e-9;
+
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Aabb { min, max }
+    }
+
+    /// Smallest box containing all the points
+    pub fn from_points<I: IntoIterator<Item = Vec3>>(points: I) -> Option<Self> {
+        points.into_iter().fold(None, |aabb: Option<Aabb>, point| {
+            let point_aabb = Aabb::new(point, point);
+            Some(aabb.map_or(point_aabb, |aabb| aabb.union(&point_aabb)))
+        })
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Vec3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    /// Distances along the ray where it enters and leaves the box, if it crosses it
+    pub fn intersect(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let mut t_enter = f64::NEG_INFINITY;
+        let mut t_leave = f64::INFINITY;
+        for axis in 0..3 {
+            let source = component(ray.source, axis);
+            let direction = component(ray.direction, axis);
+            let min = component(self.min, axis) - Self::PADDING;
+            let max = component(self.max, axis) + Self::PADDING;
+            if direction == 0.0 {
+                if source < min || source > max {
+                    return None;
+                }
+                continue;
+            }
+            let t_1 = (min - source) / direction;
+            let t_2 = (max - source) / direction;
+            t_enter = t_enter.max(t_1.min(t_2));
+            t_leave = t_leave.min(t_1.max(t_2));
+        }
+        if t_enter <= t_leave && t_leave >= 0.0 {
+            Some((t_enter.max(0.0), t_leave))
+        } else {
+            None
+        }
+    }
+
+    /// Whether the point is in the box grown by the margin
+    pub fn contains(&self, point: Vec3, margin: f64) -> bool {
+        (0..3).all(|axis| {
+            let value = component(point, axis);
+            component(self.min, axis) - margin <= value
+                && value <= component(self.max, axis) + margin
+        })
+    }
+
+    fn longest_axis(&self) -> usize {
+        let size = self.max - self.min;
+        if size.x >= size.y && size.x >= size.z {
+            0
+        } else if size.y >= size.z {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+fn component(vector: Vec3, axis: usize) -> f64 {
+    match axis {
+        0 => vector.x,
+        1 => vector.y,
+        _ => vector.z,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type")]
+enum BvhNode {
+    Leaf {
+        aabb: Aabb,
+        indexes: Vec<usize>,
+    },
+    Branch {
+        aabb: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl BvhNode {
+    fn aabb(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { aabb, .. } | BvhNode::Branch { aabb, .. } => aabb,
+        }
+    }
+}
+
+/// Hierarchy of object indexes; objects without bounding box, like infinite
+/// plans, are kept apart and always tested. It is serialized for the scene caches, so that
+/// the hierarchies of large meshes are not built again.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    unbounded: Vec<usize>,
+}
+
+impl Bvh {
+    const MAXIMUM_LEAF_SIZE: usize = 2;
+
+    /// Build the hierarchy from the bounding box of each object, if any
+    pub fn new(aabbs: &[Option<Aabb>]) -> Self {
+        let mut bvh = Bvh::default();
+        let mut bounded = Vec::new();
+        for (index, aabb) in aabbs.iter().enumerate() {
+            match aabb {
+                Some(aabb) => bounded.push((index, *aabb)),
+                None => bvh.unbounded.push(index),
+            }
+        }
+        if !bounded.is_empty() {
+            bvh.build_node(&mut bounded);
+        }
+        bvh
+    }
+
+    /// Median split on the longest axis of the object centers
+    fn build_node(&mut self, objects: &mut [(usize, Aabb)]) -> usize {
+        let aabb = objects
+            .iter()
+            .skip(1)
+            .fold(objects[0].1, |aabb, (_, other)| aabb.union(other));
+        if objects.len() <= Self::MAXIMUM_LEAF_SIZE {
+            self.nodes.push(BvhNode::Leaf {
+                aabb,
+                indexes: objects.iter().map(|(index, _)| *index).collect(),
+            });
+            return self.nodes.len() - 1;
+        }
+        let centers = Aabb::from_points(objects.iter().map(|(_, aabb)| aabb.center())).unwrap();
+        let axis = centers.longest_axis();
+        objects.sort_by(|(_, a), (_, b)| {
+            component(a.center(), axis)
+                .partial_cmp(&component(b.center(), axis))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let node_index = self.nodes.len();
+        // Placeholder, replaced when children are built
+        self.nodes.push(BvhNode::Leaf {
+            aabb,
+            indexes: Vec::new(),
+        });
+        let (left_objects, right_objects) = objects.split_at_mut(objects.len() / 2);
+        let left = self.build_node(left_objects);
+        let right = self.build_node(right_objects);
+        self.nodes[node_index] = BvhNode::Branch { aabb, left, right };
+        node_index
+    }
+
+    /// Update the boxes to the new bounding box of each object, keeping the tree as it
+    /// is. Fails, leaving the hierarchy unchanged, if the objects changed in number or
+    /// in being bounded, which needs a new hierarchy, or if the tree is not one of these
+    /// objects, such as a deserialized one from another scene.
+    pub fn refit(&mut self, aabbs: &[Option<Aabb>]) -> bool {
+        let object_count = self.unbounded.len()
+            + self
+                .nodes
+                .iter()
+                .map(|node| match node {
+                    BvhNode::Leaf { indexes, .. } => indexes.len(),
+                    BvhNode::Branch { .. } => 0,
+                })
+                .sum::<usize>();
+        let bounded_count = aabbs.iter().filter(|aabb| aabb.is_some()).count();
+        let node_count = self.nodes.len();
+        let same_tree = self
+            .nodes
+            .iter()
+            .enumerate()
+            .all(|(node_index, node)| match node {
+                BvhNode::Leaf { indexes, .. } => {
+                    !indexes.is_empty()
+                        && indexes
+                            .iter()
+                            .all(|&index| matches!(aabbs.get(index), Some(Some(_))))
+                }
+                BvhNode::Branch { left, right, .. } => [left, right]
+                    .iter()
+                    .all(|&&child| child > node_index && child < node_count),
+            });
+        let same_objects = object_count == aabbs.len()
+            && bounded_count + self.unbounded.len() == aabbs.len()
+            && self
+                .unbounded
+                .iter()
+                .all(|&index| matches!(aabbs.get(index), Some(None)));
+        if !(same_tree && same_objects) {
+            return false;
+        }
+        // Children are always after their parent, so are refit before it
+        for node_index in (0..self.nodes.len()).rev() {
+            let refit_aabb = match &self.nodes[node_index] {
+                BvhNode::Leaf { indexes, .. } => indexes
+                    .iter()
+                    .filter_map(|&index| aabbs[index])
+                    .reduce(|aabb, other| aabb.union(&other))
+                    .expect("leaves have bounded objects"),
+                BvhNode::Branch { left, right, .. } => {
+                    self.nodes[*left].aabb().union(self.nodes[*right].aabb())
+                }
+            };
+            match &mut self.nodes[node_index] {
+                BvhNode::Leaf { aabb, .. } | BvhNode::Branch { aabb, .. } => *aabb = refit_aabb,
+            }
+        }
+        true
+    }
+
+    /// Memory used by the hierarchy, in bytes
+    pub fn memory_size(&self) -> usize {
+        let leaf_indexes = self
+            .nodes
+            .iter()
+            .map(|node| match node {
+                BvhNode::Leaf { indexes, .. } => indexes.len(),
+                BvhNode::Branch { .. } => 0,
+            })
+            .sum::<usize>();
+        std::mem::size_of::<Self>()
+            + self.nodes.len() * std::mem::size_of::<BvhNode>()
+            + (leaf_indexes + self.unbounded.len()) * std::mem::size_of::<usize>()
+    }
+
+    /// Call the visitor with each object index whose bounding box is crossed by the ray
+    /// before the maximum distance. The visitor returns the new maximum distance, so
+    /// farther boxes can be skipped once an object is found.
+    pub fn visit<F>(&self, ray: &Ray, maximum_distance: f64, mut visitor: F)
+    where
+        F: FnMut(usize, f64) -> f64,
+    {
+        let mut maximum_distance = maximum_distance;
+        for &index in &self.unbounded {
+            maximum_distance = visitor(index, maximum_distance);
+        }
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            count(Event::BvhNodeVisit);
+            let node = &self.nodes[node_index];
+            match node.aabb().intersect(ray) {
+                Some((t_enter, _)) if t_enter <= maximum_distance => {}
+                _ => continue,
+            }
+            match node {
+                BvhNode::Leaf { indexes, .. } => {
+                    for &index in indexes {
+                        maximum_distance = visitor(index, maximum_distance);
+                    }
+                }
+                BvhNode::Branch { left, right, .. } => {
+                    stack.push(*right);
+                    stack.push(*left);
+                }
+            }
+        }
+    }
+
+    /// Call the visitor with each object index whose bounding box, grown by the margin,
+    /// contains the point
+    pub fn visit_containing<F>(&self, point: Vec3, margin: f64, mut visitor: F)
+    where
+        F: FnMut(usize),
+    {
+        for &index in &self.unbounded {
+            visitor(index);
+        }
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if !node.aabb().contains(point, margin) {
+                continue;
+            }
+            match node {
+                BvhNode::Leaf { indexes, .. } => indexes.iter().for_each(|&index| visitor(index)),
+                BvhNode::Branch { left, right, .. } => {
+                    stack.push(*right);
+                    stack.push(*left);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box_at(x: f64) -> Option<Aabb> {
+        Some(Aabb::new(
+            Vec3::new(x - 0.5, -0.5, -0.5),
+            Vec3::new(x + 0.5, 0.5, 0.5),
+        ))
+    }
+
+    #[test]
+    fn ray_box_intersection() {
+        let aabb = unit_box_at(0.0).unwrap();
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0));
+        let (t_enter, t_leave) = aabb.intersect(&ray).unwrap();
+        assert!((t_enter - 9.5).abs() < 1e-6 && (t_leave - 10.5).abs() < 1e-6);
+        let ray = Ray::new(Vec3::new(2.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(aabb.intersect(&ray).is_none());
+    }
+
+    #[test]
+    fn visit_only_crossed_objects() {
+        let aabbs = (0..10)
+            .map(|i| unit_box_at(2.0 * i as f64))
+            .collect::<Vec<_>>();
+        let bvh = Bvh::new(&aabbs);
+        let ray = Ray::new(Vec3::new(6.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut visited = Vec::new();
+        bvh.visit(&ray, f64::MAX, |index, distance| {
+            visited.push(index);
+            distance
+        });
+        // Only the leaf containing the crossed box is visited
+        assert!(visited.contains(&3));
+        assert!(visited.len() <= Bvh::MAXIMUM_LEAF_SIZE);
+    }
+
+    #[test]
+    fn refit_follows_moved_objects() {
+        let mut aabbs = (0..10)
+            .map(|i| unit_box_at(2.0 * i as f64))
+            .collect::<Vec<_>>();
+        let mut bvh = Bvh::new(&aabbs);
+        aabbs[3] = unit_box_at(-20.0);
+        assert!(bvh.refit(&aabbs));
+        let ray = Ray::new(Vec3::new(-20.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut visited = Vec::new();
+        bvh.visit(&ray, f64::MAX, |index, distance| {
+            visited.push(index);
+            distance
+        });
+        assert!(visited.contains(&3));
+        let root_aabb = bvh.nodes[0].aabb();
+        assert!((root_aabb.min.x + 20.5).abs() < 1e-6 && (root_aabb.max.x - 18.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn refit_fails_when_objects_change() {
+        let mut aabbs = vec![None, unit_box_at(0.0), unit_box_at(2.0)];
+        let mut bvh = Bvh::new(&aabbs);
+        assert!(!bvh.refit(&aabbs[1..]));
+        aabbs[1] = None;
+        assert!(!bvh.refit(&aabbs));
+        aabbs[0] = unit_box_at(4.0);
+        assert!(!bvh.refit(&aabbs));
+    }
+
+    #[test]
+    fn deserialized_tree_of_other_objects_is_not_refit() {
+        let aabbs = (0..10)
+            .map(|i| unit_box_at(2.0 * i as f64))
+            .collect::<Vec<_>>();
+        let value = serde_json::to_value(Bvh::new(&aabbs)).unwrap();
+        let mut bvh: Bvh = serde_json::from_value(value.clone()).unwrap();
+        assert!(bvh.refit(&aabbs));
+        assert!(!bvh.refit(&aabbs[..9]));
+        // A child before its parent would loop forever
+        let mut looping = value;
+        looping["nodes"][0]["left"] = 0.into();
+        let mut bvh: Bvh = serde_json::from_value(looping).unwrap();
+        assert!(!bvh.refit(&aabbs));
+    }
+
+    #[test]
+    fn visit_only_boxes_containing_point() {
+        let aabbs = (0..10)
+            .map(|i| unit_box_at(2.0 * i as f64))
+            .collect::<Vec<_>>();
+        let bvh = Bvh::new(&aabbs);
+        let mut visited = Vec::new();
+        bvh.visit_containing(Vec3::new(4.6, 0.0, 0.0), 0.2, |index| visited.push(index));
+        assert_eq!(visited, vec![2]);
+        visited.clear();
+        bvh.visit_containing(Vec3::new(5.0, 0.0, 0.0), 0.2, |index| visited.push(index));
+        assert!(visited.is_empty());
+    }
+
+    #[test]
+    fn unbounded_objects_are_always_visited() {
+        let bvh = Bvh::new(&[None, unit_box_at(0.0)]);
+        let ray = Ray::new(Vec3::new(6.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut visited = Vec::new();
+        bvh.visit(&ray, f64::MAX, |index, distance| {
+            visited.push(index);
+            distance
+        });
+        assert_eq!(visited, vec![0]);
+    }
+}
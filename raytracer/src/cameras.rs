@@ -24,10 +24,89 @@ SOFTWARE.
 
 use crate::primitives::Ray;
 use crate::scene::RayEmitter;
+use crate::textures::Texture;
 use crate::utils::{f64_gt, f64_lt};
 use crate::vector::{Mat3, Vec3};
 use crate::UnitInterval;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Attempts to draw a lens point accepted by the bokeh texture, before taking any point
+const BOKEH_TEXTURE_ATTEMPTS: u32 = 16;
+
+/// Shape of the lens aperture, which out of focus highlights take
+#[derive(Default)]
+pub struct Bokeh {
+    /// Straight blades of the diaphragm giving a polygon, a circle if none or less than 3
+    pub blades: Option<u32>,
+    /// Rotation of the polygon around the view axis, in radians
+    pub rotation: f64,
+    /// Brightness of the texture weighting each point of the lens, u and v going along
+    /// its width and height
+    pub texture: Option<Box<dyn Texture>>,
+}
+
+impl fmt::Debug for Bokeh {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("Bokeh")
+            .field("blades", &self.blades)
+            .field("rotation", &self.rotation)
+            .field("texture", &self.texture.is_some())
+            .finish()
+    }
+}
+
+impl Bokeh {
+    /// Random point of a lens of radius 1, centered on the origin
+    pub fn sample_unit_lens<R: Rng + ?Sized>(&self, rng: &mut R) -> (f64, f64) {
+        let texture = match &self.texture {
+            Some(texture) => texture,
+            None => return self.sample_shape(rng),
+        };
+        let mut point = self.sample_shape(rng);
+        for _ in 1..BOKEH_TEXTURE_ATTEMPTS {
+            let u = ((point.0 + 1.0) / 2.0).clamp(1e-6, 1.0 - 1e-6);
+            let v = ((1.0 - point.1) / 2.0).clamp(1e-6, 1.0 - 1e-6);
+            let color = texture.color_at(u, v);
+            let weight = (color.red() + color.green() + color.blue()) / 3.0;
+            if rng.gen::<f64>() < weight {
+                break;
+            }
+            point = self.sample_shape(rng);
+        }
+        point
+    }
+
+    fn sample_shape<R: Rng + ?Sized>(&self, rng: &mut R) -> (f64, f64) {
+        match self.blades.filter(|&blades| blades >= 3) {
+            None => {
+                let radius = rng.gen::<f64>().sqrt();
+                let angle = 2.0 * PI * rng.gen::<f64>();
+                (radius * angle.cos(), radius * angle.sin())
+            }
+            Some(blades) => {
+                // Uniform point of one of the triangles between the center and two corners
+                let corner = |index: u32| {
+                    let angle = self.rotation + 2.0 * PI * index as f64 / blades as f64;
+                    (angle.cos(), angle.sin())
+                };
+                let index = rng.gen_range(0, blades);
+                let (first, second) = (corner(index), corner(index + 1));
+                let (mut a, mut b) = (rng.gen::<f64>(), rng.gen::<f64>());
+                if a + b > 1.0 {
+                    a = 1.0 - a;
+                    b = 1.0 - b;
+                }
+                (a * first.0 + b * second.0, a * first.1 + b * second.1)
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct PerspectiveCamera {
@@ -38,6 +117,13 @@ pub struct PerspectiveCamera {
     axis_x: Vec3,
     axis_y: Vec3,
     axis_z: Vec3,
+    near_clip: Option<f64>,
+    far_clip: Option<f64>,
+    /// Radius of the lens, 0 for a pinhole camera where everything is in focus
+    aperture_radius: f64,
+    /// Distance from the eye of the plane in focus
+    focal_distance: f64,
+    bokeh: Bokeh,
 }
 
 impl PerspectiveCamera {
@@ -63,6 +149,80 @@ impl PerspectiveCamera {
             axis_x,
             axis_y,
             axis_z,
+            near_clip: None,
+            far_clip: None,
+            aperture_radius: 0.0,
+            focal_distance: distance_eye_center,
+            bokeh: Default::default(),
+        }
+    }
+
+    /// Blur what is not at `focal_distance` from the eye, rays starting from random
+    /// points of the lens, so several rays per pixel are needed to get a smooth blur
+    pub fn with_depth_of_field(self, aperture_radius: f64, focal_distance: f64) -> Self {
+        PerspectiveCamera {
+            aperture_radius,
+            focal_distance,
+            ..self
+        }
+    }
+
+    pub fn with_bokeh(self, bokeh: Bokeh) -> Self {
+        PerspectiveCamera { bokeh, ..self }
+    }
+
+    pub fn focal_distance(&self) -> f64 {
+        self.focal_distance
+    }
+
+    /// Random point of the lens, relative to its center
+    fn sample_lens(&self, rng: &mut dyn RngCore) -> Vec3 {
+        let (x, y) = self.bokeh.sample_unit_lens(rng);
+        self.aperture_radius * (x * self.axis_x + y * self.axis_y)
+    }
+
+    /// Ray through a random point of the lens, toward the focal plane
+    fn lens_ray(
+        &self,
+        canvas_x: UnitInterval,
+        canvas_y: UnitInterval,
+        rng: &mut dyn RngCore,
+    ) -> Ray {
+        assert!(
+            f64_lt(canvas_x, 1.0) && f64_gt(canvas_x, 0.0),
+            "canvas_x is: {}",
+            canvas_x
+        );
+        assert!(
+            f64_lt(canvas_y, 1.0) && f64_gt(canvas_y, 0.0),
+            "canvas_y is: {}",
+            canvas_y
+        );
+        let ray_destination = self.screen_center - (self.width / 2.0) * self.axis_x
+            + (self.height / 2.0) * self.axis_y
+            + canvas_x * self.width * self.axis_x
+            - canvas_y * self.height * self.axis_y;
+        if self.aperture_radius <= 0.0 {
+            return Ray::ray_from_to(self.eye, ray_destination);
+        }
+        // Every ray going through the same point of the focal plane, it stays sharp
+        let direction = Vec3::between_points(self.eye, ray_destination).normalize();
+        let focus_point =
+            self.eye + (self.focal_distance / direction.dot_product(self.axis_z)) * direction;
+        Ray::ray_from_to(self.eye + self.sample_lens(rng), focus_point)
+    }
+
+    pub fn with_near_clip(self, near_clip: f64) -> Self {
+        PerspectiveCamera {
+            near_clip: Some(near_clip),
+            ..self
+        }
+    }
+
+    pub fn with_far_clip(self, far_clip: f64) -> Self {
+        PerspectiveCamera {
+            far_clip: Some(far_clip),
+            ..self
         }
     }
 }
@@ -89,21 +249,40 @@ impl RayEmitter for PerspectiveCamera {
     }
 
     fn generate_ray(&self, canvas_x: UnitInterval, canvas_y: UnitInterval) -> Ray {
-        assert!(
-            f64_lt(canvas_x, 1.0) && f64_gt(canvas_x, 0.0),
-            "canvas_x is: {}",
-            canvas_x
-        );
-        assert!(
-            f64_lt(canvas_y, 1.0) && f64_gt(canvas_y, 0.0),
-            "canvas_y is: {}",
-            canvas_y
-        );
-        let ray_destination = self.screen_center - (self.width / 2.0) * self.axis_x
-            + (self.height / 2.0) * self.axis_y
-            + canvas_x * self.width * self.axis_x
-            - canvas_y * self.height * self.axis_y;
-        Ray::ray_from_to(self.eye, ray_destination)
+        self.lens_ray(canvas_x, canvas_y, &mut rand::thread_rng())
+    }
+
+    fn sample_ray(
+        &self,
+        canvas_x: UnitInterval,
+        canvas_y: UnitInterval,
+        time: f64,
+        rng: &mut dyn RngCore,
+    ) -> Ray {
+        self.lens_ray(canvas_x, canvas_y, rng).at_time(time)
+    }
+
+    /// Seen from the center of the lens
+    fn canvas_position(&self, point: Vec3) -> Option<(f64, f64)> {
+        let direction = Vec3::between_points(self.eye, point);
+        let depth = direction.dot_product(self.axis_z);
+        if depth <= 0.0 {
+            return None;
+        }
+        let screen_distance = Vec3::between_points(self.eye, self.screen_center).norm();
+        let on_screen = self.eye + (screen_distance / depth) * direction - self.screen_center;
+        Some((
+            0.5 + on_screen.dot_product(self.axis_x) / self.width,
+            0.5 - on_screen.dot_product(self.axis_y) / self.height,
+        ))
+    }
+
+    fn near_clip(&self) -> Option<f64> {
+        self.near_clip
+    }
+
+    fn far_clip(&self) -> Option<f64> {
+        self.far_clip
     }
 }
 
@@ -115,6 +294,8 @@ pub struct OrthogonalCamera {
     axis_x: Vec3,
     axis_y: Vec3,
     axis_z: Vec3,
+    near_clip: Option<f64>,
+    far_clip: Option<f64>,
 }
 
 impl OrthogonalCamera {
@@ -130,6 +311,22 @@ impl OrthogonalCamera {
             axis_z,
             width,
             height,
+            near_clip: None,
+            far_clip: None,
+        }
+    }
+
+    pub fn with_near_clip(self, near_clip: f64) -> Self {
+        OrthogonalCamera {
+            near_clip: Some(near_clip),
+            ..self
+        }
+    }
+
+    pub fn with_far_clip(self, far_clip: f64) -> Self {
+        OrthogonalCamera {
+            far_clip: Some(far_clip),
+            ..self
         }
     }
 }
@@ -170,4 +367,482 @@ impl RayEmitter for OrthogonalCamera {
             - canvas_y * self.height * self.axis_y;
         Ray::new(ray_source, self.axis_z)
     }
+
+    fn canvas_position(&self, point: Vec3) -> Option<(f64, f64)> {
+        let offset = Vec3::between_points(self.screen_center, point);
+        Some((
+            0.5 + offset.dot_product(self.axis_x) / self.width,
+            0.5 - offset.dot_product(self.axis_y) / self.height,
+        ))
+    }
+
+    fn near_clip(&self) -> Option<f64> {
+        self.near_clip
+    }
+
+    fn far_clip(&self) -> Option<f64> {
+        self.far_clip
+    }
+}
+
+/// Interval during which the shutter of a camera is open, in frames from the rendered one,
+/// what moves meanwhile being blurred. It is closed by default, the scene being seen at
+/// the time of the frame only.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Shutter {
+    pub open: f64,
+    pub close: f64,
+}
+
+impl Shutter {
+    pub fn is_open(&self) -> bool {
+        self.close > self.open
+    }
+
+    /// Times when the shutter opens and closes, in seconds from the frame
+    pub fn times(&self, frames_per_second: f64) -> Range<f64> {
+        self.open / frames_per_second..self.close / frames_per_second
+    }
+
+    /// Time in seconds from the frame at a fraction of the interval, 0 when the shutter
+    /// opens and 1 when it closes
+    pub fn time_at(&self, fraction: UnitInterval, frames_per_second: f64) -> f64 {
+        (self.open + fraction * (self.close - self.open)) / frames_per_second
+    }
+}
+
+/// Camera moved by an offset and turned around the center of its view, sharing the camera
+/// it moves so that it can be moved again from where it started, as when a preview
+/// follows the keyboard or an animation moves the camera
+pub struct MovedCamera {
+    camera: Arc<dyn RayEmitter>,
+    offset: Vec3,
+    rotation: Mat3,
+    /// Where the ray of the center of the canvas starts
+    pivot: Vec3,
+    shutter: Shutter,
+    /// Moves of the camera while the shutter is open, per second
+    velocity: Vec3,
+    /// Angles in radians around the x, y and z axes
+    angular_velocity: Vec3,
+}
+
+impl MovedCamera {
+    pub fn new(camera: Arc<dyn RayEmitter>, offset: Vec3) -> Self {
+        let pivot = camera.generate_ray(0.5, 0.5).source;
+        MovedCamera {
+            camera,
+            offset,
+            rotation: Mat3::ID,
+            pivot,
+            shutter: Default::default(),
+            velocity: Vec3::default(),
+            angular_velocity: Vec3::default(),
+        }
+    }
+
+    pub fn with_rotation(self, rotation: Mat3) -> Self {
+        MovedCamera { rotation, ..self }
+    }
+
+    pub fn with_shutter(self, shutter: Shutter) -> Self {
+        MovedCamera { shutter, ..self }
+    }
+
+    /// Camera moving on and turning further while the shutter is open, the angular
+    /// velocity being in radians per second around the x, y and z axes
+    pub fn with_motion(self, velocity: Vec3, angular_velocity: Vec3) -> Self {
+        MovedCamera {
+            velocity,
+            angular_velocity,
+            ..self
+        }
+    }
+
+    /// Ray of the moved camera, seen at a time
+    fn moved_ray(&self, ray: Ray, time: f64) -> Ray {
+        let rotation = Mat3::rotation(time * self.angular_velocity) * self.rotation;
+        let offset = self.offset + time * self.velocity;
+        let source = self.pivot + rotation * (ray.source - self.pivot) + offset;
+        Ray::new(source, rotation * ray.direction).at_time(time)
+    }
+}
+
+impl RayEmitter for MovedCamera {
+    fn width(&self) -> f64 {
+        self.camera.width()
+    }
+
+    fn height(&self) -> f64 {
+        self.camera.height()
+    }
+
+    fn size_ratio(&self) -> f64 {
+        self.camera.size_ratio()
+    }
+
+    fn generate_ray(&self, canvas_x: UnitInterval, canvas_y: UnitInterval) -> Ray {
+        self.generate_ray_at(canvas_x, canvas_y, 0.0)
+    }
+
+    fn generate_ray_at(&self, canvas_x: UnitInterval, canvas_y: UnitInterval, time: f64) -> Ray {
+        self.moved_ray(self.camera.generate_ray(canvas_x, canvas_y), time)
+    }
+
+    fn sample_ray(
+        &self,
+        canvas_x: UnitInterval,
+        canvas_y: UnitInterval,
+        time: f64,
+        rng: &mut dyn RngCore,
+    ) -> Ray {
+        self.moved_ray(self.camera.sample_ray(canvas_x, canvas_y, 0.0, rng), time)
+    }
+
+    fn canvas_position(&self, point: Vec3) -> Option<(f64, f64)> {
+        let point = self.pivot + self.rotation.transpose() * (point - self.offset - self.pivot);
+        self.camera.canvas_position(point)
+    }
+
+    fn near_clip(&self) -> Option<f64> {
+        self.camera.near_clip()
+    }
+
+    fn far_clip(&self) -> Option<f64> {
+        self.camera.far_clip()
+    }
+
+    fn shutter(&self) -> Shutter {
+        self.shutter
+    }
+}
+
+/// Part of the canvas of another camera, seen as a whole canvas, so that an image too
+/// large to be rendered at once is rendered part after part
+pub struct CroppedCamera {
+    camera: Box<dyn RayEmitter>,
+    /// Part of the canvas of the camera, as canvas positions of its corners
+    left: UnitInterval,
+    top: UnitInterval,
+    right: UnitInterval,
+    bottom: UnitInterval,
+}
+
+impl CroppedCamera {
+    pub fn new(
+        camera: Box<dyn RayEmitter>,
+        (left, top): (UnitInterval, UnitInterval),
+        (right, bottom): (UnitInterval, UnitInterval),
+    ) -> Self {
+        CroppedCamera {
+            camera,
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    /// Canvas position for the camera of a position on the part
+    fn uncropped(&self, canvas_x: UnitInterval, canvas_y: UnitInterval) -> (f64, f64) {
+        (
+            self.left + canvas_x * (self.right - self.left),
+            self.top + canvas_y * (self.bottom - self.top),
+        )
+    }
+}
+
+impl RayEmitter for CroppedCamera {
+    fn width(&self) -> f64 {
+        self.camera.width() * (self.right - self.left)
+    }
+
+    fn height(&self) -> f64 {
+        self.camera.height() * (self.bottom - self.top)
+    }
+
+    fn size_ratio(&self) -> f64 {
+        self.camera.size_ratio() * (self.right - self.left) / (self.bottom - self.top)
+    }
+
+    fn generate_ray(&self, canvas_x: UnitInterval, canvas_y: UnitInterval) -> Ray {
+        let (canvas_x, canvas_y) = self.uncropped(canvas_x, canvas_y);
+        self.camera.generate_ray(canvas_x, canvas_y)
+    }
+
+    fn generate_ray_at(&self, canvas_x: UnitInterval, canvas_y: UnitInterval, time: f64) -> Ray {
+        let (canvas_x, canvas_y) = self.uncropped(canvas_x, canvas_y);
+        self.camera.generate_ray_at(canvas_x, canvas_y, time)
+    }
+
+    fn sample_ray(
+        &self,
+        canvas_x: UnitInterval,
+        canvas_y: UnitInterval,
+        time: f64,
+        rng: &mut dyn RngCore,
+    ) -> Ray {
+        let (canvas_x, canvas_y) = self.uncropped(canvas_x, canvas_y);
+        self.camera.sample_ray(canvas_x, canvas_y, time, rng)
+    }
+
+    fn canvas_position(&self, point: Vec3) -> Option<(f64, f64)> {
+        let (canvas_x, canvas_y) = self.camera.canvas_position(point)?;
+        Some((
+            (canvas_x - self.left) / (self.right - self.left),
+            (canvas_y - self.top) / (self.bottom - self.top),
+        ))
+    }
+
+    fn near_clip(&self) -> Option<f64> {
+        self.camera.near_clip()
+    }
+
+    fn far_clip(&self) -> Option<f64> {
+        self.camera.far_clip()
+    }
+
+    fn shutter(&self) -> Shutter {
+        self.camera.shutter()
+    }
+}
+
+/// Axes of the view, `axis_z` going toward where the camera looks
+fn view_axes(eye: Vec3, look_at: Vec3) -> (Vec3, Vec3, Vec3) {
+    let axis_z = Vec3::between_points(eye, look_at).normalize();
+    let transform = Mat3::transformation_between(Vec3::new(0.0, 0.0, 1.0), axis_z);
+    let axis_y = transform * Vec3::new(0.0, 1.0, 0.0);
+    let axis_x = axis_y.cross_product(axis_z);
+    (axis_x, axis_y, axis_z)
+}
+
+/// Equidistant fisheye, the angle from the view axis growing linearly with the distance
+/// from the center of a square canvas
+#[derive(Debug)]
+pub struct FisheyeCamera {
+    eye: Vec3,
+    axis_x: Vec3,
+    axis_y: Vec3,
+    axis_z: Vec3,
+    /// Angle covered across the inscribed circle of the canvas, in radians
+    field_of_view: f64,
+    near_clip: Option<f64>,
+    far_clip: Option<f64>,
+}
+
+impl FisheyeCamera {
+    pub fn new(eye: Vec3, look_at: Vec3, field_of_view: f64) -> Self {
+        let (axis_x, axis_y, axis_z) = view_axes(eye, look_at);
+        FisheyeCamera {
+            eye,
+            axis_x,
+            axis_y,
+            axis_z,
+            field_of_view,
+            near_clip: None,
+            far_clip: None,
+        }
+    }
+
+    pub fn with_near_clip(self, near_clip: f64) -> Self {
+        FisheyeCamera {
+            near_clip: Some(near_clip),
+            ..self
+        }
+    }
+
+    pub fn with_far_clip(self, far_clip: f64) -> Self {
+        FisheyeCamera {
+            far_clip: Some(far_clip),
+            ..self
+        }
+    }
+}
+
+impl RayEmitter for FisheyeCamera {
+    fn width(&self) -> f64 {
+        1.0
+    }
+
+    fn height(&self) -> f64 {
+        1.0
+    }
+
+    fn generate_ray(&self, canvas_x: UnitInterval, canvas_y: UnitInterval) -> Ray {
+        let (x, y) = (2.0 * canvas_x - 1.0, 1.0 - 2.0 * canvas_y);
+        let angle_from_axis = (x * x + y * y).sqrt() * self.field_of_view / 2.0;
+        let angle_around_axis = y.atan2(x);
+        let direction = angle_from_axis.sin() * angle_around_axis.cos() * self.axis_x
+            + angle_from_axis.sin() * angle_around_axis.sin() * self.axis_y
+            + angle_from_axis.cos() * self.axis_z;
+        Ray::new(self.eye, direction.normalize())
+    }
+
+    fn near_clip(&self) -> Option<f64> {
+        self.near_clip
+    }
+
+    fn far_clip(&self) -> Option<f64> {
+        self.far_clip
+    }
+}
+
+/// 360° panorama, longitude along the canvas width and latitude along its height,
+/// as used by environment maps and VR viewers
+#[derive(Debug)]
+pub struct EquirectangularCamera {
+    eye: Vec3,
+    axis_x: Vec3,
+    axis_y: Vec3,
+    axis_z: Vec3,
+    near_clip: Option<f64>,
+    far_clip: Option<f64>,
+}
+
+impl EquirectangularCamera {
+    /// `look_at` is seen at the center of the canvas
+    pub fn new(eye: Vec3, look_at: Vec3) -> Self {
+        let (axis_x, axis_y, axis_z) = view_axes(eye, look_at);
+        EquirectangularCamera {
+            eye,
+            axis_x,
+            axis_y,
+            axis_z,
+            near_clip: None,
+            far_clip: None,
+        }
+    }
+
+    pub fn with_near_clip(self, near_clip: f64) -> Self {
+        EquirectangularCamera {
+            near_clip: Some(near_clip),
+            ..self
+        }
+    }
+
+    pub fn with_far_clip(self, far_clip: f64) -> Self {
+        EquirectangularCamera {
+            far_clip: Some(far_clip),
+            ..self
+        }
+    }
+}
+
+impl RayEmitter for EquirectangularCamera {
+    fn width(&self) -> f64 {
+        2.0
+    }
+
+    fn height(&self) -> f64 {
+        1.0
+    }
+
+    fn generate_ray(&self, canvas_x: UnitInterval, canvas_y: UnitInterval) -> Ray {
+        let longitude = (canvas_x - 0.5) * 2.0 * PI;
+        let latitude = (0.5 - canvas_y) * PI;
+        let direction = latitude.cos() * longitude.sin() * self.axis_x
+            + latitude.sin() * self.axis_y
+            + latitude.cos() * longitude.cos() * self.axis_z;
+        Ray::new(self.eye, direction.normalize())
+    }
+
+    fn near_clip(&self) -> Option<f64> {
+        self.near_clip
+    }
+
+    fn far_clip(&self) -> Option<f64> {
+        self.far_clip
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rays_meet_on_the_focal_plane() {
+        let camera = PerspectiveCamera::default().with_depth_of_field(2.0, 80.0);
+        let rays: Vec<Ray> = (0..10).map(|_| camera.generate_ray(0.3, 0.6)).collect();
+        let point_at_focus = |ray: &Ray| {
+            let distance = (80.0
+                - Vec3::between_points(camera.eye, ray.source).dot_product(camera.axis_z))
+                / ray.direction.dot_product(camera.axis_z);
+            ray.source + distance * ray.direction
+        };
+        let focus = point_at_focus(&rays[0]);
+        assert!(rays
+            .iter()
+            .all(|ray| point_at_focus(ray).distance(focus) < 1e-9));
+        assert!(rays
+            .iter()
+            .any(|ray| ray.source.distance(rays[0].source) > 1e-6));
+    }
+
+    #[test]
+    fn canvas_position_is_where_rays_are_sent() {
+        let perspective = PerspectiveCamera::default();
+        let orthogonal = OrthogonalCamera::default();
+        let camera = Arc::new(PerspectiveCamera::default());
+        let moved = MovedCamera::new(camera, Vec3::new(1.0, 2.0, 0.0))
+            .with_rotation(Mat3::rotation(Vec3::new(0.0, 0.5, 0.2)));
+        let cameras: [&dyn RayEmitter; 3] = [&perspective, &orthogonal, &moved];
+        for camera in cameras.iter() {
+            for &(x, y) in &[(0.5, 0.5), (0.2, 0.7), (0.9, 0.1)] {
+                let ray = camera.generate_ray(x, y);
+                let point = ray.source + 30.0 * ray.direction;
+                let (canvas_x, canvas_y) = camera.canvas_position(point).unwrap();
+                assert!((canvas_x - x).abs() < 1e-9 && (canvas_y - y).abs() < 1e-9);
+            }
+        }
+        let behind = perspective.eye - perspective.axis_z;
+        assert!(perspective.canvas_position(behind).is_none());
+    }
+
+    #[test]
+    fn square_bokeh_keeps_lens_points_inside_the_square() {
+        let bokeh = Bokeh {
+            blades: Some(4),
+            ..Default::default()
+        };
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let (x, y) = bokeh.sample_unit_lens(&mut rng);
+            assert!(
+                x.abs() + y.abs() <= 1.0 + 1e-9,
+                "({}, {}) is out of the square",
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn fisheye_sees_sideways_at_the_canvas_border() {
+        let camera = FisheyeCamera::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), PI);
+        let center = camera.generate_ray(0.5, 0.5);
+        assert!(center.direction.distance(camera.axis_z) < 1e-9);
+        let border = camera.generate_ray(1.0 - 1e-9, 0.5);
+        assert!(border.direction.dot_product(camera.axis_z).abs() < 1e-6);
+    }
+
+    #[test]
+    fn equirectangular_covers_every_direction() {
+        let camera = EquirectangularCamera::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let front = camera.generate_ray(0.5, 0.5);
+        assert!(front.direction.distance(camera.axis_z) < 1e-9);
+        let right = camera.generate_ray(0.75, 0.5);
+        assert!(right.direction.distance(camera.axis_x) < 1e-9);
+        let behind = camera.generate_ray(1e-9, 0.5);
+        assert!(behind.direction.distance(-camera.axis_z) < 1e-6);
+        let up = camera.generate_ray(0.5, 1e-9);
+        assert!(up.direction.distance(camera.axis_y) < 1e-6);
+    }
+
+    #[test]
+    fn pinhole_camera_starts_every_ray_at_the_eye() {
+        let camera = PerspectiveCamera::default();
+        let ray = camera.generate_ray(0.1, 0.9);
+        assert!(ray.source.distance(camera.eye) < 1e-12);
+    }
 }
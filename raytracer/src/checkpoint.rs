@@ -0,0 +1,390 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Tiles of a rendering already completed, saved so that an interrupted rendering continues
+//! where it stopped instead of starting again. Only the tiles whose pixels were all given
+//! are kept, the others being rendered again.
+//!
+//! A checkpoint is written as little-endian values: the canvas and tile sizes, the
+//! fingerprint of the scene it was made for, then each completed tile with the color and
+//! the opacity of its pixels in single precision, far more than the 8 bits of an image.
+
+use crate::colors::Color;
+use crate::io::cache::fingerprint;
+use crate::renderer::{Pixel, RenderConfiguration};
+use crate::result::{RaytracerError, Result};
+use crate::tiles::Tile;
+use std::collections::BTreeMap;
+
+const MAGIC: &[u8; 8] = b"RTCHECKP";
+const VERSION: u32 = 1;
+/// Bytes of a pixel, its color and opacity
+const PIXEL_BYTES: usize = 16;
+
+pub struct Checkpoint {
+    canvas_width: u32,
+    canvas_height: u32,
+    tile_size: u32,
+    fingerprint: u64,
+    /// Pixels of the tiles with at least one pixel recorded, by their index in the rows of
+    /// tiles, the memory following the pixels recorded rather than the size of the canvas
+    tiles: BTreeMap<usize, TilePixels>,
+}
+
+/// Color and opacity of the pixels recorded in a tile, row after row
+struct TilePixels {
+    pixels: Vec<Option<[f32; 4]>>,
+    recorded: usize,
+}
+
+impl Checkpoint {
+    /// Empty checkpoint of a rendering, `description` being the scene description and
+    /// anything else changing the rendered pixels
+    pub fn new(config: &RenderConfiguration, description: &str) -> Checkpoint {
+        Checkpoint {
+            canvas_width: config.canvas_width,
+            canvas_height: config.canvas_height,
+            tile_size: config.tile_size.max(1),
+            fingerprint: fingerprint(description),
+            tiles: BTreeMap::new(),
+        }
+    }
+
+    /// If the checkpoint was made for this canvas and this description, whatever its tiles
+    pub fn is_for(&self, config: &RenderConfiguration, description: &str) -> bool {
+        self.fits(config) && self.fingerprint == fingerprint(description)
+    }
+
+    /// If the checkpoint was made for a canvas of this size
+    pub fn fits(&self, config: &RenderConfiguration) -> bool {
+        (self.canvas_width, self.canvas_height) == (config.canvas_width, config.canvas_height)
+    }
+
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    fn columns(&self) -> usize {
+        self.canvas_width.div_ceil(self.tile_size) as usize
+    }
+
+    fn tile_count(&self) -> usize {
+        self.columns() * self.canvas_height.div_ceil(self.tile_size) as usize
+    }
+
+    fn tile_index(&self, x: u32, y: u32) -> usize {
+        (y / self.tile_size) as usize * self.columns() + (x / self.tile_size) as usize
+    }
+
+    /// Tile at an index in the rows of tiles, which must be less than the tile count
+    fn tile(&self, index: usize) -> Tile {
+        let columns = self.columns();
+        let x = (index % columns) as u32 * self.tile_size;
+        let y = (index / columns) as u32 * self.tile_size;
+        Tile {
+            x,
+            y,
+            width: self.tile_size.min(self.canvas_width - x),
+            height: self.tile_size.min(self.canvas_height - y),
+        }
+    }
+
+    /// Keep a rendered pixel, the ones outside of the canvas or already kept being ignored
+    pub fn record(&mut self, pixel: &Pixel) {
+        if pixel.x >= self.canvas_width || pixel.y >= self.canvas_height {
+            return;
+        }
+        let tile_index = self.tile_index(pixel.x, pixel.y);
+        let tile = self.tile(tile_index);
+        let index = (pixel.y - tile.y) as usize * tile.width as usize + (pixel.x - tile.x) as usize;
+        let tile_pixels = self.tiles.entry(tile_index).or_insert_with(|| TilePixels {
+            pixels: vec![None; tile.pixel_count()],
+            recorded: 0,
+        });
+        if tile_pixels.pixels[index].is_some() {
+            return;
+        }
+        let color = &pixel.color;
+        tile_pixels.pixels[index] = Some([
+            color.red() as f32,
+            color.green() as f32,
+            color.blue() as f32,
+            pixel.alpha as f32,
+        ]);
+        tile_pixels.recorded += 1;
+    }
+
+    /// Tiles whose pixels were all recorded, with their pixels, in the order of the rows
+    fn indexed_completed_tiles(&self) -> impl Iterator<Item = (usize, Tile, &TilePixels)> + '_ {
+        self.tiles
+            .iter()
+            .map(move |(index, pixels)| (*index, self.tile(*index), pixels))
+            .filter(|(_, tile, pixels)| pixels.recorded == tile.pixel_count())
+    }
+
+    pub fn completed_tiles(&self) -> Vec<Tile> {
+        self.indexed_completed_tiles()
+            .map(|(_, tile, _)| tile)
+            .collect()
+    }
+
+    /// Pixels of the completed tiles
+    pub fn completed_pixels(&self) -> Vec<Pixel> {
+        self.indexed_completed_tiles()
+            .flat_map(|(_, tile, pixels)| tile.pixels().zip(pixels.pixels.iter()))
+            .filter_map(|((x, y), components)| {
+                let [red, green, blue, alpha] = (*components)?;
+                let color = Color::unclamped(red as f64, green as f64, blue as f64);
+                Some(Pixel::with_alpha(x, y, color, alpha as f64))
+            })
+            .collect()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        for size in [self.canvas_width, self.canvas_height, self.tile_size].iter() {
+            bytes.extend_from_slice(&size.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.fingerprint.to_le_bytes());
+        let tiles: Vec<_> = self.indexed_completed_tiles().collect();
+        bytes.extend_from_slice(&(tiles.len() as u64).to_le_bytes());
+        for (index, _, pixels) in tiles {
+            bytes.extend_from_slice(&(index as u64).to_le_bytes());
+            for components in pixels.pixels.iter() {
+                for component in components.unwrap().iter() {
+                    bytes.extend_from_slice(&component.to_le_bytes());
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Checkpoint of encoded bytes, the sizes of its header being checked against the bytes
+    /// following them before anything is allocated for its tiles
+    pub fn decode(bytes: &[u8]) -> Result<Checkpoint> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(to_error("not a checkpoint"));
+        }
+        if reader.u32()? != VERSION {
+            return Err(to_error("made by another version"));
+        }
+        let config = RenderConfiguration {
+            canvas_width: reader.u32()?,
+            canvas_height: reader.u32()?,
+            tile_size: reader.u32()?,
+            ..Default::default()
+        };
+        if config.tile_size == 0 {
+            return Err(to_error("tiles without pixels"));
+        }
+        let mut checkpoint = Checkpoint {
+            fingerprint: reader.u64()?,
+            ..Checkpoint::new(&config, "")
+        };
+        // An index and at least a pixel for each tile
+        let tile_count = reader.u64()?;
+        let remaining = (bytes.len() - reader.position) as u64;
+        if tile_count > remaining / (8 + PIXEL_BYTES as u64) {
+            return Err(to_error("more tiles than bytes"));
+        }
+        for _ in 0..tile_count {
+            let index = reader.u64()?;
+            if index >= checkpoint.tile_count() as u64 {
+                return Err(to_error("tile outside of the canvas"));
+            }
+            let index = index as usize;
+            if checkpoint.tiles.contains_key(&index) {
+                return Err(to_error("tile given twice"));
+            }
+            let tile = checkpoint.tile(index);
+            let components = reader.take(tile.pixel_count() * PIXEL_BYTES)?;
+            let pixels = components
+                .chunks_exact(PIXEL_BYTES)
+                .map(|pixel| {
+                    let mut components = [0.0; 4];
+                    for (component, bytes) in components.iter_mut().zip(pixel.chunks_exact(4)) {
+                        let mut float_bytes = [0; 4];
+                        float_bytes.copy_from_slice(bytes);
+                        *component = f32::from_le_bytes(float_bytes);
+                    }
+                    Some(components)
+                })
+                .collect();
+            let recorded = tile.pixel_count();
+            checkpoint
+                .tiles
+                .insert(index, TilePixels { pixels, recorded });
+        }
+        if reader.position != bytes.len() {
+            return Err(to_error("trailing bytes"));
+        }
+        Ok(checkpoint)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + count)
+            .ok_or_else(|| to_error("truncated file"))?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+fn to_error(message: &str) -> RaytracerError {
+    RaytracerError::ParsingError(format!("checkpoint: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RenderConfiguration {
+        RenderConfiguration {
+            canvas_width: 5,
+            canvas_height: 3,
+            tile_size: 2,
+            ..Default::default()
+        }
+    }
+
+    fn pixel(x: u32, y: u32) -> Pixel {
+        Pixel::with_alpha(x, y, Color::unclamped(x as f64, 0.25, 1.5), 0.5)
+    }
+
+    #[test]
+    fn only_tiles_with_all_their_pixels_are_completed() {
+        let mut checkpoint = Checkpoint::new(&config(), "scene");
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1), (4, 0), (4, 1), (2, 0)].iter() {
+            checkpoint.record(&pixel(*x, *y));
+        }
+        checkpoint.record(&pixel(4, 1));
+        let completed = checkpoint.completed_tiles();
+        let positions: Vec<_> = completed.iter().map(|tile| (tile.x, tile.y)).collect();
+        assert_eq!(positions, vec![(0, 0), (4, 0)]);
+        assert_eq!(checkpoint.completed_pixels().len(), 6);
+    }
+
+    #[test]
+    fn decoded_checkpoint_keeps_the_completed_tiles() {
+        let mut checkpoint = Checkpoint::new(&config(), "scene");
+        for y in 0..2 {
+            for x in 0..3 {
+                checkpoint.record(&pixel(x, y));
+            }
+        }
+        let decoded = Checkpoint::decode(&checkpoint.encode()).unwrap();
+        assert!(decoded.is_for(&config(), "scene"));
+        assert!(!decoded.is_for(&config(), "another scene"));
+        assert_eq!(decoded.tile_size(), 2);
+        assert_eq!(decoded.completed_tiles(), checkpoint.completed_tiles());
+        let colors = |checkpoint: &Checkpoint| -> Vec<_> {
+            let pixels = checkpoint.completed_pixels();
+            let colors = pixels.iter().map(|pixel| {
+                let color = &pixel.color;
+                (
+                    pixel.x,
+                    pixel.y,
+                    color.red(),
+                    color.green(),
+                    color.blue(),
+                    pixel.alpha,
+                )
+            });
+            colors.collect()
+        };
+        assert_eq!(colors(&decoded), colors(&checkpoint));
+        assert_eq!(colors(&decoded).len(), 4);
+    }
+
+    #[test]
+    fn truncated_checkpoint_is_refused() {
+        let mut checkpoint = Checkpoint::new(&config(), "scene");
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+            checkpoint.record(&pixel(*x, *y));
+        }
+        let bytes = checkpoint.encode();
+        assert!(Checkpoint::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Checkpoint::decode(b"RTCACHE!").is_err());
+    }
+
+    #[test]
+    fn header_larger_than_the_file_is_refused() {
+        let config = RenderConfiguration {
+            canvas_width: u32::MAX,
+            canvas_height: u32::MAX,
+            tile_size: 1,
+            ..Default::default()
+        };
+        let mut bytes = Checkpoint::new(&config, "scene").encode();
+        assert!(Checkpoint::decode(&bytes)
+            .unwrap()
+            .completed_tiles()
+            .is_empty());
+        // A billion tiles announced, without their pixels
+        let count_position = bytes.len() - 8;
+        bytes[count_position..].copy_from_slice(&1_000_000_000u64.to_le_bytes());
+        assert!(Checkpoint::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn pixels_beyond_four_billions_are_recorded() {
+        let config = RenderConfiguration {
+            canvas_width: 100_000,
+            canvas_height: 100_000,
+            tile_size: 1,
+            ..Default::default()
+        };
+        let mut checkpoint = Checkpoint::new(&config, "scene");
+        checkpoint.record(&pixel(99_999, 99_999));
+        let decoded = Checkpoint::decode(&checkpoint.encode()).unwrap();
+        let positions: Vec<_> = decoded
+            .completed_pixels()
+            .iter()
+            .map(|p| (p.x, p.y))
+            .collect();
+        assert_eq!(positions, vec![(99_999, 99_999)]);
+    }
+}
@@ -25,11 +25,13 @@ SOFTWARE.
 use crate::parser::ModelColor;
 use crate::utils::unit_interval_clamp;
 use crate::UnitInterval;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
-#[derive(Clone, Debug, Default, Deserialize)]
-#[serde(from = "ModelColor")]
+/// Components are given between 0 and 1, but the light computed by adding and
+/// multiplying colors can go beyond 1, until the renderer tone mapping
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(try_from = "ModelColor", into = "ModelColor")]
 pub struct Color {
     red: UnitInterval,
     green: UnitInterval,
@@ -45,6 +47,11 @@ impl Color {
         }
     }
 
+    /// Light with components which are not clamped, as computed by a physical model
+    pub fn unclamped(red: f64, green: f64, blue: f64) -> Self {
+        Color { red, green, blue }
+    }
+
     pub fn red(&self) -> UnitInterval {
         self.red
     }
@@ -57,6 +64,55 @@ impl Color {
         self.green
     }
 
+    /// Color of a black body at a temperature in Kelvin, from red embers around 1000 K to
+    /// white around 6500 K and blue beyond, with the approximation of Tanner Helland
+    pub fn from_temperature(kelvin: f64) -> Self {
+        let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+        let red = if t <= 66.0 {
+            255.0
+        } else {
+            329.698_727_446 * (t - 60.0).powf(-0.133_204_759_2)
+        };
+        let green = if t <= 66.0 {
+            99.470_802_586_1 * t.ln() - 161.119_568_166_1
+        } else {
+            288.122_169_528_3 * (t - 60.0).powf(-0.075_514_849_2)
+        };
+        let blue = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.517_731_223_1 * (t - 10.0).ln() - 305.044_792_730_7
+        };
+        Color::new(red / 255.0, green / 255.0, blue / 255.0)
+    }
+
+    /// Color of 8 bits components encoded with the sRGB transfer curve, as in most images
+    pub fn from_srgb8(components: [u8; 3]) -> Self {
+        let [red, green, blue] = components.map(|value| srgb_decode(value as f64 / 255.0));
+        Color::new(red, green, blue)
+    }
+
+    /// 8 bits red, green and blue components, encoded with the sRGB transfer curve
+    pub fn to_srgb8(&self) -> [u8; 3] {
+        [self.red, self.green, self.blue].map(|value| to_u8(srgb_encode(value)))
+    }
+
+    /// 8 bits red, green and blue components, without any encoding
+    pub fn to_linear8(&self) -> [u8; 3] {
+        [self.red, self.green, self.blue].map(to_u8)
+    }
+
+    /// 8 bits components for display or image files, sRGB encoded unless disabled
+    pub fn to_rgb8(&self, srgb: bool) -> [u8; 3] {
+        if srgb {
+            self.to_srgb8()
+        } else {
+            self.to_linear8()
+        }
+    }
+
     pub const WHITE: Self = Color {
         red: 1.0,
         green: 1.0,
@@ -87,6 +143,32 @@ impl Color {
         green: 1.0,
         blue: 0.0,
     };
+    pub const MAGENTA: Self = Color {
+        red: 1.0,
+        green: 0.0,
+        blue: 1.0,
+    };
+}
+
+fn srgb_encode(linear: f64) -> f64 {
+    let linear = unit_interval_clamp(linear);
+    if linear <= 0.003_130_8 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn srgb_decode(encoded: f64) -> f64 {
+    if encoded <= 0.040_45 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn to_u8(value: UnitInterval) -> u8 {
+    (255.0 * unit_interval_clamp(value)).round() as u8
 }
 
 impl FromStr for Color {
@@ -100,6 +182,7 @@ impl FromStr for Color {
             "green" => Self::GREEN,
             "blue" => Self::BLUE,
             "yellow" => Self::YELLOW,
+            "magenta" => Self::MAGENTA,
             other => return Err(format!("{} is not a valid color reference", other)),
         })
     }
@@ -109,19 +192,19 @@ impl std::ops::Add for Color {
     type Output = Color;
 
     fn add(self, rhs: Self) -> Self::Output {
-        Color::new(
-            unit_interval_clamp(self.red + rhs.red),
-            unit_interval_clamp(self.green + rhs.green),
-            unit_interval_clamp(self.blue + rhs.blue),
-        )
+        Color {
+            red: self.red + rhs.red,
+            green: self.green + rhs.green,
+            blue: self.blue + rhs.blue,
+        }
     }
 }
 
 impl std::ops::AddAssign for Color {
     fn add_assign(&mut self, rhs: Self) {
-        self.red = unit_interval_clamp(self.red + rhs.red);
-        self.green = unit_interval_clamp(self.green + rhs.green);
-        self.blue = unit_interval_clamp(self.blue + rhs.blue);
+        self.red += rhs.red;
+        self.green += rhs.green;
+        self.blue += rhs.blue;
     }
 }
 
@@ -129,11 +212,7 @@ impl std::ops::Mul for Color {
     type Output = Color;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        Color::new(
-            self.red * rhs.red,
-            self.green * rhs.green,
-            self.blue * rhs.blue,
-        )
+        &self * &rhs
     }
 }
 
@@ -141,11 +220,11 @@ impl std::ops::Mul for &Color {
     type Output = Color;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        Color::new(
-            self.red * rhs.red,
-            self.green * rhs.green,
-            self.blue * rhs.blue,
-        )
+        Color {
+            red: self.red * rhs.red,
+            green: self.green * rhs.green,
+            blue: self.blue * rhs.blue,
+        }
     }
 }
 
@@ -154,9 +233,9 @@ impl std::ops::Mul<UnitInterval> for &Color {
 
     fn mul(self, rhs: UnitInterval) -> Self::Output {
         Color {
-            red: unit_interval_clamp(rhs * self.red),
-            green: unit_interval_clamp(rhs * self.green),
-            blue: unit_interval_clamp(rhs * self.blue),
+            red: rhs * self.red,
+            green: rhs * self.green,
+            blue: rhs * self.blue,
         }
     }
 }
@@ -165,11 +244,7 @@ impl std::ops::Mul<UnitInterval> for Color {
     type Output = Color;
 
     fn mul(self, rhs: UnitInterval) -> Self::Output {
-        Color {
-            red: unit_interval_clamp(rhs * self.red),
-            green: unit_interval_clamp(rhs * self.green),
-            blue: unit_interval_clamp(rhs * self.blue),
-        }
+        &self * rhs
     }
 }
 
@@ -177,11 +252,7 @@ impl std::ops::Mul<Color> for UnitInterval {
     type Output = Color;
 
     fn mul(self, rhs: Color) -> Self::Output {
-        Color {
-            red: unit_interval_clamp(self * rhs.red),
-            green: unit_interval_clamp(self * rhs.green),
-            blue: unit_interval_clamp(self * rhs.blue),
-        }
+        &rhs * self
     }
 }
 
@@ -189,18 +260,14 @@ impl std::ops::Mul<&Color> for UnitInterval {
     type Output = Color;
 
     fn mul(self, rhs: &Color) -> Self::Output {
-        Color {
-            red: unit_interval_clamp(self * rhs.red),
-            green: unit_interval_clamp(self * rhs.green),
-            blue: unit_interval_clamp(self * rhs.blue),
-        }
+        rhs * self
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::utils::{f64_gt, f64_lt};
+    use crate::utils::{f64_eq, f64_gt, f64_lt};
 
     #[test]
     fn new_color_with_high_value_is_clamped() {
@@ -219,21 +286,40 @@ mod tests {
     }
 
     #[test]
-    fn add_with_high_color_is_clamped() {
+    fn srgb_encoding_brightens_mid_tones() {
+        assert_eq!(Color::BLACK.to_srgb8(), [0, 0, 0]);
+        assert_eq!(Color::WHITE.to_srgb8(), [255, 255, 255]);
+        let [red, _, _] = Color::new(0.5, 0.5, 0.5).to_srgb8();
+        assert_eq!(red, 188);
+        assert_eq!(Color::new(0.5, 0.5, 0.5).to_linear8(), [128, 128, 128]);
+    }
+
+    #[test]
+    fn black_body_color_goes_from_red_to_blue() {
+        let embers = Color::from_temperature(1500.0);
+        assert!(embers.red > embers.green && embers.green > embers.blue);
+        let daylight = Color::from_temperature(6600.0);
+        assert!(daylight.red > 0.99 && daylight.green > 0.95 && daylight.blue > 0.95);
+        let sky = Color::from_temperature(15000.0);
+        assert!(sky.blue > sky.red);
+    }
+
+    #[test]
+    fn add_with_high_color_keeps_high_range() {
         let color_1 = Color::new(1.0, 1.0, 1.0);
         let color_2 = Color::new(1.0, 1.0, 1.0);
         let result = color_1 + color_2;
-        assert!(f64_lt(result.red, 1.0));
-        assert!(f64_lt(result.green, 1.0));
-        assert!(f64_lt(result.blue, 1.0));
+        assert!(f64_eq(result.red, 2.0));
+        assert!(f64_eq(result.green, 2.0));
+        assert!(f64_eq(result.blue, 2.0));
     }
 
     #[test]
-    fn mul_with_high_constant_is_clamped() {
+    fn mul_with_high_constant_keeps_high_range() {
         let color = Color::new(1.0, 1.0, 1.0);
         let result = 10.0 * color;
-        assert!(f64_lt(result.red, 1.0));
-        assert!(f64_lt(result.green, 1.0));
-        assert!(f64_lt(result.blue, 1.0));
+        assert!(f64_eq(result.red, 10.0));
+        assert!(f64_eq(result.green, 10.0));
+        assert!(f64_eq(result.blue, 10.0));
     }
 }
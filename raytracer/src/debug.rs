@@ -0,0 +1,152 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Tools to inspect what the ray algorithm does for one pixel.
+
+use crate::primitives::Ray;
+use crate::ray_algorithm::{launch_recorded_ray, MediaStack, RayKind, RayRecorder};
+use crate::result::Result;
+use crate::scene::Scene;
+use crate::vector::Vec3;
+use crate::UnitInterval;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Length given in exports to rays which do not hit anything
+pub const MISSED_RAY_LENGTH: f64 = 1000.0;
+
+#[derive(Debug, Serialize)]
+pub struct RecordedRay {
+    pub kind: RayKind,
+    pub depth: u8,
+    pub source: Vec3,
+    pub direction: Vec3,
+    /// Hit point, or light position for shadow rays reaching their light
+    pub end: Option<Vec3>,
+}
+
+impl RecordedRay {
+    pub fn end_or_far(&self) -> Vec3 {
+        self.end
+            .unwrap_or_else(|| self.source + MISSED_RAY_LENGTH * self.direction)
+    }
+}
+
+/// All rays launched to compute the color of a pixel, in launch order.
+#[derive(Debug, Default, Serialize)]
+pub struct RayTree {
+    pub rays: Vec<RecordedRay>,
+}
+
+impl RayRecorder for RayTree {
+    fn record(&mut self, kind: RayKind, depth: u8, ray: &Ray, end: Option<Vec3>) {
+        self.rays.push(RecordedRay {
+            kind,
+            depth,
+            source: ray.source,
+            direction: ray.direction,
+            end,
+        });
+    }
+}
+
+impl RayTree {
+    /// Wavefront OBJ export, each ray being a line in a group named after its kind.
+    pub fn to_obj(&self) -> String {
+        let mut obj = String::from("# Ray tree exported by raytracer-rust\n");
+        for (index, ray) in self.rays.iter().enumerate() {
+            let start = ray.source;
+            let end = ray.end_or_far();
+            let group = match ray.kind {
+                RayKind::Camera => "camera",
+                RayKind::Reflection => "reflection",
+                RayKind::Refraction => "refraction",
+                RayKind::Shadow => "shadow",
+                RayKind::Diffuse => "diffuse",
+            };
+            // Writing to a String cannot fail
+            writeln!(obj, "g {}", group).unwrap();
+            writeln!(obj, "v {} {} {}", start.x, start.y, start.z).unwrap();
+            writeln!(obj, "v {} {} {}", end.x, end.y, end.z).unwrap();
+            writeln!(obj, "l {} {}", 2 * index + 1, 2 * index + 2).unwrap();
+        }
+        obj
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Ray tree types always serialize")
+    }
+}
+
+/// Launch the ray going through the canvas position and record the resulting ray tree.
+pub fn trace_pixel(
+    scene: &Scene,
+    canvas_x: UnitInterval,
+    canvas_y: UnitInterval,
+) -> Result<RayTree> {
+    let mut tree = RayTree::default();
+    let camera_ray = scene.camera.generate_ray(canvas_x, canvas_y);
+    launch_recorded_ray(
+        &camera_ray,
+        scene,
+        0,
+        RayKind::Camera,
+        &MediaStack::default(),
+        &mut tree,
+        &mut StdRng::seed_from_u64(scene.config.seed),
+    )?;
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn obj_export_has_one_line_per_ray() {
+        let mut tree = RayTree::default();
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        tree.record(RayKind::Camera, 0, &ray, Some(Vec3::new(0.0, 0.0, 5.0)));
+        tree.record(RayKind::Reflection, 1, &ray, None);
+        let obj = tree.to_obj();
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 4);
+        assert!(obj.contains("l 3 4"));
+        assert!(obj.contains(&format!("v 0 0 {}", MISSED_RAY_LENGTH)));
+    }
+
+    #[test]
+    fn json_export_has_vectors_as_arrays() {
+        let mut tree = RayTree::default();
+        let ray = Ray::new(Vec3::new(1.0, 2.0, 3.0), Vec3::new(0.0, 0.0, 1.0));
+        tree.record(RayKind::Shadow, 0, &ray, None);
+        let json: serde_json::Value = serde_json::from_str(&tree.to_json()).unwrap();
+        assert_eq!(json["rays"][0]["kind"], "shadow");
+        assert_eq!(
+            json["rays"][0]["source"],
+            serde_json::json!([1.0, 2.0, 3.0])
+        );
+    }
+}
@@ -0,0 +1,219 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Depth of the scene seen from a light, as used by real-time engines to bake shadows: a
+//! point whose distance to the light is beyond the one of its pixel is in the shadow.
+
+use crate::bvh::Aabb;
+use crate::cameras::{OrthogonalCamera, PerspectiveCamera};
+use crate::io::exr::encode_exr;
+use crate::lights::{AnyLightObject, LIGHT_AT_INFINITY_DISTANCE};
+use crate::ray_algorithm::shadow_caster_hit;
+use crate::result::{RaytracerError, Result};
+use crate::scene::{RayEmitter, Scene};
+use crate::vector::Vec3;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// How the light sees the scene, both views just covering the sphere bounding its objects
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LightProjection {
+    /// Parallel rays starting from the plane facing the light and touching the bounding
+    /// sphere, as for the sun
+    #[default]
+    Orthographic,
+    /// Rays spreading from the light position, as for a spot light
+    Perspective,
+}
+
+impl FromStr for LightProjection {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_ref() {
+            "orthographic" => LightProjection::Orthographic,
+            "perspective" => LightProjection::Perspective,
+            other => return Err(format!("{} is not a valid light projection", other)),
+        })
+    }
+}
+
+/// Distance, from where the rays of a light view start, to the first object casting a
+/// shadow through each pixel, infinite where no object is seen
+pub struct DepthMap {
+    width: u32,
+    height: u32,
+    depths: Vec<f32>,
+}
+
+impl DepthMap {
+    /// Depth map of `size` by `size` pixels of the scene seen from the light
+    pub fn from_light(
+        scene: &Scene,
+        light: &dyn AnyLightObject,
+        projection: LightProjection,
+        size: u32,
+    ) -> Result<DepthMap> {
+        let view = light_view(scene, light, projection)?;
+        let pixel_size = 1.0 / size as f64;
+        let depths = (0..size)
+            .into_par_iter()
+            .flat_map_iter(|y| {
+                let view = &view;
+                (0..size).map(move |x| {
+                    let canvas_x = (x as f64 + 0.5) * pixel_size;
+                    let canvas_y = (y as f64 + 0.5) * pixel_size;
+                    let ray = view.generate_ray(canvas_x, canvas_y);
+                    match shadow_caster_hit(&ray, scene) {
+                        Some(point) => ray.source.distance(point) as f32,
+                        None => f32::INFINITY,
+                    }
+                })
+            })
+            .collect();
+        Ok(DepthMap {
+            width: size,
+            height: size,
+            depths,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn at(&self, x: u32, y: u32) -> f32 {
+        self.depths[y as usize * self.width as usize + x as usize]
+    }
+
+    /// OpenEXR image of the depths, in their `Z` channel
+    pub fn to_exr(&self) -> Vec<u8> {
+        encode_exr(self.width, self.height, "Z", &self.depths)
+    }
+}
+
+/// Camera at the light looking at the center of the bounded objects, infinite planes being
+/// left out
+pub fn light_view(
+    scene: &Scene,
+    light: &dyn AnyLightObject,
+    projection: LightProjection,
+) -> Result<Box<dyn RayEmitter>> {
+    let bounds = scene
+        .objects
+        .iter()
+        .filter_map(|object| object.aabb())
+        .fold(None, |bounds: Option<Aabb>, aabb| {
+            Some(bounds.map_or(aabb, |bounds| bounds.union(&aabb)))
+        })
+        .ok_or_else(|| {
+            RaytracerError::InvalidGeometry(String::from(
+                "no bounded object to be seen from the light",
+            ))
+        })?;
+    let center = bounds.center();
+    let radius = (0.5 * Vec3::between_points(bounds.min, bounds.max).norm()).max(1e-9);
+    let light_distance = light.source().distance(center);
+    if light_distance <= radius {
+        return Err(RaytracerError::InvalidGeometry(String::from(
+            "the light is among the objects it should see",
+        )));
+    }
+    let direction = Vec3::between_points(light.source(), center).normalize();
+    Ok(match projection {
+        LightProjection::Orthographic => {
+            let eye = center - radius * direction;
+            Box::new(OrthogonalCamera::new(
+                eye,
+                center,
+                2.0 * radius,
+                2.0 * radius,
+            ))
+        }
+        LightProjection::Perspective => {
+            if light_distance >= LIGHT_AT_INFINITY_DISTANCE {
+                return Err(RaytracerError::InvalidGeometry(String::from(
+                    "a light at infinity is only seen with an orthographic projection",
+                )));
+            }
+            // Screen one unit in front of the light, the eye being put on the light
+            let half_angle = (radius / light_distance).asin();
+            let side = 2.0 * half_angle.tan();
+            let screen_center = light.source() + direction;
+            Box::new(PerspectiveCamera::new(
+                screen_center,
+                center,
+                side,
+                side,
+                half_angle,
+            ))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::SceneBuilder;
+    use crate::colors::Color;
+    use crate::lights::{LightDirectional, LightPoint};
+
+    fn unit_sphere_scene() -> Scene {
+        SceneBuilder::new()
+            .add_sphere(Vec3::new(0.0, 0.0, 0.0), 1.0)
+            .colored(Color::RED)
+            .build()
+    }
+
+    #[test]
+    fn orthographic_depth_starts_at_the_bounding_sphere() {
+        let scene = unit_sphere_scene();
+        let sun = LightDirectional::new(Vec3::new(0.0, -1.0, 0.0), Color::WHITE);
+        let map = DepthMap::from_light(&scene, &sun, LightProjection::Orthographic, 9).unwrap();
+        assert!((map.at(4, 4) - (3f32.sqrt() - 1.0)).abs() < 1e-3);
+        assert_eq!(map.at(0, 0), f32::INFINITY);
+    }
+
+    #[test]
+    fn perspective_depth_is_the_distance_to_the_light() {
+        let scene = unit_sphere_scene();
+        let light = LightPoint::new(Vec3::new(0.0, 5.0, 0.0));
+        let map = DepthMap::from_light(&scene, &light, LightProjection::Perspective, 9).unwrap();
+        assert!((map.at(4, 4) - 4.0).abs() < 1e-3);
+        assert!(map.at(4, 0) > map.at(4, 4));
+    }
+
+    #[test]
+    fn light_among_the_objects_is_error() {
+        let scene = unit_sphere_scene();
+        let light = LightPoint::new(Vec3::new(0.0, 0.5, 0.0));
+        assert!(light_view(&scene, &light, LightProjection::Perspective).is_err());
+    }
+}
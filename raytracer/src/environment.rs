@@ -0,0 +1,255 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! World background seen by the rays leaving the scene, a procedural sky gradient or
+//! an environment image wrapped around the scene with a latitude-longitude mapping.
+//!
+//! The Y axis goes up, and the middle of the image is seen when looking towards +Z.
+
+use crate::colors::Color;
+use crate::io::hdr;
+use crate::result::{RaytracerError, Result};
+use crate::vector::Vec3;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type")]
+pub enum World {
+    Gradient(GradientSky),
+    Image(EnvironmentMap),
+}
+
+impl World {
+    pub fn color_in(&self, direction: Vec3) -> Color {
+        match self {
+            World::Gradient(sky) => sky.color_in(direction),
+            World::Image(environment) => environment.color_in(direction),
+        }
+    }
+}
+
+/// Sky going from the horizon color to the zenith color, the ground below the horizon
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GradientSky {
+    pub zenith_color: Color,
+    pub horizon_color: Color,
+    pub ground_color: Color,
+}
+
+impl Default for GradientSky {
+    fn default() -> Self {
+        GradientSky {
+            zenith_color: Color::new(0.25, 0.45, 0.85),
+            horizon_color: Color::new(0.85, 0.9, 1.0),
+            ground_color: Color::new(0.3, 0.27, 0.25),
+        }
+    }
+}
+
+impl GradientSky {
+    pub fn color_in(&self, direction: Vec3) -> Color {
+        let elevation = direction.normalize().y.clamp(-1.0, 1.0).asin() / (PI / 2.0);
+        let (other_color, weight) = if elevation >= 0.0 {
+            (&self.zenith_color, elevation)
+        } else {
+            (&self.ground_color, -elevation)
+        };
+        (1.0 - weight) * &self.horizon_color + weight * other_color
+    }
+}
+
+/// Image around the scene, from a Radiance HDR file giving the real light intensities,
+/// or from a PNG or JPEG file whose sRGB colors are decoded
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EnvironmentMap {
+    pub path: PathBuf,
+    /// Multiplies the colors of the image
+    #[serde(default = "default_intensity")]
+    pub intensity: f64,
+    /// Rotation of the image around the Y axis
+    #[serde(default)]
+    pub rotation_degree: f64,
+    #[serde(skip)]
+    map: Option<LatLongMap>,
+}
+
+impl EnvironmentMap {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut environment = EnvironmentMap {
+            path: path.as_ref().to_path_buf(),
+            intensity: default_intensity(),
+            rotation_degree: 0.0,
+            map: None,
+        };
+        environment.load()?;
+        Ok(environment)
+    }
+
+    /// Read the image file, the map being magenta until it is loaded
+    pub fn load(&mut self) -> Result<()> {
+        self.map = Some(LatLongMap::load(&self.path)?);
+        Ok(())
+    }
+
+    pub fn color_in(&self, direction: Vec3) -> Color {
+        match self.map.as_ref() {
+            Some(map) => {
+                self.intensity * &map.color_in(direction, self.rotation_degree.to_radians())
+            }
+            None => Color::MAGENTA,
+        }
+    }
+}
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+/// Pixels of an image covering every direction, the columns going around the Y axis and
+/// the rows from the zenith to the nadir
+pub struct LatLongMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl LatLongMap {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Result<Self> {
+        if width == 0 || height == 0 || pixels.len() != width * height {
+            return Err(RaytracerError::ParsingError(format!(
+                "{} pixels given for a {}x{} environment map",
+                pixels.len(),
+                width,
+                height
+            )));
+        }
+        Ok(LatLongMap {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Radiance HDR files are recognized by their `.hdr` extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let io_error =
+            |e: &dyn fmt::Display| RaytracerError::IoError(format!("{}: {}", path.display(), e));
+        let is_hdr = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+        if is_hdr {
+            return hdr::load_hdr(path);
+        }
+        let image = image::open(path).map_err(|e| io_error(&e))?.to_rgb8();
+        let pixels = image
+            .pixels()
+            .map(|pixel| Color::from_srgb8(pixel.0))
+            .collect();
+        LatLongMap::new(image.width() as usize, image.height() as usize, pixels)
+    }
+
+    /// Pixels bilinearly interpolated around the direction, wrapping around the Y axis
+    pub fn color_in(&self, direction: Vec3, rotation: f64) -> Color {
+        let direction = direction.normalize();
+        let longitude = direction.x.atan2(direction.z) + rotation;
+        let u = (0.5 + longitude / (2.0 * PI)).rem_euclid(1.0);
+        let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+        let x = u * self.width as f64 - 0.5;
+        let y = v * self.height as f64 - 0.5;
+        let (column, row) = (x.floor(), y.floor());
+        let (tx, ty) = (x - column, y - row);
+        let pixel = |column: f64, row: f64| {
+            let column = (column as i64).rem_euclid(self.width as i64) as usize;
+            let row = (row.max(0.0) as usize).min(self.height - 1);
+            &self.pixels[row * self.width + column]
+        };
+        let top = (1.0 - tx) * pixel(column, row) + tx * pixel(column + 1.0, row);
+        let bottom = (1.0 - tx) * pixel(column, row + 1.0) + tx * pixel(column + 1.0, row + 1.0);
+        (1.0 - ty) * &top + ty * &bottom
+    }
+
+    /// Radiance HDR file of the map, read back by `load`
+    pub fn to_hdr(&self) -> Vec<u8> {
+        hdr::encode_hdr(self.width, self.height, &self.pixels)
+    }
+}
+
+impl fmt::Debug for LatLongMap {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("LatLongMap")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three columns around the Y axis, the red one in front
+    fn compass_map() -> LatLongMap {
+        let pixels = vec![Color::BLACK, Color::RED, Color::BLACK];
+        LatLongMap::new(3, 1, pixels).unwrap()
+    }
+
+    #[test]
+    fn gradient_sky_goes_from_ground_to_zenith() {
+        let sky = GradientSky::default();
+        let zenith = sky.color_in(Vec3::new(0.0, 1.0, 0.0));
+        assert!((zenith.blue() - sky.zenith_color.blue()).abs() < 1e-9);
+        let horizon = sky.color_in(Vec3::new(1.0, 0.0, 0.0));
+        assert!((horizon.red() - sky.horizon_color.red()).abs() < 1e-9);
+        let ground = sky.color_in(Vec3::new(0.0, -1.0, 0.0));
+        assert!((ground.green() - sky.ground_color.green()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn map_middle_is_in_front() {
+        let map = compass_map();
+        let front = map.color_in(Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let back = map.color_in(Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(front.red() > 0.9);
+        assert!(back.red() < 1e-9);
+    }
+
+    #[test]
+    fn map_rotation_turns_around_y() {
+        let map = compass_map();
+        let left = map.color_in(Vec3::new(-1.0, 0.0, 0.0), PI / 2.0);
+        assert!(left.red() > 0.9);
+    }
+
+    #[test]
+    fn map_size_must_match_pixels() {
+        assert!(LatLongMap::new(2, 2, vec![Color::BLACK]).is_err());
+    }
+}
@@ -0,0 +1,512 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Extrusion of flat outlines into closed triangle meshes.
+//!
+//! Outlines are closed contours in the XY plane, nested contours being holes with the
+//! even-odd rule. Each outer contour is joined to its holes by bridges, then cut into
+//! triangles by ear clipping, before the caps are linked by the side walls.
+
+use crate::io::{svg, ttf};
+use crate::primitives::TriangleMesh;
+use crate::result::{RaytracerError, Result};
+use crate::vector::Vec3;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Closed contour of an outline, as a polygon
+pub type Contour = Vec<(f64, f64)>;
+
+/// Points closer than this are merged, and corners flatter than this are removed
+const EPSILON: f64 = 1e-9;
+
+/// Longest miter of a beveled corner, in bevels
+const MITER_LIMIT: f64 = 2.0;
+
+/// Text in a TrueType font, extruded towards +Z from its front face in the Z = 0 plane.
+///
+/// The text is written towards +X from the origin on its baseline, its lines going
+/// down, with an em square of `size`: capital letters are about 0.7 times as high.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Text3D {
+    pub text: String,
+    /// TrueType file, relative to the current directory
+    pub font: PathBuf,
+    #[serde(default = "Text3D::default_size")]
+    pub size: f64,
+    pub depth: f64,
+    /// Width of the chamfer around the front and back faces
+    #[serde(default)]
+    pub bevel: f64,
+}
+
+impl Text3D {
+    fn default_size() -> f64 {
+        1.0
+    }
+
+    pub fn into_mesh(&self) -> Result<TriangleMesh> {
+        let font = ttf::load_font(&self.font)?;
+        let contours = font
+            .text_contours(&self.text)?
+            .into_iter()
+            .map(|contour| {
+                contour
+                    .into_iter()
+                    .map(|(x, y)| (x * self.size, y * self.size))
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+        extrude(&contours, self.depth, self.bevel)
+    }
+}
+
+/// Filled shapes of an SVG file, extruded towards +Z from their front face in the Z = 0 plane.
+///
+/// One SVG user unit is one scene unit, the Y axis of the image going up.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SvgExtrusion {
+    /// SVG file, relative to the current directory
+    pub svg: PathBuf,
+    pub depth: f64,
+    /// Width of the chamfer around the front and back faces
+    #[serde(default)]
+    pub bevel: f64,
+}
+
+impl SvgExtrusion {
+    pub fn into_mesh(&self) -> Result<TriangleMesh> {
+        extrude(&svg::load_svg(&self.svg)?, self.depth, self.bevel)
+    }
+}
+
+/// Mesh of the outlines extruded from Z = 0 to Z = depth, with an optional chamfer
+pub fn extrude(contours: &[Contour], depth: f64, bevel: f64) -> Result<TriangleMesh> {
+    let is_valid = depth > 0.0 && bevel >= 0.0;
+    if !is_valid {
+        return Err(RaytracerError::InvalidGeometry(format!(
+            "extrusion of depth {} with a bevel of {}",
+            depth, bevel
+        )));
+    }
+    let bevel = bevel.min(depth / 2.0);
+    let rings = oriented_rings(contours);
+    let points: Vec<(f64, f64)> = rings.iter().flatten().copied().collect();
+    let mut ranges = Vec::with_capacity(rings.len());
+    for ring in &rings {
+        let start = ranges
+            .last()
+            .map_or(0, |range: &std::ops::Range<usize>| range.end);
+        ranges.push(start..start + ring.len());
+    }
+    let cap = triangulate(&points, &ranges)?;
+
+    // Rings of the outlines along the extrusion, inset at the caps when beveled
+    let levels = if bevel > 0.0 {
+        vec![
+            (bevel, 0.0),
+            (0.0, bevel),
+            (0.0, depth - bevel),
+            (bevel, depth),
+        ]
+    } else {
+        vec![(0.0, 0.0), (0.0, depth)]
+    };
+    let mut vertices = Vec::with_capacity(levels.len() * points.len());
+    for &(inset, z) in &levels {
+        for range in &ranges {
+            let ring = &points[range.clone()];
+            vertices.extend(
+                inset_ring(ring, inset)
+                    .into_iter()
+                    .map(|(x, y)| Vec3::new(x, y, z)),
+            );
+        }
+    }
+    let count = points.len();
+    let last = (levels.len() - 1) * count;
+    let mut triangles = Vec::new();
+    for &[a, b, c] in &cap {
+        triangles.push([a, c, b]);
+        triangles.push([last + a, last + b, last + c]);
+    }
+    for level in 0..levels.len() - 1 {
+        let (lower, upper) = (level * count, (level + 1) * count);
+        for range in &ranges {
+            for index in range.clone() {
+                let next = if index + 1 == range.end {
+                    range.start
+                } else {
+                    index + 1
+                };
+                triangles.push([lower + index, lower + next, upper + next]);
+                triangles.push([lower + index, upper + next, upper + index]);
+            }
+        }
+    }
+    // Insets can flatten some triangles of the chamfer
+    triangles.retain(|&[a, b, c]| {
+        let normal = (vertices[b] - vertices[a]).cross_product(vertices[c] - vertices[a]);
+        normal.norm() > EPSILON * EPSILON
+    });
+    if triangles.is_empty() {
+        return Err(RaytracerError::InvalidGeometry(String::from(
+            "outlines have no area to extrude",
+        )));
+    }
+    TriangleMesh::new(vertices, triangles)
+}
+
+fn signed_area(ring: &[(f64, f64)]) -> f64 {
+    let count = ring.len();
+    (0..count)
+        .map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % count]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum::<f64>()
+        / 2.0
+}
+
+fn cross(o: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+fn contains(ring: &[(f64, f64)], point: (f64, f64)) -> bool {
+    let mut inside = false;
+    let count = ring.len();
+    for i in 0..count {
+        let (a, b) = (ring[i], ring[(i + count - 1) % count]);
+        if (a.1 > point.1) != (b.1 > point.1)
+            && point.0 < a.0 + (point.1 - a.1) * (b.0 - a.0) / (b.1 - a.1)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Contour without repeated points nor flat corners
+fn cleaned(contour: &[(f64, f64)]) -> Contour {
+    let mut ring: Contour = Vec::with_capacity(contour.len());
+    for &point in contour {
+        match ring.last() {
+            Some(&last) if (point.0 - last.0).hypot(point.1 - last.1) <= EPSILON => (),
+            _ => ring.push(point),
+        }
+    }
+    while ring.len() > 1 && {
+        let (first, last) = (ring[0], ring[ring.len() - 1]);
+        (first.0 - last.0).hypot(first.1 - last.1) <= EPSILON
+    } {
+        ring.pop();
+    }
+    let mut index = 0;
+    while ring.len() >= 3 && index < ring.len() {
+        let count = ring.len();
+        let (previous, next) = (ring[(index + count - 1) % count], ring[(index + 1) % count]);
+        if cross(previous, ring[index], next).abs() <= EPSILON * EPSILON {
+            ring.remove(index);
+            index = index.saturating_sub(1);
+        } else {
+            index += 1;
+        }
+    }
+    ring
+}
+
+/// Outer contours counter-clockwise and holes clockwise, so that the filled side is always
+/// on the left, each outer contour followed by its holes
+fn oriented_rings(contours: &[Contour]) -> Vec<Contour> {
+    let rings: Vec<Contour> = contours
+        .iter()
+        .map(|contour| cleaned(contour))
+        .filter(|ring| ring.len() >= 3 && signed_area(ring).abs() > EPSILON)
+        .collect();
+    let nesting: Vec<usize> = rings
+        .iter()
+        .enumerate()
+        .map(|(i, ring)| {
+            let inside = |(j, other): (usize, &Contour)| i != j && contains(other, ring[0]);
+            rings
+                .iter()
+                .enumerate()
+                .filter(|&pair| inside(pair))
+                .count()
+        })
+        .collect();
+    // Parent of a hole is the smallest contour containing it one level up
+    let parent = |i: usize| {
+        (0..rings.len())
+            .filter(|&j| nesting[j] + 1 == nesting[i] && contains(&rings[j], rings[i][0]))
+            .min_by(|&a, &b| {
+                let area = |k: usize| signed_area(&rings[k]).abs();
+                area(a).total_cmp(&area(b))
+            })
+    };
+    let oriented = |ring: &Contour, counter_clockwise: bool| {
+        let mut ring = ring.clone();
+        if (signed_area(&ring) > 0.0) != counter_clockwise {
+            ring.reverse();
+        }
+        ring
+    };
+    let mut result = Vec::with_capacity(rings.len());
+    for (i, ring) in rings
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| nesting[i] % 2 != 1)
+    {
+        result.push(oriented(ring, true));
+        for (_, hole) in rings
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| nesting[j] % 2 == 1 && parent(j) == Some(i))
+        {
+            result.push(oriented(hole, false));
+        }
+    }
+    result
+}
+
+/// Ring moved by a distance towards its filled side
+fn inset_ring(ring: &[(f64, f64)], distance: f64) -> Contour {
+    if distance == 0.0 {
+        return ring.to_vec();
+    }
+    let count = ring.len();
+    let left_normal = |a: (f64, f64), b: (f64, f64)| {
+        let length = (b.0 - a.0).hypot(b.1 - a.1);
+        (-(b.1 - a.1) / length, (b.0 - a.0) / length)
+    };
+    (0..count)
+        .map(|i| {
+            let (previous, point, next) = (
+                ring[(i + count - 1) % count],
+                ring[i],
+                ring[(i + 1) % count],
+            );
+            let (n1, n2) = (left_normal(previous, point), left_normal(point, next));
+            let (mx, my) = (n1.0 + n2.0, n1.1 + n2.1);
+            let length = mx.hypot(my);
+            if length <= EPSILON {
+                return (point.0 + distance * n1.0, point.1 + distance * n1.1);
+            }
+            let (mx, my) = (mx / length, my / length);
+            let miter = (distance / (mx * n1.0 + my * n1.1)).min(MITER_LIMIT * distance);
+            (point.0 + miter * mx, point.1 + miter * my)
+        })
+        .collect()
+}
+
+/// Triangles filling the rings, outer rings being followed by their holes
+fn triangulate(
+    points: &[(f64, f64)],
+    ranges: &[std::ops::Range<usize>],
+) -> Result<Vec<[usize; 3]>> {
+    let is_outer = |range: &std::ops::Range<usize>| signed_area(&points[range.clone()]) > 0.0;
+    let mut triangles = Vec::new();
+    let mut index = 0;
+    while index < ranges.len() {
+        let outer = &ranges[index];
+        let holes_end = (index + 1..ranges.len())
+            .find(|&i| is_outer(&ranges[i]))
+            .unwrap_or(ranges.len());
+        let holes = &ranges[index + 1..holes_end];
+        let polygon = bridged_polygon(points, outer.clone().collect(), holes)?;
+        triangles.extend(ear_clipping(points, polygon));
+        index = holes_end;
+    }
+    Ok(triangles)
+}
+
+/// Single polygon made of an outer ring and its holes, each hole being linked to the
+/// polygon by a bridge going from its rightmost point to a visible point on the right
+fn bridged_polygon(
+    points: &[(f64, f64)],
+    mut polygon: Vec<usize>,
+    holes: &[std::ops::Range<usize>],
+) -> Result<Vec<usize>> {
+    let rightmost = |range: &std::ops::Range<usize>| {
+        range
+            .clone()
+            .max_by(|&a, &b| points[a].0.total_cmp(&points[b].0))
+            .unwrap_or(range.start)
+    };
+    let mut holes = holes.to_vec();
+    holes.sort_by(|a, b| points[rightmost(b)].0.total_cmp(&points[rightmost(a)].0));
+    for (hole_index, hole) in holes.iter().enumerate() {
+        let start = rightmost(hole);
+        let from = points[start];
+        // Edges which can hide a point: the polygon and the holes remaining to link
+        let mut edges: Vec<(usize, usize)> = (0..polygon.len())
+            .map(|i| (polygon[i], polygon[(i + 1) % polygon.len()]))
+            .collect();
+        for other in &holes[hole_index..] {
+            edges.extend(other.clone().map(|i| {
+                (
+                    i,
+                    if i + 1 == other.end {
+                        other.start
+                    } else {
+                        i + 1
+                    },
+                )
+            }));
+        }
+        let mut candidates: Vec<usize> = (0..polygon.len())
+            .filter(|&i| points[polygon[i]].0 >= from.0)
+            .collect();
+        let distance = |i: usize| {
+            let to = points[polygon[i]];
+            (to.0 - from.0).hypot(to.1 - from.1)
+        };
+        candidates.sort_by(|&a, &b| distance(a).total_cmp(&distance(b)));
+        let visible = candidates
+            .into_iter()
+            .find(|&i| is_visible(points, &edges, from, points[polygon[i]]))
+            .ok_or_else(|| {
+                RaytracerError::InvalidGeometry(String::from("hole of an outline is not inside"))
+            })?;
+        // Going around the hole, then back on the bridge
+        let ring: Vec<usize> = hole.clone().collect();
+        let offset = start - hole.start;
+        let mut inserted: Vec<usize> = (0..=ring.len())
+            .map(|i| ring[(offset + i) % ring.len()])
+            .collect();
+        inserted.push(polygon[visible]);
+        polygon.splice(visible + 1..visible + 1, inserted);
+    }
+    Ok(polygon)
+}
+
+fn is_visible(
+    points: &[(f64, f64)],
+    edges: &[(usize, usize)],
+    from: (f64, f64),
+    to: (f64, f64),
+) -> bool {
+    let same = |a: (f64, f64), b: (f64, f64)| (a.0 - b.0).hypot(a.1 - b.1) <= EPSILON;
+    edges.iter().all(|&(a, b)| {
+        let (a, b) = (points[a], points[b]);
+        if same(a, from) || same(b, from) || same(a, to) || same(b, to) {
+            return true;
+        }
+        let (d1, d2) = (cross(from, to, a), cross(from, to, b));
+        let (d3, d4) = (cross(a, b, from), cross(a, b, to));
+        // Touching the bridge counts as hiding it
+        !((d1 * d2 <= 0.0) && (d3 * d4 <= 0.0))
+    })
+}
+
+/// Triangles of a counter-clockwise polygon, cutting its convex corners without points
+/// inside them
+fn ear_clipping(points: &[(f64, f64)], mut polygon: Vec<usize>) -> Vec<[usize; 3]> {
+    let mut triangles = Vec::with_capacity(polygon.len().saturating_sub(2));
+    let mut index = 0;
+    let mut attempts = 0;
+    while polygon.len() > 3 {
+        let count = polygon.len();
+        let (a, b, c) = (
+            polygon[(index + count - 1) % count],
+            polygon[index],
+            polygon[(index + 1) % count],
+        );
+        let (pa, pb, pc) = (points[a], points[b], points[c]);
+        let area = cross(pa, pb, pc);
+        let is_ear = area > 0.0
+            && polygon.iter().all(|&other| {
+                let p = points[other];
+                let on_corner = [pa, pb, pc]
+                    .iter()
+                    .any(|&q| (q.0 - p.0).hypot(q.1 - p.1) <= EPSILON);
+                on_corner
+                    || !(cross(pa, pb, p) > 0.0 && cross(pb, pc, p) > 0.0 && cross(pc, pa, p) > 0.0)
+            });
+        // Flat corners, such as the ends of the bridges, are removed without a triangle,
+        // and polygons left without ears by rounding errors are cut anyway
+        let is_flat = area.abs() <= EPSILON * EPSILON;
+        if is_ear || is_flat || attempts > count {
+            if area > EPSILON * EPSILON {
+                triangles.push([a, b, c]);
+            }
+            polygon.remove(index);
+            attempts = 0;
+            index %= count - 1;
+        } else {
+            index = (index + 1) % count;
+            attempts += 1;
+        }
+    }
+    if polygon.len() == 3 && cross(points[polygon[0]], points[polygon[1]], points[polygon[2]]) > 0.0
+    {
+        triangles.push([polygon[0], polygon[1], polygon[2]]);
+    }
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::{Ray, Shape};
+
+    fn square(min: f64, max: f64) -> Contour {
+        vec![(min, min), (max, min), (max, max), (min, max)]
+    }
+
+    fn hit(mesh: &TriangleMesh, x: f64, y: f64) -> Option<Vec3> {
+        let ray = Ray::new(Vec3::new(x, y, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        mesh.check_collision(&ray)
+    }
+
+    #[test]
+    fn extruded_square_with_a_hole_is_closed() {
+        // Hole given with the same orientation as the outer contour
+        let mesh = extrude(&[square(0.0, 2.0), square(0.5, 1.5)], 0.5, 0.0).unwrap();
+        assert_eq!(mesh.triangles().len(), 2 * 8 + 2 * 8);
+        assert!(hit(&mesh, 1.0, 1.0).is_none());
+        let front = hit(&mesh, 0.25, 1.0).unwrap();
+        assert!(front.z.abs() < 1e-9);
+        let normal = mesh.normal_at(front).unwrap();
+        assert!((normal.z + 1.0).abs() < 1e-9);
+        let wall = Ray::new(Vec3::new(1.0, 1.0, 0.25), Vec3::new(1.0, 0.0, 0.0));
+        let inner_wall = mesh.check_collision(&wall).unwrap();
+        assert!((inner_wall.x - 1.5).abs() < 1e-9);
+        assert!(mesh.normal_at(inner_wall).unwrap().x < -0.99);
+    }
+
+    #[test]
+    fn beveled_caps_are_inset() {
+        let mesh = extrude(&[square(0.0, 2.0)], 1.0, 0.1).unwrap();
+        let front = hit(&mesh, 0.05, 1.0).unwrap();
+        assert!(front.z > 0.0 && front.z < 0.1);
+        assert!(hit(&mesh, 0.15, 1.0).unwrap().z.abs() < 1e-9);
+    }
+
+    #[test]
+    fn extrusion_needs_a_depth_and_an_area() {
+        assert!(extrude(&[square(0.0, 1.0)], 0.0, 0.0).is_err());
+        assert!(extrude(&[vec![(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)]], 1.0, 0.0).is_err());
+    }
+}
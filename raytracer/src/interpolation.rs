@@ -0,0 +1,263 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! In-between frames of an animation, synthesized from the rendered frames around them
+//! instead of being traced. Each pixel follows the screen motion of what it shows, so
+//! moving objects are shifted rather than ghosted, the result being only approximate
+//! where objects get hidden or revealed.
+
+use crate::colors::Color;
+use crate::io::pfm::encode_pfm;
+use crate::renderer::Pixel;
+use crate::result::{RaytracerError, Result};
+use crate::UnitInterval;
+
+/// Rendered image kept in memory, pixels stored row by row
+#[derive(Debug, Clone)]
+pub struct Frame {
+    width: u32,
+    height: u32,
+    pixels: Vec<Pixel>,
+}
+
+impl Frame {
+    /// Fully transparent black frame
+    pub fn new(width: u32, height: u32) -> Frame {
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| Pixel::with_alpha(x, y, Color::BLACK, 0.0)))
+            .collect();
+        Frame {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Frame of the pixels given by a rendering, in any order
+    pub fn from_pixels<I>(width: u32, height: u32, pixels: I) -> Result<Frame>
+    where
+        I: IntoIterator<Item = Result<Pixel>>,
+    {
+        let mut frame = Frame::new(width, height);
+        for pixel in pixels {
+            let pixel = pixel?;
+            if pixel.x >= width || pixel.y >= height {
+                return Err(RaytracerError::InvalidGeometry(format!(
+                    "pixel ({}, {}) outside of a {}x{} frame",
+                    pixel.x, pixel.y, width, height
+                )));
+            }
+            let index = frame.index(pixel.x, pixel.y);
+            frame.pixels[index] = pixel;
+        }
+        Ok(frame)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> &Pixel {
+        &self.pixels[self.index(x, y)]
+    }
+
+    pub fn pixels(&self) -> impl Iterator<Item = &Pixel> {
+        self.pixels.iter()
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    /// Color and alpha bilinearly interpolated at a position in pixels, the border
+    /// pixels being repeated outside of the frame
+    fn sample(&self, x: f64, y: f64) -> (Color, UnitInterval) {
+        let x = (x - 0.5).max(0.0).min((self.width - 1) as f64);
+        let y = (y - 0.5).max(0.0).min((self.height - 1) as f64);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+        let mut color = Color::BLACK;
+        let mut alpha = 0.0;
+        for &(px, py, weight) in &[
+            (x0, y0, (1.0 - fx) * (1.0 - fy)),
+            (x1, y0, fx * (1.0 - fy)),
+            (x0, y1, (1.0 - fx) * fy),
+            (x1, y1, fx * fy),
+        ] {
+            let pixel = self.pixel(px, py);
+            color += &pixel.color * weight;
+            alpha += pixel.alpha * weight;
+        }
+        (color, alpha)
+    }
+}
+
+/// Screen motion of what is seen at each pixel of a frame until the next one, in pixels
+#[derive(Debug, Clone)]
+pub struct MotionVectors {
+    width: u32,
+    height: u32,
+    vectors: Vec<(f64, f64)>,
+}
+
+impl MotionVectors {
+    /// Nothing moving on screen, in-between frames then being cross-fades
+    pub fn still(width: u32, height: u32) -> MotionVectors {
+        MotionVectors {
+            width,
+            height,
+            vectors: vec![(0.0, 0.0); width as usize * height as usize],
+        }
+    }
+
+    pub(crate) fn new(width: u32, height: u32, vectors: Vec<(f64, f64)>) -> MotionVectors {
+        MotionVectors {
+            width,
+            height,
+            vectors,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, motion: (f64, f64)) {
+        let index = y as usize * self.width as usize + x as usize;
+        self.vectors[index] = motion;
+    }
+
+    pub fn at(&self, x: u32, y: u32) -> (f64, f64) {
+        self.vectors[y as usize * self.width as usize + x as usize]
+    }
+
+    /// Portable float map of the motion, x going right and y down in the red and green
+    /// channels, the blue one being zero
+    pub fn to_pfm(&self) -> Vec<u8> {
+        let pixels: Vec<[f32; 3]> = self
+            .vectors
+            .iter()
+            .map(|&(dx, dy)| [dx as f32, dy as f32, 0.0])
+            .collect();
+        encode_pfm(self.width, self.height, &pixels)
+    }
+}
+
+/// Frame seen at `time` between two rendered ones, zero being `previous` and one `next`.
+/// The motion is the one of `previous`, each pixel being fetched back along it in
+/// `previous` and forward in `next`.
+pub fn interpolate_frame(
+    previous: &Frame,
+    next: &Frame,
+    motion: &MotionVectors,
+    time: UnitInterval,
+) -> Result<Frame> {
+    let (width, height) = (previous.width, previous.height);
+    if (next.width, next.height) != (width, height)
+        || (motion.width, motion.height) != (width, height)
+    {
+        return Err(RaytracerError::InvalidGeometry(format!(
+            "frames to interpolate of different sizes, {}x{} and {}x{} with motion of {}x{}",
+            width, height, next.width, next.height, motion.width, motion.height
+        )));
+    }
+    let mut frame = Frame::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let (dx, dy) = motion.at(x, y);
+            let (center_x, center_y) = (x as f64 + 0.5, y as f64 + 0.5);
+            let (before, before_alpha) =
+                previous.sample(center_x - time * dx, center_y - time * dy);
+            let (after, after_alpha) =
+                next.sample(center_x + (1.0 - time) * dx, center_y + (1.0 - time) * dy);
+            let color = before * (1.0 - time) + after * time;
+            let alpha = before_alpha * (1.0 - time) + after_alpha * time;
+            let index = frame.index(x, y);
+            frame.pixels[index] = Pixel::with_alpha(x, y, color, alpha);
+        }
+    }
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moving_square(offset: u32) -> Frame {
+        let pixels = (0..8).flat_map(|y| {
+            (0..8).map(move |x| {
+                let inside = (offset..offset + 2).contains(&x) && (3..5).contains(&y);
+                Ok(Pixel::new(
+                    x,
+                    y,
+                    if inside { Color::WHITE } else { Color::BLACK },
+                ))
+            })
+        });
+        Frame::from_pixels(8, 8, pixels).unwrap()
+    }
+
+    #[test]
+    fn still_frames_are_cross_faded() {
+        let previous = moving_square(1);
+        let next = moving_square(5);
+        let frame = interpolate_frame(&previous, &next, &MotionVectors::still(8, 8), 0.5).unwrap();
+        assert!((frame.pixel(1, 3).color.red() - 0.5).abs() < 1e-9);
+        assert!((frame.pixel(5, 3).color.red() - 0.5).abs() < 1e-9);
+        assert!(frame.pixel(3, 3).color.red() < 1e-9);
+    }
+
+    #[test]
+    fn moving_objects_are_shifted() {
+        let previous = moving_square(1);
+        let next = moving_square(5);
+        let mut motion = MotionVectors::still(8, 8);
+        for y in 0..8 {
+            for x in 0..8 {
+                motion.set(x, y, (4.0, 0.0));
+            }
+        }
+        let frame = interpolate_frame(&previous, &next, &motion, 0.5).unwrap();
+        for x in 0..8 {
+            let expected = if (3..5).contains(&x) { 1.0 } else { 0.0 };
+            assert!((frame.pixel(x, 3).color.red() - expected).abs() < 1e-9);
+        }
+        assert!((frame.pixel(3, 3).alpha - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn different_sizes_are_refused() {
+        let motion = MotionVectors::still(8, 8);
+        assert!(interpolate_frame(&Frame::new(8, 8), &Frame::new(4, 8), &motion, 0.5).is_err());
+    }
+}
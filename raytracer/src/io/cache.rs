@@ -0,0 +1,277 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Binary cache of prepared scene descriptions.
+//!
+//! A cache holds the description of a scene with its meshes, fonts, SVG files and
+//! heightmaps already turned into triangles, with the bounding volume hierarchies of these
+//! triangles, so that a scene is rebuilt without reading, tessellating nor sorting them
+//! again. It is tied to the scene description it was made from, and to
+//! the modification times of the files it loaded: any change makes it stale.
+//!
+//! The description is written as the self-describing values of the serde data model, each
+//! one being a tag byte followed by its little-endian content.
+
+use crate::result::{RaytracerError, Result};
+use serde_json::{Map, Number, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 8] = b"RTSCACHE";
+const VERSION: u32 = 1;
+
+const TAG_NULL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_UNSIGNED: u8 = 3;
+const TAG_SIGNED: u8 = 4;
+const TAG_FLOAT: u8 = 5;
+const TAG_STRING: u8 = 6;
+const TAG_ARRAY: u8 = 7;
+const TAG_MAP: u8 = 8;
+
+/// Files loaded by a prepared description, with their modification time if they exist
+pub(crate) type Assets = Vec<(PathBuf, Option<(u64, u32)>)>;
+
+pub(crate) fn modification_time(path: &Path) -> Option<(u64, u32)> {
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()?;
+    let duration = modified.duration_since(UNIX_EPOCH).ok()?;
+    Some((duration.as_secs(), duration.subsec_nanos()))
+}
+
+/// FNV-1a hash, stable between builds unlike the hasher of the standard library
+pub(crate) fn fingerprint(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+pub(crate) fn encode(scene_str: &str, assets: &Assets, description: &Value) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&fingerprint(scene_str).to_le_bytes());
+    bytes.extend_from_slice(&(assets.len() as u64).to_le_bytes());
+    for (path, modified) in assets {
+        write_string(&mut bytes, &path.to_string_lossy());
+        match modified {
+            None => bytes.push(TAG_NULL),
+            Some((seconds, nanoseconds)) => {
+                bytes.push(TAG_UNSIGNED);
+                bytes.extend_from_slice(&seconds.to_le_bytes());
+                bytes.extend_from_slice(&nanoseconds.to_le_bytes());
+            }
+        }
+    }
+    write_value(&mut bytes, description);
+    bytes
+}
+
+/// Description of a cache, none if it was made from another scene description or if one
+/// of its files has changed since
+pub(crate) fn decode(bytes: &[u8], scene_str: &str) -> Result<Option<Value>> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(to_error("not a scene cache"));
+    }
+    if reader.u32()? != VERSION || reader.u64()? != fingerprint(scene_str) {
+        return Ok(None);
+    }
+    let asset_count = reader.u64()?;
+    for _ in 0..asset_count {
+        let path = PathBuf::from(reader.string()?);
+        let modified = match reader.u8()? {
+            TAG_NULL => None,
+            _ => Some((reader.u64()?, reader.u32()?)),
+        };
+        if modification_time(&path) != modified {
+            return Ok(None);
+        }
+    }
+    let description = reader.value()?;
+    if reader.position != bytes.len() {
+        return Err(to_error("trailing bytes"));
+    }
+    Ok(Some(description))
+}
+
+fn to_error(message: &str) -> RaytracerError {
+    RaytracerError::ParsingError(format!("scene cache: {}", message))
+}
+
+fn write_string(bytes: &mut Vec<u8>, text: &str) {
+    bytes.extend_from_slice(&(text.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(text.as_bytes());
+}
+
+fn write_value(bytes: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => bytes.push(TAG_NULL),
+        Value::Bool(false) => bytes.push(TAG_FALSE),
+        Value::Bool(true) => bytes.push(TAG_TRUE),
+        Value::Number(number) => {
+            if let Some(unsigned) = number.as_u64() {
+                bytes.push(TAG_UNSIGNED);
+                bytes.extend_from_slice(&unsigned.to_le_bytes());
+            } else if let Some(signed) = number.as_i64() {
+                bytes.push(TAG_SIGNED);
+                bytes.extend_from_slice(&signed.to_le_bytes());
+            } else {
+                bytes.push(TAG_FLOAT);
+                bytes.extend_from_slice(&number.as_f64().unwrap_or_default().to_le_bytes());
+            }
+        }
+        Value::String(text) => {
+            bytes.push(TAG_STRING);
+            write_string(bytes, text);
+        }
+        Value::Array(values) => {
+            bytes.push(TAG_ARRAY);
+            bytes.extend_from_slice(&(values.len() as u64).to_le_bytes());
+            for value in values {
+                write_value(bytes, value);
+            }
+        }
+        Value::Object(map) => {
+            bytes.push(TAG_MAP);
+            bytes.extend_from_slice(&(map.len() as u64).to_le_bytes());
+            for (key, value) in map {
+                write_string(bytes, key);
+                write_value(bytes, value);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or_else(|| to_error("truncated file"))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Length of a sequence, checked against the remaining bytes before any allocation
+    fn length(&mut self) -> Result<usize> {
+        let length = self.u64()?;
+        if length > (self.bytes.len() - self.position) as u64 {
+            return Err(to_error("truncated file"));
+        }
+        Ok(length as usize)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let length = self.length()?;
+        let bytes = self.take(length)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| to_error("invalid string"))
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        Ok(match self.u8()? {
+            TAG_NULL => Value::Null,
+            TAG_FALSE => Value::Bool(false),
+            TAG_TRUE => Value::Bool(true),
+            TAG_UNSIGNED => Value::from(self.u64()?),
+            TAG_SIGNED => Value::from(self.u64()? as i64),
+            TAG_FLOAT => {
+                let value = f64::from_bits(self.u64()?);
+                Value::Number(Number::from_f64(value).ok_or_else(|| to_error("invalid number"))?)
+            }
+            TAG_STRING => Value::String(self.string()?),
+            TAG_ARRAY => {
+                let length = self.length()?;
+                (0..length)
+                    .map(|_| self.value())
+                    .collect::<Result<Vec<_>>>()?
+                    .into()
+            }
+            TAG_MAP => {
+                let length = self.length()?;
+                let mut map = Map::new();
+                for _ in 0..length {
+                    let key = self.string()?;
+                    map.insert(key, self.value()?);
+                }
+                Value::Object(map)
+            }
+            tag => return Err(to_error(&format!("unknown value tag {}", tag))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn values_are_read_back() {
+        let value = json!({"camera": [1.5, -2, 3], "name": "scene", "on": true, "off": null});
+        let bytes = encode("scene", &Vec::new(), &value);
+        assert_eq!(decode(&bytes, "scene").unwrap(), Some(value));
+    }
+
+    #[test]
+    fn cache_of_another_scene_is_stale() {
+        let bytes = encode("scene", &Vec::new(), &json!(1));
+        assert_eq!(decode(&bytes, "other scene").unwrap(), None);
+        let assets = vec![(PathBuf::from("missing.obj"), Some((1, 2)))];
+        let bytes = encode("scene", &assets, &json!(1));
+        assert_eq!(decode(&bytes, "scene").unwrap(), None);
+    }
+
+    #[test]
+    fn truncated_cache_is_error() {
+        let bytes = encode("scene", &Vec::new(), &json!(["a", "b"]));
+        assert!(decode(&bytes[..bytes.len() - 1], "scene").is_err());
+        assert!(decode(b"PNG", "scene").is_err());
+    }
+}
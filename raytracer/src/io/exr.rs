@@ -0,0 +1,114 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! OpenEXR writer for single channel images of 32 bits floating point values, like depth
+//! maps, stored as uncompressed scanlines which every reader supports.
+
+/// Magic number starting every OpenEXR file
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+/// Version 2, single part scanline file
+const VERSION: [u8; 4] = [2, 0, 0, 0];
+/// Pixel type of 32 bits floating point values
+const PIXEL_TYPE_FLOAT: i32 = 2;
+
+/// File content of an image of the channel `channel`, given row by row from the top
+pub fn encode_exr(width: u32, height: u32, channel: &str, values: &[f32]) -> Vec<u8> {
+    let mut content = Vec::new();
+    content.extend_from_slice(&MAGIC);
+    content.extend_from_slice(&VERSION);
+
+    let mut channels = Vec::new();
+    channels.extend_from_slice(channel.as_bytes());
+    channels.push(0);
+    channels.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+    // Not linearly perceived, then reserved bytes
+    channels.extend_from_slice(&[0, 0, 0, 0]);
+    // Sampled at every pixel, along x then y
+    channels.extend_from_slice(&1i32.to_le_bytes());
+    channels.extend_from_slice(&1i32.to_le_bytes());
+    channels.push(0);
+    write_attribute(&mut content, "channels", "chlist", &channels);
+    write_attribute(&mut content, "compression", "compression", &[0]);
+    let mut window = Vec::new();
+    for bound in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&bound.to_le_bytes());
+    }
+    write_attribute(&mut content, "dataWindow", "box2i", &window);
+    write_attribute(&mut content, "displayWindow", "box2i", &window);
+    // Rows stored from the top
+    write_attribute(&mut content, "lineOrder", "lineOrder", &[0]);
+    let one = 1f32.to_le_bytes();
+    write_attribute(&mut content, "pixelAspectRatio", "float", &one);
+    write_attribute(&mut content, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(&mut content, "screenWindowWidth", "float", &one);
+    content.push(0);
+
+    // Offsets of the rows from the start of the file, each one being its own block
+    let row_size = 4 * width as usize;
+    let first_row = content.len() + 8 * height as usize;
+    for y in 0..height as usize {
+        let offset = first_row + y * (8 + row_size);
+        content.extend_from_slice(&(offset as u64).to_le_bytes());
+    }
+    for (y, row) in values.chunks(width as usize).enumerate() {
+        content.extend_from_slice(&(y as i32).to_le_bytes());
+        content.extend_from_slice(&(row_size as i32).to_le_bytes());
+        for value in row {
+            content.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    content
+}
+
+fn write_attribute(content: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    content.extend_from_slice(name.as_bytes());
+    content.push(0);
+    content.extend_from_slice(kind.as_bytes());
+    content.push(0);
+    content.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    content.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u64_at(content: &[u8], index: usize) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&content[index..index + 8]);
+        u64::from_le_bytes(bytes)
+    }
+
+    #[test]
+    fn rows_follow_their_offsets() {
+        let content = encode_exr(2, 3, "Z", &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(&content[..8], &[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+        let header_end = content.len() - 3 * 8 - 3 * (8 + 2 * 4);
+        assert_eq!(content[header_end - 1], 0);
+        let last_row = u64_at(&content, header_end + 2 * 8) as usize;
+        assert_eq!(&content[last_row..last_row + 4], &2i32.to_le_bytes());
+        assert_eq!(&content[last_row + 8..last_row + 12], &5f32.to_le_bytes());
+        assert_eq!(last_row + 8 + 2 * 4, content.len());
+    }
+}
@@ -0,0 +1,264 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Radiance HDR loader, giving an environment map from the RGBE pixels of a file, and
+//! writer of such files.
+//!
+//! Only the usual `-Y height +X width` orientation is read, with flat or run-length
+//! encoded scanlines; the exposure and color correction headers are ignored. Files are
+//! written with this orientation and flat scanlines.
+
+use crate::colors::Color;
+use crate::environment::LatLongMap;
+use crate::result::{RaytracerError, Result};
+use std::fs;
+use std::path::Path;
+
+pub fn load_hdr<P: AsRef<Path>>(path: P) -> Result<LatLongMap> {
+    let path = path.as_ref();
+    let content = fs::read(path)
+        .map_err(|e| RaytracerError::IoError(format!("{}: {}", path.display(), e)))?;
+    parse_hdr(&content)
+}
+
+pub fn parse_hdr(content: &[u8]) -> Result<LatLongMap> {
+    let mut reader = Reader {
+        content,
+        position: 0,
+    };
+    if !reader.line()?.starts_with("#?") {
+        return Err(to_error("missing #? signature"));
+    }
+    loop {
+        let line = reader.line()?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(to_error(&format!("unsupported {}", line)));
+        }
+    }
+    let resolution = reader.line()?.split_whitespace().collect::<Vec<_>>();
+    let (height, width) = match resolution.as_slice() {
+        ["-Y", height, "+X", width] => (height.parse::<usize>(), width.parse::<usize>()),
+        _ => return Err(to_error("only the -Y height +X width orientation is read")),
+    };
+    let (height, width) = match (height, width) {
+        (Ok(height), Ok(width)) => (height, width),
+        _ => return Err(to_error("invalid resolution")),
+    };
+    let mut pixels = Vec::with_capacity(width * height);
+    for _ in 0..height {
+        let scanline = reader.scanline(width)?;
+        pixels.extend(scanline.iter().map(|&rgbe| rgbe_to_color(rgbe)));
+    }
+    LatLongMap::new(width, height, pixels)
+}
+
+/// File content of an image given row by row from the top
+pub fn encode_hdr(width: usize, height: usize, pixels: &[Color]) -> Vec<u8> {
+    let mut content = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n".to_vec();
+    content.extend_from_slice(format!("-Y {} +X {}\n", height, width).as_bytes());
+    for color in pixels {
+        content.extend_from_slice(&color_to_rgbe(color));
+    }
+    content
+}
+
+fn to_error(message: &str) -> RaytracerError {
+    RaytracerError::ParsingError(format!("HDR file: {}", message))
+}
+
+/// Mantissas sharing a power of two exponent
+fn rgbe_to_color([red, green, blue, exponent]: [u8; 4]) -> Color {
+    if exponent == 0 {
+        return Color::BLACK;
+    }
+    let scale = 2f64.powi(exponent as i32 - (128 + 8));
+    Color::unclamped(
+        red as f64 * scale,
+        green as f64 * scale,
+        blue as f64 * scale,
+    )
+}
+
+/// Mantissas of the components for the exponent of the brightest one, negative values
+/// being black
+fn color_to_rgbe(color: &Color) -> [u8; 4] {
+    let (red, green, blue) = (
+        color.red().max(0.0),
+        color.green().max(0.0),
+        color.blue().max(0.0),
+    );
+    let brightest = red.max(green).max(blue);
+    if brightest < 1e-32 {
+        return [0; 4];
+    }
+    // The brightest mantissa goes from 128 to 255
+    let exponent = (brightest.log2().floor() as i32 + 1).clamp(-128, 127);
+    let scale = 2f64.powi(8 - exponent);
+    let mantissa = |value: f64| (value * scale).min(255.0) as u8;
+    [
+        mantissa(red),
+        mantissa(green),
+        mantissa(blue),
+        (exponent + 128) as u8,
+    ]
+}
+
+struct Reader<'a> {
+    content: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8> {
+        let byte = *self
+            .content
+            .get(self.position)
+            .ok_or_else(|| to_error("truncated pixels"))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn line(&mut self) -> Result<&'a str> {
+        let rest = &self.content[self.position..];
+        let length = rest
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .ok_or_else(|| to_error("truncated header"))?;
+        self.position += length + 1;
+        std::str::from_utf8(&rest[..length]).map_err(|_| to_error("invalid header"))
+    }
+
+    /// Run-length encoded scanlines start with 2, 2 and their width, then give each
+    /// component in turn as runs of a repeated byte or of literal bytes
+    fn scanline(&mut self, width: usize) -> Result<Vec<[u8; 4]>> {
+        let start = self.content.get(self.position..self.position + 4);
+        let is_encoded = match start {
+            Some(&[2, 2, high, low]) => {
+                (8..=0x7fff).contains(&width) && ((high as usize) << 8 | low as usize) == width
+            }
+            _ => false,
+        };
+        let mut scanline = vec![[0; 4]; width];
+        if !is_encoded {
+            for pixel in scanline.iter_mut() {
+                for component in pixel.iter_mut() {
+                    *component = self.byte()?;
+                }
+            }
+            return Ok(scanline);
+        }
+        self.position += 4;
+        for component in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = self.byte()? as usize;
+                let (length, repeated) = if count > 128 {
+                    (count - 128, Some(self.byte()?))
+                } else {
+                    (count, None)
+                };
+                if length == 0 || x + length > width {
+                    return Err(to_error("invalid scanline run"));
+                }
+                for pixel in &mut scanline[x..x + length] {
+                    pixel[component] = match repeated {
+                        Some(byte) => byte,
+                        None => self.byte()?,
+                    };
+                }
+                x += length;
+            }
+        }
+        Ok(scanline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::Vec3;
+
+    const HEADER: &[u8] = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=1.0\n\n";
+
+    #[test]
+    fn flat_pixels_are_decoded() {
+        // A pixel of intensity 1 then a doubled one, 2 pixels wide
+        let mut content = HEADER.to_vec();
+        content.extend_from_slice(b"-Y 1 +X 2\n");
+        content.extend_from_slice(&[128, 64, 0, 129, 128, 0, 0, 130]);
+        let map = parse_hdr(&content).unwrap();
+        let color = map.color_in(Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        assert_eq!((color.red(), color.green(), color.blue()), (1.0, 0.5, 0.0));
+        let color = map.color_in(Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert_eq!(color.red(), 2.0);
+    }
+
+    #[test]
+    fn written_pixels_are_read_back() {
+        let pixels = [Color::unclamped(12.5, 0.25, 0.0), Color::BLACK];
+        let content = encode_hdr(2, 1, &pixels);
+        let map = parse_hdr(&content).unwrap();
+        let color = map.color_in(Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        assert_eq!(
+            (color.red(), color.green(), color.blue()),
+            (12.5, 0.25, 0.0)
+        );
+        assert_eq!(map.color_in(Vec3::new(1.0, 0.0, 0.0), 0.0).red(), 0.0);
+    }
+
+    #[test]
+    fn encoded_scanline_is_decoded() {
+        let mut content = HEADER.to_vec();
+        content.extend_from_slice(b"-Y 1 +X 8\n");
+        content.extend_from_slice(&[2, 2, 0, 8]);
+        // Red as a run, green as literals, blue and exponent as runs
+        content.extend_from_slice(&[136, 128]);
+        content.extend_from_slice(&[8, 0, 0, 0, 0, 128, 128, 128, 128]);
+        content.extend_from_slice(&[136, 0, 136, 129]);
+        let map = parse_hdr(&content).unwrap();
+        let color = map.color_in(Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert_eq!((color.red(), color.green(), color.blue()), (1.0, 1.0, 0.0));
+        let color = map.color_in(Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        assert_eq!((color.red(), color.green()), (1.0, 0.0));
+    }
+
+    #[test]
+    fn truncated_file_is_error() {
+        let mut content = HEADER.to_vec();
+        content.extend_from_slice(b"-Y 2 +X 2\n");
+        content.extend_from_slice(&[128, 128, 128, 129]);
+        assert!(parse_hdr(&content).is_err());
+    }
+
+    #[test]
+    fn other_orientation_is_error() {
+        let mut content = HEADER.to_vec();
+        content.extend_from_slice(b"+X 1 -Y 1\n");
+        content.extend_from_slice(&[128, 128, 128, 129]);
+        assert!(parse_hdr(&content).is_err());
+    }
+}
@@ -0,0 +1,281 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Wavefront OBJ loader, giving a triangle mesh from the vertices, normals,
+//! texture coordinates and faces of a file.
+//!
+//! Objects, groups, materials and smoothing groups are ignored: the whole file
+//! is loaded as one mesh. Faces with more than three vertices are split as
+//! triangle fans. The `shading` of the scene object chooses between the file
+//! normals, flat faces, and normals smoothed over the whole mesh.
+
+use crate::primitives::TriangleMesh;
+use crate::result::{RaytracerError, Result};
+use crate::vector::Vec3;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Indexes of a face vertex in the position, texture coordinate and normal lists
+type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<TriangleMesh> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+        .map_err(|e| RaytracerError::IoError(format!("{}: {}", path.display(), e)))?;
+    parse_obj(&content)
+}
+
+pub fn parse_obj(content: &str) -> Result<TriangleMesh> {
+    let mut positions = Vec::new();
+    let mut texture_coordinates = Vec::new();
+    let mut normals = Vec::new();
+    let mut faces: Vec<Vec<FaceVertex>> = Vec::new();
+
+    for (line_index, line) in content.lines().enumerate() {
+        let to_error = |message: String| {
+            RaytracerError::ParsingError(format!("OBJ line {}: {}", line_index + 1, message))
+        };
+        let mut tokens = line
+            .split('#')
+            .next()
+            .unwrap_or_default()
+            .split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let arguments = tokens.collect::<Vec<_>>();
+        match keyword {
+            "v" => {
+                let values = parse_floats(&arguments, 3).map_err(to_error)?;
+                positions.push(Vec3::new(values[0], values[1], values[2]));
+            }
+            "vt" => {
+                let values = parse_floats(&arguments, 1).map_err(to_error)?;
+                texture_coordinates.push((values[0], values.get(1).copied().unwrap_or(0.0)));
+            }
+            "vn" => {
+                let values = parse_floats(&arguments, 3).map_err(to_error)?;
+                normals.push(Vec3::new(values[0], values[1], values[2]));
+            }
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(to_error(String::from("a face needs at least 3 vertices")));
+                }
+                let face = arguments
+                    .iter()
+                    .map(|argument| {
+                        parse_face_vertex(
+                            argument,
+                            positions.len(),
+                            texture_coordinates.len(),
+                            normals.len(),
+                        )
+                    })
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(to_error)?;
+                faces.push(face);
+            }
+            _ => {}
+        }
+    }
+    build_mesh(positions, texture_coordinates, normals, faces)
+}
+
+fn parse_floats(arguments: &[&str], minimum_count: usize) -> std::result::Result<Vec<f64>, String> {
+    if arguments.len() < minimum_count {
+        return Err(format!(
+            "expected {} values, found {}",
+            minimum_count,
+            arguments.len()
+        ));
+    }
+    arguments
+        .iter()
+        .map(|argument| {
+            argument
+                .parse::<f64>()
+                .map_err(|_| format!("invalid number: {}", argument))
+        })
+        .collect()
+}
+
+/// Parse a `v`, `v/vt`, `v//vn` or `v/vt/vn` face vertex, giving zero-based indexes
+fn parse_face_vertex(
+    argument: &str,
+    position_count: usize,
+    texture_count: usize,
+    normal_count: usize,
+) -> std::result::Result<FaceVertex, String> {
+    let mut parts = argument.split('/');
+    let position = match parts.next() {
+        Some(part) => resolve_index(part, position_count)?,
+        None => return Err(format!("invalid face vertex: {}", argument)),
+    };
+    let texture = match parts.next() {
+        Some("") | None => None,
+        Some(part) => Some(resolve_index(part, texture_count)?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(part) => Some(resolve_index(part, normal_count)?),
+    };
+    if parts.next().is_some() {
+        return Err(format!("invalid face vertex: {}", argument));
+    }
+    Ok((position, texture, normal))
+}
+
+/// OBJ indexes start at 1, and negative ones are relative to the end of the list
+fn resolve_index(part: &str, count: usize) -> std::result::Result<usize, String> {
+    let index = part
+        .parse::<i64>()
+        .map_err(|_| format!("invalid index: {}", part))?;
+    let resolved = match index {
+        i if i > 0 => i - 1,
+        i if i < 0 => count as i64 + i,
+        _ => return Err(String::from("index 0 is not valid")),
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!("index {} is out of {} elements", index, count));
+    }
+    Ok(resolved as usize)
+}
+
+/// Positions, normals and texture coordinates can have their own indexes in OBJ
+/// faces, so each distinct combination becomes a vertex of the mesh.
+fn build_mesh(
+    positions: Vec<Vec3>,
+    texture_coordinates: Vec<(f64, f64)>,
+    normals: Vec<Vec3>,
+    faces: Vec<Vec<FaceVertex>>,
+) -> Result<TriangleMesh> {
+    if faces.is_empty() {
+        return Err(RaytracerError::ParsingError(String::from(
+            "OBJ file has no face",
+        )));
+    }
+    // Normals and texture coordinates are only used if given for all vertices
+    let face_vertices = || faces.iter().flatten();
+    let has_textures = face_vertices().all(|(_, texture, _)| texture.is_some());
+    let has_normals = face_vertices().all(|(_, _, normal)| normal.is_some());
+
+    let mut vertex_indexes: HashMap<FaceVertex, usize> = HashMap::new();
+    let mut mesh_vertices = Vec::new();
+    let mut mesh_textures = Vec::new();
+    let mut mesh_normals = Vec::new();
+    let mut triangles = Vec::new();
+    for face in &faces {
+        let indexes = face
+            .iter()
+            .map(|&(position, texture, normal)| {
+                let key = (
+                    position,
+                    texture.filter(|_| has_textures),
+                    normal.filter(|_| has_normals),
+                );
+                *vertex_indexes.entry(key).or_insert_with(|| {
+                    mesh_vertices.push(positions[position]);
+                    if let Some(texture) = key.1 {
+                        mesh_textures.push(texture_coordinates[texture]);
+                    }
+                    if let Some(normal) = key.2 {
+                        mesh_normals.push(normals[normal]);
+                    }
+                    mesh_vertices.len() - 1
+                })
+            })
+            .collect::<Vec<_>>();
+        for i in 1..indexes.len() - 1 {
+            triangles.push([indexes[0], indexes[i], indexes[i + 1]]);
+        }
+    }
+
+    let mesh = if has_normals {
+        TriangleMesh::with_normals(mesh_vertices, triangles, mesh_normals)?
+    } else {
+        TriangleMesh::new(mesh_vertices, triangles)?
+    };
+    if has_textures {
+        mesh.with_texture_coordinates(mesh_textures)
+    } else {
+        Ok(mesh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD_OBJ: &str = r##"
+        # A unit square on the XY plane
+        o square
+        v 0 0 0
+        v 1 0 0
+        v 1 1 0
+        v 0 1 0
+        vt 0 0
+        vt 1 0
+        vt 1 1
+        vt 0 1
+        vn 0 0 -1
+        s off
+        f 1/1/1 2/2/1 3/3/1 4/4/1
+    "##;
+
+    #[test]
+    fn polygon_is_split_in_triangles() {
+        let mesh = parse_obj(QUAD_OBJ).unwrap();
+        assert_eq!(mesh.vertices().len(), 4);
+        assert_eq!(mesh.triangles(), &[[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn face_vertex_formats_and_negative_indexes() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf -3//1 -2//1 -1//1\n";
+        let mesh = parse_obj(obj).unwrap();
+        assert_eq!(mesh.triangles(), &[[0, 1, 2]]);
+    }
+
+    #[test]
+    fn out_of_range_index_is_error() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n";
+        assert!(parse_obj(obj).is_err());
+    }
+
+    #[test]
+    fn file_without_face_is_error() {
+        assert!(parse_obj("v 0 0 0\n").is_err());
+    }
+
+    #[test]
+    fn degenerate_face_is_left_out() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 2 0 0\nf 1 2 3\nf 1 2 4\n";
+        let mesh = parse_obj(obj).unwrap();
+        assert_eq!(mesh.triangles(), &[[0, 1, 2]]);
+        assert_eq!(mesh.degenerate_triangle_count(), 1);
+        assert!(parse_obj("v 0 0 0\nv 1 0 0\nv 2 0 0\nf 1 2 3\n").is_err());
+    }
+}
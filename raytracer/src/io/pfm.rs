@@ -0,0 +1,58 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Portable float map writer, giving images of 32 bits floating point values which are
+//! not colors, like motion vectors, as three channels read by most compositing tools.
+
+/// File content of an image given row by row from the top, PFM storing rows from the
+/// bottom in little endian order
+pub fn encode_pfm(width: u32, height: u32, pixels: &[[f32; 3]]) -> Vec<u8> {
+    let mut content = format!("PF\n{} {}\n-1.0\n", width, height).into_bytes();
+    for row in pixels.chunks(width as usize).rev() {
+        for value in row.iter().flatten() {
+            content.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_written_from_the_bottom() {
+        let content = encode_pfm(1, 2, &[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        let header = b"PF\n1 2\n-1.0\n";
+        assert_eq!(&content[..header.len()], header);
+        assert_eq!(content.len(), header.len() + 2 * 3 * 4);
+        let first = f32::from_le_bytes([
+            content[header.len()],
+            content[header.len() + 1],
+            content[header.len() + 2],
+            content[header.len() + 3],
+        ]);
+        assert_eq!(first, 4.0);
+    }
+}
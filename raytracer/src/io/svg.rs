@@ -0,0 +1,614 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! SVG loader, giving the outlines of the filled shapes of a file.
+//!
+//! The `path`, `rect`, `circle`, `ellipse`, `polygon` and `polyline` elements are read with
+//! the transformations of their groups, curves and arcs being flattened into polygons.
+//! Shapes with `fill="none"` and those inside definitions, clip paths or masks are ignored.
+//! Holes follow the even-odd rule, whatever the fill rule of the file.
+
+use crate::extrusion::Contour;
+use crate::result::{RaytracerError, Result};
+use std::f64::consts::PI;
+use std::fs;
+use std::path::Path;
+
+/// Segments approximating each Bézier curve
+const CURVE_STEPS: usize = 12;
+
+/// Segments approximating a full turn of an arc or circle
+const TURN_STEPS: usize = 48;
+
+/// Elements whose content is not drawn directly
+const HIDDEN_ELEMENTS: [&str; 7] = [
+    "defs", "clipPath", "mask", "symbol", "pattern", "marker", "metadata",
+];
+
+/// Affine transformation [a, b, c, d, e, f], giving (a x + c y + e, b x + d y + f)
+type Transform = [f64; 6];
+
+const IDENTITY: Transform = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+pub fn load_svg<P: AsRef<Path>>(path: P) -> Result<Vec<Contour>> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+        .map_err(|e| RaytracerError::IoError(format!("{}: {}", path.display(), e)))?;
+    parse_svg(&content)
+}
+
+fn to_error(message: String) -> RaytracerError {
+    RaytracerError::ParsingError(format!("SVG: {}", message))
+}
+
+pub fn parse_svg(content: &str) -> Result<Vec<Contour>> {
+    let mut contours = Vec::new();
+    // Transformation and visibility of the open elements
+    let mut stack: Vec<(Transform, bool)> = vec![(IDENTITY, true)];
+    let mut rest = content;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        let end_marker = if rest.starts_with("<!--") {
+            "-->"
+        } else if rest.starts_with("<![CDATA[") {
+            "]]>"
+        } else {
+            ">"
+        };
+        let end = match rest.starts_with("<!--") || rest.starts_with("<![CDATA[") {
+            true => rest.find(end_marker),
+            false => tag_end(rest),
+        }
+        .ok_or_else(|| to_error(String::from("unclosed tag")))?;
+        let tag = &rest[1..end];
+        rest = &rest[end + end_marker.len()..];
+        if tag.starts_with('!') || tag.starts_with('?') {
+            continue;
+        }
+        if tag.starts_with('/') {
+            if stack.len() > 1 {
+                stack.pop();
+            }
+            continue;
+        }
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let name = tag.split_whitespace().next().unwrap_or_default();
+        let attributes = parse_attributes(&tag[name.len()..]);
+        let attribute = |key: &str| {
+            attributes
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.as_str())
+        };
+        let &(parent_transform, parent_visible) = stack.last().unwrap_or(&(IDENTITY, true));
+        let transform = match attribute("transform") {
+            Some(value) => multiply(parent_transform, parse_transform(value)?),
+            None => parent_transform,
+        };
+        let filled = attribute("fill") != Some("none")
+            && !attribute("style").is_some_and(|style| {
+                style
+                    .split(';')
+                    .any(|declaration| declaration.replace(' ', "") == "fill:none")
+            });
+        let visible = parent_visible && !HIDDEN_ELEMENTS.contains(&name);
+        if visible && filled {
+            let shapes = shape_contours(name, &attribute)?;
+            contours.extend(shapes.into_iter().map(|contour| {
+                contour
+                    .into_iter()
+                    .map(|point| {
+                        let (x, y) = apply(transform, point);
+                        // The Y axis of the image goes down
+                        (x, -y)
+                    })
+                    .collect()
+            }));
+        }
+        if !self_closing {
+            stack.push((transform, visible));
+        }
+    }
+    Ok(contours)
+}
+
+/// End of a tag, ignoring the `>` inside the attribute values
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    for (index, character) in tag.char_indices() {
+        match (quote, character) {
+            (None, '"') | (None, '\'') => quote = Some(character),
+            (Some(open), _) if open == character => quote = None,
+            (None, '>') => return Some(index),
+            _ => (),
+        }
+    }
+    None
+}
+
+fn parse_attributes(text: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = text;
+    while let Some(equal) = rest.find('=') {
+        let name = rest[..equal].trim().to_string();
+        let value = rest[equal + 1..].trim_start();
+        let quote = match value.chars().next() {
+            Some(quote @ '"') | Some(quote @ '\'') => quote,
+            _ => break,
+        };
+        let value = &value[1..];
+        let end = match value.find(quote) {
+            Some(end) => end,
+            None => break,
+        };
+        attributes.push((name, value[..end].to_string()));
+        rest = &value[end + 1..];
+    }
+    attributes
+}
+
+fn multiply(m: Transform, n: Transform) -> Transform {
+    [
+        m[0] * n[0] + m[2] * n[1],
+        m[1] * n[0] + m[3] * n[1],
+        m[0] * n[2] + m[2] * n[3],
+        m[1] * n[2] + m[3] * n[3],
+        m[0] * n[4] + m[2] * n[5] + m[4],
+        m[1] * n[4] + m[3] * n[5] + m[5],
+    ]
+}
+
+fn apply(m: Transform, (x, y): (f64, f64)) -> (f64, f64) {
+    (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
+
+fn parse_transform(text: &str) -> Result<Transform> {
+    let mut transform = IDENTITY;
+    let mut rest = text;
+    while let Some(open) = rest.find('(') {
+        let name = rest[..open].trim_matches(|c: char| c.is_whitespace() || c == ',');
+        let close = rest[open..]
+            .find(')')
+            .map(|close| open + close)
+            .ok_or_else(|| to_error(format!("unclosed transform {}", text)))?;
+        let mut reader = Reader::new(&rest[open + 1..close]);
+        let mut values = Vec::new();
+        while let Some(value) = reader.number() {
+            values.push(value);
+        }
+        let value = |index: usize| values.get(index).copied();
+        let (first, second) = (value(0).unwrap_or(0.0), value(1));
+        let step = match (name, values.len()) {
+            ("matrix", 6) => [
+                values[0], values[1], values[2], values[3], values[4], values[5],
+            ],
+            ("translate", 1) | ("translate", 2) => {
+                [1.0, 0.0, 0.0, 1.0, first, second.unwrap_or(0.0)]
+            }
+            ("scale", 1) | ("scale", 2) => [first, 0.0, 0.0, second.unwrap_or(first), 0.0, 0.0],
+            ("rotate", 1) | ("rotate", 3) => {
+                let (sin, cos) = first.to_radians().sin_cos();
+                let (cx, cy) = (second.unwrap_or(0.0), value(2).unwrap_or(0.0));
+                let rotation = [cos, sin, -sin, cos, 0.0, 0.0];
+                let to_center = [1.0, 0.0, 0.0, 1.0, cx, cy];
+                let from_center = [1.0, 0.0, 0.0, 1.0, -cx, -cy];
+                multiply(multiply(to_center, rotation), from_center)
+            }
+            ("skewX", 1) => [1.0, 0.0, first.to_radians().tan(), 1.0, 0.0, 0.0],
+            ("skewY", 1) => [1.0, first.to_radians().tan(), 0.0, 1.0, 0.0, 0.0],
+            _ => return Err(to_error(format!("invalid transform {}", &rest[..=close]))),
+        };
+        transform = multiply(transform, step);
+        rest = &rest[close + 1..];
+    }
+    Ok(transform)
+}
+
+fn shape_contours<'a, F>(name: &str, attribute: &F) -> Result<Vec<Contour>>
+where
+    F: Fn(&str) -> Option<&'a str>,
+{
+    let number = |key: &str| -> Result<f64> {
+        match attribute(key) {
+            None => Ok(0.0),
+            Some(value) => {
+                let value = value.trim().trim_end_matches("px");
+                value
+                    .parse()
+                    .map_err(|_| to_error(format!("invalid {} of {}: {}", key, name, value)))
+            }
+        }
+    };
+    let contour = match name {
+        "path" => return parse_path(attribute("d").unwrap_or_default()),
+        "rect" => {
+            let (x, y) = (number("x")?, number("y")?);
+            let (width, height) = (number("width")?, number("height")?);
+            vec![
+                (x, y),
+                (x + width, y),
+                (x + width, y + height),
+                (x, y + height),
+            ]
+        }
+        "circle" | "ellipse" => {
+            let (cx, cy) = (number("cx")?, number("cy")?);
+            let (rx, ry) = match name {
+                "circle" => (number("r")?, number("r")?),
+                _ => (number("rx")?, number("ry")?),
+            };
+            (0..TURN_STEPS)
+                .map(|step| {
+                    let angle = 2.0 * PI * step as f64 / TURN_STEPS as f64;
+                    (cx + rx * angle.cos(), cy + ry * angle.sin())
+                })
+                .collect()
+        }
+        "polygon" | "polyline" => {
+            let mut reader = Reader::new(attribute("points").unwrap_or_default());
+            let mut points = Vec::new();
+            while let Some(point) = reader.point() {
+                points.push(point);
+            }
+            points
+        }
+        _ => return Ok(Vec::new()),
+    };
+    Ok(vec![contour])
+}
+
+/// Contours of the `d` attribute of a path, each subpath being closed
+fn parse_path(data: &str) -> Result<Vec<Contour>> {
+    let mut reader = Reader::new(data);
+    let mut contours = Vec::new();
+    let mut contour: Contour = Vec::new();
+    let mut current = (0.0, 0.0);
+    let mut start = (0.0, 0.0);
+    // Second control point of the previous curve, reflected by the smooth curves
+    let mut last_control: Option<(char, (f64, f64))> = None;
+    let mut command = None;
+    loop {
+        reader.skip_separators();
+        if reader.is_done() {
+            break;
+        }
+        if let Some(letter) = reader.command() {
+            command = Some(letter);
+        }
+        let letter = command.ok_or_else(|| to_error(String::from("path without command")))?;
+        let relative = letter.is_ascii_lowercase();
+        let offset = |(x, y): (f64, f64), current: (f64, f64)| match relative {
+            true => (x + current.0, y + current.1),
+            false => (x, y),
+        };
+        let missing = || to_error(format!("missing value after {} in path", letter));
+        let mut control = None;
+        match letter.to_ascii_uppercase() {
+            'M' => {
+                let point = offset(reader.point().ok_or_else(missing)?, current);
+                contours.push(std::mem::take(&mut contour));
+                contour.push(point);
+                current = point;
+                start = point;
+                // Coordinates following a move are lines
+                command = Some(if relative { 'l' } else { 'L' });
+            }
+            'Z' => {
+                contours.push(std::mem::take(&mut contour));
+                current = start;
+                command = None;
+            }
+            'L' => {
+                current = offset(reader.point().ok_or_else(missing)?, current);
+                line_to(&mut contour, start, current);
+            }
+            'H' => {
+                let x = reader.number().ok_or_else(missing)?;
+                current.0 = if relative { current.0 + x } else { x };
+                line_to(&mut contour, start, current);
+            }
+            'V' => {
+                let y = reader.number().ok_or_else(missing)?;
+                current.1 = if relative { current.1 + y } else { y };
+                line_to(&mut contour, start, current);
+            }
+            'C' | 'S' => {
+                let first = match letter.to_ascii_uppercase() {
+                    'C' => offset(reader.point().ok_or_else(missing)?, current),
+                    _ => reflected(last_control, &['C', 'S'], current),
+                };
+                let second = offset(reader.point().ok_or_else(missing)?, current);
+                let end = offset(reader.point().ok_or_else(missing)?, current);
+                line_to(&mut contour, start, current);
+                for step in 1..=CURVE_STEPS {
+                    let t = step as f64 / CURVE_STEPS as f64;
+                    let u = 1.0 - t;
+                    let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+                    contour.push((
+                        a * current.0 + b * first.0 + c * second.0 + d * end.0,
+                        a * current.1 + b * first.1 + c * second.1 + d * end.1,
+                    ));
+                }
+                control = Some(('C', second));
+                current = end;
+            }
+            'Q' | 'T' => {
+                let middle = match letter.to_ascii_uppercase() {
+                    'Q' => offset(reader.point().ok_or_else(missing)?, current),
+                    _ => reflected(last_control, &['Q'], current),
+                };
+                let end = offset(reader.point().ok_or_else(missing)?, current);
+                line_to(&mut contour, start, current);
+                for step in 1..=CURVE_STEPS {
+                    let t = step as f64 / CURVE_STEPS as f64;
+                    let (a, b, c) = ((1.0 - t) * (1.0 - t), 2.0 * t * (1.0 - t), t * t);
+                    contour.push((
+                        a * current.0 + b * middle.0 + c * end.0,
+                        a * current.1 + b * middle.1 + c * end.1,
+                    ));
+                }
+                control = Some(('Q', middle));
+                current = end;
+            }
+            'A' => {
+                let radii = reader.point().ok_or_else(missing)?;
+                let rotation = reader.number().ok_or_else(missing)?;
+                let large_arc = reader.flag().ok_or_else(missing)?;
+                let sweep = reader.flag().ok_or_else(missing)?;
+                let end = offset(reader.point().ok_or_else(missing)?, current);
+                line_to(&mut contour, start, current);
+                contour.extend(arc_points(current, radii, rotation, large_arc, sweep, end));
+                current = end;
+            }
+            _ => return Err(to_error(format!("unknown path command {}", letter))),
+        }
+        last_control = control;
+    }
+    contours.push(contour);
+    contours.retain(|contour| contour.len() >= 3);
+    Ok(contours)
+}
+
+/// Starts a subpath drawn after a close, which reopens it at its start
+fn line_to(contour: &mut Contour, start: (f64, f64), point: (f64, f64)) {
+    if contour.is_empty() {
+        contour.push(start);
+    }
+    if contour.last() != Some(&point) {
+        contour.push(point);
+    }
+}
+
+/// First control point of a smooth curve, mirror of the previous curve control point
+fn reflected(
+    last_control: Option<(char, (f64, f64))>,
+    kinds: &[char],
+    current: (f64, f64),
+) -> (f64, f64) {
+    match last_control {
+        Some((kind, (x, y))) if kinds.contains(&kind) => (2.0 * current.0 - x, 2.0 * current.1 - y),
+        _ => current,
+    }
+}
+
+/// Points of an elliptical arc after its start, from the endpoint parameters of SVG
+fn arc_points(
+    from: (f64, f64),
+    (rx, ry): (f64, f64),
+    rotation_degree: f64,
+    large_arc: bool,
+    sweep: bool,
+    to: (f64, f64),
+) -> Contour {
+    let (mut rx, mut ry) = (rx.abs(), ry.abs());
+    if rx == 0.0 || ry == 0.0 || from == to {
+        return vec![to];
+    }
+    let (sin, cos) = rotation_degree.to_radians().sin_cos();
+    let (dx, dy) = ((from.0 - to.0) / 2.0, (from.1 - to.1) / 2.0);
+    let (x1, y1) = (cos * dx + sin * dy, -sin * dx + cos * dy);
+    // Radii too small to join the points are scaled up
+    let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+    let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+    let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let sign = if large_arc != sweep { 1.0 } else { -1.0 };
+    let coefficient = sign * (numerator / denominator).max(0.0).sqrt();
+    let (cx1, cy1) = (coefficient * rx * y1 / ry, -coefficient * ry * x1 / rx);
+    let center = (
+        cos * cx1 - sin * cy1 + (from.0 + to.0) / 2.0,
+        sin * cx1 + cos * cy1 + (from.1 + to.1) / 2.0,
+    );
+    let angle =
+        |(ux, uy): (f64, f64), (vx, vy): (f64, f64)| (ux * vy - uy * vx).atan2(ux * vx + uy * vy);
+    let start_vector = ((x1 - cx1) / rx, (y1 - cy1) / ry);
+    let end_vector = ((-x1 - cx1) / rx, (-y1 - cy1) / ry);
+    let start_angle = angle((1.0, 0.0), start_vector);
+    let mut sweep_angle = angle(start_vector, end_vector);
+    if !sweep && sweep_angle > 0.0 {
+        sweep_angle -= 2.0 * PI;
+    } else if sweep && sweep_angle < 0.0 {
+        sweep_angle += 2.0 * PI;
+    }
+    let steps = ((sweep_angle.abs() / (2.0 * PI) * TURN_STEPS as f64).ceil() as usize).max(1);
+    let mut points: Contour = (1..steps)
+        .map(|step| {
+            let theta = start_angle + sweep_angle * step as f64 / steps as f64;
+            let (x, y) = (rx * theta.cos(), ry * theta.sin());
+            (cos * x - sin * y + center.0, sin * x + cos * y + center.1)
+        })
+        .collect();
+    points.push(to);
+    points
+}
+
+/// Numbers of path data and lists, separated by spaces, commas or their own signs
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(text: &'a str) -> Self {
+        Reader {
+            bytes: text.as_bytes(),
+            position: 0,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn skip_separators(&mut self) {
+        while self.position < self.bytes.len()
+            && (self.bytes[self.position].is_ascii_whitespace()
+                || self.bytes[self.position] == b',')
+        {
+            self.position += 1;
+        }
+    }
+
+    fn command(&mut self) -> Option<char> {
+        self.skip_separators();
+        let byte = *self.bytes.get(self.position)?;
+        if byte.is_ascii_alphabetic() && byte != b'e' && byte != b'E' {
+            self.position += 1;
+            Some(byte as char)
+        } else {
+            None
+        }
+    }
+
+    fn number(&mut self) -> Option<f64> {
+        self.skip_separators();
+        let start = self.position;
+        let mut end = start;
+        let at = |index: usize| self.bytes.get(index).copied().unwrap_or(0);
+        if at(end) == b'+' || at(end) == b'-' {
+            end += 1;
+        }
+        let mut seen_point = false;
+        while at(end).is_ascii_digit() || (at(end) == b'.' && !seen_point) {
+            seen_point |= at(end) == b'.';
+            end += 1;
+        }
+        if (at(end) == b'e' || at(end) == b'E')
+            && (at(end + 1).is_ascii_digit()
+                || ((at(end + 1) == b'-' || at(end + 1) == b'+') && at(end + 2).is_ascii_digit()))
+        {
+            end += 2;
+            while at(end).is_ascii_digit() {
+                end += 1;
+            }
+        }
+        let value = std::str::from_utf8(&self.bytes[start..end])
+            .ok()?
+            .parse()
+            .ok()?;
+        self.position = end;
+        Some(value)
+    }
+
+    fn point(&mut self) -> Option<(f64, f64)> {
+        let x = self.number()?;
+        let y = self.number()?;
+        Some((x, y))
+    }
+
+    /// Flags of the arcs are single digits, which can be written without separators
+    fn flag(&mut self) -> Option<bool> {
+        self.skip_separators();
+        let flag = match self.bytes.get(self.position)? {
+            b'0' => false,
+            b'1' => true,
+            _ => return None,
+        };
+        self.position += 1;
+        Some(flag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn near(point: (f64, f64), expected: (f64, f64)) -> bool {
+        (point.0 - expected.0).hypot(point.1 - expected.1) < 1e-9
+    }
+
+    #[test]
+    fn paths_are_flipped_upright() {
+        let svg = r#"<svg><path d="M0 0 H10 V10 H0 Z m2 2 h6 v6 h-6 z"/></svg>"#;
+        let contours = parse_svg(svg).unwrap();
+        assert_eq!(contours.len(), 2);
+        assert_eq!(
+            contours[0],
+            vec![(0.0, 0.0), (10.0, 0.0), (10.0, -10.0), (0.0, -10.0)]
+        );
+        // A relative move after a close starts from the subpath start
+        assert_eq!(contours[1][0], (2.0, -2.0));
+        assert_eq!(contours[1][2], (8.0, -8.0));
+    }
+
+    #[test]
+    fn numbers_without_separators_are_read() {
+        let mut reader = Reader::new("1-2.5.5e1,3E-1 -.25");
+        let values: Vec<f64> = std::iter::from_fn(|| reader.number()).collect();
+        assert_eq!(values, vec![1.0, -2.5, 5.0, 0.3, -0.25]);
+        let contours = parse_svg(r#"<path d="M0,0a5 5 0 1110 0z"/>"#).unwrap();
+        assert!(contours[0]
+            .iter()
+            .all(|&(x, y)| ((x - 5.0).hypot(y) - 5.0).abs() < 1e-9));
+        assert!(contours[0].iter().any(|&point| near(point, (5.0, 5.0))));
+    }
+
+    #[test]
+    fn group_transformations_are_applied() {
+        let svg = r#"<svg>
+            <g transform="translate(10, 0) scale(2)">
+                <rect x="1" y="1" width="1" height="1"/>
+                <circle cx="0" cy="0" r="1" transform="rotate(90)" fill="none"/>
+            </g>
+            <defs><rect width="5" height="5"/></defs>
+            <polygon points="0,0 1,0 0,1"/>
+        </svg>"#;
+        let contours = parse_svg(svg).unwrap();
+        assert_eq!(contours.len(), 2);
+        assert!(near(contours[0][0], (12.0, -2.0)));
+        assert!(near(contours[0][2], (14.0, -4.0)));
+        assert_eq!(contours[1].len(), 3);
+    }
+
+    #[test]
+    fn invalid_path_is_error() {
+        assert!(parse_svg(r#"<path d="M 0 0 L 1"/>"#).is_err());
+        assert!(parse_svg(r#"<path d="10 10"/>"#).is_err());
+        assert!(parse_svg(r#"<rect transform="spin(3)"/>"#).is_err());
+    }
+}
@@ -0,0 +1,599 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! TrueType font loader, giving the outlines of the glyphs of a text.
+//!
+//! Only the tables needed by the outlines are read: the `cmap` character map in format 4
+//! or 12, the `glyf` quadratic outlines with their composite glyphs, and the advance
+//! widths of `hmtx`. Kerning and hinting are ignored, and OpenType fonts with cubic CFF
+//! outlines are not supported.
+
+use crate::extrusion::Contour;
+use crate::result::{RaytracerError, Result};
+use std::fs;
+use std::path::Path;
+
+/// Segments approximating each quadratic curve of an outline
+const CURVE_STEPS: usize = 8;
+
+/// Depth of the composite glyphs made of other composite glyphs
+const MAX_COMPONENT_DEPTH: u32 = 8;
+
+pub struct Font {
+    data: Vec<u8>,
+    units_per_em: f64,
+    line_height: f64,
+    glyf: usize,
+    loca: Vec<usize>,
+    advances: Vec<u16>,
+    character_map: CharacterMap,
+}
+
+enum CharacterMap {
+    /// Segments of the format 4 table, with the position of their range offset
+    Segments(Vec<Segment>),
+    /// Start and end characters of the format 12 groups, with their first glyph
+    Groups(Vec<(u32, u32, u32)>),
+}
+
+struct Segment {
+    start: u32,
+    end: u32,
+    delta: u16,
+    range_offset: u16,
+    range_offset_position: usize,
+}
+
+pub fn load_font<P: AsRef<Path>>(path: P) -> Result<Font> {
+    let path = path.as_ref();
+    let data = fs::read(path)
+        .map_err(|e| RaytracerError::IoError(format!("{}: {}", path.display(), e)))?;
+    Font::parse(data)
+}
+
+fn to_error(message: &str) -> RaytracerError {
+    RaytracerError::ParsingError(format!("TrueType font: {}", message))
+}
+
+fn read_u16(data: &[u8], position: usize) -> Result<u16> {
+    match data.get(position..position + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(to_error("truncated table")),
+    }
+}
+
+fn read_i16(data: &[u8], position: usize) -> Result<i16> {
+    read_u16(data, position).map(|value| value as i16)
+}
+
+fn read_u32(data: &[u8], position: usize) -> Result<u32> {
+    match data.get(position..position + 4) {
+        Some(bytes) => Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err(to_error("truncated table")),
+    }
+}
+
+/// Fixed point number with 2 integer bits and 14 fractional bits
+fn read_f2dot14(data: &[u8], position: usize) -> Result<f64> {
+    read_i16(data, position).map(|value| value as f64 / 16384.0)
+}
+
+impl Font {
+    pub fn parse(data: Vec<u8>) -> Result<Font> {
+        let version = read_u32(&data, 0)?;
+        if version == 0x4f54_544f {
+            return Err(to_error("CFF outlines are not supported"));
+        }
+        if version != 0x0001_0000 && version != 0x7472_7565 {
+            return Err(to_error("not a TrueType font"));
+        }
+        let table_count = read_u16(&data, 4)? as usize;
+        let mut tables = std::collections::HashMap::new();
+        for index in 0..table_count {
+            let record = 12 + 16 * index;
+            let tag = data
+                .get(record..record + 4)
+                .ok_or_else(|| to_error("truncated table directory"))?;
+            tables.insert(tag.to_vec(), read_u32(&data, record + 8)? as usize);
+        }
+        let table = |tag: &[u8; 4]| {
+            tables
+                .get(&tag[..])
+                .copied()
+                .ok_or_else(|| to_error(&format!("missing {} table", String::from_utf8_lossy(tag))))
+        };
+        let (head, maxp, hhea, hmtx) = (
+            table(b"head")?,
+            table(b"maxp")?,
+            table(b"hhea")?,
+            table(b"hmtx")?,
+        );
+        let (loca, glyf, cmap) = (table(b"loca")?, table(b"glyf")?, table(b"cmap")?);
+
+        let units_per_em = read_u16(&data, head + 18)? as f64;
+        if units_per_em == 0.0 {
+            return Err(to_error("null units per em"));
+        }
+        let long_offsets = read_i16(&data, head + 50)? != 0;
+        let glyph_count = read_u16(&data, maxp + 4)? as usize;
+        let line_height = (read_i16(&data, hhea + 4)? as f64 - read_i16(&data, hhea + 6)? as f64
+            + read_i16(&data, hhea + 8)? as f64)
+            .max(units_per_em);
+        let metric_count = (read_u16(&data, hhea + 34)? as usize).max(1);
+        let advances = (0..metric_count)
+            .map(|index| read_u16(&data, hmtx + 4 * index))
+            .collect::<Result<Vec<_>>>()?;
+        let loca = (0..=glyph_count)
+            .map(|index| match long_offsets {
+                true => read_u32(&data, loca + 4 * index).map(|offset| offset as usize),
+                false => read_u16(&data, loca + 2 * index).map(|offset| 2 * offset as usize),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let character_map = Self::parse_character_map(&data, cmap)?;
+        Ok(Font {
+            data,
+            units_per_em,
+            line_height,
+            glyf,
+            loca,
+            advances,
+            character_map,
+        })
+    }
+
+    /// Unicode subtable of the character map, the full repertoire one if available
+    fn parse_character_map(data: &[u8], cmap: usize) -> Result<CharacterMap> {
+        let subtable_count = read_u16(data, cmap + 2)? as usize;
+        let mut best: Option<(u16, usize)> = None;
+        for index in 0..subtable_count {
+            let record = cmap + 4 + 8 * index;
+            let platform = read_u16(data, record)?;
+            let encoding = read_u16(data, record + 2)?;
+            let subtable = cmap + read_u32(data, record + 4)? as usize;
+            let format = read_u16(data, subtable)?;
+            let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+            if unicode && (format == 4 || format == 12) && best.is_none_or(|(f, _)| format > f) {
+                best = Some((format, subtable));
+            }
+        }
+        match best {
+            Some((4, subtable)) => {
+                let segment_count = read_u16(data, subtable + 6)? as usize / 2;
+                let ends = subtable + 14;
+                let starts = ends + 2 * segment_count + 2;
+                let deltas = starts + 2 * segment_count;
+                let range_offsets = deltas + 2 * segment_count;
+                let segments = (0..segment_count)
+                    .map(|index| {
+                        Ok(Segment {
+                            start: read_u16(data, starts + 2 * index)? as u32,
+                            end: read_u16(data, ends + 2 * index)? as u32,
+                            delta: read_u16(data, deltas + 2 * index)?,
+                            range_offset: read_u16(data, range_offsets + 2 * index)?,
+                            range_offset_position: range_offsets + 2 * index,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(CharacterMap::Segments(segments))
+            }
+            Some((_, subtable)) => {
+                let group_count = read_u32(data, subtable + 12)? as usize;
+                let groups = (0..group_count)
+                    .map(|index| {
+                        let group = subtable + 16 + 12 * index;
+                        Ok((
+                            read_u32(data, group)?,
+                            read_u32(data, group + 4)?,
+                            read_u32(data, group + 8)?,
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(CharacterMap::Groups(groups))
+            }
+            None => Err(to_error("no Unicode character map")),
+        }
+    }
+
+    /// Glyph of a character, the missing glyph 0 if the font does not have it
+    fn glyph_index(&self, character: char) -> Result<usize> {
+        let code = character as u32;
+        match &self.character_map {
+            CharacterMap::Segments(segments) => {
+                let segment = match segments.iter().find(|segment| segment.end >= code) {
+                    Some(segment) if segment.start <= code => segment,
+                    _ => return Ok(0),
+                };
+                if segment.range_offset == 0 {
+                    return Ok((code as u16).wrapping_add(segment.delta) as usize);
+                }
+                let position = segment.range_offset_position
+                    + segment.range_offset as usize
+                    + 2 * (code - segment.start) as usize;
+                match read_u16(&self.data, position)? {
+                    0 => Ok(0),
+                    glyph => Ok(glyph.wrapping_add(segment.delta) as usize),
+                }
+            }
+            CharacterMap::Groups(groups) => Ok(groups
+                .iter()
+                .find(|&&(start, end, _)| start <= code && code <= end)
+                .map_or(0, |&(start, _, glyph)| (glyph + code - start) as usize)),
+        }
+    }
+
+    fn advance(&self, glyph: usize) -> f64 {
+        let index = glyph.min(self.advances.len() - 1);
+        self.advances[index] as f64
+    }
+
+    /// Contours of a glyph in font units, with the transformation of a composite glyph
+    fn glyph_contours(
+        &self,
+        glyph: usize,
+        transform: [f64; 6],
+        depth: u32,
+        contours: &mut Vec<Contour>,
+    ) -> Result<()> {
+        let (start, end) = match (self.loca.get(glyph), self.loca.get(glyph + 1)) {
+            (Some(&start), Some(&end)) => (self.glyf + start, self.glyf + end),
+            _ => return Err(to_error(&format!("glyph {} is out of the font", glyph))),
+        };
+        // Glyphs such as spaces have no outline
+        if end <= start {
+            return Ok(());
+        }
+        let data = &self.data;
+        let contour_count = read_i16(data, start)?;
+        if contour_count < 0 {
+            return self.composite_contours(start + 10, transform, depth, contours);
+        }
+        let contour_count = contour_count as usize;
+        let ends = (0..contour_count)
+            .map(|index| read_u16(data, start + 10 + 2 * index).map(|end| end as usize))
+            .collect::<Result<Vec<_>>>()?;
+        let point_count = ends.last().map_or(0, |&end| end + 1);
+        let instructions = start + 10 + 2 * contour_count;
+        let mut position = instructions + 2 + read_u16(data, instructions)? as usize;
+
+        let mut flags = Vec::with_capacity(point_count);
+        while flags.len() < point_count {
+            let flag = *data
+                .get(position)
+                .ok_or_else(|| to_error("truncated glyph"))?;
+            position += 1;
+            let mut repeat = 1;
+            if flag & 8 != 0 {
+                repeat += *data
+                    .get(position)
+                    .ok_or_else(|| to_error("truncated glyph"))? as usize;
+                position += 1;
+            }
+            flags.extend(std::iter::repeat_n(flag, repeat));
+        }
+        flags.truncate(point_count);
+        // Coordinates are deltas, of one byte with a sign flag or two bytes
+        let mut read_coordinates = |short_bit: u8, same_bit: u8| -> Result<Vec<f64>> {
+            let mut value = 0i32;
+            let mut values = Vec::with_capacity(point_count);
+            for &flag in &flags {
+                if flag & short_bit != 0 {
+                    let delta = *data
+                        .get(position)
+                        .ok_or_else(|| to_error("truncated glyph"))?;
+                    position += 1;
+                    value += if flag & same_bit != 0 {
+                        delta as i32
+                    } else {
+                        -(delta as i32)
+                    };
+                } else if flag & same_bit == 0 {
+                    value += read_i16(data, position)? as i32;
+                    position += 2;
+                }
+                values.push(value as f64);
+            }
+            Ok(values)
+        };
+        let xs = read_coordinates(2, 16)?;
+        let ys = read_coordinates(4, 32)?;
+
+        let mut first = 0;
+        for &last in &ends {
+            if last < first || last >= point_count {
+                return Err(to_error("invalid contour end"));
+            }
+            let points = (first..=last)
+                .map(|index| {
+                    let (x, y) = (xs[index], ys[index]);
+                    let point = (
+                        transform[0] * x + transform[2] * y + transform[4],
+                        transform[1] * x + transform[3] * y + transform[5],
+                    );
+                    (point, flags[index] & 1 != 0)
+                })
+                .collect::<Vec<_>>();
+            contours.push(flatten_contour(&points));
+            first = last + 1;
+        }
+        Ok(())
+    }
+
+    fn composite_contours(
+        &self,
+        mut position: usize,
+        transform: [f64; 6],
+        depth: u32,
+        contours: &mut Vec<Contour>,
+    ) -> Result<()> {
+        if depth >= MAX_COMPONENT_DEPTH {
+            return Err(to_error("composite glyphs are nested too deeply"));
+        }
+        let data = &self.data;
+        loop {
+            let flags = read_u16(data, position)?;
+            let glyph = read_u16(data, position + 2)? as usize;
+            position += 4;
+            let (dx, dy) = if flags & 1 != 0 {
+                position += 4;
+                (
+                    read_i16(data, position - 4)? as f64,
+                    read_i16(data, position - 2)? as f64,
+                )
+            } else {
+                position += 2;
+                let byte = |position: usize| data.get(position).map(|&b| b as i8 as f64);
+                match (byte(position - 2), byte(position - 1)) {
+                    (Some(dx), Some(dy)) => (dx, dy),
+                    _ => return Err(to_error("truncated glyph")),
+                }
+            };
+            // Components aligned on matching points are placed without offset
+            let (dx, dy) = if flags & 2 != 0 { (dx, dy) } else { (0.0, 0.0) };
+            let [a, b, c, d] = if flags & 8 != 0 {
+                position += 2;
+                let scale = read_f2dot14(data, position - 2)?;
+                [scale, 0.0, 0.0, scale]
+            } else if flags & 0x40 != 0 {
+                position += 4;
+                [
+                    read_f2dot14(data, position - 4)?,
+                    0.0,
+                    0.0,
+                    read_f2dot14(data, position - 2)?,
+                ]
+            } else if flags & 0x80 != 0 {
+                position += 8;
+                [
+                    read_f2dot14(data, position - 8)?,
+                    read_f2dot14(data, position - 6)?,
+                    read_f2dot14(data, position - 4)?,
+                    read_f2dot14(data, position - 2)?,
+                ]
+            } else {
+                [1.0, 0.0, 0.0, 1.0]
+            };
+            // Component transformation, then the one of the glyph
+            let [ta, tb, tc, td, tx, ty] = transform;
+            let component = [
+                ta * a + tc * b,
+                tb * a + td * b,
+                ta * c + tc * d,
+                tb * c + td * d,
+                ta * dx + tc * dy + tx,
+                tb * dx + td * dy + ty,
+            ];
+            self.glyph_contours(glyph, component, depth + 1, contours)?;
+            if flags & 0x20 == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Contours of a text, one em high, starting at the origin on the baseline and going
+    /// towards +X, each new line going down
+    pub fn text_contours(&self, text: &str) -> Result<Vec<Contour>> {
+        let mut contours = Vec::new();
+        let scale = 1.0 / self.units_per_em;
+        for (line_index, line) in text.lines().enumerate() {
+            let mut pen = 0.0;
+            let baseline = -(line_index as f64) * self.line_height;
+            for character in line.chars() {
+                let glyph = self.glyph_index(character)?;
+                let transform = [scale, 0.0, 0.0, scale, pen * scale, baseline * scale];
+                self.glyph_contours(glyph, transform, 0, &mut contours)?;
+                pen += self.advance(glyph);
+            }
+        }
+        Ok(contours)
+    }
+}
+
+/// Polygon of a contour given by points on and off the curve, two consecutive off
+/// curve points having an implied on curve point between them
+fn flatten_contour(points: &[((f64, f64), bool)]) -> Contour {
+    let midpoint = |(a, b): (f64, f64), (c, d): (f64, f64)| ((a + c) / 2.0, (b + d) / 2.0);
+    let count = points.len();
+    let start = match points.iter().position(|&(_, on_curve)| on_curve) {
+        Some(start) => points[start].0,
+        None if count > 0 => midpoint(points[0].0, points[count - 1].0),
+        None => return Vec::new(),
+    };
+    let offset = points
+        .iter()
+        .position(|&(_, on_curve)| on_curve)
+        .unwrap_or(0);
+    let mut contour = vec![start];
+    let mut current = start;
+    let mut control: Option<(f64, f64)> = None;
+    for index in 1..=count {
+        let (point, on_curve) = points[(offset + index) % count];
+        match (on_curve, control) {
+            (true, None) => {
+                contour.push(point);
+                current = point;
+            }
+            (true, Some(control_point)) => {
+                push_curve(&mut contour, current, control_point, point);
+                current = point;
+                control = None;
+            }
+            (false, None) => control = Some(point),
+            (false, Some(control_point)) => {
+                let implied = midpoint(control_point, point);
+                push_curve(&mut contour, current, control_point, implied);
+                current = implied;
+                control = Some(point);
+            }
+        }
+    }
+    if let Some(control_point) = control {
+        push_curve(&mut contour, current, control_point, start);
+    }
+    // The contour is closed, its last point coming back on the first one
+    if contour.len() > 1 && contour.last() == contour.first() {
+        contour.pop();
+    }
+    contour
+}
+
+fn push_curve(contour: &mut Contour, from: (f64, f64), control: (f64, f64), to: (f64, f64)) {
+    for step in 1..=CURVE_STEPS {
+        let t = step as f64 / CURVE_STEPS as f64;
+        let (a, b, c) = ((1.0 - t) * (1.0 - t), 2.0 * t * (1.0 - t), t * t);
+        contour.push((
+            a * from.0 + b * control.0 + c * to.0,
+            a * from.1 + b * control.1 + c * to.1,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::f64_eq;
+
+    fn be16(value: i32) -> [u8; 2] {
+        (value as u16).to_be_bytes()
+    }
+
+    /// Font of 1000 units per em, with an empty glyph 0 and a 500 by 700 rectangle for 'A'
+    fn rectangle_font() -> Vec<u8> {
+        let mut head = vec![0; 54];
+        head[18..20].copy_from_slice(&be16(1000));
+        let mut maxp = vec![0; 6];
+        maxp[4..6].copy_from_slice(&be16(2));
+        let mut hhea = vec![0; 36];
+        hhea[4..6].copy_from_slice(&be16(800));
+        hhea[6..8].copy_from_slice(&be16(-200));
+        hhea[34..36].copy_from_slice(&be16(2));
+        let hmtx = [be16(500), be16(0), be16(600), be16(0)].concat();
+        let mut glyph = [
+            be16(1),
+            be16(0),
+            be16(0),
+            be16(500),
+            be16(700),
+            be16(3),
+            be16(0),
+        ]
+        .concat();
+        glyph.extend_from_slice(&[1, 1, 1, 1]);
+        for delta in &[0, 0, 500, 0, 0, 700, 0, -700] {
+            glyph.extend_from_slice(&be16(*delta));
+        }
+        let loca = [be16(0), be16(0), be16(glyph.len() as i32 / 2)].concat();
+        let mut cmap = [be16(0), be16(1), be16(3), be16(1), be16(0), be16(12)].concat();
+        let segments = [[65, 0xffff], [65, 0xffff], [1 - 65, 1], [0, 0]];
+        cmap.extend_from_slice(
+            &[be16(4), be16(32), be16(0), be16(4), [0; 2], [0; 2], [0; 2]].concat(),
+        );
+        for (index, values) in segments.iter().enumerate() {
+            if index == 1 {
+                cmap.extend_from_slice(&[0; 2]);
+            }
+            cmap.extend(values.iter().flat_map(|&value| be16(value)));
+        }
+        let tables: [(&[u8; 4], &[u8]); 7] = [
+            (b"cmap", &cmap),
+            (b"glyf", &glyph),
+            (b"head", &head),
+            (b"hhea", &hhea),
+            (b"hmtx", &hmtx),
+            (b"loca", &loca),
+            (b"maxp", &maxp),
+        ];
+        let mut font = [
+            0x0001_0000u32.to_be_bytes().to_vec(),
+            be16(7).to_vec(),
+            vec![0; 6],
+        ]
+        .concat();
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, data) in &tables {
+            font.extend_from_slice(&tag[..]);
+            font.extend_from_slice(&[0; 4]);
+            font.extend_from_slice(&(offset as u32).to_be_bytes());
+            font.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            offset += data.len();
+        }
+        for (_, data) in &tables {
+            font.extend_from_slice(data);
+        }
+        font
+    }
+
+    #[test]
+    fn text_is_laid_out_with_the_advances() {
+        let font = Font::parse(rectangle_font()).unwrap();
+        // Unknown characters use the empty glyph 0 and its advance
+        let contours = font.text_contours("AzA\nA").unwrap();
+        assert_eq!(contours.len(), 3);
+        let near = |(x, y): (f64, f64), (ex, ey): (f64, f64)| f64_eq(x, ex) && f64_eq(y, ey);
+        let rectangle = [(0.0, 0.0), (0.0, 0.7), (0.5, 0.7), (0.5, 0.0)];
+        assert!(contours[0]
+            .iter()
+            .zip(&rectangle)
+            .all(|(&p, &e)| near(p, e)));
+        assert!(near(contours[1][2], (0.6 + 0.5 + 0.5, 0.7)));
+        assert!(near(contours[2][0], (0.0, -1.0)));
+    }
+
+    #[test]
+    fn off_curve_points_give_quadratic_curves() {
+        let contour =
+            flatten_contour(&[((0.0, 0.0), true), ((1.0, 2.0), false), ((2.0, 0.0), true)]);
+        assert_eq!(contour.len(), CURVE_STEPS + 1);
+        assert_eq!(contour[CURVE_STEPS / 2], (1.0, 1.0));
+        // The implied point between two off curve points is on the curve
+        let contour =
+            flatten_contour(&[((0.0, 0.0), false), ((2.0, 0.0), false), ((2.0, 2.0), true)]);
+        assert!(contour.contains(&(1.0, 0.0)));
+    }
+
+    #[test]
+    fn not_a_font_is_error() {
+        assert!(Font::parse(b"OTTO\0\0\0\0".to_vec()).is_err());
+        assert!(Font::parse(vec![0; 3]).is_err());
+    }
+}
@@ -0,0 +1,529 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Reader of the subset of YAML used by scene descriptions: block and flow mappings and
+//! sequences of plain or quoted scalars, with comments. Anchors, aliases, tags, block
+//! scalars and documents after the first one are not supported.
+
+use crate::result::{RaytracerError, Result};
+use crate::validation::SceneError;
+use serde_json::{Map, Number, Value};
+
+/// Line with content, without its indentation nor comment
+struct Line {
+    /// Counted from 1
+    number: usize,
+    indent: usize,
+    text: String,
+}
+
+/// Generic value of a YAML text, as the one of a JSON text
+pub fn parse_yaml(text: &str) -> Result<Value> {
+    let mut lines = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let content = without_comment(line).trim_end();
+        let body = content.trim_start();
+        let indent = content.len() - body.len();
+        if body.is_empty() || body == "---" {
+            continue;
+        }
+        if body == "..." {
+            break;
+        }
+        if content[..indent].contains('\t') {
+            return Err(error(index + 1, 1, "tabulations cannot indent"));
+        }
+        lines.push(Line {
+            number: index + 1,
+            indent,
+            text: body.to_string(),
+        });
+    }
+    let mut parser = BlockParser { lines, position: 0 };
+    let indent = match parser.lines.first() {
+        Some(line) => line.indent,
+        None => return Ok(Value::Null),
+    };
+    let value = parser.block(indent)?;
+    match parser.lines.get(parser.position) {
+        Some(line) => Err(error(line.number, line.indent + 1, "unexpected content")),
+        None => Ok(value),
+    }
+}
+
+fn error(line: usize, column: usize, message: &str) -> RaytracerError {
+    RaytracerError::InvalidScene(SceneError {
+        message: message.to_string(),
+        key: None,
+        position: Some((line, column)),
+    })
+}
+
+/// Line without the comment starting at a `#` outside of quotes, after a space
+fn without_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '#') if previous.is_whitespace() => return &line[..index],
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some('"'), '\\') if previous == '\\' => {
+                previous = ' ';
+                continue;
+            }
+            (Some('"'), '"') if previous != '\\' => quote = None,
+            (Some('\''), '\'') => quote = None,
+            _ => {}
+        }
+        previous = c;
+    }
+    line
+}
+
+fn is_sequence_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// Characters of a text outside of quotes, with the depth of flow collections they are in
+fn unquoted_chars(text: &str) -> impl Iterator<Item = (usize, char, i32)> + '_ {
+    let mut quote = None;
+    let mut previous = ' ';
+    let mut depth = 0;
+    text.char_indices().filter_map(move |(index, c)| {
+        let unquoted = match (quote, c) {
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                false
+            }
+            (None, _) => true,
+            (Some('"'), '"') if previous != '\\' => {
+                quote = None;
+                false
+            }
+            (Some('\''), '\'') => {
+                quote = None;
+                false
+            }
+            _ => false,
+        };
+        previous = if previous == '\\' && c == '\\' {
+            ' '
+        } else {
+            c
+        };
+        if !unquoted {
+            return None;
+        }
+        match c {
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth -= 1,
+            _ => {}
+        }
+        Some((index, c, depth))
+    })
+}
+
+/// Key and rest of a `key: value` line
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    let (index, _, _) = unquoted_chars(text).find(|&(index, c, depth)| {
+        c == ':'
+            && depth == 0
+            && text[index + 1..]
+                .chars()
+                .next()
+                .is_none_or(char::is_whitespace)
+    })?;
+    Some((text[..index].trim_end(), text[index + 1..].trim_start()))
+}
+
+/// Whether every flow collection opened in the text is closed
+fn is_balanced(text: &str) -> bool {
+    unquoted_chars(text)
+        .last()
+        .is_none_or(|(_, _, depth)| depth <= 0)
+}
+
+struct BlockParser {
+    lines: Vec<Line>,
+    position: usize,
+}
+
+impl BlockParser {
+    /// Value whose lines start at the indentation
+    fn block(&mut self, indent: usize) -> Result<Value> {
+        let line = &self.lines[self.position];
+        if is_sequence_item(&line.text) {
+            self.sequence(indent)
+        } else if split_key(&line.text).is_some() {
+            self.mapping(indent)
+        } else {
+            let (text, number, column) = (line.text.clone(), line.number, line.indent + 1);
+            self.position += 1;
+            self.flow_value(text, number, column)
+        }
+    }
+
+    fn sequence(&mut self, indent: usize) -> Result<Value> {
+        let mut items = Vec::new();
+        while let Some(line) = self.lines.get_mut(self.position) {
+            if line.indent < indent || !is_sequence_item(&line.text) {
+                break;
+            }
+            if line.indent > indent {
+                return Err(error(
+                    line.number,
+                    line.indent + 1,
+                    "unexpected indentation",
+                ));
+            }
+            let rest = line.text[1..].trim_start().to_string();
+            if rest.is_empty() {
+                self.position += 1;
+                items.push(self.nested_block(indent)?);
+            } else {
+                // What follows the dash is a block of its own, indented where it starts
+                line.indent += line.text.len() - rest.len();
+                line.text = rest;
+                let item_indent = line.indent;
+                items.push(self.block(item_indent)?);
+            }
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn mapping(&mut self, indent: usize) -> Result<Value> {
+        let mut map = Map::new();
+        while let Some(line) = self.lines.get(self.position) {
+            if line.indent < indent {
+                break;
+            }
+            let (number, column) = (line.number, line.indent + 1);
+            if line.indent > indent {
+                return Err(error(number, column, "unexpected indentation"));
+            }
+            let (key, rest) = split_key(&line.text)
+                .ok_or_else(|| error(number, column, "expected a `key: value` pair"))?;
+            let key = match FlowParser::new(key, number, column).value(true)? {
+                Value::String(key) => key,
+                key => key.to_string(),
+            };
+            let rest = rest.to_string();
+            let rest_column = column + line.text.len() - rest.len();
+            self.position += 1;
+            let value = if rest.is_empty() {
+                match self.lines.get(self.position) {
+                    // Items of a sequence may be at the indentation of its key
+                    Some(next) if next.indent == indent && is_sequence_item(&next.text) => {
+                        self.sequence(indent)?
+                    }
+                    _ => self.nested_block(indent)?,
+                }
+            } else {
+                self.flow_value(rest, number, rest_column)?
+            };
+            if map.insert(key.clone(), value).is_some() {
+                return Err(error(number, column, &format!("duplicate key `{}`", key)));
+            }
+        }
+        Ok(Value::Object(map))
+    }
+
+    /// Block more indented than the given indentation, null if there is none
+    fn nested_block(&mut self, indent: usize) -> Result<Value> {
+        match self.lines.get(self.position) {
+            Some(next) if next.indent > indent => {
+                let nested_indent = next.indent;
+                self.block(nested_indent)
+            }
+            _ => Ok(Value::Null),
+        }
+    }
+
+    /// Value given on a line, taking the following ones until its collections are closed
+    fn flow_value(&mut self, text: String, number: usize, column: usize) -> Result<Value> {
+        if text.starts_with(['|', '>', '&', '*', '!']) {
+            return Err(error(
+                number,
+                column,
+                "block scalars, anchors, aliases and tags are not supported",
+            ));
+        }
+        let mut text = text;
+        while !is_balanced(&text) && self.position < self.lines.len() {
+            text.push(' ');
+            text.push_str(&self.lines[self.position].text);
+            self.position += 1;
+        }
+        let mut parser = FlowParser::new(&text, number, column);
+        let value = parser.value(false)?;
+        parser.skip_spaces();
+        match parser.peek() {
+            Some(_) => Err(parser.error("unexpected characters after the value")),
+            None => Ok(value),
+        }
+    }
+}
+
+/// Reader of a scalar or of a collection written between brackets or braces
+struct FlowParser {
+    chars: Vec<char>,
+    index: usize,
+    number: usize,
+    column: usize,
+}
+
+impl FlowParser {
+    fn new(text: &str, number: usize, column: usize) -> Self {
+        FlowParser {
+            chars: text.chars().collect(),
+            index: 0,
+            number,
+            column,
+        }
+    }
+
+    fn error(&self, message: &str) -> RaytracerError {
+        error(self.number, self.column + self.index, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_spaces();
+        let found = self.peek() == Some(expected);
+        if found {
+            self.index += 1;
+        }
+        found
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.index += 1;
+        }
+    }
+
+    /// Value, which ends before a comma or a closing bracket inside a collection
+    fn value(&mut self, in_collection: bool) -> Result<Value> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('[') => {
+                self.index += 1;
+                let mut items = Vec::new();
+                if !self.eat(']') {
+                    loop {
+                        items.push(self.value(true)?);
+                        if self.eat(']') {
+                            break;
+                        }
+                        if !self.eat(',') {
+                            return Err(self.error("expected `,` or `]`"));
+                        }
+                        if self.eat(']') {
+                            break;
+                        }
+                    }
+                }
+                Ok(Value::Array(items))
+            }
+            Some('{') => {
+                self.index += 1;
+                let mut map = Map::new();
+                if !self.eat('}') {
+                    loop {
+                        let key = match self.value(true)? {
+                            Value::String(key) => key,
+                            key => key.to_string(),
+                        };
+                        if !self.eat(':') {
+                            return Err(self.error("expected `:`"));
+                        }
+                        map.insert(key, self.value(true)?);
+                        if self.eat('}') {
+                            break;
+                        }
+                        if !self.eat(',') {
+                            return Err(self.error("expected `,` or `}`"));
+                        }
+                        if self.eat('}') {
+                            break;
+                        }
+                    }
+                }
+                Ok(Value::Object(map))
+            }
+            Some('"') => self.double_quoted().map(Value::String),
+            Some('\'') => self.single_quoted().map(Value::String),
+            _ => Ok(plain_scalar(&self.plain(in_collection))),
+        }
+    }
+
+    fn plain(&mut self, in_collection: bool) -> String {
+        let start = self.index;
+        while let Some(c) = self.peek() {
+            let ends_collection_value = in_collection
+                && (matches!(c, ',' | ']' | '}')
+                    || c == ':'
+                        && self
+                            .chars
+                            .get(self.index + 1)
+                            .is_none_or(|next| next.is_whitespace() || *next == ','));
+            if ends_collection_value {
+                break;
+            }
+            self.index += 1;
+        }
+        self.chars[start..self.index]
+            .iter()
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+
+    fn double_quoted(&mut self) -> Result<String> {
+        self.index += 1;
+        let mut text = String::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.index += 1;
+            match c {
+                '"' => return Ok(text),
+                '\\' => {
+                    let escaped = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.index += 1;
+                    text.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        '0' => '\0',
+                        'u' => {
+                            let digits = self.chars.get(self.index..self.index + 4);
+                            let code = digits
+                                .map(|digits| digits.iter().collect::<String>())
+                                .and_then(|digits| u32::from_str_radix(&digits, 16).ok())
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("invalid unicode escape"))?;
+                            self.index += 4;
+                            code
+                        }
+                        '"' | '\\' | '/' | ' ' => escaped,
+                        _ => return Err(self.error("unknown escape sequence")),
+                    });
+                }
+                _ => text.push(c),
+            }
+        }
+    }
+
+    fn single_quoted(&mut self) -> Result<String> {
+        self.index += 1;
+        let mut text = String::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.index += 1;
+            if c != '\'' {
+                text.push(c);
+            } else if self.peek() == Some('\'') {
+                // Quote written twice
+                self.index += 1;
+                text.push('\'');
+            } else {
+                return Ok(text);
+            }
+        }
+    }
+}
+
+/// Null, boolean, number or string written without quotes
+fn plain_scalar(text: &str) -> Value {
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => return Value::Null,
+        "true" | "True" | "TRUE" => return Value::Bool(true),
+        "false" | "False" | "FALSE" => return Value::Bool(false),
+        _ => {}
+    }
+    if let Ok(integer) = text.parse::<i64>() {
+        return Value::from(integer);
+    }
+    let is_number = text.chars().any(|c| c.is_ascii_digit())
+        && text
+            .chars()
+            .all(|c| c.is_ascii_digit() || "+-.eE".contains(c));
+    match text.parse::<f64>().ok().and_then(Number::from_f64) {
+        Some(number) if is_number => Value::Number(number),
+        _ => Value::String(text.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn block_and_flow_collections() {
+        let text = "# Scene\n\
+                    camera:\n  type: perspective\n  screen_center: [0, 1.5, -10]  # in front\n\
+                    object:\n- type: sphere\n  radius: 1e-1\n  texture: {type: 'plain', \
+                    color: \"#ff0000\"}\n-\n  type: cube\n  tags:\n    - a: 1\n      b: \
+                    [x, \"y, z\"]\n    - yes\nempty:\n";
+        let expected = json!({
+            "camera": {"type": "perspective", "screen_center": [0, 1.5, -10]},
+            "object": [
+                {"type": "sphere", "radius": 0.1,
+                 "texture": {"type": "plain", "color": "#ff0000"}},
+                {"type": "cube", "tags": [{"a": 1, "b": ["x", "y, z"]}, "yes"]},
+            ],
+            "empty": null,
+        });
+        assert_eq!(parse_yaml(text).unwrap(), expected);
+    }
+
+    #[test]
+    fn flow_collections_over_lines() {
+        let text = "points: [\n  [0, 0],\n  [1, 'it''s'],\n]\nnext: true\n";
+        let expected = json!({"points": [[0, 0], [1, "it's"]], "next": true});
+        assert_eq!(parse_yaml(text).unwrap(), expected);
+    }
+
+    #[test]
+    fn errors_are_located() {
+        let position = |text: &str| match parse_yaml(text) {
+            Err(RaytracerError::InvalidScene(error)) => error.position,
+            _ => None,
+        };
+        assert_eq!(position("a: 1\n   b: 2\n"), Some((2, 4)));
+        assert_eq!(position("a: 1\na: 2\n"), Some((2, 1)));
+        assert_eq!(position("a: [1, 2\n"), Some((1, 9)));
+        assert_eq!(position("a: |\n  text\n"), Some((1, 4)));
+    }
+}
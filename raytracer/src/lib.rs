@@ -22,16 +22,40 @@ OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE.
 */
 
+pub mod accumulation;
+pub mod animation;
+pub mod atmosphere;
+pub mod builder;
+pub mod bvh;
 pub mod cameras;
+pub mod checkpoint;
 pub mod colors;
+pub mod debug;
+pub mod depth_map;
+pub mod environment;
+pub mod extrusion;
+pub mod interpolation;
+pub mod io;
 pub mod lights;
+pub mod matrix;
+pub mod memory;
+pub mod panorama;
 pub(crate) mod parser;
 pub mod primitives;
+pub mod progress;
 pub mod ray_algorithm;
 pub mod renderer;
 pub mod result;
+pub mod sampling;
 pub mod scene;
+pub mod service;
+pub mod stats;
+pub mod terrain;
+pub mod texture_cache;
 pub mod textures;
+pub mod thumbnail;
+pub mod tiles;
+pub mod validation;
 pub mod vector;
 
 #[cfg(target_arch = "wasm32")]
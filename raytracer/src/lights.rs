@@ -24,16 +24,42 @@ SOFTWARE.
 
 use crate::colors::Color;
 use crate::primitives::Ray;
-use crate::vector::Vec3;
-use serde::Deserialize;
+use crate::utils::value_noise;
+use crate::vector::{Mat3, Vec3};
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
 
 pub trait AnyLightObject: Send + Sync {
     fn source(&self) -> Vec3;
     fn color_for_ray(&self, ray: Ray) -> Color;
+
+    /// Points of the light shadow rays are sent to, each one bringing an equal part
+    /// of the light; only the source for lights without area
+    fn sample_sources(&self, _count: u32, _rng: &mut dyn RngCore) -> Vec<Vec3> {
+        vec![self.source()]
+    }
+
+    /// Rays going from a lit point to the light samples, with the distance to the light
+    fn shadow_rays(
+        &self,
+        surface_point: Vec3,
+        count: u32,
+        rng: &mut dyn RngCore,
+    ) -> Vec<(Ray, f64)> {
+        self.sample_sources(count, rng)
+            .into_iter()
+            .map(|source| {
+                (
+                    Ray::ray_from_to(surface_point, source),
+                    surface_point.distance(source),
+                )
+            })
+            .collect()
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct LightPoint {
     source: Vec3,
     color: Color,
@@ -62,11 +88,49 @@ impl AnyLightObject for LightPoint {
     }
 }
 
+/// Distance at which lights at infinity are said to be, when a position is needed
+pub const LIGHT_AT_INFINITY_DISTANCE: f64 = 1e9;
+
+/// Light at infinity, such as the sun, with parallel rays going in the same direction
+#[derive(Debug)]
+pub struct LightDirectional {
+    direction: Vec3,
+    color: Color,
+}
+
+impl LightDirectional {
+    pub fn new(direction: Vec3, color: Color) -> Self {
+        LightDirectional {
+            direction: direction.normalize(),
+            color,
+        }
+    }
+}
+
+impl AnyLightObject for LightDirectional {
+    fn source(&self) -> Vec3 {
+        -LIGHT_AT_INFINITY_DISTANCE * self.direction
+    }
+
+    fn color_for_ray(&self, _ray: Ray) -> Color {
+        self.color.clone()
+    }
+
+    fn shadow_rays(
+        &self,
+        surface_point: Vec3,
+        _count: u32,
+        _rng: &mut dyn RngCore,
+    ) -> Vec<(Ray, f64)> {
+        vec![(Ray::new(surface_point, -self.direction), f64::INFINITY)]
+    }
+}
+
 pub struct AmbientLight {
     pub power: f64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct SpotLight {
     source: Vec3,
     color: Color,
@@ -105,6 +169,117 @@ impl SpotLight {
     }
 }
 
+/// Jittered positions in the unit square, one per cell of a grid, to get a less noisy
+/// penumbra than with fully random positions
+fn stratified_unit_square(count: u32, rng: &mut dyn RngCore) -> Vec<(f64, f64)> {
+    let count = count.max(1);
+    let columns = (count as f64).sqrt().ceil() as u32;
+    let rows = count.div_ceil(columns);
+    (0..count)
+        .map(|i| {
+            let (column, row) = (i % columns, i / columns);
+            (
+                (column as f64 + rng.gen::<f64>()) / columns as f64,
+                (row as f64 + rng.gen::<f64>()) / rows as f64,
+            )
+        })
+        .collect()
+}
+
+/// Axes of the plane orthogonal to the normal
+pub(crate) fn plane_axes(normal: Vec3) -> (Vec3, Vec3) {
+    let transform = Mat3::transformation_between(Vec3::new(0.0, 1.0, 0.0), normal.normalize());
+    (
+        transform * Vec3::new(1.0, 0.0, 0.0),
+        transform * Vec3::new(0.0, 0.0, 1.0),
+    )
+}
+
+/// Rectangle emitting light on both sides, giving soft shadows
+#[derive(Debug)]
+pub struct LightRectangle {
+    center: Vec3,
+    width_axis: Vec3,
+    height_axis: Vec3,
+    color: Color,
+}
+
+impl LightRectangle {
+    pub fn new(center: Vec3, normal: Vec3, width: f64, height: f64, color: Color) -> Self {
+        let (u_axis, v_axis) = plane_axes(normal);
+        LightRectangle {
+            center,
+            width_axis: width * u_axis,
+            height_axis: height * v_axis,
+            color,
+        }
+    }
+}
+
+impl AnyLightObject for LightRectangle {
+    fn source(&self) -> Vec3 {
+        self.center
+    }
+
+    fn color_for_ray(&self, _ray: Ray) -> Color {
+        self.color.clone()
+    }
+
+    fn sample_sources(&self, count: u32, rng: &mut dyn RngCore) -> Vec<Vec3> {
+        stratified_unit_square(count, rng)
+            .into_iter()
+            .map(|(u, v)| self.center + (u - 0.5) * self.width_axis + (v - 0.5) * self.height_axis)
+            .collect()
+    }
+}
+
+/// Disk emitting light on both sides, giving soft shadows
+#[derive(Debug)]
+pub struct LightDisk {
+    center: Vec3,
+    u_axis: Vec3,
+    v_axis: Vec3,
+    radius: f64,
+    color: Color,
+}
+
+impl LightDisk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, color: Color) -> Self {
+        let (u_axis, v_axis) = plane_axes(normal);
+        LightDisk {
+            center,
+            u_axis,
+            v_axis,
+            radius,
+            color,
+        }
+    }
+}
+
+impl AnyLightObject for LightDisk {
+    fn source(&self) -> Vec3 {
+        self.center
+    }
+
+    fn color_for_ray(&self, _ray: Ray) -> Color {
+        self.color.clone()
+    }
+
+    fn sample_sources(&self, count: u32, rng: &mut dyn RngCore) -> Vec<Vec3> {
+        // Square root of the distance, so the points are uniform on the disk surface
+        stratified_unit_square(count, rng)
+            .into_iter()
+            .map(|(u, v)| {
+                let distance = self.radius * u.sqrt();
+                let angle = 2.0 * PI * v;
+                self.center
+                    + distance * angle.cos() * self.u_axis
+                    + distance * angle.sin() * self.v_axis
+            })
+            .collect()
+    }
+}
+
 impl AnyLightObject for SpotLight {
     fn source(&self) -> Vec3 {
         self.source
@@ -122,3 +297,173 @@ impl AnyLightObject for SpotLight {
         }
     }
 }
+
+/// Flame like variations of a light, its intensity dipping randomly over time
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+#[serde(deny_unknown_fields)]
+pub struct LightFlicker {
+    /// Largest part of the intensity lost during a dip
+    pub amplitude: f64,
+    /// Dips per second
+    pub speed: f64,
+    /// Lights with different seeds flicker independently
+    pub seed: u64,
+    /// How much a dip shifts the light towards red, like a cooling flame
+    pub warmth: f64,
+}
+
+impl Default for LightFlicker {
+    fn default() -> Self {
+        LightFlicker {
+            amplitude: 0.3,
+            speed: 8.0,
+            seed: 0,
+            warmth: 0.5,
+        }
+    }
+}
+
+impl LightFlicker {
+    /// Factor applied to the light color at `time`, in seconds
+    pub fn tint_at(&self, time: f64) -> Color {
+        let noise = value_noise(Vec3::new(time * self.speed, self.seed as f64, 0.0));
+        let dip = self.amplitude * noise;
+        let intensity = 1.0 - dip;
+        Color::new(
+            intensity,
+            intensity * (1.0 - self.warmth * dip / 2.0),
+            intensity * (1.0 - self.warmth * dip),
+        )
+    }
+}
+
+/// Light whose color is multiplied by a tint, as given by an animation at some frame
+pub struct TintedLight {
+    light: Box<dyn AnyLightObject>,
+    tint: Color,
+}
+
+impl TintedLight {
+    pub fn new(light: Box<dyn AnyLightObject>, tint: Color) -> Self {
+        TintedLight { light, tint }
+    }
+}
+
+impl AnyLightObject for TintedLight {
+    fn source(&self) -> Vec3 {
+        self.light.source()
+    }
+
+    fn color_for_ray(&self, ray: Ray) -> Color {
+        self.light.color_for_ray(ray) * self.tint.clone()
+    }
+
+    fn sample_sources(&self, count: u32, rng: &mut dyn RngCore) -> Vec<Vec3> {
+        self.light.sample_sources(count, rng)
+    }
+
+    fn shadow_rays(
+        &self,
+        surface_point: Vec3,
+        count: u32,
+        rng: &mut dyn RngCore,
+    ) -> Vec<(Ray, f64)> {
+        self.light.shadow_rays(surface_point, count, rng)
+    }
+}
+
+/// Intensity at `time` of keys given as (time, intensity) pairs sorted by time, linearly
+/// interpolated between them and held before the first and after the last one
+pub fn keyframed_intensity(keys: &[(f64, f64)], time: f64) -> f64 {
+    match keys.iter().position(|&(key_time, _)| key_time > time) {
+        None => keys.last().map_or(1.0, |&(_, intensity)| intensity),
+        Some(0) => keys[0].1,
+        Some(index) => {
+            let (start_time, start) = keys[index - 1];
+            let (end_time, end) = keys[index];
+            start + (end - start) * (time - start_time) / (end_time - start_time)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn rectangle_samples_are_on_the_rectangle() {
+        let light = LightRectangle::new(
+            Vec3::new(0.0, 10.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            4.0,
+            2.0,
+            Color::WHITE,
+        );
+        let samples = light.sample_sources(10, &mut StdRng::seed_from_u64(0));
+        assert_eq!(samples.len(), 10);
+        for sample in samples {
+            assert!((sample.y - 10.0).abs() < 1e-9);
+            assert!(sample.x.abs() <= 2.0 + 1e-9);
+            assert!(sample.z.abs() <= 1.0 + 1e-9);
+        }
+    }
+
+    #[test]
+    fn disk_samples_are_on_the_disk() {
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        let light = LightDisk::new(center, Vec3::new(1.0, 0.0, 0.0), 3.0, Color::WHITE);
+        for sample in light.sample_sources(16, &mut StdRng::seed_from_u64(0)) {
+            assert!((sample.x - center.x).abs() < 1e-9);
+            assert!(sample.distance(center) <= 3.0 + 1e-9);
+        }
+    }
+
+    #[test]
+    fn directional_light_is_at_infinity() {
+        let light = LightDirectional::new(Vec3::new(0.0, -2.0, 0.0), Color::WHITE);
+        let mut rng = StdRng::seed_from_u64(0);
+        let shadow_rays = light.shadow_rays(Vec3::new(5.0, 0.0, 5.0), 16, &mut rng);
+        assert_eq!(shadow_rays.len(), 1);
+        let (ray, distance) = &shadow_rays[0];
+        assert_eq!(ray.direction, Vec3::new(0.0, 1.0, 0.0));
+        assert!(distance.is_infinite());
+    }
+
+    #[test]
+    fn point_light_has_a_single_sample() {
+        let light = LightPoint::new(Vec3::new(1.0, 2.0, 3.0));
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(
+            light.sample_sources(16, &mut rng),
+            vec![Vec3::new(1.0, 2.0, 3.0)]
+        );
+    }
+
+    #[test]
+    fn flicker_dims_and_warms_the_light() {
+        let flicker = LightFlicker::default();
+        let tints: Vec<_> = (0..24)
+            .map(|frame| flicker.tint_at(frame as f64 / 24.0))
+            .collect();
+        for tint in &tints {
+            assert!(tint.red() >= 1.0 - flicker.amplitude && tint.red() <= 1.0);
+            assert!(tint.blue() <= tint.green() && tint.green() <= tint.red());
+        }
+        assert!(tints
+            .iter()
+            .any(|tint| (tint.red() - tints[0].red()).abs() > 1e-3));
+        assert_eq!(flicker.tint_at(0.5).blue(), flicker.tint_at(0.5).blue());
+    }
+
+    #[test]
+    fn keyframes_are_interpolated() {
+        let keys = [(1.0, 0.0), (3.0, 1.0)];
+        assert_eq!(keyframed_intensity(&keys, 0.0), 0.0);
+        assert_eq!(keyframed_intensity(&keys, 2.0), 0.5);
+        assert_eq!(keyframed_intensity(&keys, 5.0), 1.0);
+        assert_eq!(keyframed_intensity(&[], 5.0), 1.0);
+    }
+}
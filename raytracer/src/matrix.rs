@@ -0,0 +1,338 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! 4x4 matrices of affine transformations, applied to points, vectors and normals
+//! in homogeneous coordinates.
+
+use crate::utils::f64_eq;
+use crate::vector::{Mat3, Vec3};
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Mat4([[f64; 4]; 4]);
+
+impl Mat4 {
+    #[rustfmt::skip]
+    pub const ID: Mat4 =
+        Mat4([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ]);
+
+    pub const ZERO: Mat4 = Mat4([[0.0; 4]; 4]);
+
+    pub const fn new() -> Self {
+        Self::ZERO
+    }
+
+    pub const fn from_rows(rows: [[f64; 4]; 4]) -> Self {
+        Mat4(rows)
+    }
+
+    pub fn rows(&self) -> [[f64; 4]; 4] {
+        self.0
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut result = Self::ID;
+        result.0[0][3] = offset.x;
+        result.0[1][3] = offset.y;
+        result.0[2][3] = offset.z;
+        result
+    }
+
+    pub fn scaling(factors: Vec3) -> Self {
+        let mut result = Self::ID;
+        result.0[0][0] = factors.x;
+        result.0[1][1] = factors.y;
+        result.0[2][2] = factors.z;
+        result
+    }
+
+    /// Matrix with the linear part given by `linear`, without translation
+    pub fn from_mat3(linear: Mat3) -> Self {
+        let columns = [
+            linear * Vec3::new(1.0, 0.0, 0.0),
+            linear * Vec3::new(0.0, 1.0, 0.0),
+            linear * Vec3::new(0.0, 0.0, 1.0),
+        ];
+        let mut result = Self::ID;
+        for (j, column) in columns.iter().enumerate() {
+            result.0[0][j] = column.x;
+            result.0[1][j] = column.y;
+            result.0[2][j] = column.z;
+        }
+        result
+    }
+
+    /// Rotation around the X, then Y, then Z axis, with angles in radians
+    pub fn rotation(angles: Vec3) -> Self {
+        Self::from_mat3(Mat3::rotation(angles))
+    }
+
+    /// Scale, then rotation, then translation, the usual order of object transformations
+    pub fn from_trs(translation: Vec3, rotation: Vec3, scale: Vec3) -> Self {
+        Self::translation(translation) * Self::rotation(rotation) * Self::scaling(scale)
+    }
+
+    /// Camera to world transformation of a camera at `eye` looking at `look_at`, the
+    /// camera looking along its Z axis with its Y axis as close to `up` as possible
+    pub fn look_at(eye: Vec3, look_at: Vec3, up: Vec3) -> Self {
+        let forward = Vec3::between_points(eye, look_at).normalize();
+        let right = up.cross_product(forward).normalize();
+        let up = forward.cross_product(right);
+        let mut result = Self::translation(eye);
+        for (j, axis) in [right, up, forward].iter().enumerate() {
+            result.0[0][j] = axis.x;
+            result.0[1][j] = axis.y;
+            result.0[2][j] = axis.z;
+        }
+        result
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut result = Mat4::new();
+        for (i, row) in self.0.iter().enumerate() {
+            for (j, &value) in row.iter().enumerate() {
+                result.0[j][i] = value;
+            }
+        }
+        result
+    }
+
+    /// Inverse computed with a Gauss-Jordan elimination, none if the matrix is singular or
+    /// has a value which is not finite
+    pub fn inverse(&self) -> Option<Self> {
+        if !self.0.iter().flatten().all(|value| value.is_finite()) {
+            return None;
+        }
+        let mut mat = self.0;
+        let mut result = Self::ID.0;
+        // Rows are brought to a largest value of 1, so the pivots are compared to the
+        // magnitude of their row and tiny but regular scales are not seen as singular
+        for (row, result_row) in mat.iter_mut().zip(result.iter_mut()) {
+            let largest = row
+                .iter()
+                .fold(0.0_f64, |largest, value| largest.max(value.abs()));
+            if largest == 0.0 {
+                return None;
+            }
+            for (value, result_value) in row.iter_mut().zip(result_row.iter_mut()) {
+                *value /= largest;
+                *result_value /= largest;
+            }
+        }
+        for column in 0..4 {
+            // The largest pivot limits rounding errors
+            let pivot_row = (column..4)
+                .max_by(|&a, &b| mat[a][column].abs().total_cmp(&mat[b][column].abs()))
+                .unwrap();
+            let pivot = mat[pivot_row][column];
+            if f64_eq(pivot, 0.0) {
+                return None;
+            }
+            mat.swap(column, pivot_row);
+            result.swap(column, pivot_row);
+            for j in 0..4 {
+                mat[column][j] /= pivot;
+                result[column][j] /= pivot;
+            }
+            for row in (0..4).filter(|&row| row != column) {
+                let factor = mat[row][column];
+                for j in 0..4 {
+                    mat[row][j] -= factor * mat[column][j];
+                    result[row][j] -= factor * result[column][j];
+                }
+            }
+        }
+        Some(Mat4(result))
+    }
+
+    /// Point with a homogeneous coordinate of 1, moved by the translation
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        let mat = self.0;
+        let transformed = self.transform_vector(point) + Vec3::new(mat[0][3], mat[1][3], mat[2][3]);
+        let w = mat[3][0] * point.x + mat[3][1] * point.y + mat[3][2] * point.z + mat[3][3];
+        if f64_eq(w, 1.0) || f64_eq(w, 0.0) {
+            transformed
+        } else {
+            (1.0 / w) * transformed
+        }
+    }
+
+    /// Direction with a homogeneous coordinate of 0, not moved by the translation
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        let mat = self.0;
+        Vec3::new(
+            vector.x * mat[0][0] + vector.y * mat[0][1] + vector.z * mat[0][2],
+            vector.x * mat[1][0] + vector.y * mat[1][1] + vector.z * mat[1][2],
+            vector.x * mat[2][0] + vector.y * mat[2][1] + vector.z * mat[2][2],
+        )
+    }
+
+    /// Normal of a surface whose points are transformed by the inverse of this matrix;
+    /// normals are transformed by the inverse transpose to stay perpendicular to
+    /// surfaces which are not scaled the same along each axis. The result is normalized.
+    pub fn transform_normal(&self, normal: Vec3) -> Vec3 {
+        self.transpose().transform_vector(normal).normalize()
+    }
+}
+
+impl std::cmp::PartialEq for Mat4 {
+    fn eq(&self, other: &Self) -> bool {
+        self.0
+            .iter()
+            .flatten()
+            .zip(other.0.iter().flatten())
+            .all(|(&left, &right)| f64_eq(left, right))
+    }
+}
+
+impl std::ops::Mul<Mat4> for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Self::Output {
+        let mut result = Mat4::new();
+        for i in 0..4 {
+            for j in 0..4 {
+                result.0[i][j] = (0..4).map(|k| self.0[i][k] * rhs.0[k][j]).sum();
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+
+    fn assert_vec_eq(left: Vec3, right: Vec3) {
+        assert!(left.distance(right) < 1e-9, "{:?} != {:?}", left, right);
+    }
+
+    fn assert_mat_eq(left: Mat4, right: Mat4) {
+        let close = left
+            .rows()
+            .iter()
+            .flatten()
+            .zip(right.rows().iter().flatten())
+            .all(|(a, b)| (a - b).abs() < 1e-9);
+        assert!(close, "{:?} != {:?}", left, right);
+    }
+
+    #[test]
+    fn identity_is_neutral_for_multiplication() {
+        let mat = Mat4::from_trs(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(0.3, 0.2, 0.1),
+            Vec3::new(2.0, 1.0, 0.5),
+        );
+        assert_eq!(Mat4::ID * mat, mat);
+        assert_eq!(mat * Mat4::ID, mat);
+    }
+
+    #[test]
+    fn transpose_swaps_rows_and_columns() {
+        let mat = Mat4::translation(Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(mat.transpose().rows()[3], [1.0, 2.0, 3.0, 1.0]);
+        assert_eq!(mat.transpose().transpose(), mat);
+    }
+
+    #[test]
+    fn inverse_undoes_the_transformation() {
+        let mat = Mat4::from_trs(
+            Vec3::new(-4.0, 2.0, 7.0),
+            Vec3::new(0.5, -1.0, 2.0),
+            Vec3::new(3.0, 0.5, 2.0),
+        );
+        let inverse = mat.inverse().unwrap();
+        assert_mat_eq(mat * inverse, Mat4::ID);
+        assert_mat_eq(inverse * mat, Mat4::ID);
+        let point = Vec3::new(1.0, -2.0, 3.0);
+        assert_vec_eq(inverse.transform_point(mat.transform_point(point)), point);
+    }
+
+    #[test]
+    fn singular_matrix_has_no_inverse() {
+        assert!(Mat4::scaling(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+        assert!(Mat4::ZERO.inverse().is_none());
+    }
+
+    #[test]
+    fn tiny_scales_are_inverted() {
+        let mat = Mat4::scaling(Vec3::new(1e-17, 1.0, 1e20));
+        let inverse = mat.inverse().unwrap();
+        assert_vec_eq(
+            inverse.transform_vector(Vec3::new(1e-17, 1.0, 1e20)),
+            Vec3::new(1.0, 1.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn non_finite_matrix_has_no_inverse() {
+        assert!(Mat4::scaling(Vec3::new(f64::NAN, 1.0, 1.0))
+            .inverse()
+            .is_none());
+        assert!(Mat4::translation(Vec3::new(f64::INFINITY, 0.0, 0.0))
+            .inverse()
+            .is_none());
+    }
+
+    #[test]
+    fn points_are_translated_but_not_vectors() {
+        let mat = Mat4::translation(Vec3::new(1.0, 2.0, 3.0))
+            * Mat4::rotation(Vec3::new(0.0, 0.0, FRAC_PI_2));
+        let x_axis = Vec3::new(1.0, 0.0, 0.0);
+        assert_vec_eq(mat.transform_point(x_axis), Vec3::new(1.0, 3.0, 3.0));
+        assert_vec_eq(mat.transform_vector(x_axis), Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_after_scaling() {
+        // Plane x + y = 0, stretched twice along x
+        let mat = Mat4::scaling(Vec3::new(2.0, 1.0, 1.0));
+        let inverse = mat.inverse().unwrap();
+        let normal = inverse.transform_normal(Vec3::new(1.0, 1.0, 0.0));
+        let tangent = mat.transform_vector(Vec3::new(1.0, -1.0, 0.0));
+        assert!(f64_eq(normal.dot_product(tangent), 0.0));
+        assert!(f64_eq(normal.norm(), 1.0));
+    }
+
+    #[test]
+    fn look_at_points_the_z_axis_to_the_target() {
+        let eye = Vec3::new(0.0, 0.0, -10.0);
+        let mat = Mat4::look_at(eye, Vec3::new(10.0, 0.0, -10.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_vec_eq(mat.transform_point(Vec3::new(0.0, 0.0, 0.0)), eye);
+        assert_vec_eq(
+            mat.transform_vector(Vec3::new(0.0, 0.0, 1.0)),
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+        assert_vec_eq(
+            mat.transform_vector(Vec3::new(0.0, 1.0, 0.0)),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+    }
+}
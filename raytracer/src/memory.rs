@@ -0,0 +1,152 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Estimation of the memory used by a render, so it is possible to know which
+//! part of a huge scene to trim.
+
+use crate::bvh::Bvh;
+use crate::renderer::{Pixel, RenderConfiguration};
+use crate::result::Result;
+use crate::scene::Scene;
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Display;
+
+/// Estimated peak memory, in bytes
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub acceleration: usize,
+    pub geometry: usize,
+    pub textures: usize,
+    /// Pixels rendered but not yet consumed, at most the whole canvas
+    pub framebuffer: usize,
+    /// Pixels of the tile rendered by each thread
+    pub per_thread: usize,
+    pub threads: usize,
+}
+
+impl MemoryUsage {
+    /// Memory of a rendering where each thread computes `batch_pixels` at once, a single
+    /// thread computing one pixel at a time if none
+    pub fn estimate(
+        scene: &Scene,
+        config: &RenderConfiguration,
+        batch_pixels: Option<usize>,
+    ) -> Self {
+        let pixel_size = std::mem::size_of::<Result<Pixel>>();
+        // Only computed when kept, as a canvas rendered by a single thread in wasm32 may
+        // have more bytes than its usize holds
+        let canvas_pixels = || config.canvas_width as usize * config.canvas_height as usize;
+        let (framebuffer, per_thread, threads) = match batch_pixels {
+            Some(batch_pixels) => (
+                canvas_pixels() * pixel_size,
+                batch_pixels * pixel_size,
+                rayon::current_num_threads(),
+            ),
+            None => (0, pixel_size, 1),
+        };
+        // Bottom level hierarchies shared by copies of a shape are counted once
+        let mut bottom_levels = HashSet::new();
+        let bottom_level_size = scene
+            .objects
+            .iter()
+            .filter_map(|object| object.shape.bottom_level_bvh())
+            .filter(|bvh| bottom_levels.insert(*bvh as *const Bvh))
+            .map(Bvh::memory_size)
+            .sum::<usize>();
+        MemoryUsage {
+            acceleration: scene.objects.bvh().memory_size() + bottom_level_size,
+            geometry: scene.objects.iter().map(|o| o.shape.memory_size()).sum(),
+            textures: scene.objects.iter().map(|o| o.texture.memory_size()).sum(),
+            framebuffer,
+            per_thread,
+            threads,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.acceleration
+            + self.geometry
+            + self.textures
+            + self.framebuffer
+            + self.per_thread * self.threads
+    }
+}
+
+impl Display for MemoryUsage {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{} total, {} acceleration, {} geometry, {} textures, {} framebuffer, {} per thread for {} threads",
+            format_bytes(self.total()),
+            format_bytes(self.acceleration),
+            format_bytes(self.geometry),
+            format_bytes(self.textures),
+            format_bytes(self.framebuffer),
+            format_bytes(self.per_thread),
+            self.threads
+        )
+    }
+}
+
+/// Human readable size, like 1.5 MiB
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_are_formatted_with_binary_units() {
+        assert_eq!(format_bytes(12), "12 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024), "3.0 MiB");
+    }
+
+    #[test]
+    fn total_counts_every_thread() {
+        let usage = MemoryUsage {
+            acceleration: 1,
+            geometry: 2,
+            textures: 3,
+            framebuffer: 4,
+            per_thread: 10,
+            threads: 8,
+        };
+        assert_eq!(usage.total(), 90);
+    }
+}
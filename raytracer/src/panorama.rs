@@ -0,0 +1,134 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Images too large for a single framebuffer, like gigapixel panoramas, split into a grid
+//! of parts rendered apart, by other runs or on other machines, then stitched together.
+
+use crate::cameras::CroppedCamera;
+use crate::result::{RaytracerError, Result};
+use crate::scene::Scene;
+use crate::tiles::Tile;
+use crate::utils::mix_seed;
+
+/// Canvas of the whole image cut into columns and rows of parts of nearly the same size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PanoramaGrid {
+    width: u32,
+    height: u32,
+    columns: u32,
+    rows: u32,
+}
+
+impl PanoramaGrid {
+    pub fn new(width: u32, height: u32, columns: u32, rows: u32) -> Result<PanoramaGrid> {
+        if columns == 0 || rows == 0 || columns > width || rows > height {
+            return Err(RaytracerError::ParsingError(format!(
+                "panorama: a {}x{} canvas cannot be cut into {}x{} parts",
+                width, height, columns, rows
+            )));
+        }
+        Ok(PanoramaGrid {
+            width,
+            height,
+            columns,
+            rows,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn columns(&self) -> u32 {
+        self.columns
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    /// Pixels of the whole canvas covered by the part at a column and a row
+    pub fn part(&self, column: u32, row: u32) -> Result<Tile> {
+        if column >= self.columns || row >= self.rows {
+            return Err(RaytracerError::ParsingError(format!(
+                "panorama: no part {},{} in a grid of {}x{} parts",
+                column, row, self.columns, self.rows
+            )));
+        }
+        let bound =
+            |index: u32, count: u32, size: u32| (index as u64 * size as u64 / count as u64) as u32;
+        let (x, y) = (
+            bound(column, self.columns, self.width),
+            bound(row, self.rows, self.height),
+        );
+        Ok(Tile {
+            x,
+            y,
+            width: bound(column + 1, self.columns, self.width) - x,
+            height: bound(row + 1, self.rows, self.height) - y,
+        })
+    }
+
+    /// Scene whose camera only sees the part, rendered on a canvas of the size of the
+    /// part. Its random samples are drawn from another seed for each part, or the noise
+    /// of the parts would repeat over the image.
+    pub fn part_scene(&self, mut scene: Scene, column: u32, row: u32) -> Result<Scene> {
+        let part = self.part(column, row)?;
+        let (width, height) = (self.width as f64, self.height as f64);
+        let top_left = (part.x as f64 / width, part.y as f64 / height);
+        let bottom_right = (
+            (part.x + part.width) as f64 / width,
+            (part.y + part.height) as f64 / height,
+        );
+        scene.camera = Box::new(CroppedCamera::new(scene.camera, top_left, bottom_right));
+        let index = row as u64 * self.columns as u64 + column as u64;
+        scene.config.seed = mix_seed(mix_seed(scene.config.seed) ^ index);
+        Ok(scene)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parts_cover_the_canvas_once() {
+        let grid = PanoramaGrid::new(101, 37, 4, 3).unwrap();
+        let mut covered = vec![0; 101 * 37];
+        for row in 0..3 {
+            for column in 0..4 {
+                for (x, y) in grid.part(column, row).unwrap().pixels() {
+                    covered[(y * 101 + x) as usize] += 1;
+                }
+            }
+        }
+        assert!(covered.iter().all(|&count| count == 1));
+        assert!(grid.part(4, 0).is_err());
+        assert!(PanoramaGrid::new(3, 3, 4, 1).is_err());
+    }
+}
@@ -22,59 +22,755 @@ OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE
 */
 
-use crate::cameras::{OrthogonalCamera, PerspectiveCamera};
+use crate::animation::{Animation, AnimationFrame, Keyframe};
+use crate::bvh::{Aabb, Bvh};
+use crate::cameras::{
+    Bokeh, EquirectangularCamera, FisheyeCamera, MovedCamera, OrthogonalCamera, PerspectiveCamera,
+    Shutter,
+};
 use crate::colors::Color;
-use crate::lights::{AnyLightObject, LightPoint, SpotLight};
-use crate::primitives::{InfinitePlan, Shape, Sphere, SquarePlan};
+use crate::environment::World;
+use crate::extrusion::{SvgExtrusion, Text3D};
+use crate::io::{cache, obj, yaml};
+use crate::lights::{
+    keyframed_intensity, AnyLightObject, LightDirectional, LightDisk, LightFlicker, LightPoint,
+    LightRectangle, SpotLight, TintedLight,
+};
+use crate::primitives::{
+    AABox, DisplacedSphere, Heightmap, InfinitePlan, MeshShading, Shape, Sphere, SquarePlan, Torus,
+    TransformedShape, TriangleMesh,
+};
 use crate::result::RaytracerError;
 use crate::result::Result;
-use crate::scene::{RayEmitter, Scene, SceneConfiguration, SceneObject};
-use crate::textures::{CheckedPattern, PlainColorTexture, Texture, TextureEffects, GradientColorTexture};
-use crate::vector::Vec3;
-use log::{info, trace};
-use serde::Deserialize;
+use crate::scene::{
+    RayEmitter, Scene, SceneConfiguration, SceneDumpFormat, SceneFormat, SceneObject, SceneObjects,
+    UnknownFields,
+};
+use crate::terrain::Terrain;
+use crate::texture_cache::TextureCache;
+use crate::textures::{
+    CheckedPattern, CloudTexture, GradientColorTexture, ImageTexture, PlainColorTexture,
+    PlanetTexture, Texture, TextureEffects, TextureTransform, TransformedTexture,
+};
+use crate::validation::{split_key, unknown_keys, KeyLocations, SceneError};
+use crate::vector::{Mat3, Vec3};
+use log::{info, trace, warn};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
 
 pub(crate) fn parse_scene_description(scene_str: &str) -> Result<Scene> {
-    let root_document = toml::from_str::<ModelRoot>(scene_str)
-        .map_err(|e| RaytracerError::ParsingError(e.to_string()))?;
+    parse_scene_description_at_frame(scene_str, SceneFormat::Toml, AnimationFrame::default())
+}
+
+/// Scene as seen at an animation frame, the one of the description if not given
+pub(crate) fn parse_scene_description_at_frame(
+    scene_str: &str,
+    format: SceneFormat,
+    frame: AnimationFrame,
+) -> Result<Scene> {
+    scene_of_description(scene_str, format, frame, None)
+}
+
+/// Scene at an animation frame, reusing the hierarchy of the objects at a previous one
+pub(crate) fn parse_scene_description_after(
+    scene_str: &str,
+    format: SceneFormat,
+    frame: AnimationFrame,
+    previous: SceneObjects,
+) -> Result<Scene> {
+    scene_of_description(scene_str, format, frame, Some(previous))
+}
+
+/// Every error of a description: its sections are checked one by one, each of them
+/// also being written back from what was understood of it to find the unknown keys
+pub(crate) fn validate_scene_description(scene_str: &str, format: SceneFormat) -> Vec<SceneError> {
+    let mut included = Vec::new();
+    let document = match document_table(scene_str, format)
+        .and_then(|document| with_included_files(document, &mut included))
+    {
+        Ok(document) => document,
+        Err(RaytracerError::InvalidScene(error)) => return vec![error],
+        Err(err) => return vec![SceneError::unlocated(err.to_string())],
+    };
+    let mut errors = Vec::new();
+    let no_materials = toml::value::Table::new();
+    let materials = match document.get("materials") {
+        Some(toml::Value::Table(materials)) => materials,
+        _ => &no_materials,
+    };
+    // Objects are checked for their material once understood
+    let check_material = |object: Option<DescriptionObject>, path: &str, errors: &mut Vec<_>| {
+        if let Some(object) = object {
+            let (material, has_texture) = (object.material.as_deref(), object.texture.is_some());
+            errors.extend(material_error(material, has_texture, materials, path));
+        }
+    };
+    for (key, value) in &document {
+        match key.as_str() {
+            "description" => {
+                checked::<String>(value, key, &mut errors);
+            }
+            "materials" => {
+                let names = checked::<toml::value::Table>(value, key, &mut errors);
+                for (name, material) in names.iter().flatten() {
+                    let path = format!("{}.{}", key, name);
+                    checked::<ModelMaterial>(material, &path, &mut errors);
+                }
+            }
+            "config" => {
+                checked::<SceneConfiguration>(value, key, &mut errors);
+            }
+            "animation" => {
+                checked::<Animation>(value, key, &mut errors);
+            }
+            "camera" => {
+                checked::<ModelCamera>(value, key, &mut errors);
+            }
+            "object" | "light" | "scatter" | "instances" => {
+                let elements = checked::<Vec<toml::Value>>(value, key, &mut errors);
+                for (index, element) in elements.iter().flatten().enumerate() {
+                    let path = format!("{}[{}]", key, index);
+                    match key.as_str() {
+                        "object" => {
+                            let object = checked(element, &path, &mut errors);
+                            check_material(object, &path, &mut errors);
+                        }
+                        "light" => {
+                            checked::<ModelLight>(element, &path, &mut errors);
+                        }
+                        "instances" => {
+                            let instances =
+                                checked::<DescriptionInstances>(element, &path, &mut errors);
+                            let objects = instances.iter().flat_map(|i| i.object.iter());
+                            for (index, object) in objects.enumerate() {
+                                let path = format!("{}.object[{}]", path, index);
+                                let object = checked(object, &path, &mut errors);
+                                check_material(object, &path, &mut errors);
+                            }
+                        }
+                        _ => {
+                            let scatter =
+                                checked::<DescriptionScatter>(element, &path, &mut errors);
+                            if let Some(scatter) = scatter {
+                                let path = format!("{}.object", path);
+                                let object = checked(&scatter.object, &path, &mut errors);
+                                check_material(object, &path, &mut errors);
+                            }
+                        }
+                    }
+                }
+            }
+            _ => errors.push((key.clone(), format!("unknown field `{}`", key))),
+        }
+    }
+    if !document.contains_key("camera") {
+        errors.push(("camera".to_string(), "missing field `camera`".to_string()));
+    }
+    // Keys merged from included files are not in the text
+    let locations = match format {
+        SceneFormat::Toml if included.is_empty() => KeyLocations::of(scene_str),
+        _ => KeyLocations::of(""),
+    };
+    errors
+        .into_iter()
+        .map(|(key, message)| locations.error(&key, message))
+        .collect()
+}
+
+/// Section of a description as understood, adding its error or its unknown keys if any
+fn checked<T: DeserializeOwned + Serialize>(
+    value: &toml::Value,
+    path: &str,
+    errors: &mut Vec<(String, String)>,
+) -> Option<T> {
+    match value.clone().try_into::<T>() {
+        Ok(section) => {
+            if let Ok(understood) = toml::Value::try_from(&section) {
+                for key in unknown_keys(value, &understood, path) {
+                    let field = key.rsplit('.').next().unwrap_or_default().to_string();
+                    errors.push((key, format!("unknown field `{}`", field)));
+                }
+            }
+            Some(section)
+        }
+        Err(err) => {
+            // The key in error is given relative to the section
+            let (message, key) = split_key(err.to_string());
+            match key {
+                Some(key) => errors.push((format!("{}.{}", path, key), message)),
+                None => errors.push((path.to_string(), message)),
+            }
+            None
+        }
+    }
+}
+
+/// Description as a TOML table, whatever its format. As TOML has no null value, null
+/// values of other formats are left out, like missing keys.
+fn document_table(scene_str: &str, format: SceneFormat) -> Result<toml::value::Table> {
+    let invalid_scene = |error| RaytracerError::InvalidScene(error);
+    let document = match format {
+        SceneFormat::Toml => {
+            return toml::from_str(scene_str).map_err(|e| invalid_scene(SceneError::of_toml(&e)))
+        }
+        SceneFormat::Json => serde_json::from_str::<serde_json::Value>(scene_str)
+            .map_err(|e| invalid_scene(SceneError::of_json(&e)))?,
+        SceneFormat::Yaml => yaml::parse_yaml(scene_str)?,
+    };
+    let not_a_table = || {
+        invalid_scene(SceneError::unlocated(
+            "the description is not a table".to_string(),
+        ))
+    };
+    // An empty document is a null value
+    if document.is_null() {
+        return Err(not_a_table());
+    }
+    match toml::Value::try_from(without_nulls(document)) {
+        Ok(toml::Value::Table(table)) => Ok(table),
+        Ok(_) => Err(not_a_table()),
+        Err(err) => Err(invalid_scene(SceneError::unlocated(err.to_string()))),
+    }
+}
+
+/// Description with the files given by its `include` key merged into it, in their order,
+/// each one being merged with its own included files first. Tables are merged key by key,
+/// the keys of a file replacing those of the files it includes, except arrays of tables
+/// like `[[object]]` which are appended. The included files, relative to the working
+/// directory like the other files of a description, are added to `included`.
+fn with_included_files(
+    document: toml::value::Table,
+    included: &mut Vec<PathBuf>,
+) -> Result<toml::value::Table> {
+    merge_included_files(document, &mut Vec::new(), included)
+}
+
+fn merge_included_files(
+    mut document: toml::value::Table,
+    chain: &mut Vec<PathBuf>,
+    included: &mut Vec<PathBuf>,
+) -> Result<toml::value::Table> {
+    let invalid_scene = |message: String| {
+        RaytracerError::InvalidScene(SceneError {
+            message,
+            key: Some("include".to_string()),
+            position: None,
+        })
+    };
+    let files = match document.remove("include") {
+        None => return Ok(document),
+        Some(toml::Value::String(file)) => vec![file],
+        Some(toml::Value::Array(files)) => files
+            .into_iter()
+            .map(|file| match file {
+                toml::Value::String(file) => Ok(file),
+                other => Err(invalid_scene(format!("{} is not a file name", other))),
+            })
+            .collect::<Result<_>>()?,
+        Some(other) => return Err(invalid_scene(format!("{} is not a list of files", other))),
+    };
+    let mut merged = toml::value::Table::new();
+    for file in files {
+        let path = PathBuf::from(&file);
+        let io_error = |e: std::io::Error| RaytracerError::IoError(format!("{}: {}", file, e));
+        let canonical = fs::canonicalize(&path).map_err(io_error)?;
+        if chain.contains(&canonical) {
+            let cycle: Vec<String> = chain
+                .iter()
+                .chain(std::iter::once(&canonical))
+                .map(|path| path.display().to_string())
+                .collect();
+            return Err(invalid_scene(format!(
+                "cycle of included files {}",
+                cycle.join(" -> ")
+            )));
+        }
+        let text = fs::read_to_string(&path).map_err(io_error)?;
+        let format = SceneFormat::from_path(&path).unwrap_or_default();
+        let in_file = |err| match err {
+            RaytracerError::InvalidScene(mut error) => {
+                error.message = format!("{}: {}", file, error.message);
+                RaytracerError::InvalidScene(error)
+            }
+            err => err,
+        };
+        chain.push(canonical);
+        let file_document = document_table(&text, format)
+            .and_then(|document| merge_included_files(document, chain, included))
+            .map_err(in_file)?;
+        chain.pop();
+        merge_tables(&mut merged, file_document);
+        included.push(path);
+    }
+    merge_tables(&mut merged, document);
+    Ok(merged)
+}
+
+fn merge_tables(base: &mut toml::value::Table, overlay: toml::value::Table) {
+    let is_table = |value: &toml::Value| value.is_table();
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => {
+                merge_tables(base, overlay)
+            }
+            (Some(toml::Value::Array(base)), toml::Value::Array(overlay))
+                if base.iter().chain(overlay.iter()).all(is_table) =>
+            {
+                base.extend(overlay)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn without_nulls(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, without_nulls(value)))
+                .collect(),
+        ),
+        serde_json::Value::Array(items) => serde_json::Value::Array(
+            items
+                .into_iter()
+                .filter(|value| !value.is_null())
+                .map(without_nulls)
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+fn root_document(scene_str: &str, format: SceneFormat) -> Result<ModelRoot> {
+    root_document_including(scene_str, format, &mut Vec::new())
+}
+
+/// Description with the files it includes, added to `included`. TOML including nothing
+/// is read from the text, for the positions of the errors.
+fn root_document_including(
+    scene_str: &str,
+    format: SceneFormat,
+    included: &mut Vec<PathBuf>,
+) -> Result<ModelRoot> {
+    let read_from_text = |scene_str| {
+        toml::from_str::<ModelRoot>(scene_str)
+            .map_err(|e| RaytracerError::InvalidScene(SceneError::of_toml(&e)))
+    };
+    if format == SceneFormat::Toml && !scene_str.contains("include") {
+        return read_from_text(scene_str);
+    }
+    let document = document_table(scene_str, format)?;
+    if format == SceneFormat::Toml && !document.contains_key("include") {
+        return read_from_text(scene_str);
+    }
+    toml::Value::Table(with_included_files(document, included)?)
+        .try_into::<ModelRoot>()
+        .map_err(|e| RaytracerError::InvalidScene(SceneError::unlocated(e.to_string())))
+}
+
+/// Scene of a description, checked for unknown keys as its configuration tells
+fn scene_of_description(
+    scene_str: &str,
+    format: SceneFormat,
+    frame: AnimationFrame,
+    previous: Option<SceneObjects>,
+) -> Result<Scene> {
+    let root_document = root_document(scene_str, format)?;
+    let unknown_fields = root_document.config.unknown_fields;
+    let unknown = match unknown_fields {
+        UnknownFields::Ignore => Vec::new(),
+        UnknownFields::Warn | UnknownFields::Deny => validate_scene_description(scene_str, format),
+    };
+    if let (UnknownFields::Deny, Some(error)) = (unknown_fields, unknown.first()) {
+        return Err(RaytracerError::InvalidScene(error.clone()));
+    }
+    let mut scene = scene_of_root_document(root_document, frame, previous)?;
+    for error in unknown {
+        let warning = error.to_string();
+        warn!("{}", warning);
+        scene.warnings.push(warning);
+    }
+    Ok(scene)
+}
+
+/// Description with the shapes loaded from files given as triangles, encoded as a cache
+pub(crate) fn scene_cache(scene_str: &str, format: SceneFormat) -> Result<Vec<u8>> {
+    let parsing_error = |e: &dyn std::fmt::Display| RaytracerError::ParsingError(e.to_string());
+    let mut assets = Vec::new();
+    let mut root_document = root_document_including(scene_str, format, &mut assets)?;
+    root_document.object = std::mem::take(&mut root_document.object)
+        .into_iter()
+        .map(|mut object| {
+            object.shape = object.shape.prepared(&mut assets);
+            object
+        })
+        .collect();
+    for scatter in root_document.scatter.iter_mut() {
+        let mut object = scatter
+            .object
+            .clone()
+            .try_into::<DescriptionObject>()
+            .map_err(|e| parsing_error(&e))?;
+        object.shape = object.shape.prepared(&mut assets);
+        scatter.object = toml::Value::try_from(object).map_err(|e| parsing_error(&e))?;
+    }
+    let instanced = root_document.instances.iter_mut();
+    for value in instanced.flat_map(|instances| instances.object.iter_mut()) {
+        let mut object = value
+            .clone()
+            .try_into::<DescriptionObject>()
+            .map_err(|e| parsing_error(&e))?;
+        object.shape = object.shape.prepared(&mut assets);
+        *value = toml::Value::try_from(object).map_err(|e| parsing_error(&e))?;
+    }
+    let assets = assets
+        .into_iter()
+        .map(|path| {
+            let modified = cache::modification_time(&path);
+            (path, modified)
+        })
+        .collect();
+    let description = serde_json::to_value(&root_document).map_err(|e| parsing_error(&e))?;
+    Ok(cache::encode(scene_str, &assets, &description))
+}
+
+/// Scene of a cache, none if the cache is stale
+pub(crate) fn parse_scene_cache(
+    bytes: &[u8],
+    scene_str: &str,
+    frame: AnimationFrame,
+) -> Result<Option<Scene>> {
+    let description = match cache::decode(bytes, scene_str)? {
+        Some(description) => description,
+        None => return Ok(None),
+    };
+    let root_document = serde_json::from_value::<ModelRoot>(description)
+        .map_err(|e| RaytracerError::ParsingError(format!("scene cache: {}", e)))?;
+    scene_of_root_document(root_document, frame, None).map(Some)
+}
+
+fn scene_of_root_document(
+    mut root_document: ModelRoot,
+    frame: AnimationFrame,
+    previous: Option<SceneObjects>,
+) -> Result<Scene> {
+    if let Some(number) = frame.frame {
+        root_document.config.frame = number;
+        root_document.config.time = None;
+    }
+    if let Some(frames_per_second) = frame.frames_per_second {
+        root_document.config.frames_per_second = frames_per_second;
+    }
+    if let Some(time) = frame.time {
+        root_document.config.time = Some(time);
+    }
+    let frames_per_second = root_document.config.frames_per_second;
+    if !(frames_per_second.is_finite() && frames_per_second > 0.0) {
+        return Err(RaytracerError::InvalidScene(SceneError {
+            message: format!(
+                "{} is not a positive number of frames per second",
+                frames_per_second
+            ),
+            key: Some("config.frames_per_second".to_string()),
+            position: None,
+        }));
+    }
+    let gamma = root_document.config.tone_mapping.gamma;
+    if !(gamma.is_finite() && gamma > 0.0) {
+        return Err(RaytracerError::InvalidScene(SceneError {
+            message: format!("{} is not a positive gamma", gamma),
+            key: Some("config.tone_mapping.gamma".to_string()),
+            position: None,
+        }));
+    }
     trace!("Parsed scene description: {:#?}", root_document);
+    let mut description = filled_description(&root_document)?;
+    description.remove("config");
     if let Some(description) = root_document.description {
         info!("Generating scene for: {}", description);
     }
-    let config = root_document.config;
-    let camera = root_document.camera.into_ray_emitter();
-    let lights = root_document
+    let mut config = root_document.config;
+    let time = config.time();
+    let mut animation = std::mem::take(&mut root_document.animation);
+    sort_keys(&mut animation.camera, |key| key.time, "animation.camera")?;
+    for (index, track) in animation.object.iter_mut().enumerate() {
+        let path = format!("animation.object[{}].keys", index);
+        sort_keys(&mut track.keys, |key| key.time, &path)?;
+    }
+    // Seconds from the frame, and from the start of the animation
+    let shutter = root_document.camera.shutter;
+    let shutter_times = shutter.times(config.frames_per_second);
+    let animation_times = time + shutter_times.start..time + shutter_times.end;
+    let camera = root_document.camera.camera.into_ray_emitter();
+    let camera: Box<dyn RayEmitter> = if animation.camera.is_empty() && !shutter.is_open() {
+        camera
+    } else {
+        let (position, rotation) = Keyframe::at(&animation.camera, time)
+            .map_or((Vec3::default(), Mat3::ID), |key| {
+                (key.position, key.rotation())
+            });
+        let (velocity, angular_velocity) =
+            Keyframe::velocities(&animation.camera, animation_times.clone()).unwrap_or_default();
+        Box::new(
+            MovedCamera::new(Arc::from(camera), position)
+                .with_rotation(rotation)
+                .with_shutter(shutter)
+                .with_motion(velocity, angular_velocity),
+        )
+    };
+    for (index, track) in animation.object.iter().enumerate() {
+        let object = root_document.object.get_mut(track.index).ok_or_else(|| {
+            RaytracerError::InvalidScene(SceneError {
+                message: format!("there is no object {} to animate", track.index),
+                key: Some(format!("animation.object[{}].index", index)),
+                position: None,
+            })
+        })?;
+        if let Some(key) = Keyframe::at(&track.keys, time) {
+            object.position = Some(key.position);
+            object.rotation_degree = Some(key.rotation_degree);
+            object.scale = Some(ModelScale::Uniform(key.scale));
+        }
+        if let Some((velocity, _)) = Keyframe::velocities(&track.keys, animation_times.clone()) {
+            object.velocity = Some(velocity);
+        }
+    }
+    TextureCache::global().set_budget(config.texture_cache_mb.saturating_mul(1024 * 1024));
+    let mut warnings = Vec::new();
+    if let Some(World::Image(environment)) = config.world.as_mut() {
+        match environment.load() {
+            Ok(()) => {}
+            Err(err) if config.asset_placeholders => {
+                let warning = format!("environment map replaced by a placeholder, {}", err);
+                warn!("{}", warning);
+                warnings.push(warning);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    let mut lights: Vec<Box<dyn AnyLightObject>> = root_document
         .light
         .into_iter()
-        .map(DescriptionLight::into_any_light_object)
-        .collect();
-    let objects = root_document
+        .enumerate()
+        .map(|(index, light)| light.into_any_light_object(time, &format!("light[{}]", index)))
+        .collect::<Result<_>>()?;
+    let materials = root_document.materials;
+    let mut objects = root_document
         .object
         .into_iter()
-        .map(DescriptionObject::into_scene_object)
-        .collect();
+        .enumerate()
+        .map(|(index, object)| {
+            let path = format!("object[{}]", index);
+            let velocity = object.velocity.filter(|_| shutter.is_open());
+            let object = object.into_scene_object(&config, &materials, &path, &mut warnings)?;
+            Ok(match velocity {
+                Some(velocity) => object.moving(velocity, shutter_times.clone()),
+                None => object,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for (index, instances) in root_document.instances.into_iter().enumerate() {
+        let path = format!("instances[{}]", index);
+        objects.extend(instances.into_scene_objects(&config, &materials, &path, &mut warnings)?);
+    }
+    for (index, scatter) in root_document.scatter.into_iter().enumerate() {
+        let path = format!("scatter[{}]", index);
+        objects.extend(scatter.into_scene_objects(&config, &materials, &path, &mut warnings)?);
+    }
+    // Waves roll with the time of the scene, unless their time is given
+    for object in objects.iter_mut() {
+        if let Some(water) = object.effects.water.as_mut() {
+            water.time.get_or_insert(time);
+        }
+    }
+    if config.three_point_lights {
+        lights.extend(three_point_lights(&objects, camera.as_ref()));
+    }
+    if config.auto_stage {
+        let (ground, key_light) = auto_stage(&objects);
+        objects.push(ground);
+        lights.push(key_light);
+    }
+    let objects = match previous {
+        Some(previous) => previous.refit(objects),
+        None => objects.into(),
+    };
 
     Ok(Scene {
         camera,
         lights,
         objects,
         config,
+        warnings,
+        description: Some(description),
     })
 }
 
-#[derive(Debug, Deserialize)]
+/// Box around the objects with a finite size, a unit one if there are none
+fn objects_bounds(objects: &[SceneObject]) -> Aabb {
+    let corners = objects
+        .iter()
+        .filter_map(SceneObject::aabb)
+        .flat_map(|aabb| vec![aabb.min, aabb.max]);
+    Aabb::from_points(corners)
+        .unwrap_or_else(|| Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0)))
+}
+
+/// Key, fill and rim lights of product shots, placed from where the camera looks at the
+/// objects: a soft key light above on the left, a dimmer fill light on the right and a
+/// rim light behind the objects to separate them from the background
+fn three_point_lights(
+    objects: &[SceneObject],
+    camera: &dyn RayEmitter,
+) -> Vec<Box<dyn AnyLightObject>> {
+    let bounds = objects_bounds(objects);
+    let center = bounds.center();
+    let size = bounds.min.distance(bounds.max).max(1.0);
+    let forward = camera.generate_ray(0.5, 0.5).direction;
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    let right = up.cross_product(forward);
+    let right = if right.norm() > 1e-9 {
+        right.normalize()
+    } else {
+        // Camera looking straight up or down
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let soft_light = |position: Vec3, width: f64, intensity: f64| -> Box<dyn AnyLightObject> {
+        Box::new(LightRectangle::new(
+            position,
+            Vec3::between_points(position, center).normalize(),
+            width,
+            width,
+            Color::new(intensity, intensity, intensity),
+        ))
+    };
+    let key_position = center + size * (-0.7 * forward - 0.7 * right + 0.7 * up);
+    let fill_position = center + size * (-0.7 * forward + 0.9 * right + 0.2 * up);
+    let rim_position = center + size * (forward + up);
+    vec![
+        soft_light(key_position, size / 2.0, 0.8),
+        soft_light(fill_position, size, 0.4),
+        Box::new(LightPoint::with_color(
+            rim_position,
+            Color::new(0.6, 0.6, 0.6),
+        )),
+    ]
+}
+
+/// Studio around the objects, a shadow catcher ground under them and a large light
+/// above, in front and on the left of them, for soft shadows
+fn auto_stage(objects: &[SceneObject]) -> (SceneObject, Box<dyn AnyLightObject>) {
+    let bounds = objects_bounds(objects);
+    let center = bounds.center();
+    let size = bounds.min.distance(bounds.max).max(1.0);
+    let ground = SceneObject {
+        texture: Box::new(PlainColorTexture::default()),
+        shape: Box::new(InfinitePlan::new(
+            Vec3::new(center.x, bounds.min.y, center.z),
+            Vec3::new(0.0, 1.0, 0.0),
+        )),
+        effects: TextureEffects {
+            shadow_catcher: true,
+            ..Default::default()
+        },
+    };
+    let light_center = center + size * Vec3::new(-1.0, 2.0, -1.0);
+    let key_light = LightRectangle::new(
+        light_center,
+        Vec3::between_points(light_center, center).normalize(),
+        size / 2.0,
+        size / 2.0,
+        Color::WHITE,
+    );
+    (ground, Box::new(key_light))
+}
+
+/// Parse the scene description and write it back with the default values filled in
+pub(crate) fn dump_scene_description(
+    scene_str: &str,
+    scene_format: SceneFormat,
+    format: SceneDumpFormat,
+) -> Result<String> {
+    let parsing_error = |e: &dyn std::fmt::Display| RaytracerError::ParsingError(e.to_string());
+    let description = filled_description(&root_document(scene_str, scene_format)?)?;
+    match format {
+        SceneDumpFormat::Toml => {
+            toml::to_string(&toml::Value::Table(description)).map_err(|e| parsing_error(&e))
+        }
+        SceneDumpFormat::Json => {
+            serde_json::to_string_pretty(&description).map_err(|e| parsing_error(&e))
+        }
+    }
+}
+
+/// Description written back with its default values, the objects of the scatterings and
+/// instances too
+fn filled_description(root_document: &ModelRoot) -> Result<toml::value::Table> {
+    let parsing_error = |e: &dyn std::fmt::Display| RaytracerError::ParsingError(e.to_string());
+    let mut description = match toml::Value::try_from(root_document) {
+        Ok(toml::Value::Table(description)) => description,
+        Ok(_) => unreachable!("a description is a table"),
+        Err(err) => return Err(parsing_error(&err)),
+    };
+    let fill = |object: &mut toml::Value| -> Result<()> {
+        let filled = object
+            .clone()
+            .try_into::<DescriptionObject>()
+            .map_err(|e| parsing_error(&e))?;
+        *object = toml::Value::try_from(filled).map_err(|e| parsing_error(&e))?;
+        Ok(())
+    };
+    if let Some(toml::Value::Array(scatters)) = description.get_mut("scatter") {
+        for object in scatters
+            .iter_mut()
+            .filter_map(|scatter| scatter.get_mut("object"))
+        {
+            fill(object)?;
+        }
+    }
+    if let Some(toml::Value::Array(instances)) = description.get_mut("instances") {
+        let objects = instances.iter_mut().filter_map(|i| i.get_mut("object"));
+        for objects in objects.filter_map(toml::Value::as_array_mut) {
+            objects.iter_mut().try_for_each(fill)?;
+        }
+    }
+    Ok(description)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct ModelRoot {
     description: Option<String>,
     #[serde(default)]
     config: SceneConfiguration,
-    camera: DescriptionCamera,
+    camera: ModelCamera,
+    #[serde(default)]
     object: Vec<DescriptionObject>,
-    light: Vec<DescriptionLight>,
+    /// Not needed when the stage gives its light
+    #[serde(default)]
+    light: Vec<ModelLight>,
+    #[serde(default)]
+    scatter: Vec<DescriptionScatter>,
+    #[serde(default)]
+    instances: Vec<DescriptionInstances>,
+    #[serde(default, skip_serializing_if = "Animation::is_empty")]
+    animation: Animation,
+    /// Textures and effects by name, shared by the objects giving it as their `material`
+    #[serde(default, skip_serializing_if = "toml::value::Table::is_empty")]
+    materials: toml::value::Table,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(transparent)]
 pub(crate) struct ModelVector([f64; 3]);
 
@@ -84,19 +780,80 @@ impl From<ModelVector> for Vec3 {
     }
 }
 
-impl From<ModelColor> for Color {
-    fn from(model_color: ModelColor) -> Self {
+impl From<Vec3> for ModelVector {
+    fn from(vector: Vec3) -> Self {
+        ModelVector([vector.x, vector.y, vector.z])
+    }
+}
+
+impl From<Color> for ModelColor {
+    fn from(color: Color) -> Self {
+        ModelColor::ByRGB([color.red(), color.green(), color.blue()])
+    }
+}
+
+impl TryFrom<ModelColor> for Color {
+    type Error = String;
+
+    fn try_from(model_color: ModelColor) -> std::result::Result<Self, Self::Error> {
         match model_color {
-            ModelColor::ByString(value) => Color::from_str(&value).unwrap(),
-            ModelColor::ByRGB(rgb) => Color::new(rgb[0], rgb[1], rgb[2]),
+            ModelColor::ByString(value) => Color::from_str(&value),
+            ModelColor::ByRGB(rgb) => Ok(Color::new(rgb[0], rgb[1], rgb[2])),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
+struct ModelLight {
+    #[serde(default)]
+    description: String,
+    #[serde(flatten)]
+    light: DescriptionLight,
+    #[serde(default)]
+    flicker: Option<LightFlicker>,
+    /// Intensity multiplying the light color, as (time in seconds, intensity) pairs
+    #[serde(default)]
+    intensity_keys: Vec<(f64, f64)>,
+}
+
+impl ModelLight {
+    /// Light at `time` in seconds, tinted by its animation if it has one, `path` being
+    /// where it is in the description
+    fn into_any_light_object(mut self, time: f64, path: &str) -> Result<Box<dyn AnyLightObject>> {
+        let light = self.light.into_any_light_object();
+        if self.flicker.is_none() && self.intensity_keys.is_empty() {
+            return Ok(light);
+        }
+        let keys_path = format!("{}.intensity_keys", path);
+        sort_keys(&mut self.intensity_keys, |(time, _)| *time, &keys_path)?;
+        let flicker_tint = self
+            .flicker
+            .map_or(Color::WHITE, |flicker| flicker.tint_at(time));
+        let intensity = keyframed_intensity(&self.intensity_keys, time);
+        Ok(Box::new(TintedLight::new(light, flicker_tint * intensity)))
+    }
+}
+
+/// Keys of an animation sorted by their time, so they can be given in any order, a time
+/// which is not a finite number being an error of the keys at `path`
+fn sort_keys<K, F>(keys: &mut [K], time: F, path: &str) -> Result<()>
+where
+    F: Fn(&K) -> f64,
+{
+    if let Some(key_time) = keys.iter().map(&time).find(|time| !time.is_finite()) {
+        return Err(RaytracerError::InvalidScene(SceneError {
+            message: format!("{} is not a time in seconds", key_time),
+            key: Some(path.to_string()),
+            position: None,
+        }));
+    }
+    keys.sort_by(|a, b| time(a).total_cmp(&time(b)));
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "type")]
-
 enum DescriptionLight {
     Point(LightPoint),
     Spot {
@@ -106,6 +863,23 @@ enum DescriptionLight {
         inner_angle_degree: f64,
         outer_angle_degree: f64,
     },
+    Rectangle {
+        center: Vec3,
+        normal: Vec3,
+        width: f64,
+        height: f64,
+        color: Color,
+    },
+    Disk {
+        center: Vec3,
+        normal: Vec3,
+        radius: f64,
+        color: Color,
+    },
+    Directional {
+        direction: Vec3,
+        color: Color,
+    },
 }
 
 impl DescriptionLight {
@@ -125,11 +899,40 @@ impl DescriptionLight {
                 outer_angle_degree,
                 color,
             )),
+            DescriptionLight::Rectangle {
+                center,
+                normal,
+                width,
+                height,
+                color,
+            } => Box::new(LightRectangle::new(center, normal, width, height, color)),
+            DescriptionLight::Disk {
+                center,
+                normal,
+                radius,
+                color,
+            } => Box::new(LightDisk::new(center, normal, radius, color)),
+            DescriptionLight::Directional { direction, color } => {
+                Box::new(LightDirectional::new(direction, color))
+            }
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Camera of any type, with the interval its shutter is open
+#[derive(Debug, Deserialize, Serialize)]
+struct ModelCamera {
+    #[serde(flatten)]
+    camera: DescriptionCamera,
+    #[serde(default, skip_serializing_if = "is_closed")]
+    shutter: Shutter,
+}
+
+fn is_closed(shutter: &Shutter) -> bool {
+    !shutter.is_open()
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "type")]
 #[serde(deny_unknown_fields)]
@@ -141,12 +944,52 @@ enum DescriptionCamera {
         height: f64,
         #[serde(default = "default_perspective_angle")]
         angle_degree: f64,
+        #[serde(default)]
+        near_clip: Option<f64>,
+        #[serde(default)]
+        far_clip: Option<f64>,
+        /// Lens radius, objects not at `focal_distance` being blurred
+        #[serde(default)]
+        aperture_radius: f64,
+        /// Defaults to the distance between the eye and the screen
+        #[serde(default)]
+        focal_distance: Option<f64>,
+        /// Polygonal aperture, circular if not given
+        #[serde(default)]
+        aperture_blades: Option<u32>,
+        #[serde(default)]
+        aperture_rotation_degree: f64,
+        /// Brightness weighting each point of the aperture
+        #[serde(default)]
+        bokeh_texture: Option<ModelTexture>,
     },
     Orthogonal {
         eye: Vec3,
         look_at: Vec3,
         width: f64,
         height: f64,
+        #[serde(default)]
+        near_clip: Option<f64>,
+        #[serde(default)]
+        far_clip: Option<f64>,
+    },
+    Fisheye {
+        eye: Vec3,
+        look_at: Vec3,
+        #[serde(default = "default_fisheye_field_of_view")]
+        field_of_view_degree: f64,
+        #[serde(default)]
+        near_clip: Option<f64>,
+        #[serde(default)]
+        far_clip: Option<f64>,
+    },
+    Equirectangular {
+        eye: Vec3,
+        look_at: Vec3,
+        #[serde(default)]
+        near_clip: Option<f64>,
+        #[serde(default)]
+        far_clip: Option<f64>,
     },
 }
 
@@ -159,37 +1002,277 @@ impl DescriptionCamera {
                 width,
                 height,
                 angle_degree,
-            } => Box::new(PerspectiveCamera::new(
-                screen_center,
-                look_at,
-                width,
-                height,
-                angle_degree,
-            )),
+                near_clip,
+                far_clip,
+                aperture_radius,
+                focal_distance,
+                aperture_blades,
+                aperture_rotation_degree,
+                bokeh_texture,
+            } => {
+                let mut camera =
+                    PerspectiveCamera::new(screen_center, look_at, width, height, angle_degree);
+                if let Some(near_clip) = near_clip {
+                    camera = camera.with_near_clip(near_clip);
+                }
+                if let Some(far_clip) = far_clip {
+                    camera = camera.with_far_clip(far_clip);
+                }
+                if aperture_radius > 0.0 {
+                    let focal_distance = focal_distance.unwrap_or(camera.focal_distance());
+                    camera = camera
+                        .with_depth_of_field(aperture_radius, focal_distance)
+                        .with_bokeh(Bokeh {
+                            blades: aperture_blades,
+                            rotation: aperture_rotation_degree.to_radians(),
+                            texture: bokeh_texture.map(ModelTexture::into_texture),
+                        });
+                }
+                Box::new(camera)
+            }
             DescriptionCamera::Orthogonal {
                 eye,
                 look_at,
                 width,
                 height,
-            } => Box::new(OrthogonalCamera::new(eye, look_at, width, height)),
+                near_clip,
+                far_clip,
+            } => {
+                let mut camera = OrthogonalCamera::new(eye, look_at, width, height);
+                if let Some(near_clip) = near_clip {
+                    camera = camera.with_near_clip(near_clip);
+                }
+                if let Some(far_clip) = far_clip {
+                    camera = camera.with_far_clip(far_clip);
+                }
+                Box::new(camera)
+            }
+            DescriptionCamera::Fisheye {
+                eye,
+                look_at,
+                field_of_view_degree,
+                near_clip,
+                far_clip,
+            } => {
+                let mut camera =
+                    FisheyeCamera::new(eye, look_at, field_of_view_degree.to_radians());
+                if let Some(near_clip) = near_clip {
+                    camera = camera.with_near_clip(near_clip);
+                }
+                if let Some(far_clip) = far_clip {
+                    camera = camera.with_far_clip(far_clip);
+                }
+                Box::new(camera)
+            }
+            DescriptionCamera::Equirectangular {
+                eye,
+                look_at,
+                near_clip,
+                far_clip,
+            } => {
+                let mut camera = EquirectangularCamera::new(eye, look_at);
+                if let Some(near_clip) = near_clip {
+                    camera = camera.with_near_clip(near_clip);
+                }
+                if let Some(far_clip) = far_clip {
+                    camera = camera.with_far_clip(far_clip);
+                }
+                Box::new(camera)
+            }
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 struct DescriptionObject {
+    #[serde(default)]
     description: String,
-    texture: ModelTexture,
+    /// Name of a material of `[materials]`, giving the texture and effects not set here
+    #[serde(default)]
+    material: Option<String>,
+    #[serde(default)]
+    texture: Option<ModelTexture>,
     #[serde(default)]
     effect: Option<TextureEffects>,
+    /// Moves the shape, after it has been scaled then rotated around the origin
+    #[serde(default)]
+    position: Option<Vec3>,
+    /// Angles of rotation around the x, then y, then z axis
+    #[serde(default)]
+    rotation_degree: Option<Vec3>,
+    #[serde(default)]
+    scale: Option<ModelScale>,
+    /// Moves per second while the shutter of the camera is open, blurring the object
+    #[serde(default)]
+    velocity: Option<Vec3>,
     #[serde(flatten)]
     shape: ModelShape,
 }
 
+/// Same scale along every axis, or one scale per axis
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(untagged)]
+enum ModelScale {
+    Uniform(f64),
+    ByAxis(Vec3),
+}
+
+/// Texture and effects shared by the objects naming it
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ModelMaterial {
+    texture: ModelTexture,
+    #[serde(default)]
+    effect: Option<TextureEffects>,
+}
+
+/// Error of the material an object at `path` refers to, if any
+fn material_error(
+    material: Option<&str>,
+    has_texture: bool,
+    materials: &toml::value::Table,
+    path: &str,
+) -> Option<(String, String)> {
+    match material {
+        Some(name) if !materials.contains_key(name) => Some((
+            format!("{}.material", path),
+            format!("unknown material `{}`", name),
+        )),
+        None if !has_texture => Some((path.to_string(), "missing field `texture`".to_string())),
+        _ => None,
+    }
+}
+
 impl DescriptionObject {
-    fn into_scene_object(self) -> SceneObject {
-        let shape: Box<dyn Shape> = match self.shape {
+    fn into_scene_object(
+        self,
+        config: &SceneConfiguration,
+        materials: &toml::value::Table,
+        path: &str,
+        warnings: &mut Vec<String>,
+    ) -> Result<SceneObject> {
+        let (texture, effect) =
+            Self::material_of(self.texture, self.effect, self.material, materials, path)?;
+        let (shape, placeholder) = self.shape.into_shape(config, warnings)?;
+        let shape = Self::transformed(shape, self.position, self.rotation_degree, self.scale);
+        Ok(Self::with_shape(texture, effect, shape, placeholder))
+    }
+
+    /// Texture and effects of an object, those it does not give taken from its material
+    fn material_of(
+        texture: Option<ModelTexture>,
+        effect: Option<TextureEffects>,
+        material: Option<String>,
+        materials: &toml::value::Table,
+        path: &str,
+    ) -> Result<(ModelTexture, Option<TextureEffects>)> {
+        let invalid_scene = |(key, message)| {
+            RaytracerError::InvalidScene(SceneError {
+                message,
+                key: Some(key),
+                position: None,
+            })
+        };
+        if let Some(error) = material_error(material.as_deref(), texture.is_some(), materials, path)
+        {
+            return Err(invalid_scene(error));
+        }
+        let material = match material {
+            Some(name) => materials[&name]
+                .clone()
+                .try_into::<ModelMaterial>()
+                .map_err(|e| invalid_scene((format!("materials.{}", name), e.to_string())))?,
+            None => return Ok((texture.unwrap(), effect)),
+        };
+        Ok((
+            texture.unwrap_or(material.texture),
+            effect.or(material.effect),
+        ))
+    }
+
+    /// Shape with the transformation of the description, kept as is if there is none
+    fn transformed(
+        shape: Box<dyn Shape>,
+        position: Option<Vec3>,
+        rotation_degree: Option<Vec3>,
+        scale: Option<ModelScale>,
+    ) -> Box<dyn Shape> {
+        if position.is_none() && rotation_degree.is_none() && scale.is_none() {
+            return shape;
+        }
+        Self::placed(Arc::from(shape), position, rotation_degree, scale)
+    }
+
+    /// Shared shape with a transformation, as the copies of an instance
+    fn placed(
+        shape: Arc<dyn Shape>,
+        position: Option<Vec3>,
+        rotation_degree: Option<Vec3>,
+        scale: Option<ModelScale>,
+    ) -> Box<dyn Shape> {
+        let rotation = rotation_degree.map_or(Mat3::ID, |angles| {
+            Mat3::rotation(Vec3::new(
+                angles.x.to_radians(),
+                angles.y.to_radians(),
+                angles.z.to_radians(),
+            ))
+        });
+        let scale = match scale {
+            None => Vec3::new(1.0, 1.0, 1.0),
+            Some(ModelScale::Uniform(scale)) => Vec3::new(scale, scale, scale),
+            Some(ModelScale::ByAxis(scale)) => scale,
+        };
+        Box::new(TransformedShape::with_axis_scales(
+            shape,
+            position.unwrap_or_default(),
+            rotation,
+            scale,
+        ))
+    }
+
+    fn with_shape(
+        texture: ModelTexture,
+        effect: Option<TextureEffects>,
+        shape: Box<dyn Shape>,
+        placeholder: bool,
+    ) -> SceneObject {
+        if placeholder {
+            return SceneObject {
+                texture: Box::new(PlainColorTexture {
+                    color: Color::MAGENTA,
+                }),
+                shape,
+                effects: Default::default(),
+            };
+        }
+        SceneObject {
+            texture: texture.into_texture(),
+            shape,
+            effects: effect.unwrap_or_default().with_preset(),
+        }
+    }
+}
+
+impl ModelShape {
+    /// Build the shape, and tell if it is a placeholder for a shape which could not be loaded
+    fn into_shape(
+        self,
+        config: &SceneConfiguration,
+        warnings: &mut Vec<String>,
+    ) -> Result<(Box<dyn Shape>, bool)> {
+        let mut placeholder = false;
+        let shape: Box<dyn Shape> = match self {
             ModelShape::Sphere(val) => Box::new(val),
+            ModelShape::DisplacedSphere {
+                center,
+                radius,
+                amplitude,
+                heightmap,
+            } => Box::new(DisplacedSphere::new(
+                Sphere { center, radius },
+                amplitude,
+                heightmap.into_heightmap(),
+            )),
             ModelShape::InfinitePlan { center, normal } => {
                 Box::new(InfinitePlan::new(center, normal))
             }
@@ -198,26 +1281,385 @@ impl DescriptionObject {
                 normal,
                 width,
             } => Box::new(SquarePlan::new(center, normal, width)),
+            ModelShape::TriangleMesh {
+                vertices,
+                triangles,
+                normals,
+                texture_coordinates,
+                shading,
+                bvh,
+            } => {
+                let mut mesh = match bvh {
+                    None => TriangleMesh::new(vertices, triangles)?,
+                    Some(bvh) => TriangleMesh::with_bvh(vertices, triangles, bvh)?,
+                };
+                if let Some(normals) = normals {
+                    mesh = mesh.with_vertex_normals(normals)?;
+                }
+                if let Some(texture_coordinates) = texture_coordinates {
+                    mesh = mesh.with_texture_coordinates(texture_coordinates)?;
+                }
+                Box::new(mesh.with_shading(shading))
+            }
+            ModelShape::AABox { min, max } => Box::new(AABox::new(min, max)),
+            ModelShape::Torus {
+                center,
+                axis,
+                major_radius,
+                minor_radius,
+            } => Box::new(Torus::new(center, axis, major_radius, minor_radius)),
+            ModelShape::Mesh { mesh, shading } => {
+                let mesh = obj::load_obj(&mesh).map(|mesh| mesh.with_shading(shading));
+                Self::loaded_or_placeholder(mesh, "mesh", config, warnings, &mut placeholder)?
+            }
+            ModelShape::Text(text) => {
+                let mesh = text.into_mesh();
+                Self::loaded_or_placeholder(mesh, "text", config, warnings, &mut placeholder)?
+            }
+            ModelShape::Svg(svg) => {
+                let mesh = svg.into_mesh();
+                Self::loaded_or_placeholder(mesh, "SVG", config, warnings, &mut placeholder)?
+            }
+            ModelShape::Terrain(terrain) => {
+                let mesh = terrain.into_mesh();
+                Self::loaded_or_placeholder(mesh, "terrain", config, warnings, &mut placeholder)?
+            }
         };
-        let texture: Box<dyn Texture> = match self.texture {
-            ModelTexture::Checked(val) => Box::new(val),
-            ModelTexture::Plain(val) => Box::new(val),
-            ModelTexture::Gradient(val) => Box::new(val),
+        Ok((shape, placeholder))
+    }
+
+    /// Shape loaded from a file given as triangles, noting the file in the assets. It is kept
+    /// as is when the file cannot be loaded, the error being reported when building the scene
+    fn prepared(self, assets: &mut Vec<PathBuf>) -> ModelShape {
+        let (path, mesh) = match self {
+            ModelShape::Mesh { ref mesh, shading } => (
+                mesh.clone(),
+                obj::load_obj(mesh).map(|mesh| mesh.with_shading(shading)),
+            ),
+            ModelShape::Text(ref text) => (text.font.clone(), text.into_mesh()),
+            ModelShape::Svg(ref svg) => (svg.svg.clone(), svg.into_mesh()),
+            ModelShape::Terrain(ref terrain) => (terrain.heightmap.clone(), terrain.into_mesh()),
+            shape => return shape,
         };
-        let effects = self.effect.unwrap_or_default();
-        SceneObject {
-            texture,
+        assets.push(path);
+        match mesh {
+            Ok(mesh) => ModelShape::TriangleMesh {
+                vertices: mesh.vertices().to_vec(),
+                triangles: mesh.triangles().to_vec(),
+                normals: mesh.vertex_normals().map(<[Vec3]>::to_vec),
+                texture_coordinates: mesh.texture_coordinates().map(<[(f64, f64)]>::to_vec),
+                shading: MeshShading::Auto,
+                bvh: mesh.bottom_level_bvh().cloned(),
+            },
+            Err(_) => self,
+        }
+    }
+
+    /// Shape of a file, or a placeholder sphere with a warning if allowed by the scene. The
+    /// triangles of no area left out of the mesh are warned about
+    fn loaded_or_placeholder(
+        loaded: Result<TriangleMesh>,
+        kind: &str,
+        config: &SceneConfiguration,
+        warnings: &mut Vec<String>,
+        placeholder: &mut bool,
+    ) -> Result<Box<dyn Shape>> {
+        match loaded {
+            Ok(mesh) => {
+                if mesh.degenerate_triangle_count() > 0 {
+                    let warning = format!(
+                        "{} has {} degenerate triangles, left out",
+                        kind,
+                        mesh.degenerate_triangle_count()
+                    );
+                    warn!("{}", warning);
+                    warnings.push(warning);
+                }
+                Ok(Box::new(mesh))
+            }
+            Err(err) if config.asset_placeholders => {
+                let warning = format!("{} replaced by a placeholder, {}", kind, err);
+                warn!("{}", warning);
+                warnings.push(warning);
+                *placeholder = true;
+                Ok(Box::new(Sphere::default()))
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Copies of an object spread over a region, each one randomly moved, scaled and rotated
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct DescriptionScatter {
+    #[serde(default)]
+    description: String,
+    region: ModelRegion,
+    #[serde(default)]
+    seed: u64,
+    /// Maximum distance added to each coordinate of a copy position
+    #[serde(default)]
+    jitter: f64,
+    #[serde(default = "default_scale_range")]
+    scale: [f64; 2],
+    /// Maximum angle of rotation around each axis, in both directions
+    #[serde(default)]
+    rotation_degree: Vec3,
+    /// Object description, its shape being placed relative to the origin
+    object: toml::Value,
+}
+
+impl DescriptionScatter {
+    /// Scales must be positive, and a surface region must have a normal, the errors being
+    /// given at `path`, the path of the scatter table
+    fn check(&self, path: &str) -> Result<()> {
+        let invalid_scene = |key: &str, message: String| {
+            Err(RaytracerError::InvalidScene(SceneError {
+                message,
+                key: Some(format!("{}.{}", path, key)),
+                position: None,
+            }))
+        };
+        if let Some(scale) = self.scale.iter().find(|s| !(s.is_finite() && **s > 0.0)) {
+            return invalid_scene("scale", format!("{} is not a positive scale", scale));
+        }
+        if let ModelRegion::Surface { normal, .. } = self.region {
+            if normal.is_null() {
+                return invalid_scene("region.normal", String::from("normal is null"));
+            }
+        }
+        Ok(())
+    }
+
+    fn into_scene_objects(
+        self,
+        config: &SceneConfiguration,
+        materials: &toml::value::Table,
+        path: &str,
+        warnings: &mut Vec<String>,
+    ) -> Result<Vec<SceneObject>> {
+        self.check(path)?;
+        let object_path = format!("{}.object", path);
+        let parse_object = || {
+            self.object
+                .clone()
+                .try_into::<DescriptionObject>()
+                .map_err(|e| RaytracerError::ParsingError(e.to_string()))
+        };
+        // The shape is only built once, every copy sharing it
+        let object = parse_object()?;
+        let (shape, placeholder) = object.shape.into_shape(config, warnings)?;
+        let shape = DescriptionObject::transformed(
             shape,
-            effects,
+            object.position,
+            object.rotation_degree,
+            object.scale,
+        );
+        let shape: Arc<dyn Shape> = Arc::from(shape);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut objects = Vec::new();
+        for position in self.region.positions(&mut rng) {
+            let mut symmetric = |max: f64| max * (2.0 * rng.gen::<f64>() - 1.0);
+            let jitter = Vec3::new(
+                symmetric(self.jitter),
+                symmetric(self.jitter),
+                symmetric(self.jitter),
+            );
+            let position = position + jitter;
+            let rotation = Mat3::rotation(Vec3::new(
+                symmetric(self.rotation_degree.x).to_radians(),
+                symmetric(self.rotation_degree.y).to_radians(),
+                symmetric(self.rotation_degree.z).to_radians(),
+            ));
+            let [scale_min, scale_max] = self.scale;
+            let scale = scale_min + rng.gen::<f64>() * (scale_max - scale_min);
+            let copy = parse_object()?;
+            let (texture, effect) = DescriptionObject::material_of(
+                copy.texture,
+                copy.effect,
+                copy.material,
+                materials,
+                &object_path,
+            )?;
+            let shape = TransformedShape::new(Arc::clone(&shape), position, rotation, scale);
+            objects.push(DescriptionObject::with_shape(
+                texture,
+                effect,
+                Box::new(shape),
+                placeholder,
+            ));
+        }
+        Ok(objects)
+    }
+}
+
+/// Objects described once and placed several times, the copies sharing their shapes
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct DescriptionInstances {
+    #[serde(default)]
+    description: String,
+    /// Object descriptions of each copy, their shapes being placed relative to the origin
+    object: Vec<toml::Value>,
+    placement: Vec<ModelPlacement>,
+}
+
+/// Transformation of a copy of the instanced objects
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ModelPlacement {
+    #[serde(default)]
+    position: Option<Vec3>,
+    #[serde(default)]
+    rotation_degree: Option<Vec3>,
+    #[serde(default)]
+    scale: Option<ModelScale>,
+}
+
+impl DescriptionInstances {
+    fn into_scene_objects(
+        self,
+        config: &SceneConfiguration,
+        materials: &toml::value::Table,
+        path: &str,
+        warnings: &mut Vec<String>,
+    ) -> Result<Vec<SceneObject>> {
+        let parse_object = |object: &toml::Value| {
+            object
+                .clone()
+                .try_into::<DescriptionObject>()
+                .map_err(|e| RaytracerError::ParsingError(e.to_string()))
+        };
+        // The shapes are only built once, every copy sharing them
+        let mut shapes = Vec::new();
+        for object in &self.object {
+            let object = parse_object(object)?;
+            let (shape, placeholder) = object.shape.into_shape(config, warnings)?;
+            let shape = DescriptionObject::transformed(
+                shape,
+                object.position,
+                object.rotation_degree,
+                object.scale,
+            );
+            shapes.push((Arc::<dyn Shape>::from(shape), placeholder));
+        }
+        let mut objects = Vec::new();
+        for placement in &self.placement {
+            for (index, (shape, placeholder)) in shapes.iter().enumerate() {
+                let copy = parse_object(&self.object[index])?;
+                let (texture, effect) = DescriptionObject::material_of(
+                    copy.texture,
+                    copy.effect,
+                    copy.material,
+                    materials,
+                    &format!("{}.object[{}]", path, index),
+                )?;
+                let shape = DescriptionObject::placed(
+                    Arc::clone(shape),
+                    placement.position,
+                    placement.rotation_degree,
+                    placement.scale,
+                );
+                objects.push(DescriptionObject::with_shape(
+                    texture,
+                    effect,
+                    shape,
+                    *placeholder,
+                ));
+            }
+        }
+        Ok(objects)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type")]
+enum ModelRegion {
+    /// Regularly spaced cells between two corners, one copy at the center of each cell
+    Grid {
+        min: Vec3,
+        max: Vec3,
+        counts: [u32; 3],
+    },
+    /// Random positions on a rectangle
+    Surface {
+        center: Vec3,
+        normal: Vec3,
+        width: f64,
+        height: f64,
+        count: u32,
+    },
+    /// Random positions in a box
+    Volume { min: Vec3, max: Vec3, count: u32 },
+}
+
+impl ModelRegion {
+    fn positions(&self, rng: &mut StdRng) -> Vec<Vec3> {
+        let lerp = |min: f64, max: f64, ratio: f64| min + ratio * (max - min);
+        match *self {
+            ModelRegion::Grid { min, max, counts } => {
+                let [count_x, count_y, count_z] = counts;
+                let cell_center = |i: u32, count: u32| (i as f64 + 0.5) / count as f64;
+                let mut positions = Vec::new();
+                for i in 0..count_x {
+                    for j in 0..count_y {
+                        for k in 0..count_z {
+                            positions.push(Vec3::new(
+                                lerp(min.x, max.x, cell_center(i, count_x)),
+                                lerp(min.y, max.y, cell_center(j, count_y)),
+                                lerp(min.z, max.z, cell_center(k, count_z)),
+                            ));
+                        }
+                    }
+                }
+                positions
+            }
+            ModelRegion::Surface {
+                center,
+                normal,
+                width,
+                height,
+                count,
+            } => {
+                let transform =
+                    Mat3::transformation_between(Vec3::new(0.0, 1.0, 0.0), normal.normalize());
+                let u_vec = transform * Vec3::new(1.0, 0.0, 0.0);
+                let v_vec = transform * Vec3::new(0.0, 0.0, 1.0);
+                (0..count)
+                    .map(|_| {
+                        let u = width * (rng.gen::<f64>() - 0.5);
+                        let v = height * (rng.gen::<f64>() - 0.5);
+                        center + u * u_vec + v * v_vec
+                    })
+                    .collect()
+            }
+            ModelRegion::Volume { min, max, count } => (0..count)
+                .map(|_| {
+                    Vec3::new(
+                        lerp(min.x, max.x, rng.gen()),
+                        lerp(min.y, max.y, rng.gen()),
+                        lerp(min.z, max.z, rng.gen()),
+                    )
+                })
+                .collect(),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "type")]
 enum ModelShape {
     Sphere(Sphere),
+    DisplacedSphere {
+        center: Vec3,
+        radius: f64,
+        amplitude: f64,
+        #[serde(default)]
+        heightmap: ModelHeightmap,
+    },
     InfinitePlan {
         center: Vec3,
         normal: Vec3,
@@ -227,24 +1669,124 @@ enum ModelShape {
         normal: Vec3,
         width: f64,
     },
+    TriangleMesh {
+        vertices: Vec<Vec3>,
+        triangles: Vec<[usize; 3]>,
+        #[serde(default)]
+        normals: Option<Vec<Vec3>>,
+        /// One pair of coordinates per vertex
+        #[serde(default)]
+        texture_coordinates: Option<Vec<(f64, f64)>>,
+        #[serde(default)]
+        shading: MeshShading,
+        /// Hierarchy over the triangles, written in scene caches so it is not built again
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bvh: Option<Bvh>,
+    },
+    #[serde(rename = "box")]
+    AABox {
+        min: Vec3,
+        max: Vec3,
+    },
+    Torus {
+        center: Vec3,
+        axis: Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    Text(Text3D),
+    Svg(SvgExtrusion),
+    Terrain(Terrain),
+    /// Wavefront OBJ file, relative to the current directory
+    Mesh {
+        mesh: PathBuf,
+        #[serde(default)]
+        shading: MeshShading,
+    },
+}
+
+/// Texture with the transformation of its coordinates, given in the same table
+#[derive(Debug, Deserialize, Serialize)]
+struct ModelTexture {
+    #[serde(flatten)]
+    kind: ModelTextureKind,
+    #[serde(flatten)]
+    transform: TextureTransform,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "type")]
-enum ModelTexture {
+enum ModelTextureKind {
     Checked(CheckedPattern),
     Plain(PlainColorTexture),
     Gradient(GradientColorTexture),
+    Planet(Box<PlanetTexture>),
+    Clouds(CloudTexture),
+    Image(ImageTexture),
+}
+
+impl ModelTexture {
+    fn into_texture(self) -> Box<dyn Texture> {
+        let texture: Box<dyn Texture> = match self.kind {
+            ModelTextureKind::Checked(val) => Box::new(val),
+            ModelTextureKind::Plain(val) => Box::new(val),
+            ModelTextureKind::Gradient(val) => Box::new(val),
+            ModelTextureKind::Planet(val) => val,
+            ModelTextureKind::Clouds(val) => Box::new(val),
+            ModelTextureKind::Image(val) => Box::new(val),
+        };
+        if self.transform.is_identity() {
+            return texture;
+        }
+        Box::new(TransformedTexture {
+            texture,
+            transform: self.transform,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type")]
+enum ModelHeightmap {
+    Noise { frequency: f64, octaves: u32 },
+    Texture { texture: ModelTexture },
 }
 
-#[derive(Debug, Deserialize)]
+impl Default for ModelHeightmap {
+    fn default() -> Self {
+        ModelHeightmap::Noise {
+            frequency: 2.0,
+            octaves: 4,
+        }
+    }
+}
+
+impl ModelHeightmap {
+    fn into_heightmap(self) -> Heightmap {
+        match self {
+            ModelHeightmap::Noise { frequency, octaves } => Heightmap::Noise { frequency, octaves },
+            ModelHeightmap::Texture { texture } => Heightmap::Texture(texture.into_texture()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 pub(crate) enum ModelColor {
     ByString(String),
     ByRGB([f64; 3]),
 }
 
+fn default_scale_range() -> [f64; 2] {
+    [1.0, 1.0]
+}
+
+fn default_fisheye_field_of_view() -> f64 {
+    180.0
+}
+
 fn default_perspective_angle() -> f64 {
     std::f64::consts::PI / 8.0
 }
@@ -253,6 +1795,9 @@ fn default_perspective_angle() -> f64 {
 mod tests {
 
     use super::*;
+    use crate::primitives::Ray;
+    use crate::renderer::ToneMappingOperator;
+    use crate::scene::{LightBounce, LightContribution};
 
     const INVALID_TOML: &str = r##"invalid_toml"##;
 
@@ -261,4 +1806,424 @@ mod tests {
         let result = parse_scene_description(INVALID_TOML);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn light_path_filter_in_config() {
+        let scene_toml = r##"
+            [config.light_paths]
+            contributions = ["diffuse"]
+            bounces = []
+            direct = false
+
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 9
+
+            [[light]]
+            type = "point"
+            source = [0, 10, 0]
+            color = "white"
+
+            [[object]]
+            description = ""
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            texture.type = "plain"
+        "##;
+        let scene = parse_scene_description(scene_toml).unwrap();
+        let light_paths = &scene.config.light_paths;
+        assert!(!light_paths.accepts(LightContribution::Diffuse, 0));
+        assert!(light_paths.accepts(LightContribution::Diffuse, 1));
+        assert!(!light_paths.accepts(LightContribution::Ambient, 1));
+        assert!(!light_paths.follows(LightBounce::Reflection));
+    }
+
+    #[test]
+    fn tone_mapping_in_config() {
+        let scene_toml = r##"
+            [config.tone_mapping]
+            operator = "aces"
+            exposure = -1.5
+
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 9
+
+            [[light]]
+            type = "point"
+            source = [0, 10, 0]
+            color = "white"
+        "##;
+        let scene = parse_scene_description(scene_toml).unwrap();
+        let tone_mapping = &scene.config.tone_mapping;
+        assert_eq!(tone_mapping.operator, ToneMappingOperator::Aces);
+        assert_eq!(tone_mapping.exposure, -1.5);
+        assert_eq!(tone_mapping.gamma, 1.0);
+    }
+
+    #[test]
+    fn image_texture_is_read_from_its_file() {
+        let path = std::env::temp_dir().join(format!("image_texture-{}.png", std::process::id()));
+        image::RgbImage::from_fn(2, 2, |_, y| match y {
+            0 => image::Rgb([255, 0, 0]),
+            _ => image::Rgb([0, 0, 255]),
+        })
+        .save(&path)
+        .unwrap();
+        let scene_toml = format!(
+            r##"
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 9
+
+            [[object]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            texture.type = "image"
+            texture.path = {:?}
+            "##,
+            path
+        );
+        let scene = parse_scene_description(&scene_toml).unwrap();
+        let texture = &scene.objects[0].texture;
+        assert_eq!(texture.color_at(0.25, 0.75).to_srgb8(), [255, 0, 0]);
+        assert_eq!(texture.color_at(0.75, 0.25).to_srgb8(), [0, 0, 255]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn gamma_must_be_positive() {
+        let scene_toml = |gamma: &str| {
+            format!(
+                r##"
+                [config.tone_mapping]
+                gamma = {}
+
+                [camera]
+                type = "orthogonal"
+                eye = [0, 0, -10]
+                look_at = [0, 0, 0]
+                width = 16
+                height = 9
+                "##,
+                gamma
+            )
+        };
+        assert!(parse_scene_description(&scene_toml("2.2")).is_ok());
+        for gamma in &["0", "-1", "inf", "nan"] {
+            match parse_scene_description(&scene_toml(gamma)) {
+                Err(RaytracerError::InvalidScene(error)) => {
+                    assert_eq!(error.key.as_deref(), Some("config.tone_mapping.gamma"));
+                }
+                other => panic!("Gamma {} accepted: {:?}", gamma, other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn light_intensity_follows_frame() {
+        let scene_toml = r##"
+            [config]
+            frames_per_second = 10
+
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 9
+
+            [[light]]
+            type = "point"
+            source = [0, 10, 0]
+            color = "white"
+            intensity_keys = [[0, 0], [2, 1]]
+            flicker = { amplitude = 0 }
+        "##;
+        let red_at = |frame: Option<u32>| {
+            let scene =
+                parse_scene_description_at_frame(scene_toml, SceneFormat::Toml, frame.into())
+                    .unwrap();
+            let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+            scene.lights[0].color_for_ray(ray).red()
+        };
+        assert_eq!(red_at(None), 0.0);
+        assert!((red_at(Some(5)) - 0.25).abs() < 1e-9);
+        assert_eq!(red_at(Some(30)), 1.0);
+    }
+
+    #[test]
+    fn time_replaces_the_one_of_the_frame() {
+        let scene_toml = r##"
+            [config]
+            frame = 30
+            time = 1
+
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 9
+
+            [[light]]
+            type = "point"
+            source = [0, 10, 0]
+            color = "white"
+            intensity_keys = [[0, 0], [2, 1]]
+        "##;
+        let red_at = |frame: AnimationFrame| {
+            let scene = parse_scene_description_at_frame(scene_toml, SceneFormat::Toml, frame);
+            let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+            scene.unwrap().lights[0].color_for_ray(ray).red()
+        };
+        assert!((red_at(AnimationFrame::default()) - 0.5).abs() < 1e-9);
+        // An animation frame gives its own time, unless a time is given with it
+        assert!((red_at(AnimationFrame::from(12)) - 0.25).abs() < 1e-9);
+        let at_time = |time| AnimationFrame::from(12).with_time(time);
+        assert!((red_at(at_time(1.5)) - 0.75).abs() < 1e-9);
+        assert!((red_at(AnimationFrame::default().with_time(0.5)) - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn object_is_scaled_rotated_then_moved() {
+        let scene_toml = r##"
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 9
+
+            [[object]]
+            type = "box"
+            min = [-1, -1, -1]
+            max = [1, 1, 1]
+            scale = [3, 1, 1]
+            rotation_degree = [0, 0, 90]
+            position = [0, 10, 0]
+            texture.type = "plain"
+        "##;
+        let scene = parse_scene_description(scene_toml).unwrap();
+        let aabb = scene.objects[0].aabb().unwrap();
+        assert!(aabb.min.distance(Vec3::new(-1.0, 7.0, -1.0)) < 1e-9);
+        assert!(aabb.max.distance(Vec3::new(1.0, 13.0, 1.0)) < 1e-9);
+    }
+
+    #[test]
+    fn water_waves_follow_the_frame() {
+        let scene_toml = r##"
+            [config]
+            frame = 48
+
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 9
+
+            [[object]]
+            type = "infinite_plan"
+            center = [0, 0, 0]
+            normal = [0, 1, 0]
+            texture.type = "plain"
+            effect.water.wind_speed = 3
+
+            [[object]]
+            type = "infinite_plan"
+            center = [0, -1, 0]
+            normal = [0, 1, 0]
+            texture.type = "plain"
+            effect.water.time = 0.5
+        "##;
+        let scene = parse_scene_description(scene_toml).unwrap();
+        let time_of = |index: usize| scene.objects[index].effects().water.as_ref().unwrap().time;
+        assert_eq!(time_of(0), Some(2.0));
+        assert_eq!(time_of(1), Some(0.5));
+    }
+
+    #[test]
+    fn auto_stage_adds_ground_and_light() {
+        let scene_toml = r##"
+            [config]
+            auto_stage = true
+
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 9
+
+            [[object]]
+            type = "sphere"
+            center = [0, 2, 0]
+            radius = 1
+            texture.type = "plain"
+        "##;
+        let scene = parse_scene_description(scene_toml).unwrap();
+        assert_eq!(scene.objects.len(), 2);
+        assert_eq!(scene.lights.len(), 1);
+        assert!(scene.objects[1].effects().shadow_catcher);
+        assert!(scene.lights[0].source().y > 3.0);
+    }
+
+    #[test]
+    fn three_point_lights_surround_objects() {
+        let scene_toml = r##"
+            [config]
+            three_point_lights = true
+
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 9
+
+            [[object]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            texture.type = "plain"
+        "##;
+        let scene = parse_scene_description(scene_toml).unwrap();
+        assert_eq!(scene.lights.len(), 3);
+        let sources: Vec<_> = scene.lights.iter().map(|light| light.source()).collect();
+        // Key and fill lights on the camera side, on both sides, the rim one behind
+        assert!(sources[0].z < 0.0 && sources[1].z < 0.0 && sources[2].z > 0.0);
+        assert!(sources[0].x * sources[1].x < 0.0);
+    }
+
+    #[test]
+    fn panorama_camera_has_two_by_one_ratio() {
+        let scene_toml = r##"
+            [camera]
+            type = "equirectangular"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+
+            [[light]]
+            type = "point"
+            source = [0, 10, 0]
+            color = "white"
+        "##;
+        let scene = parse_scene_description(scene_toml).unwrap();
+        assert_eq!(scene.camera.size_ratio(), 2.0);
+    }
+
+    #[test]
+    fn transparency_index_in_effects() {
+        let scene_toml = r##"
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 9
+
+            [[light]]
+            type = "point"
+            source = [0, 10, 0]
+            color = "white"
+
+            [[object]]
+            description = ""
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            texture.type = "plain"
+            effect.transparency.index = 1.5
+        "##;
+        let scene = parse_scene_description(scene_toml).unwrap();
+        let transparency = scene.objects[0].effects().transparency.as_ref().unwrap();
+        assert_eq!(transparency.refractive_index, 1.5);
+    }
+
+    #[test]
+    fn variation_differs_between_copies() {
+        let sphere = r##"
+            [[object]]
+            description = ""
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            texture.type = "plain"
+            texture.color = [0.5, 0.5, 0.5]
+            effect.variation.color_jitter = 0.3
+        "##;
+        let scene_toml = format!(
+            r##"
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 9
+
+            [[light]]
+            type = "point"
+            source = [0, 10, 0]
+            color = "white"
+            {}
+            {}
+            "##,
+            sphere, sphere
+        );
+        let scene = parse_scene_description(&scene_toml).unwrap();
+        let point = Vec3::new(0.0, 0.0, -1.0);
+        let color_at = |index: usize| {
+            let object = &scene.objects[index];
+            let context = object.shading_context(point, Vec3::default(), 0.0, index);
+            object.color_in(&context.unwrap())
+        };
+        let (color_1, color_2) = (color_at(0), color_at(1));
+        assert_ne!(color_1.red(), color_2.red());
+        assert!((color_1.red() - 0.5).abs() <= 0.3);
+    }
+
+    #[test]
+    fn light_keys_at_no_time_are_error() {
+        let scene_toml = |keys: &str| {
+            format!(
+                r##"
+                [camera]
+                type = "orthogonal"
+                eye = [0, 0, -10]
+                look_at = [0, 0, 0]
+                width = 16
+                height = 9
+
+                [[light]]
+                type = "point"
+                source = [0, 10, 0]
+                color = "white"
+                intensity_keys = {}
+                "##,
+                keys
+            )
+        };
+        // Keys out of order are sorted
+        let scene = parse_scene_description(&scene_toml("[[2, 1], [0, 0]]")).unwrap();
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(scene.lights[0].color_for_ray(ray).red(), 0.0);
+        match parse_scene_description(&scene_toml("[[nan, 1], [0, 0]]")) {
+            Err(RaytracerError::InvalidScene(error)) => {
+                assert_eq!(error.key.as_deref(), Some("light[0].intensity_keys"));
+            }
+            other => panic!("NaN key time accepted: {:?}", other.map(|_| ())),
+        }
+    }
 }
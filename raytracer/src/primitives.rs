@@ -22,23 +22,61 @@ OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE.
 */
 
+use crate::bvh::{Aabb, Bvh};
+use crate::matrix::Mat4;
+use crate::result::{RaytracerError, Result};
+use crate::stats::{count, Event};
+use crate::textures::Texture;
+use crate::utils::{fractal_noise, polynomial_roots};
 use crate::vector::{Mat3, Vec3};
 use crate::UnitInterval;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::f64::consts::PI;
 use std::fmt::Debug;
+use std::ops::Range;
+use std::sync::Arc;
 
 pub trait Shape: Sync + Send {
     fn check_collision(&self, ray: &Ray) -> Option<Vec3>;
     fn normal_at(&self, point: Vec3) -> Option<Vec3>;
     fn surface_mapping_at(&self, point: Vec3) -> Option<(UnitInterval, UnitInterval)>;
+    /// Bounding box of the shape, none if the shape is unbounded
+    fn aabb(&self) -> Option<Aabb>;
+    /// Memory used by the shape, in bytes, without its bottom level hierarchy
+    fn memory_size(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
+    /// Bottom level hierarchy over the parts of the shape, in the space of the shape,
+    /// none if the shape is searched as a whole
+    fn bottom_level_bvh(&self) -> Option<&Bvh> {
+        None
+    }
+    /// Point of the world in the space of the shape, where it stays when the shape is
+    /// moved, so the same surface point can be found at another animation frame
+    fn local_point(&self, point: Vec3) -> Vec3 {
+        point
+    }
+    /// Point of the space of the shape in the world, the inverse of `local_point`
+    fn world_point(&self, point: Vec3) -> Vec3 {
+        point
+    }
+    /// How far the shape has moved at a time, in seconds from the frame, where it is
+    /// hit by the rays of that time
+    fn displacement_at(&self, _time: f64) -> Vec3 {
+        Vec3::default()
+    }
 }
 
 #[derive(Debug)]
+#[allow(clippy::manual_non_exhaustive)]
 pub struct Ray {
     pub source: Vec3,
     /// The direction is normalized
     pub direction: Vec3,
+    /// Time at which the ray sees the scene, in seconds from the frame, moving objects
+    /// being met where they are at that time
+    pub time: f64,
     _use_constructor: (),
 }
 
@@ -47,6 +85,7 @@ impl Ray {
         Ray {
             source,
             direction: direction.normalize(),
+            time: 0.0,
             _use_constructor: (),
         }
     }
@@ -55,6 +94,7 @@ impl Ray {
         Ray {
             source,
             direction: (destination - source).normalize(),
+            time: 0.0,
             _use_constructor: (),
         }
     }
@@ -63,9 +103,15 @@ impl Ray {
         Ray {
             source: self.source + 1e-12 * self.direction,
             direction: self.direction,
+            time: self.time,
             _use_constructor: (),
         }
     }
+
+    /// Same ray, seeing the scene at another time
+    pub fn at_time(self, time: f64) -> Ray {
+        Ray { time, ..self }
+    }
 }
 
 #[derive(Debug)]
@@ -120,6 +166,10 @@ impl Shape for InfinitePlan {
         let v = positive_space(v);
         Some((u, v))
     }
+
+    fn aabb(&self) -> Option<Aabb> {
+        None
+    }
 }
 
 #[derive(Debug)]
@@ -187,9 +237,19 @@ impl Shape for SquarePlan {
         let v = (local_y + radius) / self.width;
         Some((u, v))
     }
+
+    fn aabb(&self) -> Option<Aabb> {
+        let radius = self.width / 2.0;
+        let corners = [(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)];
+        Aabb::from_points(
+            corners
+                .iter()
+                .map(|&(x, y)| self.center + (x * radius) * self.u_vec + (y * radius) * self.v_vec),
+        )
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f64,
@@ -241,6 +301,846 @@ impl Shape for Sphere {
         let v = 0.5 - unit_point.y.asin() / PI;
         Some((u, v))
     }
+
+    fn aabb(&self) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - radius, self.center + radius))
+    }
+}
+
+/// Heights in [0, 1] displacing the surface of a sphere
+pub enum Heightmap {
+    /// Fractal noise sampled on the unit sphere, without seams nor pinched poles
+    Noise { frequency: f64, octaves: u32 },
+    /// Brightness of a texture along the surface mapping of the sphere
+    Texture(Box<dyn Texture>),
+}
+
+/// Sphere whose radius is raised along each direction by a heightmap, giving rocks and
+/// planets from a single primitive. The displaced surface has no closed form, so rays march
+/// through the shell the heights can reach then refine the hit by bisection.
+pub struct DisplacedSphere {
+    sphere: Sphere,
+    /// Radius added by the highest points, negative to dig into the sphere
+    amplitude: f64,
+    heightmap: Heightmap,
+}
+
+impl DisplacedSphere {
+    /// Samples taken along a ray crossing the shell, thinner bumps may be missed
+    const STEPS: u32 = 128;
+    const BISECTION_STEPS: u32 = 52;
+
+    pub fn new(sphere: Sphere, amplitude: f64, heightmap: Heightmap) -> Self {
+        DisplacedSphere {
+            sphere,
+            amplitude,
+            heightmap,
+        }
+    }
+
+    fn height_at(&self, direction: Vec3) -> f64 {
+        match &self.heightmap {
+            Heightmap::Noise { frequency, octaves } => {
+                fractal_noise(*frequency * direction, *octaves)
+            }
+            Heightmap::Texture(texture) => {
+                let (u, v) = self
+                    .sphere
+                    .surface_mapping_at(self.sphere.center + direction)
+                    .unwrap_or((0.5, 0.5));
+                let color = texture.color_at(u, v);
+                0.2126 * color.red() + 0.7152 * color.green() + 0.0722 * color.blue()
+            }
+        }
+    }
+
+    /// Distance from the point to the surface along the radius, negative inside
+    fn radial_distance(&self, point: Vec3) -> f64 {
+        let radial = Vec3::between_points(self.sphere.center, point);
+        let distance = radial.norm();
+        if distance == 0.0 {
+            return -self.sphere.radius.min(self.sphere.radius + self.amplitude);
+        }
+        let direction = (1.0 / distance) * radial;
+        distance - (self.sphere.radius + self.amplitude * self.height_at(direction))
+    }
+
+    fn outer_radius(&self) -> f64 {
+        self.sphere.radius + self.amplitude.max(0.0)
+    }
+}
+
+impl Shape for DisplacedSphere {
+    fn check_collision(&self, ray: &Ray) -> Option<Vec3> {
+        let direction = ray.direction.normalize();
+        // Part of the ray inside a sphere bounding the shell, a bit larger so that the
+        // highest points are crossed even with rounding errors
+        let outer_radius = (1.0 + 1e-6) * self.outer_radius();
+        let to_center = Vec3::between_points(ray.source, self.sphere.center);
+        let middle = to_center.dot_product(direction);
+        let center_distance_square = to_center.dot_product(to_center) - middle * middle;
+        if center_distance_square > outer_radius * outer_radius {
+            return None;
+        }
+        let half_chord = (outer_radius * outer_radius - center_distance_square).sqrt();
+        let (enter, exit) = ((middle - half_chord).max(1e-9), middle + half_chord);
+        if exit <= enter {
+            return None;
+        }
+        let distance_at = |t: f64| self.radial_distance(ray.source + t * direction);
+        let starts_inside = distance_at(enter) < 0.0;
+        let step = (exit - enter) / Self::STEPS as f64;
+        let mut before = enter;
+        for index in 1..=Self::STEPS {
+            let mut after = enter + index as f64 * step;
+            if (distance_at(after) < 0.0) != starts_inside {
+                for _ in 0..Self::BISECTION_STEPS {
+                    let middle = 0.5 * (before + after);
+                    if (distance_at(middle) < 0.0) == starts_inside {
+                        before = middle;
+                    } else {
+                        after = middle;
+                    }
+                }
+                return Some(ray.source + (0.5 * (before + after)) * direction);
+            }
+            before = after;
+        }
+        None
+    }
+
+    /// Gradient of the radial distance, by central differences
+    fn normal_at(&self, point: Vec3) -> Option<Vec3> {
+        let delta = 1e-5 * self.sphere.radius;
+        let slope = |axis: Vec3| {
+            self.radial_distance(point + delta * axis) - self.radial_distance(point - delta * axis)
+        };
+        let gradient = Vec3::new(
+            slope(Vec3::new(1.0, 0.0, 0.0)),
+            slope(Vec3::new(0.0, 1.0, 0.0)),
+            slope(Vec3::new(0.0, 0.0, 1.0)),
+        );
+        if gradient.is_null() {
+            return None;
+        }
+        Some(gradient.normalize())
+    }
+
+    fn surface_mapping_at(&self, point: Vec3) -> Option<(UnitInterval, UnitInterval)> {
+        self.sphere.surface_mapping_at(point)
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        let radius = self.outer_radius();
+        let radius = Vec3::new(radius, radius, radius);
+        Some(Aabb::new(
+            self.sphere.center - radius,
+            self.sphere.center + radius,
+        ))
+    }
+
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + match &self.heightmap {
+                Heightmap::Noise { .. } => 0,
+                Heightmap::Texture(texture) => texture.memory_size(),
+            }
+    }
+}
+
+/// Box whose faces are aligned with the axes, given by two opposite corners
+#[derive(Debug)]
+pub struct AABox {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl AABox {
+    pub fn new(corner_1: Vec3, corner_2: Vec3) -> Self {
+        AABox {
+            min: Vec3::new(
+                corner_1.x.min(corner_2.x),
+                corner_1.y.min(corner_2.y),
+                corner_1.z.min(corner_2.z),
+            ),
+            max: Vec3::new(
+                corner_1.x.max(corner_2.x),
+                corner_1.y.max(corner_2.y),
+                corner_1.z.max(corner_2.z),
+            ),
+        }
+    }
+
+    fn axis(vector: Vec3, axis: usize) -> f64 {
+        match axis {
+            0 => vector.x,
+            1 => vector.y,
+            _ => vector.z,
+        }
+    }
+
+    /// Axis of the face the point is the nearest to, and if it is the max side
+    fn nearest_face(&self, point: Vec3) -> (usize, bool) {
+        let mut nearest = (0, false);
+        let mut nearest_distance = f64::MAX;
+        for axis in 0..3 {
+            let value = Self::axis(point, axis);
+            for &(bound, is_max) in &[
+                (Self::axis(self.min, axis), false),
+                (Self::axis(self.max, axis), true),
+            ] {
+                let distance = (value - bound).abs();
+                if distance < nearest_distance {
+                    nearest_distance = distance;
+                    nearest = (axis, is_max);
+                }
+            }
+        }
+        nearest
+    }
+}
+
+impl Shape for AABox {
+    fn check_collision(&self, ray: &Ray) -> Option<Vec3> {
+        // Slab method, intersecting the ray with the three pairs of parallel planes
+        let mut t_enter = f64::NEG_INFINITY;
+        let mut t_leave = f64::INFINITY;
+        for axis in 0..3 {
+            let source = Self::axis(ray.source, axis);
+            let direction = Self::axis(ray.direction, axis);
+            let (min, max) = (Self::axis(self.min, axis), Self::axis(self.max, axis));
+            if direction.abs() < 1e-12 {
+                if source < min || source > max {
+                    return None;
+                }
+                continue;
+            }
+            let t_1 = (min - source) / direction;
+            let t_2 = (max - source) / direction;
+            t_enter = t_enter.max(t_1.min(t_2));
+            t_leave = t_leave.min(t_1.max(t_2));
+        }
+        if t_enter > t_leave || t_leave <= 0.0 {
+            return None;
+        }
+        // From inside the box, the ray hits the face it leaves through
+        let t = if t_enter > 0.0 { t_enter } else { t_leave };
+        Some(ray.source + t * ray.direction)
+    }
+
+    fn normal_at(&self, point: Vec3) -> Option<Vec3> {
+        let (axis, is_max) = self.nearest_face(point);
+        let sign = if is_max { 1.0 } else { -1.0 };
+        Some(match axis {
+            0 => Vec3::new(sign, 0.0, 0.0),
+            1 => Vec3::new(0.0, sign, 0.0),
+            _ => Vec3::new(0.0, 0.0, sign),
+        })
+    }
+
+    /// Each face is mapped on the whole texture, along the two other axes
+    fn surface_mapping_at(&self, point: Vec3) -> Option<(UnitInterval, UnitInterval)> {
+        let (axis, _) = self.nearest_face(point);
+        let local = |other_axis: usize| {
+            let min = Self::axis(self.min, other_axis);
+            let size = Self::axis(self.max, other_axis) - min;
+            if size > 0.0 {
+                ((Self::axis(point, other_axis) - min) / size).clamp(0.0, 1.0)
+            } else {
+                0.0
+            }
+        };
+        Some((local((axis + 1) % 3), local((axis + 2) % 3)))
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+}
+
+/// Ring around an axis, made of the points at `minor_radius` from the circle of radius
+/// `major_radius` around the center
+pub struct Torus {
+    center: Vec3,
+    major_radius: f64,
+    minor_radius: f64,
+    /// Rotation bringing the axis of the torus on the Y axis
+    to_local: Mat3,
+    to_world: Mat3,
+}
+
+impl Torus {
+    pub fn new(center: Vec3, axis: Vec3, major_radius: f64, minor_radius: f64) -> Self {
+        let to_world = Mat3::transformation_between(Vec3::new(0.0, 1.0, 0.0), axis.normalize());
+        Torus {
+            center,
+            major_radius,
+            minor_radius,
+            to_local: to_world.transpose(),
+            to_world,
+        }
+    }
+
+    fn local_point(&self, point: Vec3) -> Vec3 {
+        self.to_local * (point - self.center)
+    }
+}
+
+impl Shape for Torus {
+    fn check_collision(&self, ray: &Ray) -> Option<Vec3> {
+        let (major, minor) = (self.major_radius, self.minor_radius);
+        let direction = self.to_local * ray.direction.normalize();
+        // Starting next to the torus keeps the coefficients small for far rays
+        let source = self.local_point(ray.source);
+        let skipped = (-source.dot_product(direction) - (major + minor)).max(0.0);
+        let origin = source + skipped * direction;
+        // (|p|² + R² - r²)² = 4R²(x² + z²), with p = origin + t * direction
+        let e = origin.dot_product(origin) + major * major - minor * minor;
+        let f = origin.dot_product(direction);
+        let four_major_square = 4.0 * major * major;
+        let coefficients = [
+            e * e - four_major_square * (origin.x * origin.x + origin.z * origin.z),
+            4.0 * e * f
+                - 2.0 * four_major_square * (origin.x * direction.x + origin.z * direction.z),
+            4.0 * f * f + 2.0 * e
+                - four_major_square * (direction.x * direction.x + direction.z * direction.z),
+            4.0 * f,
+            1.0,
+        ];
+        let max_distance = origin.norm() + major + minor;
+        let min_distance = if skipped > 0.0 { 0.0 } else { 1e-9 };
+        let t = *polynomial_roots(&coefficients, min_distance, max_distance).first()?;
+        Some(ray.source + (skipped + t) * ray.direction.normalize())
+    }
+
+    fn normal_at(&self, point: Vec3) -> Option<Vec3> {
+        let local = self.local_point(point);
+        let ring = Vec3::new(local.x, 0.0, local.z);
+        if ring.is_null() {
+            return None;
+        }
+        let ring_point = self.major_radius * ring.normalize();
+        Some((self.to_world * (local - ring_point)).normalize())
+    }
+
+    fn surface_mapping_at(&self, point: Vec3) -> Option<(UnitInterval, UnitInterval)> {
+        let local = self.local_point(point);
+        let ring_distance = (local.x * local.x + local.z * local.z).sqrt();
+        let u = 0.5 + local.z.atan2(local.x) / (2.0 * PI);
+        let v = 0.5 + local.y.atan2(ring_distance - self.major_radius) / (2.0 * PI);
+        Some((u, v))
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        let (width, height) = (self.major_radius + self.minor_radius, self.minor_radius);
+        let corners = (0..8).map(|i| {
+            let local = Vec3::new(
+                if i & 1 == 0 { -width } else { width },
+                if i & 2 == 0 { -height } else { height },
+                if i & 4 == 0 { -width } else { width },
+            );
+            self.center + self.to_world * local
+        });
+        Aabb::from_points(corners)
+    }
+}
+
+/// Shape scaled along its axes, rotated then moved, its geometry being shared between copies
+pub struct TransformedShape {
+    shape: Arc<dyn Shape>,
+    to_world: Mat4,
+    to_local: Mat4,
+}
+
+impl TransformedShape {
+    /// The point at the origin of the shape space is moved to the translation
+    pub fn new(shape: Arc<dyn Shape>, translation: Vec3, rotation: Mat3, scale: f64) -> Self {
+        Self::with_axis_scales(shape, translation, rotation, Vec3::new(scale, scale, scale))
+    }
+
+    /// Same as `new`, but stretching the shape differently along its x, y and z axes
+    pub fn with_axis_scales(
+        shape: Arc<dyn Shape>,
+        translation: Vec3,
+        rotation: Mat3,
+        scale: Vec3,
+    ) -> Self {
+        // Inverted step by step, the transposed rotation being its exact inverse
+        let unscaling = Vec3::new(1.0 / scale.x, 1.0 / scale.y, 1.0 / scale.z);
+        TransformedShape {
+            shape,
+            to_world: Mat4::translation(translation)
+                * Mat4::from_mat3(rotation)
+                * Mat4::scaling(scale),
+            to_local: Mat4::scaling(unscaling)
+                * Mat4::from_mat3(rotation.transpose())
+                * Mat4::translation(-translation),
+        }
+    }
+
+    fn to_local(&self, point: Vec3) -> Vec3 {
+        self.to_local.transform_point(point)
+    }
+
+    fn to_world(&self, point: Vec3) -> Vec3 {
+        self.to_world.transform_point(point)
+    }
+}
+
+impl Shape for TransformedShape {
+    fn check_collision(&self, ray: &Ray) -> Option<Vec3> {
+        let local_ray = Ray::new(
+            self.to_local(ray.source),
+            self.to_local.transform_vector(ray.direction),
+        );
+        self.shape
+            .check_collision(&local_ray)
+            .map(|point| self.to_world(point))
+    }
+
+    /// Normals are transformed by the inverse transpose, so they stay perpendicular to
+    /// surfaces which are not scaled the same along each axis
+    fn normal_at(&self, point: Vec3) -> Option<Vec3> {
+        self.shape
+            .normal_at(self.to_local(point))
+            .map(|normal| self.to_local.transform_normal(normal))
+    }
+
+    fn surface_mapping_at(&self, point: Vec3) -> Option<(UnitInterval, UnitInterval)> {
+        self.shape.surface_mapping_at(self.to_local(point))
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        let Aabb { min, max } = self.shape.aabb()?;
+        let corners = (0..8).map(|i| {
+            Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        });
+        Aabb::from_points(corners.map(|corner| self.to_world(corner)))
+    }
+
+    /// The shared geometry is divided between its copies
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.shape.memory_size() / Arc::strong_count(&self.shape)
+    }
+
+    fn bottom_level_bvh(&self) -> Option<&Bvh> {
+        self.shape.bottom_level_bvh()
+    }
+
+    fn local_point(&self, point: Vec3) -> Vec3 {
+        self.shape.local_point(self.to_local(point))
+    }
+
+    fn world_point(&self, point: Vec3) -> Vec3 {
+        self.to_world(self.shape.world_point(point))
+    }
+}
+
+/// Shape moving at a constant velocity while the shutter is open, between two times in
+/// seconds from the frame, the first one not after the second one. Its surface is
+/// described where it is at the frame.
+pub struct MovingShape {
+    shape: Box<dyn Shape>,
+    velocity: Vec3,
+    times: Range<f64>,
+}
+
+impl MovingShape {
+    pub fn new(shape: Box<dyn Shape>, velocity: Vec3, times: Range<f64>) -> Self {
+        MovingShape {
+            shape,
+            velocity,
+            times,
+        }
+    }
+}
+
+impl Shape for MovingShape {
+    fn check_collision(&self, ray: &Ray) -> Option<Vec3> {
+        let displacement = self.displacement_at(ray.time);
+        let still_ray = Ray::new(ray.source - displacement, ray.direction);
+        self.shape
+            .check_collision(&still_ray)
+            .map(|point| point + displacement)
+    }
+
+    fn normal_at(&self, point: Vec3) -> Option<Vec3> {
+        self.shape.normal_at(point)
+    }
+
+    fn surface_mapping_at(&self, point: Vec3) -> Option<(UnitInterval, UnitInterval)> {
+        self.shape.surface_mapping_at(point)
+    }
+
+    /// Box swept by the shape while the shutter is open
+    fn aabb(&self) -> Option<Aabb> {
+        let aabb = self.shape.aabb()?;
+        let moved = |time: f64| {
+            let displacement = time * self.velocity;
+            Aabb::new(aabb.min + displacement, aabb.max + displacement)
+        };
+        Some(moved(self.times.start).union(&moved(self.times.end)))
+    }
+
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.shape.memory_size()
+    }
+
+    fn bottom_level_bvh(&self) -> Option<&Bvh> {
+        self.shape.bottom_level_bvh()
+    }
+
+    fn local_point(&self, point: Vec3) -> Vec3 {
+        self.shape.local_point(point)
+    }
+
+    fn world_point(&self, point: Vec3) -> Vec3 {
+        self.shape.world_point(point)
+    }
+
+    fn displacement_at(&self, time: f64) -> Vec3 {
+        time.clamp(self.times.start, self.times.end) * self.velocity
+    }
+}
+
+/// How the normals of a triangle mesh are chosen
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeshShading {
+    /// Normals of the vertices when they are given, else of the faces
+    #[default]
+    Auto,
+    /// Normals of the faces, showing the facets
+    Flat,
+    /// Normals interpolated between the vertices, computed from the faces around each vertex
+    /// when they are not given
+    Smooth,
+}
+
+/// Triangles sharing a vertex buffer, counter-clockwise vertices giving the front face.
+///
+/// Normals are the face normals, or are interpolated from one normal per vertex when given.
+/// The surface mapping of each triangle is its barycentric coordinates.
+#[derive(Debug)]
+pub struct TriangleMesh {
+    vertices: Vec<Vec3>,
+    triangles: Vec<[usize; 3]>,
+    face_normals: Vec<Vec3>,
+    vertex_normals: Option<Vec<Vec3>>,
+    texture_coordinates: Option<Vec<(f64, f64)>>,
+    /// Bottom level hierarchy over the triangles
+    bvh: Bvh,
+    /// Triangles of no area left out of the mesh
+    degenerate_triangles: usize,
+}
+
+impl TriangleMesh {
+    const EPSILON: f64 = 1e-9;
+
+    pub fn new(vertices: Vec<Vec3>, triangles: Vec<[usize; 3]>) -> Result<Self> {
+        Self::with_optional_bvh(vertices, triangles, None)
+    }
+
+    /// Same as `new`, with the hierarchy over the triangles given instead of built, like
+    /// the ones written in scene caches. Fails if it is not a hierarchy of these triangles.
+    pub fn with_bvh(vertices: Vec<Vec3>, triangles: Vec<[usize; 3]>, bvh: Bvh) -> Result<Self> {
+        Self::with_optional_bvh(vertices, triangles, Some(bvh))
+    }
+
+    fn with_optional_bvh(
+        vertices: Vec<Vec3>,
+        triangles: Vec<[usize; 3]>,
+        bvh: Option<Bvh>,
+    ) -> Result<Self> {
+        if let Some(index) = triangles.iter().flatten().find(|&&i| i >= vertices.len()) {
+            return Err(RaytracerError::InvalidGeometry(format!(
+                "triangle vertex index {} is out of {} vertices",
+                index,
+                vertices.len()
+            )));
+        }
+        let given_triangles = triangles.len();
+        let (triangles, face_normals): (Vec<_>, Vec<_>) = triangles
+            .into_iter()
+            .map(|[a, b, c]| {
+                let edge_1 = Vec3::between_points(vertices[a], vertices[b]);
+                let edge_2 = Vec3::between_points(vertices[a], vertices[c]);
+                ([a, b, c], edge_1.cross_product(edge_2))
+            })
+            .filter(|(_, normal)| !normal.is_null())
+            .unzip();
+        if triangles.is_empty() && given_triangles > 0 {
+            return Err(RaytracerError::InvalidGeometry(String::from(
+                "mesh has only degenerate triangles",
+            )));
+        }
+        let triangle_aabbs = triangles
+            .iter()
+            .map(|triangle| Aabb::from_points(triangle.iter().map(|&index| vertices[index])))
+            .collect::<Vec<_>>();
+        let bvh = match bvh {
+            None => Bvh::new(&triangle_aabbs),
+            // Refit to the triangles, so that only their number and order must match
+            Some(mut bvh) => {
+                if triangles.len() != given_triangles || !bvh.refit(&triangle_aabbs) {
+                    return Err(RaytracerError::InvalidGeometry(String::from(
+                        "hierarchy of other triangles given with the mesh",
+                    )));
+                }
+                bvh
+            }
+        };
+        Ok(TriangleMesh {
+            face_normals: face_normals.iter().map(Vec3::normalize).collect(),
+            bvh,
+            vertices,
+            degenerate_triangles: given_triangles - triangles.len(),
+            triangles,
+            vertex_normals: None,
+            texture_coordinates: None,
+        })
+    }
+
+    pub fn with_normals(
+        vertices: Vec<Vec3>,
+        triangles: Vec<[usize; 3]>,
+        vertex_normals: Vec<Vec3>,
+    ) -> Result<Self> {
+        TriangleMesh::new(vertices, triangles)?.with_vertex_normals(vertex_normals)
+    }
+
+    pub fn with_vertex_normals(self, vertex_normals: Vec<Vec3>) -> Result<Self> {
+        if vertex_normals.len() != self.vertices.len() {
+            return Err(RaytracerError::InvalidGeometry(format!(
+                "{} normals given for {} vertices",
+                vertex_normals.len(),
+                self.vertices.len()
+            )));
+        }
+        Ok(TriangleMesh {
+            vertex_normals: Some(vertex_normals.iter().map(Vec3::normalize).collect()),
+            ..self
+        })
+    }
+
+    /// Texture coordinates, one per vertex, used instead of the barycentric coordinates
+    pub fn with_texture_coordinates(self, texture_coordinates: Vec<(f64, f64)>) -> Result<Self> {
+        if texture_coordinates.len() != self.vertices.len() {
+            return Err(RaytracerError::InvalidGeometry(format!(
+                "{} texture coordinates given for {} vertices",
+                texture_coordinates.len(),
+                self.vertices.len()
+            )));
+        }
+        Ok(TriangleMesh {
+            texture_coordinates: Some(texture_coordinates),
+            ..self
+        })
+    }
+
+    pub fn with_shading(self, shading: MeshShading) -> Self {
+        match shading {
+            MeshShading::Auto => self,
+            MeshShading::Flat => TriangleMesh {
+                vertex_normals: None,
+                ..self
+            },
+            MeshShading::Smooth if self.vertex_normals.is_some() => self,
+            MeshShading::Smooth => self.with_smooth_normals(),
+        }
+    }
+
+    /// Vertex normals averaged from the faces sharing the vertex position, each weighted by its
+    /// area, so that vertices duplicated along texture seams get the same normal
+    fn with_smooth_normals(self) -> Self {
+        let key = |v: Vec3| [v.x.to_bits(), v.y.to_bits(), v.z.to_bits()];
+        let mut sums: HashMap<[u64; 3], (Vec3, Vec3)> = HashMap::new();
+        for (&[a, b, c], &face_normal) in self.triangles.iter().zip(&self.face_normals) {
+            let (v0, v1, v2) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+            let area_normal = (v1 - v0).cross_product(v2 - v0);
+            for &vertex in &[v0, v1, v2] {
+                let sum = sums
+                    .entry(key(vertex))
+                    .or_insert((Vec3::default(), face_normal));
+                sum.0 = sum.0 + area_normal;
+            }
+        }
+        let vertex_normals = self
+            .vertices
+            .iter()
+            .map(|&vertex| match sums.get(&key(vertex)) {
+                Some((sum, _)) if !sum.is_null() => sum.normalize(),
+                Some(&(_, face_normal)) => face_normal,
+                None => Vec3::new(0.0, 0.0, 1.0),
+            })
+            .collect();
+        TriangleMesh {
+            vertex_normals: Some(vertex_normals),
+            ..self
+        }
+    }
+
+    pub fn vertices(&self) -> &[Vec3] {
+        &self.vertices
+    }
+
+    pub fn triangles(&self) -> &[[usize; 3]] {
+        &self.triangles
+    }
+
+    pub fn vertex_normals(&self) -> Option<&[Vec3]> {
+        self.vertex_normals.as_deref()
+    }
+
+    pub fn texture_coordinates(&self) -> Option<&[(f64, f64)]> {
+        self.texture_coordinates.as_deref()
+    }
+
+    /// Number of triangles of no area given to the mesh, which are not part of it
+    pub fn degenerate_triangle_count(&self) -> usize {
+        self.degenerate_triangles
+    }
+
+    /// Möller–Trumbore intersection, giving the distance and barycentric coordinates of the hit
+    fn intersect_triangle(&self, index: usize, ray: &Ray) -> Option<(f64, f64, f64)> {
+        count(Event::TriangleTest);
+        let [a, b, c] = self.triangles[index];
+        let (v0, v1, v2) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+        let edge_1 = v1 - v0;
+        let edge_2 = v2 - v0;
+        let p = ray.direction.cross_product(edge_2);
+        let determinant = edge_1.dot_product(p);
+        if determinant.abs() < Self::EPSILON {
+            return None;
+        }
+        let inverse_determinant = 1.0 / determinant;
+        let s = ray.source - v0;
+        let u = s.dot_product(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross_product(edge_1);
+        let v = ray.direction.dot_product(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = edge_2.dot_product(q) * inverse_determinant;
+        if t > Self::EPSILON {
+            Some((t, u, v))
+        } else {
+            None
+        }
+    }
+
+    /// Find the triangle on which a point lies, with the barycentric coordinates of the point
+    fn locate(&self, point: Vec3) -> Option<(usize, f64, f64)> {
+        let tolerance = 1e-6;
+        let maximum_distance = tolerance * (1.0 + point.norm());
+        let mut best: Option<(f64, usize, f64, f64)> = None;
+        self.bvh.visit_containing(point, maximum_distance, |index| {
+            let [a, _, _] = self.triangles[index];
+            let normal = self.face_normals[index];
+            let plane_distance = Vec3::between_points(self.vertices[a], point)
+                .dot_product(normal)
+                .abs();
+            if plane_distance > maximum_distance
+                || best.is_some_and(|(distance, ..)| distance <= plane_distance)
+            {
+                return;
+            }
+            // Project on the triangle plane, then compute barycentric coordinates
+            let projected = point - plane_distance * normal;
+            let (u, v) = self.barycentric(index, projected);
+            if u >= -tolerance && v >= -tolerance && u + v <= 1.0 + tolerance {
+                best = Some((plane_distance, index, u, v));
+            }
+        });
+        best.map(|(_, index, u, v)| (index, u, v))
+    }
+
+    fn barycentric(&self, index: usize, point: Vec3) -> (f64, f64) {
+        let [a, b, c] = self.triangles[index];
+        let edge_1 = self.vertices[b] - self.vertices[a];
+        let edge_2 = self.vertices[c] - self.vertices[a];
+        let local = point - self.vertices[a];
+        let d11 = edge_1.dot_product(edge_1);
+        let d12 = edge_1.dot_product(edge_2);
+        let d22 = edge_2.dot_product(edge_2);
+        let dl1 = local.dot_product(edge_1);
+        let dl2 = local.dot_product(edge_2);
+        let denominator = d11 * d22 - d12 * d12;
+        let u = (d22 * dl1 - d12 * dl2) / denominator;
+        let v = (d11 * dl2 - d12 * dl1) / denominator;
+        (u, v)
+    }
+}
+
+impl Shape for TriangleMesh {
+    fn check_collision(&self, ray: &Ray) -> Option<Vec3> {
+        let mut nearest = None;
+        self.bvh
+            .visit(ray, f64::INFINITY, |index, maximum_distance| {
+                match self.intersect_triangle(index, ray) {
+                    Some((t, _, _)) if t < maximum_distance => {
+                        nearest = Some(t);
+                        t
+                    }
+                    _ => maximum_distance,
+                }
+            });
+        nearest.map(|t| ray.source + t * ray.direction)
+    }
+
+    fn normal_at(&self, point: Vec3) -> Option<Vec3> {
+        let (index, u, v) = self.locate(point)?;
+        match &self.vertex_normals {
+            None => Some(self.face_normals[index]),
+            Some(normals) => {
+                let [a, b, c] = self.triangles[index];
+                let normal = (1.0 - u - v) * normals[a] + u * normals[b] + v * normals[c];
+                Some(normal.normalize())
+            }
+        }
+    }
+
+    fn surface_mapping_at(&self, point: Vec3) -> Option<(UnitInterval, UnitInterval)> {
+        let (index, u, v) = self.locate(point)?;
+        match &self.texture_coordinates {
+            None => Some((u.clamp(0.0, 1.0), v.clamp(0.0, 1.0))),
+            Some(coordinates) => {
+                let [a, b, c] = self.triangles[index];
+                let w = 1.0 - u - v;
+                let x = w * coordinates[a].0 + u * coordinates[b].0 + v * coordinates[c].0;
+                let y = w * coordinates[a].1 + u * coordinates[b].1 + v * coordinates[c].1;
+                Some((x.rem_euclid(1.0), y.rem_euclid(1.0)))
+            }
+        }
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        Aabb::from_points(self.vertices.iter().copied())
+    }
+
+    fn memory_size(&self) -> usize {
+        let vec3_count = self.vertices.len()
+            + self.face_normals.len()
+            + self.vertex_normals.as_ref().map_or(0, Vec::len);
+        std::mem::size_of::<Self>()
+            + vec3_count * std::mem::size_of::<Vec3>()
+            + self.triangles.len() * std::mem::size_of::<[usize; 3]>()
+            + self.texture_coordinates.as_ref().map_or(0, |coordinates| {
+                coordinates.len() * std::mem::size_of::<(f64, f64)>()
+            })
+    }
+
+    fn bottom_level_bvh(&self) -> Option<&Bvh> {
+        Some(&self.bvh)
+    }
 }
 
 #[cfg(test)]
@@ -291,6 +1191,134 @@ mod tests {
         assert!(result.is_some());
     }
 
+    #[test]
+    fn mesh_hierarchy_finds_nearest_triangle() {
+        // Wavy grid, with triangles in front of others along the rays
+        let size = 12;
+        let vertices = (0..size * size)
+            .map(|i| {
+                let (x, y) = ((i % size) as f64, (i / size) as f64);
+                Vec3::new(x, y, (x * 1.3).sin() * 3.0 + y * 0.4)
+            })
+            .collect::<Vec<_>>();
+        let triangles = (0..(size - 1) * (size - 1))
+            .flat_map(|i| {
+                let a = i / (size - 1) * size + i % (size - 1);
+                vec![[a, a + 1, a + size + 1], [a, a + size + 1, a + size]]
+            })
+            .collect::<Vec<_>>();
+        let mesh = TriangleMesh::new(vertices, triangles).unwrap();
+        let mut hits = 0;
+        for i in 0..50 {
+            let target = Vec3::new(0.2 * i as f64 + 0.31, 5.0 + 0.1 * i as f64, 0.0);
+            let ray = Ray::ray_from_to(Vec3::new(-4.0, 3.0, -20.0), target);
+            let brute_force = (0..mesh.triangles.len())
+                .filter_map(|index| mesh.intersect_triangle(index, &ray))
+                .map(|(t, _, _)| t)
+                .fold(f64::INFINITY, f64::min);
+            match mesh.check_collision(&ray) {
+                Some(point) => {
+                    assert!(((point - ray.source).norm() - brute_force).abs() < 1e-9);
+                    assert!(mesh.normal_at(point).is_some());
+                    hits += 1;
+                }
+                None => assert!(brute_force.is_infinite()),
+            }
+        }
+        assert!(hits > 30);
+    }
+
+    fn single_triangle(normals: Option<Vec<Vec3>>) -> TriangleMesh {
+        let vertices = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        match normals {
+            None => TriangleMesh::new(vertices, vec![[0, 1, 2]]).unwrap(),
+            Some(normals) => {
+                TriangleMesh::with_normals(vertices, vec![[0, 1, 2]], normals).unwrap()
+            }
+        }
+    }
+
+    #[test]
+    fn displaced_sphere_hit_is_on_the_bumps() {
+        let heightmap = Heightmap::Noise {
+            frequency: 3.0,
+            octaves: 4,
+        };
+        let sphere = DisplacedSphere::new(Sphere::default(), 0.2, heightmap);
+        let heights = [Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.6, 0.0, -0.8)]
+            .iter()
+            .map(|&direction| {
+                let ray = Ray::new(-5.0 * direction, direction);
+                let hit = sphere.check_collision(&ray).unwrap();
+                assert!(sphere.radial_distance(hit).abs() < 1e-9);
+                assert!(sphere.normal_at(hit).unwrap().dot_product(direction) < 0.0);
+                hit.norm()
+            })
+            .collect::<Vec<_>>();
+        assert!(heights.iter().all(|&height| (1.0..=1.2).contains(&height)));
+        assert!((heights[0] - heights[1]).abs() > 1e-3);
+    }
+
+    #[test]
+    fn smooth_mesh_normals_average_the_faces() {
+        // Two faces folded along the y axis, one facing +z and the other +x
+        let vertices = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        ];
+        let mesh = TriangleMesh::new(vertices, vec![[0, 1, 2], [0, 3, 1]]).unwrap();
+        let flat = mesh.normal_at(Vec3::new(-0.25, 0.25, 0.0)).unwrap();
+        assert!(f64_eq(flat.x, 0.0));
+        let mesh = mesh.with_shading(MeshShading::Smooth);
+        let normal = mesh.normal_at(Vec3::new(-0.01, 0.5, 0.0)).unwrap();
+        assert!(normal.x > 0.3 && normal.z > 0.3);
+        assert!(f64_eq(normal.norm(), 1.0));
+        let mesh = mesh.with_shading(MeshShading::Flat);
+        let normal = mesh.normal_at(Vec3::new(-0.01, 0.5, 0.0)).unwrap();
+        assert!(f64_eq(normal.x, 0.0));
+    }
+
+    #[test]
+    fn mesh_with_invalid_index_is_error() {
+        let vertices = vec![Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)];
+        assert!(TriangleMesh::new(vertices, vec![[0, 1, 2]]).is_err());
+    }
+
+    #[test]
+    fn ray_triangle_collision() {
+        let mesh = single_triangle(None);
+        let ray = Ray::new(Vec3::new(0.25, 0.25, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = mesh.check_collision(&ray).unwrap();
+        assert_eq!(hit, Vec3::new(0.25, 0.25, 0.0));
+        assert_eq!(mesh.normal_at(hit).unwrap(), Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn ray_triangle_no_collision_outside_edges() {
+        let mesh = single_triangle(None);
+        let ray = Ray::new(Vec3::new(0.75, 0.75, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(mesh.check_collision(&ray).is_none());
+    }
+
+    #[test]
+    fn triangle_normals_are_interpolated() {
+        let normals = vec![
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ];
+        let mesh = single_triangle(Some(normals));
+        let normal = mesh.normal_at(Vec3::new(0.5, 0.0, 0.0)).unwrap();
+        assert!(normal.x > 0.0 && normal.z > 0.0);
+        assert!(f64_eq(normal.norm(), 1.0));
+    }
+
     #[test]
     fn ray_sphere_no_collision() {
         let sphere: Sphere = Default::default(); // Given a unit sphere
@@ -309,4 +1337,56 @@ mod tests {
         );
         assert!(sphere.check_collision(&ray).is_none()); // There is no collision
     }
+
+    #[test]
+    fn ray_torus_collision() {
+        let torus = Torus::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 3.0, 1.0);
+        // Through the hole, along the axis
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(torus.check_collision(&ray).is_none());
+        // Through the tube, hitting its outer side first
+        let ray = Ray::new(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = torus.check_collision(&ray).unwrap();
+        assert!(hit.distance(Vec3::new(-4.0, 0.0, 0.0)) < 1e-9);
+        let normal = torus.normal_at(hit).unwrap();
+        assert!(normal.distance(Vec3::new(-1.0, 0.0, 0.0)) < 1e-9);
+        // From inside the tube, the inner side is hit
+        let ray = Ray::new(Vec3::new(-3.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = torus.check_collision(&ray).unwrap();
+        assert!(hit.distance(Vec3::new(-2.0, 0.0, 0.0)) < 1e-9);
+    }
+
+    #[test]
+    fn far_ray_torus_collision_is_precise() {
+        let torus = Torus::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            0.25,
+        );
+        let ray = Ray::new(Vec3::new(1.0, 1e6, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = torus.check_collision(&ray).unwrap();
+        assert!(hit.distance(Vec3::new(1.0, 0.25, 0.0)) < 1e-6);
+    }
+
+    #[test]
+    fn stretched_sphere_keeps_normals_perpendicular() {
+        // A unit sphere stretched twice along x, rotated a quarter turn around z
+        let ellipsoid = TransformedShape::with_axis_scales(
+            Arc::new(Sphere::default()),
+            Vec3::new(0.0, 0.0, 5.0),
+            Mat3::rotation(Vec3::new(0.0, 0.0, std::f64::consts::FRAC_PI_2)),
+            Vec3::new(2.0, 1.0, 1.0),
+        );
+        let ray = Ray::new(Vec3::new(0.0, -10.0, 5.0), Vec3::new(0.0, 1.0, 0.0));
+        let hit = ellipsoid.check_collision(&ray).unwrap();
+        assert!(hit.distance(Vec3::new(0.0, -2.0, 5.0)) < 1e-9);
+        let ray = Ray::new(Vec3::new(0.5, -10.0, 5.0), Vec3::new(0.0, 1.0, 0.0));
+        let hit = ellipsoid.check_collision(&ray).unwrap();
+        let normal = ellipsoid.normal_at(hit).unwrap();
+        assert!(f64_eq(normal.norm(), 1.0));
+        // The gradient of the ellipsoid x² + y²/4 = 1 in world space
+        let gradient = Vec3::new(2.0 * hit.x, hit.y / 2.0, 0.0).normalize();
+        assert!(normal.distance(gradient) < 1e-9);
+    }
 }
@@ -0,0 +1,237 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Progress of a rendering reported by the library itself, to a terminal, a window or a web
+//! page alike. The pixels are counted as the caller is given them, the callback being
+//! called each time a new percent of them or a whole tile is done.
+
+use crate::renderer::{Pixel, RenderConfiguration, Renderer};
+use crate::result::Result;
+use crate::tiles::Tile;
+use instant::Instant;
+use std::time::Duration;
+
+/// Callback of the configuration given the progress of the rendering
+pub type ProgressCallback = Box<dyn FnMut(&RenderProgress) + Send + Sync>;
+
+/// How far a rendering is, the tiles already rendered before a resumed rendering counted
+/// as done
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderProgress {
+    pub done_pixels: u64,
+    /// Pixels given by the renderer, more than those of the canvas when some are given
+    /// again
+    pub total_pixels: u64,
+    pub done_tiles: usize,
+    /// None when the renderer gives pixels again, its tiles being never done
+    pub total_tiles: Option<usize>,
+    pub elapsed: Duration,
+    /// Remaining time at the mean speed so far, unknown before the first pixel
+    pub eta: Option<Duration>,
+}
+
+impl RenderProgress {
+    pub fn percent(&self) -> u64 {
+        self.done_pixels.min(self.total_pixels) * 100 / self.total_pixels.max(1)
+    }
+}
+
+/// Pixels left in each tile, indexed by its row and column in the grid of the tiles
+struct TileGrid {
+    tile_size: u32,
+    columns: u32,
+    remaining: Vec<u32>,
+}
+
+impl TileGrid {
+    fn new(config: &RenderConfiguration) -> TileGrid {
+        let tile_size = config.tile_size.max(1);
+        let columns = config.canvas_width.div_ceil(tile_size);
+        let rows = config.canvas_height.div_ceil(tile_size);
+        let mut grid = TileGrid {
+            tile_size,
+            columns,
+            remaining: vec![0; (columns * rows) as usize],
+        };
+        for tile in config.tiles() {
+            let index = grid.index(tile.x, tile.y);
+            grid.remaining[index] = tile.pixel_count() as u32;
+        }
+        grid
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        ((y / self.tile_size) * self.columns + x / self.tile_size) as usize
+    }
+
+    /// Count a pixel of a tile, true if it was the last one
+    fn complete(&mut self, x: u32, y: u32) -> bool {
+        let index = self.index(x, y);
+        match self.remaining.get_mut(index) {
+            Some(remaining) if *remaining > 0 => {
+                *remaining -= 1;
+                *remaining == 0
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Follower of the pixels given by a rendering, calling back the configuration
+pub(crate) struct ProgressTracker {
+    callback: ProgressCallback,
+    start: Instant,
+    progress: RenderProgress,
+    /// Pixels done before the rendering started, left out of its speed
+    skipped_pixels: u64,
+    tiles: Option<TileGrid>,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(
+        callback: ProgressCallback,
+        renderer: &dyn Renderer,
+        config: &RenderConfiguration,
+    ) -> ProgressTracker {
+        let (width, height) = (config.canvas_width, config.canvas_height);
+        let total_pixels = renderer.pixel_count(width, height);
+        // Tiles are only done once when each pixel is given once
+        let tiles = match total_pixels == width as u64 * height as u64 {
+            true => Some(TileGrid::new(config)),
+            false => None,
+        };
+        let skipped_pixels: usize = config.skipped_tiles.iter().map(Tile::pixel_count).sum();
+        let skipped_pixels = match tiles {
+            Some(_) => skipped_pixels as u64,
+            None => 0,
+        };
+        let total_tiles = tiles.as_ref().map(|grid| grid.remaining.len());
+        ProgressTracker {
+            callback,
+            start: Instant::now(),
+            progress: RenderProgress {
+                done_pixels: skipped_pixels,
+                total_pixels,
+                done_tiles: config.skipped_tiles.len().min(total_tiles.unwrap_or(0)),
+                total_tiles,
+                elapsed: Duration::default(),
+                eta: None,
+            },
+            skipped_pixels,
+            tiles,
+        }
+    }
+
+    /// Count a pixel given, calling back when a new percent or a tile is done
+    pub(crate) fn advance(&mut self, pixel: &Result<Pixel>) {
+        let percent = self.progress.percent();
+        self.progress.done_pixels += 1;
+        let tile_done = match (pixel, &mut self.tiles) {
+            (Ok(pixel), Some(tiles)) => tiles.complete(pixel.x, pixel.y),
+            _ => false,
+        };
+        if tile_done {
+            self.progress.done_tiles += 1;
+        }
+        if tile_done || self.progress.percent() != percent {
+            let progress = &mut self.progress;
+            progress.elapsed = self.start.elapsed();
+            let rendered = progress.done_pixels - self.skipped_pixels;
+            let remaining = progress.total_pixels.saturating_sub(progress.done_pixels);
+            progress.eta = Some(progress.elapsed.mul_f64(remaining as f64 / rendered as f64));
+            (self.callback)(progress);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::Color;
+    use crate::renderer::SequentialRenderer;
+    use crate::tiles::TileOrder;
+    use std::sync::{Arc, Mutex};
+
+    fn tracked(config: &RenderConfiguration) -> (ProgressTracker, Arc<Mutex<Vec<RenderProgress>>>) {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let shared = reports.clone();
+        let callback = Box::new(move |progress: &RenderProgress| {
+            shared.lock().unwrap().push(progress.clone());
+        });
+        let tracker = ProgressTracker::new(callback, &SequentialRenderer, config);
+        (tracker, reports)
+    }
+
+    fn config() -> RenderConfiguration {
+        RenderConfiguration {
+            canvas_width: 20,
+            canvas_height: 10,
+            tile_size: 10,
+            tile_order: TileOrder::Scanline,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn progress_is_reported_at_each_percent_and_tile() {
+        let config = config();
+        let (mut tracker, reports) = tracked(&config);
+        for tile in config.tiles() {
+            for (x, y) in tile.pixels() {
+                tracker.advance(&Ok(Pixel::new(x, y, Color::BLACK)));
+            }
+        }
+        let reports = reports.lock().unwrap();
+        // 200 pixels, a new percent every other one
+        assert_eq!(reports.len(), 100);
+        let last = reports.last().unwrap();
+        assert_eq!((last.done_pixels, last.total_pixels), (200, 200));
+        assert_eq!((last.done_tiles, last.total_tiles), (2, Some(2)));
+        assert_eq!(last.percent(), 100);
+        assert_eq!(last.eta, Some(Duration::default()));
+        let first_tile_done = reports.iter().position(|report| report.done_tiles == 1);
+        assert_eq!(first_tile_done, Some(49));
+    }
+
+    #[test]
+    fn skipped_tiles_are_already_done() {
+        let config = RenderConfiguration {
+            skipped_tiles: vec![Tile {
+                x: 0,
+                y: 0,
+                width: 10,
+                height: 10,
+            }],
+            ..config()
+        };
+        let (mut tracker, reports) = tracked(&config);
+        tracker.advance(&Ok(Pixel::new(10, 0, Color::BLACK)));
+        tracker.advance(&Ok(Pixel::new(11, 0, Color::BLACK)));
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].done_pixels, 102);
+        assert_eq!(reports[0].done_tiles, 1);
+        assert_eq!(reports[0].percent(), 51);
+    }
+}
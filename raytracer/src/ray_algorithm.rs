@@ -23,16 +23,31 @@ SOFTWARE.
 */
 
 use crate::colors::Color;
-use crate::lights::AnyLightObject;
+use crate::lights::plane_axes;
 use crate::primitives::Ray;
 use crate::result::RaytracerError;
 use crate::result::Result;
-use crate::scene::{Scene, SceneObject};
+use crate::scene::{
+    BackgroundVisibility, Integrator, LightBounce, LightContribution, Scene, SceneObject,
+    SceneObjects, TransparencyMode,
+};
+use crate::stats::{count, Event};
+use crate::textures::{
+    schlick_reflectance, ShadingContext, TextureEffects, Transparency, REFERENCE_WAVELENGTH,
+};
 use crate::vector::Vec3;
 use crate::UnitInterval;
-use rand::Rng;
+use rand::{Rng, RngCore};
+use serde::Serialize;
 use std::f64;
 
+/// Color computed for a pixel, with its coverage by non holdout objects
+#[derive(Debug, Clone)]
+pub struct PixelSample {
+    pub color: Color,
+    pub alpha: UnitInterval,
+}
+
 pub trait AnyPixelRenderStrategy: Send + Sync {
     fn render_pixel(
         &self,
@@ -41,7 +56,8 @@ pub trait AnyPixelRenderStrategy: Send + Sync {
         canvas_y: UnitInterval,
         pixel_width: f64,
         pixel_height: f64,
-    ) -> Result<Color>;
+        rng: &mut dyn RngCore,
+    ) -> Result<PixelSample>;
 }
 
 pub mod strategy {
@@ -57,11 +73,17 @@ pub mod strategy {
             canvas_y: UnitInterval,
             pixel_width: f64,
             pixel_height: f64,
-        ) -> Result<Color> {
+            rng: &mut dyn RngCore,
+        ) -> Result<PixelSample> {
             let x_unit = pixel_width / 2.0 + canvas_x;
             let y_unit = pixel_height / 2.0 + canvas_y;
-            let camera_ray = scene.camera.generate_ray(x_unit, y_unit);
-            launch_ray(&camera_ray, scene, 0)
+            // Seen in the middle of the shutter interval
+            let time = scene
+                .camera
+                .shutter()
+                .time_at(0.5, scene.config.frames_per_second);
+            let camera_ray = scene.camera.sample_ray(x_unit, y_unit, time, rng);
+            launch_camera_ray(&camera_ray, scene, rng)
         }
     }
 
@@ -77,165 +99,1144 @@ pub mod strategy {
             canvas_y: UnitInterval,
             pixel_width: f64,
             pixel_height: f64,
-        ) -> Result<Color> {
-            let mut rng = rand::thread_rng();
-            let mut result_color = Color::BLACK;
+            rng: &mut dyn RngCore,
+        ) -> Result<PixelSample> {
+            let shutter = scene.camera.shutter();
+            let ray_weight = 1.0 / (self.rays_per_pixel as f64);
+            let mut result = PixelSample {
+                color: Color::BLACK,
+                alpha: 0.0,
+            };
             for _ in 0..self.rays_per_pixel {
                 let x_unit: f64 = rng.gen::<f64>() * pixel_width + canvas_x;
                 let y_unit: f64 = rng.gen::<f64>() * pixel_height + canvas_y;
-                let camera_ray = scene.camera.generate_ray(x_unit, y_unit);
-                result_color +=
-                    (1.0 / (self.rays_per_pixel as f64)) * launch_ray(&camera_ray, scene, 0)?;
+                let time = shutter.time_at(rng.gen(), scene.config.frames_per_second);
+                let camera_ray = scene.camera.sample_ray(x_unit, y_unit, time, rng);
+                let sample = launch_camera_ray(&camera_ray, scene, rng)?;
+                result.color += ray_weight * sample.color;
+                result.alpha += ray_weight * sample.alpha;
+            }
+            Ok(result)
+        }
+    }
+}
+
+/// Kind of ray launched while computing the color of a pixel
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RayKind {
+    Camera,
+    Reflection,
+    Refraction,
+    Shadow,
+    Diffuse,
+}
+
+/// Receives every ray launched by the ray algorithm, with the point where it stops if any.
+pub(crate) trait RayRecorder {
+    fn record(&mut self, kind: RayKind, depth: u8, ray: &Ray, end: Option<Vec3>);
+}
+
+/// Transparent objects a ray is inside of, with their refractive index, the innermost last
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MediaStack {
+    /// Object index, refractive index and Cauchy B coefficient
+    media: Vec<(usize, f64, f64)>,
+    /// Only set once the ray has been split by a dispersive medium, in µm
+    wavelength: Option<f64>,
+}
+
+/// Phong size of the highlight of a car paint flake, small flakes making sharp sparkles
+const FLAKE_SHININESS: i32 = 200;
+
+/// Wavelengths, in µm, followed for each color channel once light is dispersed
+const CHANNEL_WAVELENGTHS: [(f64, [f64; 3]); 3] = [
+    (0.65, [1.0, 0.0, 0.0]),
+    (REFERENCE_WAVELENGTH, [0.0, 1.0, 0.0]),
+    (0.45, [0.0, 0.0, 1.0]),
+];
+
+impl MediaStack {
+    /// Refractive index of the medium the ray goes through
+    fn refractive_index(&self, world_refractive_index: f64) -> f64 {
+        match (self.media.last(), self.wavelength) {
+            (None, _) => world_refractive_index,
+            (Some(&(_, refractive_index, _)), None) => refractive_index,
+            (Some(&(_, refractive_index, cauchy_b)), Some(wavelength)) => Transparency {
+                refractive_index,
+                cauchy_b,
+                ..Default::default()
             }
-            Ok(result_color)
+            .refractive_index_at(wavelength),
         }
     }
+
+    fn contains(&self, object_index: usize) -> bool {
+        self.media.iter().any(|&(index, ..)| index == object_index)
+    }
+
+    fn entering(&self, object_index: usize, transparency: &Transparency) -> MediaStack {
+        let mut media = self.media.clone();
+        media.push((
+            object_index,
+            transparency.refractive_index,
+            transparency.cauchy_b,
+        ));
+        MediaStack { media, ..*self }
+    }
+
+    /// Objects can overlap, so the left one is not always the innermost
+    fn leaving(&self, object_index: usize) -> MediaStack {
+        let mut media = self.media.clone();
+        if let Some(position) = media.iter().rposition(|&(index, ..)| index == object_index) {
+            media.remove(position);
+        }
+        MediaStack { media, ..*self }
+    }
+
+    /// Ray split in one ray per color channel when entering a dispersive medium, each
+    /// with the mask of the channel it gives
+    fn dispersed(&self, transparency: &Transparency) -> Vec<(Color, MediaStack)> {
+        if transparency.cauchy_b == 0.0 || self.wavelength.is_some() {
+            return vec![(Color::WHITE, self.clone())];
+        }
+        CHANNEL_WAVELENGTHS
+            .iter()
+            .map(|&(wavelength, [red, green, blue])| {
+                let media = MediaStack {
+                    media: self.media.clone(),
+                    wavelength: Some(wavelength),
+                };
+                (Color::new(red, green, blue), media)
+            })
+            .collect()
+    }
 }
 
-pub(crate) fn launch_ray(camera_ray: &Ray, scene: &Scene, depth: u8) -> Result<Color> {
+pub(crate) struct NoRayRecorder;
+
+impl RayRecorder for NoRayRecorder {
+    fn record(&mut self, _kind: RayKind, _depth: u8, _ray: &Ray, _end: Option<Vec3>) {}
+}
+
+/// Launch a ray from the camera, it is transparent if it first hits a holdout object
+pub(crate) fn launch_camera_ray(
+    camera_ray: &Ray,
+    scene: &Scene,
+    rng: &mut dyn RngCore,
+) -> Result<PixelSample> {
+    let (camera_ray, collision_context) = search_ray_collision(camera_ray, scene, RayKind::Camera);
+    let alpha = match collision_context {
+        None if scene.config.camera_background == BackgroundVisibility::Transparent => 0.0,
+        Some(ref collision_context) if collision_context.object.effects().holdout => 0.0,
+        Some(ref collision_context)
+            if collision_context.object.effects().shadow_catcher
+                && scene.config.integrator != Integrator::AmbientOcclusion =>
+        {
+            let visibility = light_visibility(collision_context, scene, 0, &mut NoRayRecorder, rng);
+            return Ok(PixelSample {
+                color: visibility * &scene.config.world_color,
+                alpha: 1.0 - visibility,
+            });
+        }
+        _ => 1.0,
+    };
+    let color = shade_ray(
+        &camera_ray,
+        collision_context,
+        scene,
+        0,
+        RayKind::Camera,
+        &MediaStack::default(),
+        &mut NoRayRecorder,
+        rng,
+    )?;
+    Ok(PixelSample { color, alpha })
+}
+
+/// Index of the object first seen by a camera ray, with the point where it is seen
+pub(crate) fn camera_ray_hit(camera_ray: &Ray, scene: &Scene) -> Option<(usize, Vec3)> {
+    let (_, collision_context) = search_ray_collision(camera_ray, scene, RayKind::Camera);
+    collision_context.map(|context| (context.array_index, context.collision_point))
+}
+
+pub(crate) fn launch_recorded_ray(
+    camera_ray: &Ray,
+    scene: &Scene,
+    depth: u8,
+    kind: RayKind,
+    media: &MediaStack,
+    recorder: &mut dyn RayRecorder,
+    rng: &mut dyn RngCore,
+) -> Result<Color> {
     if depth > scene.config.maximum_light_recursion {
         return Ok(Color::BLACK);
     }
+    let (camera_ray, collision_context) = search_ray_collision(camera_ray, scene, kind);
+    shade_ray(
+        &camera_ray,
+        collision_context,
+        scene,
+        depth,
+        kind,
+        media,
+        recorder,
+        rng,
+    )
+}
+
+/// Search the object hit by a ray, the ray being returned as clipped for the search
+fn search_ray_collision<'a>(
+    ray: &Ray,
+    scene: &'a Scene,
+    kind: RayKind,
+) -> (Ray, Option<CollisionContext<'a>>) {
+    count(Event::Ray);
+    // Camera rays only start at the near clipping distance, so objects
+    // around the eye do not hide the scene
+    let near_clip = match kind {
+        RayKind::Camera => scene.camera.near_clip().unwrap_or(0.0),
+        _ => 0.0,
+    };
+    let clipped_ray =
+        Ray::new(ray.source + near_clip * ray.direction, ray.direction).at_time(ray.time);
 
     // Check if there is an object to process for this pixel
-    let collision_context = match search_object_collision(&camera_ray, &scene.objects) {
+    let maximum_distance = match kind {
+        RayKind::Camera => scene.camera.far_clip(),
+        _ => scene.config.maximum_ray_distance,
+    };
+    let collision_context = search_object_collision(
+        &clipped_ray,
+        &scene.objects,
+        maximum_distance.map_or(f64::MAX, |distance| distance - near_clip),
+    );
+    (clipped_ray, collision_context)
+}
+
+/// Color seen along a ray, knowing the object it hits, seen through the height fog
+/// of the atmosphere if any
+#[allow(clippy::too_many_arguments)]
+fn shade_ray(
+    camera_ray: &Ray,
+    collision_context: Option<CollisionContext>,
+    scene: &Scene,
+    depth: u8,
+    kind: RayKind,
+    media: &MediaStack,
+    recorder: &mut dyn RayRecorder,
+    rng: &mut dyn RngCore,
+) -> Result<Color> {
+    let collision_point = collision_context.as_ref().map(|c| c.collision_point);
+    let color = shade_surface(
+        camera_ray,
+        collision_context,
+        scene,
+        depth,
+        kind,
+        media,
+        recorder,
+        rng,
+    )?;
+    let atmosphere = scene.config.atmosphere.as_ref();
+    Ok(match (atmosphere, collision_point) {
+        (Some(atmosphere), Some(point))
+            if atmosphere.height_fog && scene.config.integrator != Integrator::AmbientOcclusion =>
+        {
+            atmosphere.aerial_perspective(color, camera_ray.source, point)
+        }
+        _ => color,
+    })
+}
+
+/// Color of the background seen by a ray hitting nothing, depending on its kind
+fn background_seen(ray: &Ray, scene: &Scene, kind: RayKind) -> Color {
+    let visibility = match kind {
+        RayKind::Camera => scene.config.camera_background,
+        RayKind::Reflection | RayKind::Refraction => scene.config.reflection_background,
+        RayKind::Shadow | RayKind::Diffuse => BackgroundVisibility::Environment,
+    };
+    match visibility {
+        BackgroundVisibility::Environment => scene.config.background_color(ray.direction),
+        BackgroundVisibility::WorldColor => scene.config.world_color.clone(),
+        BackgroundVisibility::Transparent => Color::BLACK,
+    }
+}
+
+/// Color of the object hit by a ray, or of the background if there is none
+#[allow(clippy::too_many_arguments)]
+fn shade_surface(
+    camera_ray: &Ray,
+    collision_context: Option<CollisionContext>,
+    scene: &Scene,
+    depth: u8,
+    kind: RayKind,
+    media: &MediaStack,
+    recorder: &mut dyn RayRecorder,
+    rng: &mut dyn RngCore,
+) -> Result<Color> {
+    let light_paths = &scene.config.light_paths;
+    recorder.record(
+        kind,
+        depth,
+        camera_ray,
+        collision_context.as_ref().map(|c| c.collision_point),
+    );
+    let collision_context = match collision_context {
         Some(collision_context) => collision_context,
-        None => {
-            return Ok(scene.config.world_color.clone());
+        None if light_paths.accepts(LightContribution::Background, depth) => {
+            return Ok(background_seen(camera_ray, scene, kind));
         }
+        None => return Ok(Color::BLACK),
     };
     let CollisionContext {
         object: nearest_object,
-        collision_point,
+        surface_point,
         array_index,
+        ..
     } = collision_context;
 
+    // Holdout objects only hide what is behind them
+    if nearest_object.effects().holdout {
+        return Ok(Color::BLACK);
+    }
+    if scene.config.integrator == Integrator::AmbientOcclusion {
+        let openness =
+            ambient_openness(camera_ray, &collision_context, scene, depth, recorder, rng)?;
+        return Ok(Color::new(openness, openness, openness));
+    }
+    if nearest_object.effects().shadow_catcher {
+        let visibility = light_visibility(&collision_context, scene, depth, recorder, rng);
+        return Ok(visibility * &scene.config.world_color);
+    }
+    if nearest_object.effects().emission.is_some() {
+        return launch_ray_through_volume(
+            camera_ray,
+            &collision_context,
+            scene,
+            depth,
+            kind,
+            media,
+            recorder,
+            rng,
+        );
+    }
+
+    // Going out of a transparent object, only the light coming from behind is seen, and
+    // the light reflected inside with the Fresnel proportions
+    if media.contains(array_index) {
+        let outer_media = media.leaving(array_index);
+        let fresnel = nearest_object
+            .effects()
+            .transparency
+            .as_ref()
+            .is_some_and(|t| t.fresnel);
+        let reflectance = if fresnel {
+            fresnel_reflectance(camera_ray, &collision_context, scene, media, &outer_media)?
+        } else {
+            0.0
+        };
+        let mut total_color = Color::BLACK;
+        if reflectance < 1.0 {
+            total_color += (1.0 - reflectance)
+                * launch_transmitted_ray(
+                    camera_ray,
+                    &collision_context,
+                    scene,
+                    depth,
+                    media,
+                    &outer_media,
+                    recorder,
+                    rng,
+                )?;
+        }
+        if reflectance > 0.0 && light_paths.follows(LightBounce::Reflection) {
+            total_color += reflectance
+                * launch_reflected_ray(
+                    camera_ray,
+                    &collision_context,
+                    scene,
+                    depth,
+                    media,
+                    recorder,
+                    rng,
+                )?;
+        }
+        return Ok(total_color);
+    }
+
+    if nearest_object.effects().water.is_some() {
+        return launch_water_rays(
+            camera_ray,
+            &collision_context,
+            scene,
+            depth,
+            media,
+            recorder,
+            rng,
+        );
+    }
+
+    if scene.config.transparency == TransparencyMode::Composited
+        && nearest_object.effects().transparency.is_some()
+        && light_paths.follows(LightBounce::Refraction)
+    {
+        return composite_transparent_hits(
+            camera_ray,
+            collision_context,
+            scene,
+            depth,
+            kind,
+            media,
+            recorder,
+            rng,
+        );
+    }
+
     // After having found the nearest object, we launch a ray to the light
     let mut total_color = Color::BLACK;
-    total_color += illumination_from_lights(
-        &collision_context,
-        &scene.lights,
-        &scene.objects,
-        &camera_ray,
-    )?;
+    total_color +=
+        illumination_from_lights(&collision_context, scene, camera_ray, depth, recorder, rng)?;
 
     // Refraction light
-    if let Some(transparency) = &nearest_object.effects().transparency {
-        let surface_normal = nearest_object
-            .normal_at(collision_point)
-            .ok_or(RaytracerError::NormalNotFound(array_index))?
-            .normalize();
-        let n_ratio = scene.config.world_refractive_index / transparency.refractive_index;
-        let cos_refraction = camera_ray.direction.dot_product(surface_normal);
-        let sin_square_refraction = n_ratio.powi(2) * (1.0 - cos_refraction.powi(2));
-        let refraction_direction = n_ratio * camera_ray.direction
-            - (n_ratio * cos_refraction + (1.0 - sin_square_refraction).sqrt()) * surface_normal;
-        // Go up to object exterior
-        let refraction_ray = Ray::new(collision_point, refraction_direction).shift_source();
-        if let Some(collision_context) = search_object_collision(&refraction_ray, &scene.objects) {
-            // TODO only the nearest_object is necessary
-            // launch new ray
-            let exit_point = collision_context.collision_point;
-            let new_ray = Ray::new(exit_point, camera_ray.direction).shift_source();
-            total_color += transparency.alpha * launch_ray(&new_ray, scene, depth + 1)?;
+    let transparency = nearest_object.effects().transparency.as_ref();
+    if let Some(transparency) =
+        transparency.filter(|_| light_paths.follows(LightBounce::Refraction))
+    {
+        let (transmittance, reflectance) = if transparency.fresnel {
+            let inner_media = media.entering(array_index, transparency);
+            let reflectance =
+                fresnel_reflectance(camera_ray, &collision_context, scene, media, &inner_media)?;
+            (1.0 - reflectance, reflectance)
+        } else {
+            (transparency.alpha, 0.0)
+        };
+        let mut transmitted_color = Color::BLACK;
+        for (channel_mask, media) in media.dispersed(transparency) {
+            let inner_media = media.entering(array_index, transparency);
+            transmitted_color += channel_mask
+                * launch_transmitted_ray(
+                    camera_ray,
+                    &collision_context,
+                    scene,
+                    depth + 1,
+                    &media,
+                    &inner_media,
+                    recorder,
+                    rng,
+                )?;
+        }
+        total_color += transmittance * transmitted_color;
+        if reflectance > 0.0 && light_paths.follows(LightBounce::Reflection) {
+            total_color += reflectance
+                * launch_reflected_ray(
+                    camera_ray,
+                    &collision_context,
+                    scene,
+                    depth,
+                    media,
+                    recorder,
+                    rng,
+                )?;
         }
     }
 
-    // Reflexion
-    if let Some(mirror) = &nearest_object.effects().mirror {
-        let surface_normal = nearest_object
-            .normal_at(collision_point)
-            .ok_or(RaytracerError::NormalNotFound(array_index))?
-            .normalize();
-        let ray_reflexion = Ray::new(
-            collision_point,
-            camera_ray.direction.reflect(surface_normal).normalize(),
+    // Reflexion, blended with the surface color once it is known
+    let mut reflections = Vec::new();
+    let effects = nearest_object.effects();
+    if light_paths.follows(LightBounce::Reflection) {
+        if let Some(mirror) = effects.mirror.as_ref() {
+            let reflected_color = launch_reflected_ray(
+                camera_ray,
+                &collision_context,
+                scene,
+                depth,
+                media,
+                recorder,
+                rng,
+            )?;
+            reflections.push((mirror.coeff, reflected_color));
+        }
+        // The clear coat of car paints is above the rest, so it is blended last
+        if let Some(car_paint) = effects.car_paint.as_ref() {
+            let surface_normal = nearest_object
+                .normal_at(surface_point)
+                .ok_or(RaytracerError::NormalNotFound(array_index))?
+                .normalize();
+            let reflectance =
+                car_paint.clearcoat_reflectance(camera_ray.direction.dot_product(surface_normal));
+            let reflected_color = launch_reflected_ray(
+                camera_ray,
+                &collision_context,
+                scene,
+                depth,
+                media,
+                recorder,
+                rng,
+            )?;
+            reflections.push((reflectance, reflected_color));
+        }
+    }
+
+    // Light scattered by other surfaces, approximated by the ambient light if not traced
+    match scene.config.integrator {
+        Integrator::Whitted => {
+            let ambient_light = scene.config.ambient_light.as_ref();
+            if let Some(ambient_light) =
+                ambient_light.filter(|_| light_paths.accepts(LightContribution::Ambient, depth))
+            {
+                let shading = collision_context.shading(camera_ray, scene)?;
+                total_color += ambient_light * &nearest_object.color_in(&shading);
+            }
+        }
+        Integrator::Path if light_paths.follows(LightBounce::Diffuse) => {
+            total_color += launch_diffuse_ray(
+                camera_ray,
+                &collision_context,
+                scene,
+                depth,
+                media,
+                recorder,
+                rng,
+            )?;
+        }
+        Integrator::Path | Integrator::AmbientOcclusion => {}
+    }
+
+    for (reflectivity, reflected_color) in reflections {
+        total_color = (1.0 - reflectivity) * total_color + reflectivity * reflected_color;
+    }
+
+    Ok(total_color)
+}
+
+/// Transparent surfaces met by a ray blended front to back, each one adding its own light,
+/// lit by the lights and the ambient light, in proportion to what the surfaces in front of
+/// it let through. Surfaces where the ray leaves an object let everything through, as the
+/// refracted rays do. The first surface which is not composited, or the background, ends
+/// the ray, shaded as usual.
+#[allow(clippy::too_many_arguments)]
+fn composite_transparent_hits<'a>(
+    camera_ray: &Ray,
+    first_collision: CollisionContext<'a>,
+    scene: &'a Scene,
+    depth: u8,
+    kind: RayKind,
+    media: &MediaStack,
+    recorder: &mut dyn RayRecorder,
+    rng: &mut dyn RngCore,
+) -> Result<Color> {
+    let mut total_color = Color::BLACK;
+    let mut throughput = 1.0;
+    // Objects the ray went into, the next hit of one being where the ray leaves it
+    let mut entered: Vec<usize> = Vec::new();
+    let mut ray = Ray::new(camera_ray.source, camera_ray.direction).at_time(camera_ray.time);
+    let mut collision_context = first_collision;
+    for _ in 0..COMPOSITED_MAXIMUM_LAYERS {
+        let index = collision_context.array_index;
+        if let Some(position) = entered.iter().position(|&entered| entered == index) {
+            entered.swap_remove(position);
+        } else {
+            let effects = collision_context.object.effects();
+            let alpha = match effects.transparency.as_ref() {
+                Some(transparency) if is_composited(effects) => transparency.alpha,
+                _ => {
+                    let color = shade_surface(
+                        &ray,
+                        Some(collision_context),
+                        scene,
+                        depth,
+                        kind,
+                        media,
+                        recorder,
+                        rng,
+                    )?;
+                    return Ok(total_color + throughput * color);
+                }
+            };
+            let surface_color =
+                composited_surface_light(&ray, &collision_context, scene, depth, recorder, rng)?;
+            total_color += throughput * surface_color;
+            throughput *= alpha;
+            entered.push(index);
+        }
+        if throughput < COMPOSITED_MINIMUM_THROUGHPUT {
+            return Ok(total_color);
+        }
+        let behind_ray = Ray::new(collision_context.collision_point, ray.direction)
+            .at_time(collision_context.time)
+            .shift_source();
+        let (behind_ray, behind_collision) =
+            search_ray_collision(&behind_ray, scene, RayKind::Refraction);
+        ray = behind_ray;
+        collision_context = match behind_collision {
+            Some(behind_collision) => behind_collision,
+            None => {
+                let background = shade_surface(
+                    &ray,
+                    None,
+                    scene,
+                    depth,
+                    RayKind::Refraction,
+                    media,
+                    recorder,
+                    rng,
+                )?;
+                return Ok(total_color + throughput * background);
+            }
+        };
+    }
+    Ok(total_color)
+}
+
+/// Transparent objects without another effect replacing their shading are composited
+fn is_composited(effects: &TextureEffects) -> bool {
+    effects.transparency.is_some()
+        && !effects.holdout
+        && !effects.shadow_catcher
+        && effects.emission.is_none()
+        && effects.water.is_none()
+}
+
+/// Light of a composited surface itself, from the lights and the ambient light
+fn composited_surface_light(
+    ray: &Ray,
+    collision_context: &CollisionContext,
+    scene: &Scene,
+    depth: u8,
+    recorder: &mut dyn RayRecorder,
+    rng: &mut dyn RngCore,
+) -> Result<Color> {
+    let mut color = illumination_from_lights(collision_context, scene, ray, depth, recorder, rng)?;
+    let ambient_light = scene.config.ambient_light.as_ref().filter(|_| {
+        scene.config.integrator == Integrator::Whitted
+            && scene
+                .config
+                .light_paths
+                .accepts(LightContribution::Ambient, depth)
+    });
+    if let Some(ambient_light) = ambient_light {
+        let shading = collision_context.shading(ray, scene)?;
+        color += ambient_light * &collision_context.object.color_in(&shading);
+    }
+    Ok(color)
+}
+
+/// Light emitted by a volume along a ray crossing it, added to the light coming from
+/// behind the volume
+#[allow(clippy::too_many_arguments)]
+fn launch_ray_through_volume(
+    incident_ray: &Ray,
+    collision_context: &CollisionContext,
+    scene: &Scene,
+    depth: u8,
+    kind: RayKind,
+    media: &MediaStack,
+    recorder: &mut dyn RayRecorder,
+    rng: &mut dyn RngCore,
+) -> Result<Color> {
+    let direction = incident_ray.direction;
+    let entry = collision_context.collision_point;
+    let time = collision_context.time;
+    let inner_ray = Ray::new(entry + VOLUME_RAY_OFFSET * direction, direction).at_time(time);
+    let exit = collision_context
+        .object
+        .check_collision(&inner_ray)
+        .unwrap_or(entry);
+    let (emitted, transmittance) = match collision_context.object.effects().emission.as_ref() {
+        Some(emission) => emission.integrate(entry, exit),
+        None => (Color::BLACK, 1.0),
+    };
+    let behind_ray = Ray::new(exit + VOLUME_RAY_OFFSET * direction, direction).at_time(time);
+    let behind_color = launch_recorded_ray(&behind_ray, scene, depth, kind, media, recorder, rng)?;
+    let mut total_color = transmittance * behind_color;
+    if scene
+        .config
+        .light_paths
+        .accepts(LightContribution::Emission, depth)
+    {
+        total_color += emitted;
+    }
+    Ok(total_color)
+}
+
+/// Follow one ray scattered by a diffuse surface, in a random direction
+fn launch_diffuse_ray(
+    incident_ray: &Ray,
+    collision_context: &CollisionContext,
+    scene: &Scene,
+    depth: u8,
+    media: &MediaStack,
+    recorder: &mut dyn RayRecorder,
+    rng: &mut dyn RngCore,
+) -> Result<Color> {
+    let shading = collision_context.shading(incident_ray, scene)?;
+    let albedo = collision_context.object.color_in(&shading);
+    // Russian roulette, the rays which survive being brighter so the result is unbiased
+    let mut throughput = 1.0;
+    if depth >= scene.config.russian_roulette_depth {
+        let survival = albedo
+            .red()
+            .max(albedo.green())
+            .max(albedo.blue())
+            .clamp(0.05, 1.0);
+        if rng.gen::<f64>() >= survival {
+            return Ok(Color::BLACK);
+        }
+        throughput = 1.0 / survival;
+    }
+    let surface_normal = collision_context.object.shading_normal_in(&shading);
+    let surface_normal = if incident_ray.direction.dot_product(surface_normal) > 0.0 {
+        -surface_normal
+    } else {
+        surface_normal
+    };
+    let diffuse_ray = Ray::new(
+        collision_context.collision_point,
+        cosine_weighted_direction(surface_normal, rng),
+    )
+    .at_time(collision_context.time)
+    .shift_source();
+    let incoming = launch_recorded_ray(
+        &diffuse_ray,
+        scene,
+        depth + 1,
+        RayKind::Diffuse,
+        media,
+        recorder,
+        rng,
+    )?;
+    // The cosine of the Lambert law is cancelled by the probability of the direction
+    Ok(throughput * &(albedo * incoming))
+}
+
+/// Distance from the surface where occlusion rays start
+const OCCLUSION_RAY_OFFSET: f64 = 1e-6;
+
+/// Distance after the volume boundaries where rays crossing it restart
+const VOLUME_RAY_OFFSET: f64 = 1e-6;
+
+/// Transparent surfaces blended along a ray before it is given up
+const COMPOSITED_MAXIMUM_LAYERS: usize = 64;
+
+/// Part of the light let through the composited surfaces below which what is behind them
+/// is not seen anymore
+const COMPOSITED_MINIMUM_THROUGHPUT: f64 = 1e-3;
+
+/// Part of the rays sent around the normal which are not stopped by an object, 1 for
+/// a surface fully open to its surroundings
+fn ambient_openness(
+    incident_ray: &Ray,
+    collision_context: &CollisionContext,
+    scene: &Scene,
+    depth: u8,
+    recorder: &mut dyn RayRecorder,
+    rng: &mut dyn RngCore,
+) -> Result<f64> {
+    let config = &scene.config.ambient_occlusion;
+    let shading = collision_context.shading(incident_ray, scene)?;
+    let surface_normal = collision_context.object.shading_normal_in(&shading);
+    let surface_normal = if incident_ray.direction.dot_product(surface_normal) > 0.0 {
+        -surface_normal
+    } else {
+        surface_normal
+    };
+    let samples = config.samples.max(1);
+    let distance = config.distance.unwrap_or(f64::INFINITY);
+    let mut open_rays = 0;
+    for _ in 0..samples {
+        let direction = cosine_weighted_direction(surface_normal, rng);
+        // Moved away from the surface, or rays grazing it would hit it again
+        let occlusion_ray = Ray::new(
+            collision_context.collision_point + OCCLUSION_RAY_OFFSET * surface_normal,
+            direction,
         )
-        .shift_source();
-        total_color += mirror.coeff * launch_ray(&ray_reflexion, scene, depth + 1)?;
+        .at_time(collision_context.time);
+        let obstacle = search_obstacle(&occlusion_ray, distance, &scene.objects);
+        recorder.record(RayKind::Diffuse, depth + 1, &occlusion_ray, obstacle);
+        if obstacle.is_none() {
+            open_rays += 1;
+        }
     }
+    Ok(open_rays as f64 / samples as f64)
+}
 
-    // Ambient light
-    if let Some(ambient_light) = &scene.config.ambient_light {
-        total_color += ambient_light * &nearest_object.color_at(collision_point);
+/// Random direction around the normal, more probable the closer to the normal it is
+fn cosine_weighted_direction(normal: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+    let (axis_u, axis_v) = plane_axes(normal);
+    let radius = rng.gen::<f64>().sqrt();
+    let angle = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+    let height = (1.0 - radius * radius).max(0.0).sqrt();
+    (radius * angle.cos() * axis_u + radius * angle.sin() * axis_v + height * normal).normalize()
+}
+
+/// Direction given by Snell's law to a ray going through a surface whose normal faces
+/// it, none if the ray is totally reflected
+fn refracted_direction(direction: Vec3, surface_normal: Vec3, n_ratio: f64) -> Option<Vec3> {
+    let cos_incident = -direction.dot_product(surface_normal);
+    let sin_square_refraction = n_ratio.powi(2) * (1.0 - cos_incident.powi(2));
+    if sin_square_refraction > 1.0 {
+        return None;
     }
+    Some(
+        n_ratio * direction
+            + (n_ratio * cos_incident - (1.0 - sin_square_refraction).sqrt()) * surface_normal,
+    )
+}
 
+/// Light reflected and refracted by a water surface, with its tilted normal, in the
+/// proportions given by the Fresnel equations
+fn launch_water_rays(
+    incident_ray: &Ray,
+    collision_context: &CollisionContext,
+    scene: &Scene,
+    depth: u8,
+    media: &MediaStack,
+    recorder: &mut dyn RayRecorder,
+    rng: &mut dyn RngCore,
+) -> Result<Color> {
+    let CollisionContext {
+        object,
+        collision_point,
+        array_index,
+        time,
+        ..
+    } = *collision_context;
+    let water = match object.effects().water.as_ref() {
+        Some(water) => water,
+        None => return Ok(Color::BLACK),
+    };
+    let light_paths = &scene.config.light_paths;
+    let shading = collision_context.shading(incident_ray, scene)?;
+    let surface_normal = object.shading_normal_in(&shading);
+    let surface_normal = if incident_ray.direction.dot_product(surface_normal) > 0.0 {
+        -surface_normal
+    } else {
+        surface_normal
+    };
+    let outside_refractive_index = media.refractive_index(scene.config.world_refractive_index);
+    let n_ratio = outside_refractive_index / water.refractive_index;
+    let cos_incident = -incident_ray.direction.dot_product(surface_normal);
+    let refraction_direction = refracted_direction(incident_ray.direction, surface_normal, n_ratio);
+    let reflectance = match refraction_direction {
+        Some(_) => water.reflectance(cos_incident, outside_refractive_index),
+        None => 1.0,
+    };
+
+    let mut total_color =
+        illumination_from_lights(collision_context, scene, incident_ray, depth, recorder, rng)?;
+    if light_paths.follows(LightBounce::Reflection) {
+        let reflection_ray = Ray::new(
+            collision_point,
+            incident_ray.direction.reflect(surface_normal),
+        )
+        .at_time(time)
+        .shift_source();
+        total_color += reflectance
+            * launch_recorded_ray(
+                &reflection_ray,
+                scene,
+                depth + 1,
+                RayKind::Reflection,
+                media,
+                recorder,
+                rng,
+            )?;
+    }
+    if let Some(refraction_direction) =
+        refraction_direction.filter(|_| light_paths.follows(LightBounce::Refraction))
+    {
+        let transparency = Transparency {
+            refractive_index: water.refractive_index,
+            ..Default::default()
+        };
+        let refraction_ray = Ray::new(collision_point, refraction_direction)
+            .at_time(time)
+            .shift_source();
+        let transmitted_color = launch_recorded_ray(
+            &refraction_ray,
+            scene,
+            depth + 1,
+            RayKind::Refraction,
+            &media.entering(array_index, &transparency),
+            recorder,
+            rng,
+        )?;
+        total_color += (1.0 - reflectance) * (&water.color * &transmitted_color);
+    }
     Ok(total_color)
 }
 
+/// Follow the ray reflected at the collision point, as by a perfect mirror
+fn launch_reflected_ray(
+    incident_ray: &Ray,
+    collision_context: &CollisionContext,
+    scene: &Scene,
+    depth: u8,
+    media: &MediaStack,
+    recorder: &mut dyn RayRecorder,
+    rng: &mut dyn RngCore,
+) -> Result<Color> {
+    let surface_normal = collision_context
+        .object
+        .normal_at(collision_context.surface_point)
+        .ok_or(RaytracerError::NormalNotFound(
+            collision_context.array_index,
+        ))?
+        .normalize();
+    let ray_reflexion = Ray::new(
+        collision_context.collision_point,
+        incident_ray.direction.reflect(surface_normal).normalize(),
+    )
+    .at_time(collision_context.time)
+    .shift_source();
+    launch_recorded_ray(
+        &ray_reflexion,
+        scene,
+        depth + 1,
+        RayKind::Reflection,
+        media,
+        recorder,
+        rng,
+    )
+}
+
+/// Part of the light reflected where the ray goes from a medium to the next one
+fn fresnel_reflectance(
+    incident_ray: &Ray,
+    collision_context: &CollisionContext,
+    scene: &Scene,
+    media: &MediaStack,
+    next_media: &MediaStack,
+) -> Result<f64> {
+    let surface_normal = collision_context
+        .object
+        .normal_at(collision_context.surface_point)
+        .ok_or(RaytracerError::NormalNotFound(
+            collision_context.array_index,
+        ))?
+        .normalize();
+    let world_refractive_index = scene.config.world_refractive_index;
+    Ok(schlick_reflectance(
+        incident_ray.direction.dot_product(surface_normal),
+        media.refractive_index(world_refractive_index),
+        next_media.refractive_index(world_refractive_index),
+    ))
+}
+
+/// Refract the ray at the boundary between two media with Snell's law, and follow it
+/// on the other side
+#[allow(clippy::too_many_arguments)]
+fn launch_transmitted_ray(
+    incident_ray: &Ray,
+    collision_context: &CollisionContext,
+    scene: &Scene,
+    depth: u8,
+    media: &MediaStack,
+    next_media: &MediaStack,
+    recorder: &mut dyn RayRecorder,
+    rng: &mut dyn RngCore,
+) -> Result<Color> {
+    let world_refractive_index = scene.config.world_refractive_index;
+    let surface_normal = collision_context
+        .object
+        .normal_at(collision_context.surface_point)
+        .ok_or(RaytracerError::NormalNotFound(
+            collision_context.array_index,
+        ))?
+        .normalize();
+    // The normal must face the incident ray, it points outside when leaving an object
+    let surface_normal = if incident_ray.direction.dot_product(surface_normal) > 0.0 {
+        -surface_normal
+    } else {
+        surface_normal
+    };
+    let n_ratio = media.refractive_index(world_refractive_index)
+        / next_media.refractive_index(world_refractive_index);
+    let refraction_direction =
+        match refracted_direction(incident_ray.direction, surface_normal, n_ratio) {
+            Some(refraction_direction) => refraction_direction,
+            None => {
+                // Total internal reflection, the light stays in the current medium
+                let reflection_ray = Ray::new(
+                    collision_context.collision_point,
+                    incident_ray.direction.reflect(surface_normal),
+                )
+                .at_time(collision_context.time)
+                .shift_source();
+                return launch_recorded_ray(
+                    &reflection_ray,
+                    scene,
+                    depth + 1,
+                    RayKind::Reflection,
+                    media,
+                    recorder,
+                    rng,
+                );
+            }
+        };
+    let refraction_ray = Ray::new(collision_context.collision_point, refraction_direction)
+        .at_time(collision_context.time)
+        .shift_source();
+    launch_recorded_ray(
+        &refraction_ray,
+        scene,
+        depth,
+        RayKind::Refraction,
+        next_media,
+        recorder,
+        rng,
+    )
+}
+
 pub struct CollisionContext<'a> {
     pub object: &'a SceneObject,
     pub collision_point: Vec3,
+    /// Point of the surface hit as the object is at the frame, the same as the collision
+    /// point unless the object moves while the shutter is open
+    pub surface_point: Vec3,
     pub array_index: usize,
+    /// Time of the ray hitting the object, given to the rays leaving it
+    pub time: f64,
+}
+
+impl CollisionContext<'_> {
+    /// What the textures and effects know of the point, reached by the ray
+    pub fn shading(&self, ray: &Ray, scene: &Scene) -> Result<ShadingContext> {
+        let context = self.object.shading_context(
+            self.surface_point,
+            ray.direction,
+            self.time,
+            self.array_index,
+        );
+        let context = context.ok_or(RaytracerError::NormalNotFound(self.array_index))?;
+        Ok(context.with_scene_time(scene.config.time()))
+    }
 }
 
 fn search_object_collision<'a>(
     ray: &Ray,
-    objects: &'a [SceneObject],
+    objects: &'a SceneObjects,
+    maximum_distance: f64,
 ) -> Option<CollisionContext<'a>> {
-    let mut shortest_distance: f64 = f64::MAX;
     let mut nearest_object_opt: Option<&SceneObject> = None;
     let mut collision_point: Vec3 = Default::default();
-    let mut array_index = std::usize::MAX;
-    // For each pixel, we search for collision with objects
+    let mut array_index = usize::MAX;
+    // For each pixel, we search for collision with objects whose bounding box is crossed
     // We also take into account the nearest object, for now
-    for (index, object_candidate) in objects.iter().enumerate() {
-        if let Some(collision_point_candidate) = object_candidate.check_collision(&ray) {
-            let distance = collision_point_candidate.distance(ray.source);
-            if distance <= 1e-12 {
-                continue;
-            } else if distance < shortest_distance {
-                shortest_distance = distance;
-                nearest_object_opt = Some(object_candidate);
-                collision_point = collision_point_candidate;
-                array_index = index;
+    objects
+        .bvh()
+        .visit(ray, maximum_distance, |index, shortest_distance| {
+            let object_candidate = &objects[index];
+            if let Some(collision_point_candidate) = object_candidate.check_collision(ray) {
+                let distance = collision_point_candidate.distance(ray.source);
+                if distance > 1e-12 && distance < shortest_distance {
+                    nearest_object_opt = Some(object_candidate);
+                    collision_point = collision_point_candidate;
+                    array_index = index;
+                    return distance;
+                }
             }
-        }
-    }
+            shortest_distance
+        });
     nearest_object_opt.map(|n| CollisionContext {
         object: n,
         collision_point,
+        surface_point: collision_point - n.shape.displacement_at(ray.time),
         array_index,
+        time: ray.time,
     })
 }
 
 fn illumination_from_lights(
     collision_context: &CollisionContext,
-    lights: &[Box<dyn AnyLightObject>],
-    objects: &[SceneObject],
+    scene: &Scene,
     camera_ray: &Ray,
+    depth: u8,
+    recorder: &mut dyn RayRecorder,
+    rng: &mut dyn RngCore,
 ) -> Result<Color> {
+    let light_paths = &scene.config.light_paths;
     let mut total_color = Color::BLACK;
-    let surface_point = collision_context.collision_point;
-    let object = collision_context.object;
-    for current_light in lights {
-        let light_ray = Ray::ray_from_to(surface_point, current_light.source());
-
+    let with_diffuse = light_paths.accepts(LightContribution::Diffuse, depth);
+    let with_specular = light_paths.accepts(LightContribution::Specular, depth);
+    if !with_diffuse && !with_specular {
+        return Ok(total_color);
+    }
+    let CollisionContext {
+        object,
+        collision_point,
+        time,
+        ..
+    } = *collision_context;
+    let shading = collision_context.shading(camera_ray, scene)?;
+    let surface_normal = object.shading_normal_in(&shading);
+    // Lights with an area are sampled at several points, so they are partially hidden
+    // in the penumbra
+    let light_samples = scene.lights.iter().flat_map(|light| {
+        let shadow_rays = light.shadow_rays(collision_point, scene.config.area_light_samples, rng);
+        let weight = 1.0 / shadow_rays.len() as f64;
+        shadow_rays
+            .into_iter()
+            .map(move |(ray, distance)| (light, ray.at_time(time), distance, weight))
+    });
+    for (current_light, light_ray, light_distance, weight) in light_samples {
         // Generate shadow, by skipping process if there is an obstacle between object and light
-        if ray_encounter_obstacle(&light_ray, &current_light.source(), objects) {
+        let (obstacle, transmittance) = search_shadow(&light_ray, light_distance, scene);
+        let light_end = Some(light_ray.source + light_distance * light_ray.direction)
+            .filter(|_| light_distance.is_finite());
+        recorder.record(RayKind::Shadow, depth, &light_ray, obstacle.or(light_end));
+        if obstacle.is_some() {
             continue;
         }
 
         // Build values needed for light computation
         let light_direction = light_ray.direction;
-        let light_color = current_light.color_for_ray(light_ray);
-        let surface_normal =
-            object
-                .normal_at(surface_point)
-                .ok_or(RaytracerError::NormalNotFound(
-                    collision_context.array_index,
-                ))?;
+        let light_color = weight * (current_light.color_for_ray(light_ray) * transmittance);
         let ray_reflexion = camera_ray.direction.reflect(surface_normal).normalize();
 
         // Diffuse reflection
         let reflection_angle = light_direction.dot_product(surface_normal);
-        if reflection_angle > 0.0 {
-            total_color +=
-                reflection_angle * &(light_color.clone() * object.color_at(surface_point));
+        if with_diffuse && reflection_angle > 0.0 {
+            total_color += reflection_angle * &(light_color.clone() * object.color_in(&shading));
+        }
+
+        // Sheen, strongest where the surface is seen edge-on
+        if let Some(sheen) = object.effects().sheen.as_ref().filter(|_| with_diffuse) {
+            if reflection_angle > 0.0 {
+                let facing = camera_ray.direction.dot_product(surface_normal.normalize());
+                let rim = (1.0 - facing.abs()).powf(sheen.power);
+                total_color += (reflection_angle * rim * sheen.coeff)
+                    * &(light_color.clone() * sheen.color.clone());
+            }
+        }
+
+        // Highlights of car paints, tinted by the metal base and sparkling on the flakes
+        if let Some(car_paint) = object
+            .effects()
+            .car_paint
+            .as_ref()
+            .filter(|_| with_specular)
+        {
+            let base_angle = light_direction.dot_product(ray_reflexion);
+            if base_angle > 0.0 {
+                total_color += base_angle.powi(car_paint.metallic_size as i32)
+                    * &(light_color.clone() * object.color_in(&shading));
+            }
+            let flake_normal = car_paint.flake_normal_at(&shading.with_normal(surface_normal));
+            if let Some(flake_normal) = flake_normal {
+                let flake_reflexion = camera_ray.direction.reflect(flake_normal).normalize();
+                let flake_angle = light_direction.dot_product(flake_reflexion);
+                if flake_angle > 0.0 {
+                    total_color += light_color.clone()
+                        * flake_angle.powi(FLAKE_SHININESS)
+                        * car_paint.flake_brightness;
+                }
+            }
         }
 
         // Add specular / phong light
-        if let Some(phong) = &object.effects().phong {
+        if let Some(phong) = object.effects().phong.as_ref().filter(|_| with_specular) {
             let specular_angle = light_direction.dot_product(ray_reflexion);
             if specular_angle > 0.0 {
                 total_color += light_color.clone()
@@ -247,26 +1248,115 @@ fn illumination_from_lights(
     Ok(total_color)
 }
 
-#[allow(clippy::if_same_then_else)]
-fn ray_encounter_obstacle(ray: &Ray, destination: &Vec3, objects: &[SceneObject]) -> bool {
-    let source = ray.source;
-    let light_distance = Vec3::between_points(source, *destination).norm();
-    // Check of object obstruction between light and collision point
-    for candidate_object in objects {
-        if let Some(obstruction_point) = candidate_object.check_collision(ray) {
-            let object_distance = Vec3::between_points(source, obstruction_point).norm();
-            if object_distance > light_distance {
-                // Not between the object and the light
-                continue;
-            } else if object_distance <= 1e-12 {
-                // TODO Check why this value is so high, it was f64::EPSILON before
-                // Float comparison error, source is probably also the candidate object
-                continue;
-            } else {
-                // Object is hiding an other
-                return true;
+/// Part of the light reaching the point, between 0 in full shadow and 1, each light
+/// counting the same
+fn light_visibility(
+    collision_context: &CollisionContext,
+    scene: &Scene,
+    depth: u8,
+    recorder: &mut dyn RayRecorder,
+    rng: &mut dyn RngCore,
+) -> f64 {
+    if scene.lights.is_empty() {
+        return 1.0;
+    }
+    let light_weight = 1.0 / scene.lights.len() as f64;
+    let mut visibility = 0.0;
+    for light in scene.lights.iter() {
+        let shadow_rays = light.shadow_rays(
+            collision_context.collision_point,
+            scene.config.area_light_samples,
+            rng,
+        );
+        let sample_weight = light_weight / shadow_rays.len() as f64;
+        for (light_ray, light_distance) in shadow_rays {
+            let light_ray = light_ray.at_time(collision_context.time);
+            let (obstacle, transmittance) = search_shadow(&light_ray, light_distance, scene);
+            let light_end = Some(light_ray.source + light_distance * light_ray.direction)
+                .filter(|_| light_distance.is_finite());
+            recorder.record(RayKind::Shadow, depth, &light_ray, obstacle.or(light_end));
+            if obstacle.is_none() {
+                let transmitted =
+                    (transmittance.red() + transmittance.green() + transmittance.blue()) / 3.0;
+                visibility += sample_weight * transmitted;
             }
         }
     }
-    false
+    visibility
+}
+
+/// Point where a ray is first stopped by an object casting shadows, whatever the clipping
+/// of the camera
+pub(crate) fn shadow_caster_hit(ray: &Ray, scene: &Scene) -> Option<Vec3> {
+    search_obstacle(ray, f64::INFINITY, &scene.objects)
+}
+
+/// Search an object hiding the light, which can be at an infinite distance
+fn search_obstacle(ray: &Ray, light_distance: f64, objects: &SceneObjects) -> Option<Vec3> {
+    search_obstacle_through(ray, light_distance, objects, None).0
+}
+
+/// Search an object hiding the light, the light going through the transparent objects
+/// casting colored shadows, each one tinting it once. Gives the part of the light going
+/// through them.
+fn search_shadow(ray: &Ray, light_distance: f64, scene: &Scene) -> (Option<Vec3>, Color) {
+    let scene_time = Some(scene.config.time());
+    search_obstacle_through(ray, light_distance, &scene.objects, scene_time)
+}
+
+/// Obstacle on the way of the light, the objects casting colored shadows tinting it at
+/// the time of the scene if it is given, hiding it otherwise
+fn search_obstacle_through(
+    ray: &Ray,
+    light_distance: f64,
+    objects: &SceneObjects,
+    colored_shadows_time: Option<f64>,
+) -> (Option<Vec3>, Color) {
+    count(Event::ShadowRay);
+    let source = ray.source;
+    let mut obstacle = None;
+    let mut transmittance = Color::WHITE;
+    // Check of object obstruction between light and collision point
+    objects
+        .bvh()
+        .visit(ray, light_distance, |index, maximum_distance| {
+            if obstacle.is_some() {
+                return maximum_distance;
+            }
+            // Emissive volumes and shadowless objects let the light go through
+            let effects = objects[index].effects();
+            if effects.emission.is_some() || effects.shadowless {
+                return maximum_distance;
+            }
+            if let Some(obstruction_point) = objects[index].check_collision(ray) {
+                let object_distance = Vec3::between_points(source, obstruction_point).norm();
+                if object_distance > light_distance {
+                    // Not between the object and the light
+                } else if object_distance <= 1e-12 {
+                    // TODO Check why this value is so high, it was f64::EPSILON before
+                    // Float comparison error, source is probably also the candidate object
+                } else if let Some((transparency, scene_time)) = effects
+                    .transparency
+                    .as_ref()
+                    .filter(|t| t.colored_shadow)
+                    .zip(colored_shadows_time)
+                {
+                    let object = &objects[index];
+                    let surface_point = obstruction_point - object.shape.displacement_at(ray.time);
+                    let shading =
+                        object.shading_context(surface_point, ray.direction, ray.time, index);
+                    if let Some(shading) = shading {
+                        let shading = shading.with_scene_time(scene_time);
+                        let filter = transparency.alpha * object.color_in(&shading);
+                        transmittance = transmittance.clone() * filter;
+                    }
+                } else {
+                    // Object is hiding an other, no other box needs to be visited
+                    obstacle = Some(obstruction_point);
+                    return -1.0;
+                }
+            }
+            maximum_distance
+        });
+    (obstacle, transmittance)
 }
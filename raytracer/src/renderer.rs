@@ -22,26 +22,134 @@ OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE.
 */
 
+use crate::cameras::EquirectangularCamera;
+use crate::checkpoint::Checkpoint;
 use crate::colors::Color;
+use crate::environment::LatLongMap;
+use crate::interpolation::MotionVectors;
+use crate::memory::MemoryUsage;
+use crate::progress::{ProgressCallback, ProgressTracker};
 use crate::ray_algorithm::strategy::StandardRenderStrategy;
-use crate::ray_algorithm::AnyPixelRenderStrategy;
+use crate::ray_algorithm::{camera_ray_hit, AnyPixelRenderStrategy, PixelSample};
 use crate::result::{RaytracerError, Result};
-use crate::scene::Scene;
+use crate::sampling::pixel_rng;
+use crate::scene::{Integrator, Scene};
+use crate::stats::{RenderStats, StatsCounters};
+use crate::tiles::{Tile, TileOrder};
+use crate::vector::Vec3;
+use crate::UnitInterval;
 use instant::Instant;
 use log::{debug, info, trace, warn};
-use std::iter::from_fn;
-use std::sync::mpsc;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::iter::once_with;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Pixel {
     pub x: u32,
     pub y: u32,
     pub color: Color,
+    /// Opacity, zero where holdout objects cut the image
+    pub alpha: UnitInterval,
 }
 
 impl Pixel {
     pub fn new(x: u32, y: u32, color: Color) -> Pixel {
-        Pixel { x, y, color }
+        Pixel::with_alpha(x, y, color, 1.0)
+    }
+
+    pub fn with_alpha(x: u32, y: u32, color: Color, alpha: UnitInterval) -> Pixel {
+        Pixel { x, y, color, alpha }
+    }
+
+    fn from_sample(x: u32, y: u32, sample: PixelSample, tone_mapping: &ToneMapping) -> Pixel {
+        Pixel::with_alpha(x, y, tone_mapping.apply(&sample.color), sample.alpha)
+    }
+}
+
+/// Curve bringing the computed light, which can go beyond 1, back into displayable colors
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMappingOperator {
+    /// Values beyond 1 are cut, so bright areas blow out
+    #[default]
+    Clamp,
+    /// `c / (1 + c)`, never reaching full white
+    Reinhard,
+    /// Krzysztof Narkowicz approximation of the ACES filmic curve
+    Aces,
+}
+
+impl FromStr for ToneMappingOperator {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_ref() {
+            "clamp" => ToneMappingOperator::Clamp,
+            "reinhard" => ToneMappingOperator::Reinhard,
+            "aces" => ToneMappingOperator::Aces,
+            other => return Err(format!("{} is not a valid tone mapping operator", other)),
+        })
+    }
+}
+
+/// Last stage of the rendering of a pixel, from the computed light to its color
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToneMapping {
+    pub operator: ToneMappingOperator,
+    /// In stops, each one doubling the light
+    pub exposure: f64,
+    /// Gamma applied after the curve, before the sRGB output encoding, 1 changing nothing
+    pub gamma: f64,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            operator: Default::default(),
+            exposure: 0.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl ToneMapping {
+    pub fn apply(&self, color: &Color) -> Color {
+        let exposure = self.exposure.exp2();
+        let map = |value: f64| {
+            let value = (exposure * value).max(0.0);
+            let mapped = match self.operator {
+                ToneMappingOperator::Clamp => value,
+                ToneMappingOperator::Reinhard => value / (1.0 + value),
+                ToneMappingOperator::Aces => {
+                    (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)
+                }
+            };
+            mapped.min(1.0).powf(1.0 / self.gamma)
+        };
+        Color::new(map(color.red()), map(color.green()), map(color.blue()))
+    }
+}
+
+/// Flag stopping the renderings it is given to, its clones sharing it, so a window being
+/// closed or a web page can abort a rendering in another thread
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Stop the renderings: they render no new tile or row, and end once the pixels
+    /// already computed are given with a last `RaytracerError::Cancelled`
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
     }
 }
 
@@ -49,6 +157,19 @@ pub struct RenderConfiguration {
     pub canvas_width: u32,
     pub canvas_height: u32,
     pub render_strategy: Box<dyn AnyPixelRenderStrategy>,
+    pub renderer: RendererKind,
+    /// Side, in pixels, of the square canvas areas rendered one at a time
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    /// Tiles already rendered, left out by the sequential and the tiled renderers, the
+    /// other ones rendering them again
+    pub skipped_tiles: Vec<Tile>,
+    /// Counters the pixels rendered with this configuration add their work to
+    pub stats: StatsCounters,
+    /// Called back with the progress of `render_scene` and the functions like it, when a
+    /// new percent of the pixels or a tile is done
+    pub progress: Option<ProgressCallback>,
+    pub cancellation: CancellationToken,
 }
 
 impl Default for RenderConfiguration {
@@ -57,24 +178,139 @@ impl Default for RenderConfiguration {
             canvas_width: 1024,
             canvas_height: 576,
             render_strategy: Box::new(StandardRenderStrategy),
+            renderer: Default::default(),
+            tile_size: DEFAULT_TILE_SIZE,
+            tile_order: Default::default(),
+            skipped_tiles: Vec::new(),
+            stats: Default::default(),
+            progress: None,
+            cancellation: Default::default(),
         }
     }
 }
 
+impl RenderConfiguration {
+    /// Tiles covering the canvas but the skipped ones, in the order they are rendered
+    pub fn tiles(&self) -> Vec<Tile> {
+        let (width, height) = (self.canvas_width, self.canvas_height);
+        let mut tiles = self.tile_order.tiles(width, height, self.tile_size);
+        tiles.retain(|tile| !self.skipped_tiles.contains(tile));
+        tiles
+    }
+}
+
+/// Way the pixels of a canvas are computed, by one thread or shared between several
+pub trait Renderer: Send + Sync {
+    /// Every pixel of the canvas at least once, in any order, the last one given for a
+    /// position being the final one
+    fn render(
+        &self,
+        scene: Scene,
+        config: RenderConfiguration,
+    ) -> Box<dyn Iterator<Item = Result<Pixel>>>;
+
+    /// Pixels given by `render`, more than those of the canvas when some are given again
+    fn pixel_count(&self, canvas_width: u32, canvas_height: u32) -> u64 {
+        canvas_width as u64 * canvas_height as u64
+    }
+
+    /// Pixels computed together by each thread before being given, none when there is a
+    /// single thread giving them as they are computed
+    fn batch_pixels(&self, config: &RenderConfiguration) -> Option<usize>;
+}
+
+/// Renderer of a configuration, built by `renderer`. Another backend only needs its own
+/// `Renderer` to be rendered with `render_scene_with`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RendererKind {
+    /// Tiles computed one after another in the calling thread, as in WebAssembly
+    Sequential,
+    /// Rows of pixels shared between threads
+    Threaded,
+    /// Square tiles shared between threads, keeping the rays of a task close
+    #[default]
+    Tiled,
+    /// Coarse image first, sharpened pass after pass, to see the whole picture quickly
+    Progressive,
+}
+
+impl RendererKind {
+    pub fn renderer(self) -> Box<dyn Renderer> {
+        match self {
+            RendererKind::Sequential => Box::new(SequentialRenderer),
+            RendererKind::Threaded => Box::new(ThreadedRenderer),
+            RendererKind::Tiled => Box::new(TiledRenderer),
+            RendererKind::Progressive => Box::new(ProgressiveRenderer {
+                coarse_step: PROGRESSIVE_COARSE_STEP,
+                threaded: !cfg!(target_arch = "wasm32"),
+            }),
+        }
+    }
+}
+
+impl FromStr for RendererKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_ref() {
+            "sequential" => RendererKind::Sequential,
+            "threaded" => RendererKind::Threaded,
+            "tiled" => RendererKind::Tiled,
+            "progressive" => RendererKind::Progressive,
+            other => return Err(format!("{} is not a valid renderer", other)),
+        })
+    }
+}
+
+/// Pixels given by a rendering, with the counts of the work done to compute them
+pub struct RenderedPixels<I> {
+    pixels: I,
+    stats: StatsCounters,
+}
+
+impl<I> RenderedPixels<I> {
+    /// Work done so far, complete once every pixel is given
+    pub fn stats(&self) -> RenderStats {
+        self.stats.snapshot()
+    }
+}
+
+impl<I: Iterator<Item = Result<Pixel>>> Iterator for RenderedPixels<I> {
+    type Item = Result<Pixel>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pixels.next()
+    }
+}
+
+/// Render with the renderer of the configuration
 pub fn render_scene(
     scene: Scene,
     config: RenderConfiguration,
-    parallel: bool,
-) -> Result<impl Iterator<Item = Result<Pixel>>> {
-    render_scene_with_finally(scene, config, parallel, || {})
+) -> Result<RenderedPixels<impl Iterator<Item = Result<Pixel>>>> {
+    render_scene_with_finally(scene, config, || {})
 }
 
 pub fn render_scene_with_finally<F>(
     scene: Scene,
     config: RenderConfiguration,
-    parallel: bool,
+    finally: F,
+) -> Result<RenderedPixels<impl Iterator<Item = Result<Pixel>>>>
+where
+    F: FnMut(),
+{
+    let renderer = config.renderer.renderer();
+    render_scene_with(renderer.as_ref(), scene, config, finally)
+}
+
+/// Render with any renderer, `finally` being called once every pixel is given, or once the
+/// rendering is cancelled, a `RaytracerError::Cancelled` then ending the pixels
+pub fn render_scene_with<F>(
+    renderer: &dyn Renderer,
+    scene: Scene,
+    mut config: RenderConfiguration,
     mut finally: F,
-) -> Result<impl Iterator<Item = Result<Pixel>>>
+) -> Result<RenderedPixels<impl Iterator<Item = Result<Pixel>>>>
 where
     F: FnMut(),
 {
@@ -83,13 +319,26 @@ where
     }
     debug!("render: {} objects to process", scene.objects.len());
     debug!("render: {} lights to process", scene.lights.len());
-    if scene.lights.is_empty() {
+    // Ambient occlusion only depends on the geometry
+    if scene.lights.is_empty() && scene.config.integrator != Integrator::AmbientOcclusion {
         return Err(RaytracerError::NoLight);
     }
+    info!(
+        "Estimated peak memory: {}",
+        MemoryUsage::estimate(&scene, &config, renderer.batch_pixels(&config))
+    );
     info!("Rendering start...");
     let instant_start = Instant::now();
+    let stats = config.stats.clone();
+    let rendering_stats = stats.clone();
+    let cancellation = config.cancellation.clone();
     let iter_end = move || {
+        rendering_stats.add_phase("Rendering", instant_start.elapsed());
         finally();
+        if cancellation.is_cancelled() {
+            warn!("Rendering cancelled!");
+            return Some(Err(RaytracerError::Cancelled));
+        }
         info!("Rendering done!");
         info!(
             "Rendering duration: {:.3} seconds",
@@ -97,140 +346,572 @@ where
         );
         None
     };
-    let render_iter: Box<dyn Iterator<Item = Result<Pixel>>> = if parallel {
-        Box::new(renderer_parallel(scene, config))
-    } else {
-        Box::new(renderer_sequential(scene, config))
-    };
-    let render_iter = render_iter.chain(from_fn(iter_end)).fuse();
-    Ok(render_iter)
+    let mut tracker = config
+        .progress
+        .take()
+        .map(|callback| ProgressTracker::new(callback, renderer, &config));
+    let render_iter = renderer.render(scene, config).inspect(move |pixel| {
+        if let Some(tracker) = &mut tracker {
+            tracker.advance(pixel);
+        }
+    });
+    let render_iter = render_iter.chain(once_with(iter_end).flatten()).fuse();
+    Ok(RenderedPixels {
+        pixels: render_iter,
+        stats,
+    })
 }
 
-pub fn renderer_parallel(
+/// Continue the rendering saved in a checkpoint: its pixels are given first, then the ones
+/// of the tiles it misses
+pub fn resume_scene_with_finally<F>(
     scene: Scene,
-    config: RenderConfiguration,
-) -> impl Iterator<Item = Result<Pixel>> {
-    let (tx, rx) = mpsc::channel();
-
-    std::thread::spawn(move || {
-        let scene = &scene;
-        let config = &config;
-        let pixel_width = 1.0 / config.canvas_width as f64;
-        let pixel_height = 1.0 / config.canvas_height as f64;
-
-        rayon::scope(move |s| {
-            for y in 0..config.canvas_height {
-                for x in 0..config.canvas_width {
-                    let tx = tx.clone();
-                    s.spawn(move |_| {
-                        let canvas_x = x as f64 / (config.canvas_width as f64);
-                        let canvas_y = y as f64 / (config.canvas_height as f64);
-                        let res_color = config.render_strategy.render_pixel(
-                            &scene,
+    mut config: RenderConfiguration,
+    checkpoint: &Checkpoint,
+    finally: F,
+) -> Result<RenderedPixels<impl Iterator<Item = Result<Pixel>>>>
+where
+    F: FnMut(),
+{
+    if !checkpoint.fits(&config) {
+        return Err(RaytracerError::ParsingError(String::from(
+            "checkpoint: made for another canvas",
+        )));
+    }
+    config.tile_size = checkpoint.tile_size();
+    config.skipped_tiles = checkpoint.completed_tiles();
+    info!(
+        "Resuming with {} of {} tiles already rendered",
+        config.skipped_tiles.len(),
+        config.skipped_tiles.len() + config.tiles().len()
+    );
+    let saved_pixels = checkpoint.completed_pixels().into_iter().map(Ok);
+    let rendered = render_scene_with_finally(scene, config, finally)?;
+    Ok(RenderedPixels {
+        stats: rendered.stats.clone(),
+        pixels: saved_pixels.chain(rendered),
+    })
+}
+
+/// Every pixel rendered once in parallel, the scene being only borrowed so that passes
+/// can follow each other over the same scene. The colors are the ones before tone
+/// mapping, for the passes to be averaged, each `pass` drawing other random samples.
+/// A cancelled pass is an error, its pixels being incomplete.
+pub fn render_linear_pass(
+    scene: &Scene,
+    config: &RenderConfiguration,
+    pass: u32,
+) -> Result<Vec<Pixel>> {
+    if scene.lights.is_empty() && scene.config.integrator != Integrator::AmbientOcclusion {
+        return Err(RaytracerError::NoLight);
+    }
+    let pixel_width = 1.0 / config.canvas_width as f64;
+    let pixel_height = 1.0 / config.canvas_height as f64;
+    let pixels = (0..config.canvas_height)
+        .into_par_iter()
+        .filter(|_| !config.cancellation.is_cancelled())
+        .flat_map_iter(|y| {
+            (0..config.canvas_width).map(move |x| {
+                let canvas_x = x as f64 * pixel_width;
+                let canvas_y = y as f64 * pixel_height;
+                let mut rng = pixel_rng(scene.config.seed, x, y, pass);
+                config.stats.record_pixel(|| {
+                    config
+                        .render_strategy
+                        .render_pixel(
+                            scene,
                             canvas_x,
                             canvas_y,
                             pixel_width,
                             pixel_height,
-                        );
-                        let pixel = match res_color {
-                            Ok(color) => Ok(Pixel::new(x, y, color)),
-                            Err(err) => Err(err),
-                        };
-                        tx.send(pixel).unwrap_or_else(|err| {
+                            &mut rng,
+                        )
+                        .map(|sample| Pixel::with_alpha(x, y, sample.color, sample.alpha))
+                })
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    match config.cancellation.is_cancelled() {
+        true => Err(RaytracerError::Cancelled),
+        false => Ok(pixels),
+    }
+}
+
+/// Pixels of a single tile of the canvas, row after row, rendered in the calling thread
+/// with the scene only borrowed, so that separate workers, like web workers which share no
+/// memory, each render their own tiles of the same canvas. The pixels are the ones the
+/// renderers give. A tile cancelled before its end is an error.
+pub fn render_tile(scene: &Scene, config: &RenderConfiguration, tile: Tile) -> Result<Vec<Pixel>> {
+    if scene.lights.is_empty() && scene.config.integrator != Integrator::AmbientOcclusion {
+        return Err(RaytracerError::NoLight);
+    }
+    if tile.x + tile.width > config.canvas_width || tile.y + tile.height > config.canvas_height {
+        return Err(RaytracerError::InvalidGeometry(format!(
+            "tile at ({}, {}) of {}x{} outside of a {}x{} canvas",
+            tile.x, tile.y, tile.width, tile.height, config.canvas_width, config.canvas_height
+        )));
+    }
+    tile.pixels()
+        .map(|(x, y)| match config.cancellation.is_cancelled() {
+            true => Err(RaytracerError::Cancelled),
+            false => render_pixel_at(scene, config, x, y),
+        })
+        .collect()
+}
+
+/// Environment map of the scene seen all around `eye`, of the canvas size of the
+/// configuration which should be twice as wide as high. Its colors are the light before
+/// tone mapping, so it lights other scenes as it lit this one, the middle of the map being
+/// seen towards +Z.
+pub fn render_environment(
+    mut scene: Scene,
+    eye: Vec3,
+    config: &RenderConfiguration,
+) -> Result<LatLongMap> {
+    scene.camera = Box::new(EquirectangularCamera::new(
+        eye,
+        eye + Vec3::new(0.0, 0.0, 1.0),
+    ));
+    let (width, height) = (config.canvas_width as usize, config.canvas_height as usize);
+    let mut colors = vec![Color::BLACK; width * height];
+    for pixel in render_linear_pass(&scene, config, 0)? {
+        colors[pixel.y as usize * width + pixel.x as usize] = pixel.color;
+    }
+    LatLongMap::new(width, height, colors)
+}
+
+/// Side, in pixels, of the tiles unless configured
+pub const DEFAULT_TILE_SIZE: u32 = 16;
+
+pub struct SequentialRenderer;
+
+impl Renderer for SequentialRenderer {
+    fn render(
+        &self,
+        scene: Scene,
+        config: RenderConfiguration,
+    ) -> Box<dyn Iterator<Item = Result<Pixel>>> {
+        Box::new(TileRenderIterator::new(scene, config))
+    }
+
+    fn batch_pixels(&self, _config: &RenderConfiguration) -> Option<usize> {
+        None
+    }
+}
+
+pub struct ThreadedRenderer;
+
+impl Renderer for ThreadedRenderer {
+    fn render(
+        &self,
+        scene: Scene,
+        config: RenderConfiguration,
+    ) -> Box<dyn Iterator<Item = Result<Pixel>>> {
+        let (tx, rx) = mpsc::channel::<Vec<Result<Pixel>>>();
+
+        std::thread::spawn(move || {
+            let scene = &scene;
+            let config = &config;
+
+            (0..config.canvas_height)
+                .into_par_iter()
+                .for_each_with(tx, |tx, y| {
+                    if config.cancellation.is_cancelled() {
+                        return;
+                    }
+                    let pixels = (0..config.canvas_width)
+                        .map(|x| render_pixel_at(scene, config, x, y))
+                        .collect();
+                    tx.send(pixels).unwrap_or_else(|err| {
+                        trace!("Error: {}", err);
+                    });
+                });
+        });
+
+        Box::new(rx.into_iter().flatten())
+    }
+
+    fn batch_pixels(&self, config: &RenderConfiguration) -> Option<usize> {
+        Some(config.canvas_width as usize)
+    }
+}
+
+/// Tiles shared between threads, started in the order of the configuration
+pub struct TiledRenderer;
+
+impl Renderer for TiledRenderer {
+    fn render(
+        &self,
+        scene: Scene,
+        config: RenderConfiguration,
+    ) -> Box<dyn Iterator<Item = Result<Pixel>>> {
+        let (tx, rx) = mpsc::channel::<Vec<Result<Pixel>>>();
+
+        std::thread::spawn(move || {
+            let scene = &scene;
+            let config = &config;
+            let tiles = config.tiles();
+
+            // First in, first out, so the first tiles of the order are the first rendered
+            rayon::scope_fifo(move |s| {
+                for tile in tiles {
+                    let tx = tx.clone();
+                    s.spawn_fifo(move |_| {
+                        if config.cancellation.is_cancelled() {
+                            return;
+                        }
+                        let pixels = tile
+                            .pixels()
+                            .map(|(x, y)| render_pixel_at(scene, config, x, y))
+                            .collect();
+                        tx.send(pixels).unwrap_or_else(|err| {
                             trace!("Error: {}", err);
                         });
                     });
                 }
-            }
+            });
         });
-    });
 
-    rx.into_iter()
+        Box::new(rx.into_iter().flatten())
+    }
+
+    fn batch_pixels(&self, config: &RenderConfiguration) -> Option<usize> {
+        let tile_size = config.tile_size.max(1) as usize;
+        Some(tile_size * tile_size)
+    }
 }
 
-pub fn renderer_sequential(
-    scene: Scene,
-    config: RenderConfiguration,
-) -> impl Iterator<Item = Result<Pixel>> {
-    AreaRenderIterator::with_full_area(scene, config)
+/// Distance at which the background is taken to follow its motion when the camera moves
+pub(crate) const BACKGROUND_DISTANCE: f64 = 1e6;
+
+/// Screen motion, in pixels, of what is seen at the center of each pixel of `scene` until
+/// it is seen in `other`, usually the same scene at the previous or next animation frame.
+/// Objects are matched by their position in the scene, a surface point following the
+/// transformation of its object; it is still where an object has no match or when the
+/// camera of `other` cannot tell where points are seen.
+pub fn render_motion_vectors(
+    scene: &Scene,
+    other: &Scene,
+    canvas_width: u32,
+    canvas_height: u32,
+) -> MotionVectors {
+    let (width, height) = (canvas_width as f64, canvas_height as f64);
+    let vectors = (0..canvas_height)
+        .into_par_iter()
+        .flat_map_iter(|y| {
+            (0..canvas_width).map(move |x| {
+                let canvas_x = (x as f64 + 0.5) / width;
+                let canvas_y = (y as f64 + 0.5) / height;
+                let ray = scene.camera.generate_ray(canvas_x, canvas_y);
+                let hit = camera_ray_hit(&ray, scene);
+                let seen_point = match hit {
+                    Some((_, point)) => point,
+                    None => ray.source + BACKGROUND_DISTANCE * ray.direction,
+                };
+                let seen = match hit {
+                    Some((index, point)) => match other.objects.get(index) {
+                        Some(moved) => {
+                            let local = scene.objects[index].shape.local_point(point);
+                            moved.shape.world_point(local)
+                        }
+                        None => point,
+                    },
+                    None => seen_point,
+                };
+                // The point is not exactly at the pixel center through a lens
+                let (from_x, from_y) = match scene.camera.canvas_position(seen_point) {
+                    Some(position) => position,
+                    None => (canvas_x, canvas_y),
+                };
+                match other.camera.canvas_position(seen) {
+                    Some((other_x, other_y)) => {
+                        ((other_x - from_x) * width, (other_y - from_y) * height)
+                    }
+                    None => (0.0, 0.0),
+                }
+            })
+        })
+        .collect();
+    MotionVectors::new(canvas_width, canvas_height, vectors)
 }
 
-pub struct AreaRenderIterator {
-    scene: Scene,
-    config: RenderConfiguration,
-    area_x_origin: u32,
-    #[allow(dead_code)]
-    area_y_origin: u32,
-    area_width: u32,
-    area_height: u32,
-    area_x_current: u32,
-    area_y_current: u32,
-    pixel_width: f64,
-    pixel_height: f64,
-}
-
-impl AreaRenderIterator {
-    pub fn new(
+/// Side, in pixels, of the blocks filled by each pixel of the first progressive pass
+pub const PROGRESSIVE_COARSE_STEP: u32 = 8;
+
+/// Renders first one pixel every `coarse_step`, given again over the whole block it stands
+/// for, then at each pass the pixels halfway between those already rendered, over blocks
+/// half as large, until every pixel is rendered. Each pixel is computed once, and a block
+/// never covers a pixel already computed, so the last pixel given at a position is final.
+pub struct ProgressiveRenderer {
+    /// Side of the blocks of the first pass, rounded up to a power of two
+    pub coarse_step: u32,
+    /// Rows of each pass shared between threads, else rendered in the calling thread
+    pub threaded: bool,
+}
+
+impl ProgressiveRenderer {
+    /// Sides of the blocks of each pass, from the coarsest to single pixels
+    fn steps(&self) -> impl Iterator<Item = u32> {
+        let coarse_step = self.coarse_step.max(1).next_power_of_two();
+        std::iter::successors(Some(coarse_step), |&step| (step > 1).then_some(step / 2))
+    }
+
+    /// Columns of the pixels of row `y` first rendered at the pass of side `step`, those of
+    /// the coarser passes being left out
+    fn pass_columns(width: u32, y: u32, step: u32, first: bool) -> impl Iterator<Item = u32> {
+        let new_row = first || !y.is_multiple_of(2 * step);
+        (0..width)
+            .step_by(step as usize)
+            .filter(move |x| new_row || !x.is_multiple_of(2 * step))
+    }
+
+    /// Pixel at `x`, `y` given over the block of side `step` it stands for
+    fn render_block(
+        scene: &Scene,
+        config: &RenderConfiguration,
+        x: u32,
+        y: u32,
+        step: u32,
+    ) -> Vec<Result<Pixel>> {
+        let pixel = match render_pixel_at(scene, config, x, y) {
+            Ok(pixel) => pixel,
+            Err(err) => return vec![Err(err)],
+        };
+        let x_end = (x + step).min(config.canvas_width);
+        let y_end = (y + step).min(config.canvas_height);
+        (y..y_end)
+            .flat_map(|block_y| (x..x_end).map(move |block_x| (block_x, block_y)))
+            .map(|(block_x, block_y)| {
+                Ok(Pixel {
+                    x: block_x,
+                    y: block_y,
+                    ..pixel.clone()
+                })
+            })
+            .collect()
+    }
+}
+
+impl Renderer for ProgressiveRenderer {
+    fn render(
+        &self,
         scene: Scene,
         config: RenderConfiguration,
-        area_x: u32,
-        area_y: u32,
-        area_width: u32,
-        area_height: u32,
-    ) -> AreaRenderIterator {
-        AreaRenderIterator {
-            pixel_width: 1.0 / config.canvas_width as f64,
-            pixel_height: 1.0 / config.canvas_height as f64,
-            scene,
-            config,
-            area_x_origin: area_x,
-            area_y_origin: area_y,
-            area_width,
-            area_height,
-            area_x_current: area_x,
-            area_y_current: area_y,
+    ) -> Box<dyn Iterator<Item = Result<Pixel>>> {
+        let steps: Vec<u32> = self.steps().collect();
+        let coarse_step = steps[0];
+        if !self.threaded {
+            let context = Rc::new((scene, config));
+            return Box::new(steps.into_iter().flat_map(move |step| {
+                let context = context.clone();
+                let (width, height) = (context.1.canvas_width, context.1.canvas_height);
+                let cancellation = context.1.cancellation.clone();
+                (0..height)
+                    .step_by(step as usize)
+                    .flat_map(move |y| {
+                        Self::pass_columns(width, y, step, step == coarse_step).map(move |x| (x, y))
+                    })
+                    .take_while(move |_| !cancellation.is_cancelled())
+                    .flat_map(move |(x, y)| {
+                        let (scene, config) = &*context;
+                        Self::render_block(scene, config, x, y, step)
+                    })
+            }));
         }
+
+        let (tx, rx) = mpsc::channel::<Vec<Result<Pixel>>>();
+
+        std::thread::spawn(move || {
+            let scene = &scene;
+            let config = &config;
+
+            for step in steps {
+                let first = step == coarse_step;
+                (0..config.canvas_height.div_ceil(step))
+                    .into_par_iter()
+                    .for_each_with(tx.clone(), |tx, row| {
+                        if config.cancellation.is_cancelled() {
+                            return;
+                        }
+                        let y = row * step;
+                        let pixels = Self::pass_columns(config.canvas_width, y, step, first)
+                            .flat_map(|x| Self::render_block(scene, config, x, y, step))
+                            .collect();
+                        tx.send(pixels).unwrap_or_else(|err| {
+                            trace!("Error: {}", err);
+                        });
+                    });
+            }
+        });
+
+        Box::new(rx.into_iter().flatten())
     }
 
-    pub fn with_full_area(scene: Scene, config: RenderConfiguration) -> AreaRenderIterator {
-        let area_width = config.canvas_width;
-        let area_height = config.canvas_height;
-        Self::new(scene, config, 0, 0, area_width, area_height)
+    fn pixel_count(&self, canvas_width: u32, canvas_height: u32) -> u64 {
+        let coarse_step = self.steps().next().unwrap_or(1);
+        self.steps()
+            .map(|step| {
+                let first = step == coarse_step;
+                (0..canvas_height)
+                    .step_by(step as usize)
+                    .map(|y| {
+                        let block_height = step.min(canvas_height - y) as u64;
+                        Self::pass_columns(canvas_width, y, step, first)
+                            .map(|x| step.min(canvas_width - x) as u64 * block_height)
+                            .sum::<u64>()
+                    })
+                    .sum::<u64>()
+            })
+            .sum()
     }
 
-    pub fn total_pixels(&self) -> usize {
-        (self.area_width * self.area_height) as usize
+    fn batch_pixels(&self, config: &RenderConfiguration) -> Option<usize> {
+        let coarse_step = self.steps().next().unwrap_or(1) as usize;
+        match self.threaded {
+            true => Some(config.canvas_width as usize * coarse_step),
+            false => None,
+        }
+    }
+}
+
+fn render_pixel_at(scene: &Scene, config: &RenderConfiguration, x: u32, y: u32) -> Result<Pixel> {
+    let canvas_x = x as f64 / (config.canvas_width as f64);
+    let canvas_y = y as f64 / (config.canvas_height as f64);
+    let pixel_width = 1.0 / config.canvas_width as f64;
+    let pixel_height = 1.0 / config.canvas_height as f64;
+    let mut rng = pixel_rng(scene.config.seed, x, y, 0);
+    config.stats.record_pixel(|| {
+        config
+            .render_strategy
+            .render_pixel(
+                scene,
+                canvas_x,
+                canvas_y,
+                pixel_width,
+                pixel_height,
+                &mut rng,
+            )
+            .map(|sample| Pixel::from_sample(x, y, sample, &scene.config.tone_mapping))
+    })
+}
+
+/// Pixels of the canvas rendered tile after tile in the calling thread, in the order of
+/// the configuration
+pub struct TileRenderIterator {
+    scene: Scene,
+    config: RenderConfiguration,
+    tiles: std::vec::IntoIter<Tile>,
+    current_tile: Option<Tile>,
+    /// Pixels of the current tile already given
+    tile_index: u32,
+}
+
+impl TileRenderIterator {
+    pub fn new(scene: Scene, config: RenderConfiguration) -> TileRenderIterator {
+        let tiles = config.tiles();
+        TileRenderIterator {
+            scene,
+            config,
+            tiles: tiles.into_iter(),
+            current_tile: None,
+            tile_index: 0,
+        }
     }
 }
 
-impl Iterator for AreaRenderIterator {
+impl Iterator for TileRenderIterator {
     type Item = Result<Pixel>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.area_y_current >= self.area_height {
-            return None;
+        loop {
+            let tile = match self.current_tile {
+                Some(tile) => tile,
+                None => {
+                    self.tile_index = 0;
+                    *self.current_tile.insert(self.tiles.next()?)
+                }
+            };
+            if self.config.cancellation.is_cancelled() {
+                return None;
+            }
+            if (self.tile_index as usize) < tile.pixel_count() {
+                let x = tile.x + self.tile_index % tile.width;
+                let y = tile.y + self.tile_index / tile.width;
+                self.tile_index += 1;
+                return Some(render_pixel_at(&self.scene, &self.config, x, y));
+            }
+            self.current_tile = None;
         }
-        let x = self.area_x_current;
-        let y = self.area_y_current;
-        let canvas_x = (self.area_x_current as f64) / (self.config.canvas_width as f64);
-        let canvas_y = (self.area_y_current as f64) / (self.config.canvas_height as f64);
-        let render_strategy = &*self.config.render_strategy;
-        let result_color = render_strategy.render_pixel(
-            &self.scene,
-            canvas_x,
-            canvas_y,
-            self.pixel_width,
-            self.pixel_height,
-        );
-        let color = match result_color {
-            Ok(val) => val,
-            Err(val) => return Some(Err(val)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::f64_eq;
+
+    #[test]
+    fn default_tone_mapping_only_clamps() {
+        let tone_mapping = ToneMapping::default();
+        let color = tone_mapping.apply(&Color::new(0.25, 0.5, 1.0));
+        assert!(f64_eq(color.red(), 0.25));
+        assert!(f64_eq(color.green(), 0.5));
+        let color = tone_mapping.apply(&(4.0 * Color::WHITE));
+        assert!(f64_eq(color.red(), 1.0));
+    }
+
+    #[test]
+    fn reinhard_keeps_bright_colors_apart() {
+        let tone_mapping = ToneMapping {
+            operator: ToneMappingOperator::Reinhard,
+            ..Default::default()
         };
-        self.area_x_current += 1;
-        if self.area_x_current >= self.area_width {
-            self.area_x_current = self.area_x_origin;
-            self.area_y_current += 1;
+        let bright = tone_mapping.apply(&(2.0 * Color::WHITE));
+        let brighter = tone_mapping.apply(&(4.0 * Color::WHITE));
+        assert!(f64_eq(tone_mapping.apply(&Color::WHITE).red(), 0.5));
+        assert!(bright.red() < brighter.red() && brighter.red() < 1.0);
+    }
+
+    #[test]
+    fn exposure_and_gamma_are_applied() {
+        let tone_mapping = ToneMapping {
+            exposure: 1.0,
+            gamma: 2.0,
+            ..Default::default()
+        };
+        let color = tone_mapping.apply(&Color::new(0.125, 0.0, 0.0));
+        assert!(f64_eq(color.red(), 0.5));
+    }
+
+    #[test]
+    fn aces_maps_to_unit_interval() {
+        let tone_mapping = ToneMapping {
+            operator: ToneMappingOperator::Aces,
+            ..Default::default()
+        };
+        assert!(f64_eq(tone_mapping.apply(&Color::BLACK).red(), 0.0));
+        let color = tone_mapping.apply(&(100.0 * Color::WHITE));
+        assert!(color.red() > 0.99 && color.red() <= 1.0);
+    }
+
+    #[test]
+    fn progressive_passes_render_each_pixel_once() {
+        let renderer = ProgressiveRenderer {
+            coarse_step: 6,
+            threaded: false,
+        };
+        let steps: Vec<u32> = renderer.steps().collect();
+        assert_eq!(steps, vec![8, 4, 2, 1]);
+        let (width, height) = (21, 13);
+        let mut rendered = vec![0; (width * height) as usize];
+        for &step in &steps {
+            for y in (0..height).step_by(step as usize) {
+                for x in ProgressiveRenderer::pass_columns(width, y, step, step == 8) {
+                    rendered[(x + y * width) as usize] += 1;
+                }
+            }
         }
-        Some(Ok(Pixel::new(x, y, color)))
+        assert!(rendered.iter().all(|&count| count == 1));
+        assert!(renderer.pixel_count(width, height) > (width * height) as u64);
     }
 }
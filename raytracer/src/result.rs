@@ -22,6 +22,7 @@ OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE.
 */
 
+use crate::validation::SceneError;
 use std::fmt;
 use std::fmt::{Debug, Display};
 
@@ -31,7 +32,13 @@ pub type Result<T> = std::result::Result<T, RaytracerError>;
 pub enum RaytracerError {
     NormalNotFound(usize),
     ParsingError(String),
+    /// Scene description not matching what is expected, at a known place
+    InvalidScene(SceneError),
+    InvalidGeometry(String),
+    IoError(String),
     NoLight,
+    /// Rendering stopped by its cancellation token, the pixels given before being kept
+    Cancelled,
 }
 
 impl Display for RaytracerError {
@@ -41,9 +48,15 @@ impl Display for RaytracerError {
                 write!(formatter, "Normal not found for object at index: {}", val)
             }
             RaytracerError::NoLight => write!(formatter, "There is no light in the scene"),
+            RaytracerError::Cancelled => write!(formatter, "Rendering cancelled"),
             RaytracerError::ParsingError(val) => {
                 write!(formatter, "Error while parsing scene: {}", val)
             }
+            RaytracerError::InvalidScene(val) => {
+                write!(formatter, "Error while parsing scene: {}", val)
+            }
+            RaytracerError::InvalidGeometry(val) => write!(formatter, "Invalid geometry: {}", val),
+            RaytracerError::IoError(val) => write!(formatter, "Error while reading file: {}", val),
         }
     }
 }
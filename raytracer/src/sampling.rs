@@ -0,0 +1,57 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Random numbers of the samplers: the anti-aliasing rays, the lens of the camera, the
+//! points of the area lights and the diffuse and ambient occlusion rays. Each pixel draws
+//! them from its own generator, seeded by the seed of the scene and the position of the
+//! pixel, so that a rendering gives the same image whatever the renderer, the order of
+//! the pixels and the number of threads.
+
+use crate::utils::mix_seed;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// Generator of the samples of a pixel, another pass over the same pixel drawing others
+pub fn pixel_rng(seed: u64, x: u32, y: u32, pass: u32) -> StdRng {
+    let position = (x as u64) << 32 | y as u64;
+    StdRng::seed_from_u64(mix_seed(mix_seed(mix_seed(seed) ^ position) ^ pass as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pixels_draw_the_same_samples_for_the_same_seed_only() {
+        let samples = |seed, x, y, pass| -> Vec<u64> {
+            let mut rng = pixel_rng(seed, x, y, pass);
+            (0..4).map(|_| rng.gen()).collect()
+        };
+        assert_eq!(samples(7, 3, 5, 0), samples(7, 3, 5, 0));
+        assert_ne!(samples(7, 3, 5, 0), samples(8, 3, 5, 0));
+        assert_ne!(samples(7, 3, 5, 0), samples(7, 5, 3, 0));
+        assert_ne!(samples(7, 3, 5, 0), samples(7, 3, 5, 1));
+    }
+}
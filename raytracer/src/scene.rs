@@ -22,24 +22,76 @@ OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE.
 */
 
+use crate::animation::AnimationFrame;
+use crate::atmosphere::Atmosphere;
+use crate::bvh::{Aabb, Bvh};
+use crate::cameras::Shutter;
 use crate::colors::Color;
+use crate::environment::World;
 use crate::lights::AnyLightObject;
 use crate::parser;
-use crate::primitives::{Ray, Shape};
+use crate::primitives::{MovingShape, Ray, Shape};
+use crate::renderer::ToneMapping;
 use crate::result::{RaytracerError, Result};
-use crate::textures::{Texture, TextureEffects};
+use crate::texture_cache::DEFAULT_BUDGET_MB;
+use crate::textures::{ShadingContext, Texture, TextureEffects};
+use crate::validation::SceneError;
 use crate::vector::Vec3;
 use crate::UnitInterval;
-use serde::Deserialize;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::iter::FromIterator;
+use std::ops::{Deref, Range};
+use std::path::Path;
 use std::str::FromStr;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(default)]
 pub struct SceneConfiguration {
     pub world_color: Color,
     pub world_refractive_index: f64,
     pub ambient_light: Option<Color>,
     pub maximum_light_recursion: u8,
+    /// Distance after which objects are not seen by reflected or refracted rays
+    pub maximum_ray_distance: Option<f64>,
+    pub light_paths: LightPathFilter,
+    /// Replace assets which cannot be loaded by magenta placeholders instead of failing
+    pub asset_placeholders: bool,
+    pub tone_mapping: ToneMapping,
+    /// Shadow rays sent to lights with an area, more giving smoother penumbrae
+    pub area_light_samples: u32,
+    /// Encode the displayed or saved colors with the sRGB curve, disable it to get linear values
+    pub srgb_output: bool,
+    /// Add a shadow catcher ground under the objects and a soft key light above them
+    pub auto_stage: bool,
+    /// Add key, fill and rim lights around the objects, placed from the camera
+    pub three_point_lights: bool,
+    pub integrator: Integrator,
+    pub transparency: TransparencyMode,
+    /// Bounces after which path tracing randomly stops rays, darker surfaces stopping more
+    pub russian_roulette_depth: u8,
+    pub ambient_occlusion: AmbientOcclusion,
+    /// Seed of the random samples, an image rendered twice with the same one being the same
+    pub seed: u64,
+    /// Frame of an animation, giving the time of the scene unless `time` is set
+    pub frame: u32,
+    pub frames_per_second: f64,
+    /// Time of the scene in seconds, seen by the animated transforms, lights and textures,
+    /// replacing the one of the frame
+    pub time: Option<f64>,
+    /// Sky of a planet shown in the background instead of the world color
+    pub atmosphere: Option<Atmosphere>,
+    /// Sky gradient or environment image shown in the background instead of the world color
+    pub world: Option<World>,
+    /// What camera rays see when they hit nothing
+    pub camera_background: BackgroundVisibility,
+    /// What reflected and refracted rays see when they hit nothing
+    pub reflection_background: BackgroundVisibility,
+    /// What is done with the keys of the description which are not known, often typos
+    pub unknown_fields: UnknownFields,
+    /// Megabytes of texels of the image textures kept in memory, the least recently used
+    /// tiles leaving it beyond
+    pub texture_cache_mb: usize,
 }
 
 impl Default for SceneConfiguration {
@@ -49,10 +101,189 @@ impl Default for SceneConfiguration {
             world_refractive_index: 1.0,
             ambient_light: Some(Color::new(0.2, 0.2, 0.2)),
             maximum_light_recursion: 2,
+            maximum_ray_distance: None,
+            light_paths: Default::default(),
+            asset_placeholders: false,
+            tone_mapping: Default::default(),
+            srgb_output: true,
+            area_light_samples: 16,
+            auto_stage: false,
+            three_point_lights: false,
+            integrator: Default::default(),
+            transparency: Default::default(),
+            russian_roulette_depth: 2,
+            ambient_occlusion: Default::default(),
+            seed: 0,
+            frame: 0,
+            frames_per_second: 24.0,
+            time: None,
+            atmosphere: None,
+            world: None,
+            camera_background: Default::default(),
+            reflection_background: Default::default(),
+            unknown_fields: Default::default(),
+            texture_cache_mb: DEFAULT_BUDGET_MB,
         }
     }
 }
 
+impl SceneConfiguration {
+    /// Time of the scene in the animation, in seconds, the one of the frame if not set
+    pub fn time(&self) -> f64 {
+        self.time
+            .unwrap_or(self.frame as f64 / self.frames_per_second)
+    }
+
+    /// Color seen by a ray leaving the scene in a direction
+    pub fn background_color(&self, direction: Vec3) -> Color {
+        match (self.atmosphere.as_ref(), self.world.as_ref()) {
+            (Some(atmosphere), _) => atmosphere.sky_color(direction),
+            (None, Some(world)) => world.color_in(direction),
+            (None, None) => self.world_color.clone(),
+        }
+    }
+}
+
+/// Rays of the ambient occlusion integrator
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AmbientOcclusion {
+    /// Rays sent around each hit point
+    pub samples: u32,
+    /// Distance after which objects do not occlude anymore, unlimited if not set
+    pub distance: Option<f64>,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        AmbientOcclusion {
+            samples: 16,
+            distance: None,
+        }
+    }
+}
+
+/// Handling of the keys of a scene description which are not known
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownFields {
+    Ignore,
+    /// Add a warning to the scene for each of them
+    #[default]
+    Warn,
+    /// Fail to load the scene
+    Deny,
+}
+
+/// What a ray sees when it leaves the scene without hitting anything, compositing often
+/// wanting the background out of the image but still seen in the reflections
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackgroundVisibility {
+    /// Atmosphere sky or world environment if any, else the world color
+    #[default]
+    Environment,
+    /// World color, even with an atmosphere or a world environment
+    WorldColor,
+    /// Nothing, black for reflections, and transparent pixels for camera rays
+    Transparent,
+}
+
+/// How the light reaching a surface is gathered
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    /// Light coming directly from the lights, mirrors and transparent objects, the rest
+    /// being given by the ambient light
+    #[default]
+    Whitted,
+    /// Also follows light scattered by diffuse surfaces, in random directions, giving
+    /// global illumination and color bleeding but needing many rays per pixel; the
+    /// bounces are limited by `maximum_light_recursion`
+    Path,
+    /// Gray level of how much the surface is open to its surroundings, without lights
+    /// nor materials, to preview the geometry quickly
+    AmbientOcclusion,
+}
+
+/// How rays go through transparent objects
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransparencyMode {
+    /// Rays refracted at each surface, each transparent object seen through taking one
+    /// of the `maximum_light_recursion` bounces
+    #[default]
+    Refracted,
+    /// Transparent surfaces met along a ray, nearest first, blended with their alpha while
+    /// the ray goes straight on, without recursion nor refraction nor Fresnel reflection,
+    /// much faster for many overlapping transparent objects
+    Composited,
+}
+
+/// Restricts the light paths taking part in the rendered image, mostly to debug
+/// the ray algorithm or to render one kind of light transport at a time.
+/// By default, every path is rendered.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LightPathFilter {
+    /// Light terms accumulated where a ray hits something
+    pub contributions: Vec<LightContribution>,
+    /// Secondary rays which are followed from a hit point
+    pub bounces: Vec<LightBounce>,
+    /// If false, nothing is accumulated where camera rays directly end
+    pub direct: bool,
+}
+
+impl Default for LightPathFilter {
+    fn default() -> Self {
+        LightPathFilter {
+            contributions: vec![
+                LightContribution::Diffuse,
+                LightContribution::Specular,
+                LightContribution::Ambient,
+                LightContribution::Background,
+                LightContribution::Emission,
+            ],
+            bounces: vec![
+                LightBounce::Reflection,
+                LightBounce::Refraction,
+                LightBounce::Diffuse,
+            ],
+            direct: true,
+        }
+    }
+}
+
+impl LightPathFilter {
+    pub fn accepts(&self, contribution: LightContribution, depth: u8) -> bool {
+        (depth > 0 || self.direct) && self.contributions.contains(&contribution)
+    }
+
+    pub fn follows(&self, bounce: LightBounce) -> bool {
+        self.bounces.contains(&bounce)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LightContribution {
+    Diffuse,
+    Specular,
+    Ambient,
+    Background,
+    /// Light given by emissive volumes
+    Emission,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LightBounce {
+    Reflection,
+    Refraction,
+    /// Only followed by the path tracing integrator
+    Diffuse,
+}
+
 pub struct SceneObject {
     pub texture: Box<dyn Texture>,
     pub shape: Box<dyn Shape>,
@@ -60,9 +291,36 @@ pub struct SceneObject {
 }
 
 impl SceneObject {
-    pub fn color_at(&self, point: Vec3) -> Color {
-        let (u, v) = self.shape.surface_mapping_at(point).unwrap();
-        self.texture.color_at(u, v)
+    /// Context of a point of the surface seen along a direction, by a ray of some time at
+    /// the start of the animation, none where the shape has no normal or surface mapping
+    pub fn shading_context(
+        &self,
+        point: Vec3,
+        incoming: Vec3,
+        time: f64,
+        instance_id: usize,
+    ) -> Option<ShadingContext> {
+        let (u, v) = self.shape.surface_mapping_at(point)?;
+        Some(ShadingContext {
+            point,
+            normal: self.shape.normal_at(point)?.normalize(),
+            u,
+            v,
+            incoming,
+            time,
+            scene_time: 0.0,
+            instance_id,
+        })
+    }
+
+    pub fn color_in(&self, context: &ShadingContext) -> Color {
+        match &self.effects.variation {
+            Some(variation) => {
+                let seed = self.effects.seed.unwrap_or_default();
+                variation.color_in(self.texture.as_ref(), context, seed)
+            }
+            None => self.texture.color_in(context),
+        }
     }
 
     pub fn check_collision(&self, ray: &Ray) -> Option<Vec3> {
@@ -73,11 +331,106 @@ impl SceneObject {
         self.shape.normal_at(point)
     }
 
+    /// Normal used for lighting, the one of the context tilted by the bump or water effects
+    /// if any, normalized
+    pub fn shading_normal_in(&self, context: &ShadingContext) -> Vec3 {
+        let mut context = *context;
+        if let Some(bump) = &self.effects.bump {
+            context = context.with_normal(bump.perturb(&context));
+        }
+        if let Some(water) = &self.effects.water {
+            context = context.with_normal(water.perturb(&context));
+        }
+        context.normal
+    }
+
+    pub fn aabb(&self) -> Option<Aabb> {
+        self.shape.aabb()
+    }
+
+    /// Same object moving at a velocity, per second, between two times of the shutter
+    pub fn moving(self, velocity: Vec3, times: Range<f64>) -> SceneObject {
+        SceneObject {
+            shape: Box::new(MovingShape::new(self.shape, velocity, times)),
+            ..self
+        }
+    }
+
     pub fn effects(&self) -> &TextureEffects {
         &self.effects
     }
 }
 
+/// Objects of a scene, with the top level bounding volume hierarchy used to search them
+#[derive(Default)]
+pub struct SceneObjects {
+    objects: Vec<SceneObject>,
+    bvh: Bvh,
+}
+
+impl SceneObjects {
+    /// Objects without seed receive one from their position in the scene
+    pub fn new(mut objects: Vec<SceneObject>) -> Self {
+        Self::assign_seeds(&mut objects);
+        let aabbs = objects.iter().map(SceneObject::aabb).collect::<Vec<_>>();
+        SceneObjects {
+            bvh: Bvh::new(&aabbs),
+            objects,
+        }
+    }
+
+    /// Add an object, rebuilding the hierarchy
+    pub fn push(&mut self, object: SceneObject) {
+        let mut objects = std::mem::take(&mut self.objects);
+        objects.push(object);
+        *self = SceneObjects::new(objects);
+    }
+
+    /// Replace the objects by the same ones at another animation frame. The hierarchy
+    /// is only refit to their new bounding boxes, which is much faster than building it
+    /// but slows down rendering as objects move far from where it was built. It is built
+    /// again if the objects changed in number or in being bounded.
+    pub fn refit(mut self, mut objects: Vec<SceneObject>) -> Self {
+        Self::assign_seeds(&mut objects);
+        let aabbs = objects.iter().map(SceneObject::aabb).collect::<Vec<_>>();
+        if !self.bvh.refit(&aabbs) {
+            self.bvh = Bvh::new(&aabbs);
+        }
+        self.objects = objects;
+        self
+    }
+
+    pub fn bvh(&self) -> &Bvh {
+        &self.bvh
+    }
+
+    fn assign_seeds(objects: &mut [SceneObject]) {
+        for (index, object) in objects.iter_mut().enumerate() {
+            object.effects.seed.get_or_insert(index as u64);
+        }
+    }
+}
+
+impl Deref for SceneObjects {
+    type Target = [SceneObject];
+
+    fn deref(&self) -> &Self::Target {
+        &self.objects
+    }
+}
+
+impl From<Vec<SceneObject>> for SceneObjects {
+    fn from(objects: Vec<SceneObject>) -> Self {
+        SceneObjects::new(objects)
+    }
+}
+
+impl FromIterator<SceneObject> for SceneObjects {
+    fn from_iter<I: IntoIterator<Item = SceneObject>>(iter: I) -> Self {
+        SceneObjects::new(iter.into_iter().collect())
+    }
+}
+
 pub trait RayEmitter: Send + Sync {
     fn width(&self) -> f64;
     fn height(&self) -> f64;
@@ -85,19 +438,236 @@ pub trait RayEmitter: Send + Sync {
         self.width() / self.height()
     }
     fn generate_ray(&self, canvas_x: UnitInterval, canvas_y: UnitInterval) -> Ray;
+    /// Ray seeing the scene at a time, in seconds from the frame, for cameras which
+    /// move while their shutter is open
+    fn generate_ray_at(&self, canvas_x: UnitInterval, canvas_y: UnitInterval, time: f64) -> Ray {
+        self.generate_ray(canvas_x, canvas_y).at_time(time)
+    }
+    /// Ray of `generate_ray_at` drawing its random samples, as the point of a lens, from
+    /// the generator of the pixel
+    fn sample_ray(
+        &self,
+        canvas_x: UnitInterval,
+        canvas_y: UnitInterval,
+        time: f64,
+        _rng: &mut dyn RngCore,
+    ) -> Ray {
+        self.generate_ray_at(canvas_x, canvas_y, time)
+    }
+    /// Canvas coordinates where a point is seen, the inverse of `generate_ray` but out of
+    /// the unit interval for points outside of the view, none behind the camera or when
+    /// the camera cannot tell
+    fn canvas_position(&self, _point: Vec3) -> Option<(f64, f64)> {
+        None
+    }
+    /// Distance before which objects are not seen by camera rays
+    fn near_clip(&self) -> Option<f64> {
+        None
+    }
+    /// Distance after which objects are not seen by camera rays
+    fn far_clip(&self) -> Option<f64> {
+        None
+    }
+    /// Interval during which the scene is seen
+    fn shutter(&self) -> Shutter {
+        Shutter::default()
+    }
 }
 
 pub struct Scene {
     pub camera: Box<dyn RayEmitter>,
     pub lights: Vec<Box<dyn AnyLightObject>>,
-    pub objects: Vec<SceneObject>,
+    pub objects: SceneObjects,
     pub config: SceneConfiguration,
+    /// Problems which did not prevent to build the scene, like replaced assets
+    pub warnings: Vec<String>,
+    /// Description the scene was built from with its default values, but without its
+    /// configuration kept in `config`, none for a scene built in code from parts which
+    /// cannot be described
+    pub description: Option<toml::value::Table>,
+}
+
+/// Format in which a scene description is written back
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SceneDumpFormat {
+    Toml,
+    Json,
+}
+
+impl FromStr for SceneDumpFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_ref() {
+            "toml" => SceneDumpFormat::Toml,
+            "json" => SceneDumpFormat::Json,
+            other => return Err(format!("{} is not a valid scene dump format", other)),
+        })
+    }
+}
+
+/// Language in which a scene is described, all of them giving the same keys
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum SceneFormat {
+    #[default]
+    Toml,
+    Json,
+    /// Block and flow collections of plain or quoted scalars, without anchors nor tags
+    Yaml,
+}
+
+impl SceneFormat {
+    /// Format given by the extension of a file, if it is a known one
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_ref() {
+            "toml" => Some(SceneFormat::Toml),
+            "json" => Some(SceneFormat::Json),
+            "yaml" | "yml" => Some(SceneFormat::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// Scene description as it is understood by the parser, every default value being written,
+/// to check what a short description really gives
+pub fn dump_scene_description(
+    scene_str: &str,
+    scene_format: SceneFormat,
+    format: SceneDumpFormat,
+) -> Result<String> {
+    parser::dump_scene_description(scene_str, scene_format, format)
+}
+
+/// Binary cache of a scene description, with its meshes, fonts, SVG files and heightmaps
+/// already turned into triangles, to build the scene again without loading them
+pub fn scene_cache(scene_str: &str, format: SceneFormat) -> Result<Vec<u8>> {
+    parser::scene_cache(scene_str, format)
 }
 
 impl FromStr for Scene {
     type Err = RaytracerError;
 
     fn from_str(scene_str: &str) -> Result<Scene> {
+        Scene::from_toml(scene_str)
+    }
+}
+
+impl Scene {
+    pub fn from_toml(scene_str: &str) -> Result<Scene> {
         parser::parse_scene_description(scene_str)
     }
+
+    pub fn from_json(scene_str: &str) -> Result<Scene> {
+        parser::parse_scene_description_at_frame(scene_str, SceneFormat::Json, Default::default())
+    }
+
+    pub fn from_yaml(scene_str: &str) -> Result<Scene> {
+        parser::parse_scene_description_at_frame(scene_str, SceneFormat::Yaml, Default::default())
+    }
+
+    /// Scene of a description in any format, at an animation frame and frame rate if given
+    /// instead of those of its configuration
+    pub fn from_description<F: Into<AnimationFrame>>(
+        scene_str: &str,
+        format: SceneFormat,
+        frame: F,
+    ) -> Result<Scene> {
+        parser::parse_scene_description_at_frame(scene_str, format, frame.into())
+    }
+
+    /// Scene at an animation frame, replacing the one of its configuration
+    pub fn from_str_at_frame(scene_str: &str, frame: u32) -> Result<Scene> {
+        parser::parse_scene_description_at_frame(scene_str, SceneFormat::Toml, frame.into())
+    }
+
+    /// Scene at another animation frame of the same description, refitting the object
+    /// hierarchy of this one instead of building it again
+    pub fn next_frame<F: Into<AnimationFrame>>(
+        self,
+        scene_str: &str,
+        format: SceneFormat,
+        frame: F,
+    ) -> Result<Scene> {
+        parser::parse_scene_description_after(scene_str, format, frame.into(), self.objects)
+    }
+
+    /// Every error of a scene description instead of only the first one, with the keys
+    /// which are not known, checked without loading the files it refers to. Only TOML
+    /// errors are located in the text.
+    pub fn validate(scene_str: &str, format: SceneFormat) -> Vec<SceneError> {
+        parser::validate_scene_description(scene_str, format)
+    }
+
+    /// Scene of a cache made by [`scene_cache`], none if it was made from another description
+    /// or if the files it loaded have changed since
+    pub fn from_cache<F: Into<AnimationFrame>>(
+        cache: &[u8],
+        scene_str: &str,
+        frame: F,
+    ) -> Result<Option<Scene>> {
+        parser::parse_scene_cache(cache, scene_str, frame.into())
+    }
+
+    /// Description building this scene again, with its default values and its current
+    /// configuration, to be edited, generated by tools or compared
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(&toml::Value::Table(self.full_description()?))
+            .map_err(|e| RaytracerError::ParsingError(e.to_string()))
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(|e| RaytracerError::ParsingError(e.to_string()))
+    }
+
+    /// Shapes and lights cannot be written back, so a scene is described by what it was
+    /// built from, with the configuration which may have been changed since
+    fn full_description(&self) -> Result<toml::value::Table> {
+        let mut description = self.description.clone().ok_or_else(|| {
+            RaytracerError::ParsingError(
+                "scene built from parts which cannot be described".to_string(),
+            )
+        })?;
+        let config = toml::Value::try_from(&self.config)
+            .map_err(|e| RaytracerError::ParsingError(e.to_string()))?;
+        description.insert("config".to_string(), config);
+        Ok(description)
+    }
+}
+
+impl Serialize for Scene {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        self.full_description()
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::TriangleMesh;
+    use crate::textures::PlainColorTexture;
+
+    #[test]
+    fn no_shading_context_off_the_surface() {
+        let vertices = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        let object = SceneObject {
+            shape: Box::new(TriangleMesh::new(vertices, vec![[0, 1, 2]]).unwrap()),
+            texture: Box::new(PlainColorTexture::default()),
+            effects: Default::default(),
+        };
+        let incoming = Vec3::new(0.0, 0.0, 1.0);
+        let on = object.shading_context(Vec3::new(0.2, 0.2, 0.0), incoming, 0.0, 0);
+        assert!(on.is_some());
+        let off = object.shading_context(Vec3::new(5.0, 5.0, 3.0), incoming, 0.0, 0);
+        assert!(off.is_none());
+    }
 }
@@ -0,0 +1,321 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Long running service rendering the jobs of several users on one machine. Jobs wait in a
+//! queue by priority, a few of them are rendered at the same time, their tiles and rows all
+//! shared by the worker threads of the global thread pool, and the progress of each job is
+//! kept to be reported.
+
+use crate::interpolation::Frame;
+use crate::progress::RenderProgress;
+use crate::renderer::{render_scene, CancellationToken, RenderConfiguration};
+use crate::result::{RaytracerError, Result};
+use crate::scene::Scene;
+use log::{info, warn};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+pub type JobId = u64;
+
+/// Scene to render with its configuration, a job of higher priority being started before
+/// the ones queued with a lower one, and jobs of the same priority in the order they came
+pub struct RenderJob {
+    pub name: String,
+    pub priority: i32,
+    pub scene: Scene,
+    pub config: RenderConfiguration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobStatus {
+    Queued,
+    /// Progress unknown until the first percent is done
+    Rendering(Option<RenderProgress>),
+    /// Rendered in the given time, its frame waiting for `take_frame`
+    Done(Duration),
+    Failed(String),
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Neither queued nor being rendered anymore
+    pub fn is_over(&self) -> bool {
+        !matches!(self, JobStatus::Queued | JobStatus::Rendering(_))
+    }
+}
+
+/// What is reported of a job, in the order the jobs were submitted
+#[derive(Debug, Clone)]
+pub struct JobReport {
+    pub id: JobId,
+    pub name: String,
+    pub priority: i32,
+    pub status: JobStatus,
+}
+
+struct JobEntry {
+    name: String,
+    priority: i32,
+    status: JobStatus,
+    cancellation: CancellationToken,
+    frame: Option<Frame>,
+}
+
+/// Job in the queue, the greatest one being rendered first
+struct QueuedJob {
+    id: JobId,
+    job: RenderJob,
+}
+
+impl QueuedJob {
+    fn key(&self) -> (i32, std::cmp::Reverse<JobId>) {
+        (self.job.priority, std::cmp::Reverse(self.id))
+    }
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for QueuedJob {}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedJob {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+#[derive(Default)]
+struct State {
+    queue: BinaryHeap<QueuedJob>,
+    jobs: HashMap<JobId, JobEntry>,
+    next_id: JobId,
+    /// No job is taken from the queue anymore once the service is shut down
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    /// Notified when a job is queued, when one is over and when the service is shut down
+    changed: Condvar,
+}
+
+impl Shared {
+    fn update(&self, id: JobId, status: JobStatus) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(entry) = state.jobs.get_mut(&id) {
+            entry.status = status;
+        }
+        self.changed.notify_all();
+    }
+}
+
+pub struct RenderService {
+    shared: Arc<Shared>,
+    runners: Vec<JoinHandle<()>>,
+}
+
+impl RenderService {
+    /// Service rendering up to `concurrent_jobs` jobs at the same time, at least one
+    pub fn new(concurrent_jobs: usize) -> RenderService {
+        let shared = Arc::new(Shared::default());
+        let runners = (0..concurrent_jobs.max(1))
+            .map(|_| {
+                let shared = shared.clone();
+                std::thread::spawn(move || run_jobs(shared))
+            })
+            .collect();
+        RenderService { shared, runners }
+    }
+
+    /// Queue a job, its id being used to follow it
+    pub fn submit(&self, job: RenderJob) -> JobId {
+        let mut state = self.shared.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        info!(
+            "Job {} queued: {} at priority {}",
+            id, job.name, job.priority
+        );
+        let entry = JobEntry {
+            name: job.name.clone(),
+            priority: job.priority,
+            status: JobStatus::Queued,
+            cancellation: job.config.cancellation.clone(),
+            frame: None,
+        };
+        state.jobs.insert(id, entry);
+        state.queue.push(QueuedJob { id, job });
+        self.shared.changed.notify_all();
+        id
+    }
+
+    pub fn status(&self, id: JobId) -> Option<JobStatus> {
+        let state = self.shared.state.lock().unwrap();
+        state.jobs.get(&id).map(|entry| entry.status.clone())
+    }
+
+    /// Every job submitted, the ones whose frame was taken included
+    pub fn reports(&self) -> Vec<JobReport> {
+        let state = self.shared.state.lock().unwrap();
+        let mut reports: Vec<_> = state
+            .jobs
+            .iter()
+            .map(|(&id, entry)| JobReport {
+                id,
+                name: entry.name.clone(),
+                priority: entry.priority,
+                status: entry.status.clone(),
+            })
+            .collect();
+        reports.sort_by_key(|report| report.id);
+        reports
+    }
+
+    /// Stop a job: a queued one is never started, a rendering one ends with the pixels it
+    /// computed being dropped. False when it is unknown or already over.
+    pub fn cancel(&self, id: JobId) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        match state.jobs.get_mut(&id) {
+            Some(entry) if !entry.status.is_over() => {
+                entry.cancellation.cancel();
+                if entry.status == JobStatus::Queued {
+                    entry.status = JobStatus::Cancelled;
+                    self.shared.changed.notify_all();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Status of a job once it is over, none when it is unknown
+    pub fn wait(&self, id: JobId) -> Option<JobStatus> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            match state.jobs.get(&id).map(|entry| &entry.status) {
+                None => return None,
+                Some(status) if status.is_over() => return Some(status.clone()),
+                Some(_) => state = self.shared.changed.wait(state).unwrap(),
+            }
+        }
+    }
+
+    /// Frame of a job done, given once
+    pub fn take_frame(&self, id: JobId) -> Option<Frame> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.jobs.get_mut(&id)?.frame.take()
+    }
+
+    /// Wait for the jobs already queued to be over, then stop the service
+    pub fn shutdown(mut self) {
+        self.close();
+    }
+
+    fn close(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.changed.notify_all();
+        for runner in self.runners.drain(..) {
+            if runner.join().is_err() {
+                warn!("A job runner of the render service panicked");
+            }
+        }
+    }
+}
+
+impl Drop for RenderService {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Loop of a runner thread, rendering the job of highest priority until the service is
+/// shut down with an empty queue
+fn run_jobs(shared: Arc<Shared>) {
+    loop {
+        let QueuedJob { id, job } = {
+            let mut state = shared.state.lock().unwrap();
+            loop {
+                if let Some(queued) = state.queue.pop() {
+                    break queued;
+                }
+                if state.closed {
+                    return;
+                }
+                state = shared.changed.wait(state).unwrap();
+            }
+        };
+        let outcome = render_job(&shared, id, job);
+        let mut state = shared.state.lock().unwrap();
+        if let Some(entry) = state.jobs.get_mut(&id) {
+            entry.status = match outcome {
+                Ok((frame, duration)) => {
+                    info!("Job {} done: {}", id, entry.name);
+                    entry.frame = Some(frame);
+                    JobStatus::Done(duration)
+                }
+                Err(RaytracerError::Cancelled) => JobStatus::Cancelled,
+                Err(err) => {
+                    warn!("Job {} failed: {}: {}", id, entry.name, err);
+                    JobStatus::Failed(err.to_string())
+                }
+            };
+        }
+        shared.changed.notify_all();
+    }
+}
+
+fn render_job(shared: &Arc<Shared>, id: JobId, job: RenderJob) -> Result<(Frame, Duration)> {
+    let RenderJob {
+        scene, mut config, ..
+    } = job;
+    if config.cancellation.is_cancelled() {
+        return Err(RaytracerError::Cancelled);
+    }
+    shared.update(id, JobStatus::Rendering(None));
+    let instant_start = Instant::now();
+    let (width, height) = (config.canvas_width, config.canvas_height);
+    let mut job_callback = config.progress.take();
+    let reported = shared.clone();
+    config.progress = Some(Box::new(move |progress: &RenderProgress| {
+        if let Some(callback) = &mut job_callback {
+            callback(progress);
+        }
+        reported.update(id, JobStatus::Rendering(Some(progress.clone())));
+    }));
+    let frame = Frame::from_pixels(width, height, render_scene(scene, config)?)?;
+    Ok((frame, instant_start.elapsed()))
+}
@@ -0,0 +1,198 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Counts of the work done by a rendering, to see where its time goes. The ray tracing code
+//! counts its events in the thread computing a pixel, and these counts are added to the
+//! counters of the configuration once the pixel is done, so threads do not share a counter
+//! at each event.
+
+use std::cell::Cell;
+use std::fmt;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Work done by a rendering, so far if it is not finished
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RenderStats {
+    /// Pixels computed, a block of the progressive renderer being one pixel
+    pub pixels: u64,
+    /// Rays searching the object they hit, from the camera or bounced by a surface
+    pub rays: u64,
+    /// Rays only looking for an obstacle, towards a light or for ambient occlusion
+    pub shadow_rays: u64,
+    pub bvh_node_visits: u64,
+    pub triangle_tests: u64,
+    /// Duration of each phase of the rendering, in the order they ended
+    pub phases: Vec<(&'static str, Duration)>,
+}
+
+impl Display for RenderStats {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let per_pixel = |count: u64| count as f64 / self.pixels.max(1) as f64;
+        writeln!(formatter, "{:<17}{}", "Pixels:", self.pixels)?;
+        for (label, count) in [
+            ("Rays:", self.rays),
+            ("Shadow rays:", self.shadow_rays),
+            ("BVH node visits:", self.bvh_node_visits),
+            ("Triangle tests:", self.triangle_tests),
+        ] {
+            writeln!(
+                formatter,
+                "{:<17}{} ({:.1} per pixel)",
+                label,
+                count,
+                per_pixel(count)
+            )?;
+        }
+        for (phase, duration) in &self.phases {
+            let label = format!("{} time:", phase);
+            writeln!(formatter, "{:<17}{:.3} s", label, duration.as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
+
+/// Event counted while rendering a pixel
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Event {
+    Ray,
+    ShadowRay,
+    BvhNodeVisit,
+    TriangleTest,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct PixelCounts {
+    rays: u64,
+    shadow_rays: u64,
+    bvh_node_visits: u64,
+    triangle_tests: u64,
+}
+
+thread_local! {
+    static PIXEL_COUNTS: Cell<PixelCounts> = Cell::new(PixelCounts::default());
+}
+
+/// Count an event of the pixel computed by the current thread
+pub(crate) fn count(event: Event) {
+    PIXEL_COUNTS.with(|counts| {
+        let mut pixel_counts = counts.get();
+        match event {
+            Event::Ray => pixel_counts.rays += 1,
+            Event::ShadowRay => pixel_counts.shadow_rays += 1,
+            Event::BvhNodeVisit => pixel_counts.bvh_node_visits += 1,
+            Event::TriangleTest => pixel_counts.triangle_tests += 1,
+        }
+        counts.set(pixel_counts);
+    });
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    pixels: AtomicU64,
+    rays: AtomicU64,
+    shadow_rays: AtomicU64,
+    bvh_node_visits: AtomicU64,
+    triangle_tests: AtomicU64,
+    phases: Mutex<Vec<(&'static str, Duration)>>,
+}
+
+/// Counters shared by the threads of a rendering, its clones adding to the same counts
+#[derive(Debug, Default, Clone)]
+pub struct StatsCounters(Arc<Counters>);
+
+impl StatsCounters {
+    /// Counts of the work done since the counters were created
+    pub fn snapshot(&self) -> RenderStats {
+        let counters = &self.0;
+        RenderStats {
+            pixels: counters.pixels.load(Ordering::Relaxed),
+            rays: counters.rays.load(Ordering::Relaxed),
+            shadow_rays: counters.shadow_rays.load(Ordering::Relaxed),
+            bvh_node_visits: counters.bvh_node_visits.load(Ordering::Relaxed),
+            triangle_tests: counters.triangle_tests.load(Ordering::Relaxed),
+            phases: counters.phases.lock().unwrap().clone(),
+        }
+    }
+
+    /// Time taken by a phase of the rendering, or by one done around it like the loading
+    /// of the scene
+    pub fn add_phase(&self, phase: &'static str, duration: Duration) {
+        self.0.phases.lock().unwrap().push((phase, duration));
+    }
+
+    /// Compute a pixel, adding the events counted meanwhile by the current thread
+    pub(crate) fn record_pixel<T, F: FnOnce() -> T>(&self, render: F) -> T {
+        let outer_counts = PIXEL_COUNTS.with(|counts| counts.replace(PixelCounts::default()));
+        let result = render();
+        let pixel_counts = PIXEL_COUNTS.with(|counts| counts.replace(outer_counts));
+        let counters = &self.0;
+        for (counter, value) in [
+            (&counters.pixels, 1),
+            (&counters.rays, pixel_counts.rays),
+            (&counters.shadow_rays, pixel_counts.shadow_rays),
+            (&counters.bvh_node_visits, pixel_counts.bvh_node_visits),
+            (&counters.triangle_tests, pixel_counts.triangle_tests),
+        ] {
+            counter.fetch_add(value, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_added_once_the_pixel_is_done() {
+        let counters = StatsCounters::default();
+        let shared = counters.clone();
+        counters.record_pixel(|| {
+            count(Event::Ray);
+            count(Event::ShadowRay);
+            count(Event::ShadowRay);
+            assert_eq!(shared.snapshot().shadow_rays, 0);
+        });
+        shared.record_pixel(|| count(Event::TriangleTest));
+        let stats = counters.snapshot();
+        assert_eq!(stats.pixels, 2);
+        assert_eq!(stats.rays, 1);
+        assert_eq!(stats.shadow_rays, 2);
+        assert_eq!(stats.bvh_node_visits, 0);
+        assert_eq!(stats.triangle_tests, 1);
+    }
+
+    #[test]
+    fn events_out_of_a_pixel_are_not_added() {
+        let counters = StatsCounters::default();
+        count(Event::Ray);
+        counters.record_pixel(|| count(Event::BvhNodeVisit));
+        let stats = counters.snapshot();
+        assert_eq!(stats.rays, 0);
+        assert_eq!(stats.bvh_node_visits, 1);
+    }
+}
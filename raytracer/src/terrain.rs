@@ -0,0 +1,178 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Terrain meshes displaced by the gray levels of a heightmap image.
+
+use crate::primitives::{MeshShading, TriangleMesh};
+use crate::result::{RaytracerError, Result};
+use crate::vector::Vec3;
+use image::GenericImageView;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Grid of `resolution` vertices centered on the origin in the XZ plane, raised along +Y by
+/// `height` times the gray level of the heightmap, white being the highest.
+///
+/// The top of the image is towards +Z. The gray levels are used as stored in the file,
+/// without sRGB decoding, 16 bits images giving smoother slopes.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Terrain {
+    /// Grayscale image, relative to the current directory
+    pub heightmap: PathBuf,
+    /// Extent along X
+    pub width: f64,
+    /// Extent along Z
+    pub length: f64,
+    pub height: f64,
+    /// Vertices along X then along Z
+    #[serde(default = "Terrain::default_resolution")]
+    pub resolution: [usize; 2],
+    #[serde(default = "Terrain::default_shading")]
+    pub shading: MeshShading,
+}
+
+impl Terrain {
+    fn default_resolution() -> [usize; 2] {
+        [64, 64]
+    }
+
+    fn default_shading() -> MeshShading {
+        MeshShading::Smooth
+    }
+
+    pub fn into_mesh(&self) -> Result<TriangleMesh> {
+        let path = &self.heightmap;
+        let image = image::open(path)
+            .map_err(|e| RaytracerError::IoError(format!("{}: {}", path.display(), e)))?;
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let color = image.color();
+        let levels: Vec<f64> = if color.bytes_per_pixel() > color.channel_count() {
+            let image = image.to_luma16();
+            image
+                .pixels()
+                .map(|pixel| pixel.0[0] as f64 / u16::MAX as f64)
+                .collect()
+        } else {
+            let image = image.to_luma8();
+            image
+                .pixels()
+                .map(|pixel| pixel.0[0] as f64 / u8::MAX as f64)
+                .collect()
+        };
+        self.mesh_of(width, height, &levels)
+    }
+
+    /// Mesh of gray levels between 0 and 1, given row by row from the top of the image
+    fn mesh_of(
+        &self,
+        image_width: usize,
+        image_height: usize,
+        levels: &[f64],
+    ) -> Result<TriangleMesh> {
+        let [columns, rows] = self.resolution;
+        if columns < 2 || rows < 2 || image_width == 0 || image_height == 0 {
+            return Err(RaytracerError::InvalidGeometry(format!(
+                "terrain of {} by {} vertices from a {} by {} heightmap",
+                columns, rows, image_width, image_height
+            )));
+        }
+        // Bilinear interpolation between the pixel centers
+        let level_at = |u: f64, v: f64| {
+            let x = u * (image_width - 1) as f64;
+            let y = (1.0 - v) * (image_height - 1) as f64;
+            let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+            let (x1, y1) = (
+                (x0 + 1).min(image_width - 1),
+                (y0 + 1).min(image_height - 1),
+            );
+            let (tx, ty) = (x - x0 as f64, y - y0 as f64);
+            let level = |x: usize, y: usize| levels[y * image_width + x];
+            let top = level(x0, y0) * (1.0 - tx) + level(x1, y0) * tx;
+            let bottom = level(x0, y1) * (1.0 - tx) + level(x1, y1) * tx;
+            top * (1.0 - ty) + bottom * ty
+        };
+        let mut vertices = Vec::with_capacity(columns * rows);
+        let mut texture_coordinates = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            let v = row as f64 / (rows - 1) as f64;
+            for column in 0..columns {
+                let u = column as f64 / (columns - 1) as f64;
+                vertices.push(Vec3::new(
+                    (u - 0.5) * self.width,
+                    self.height * level_at(u, v),
+                    (v - 0.5) * self.length,
+                ));
+                texture_coordinates.push((u, v));
+            }
+        }
+        // Counter-clockwise seen from above
+        let mut triangles = Vec::with_capacity(2 * (columns - 1) * (rows - 1));
+        for row in 0..rows - 1 {
+            for column in 0..columns - 1 {
+                let a = row * columns + column;
+                let (b, c, d) = (a + 1, a + columns + 1, a + columns);
+                triangles.push([a, c, b]);
+                triangles.push([a, d, c]);
+            }
+        }
+        Ok(TriangleMesh::new(vertices, triangles)?
+            .with_texture_coordinates(texture_coordinates)?
+            .with_shading(self.shading))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::{Ray, Shape};
+
+    fn terrain(resolution: [usize; 2]) -> Terrain {
+        Terrain {
+            heightmap: PathBuf::new(),
+            width: 2.0,
+            length: 2.0,
+            height: 1.0,
+            resolution,
+            shading: MeshShading::Flat,
+        }
+    }
+
+    #[test]
+    fn terrain_follows_the_gray_levels() {
+        // White on the right of the image, black on the left
+        let mesh = terrain([3, 5]).mesh_of(2, 1, &[0.0, 1.0]).unwrap();
+        assert_eq!(mesh.triangles().len(), 2 * 2 * 4);
+        let ray = Ray::new(Vec3::new(0.5, 5.0, 0.3), Vec3::new(0.0, -1.0, 0.0));
+        let hit = mesh.check_collision(&ray).unwrap();
+        assert!((hit.y - 0.75).abs() < 1e-9);
+        let normal = mesh.normal_at(hit).unwrap();
+        assert!(normal.y > 0.0 && normal.x < 0.0 && normal.z.abs() < 1e-9);
+    }
+
+    #[test]
+    fn terrain_needs_a_grid() {
+        assert!(terrain([1, 5]).mesh_of(2, 1, &[0.0, 1.0]).is_err());
+        assert!(terrain([2, 2]).mesh_of(0, 0, &[]).is_err());
+    }
+}
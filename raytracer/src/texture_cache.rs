@@ -0,0 +1,360 @@
+/*
+MIT License
+
+Copyright (c) 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Texels of the image textures, kept in memory by tiles within a budget shared by all
+//! the images.
+//!
+//! An image is decoded the first time it is needed and its tiles are written to a
+//! temporary file, from which each tile is read back when a texel of it is looked up.
+//! When the budget is reached, the least recently used tiles leave the memory, so scenes
+//! with many huge images only keep the parts of them which are seen.
+
+use crate::colors::Color;
+use crate::result::{RaytracerError, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Width and height of the tiles, in texels
+pub const TILE_SIZE: usize = 64;
+
+/// sRGB texels of a tile, the ones outside of the image being black
+const TILE_BYTES: usize = 3 * TILE_SIZE * TILE_SIZE;
+
+/// Memory budget of the shared cache until a scene sets it, in megabytes
+pub const DEFAULT_BUDGET_MB: usize = 1024;
+
+/// Images opened by all the caches, so their tile files have different names
+static OPENED_IMAGES: AtomicUsize = AtomicUsize::new(0);
+
+type Tile = Arc<Vec<u8>>;
+
+/// Image and tile indices
+type TileKey = (usize, usize);
+
+/// Tiles of the images kept in memory, at most the budget of bytes of texels but at
+/// least the last one read
+pub struct TextureCache {
+    budget: AtomicUsize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    tiles: HashMap<TileKey, CachedTile>,
+    /// Tiles by their last use, the least recently used first
+    uses: BTreeMap<u64, TileKey>,
+    clock: u64,
+    loaded_tiles: u64,
+    evicted_tiles: u64,
+}
+
+struct CachedTile {
+    texels: Tile,
+    last_use: u64,
+}
+
+/// Tiles read from the tile files so far, those evicted, and the memory of those kept
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStatistics {
+    pub loaded_tiles: u64,
+    pub evicted_tiles: u64,
+    pub memory: usize,
+}
+
+impl TextureCache {
+    /// Cache keeping at most `budget` bytes of texels
+    pub fn new(budget: usize) -> Self {
+        TextureCache {
+            budget: AtomicUsize::new(budget),
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Cache of the image textures of the scenes, its budget being the one of the last
+    /// scene loaded
+    pub fn global() -> &'static TextureCache {
+        static GLOBAL: OnceLock<TextureCache> = OnceLock::new();
+        GLOBAL.get_or_init(|| TextureCache::new(DEFAULT_BUDGET_MB * 1024 * 1024))
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget.load(Ordering::Relaxed)
+    }
+
+    /// The tiles beyond a lower budget leave the memory when the next tile is read
+    pub fn set_budget(&self, budget: usize) {
+        self.budget.store(budget, Ordering::Relaxed);
+    }
+
+    pub fn statistics(&self) -> CacheStatistics {
+        let state = self.state.lock().unwrap();
+        CacheStatistics {
+            loaded_tiles: state.loaded_tiles,
+            evicted_tiles: state.evicted_tiles,
+            memory: state.tiles.len() * TILE_BYTES,
+        }
+    }
+
+    /// Tile of an image, read from its tile file if it is not in memory
+    fn tile(&self, image: &TiledImage, index: usize) -> Result<Tile> {
+        let key = (image.id, index);
+        if let Some(texels) = self.state.lock().unwrap().touch(key) {
+            return Ok(texels);
+        }
+        let texels = Arc::new(image.read_tile(index)?);
+        let mut state = self.state.lock().unwrap();
+        // Another thread may have read it in the meantime
+        if let Some(texels) = state.touch(key) {
+            return Ok(texels);
+        }
+        state.insert(key, texels.clone());
+        state.evict(self.budget());
+        Ok(texels)
+    }
+}
+
+impl CacheState {
+    fn touch(&mut self, key: TileKey) -> Option<Tile> {
+        let tile = self.tiles.get_mut(&key)?;
+        self.uses.remove(&tile.last_use);
+        self.clock += 1;
+        tile.last_use = self.clock;
+        self.uses.insert(self.clock, key);
+        Some(tile.texels.clone())
+    }
+
+    fn insert(&mut self, key: TileKey, texels: Tile) {
+        self.clock += 1;
+        self.uses.insert(self.clock, key);
+        let last_use = self.clock;
+        self.tiles.insert(key, CachedTile { texels, last_use });
+        self.loaded_tiles += 1;
+    }
+
+    /// Drop the least recently used tiles beyond the budget, except the last one used
+    fn evict(&mut self, budget: usize) {
+        while self.tiles.len() > 1 && self.tiles.len() * TILE_BYTES > budget {
+            let (_, key) = match self.uses.pop_first() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            self.tiles.remove(&key);
+            self.evicted_tiles += 1;
+        }
+    }
+}
+
+/// Image whose texels are read by tiles through a cache, its tile file being removed
+/// when it is dropped
+pub struct TiledImage {
+    id: usize,
+    width: usize,
+    height: usize,
+    /// Tiles in a row of the image
+    columns: usize,
+    tiles_path: PathBuf,
+    tiles_file: Mutex<File>,
+}
+
+impl TiledImage {
+    /// Decode a PNG or JPEG image and write its tiles one after another, row by row
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let image = image::open(path)
+            .map_err(|e| RaytracerError::IoError(format!("{}: {}", path.display(), e)))?
+            .to_rgb8();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        if width == 0 || height == 0 {
+            return Err(RaytracerError::IoError(format!(
+                "{}: the image has no pixel",
+                path.display()
+            )));
+        }
+        let columns = width.div_ceil(TILE_SIZE);
+        let rows = height.div_ceil(TILE_SIZE);
+        let id = OPENED_IMAGES.fetch_add(1, Ordering::Relaxed);
+        let tiles_path =
+            std::env::temp_dir().join(format!("raytracer-{}-{}.tiles", std::process::id(), id));
+        let io_error =
+            |e: std::io::Error| RaytracerError::IoError(format!("{}: {}", tiles_path.display(), e));
+        let mut tiles_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tiles_path)
+            .map_err(io_error)?;
+        let mut tile = vec![0; TILE_BYTES];
+        for row in 0..rows {
+            for column in 0..columns {
+                tile.iter_mut().for_each(|byte| *byte = 0);
+                let tile_height = TILE_SIZE.min(height - row * TILE_SIZE);
+                let tile_width = TILE_SIZE.min(width - column * TILE_SIZE);
+                for y in 0..tile_height {
+                    for x in 0..tile_width {
+                        let pixel = image.get_pixel(
+                            (column * TILE_SIZE + x) as u32,
+                            (row * TILE_SIZE + y) as u32,
+                        );
+                        let offset = 3 * (y * TILE_SIZE + x);
+                        tile[offset..offset + 3].copy_from_slice(&pixel.0);
+                    }
+                }
+                tiles_file.write_all(&tile).map_err(io_error)?;
+            }
+        }
+        Ok(TiledImage {
+            id,
+            width,
+            height,
+            columns,
+            tiles_path,
+            tiles_file: Mutex::new(tiles_file),
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Texel of a column and a row of the image, from the top left corner
+    pub fn texel(&self, cache: &TextureCache, x: usize, y: usize) -> Result<Color> {
+        let (x, y) = (x.min(self.width - 1), y.min(self.height - 1));
+        let index = (y / TILE_SIZE) * self.columns + x / TILE_SIZE;
+        let tile = cache.tile(self, index)?;
+        let offset = 3 * ((y % TILE_SIZE) * TILE_SIZE + x % TILE_SIZE);
+        Ok(Color::from_srgb8([
+            tile[offset],
+            tile[offset + 1],
+            tile[offset + 2],
+        ]))
+    }
+
+    fn read_tile(&self, index: usize) -> Result<Vec<u8>> {
+        let io_error = |e: std::io::Error| {
+            RaytracerError::IoError(format!("{}: {}", self.tiles_path.display(), e))
+        };
+        let mut file = self.tiles_file.lock().unwrap();
+        file.seek(SeekFrom::Start((index * TILE_BYTES) as u64))
+            .map_err(io_error)?;
+        let mut texels = vec![0; TILE_BYTES];
+        file.read_exact(&mut texels).map_err(io_error)?;
+        Ok(texels)
+    }
+}
+
+impl Drop for TiledImage {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.tiles_path);
+    }
+}
+
+impl fmt::Debug for TiledImage {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("TiledImage")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Image of 3x2 tiles, each texel giving its column and row
+    fn gradient_image(name: &str) -> PathBuf {
+        let (width, height) = (3 * TILE_SIZE as u32 - 10, 2 * TILE_SIZE as u32);
+        let image =
+            image::RgbImage::from_fn(width, height, |x, y| image::Rgb([x as u8, y as u8, 7]));
+        let path = std::env::temp_dir().join(format!("{}-{}.png", name, std::process::id()));
+        image.save(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn texels_are_read_from_their_tile() {
+        let path = gradient_image("texture_cache_texels");
+        let image = TiledImage::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((image.width(), image.height()), (182, 128));
+        let cache = TextureCache::new(1024 * 1024);
+        for &(x, y) in &[(0, 0), (70, 3), (181, 127)] {
+            let texel = image.texel(&cache, x, y).unwrap();
+            assert_eq!(texel.to_srgb8(), [x as u8, y as u8, 7]);
+        }
+        assert_eq!(cache.statistics().loaded_tiles, 3);
+        // Outside of the image, the texels of the border are given
+        let texel = image.texel(&cache, 500, 500).unwrap();
+        assert_eq!(texel.to_srgb8(), [181, 127, 7]);
+        assert_eq!(cache.statistics().loaded_tiles, 3);
+    }
+
+    #[test]
+    fn least_recently_used_tiles_are_evicted() {
+        let path = gradient_image("texture_cache_eviction");
+        let image = TiledImage::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let cache = TextureCache::new(2 * TILE_BYTES);
+        image.texel(&cache, 0, 0).unwrap();
+        image.texel(&cache, TILE_SIZE, 0).unwrap();
+        image.texel(&cache, 0, 0).unwrap();
+        image.texel(&cache, 2 * TILE_SIZE, 0).unwrap();
+        let statistics = cache.statistics();
+        assert_eq!(statistics.loaded_tiles, 3);
+        assert_eq!(statistics.evicted_tiles, 1);
+        assert_eq!(statistics.memory, 2 * TILE_BYTES);
+        // The first tile was used after the second one, which left
+        image.texel(&cache, 0, 0).unwrap();
+        assert_eq!(cache.statistics().loaded_tiles, 3);
+        image.texel(&cache, TILE_SIZE, 0).unwrap();
+        assert_eq!(cache.statistics().loaded_tiles, 4);
+        // Even without budget, the tile being read is kept
+        cache.set_budget(0);
+        let texel = image.texel(&cache, 5, TILE_SIZE + 1).unwrap();
+        assert_eq!(texel.to_srgb8(), [5, TILE_SIZE as u8 + 1, 7]);
+        assert_eq!(cache.statistics().memory, TILE_BYTES);
+    }
+
+    #[test]
+    fn tile_file_is_removed_with_the_image() {
+        let path = gradient_image("texture_cache_removal");
+        let image = TiledImage::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let tiles_path = image.tiles_path.clone();
+        assert!(tiles_path.exists());
+        drop(image);
+        assert!(!tiles_path.exists());
+    }
+}
@@ -23,15 +23,76 @@ SOFTWARE.
 */
 
 use crate::colors::Color;
-use crate::utils::{f64_gt, f64_lt};
+use crate::lights::plane_axes;
+use crate::texture_cache::{TextureCache, TiledImage};
+use crate::utils::{cell_seed, f64_gt, f64_lt, fractal_noise, seeded_unit_interval, value_noise};
+use crate::vector::Vec3;
 use crate::UnitInterval;
-use serde::Deserialize;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// What is known of the point of a surface being shaded, given to the textures and the
+/// effects so they can depend on the view, the time or the object
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ShadingContext {
+    /// Point of the surface, as the object is at the frame
+    pub point: Vec3,
+    /// Normal of the surface at the point, normalized, as tilted by the effects so far
+    pub normal: Vec3,
+    /// Texture coordinates of the point
+    pub u: UnitInterval,
+    pub v: UnitInterval,
+    /// Direction of the ray reaching the point, normalized
+    pub incoming: Vec3,
+    /// Time of the ray, in seconds from the frame
+    pub time: f64,
+    /// Time of the scene in the animation, in seconds, the ray being seen `time` after it
+    pub scene_time: f64,
+    /// Index of the object in the scene, different for each copy of an instanced shape
+    pub instance_id: usize,
+}
+
+impl ShadingContext {
+    /// Context of a point and its normal only, the rest being left to the defaults
+    pub fn at(point: Vec3, normal: Vec3) -> Self {
+        ShadingContext {
+            point,
+            normal,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_uv(self, u: UnitInterval, v: UnitInterval) -> Self {
+        ShadingContext { u, v, ..self }
+    }
+
+    pub fn with_normal(self, normal: Vec3) -> Self {
+        ShadingContext { normal, ..self }
+    }
+
+    pub fn with_scene_time(self, scene_time: f64) -> Self {
+        ShadingContext { scene_time, ..self }
+    }
+}
 
 pub trait Texture: Sync + Send {
     fn color_at(&self, u: f64, v: f64) -> Color;
+    /// Color at a shaded point, the one at its texture coordinates unless the texture
+    /// depends on more of the context
+    fn color_in(&self, context: &ShadingContext) -> Color {
+        self.color_at(context.u, context.v)
+    }
+    /// Memory used by the texture, in bytes
+    fn memory_size(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(default)]
 pub struct PlainColorTexture {
     pub color: Color,
@@ -51,7 +112,7 @@ impl Texture for PlainColorTexture {
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct GradientColorTexture {
     pub start_color: Color,
     pub end_color: Color,
@@ -63,7 +124,7 @@ impl Texture for GradientColorTexture {
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(default)]
 pub struct CheckedPattern {
     pub primary_color: Color,
@@ -94,7 +155,273 @@ impl Texture for CheckedPattern {
     }
 }
 
-#[derive(Deserialize, Debug)]
+/// Same scale for both texture coordinates, or one for each
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum TextureScale {
+    Uniform(f64),
+    ByAxis([f64; 2]),
+}
+
+/// Change of the texture coordinates before the texture is read: they are scaled, then
+/// rotated around the origin, then moved, the texture repeating itself outside of [0, 1)
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq)]
+#[serde(default)]
+pub struct TextureTransform {
+    /// Repetitions of the texture along each coordinate
+    pub scale: TextureScale,
+    pub offset: [f64; 2],
+    pub rotation_degree: f64,
+}
+
+impl Default for TextureTransform {
+    fn default() -> Self {
+        TextureTransform {
+            scale: TextureScale::Uniform(1.0),
+            offset: [0.0, 0.0],
+            rotation_degree: 0.0,
+        }
+    }
+}
+
+impl TextureTransform {
+    pub fn is_identity(&self) -> bool {
+        *self == TextureTransform::default()
+    }
+
+    pub fn apply(&self, u: f64, v: f64) -> (UnitInterval, UnitInterval) {
+        let [scale_u, scale_v] = match self.scale {
+            TextureScale::Uniform(scale) => [scale, scale],
+            TextureScale::ByAxis(scale) => scale,
+        };
+        let (u, v) = (u * scale_u, v * scale_v);
+        let (sin, cos) = self.rotation_degree.to_radians().sin_cos();
+        let (u, v) = (cos * u - sin * v, sin * u + cos * v);
+        // Tiny negative values would wrap to 1
+        let wrap = |value: f64| value.rem_euclid(1.0).min(1.0 - f64::EPSILON);
+        (wrap(u + self.offset[0]), wrap(v + self.offset[1]))
+    }
+}
+
+/// Texture read at transformed coordinates
+pub struct TransformedTexture {
+    pub texture: Box<dyn Texture>,
+    pub transform: TextureTransform,
+}
+
+impl Texture for TransformedTexture {
+    fn color_at(&self, u: f64, v: f64) -> Color {
+        let (u, v) = self.transform.apply(u, v);
+        self.texture.color_at(u, v)
+    }
+
+    fn color_in(&self, context: &ShadingContext) -> Color {
+        let (u, v) = self.transform.apply(context.u, context.v);
+        self.texture.color_in(&context.with_uv(u, v))
+    }
+
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.texture.memory_size()
+    }
+}
+
+/// Point of the unit sphere seen at the coordinates of the sphere surface mapping
+fn sphere_direction(u: f64, v: f64) -> Vec3 {
+    let longitude = (u - 0.5) * 2.0 * PI;
+    let latitude = (0.5 - v) * PI;
+    Vec3::new(
+        latitude.cos() * longitude.cos(),
+        latitude.sin(),
+        latitude.cos() * longitude.sin(),
+    )
+}
+
+/// Offset of the noise field drawn from a seed, so each seed gives another world
+fn seeded_offset(seed: u64, stream: u64) -> Vec3 {
+    let component = |axis: u64| 1000.0 * seeded_unit_interval(seed, 3 * stream + axis);
+    Vec3::new(component(0), component(1), component(2))
+}
+
+/// Oceans, continents and ice caps of a planet, drawn from the noise on the sphere
+/// given by spherical texture coordinates so the map has no seam nor pinched poles
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct PlanetTexture {
+    pub seed: u64,
+    /// Number of continents around the planet, roughly
+    pub frequency: f64,
+    /// Noise layers, each one twice as detailed and half as strong as the previous one
+    pub octaves: u32,
+    /// Elevation of the sea between 0 and 1, higher giving more oceans
+    pub sea_level: f64,
+    /// Latitude from which the ice caps start
+    pub ice_latitude_degree: f64,
+    pub deep_ocean_color: Color,
+    pub shallow_ocean_color: Color,
+    pub lowland_color: Color,
+    pub highland_color: Color,
+    pub ice_color: Color,
+}
+
+impl Default for PlanetTexture {
+    fn default() -> Self {
+        PlanetTexture {
+            seed: 0,
+            frequency: 1.5,
+            octaves: 6,
+            sea_level: 0.5,
+            ice_latitude_degree: 70.0,
+            deep_ocean_color: Color::new(0.02, 0.06, 0.2),
+            shallow_ocean_color: Color::new(0.05, 0.25, 0.45),
+            lowland_color: Color::new(0.2, 0.4, 0.12),
+            highland_color: Color::new(0.45, 0.35, 0.25),
+            ice_color: Color::new(0.95, 0.95, 0.97),
+        }
+    }
+}
+
+impl PlanetTexture {
+    /// Elevation of the ground between 0 and 1 along a direction from the center
+    pub fn elevation_at(&self, direction: Vec3) -> f64 {
+        let point = self.frequency * direction + seeded_offset(self.seed, 0);
+        fractal_noise(point, self.octaves)
+    }
+}
+
+impl Texture for PlanetTexture {
+    fn color_at(&self, u: f64, v: f64) -> Color {
+        let direction = sphere_direction(u, v);
+        // Ice caps with a ragged border, over the sea as over the lands
+        let border = 15.0 * (value_noise(4.0 * direction + seeded_offset(self.seed, 1)) - 0.5);
+        let latitude = direction.y.clamp(-1.0, 1.0).asin().to_degrees().abs();
+        if latitude > self.ice_latitude_degree + border {
+            return self.ice_color.clone();
+        }
+        let elevation = self.elevation_at(direction);
+        let sea_level = self.sea_level.clamp(1e-3, 1.0 - 1e-3);
+        let (low, high, weight) = if elevation < sea_level {
+            let depth = (sea_level - elevation) / sea_level;
+            (
+                &self.shallow_ocean_color,
+                &self.deep_ocean_color,
+                4.0 * depth,
+            )
+        } else {
+            let height = (elevation - sea_level) / (1.0 - sea_level);
+            (&self.lowland_color, &self.highland_color, 2.5 * height)
+        };
+        let weight = weight.min(1.0);
+        (1.0 - weight) * low + weight * high
+    }
+}
+
+/// Clouds around a planet, black where the sky is clear so that a slightly larger sphere
+/// with this texture, a full transparency and no shadow only adds the clouds to the planet
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct CloudTexture {
+    pub seed: u64,
+    pub frequency: f64,
+    pub octaves: u32,
+    /// Part of the sky covered by the clouds, between 0 and 1
+    pub coverage: f64,
+    pub color: Color,
+    /// Turn of the clouds around the poles with the time, in degrees per second
+    pub drift_degree: f64,
+}
+
+impl Default for CloudTexture {
+    fn default() -> Self {
+        CloudTexture {
+            seed: 0,
+            frequency: 3.0,
+            octaves: 5,
+            coverage: 0.5,
+            color: Color::WHITE,
+            drift_degree: 0.0,
+        }
+    }
+}
+
+impl Texture for CloudTexture {
+    fn color_at(&self, u: f64, v: f64) -> Color {
+        let point = self.frequency * sphere_direction(u, v) + seeded_offset(self.seed, 2);
+        let density = fractal_noise(point, self.octaves);
+        // Clouds form where the noise is above a threshold lowered by the coverage
+        let threshold = 1.0 - 0.8 * self.coverage.clamp(0.0, 1.0);
+        let opacity = ((density - threshold) / 0.1).clamp(0.0, 1.0);
+        opacity * &self.color
+    }
+
+    fn color_in(&self, context: &ShadingContext) -> Color {
+        let turn = self.drift_degree * (context.scene_time + context.time) / 360.0;
+        let u = (context.u - turn).rem_euclid(1.0).min(1.0 - f64::EPSILON);
+        self.color_at(u, context.v)
+    }
+}
+
+/// PNG or JPEG image mapped on the surface, u going along its rows and v from its bottom
+/// to its top, repeated beyond. Decoded when a ray first sees it, its texels are then
+/// read by tiles through the texture cache, and it is magenta if it cannot be read.
+#[derive(Deserialize, Serialize)]
+pub struct ImageTexture {
+    pub path: PathBuf,
+    #[serde(skip)]
+    image: OnceLock<Option<TiledImage>>,
+}
+
+impl ImageTexture {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        ImageTexture {
+            path: path.as_ref().to_path_buf(),
+            image: OnceLock::new(),
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    /// Texels bilinearly interpolated around the coordinates
+    fn color_at(&self, u: f64, v: f64) -> Color {
+        let image = self.image.get_or_init(|| match TiledImage::open(&self.path) {
+            Ok(image) => Some(image),
+            Err(err) => {
+                error!("Texture image replaced by magenta, {}", err);
+                None
+            }
+        });
+        let image = match image {
+            Some(image) => image,
+            None => return Color::MAGENTA,
+        };
+        let (width, height) = (image.width() as i64, image.height() as i64);
+        let x = u.rem_euclid(1.0) * width as f64 - 0.5;
+        let y = (1.0 - v.rem_euclid(1.0)) * height as f64 - 0.5;
+        let (column, row) = (x.floor(), y.floor());
+        let (tx, ty) = (x - column, y - row);
+        let texel = |column: f64, row: f64| {
+            let column = (column as i64).rem_euclid(width) as usize;
+            let row = (row as i64).rem_euclid(height) as usize;
+            image
+                .texel(TextureCache::global(), column, row)
+                .unwrap_or(Color::MAGENTA)
+        };
+        let top = (1.0 - tx) * &texel(column, row) + tx * &texel(column + 1.0, row);
+        let bottom =
+            (1.0 - tx) * &texel(column, row + 1.0) + tx * &texel(column + 1.0, row + 1.0);
+        (1.0 - ty) * &top + ty * &bottom
+    }
+}
+
+impl fmt::Debug for ImageTexture {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("ImageTexture")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
 #[serde(default)]
 pub struct TextureEffects {
     #[serde(default)]
@@ -103,21 +430,373 @@ pub struct TextureEffects {
     pub transparency: Option<Transparency>,
     #[serde(default)]
     pub mirror: Option<Mirror>,
+    #[serde(default)]
+    pub variation: Option<Variation>,
+    /// Seed of the procedural variation, given automatically to each object if not set
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Render the object as a transparent black matte, still casting shadows and
+    /// hiding what is behind it, for compositing
+    #[serde(default)]
+    pub holdout: bool,
+    /// Only show the shadows cast on the object, over the world color, the rest being
+    /// transparent for compositing
+    #[serde(default)]
+    pub shadow_catcher: bool,
+    /// Let the light of the lights go through the object, as for a thin cloud layer
+    #[serde(default)]
+    pub shadowless: bool,
+    #[serde(default)]
+    pub bump: Option<Bump>,
+    #[serde(default)]
+    pub sheen: Option<Sheen>,
+    #[serde(default)]
+    pub car_paint: Option<CarPaint>,
+    #[serde(default)]
+    pub emission: Option<Emission>,
+    #[serde(default)]
+    pub water: Option<Water>,
+    /// Material whose effects are used when they are not given
+    #[serde(default)]
+    pub preset: Option<EffectPreset>,
+}
+
+/// Ready made materials, giving the effects not set by the description
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EffectPreset {
+    /// Crown glass, slightly dispersive
+    Glass,
+    /// Highly refractive and dispersive, most rays entering it being totally reflected
+    /// several times before leaving it
+    Diamond,
+}
+
+impl TextureEffects {
+    /// Effects with the ones of the preset filled in, if there is a preset
+    pub fn with_preset(mut self) -> Self {
+        let (refractive_index, cauchy_b, size) = match self.preset {
+            None => return self,
+            Some(EffectPreset::Glass) => (1.52, 0.004_2, 100),
+            Some(EffectPreset::Diamond) => (2.42, 0.015, 200),
+        };
+        self.transparency.get_or_insert(Transparency {
+            refractive_index,
+            alpha: 0.9,
+            fresnel: true,
+            cauchy_b,
+            colored_shadow: false,
+        });
+        self.phong.get_or_insert(Phong {
+            size,
+            lum_coeff: 0.8,
+        });
+        self
+    }
+}
+
+/// Glowing volume filling the object, such as fire or a nebula, its temperature given by
+/// a noise and its color by the one of a black body at that temperature. It lets the
+/// light from behind go through depending on its density, but does not cast shadows
+/// nor light the other objects
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct Emission {
+    /// Number of noise features per unit of length
+    pub frequency: f64,
+    /// Noise layers, each one twice as detailed and half as strong as the previous one
+    pub octaves: u32,
+    /// Temperatures in Kelvin of the coldest and hottest parts of the volume
+    pub min_temperature: f64,
+    pub max_temperature: f64,
+    /// Light emitted per unit of length by the hottest parts
+    pub intensity: f64,
+    /// Light absorbed per unit of length by the hottest parts, 0 for a fully clear glow
+    pub density: f64,
+    /// Samples taken along a ray going through the volume
+    pub steps: u32,
+}
+
+impl Default for Emission {
+    fn default() -> Self {
+        Emission {
+            frequency: 1.0,
+            octaves: 3,
+            min_temperature: 1000.0,
+            max_temperature: 6500.0,
+            intensity: 1.0,
+            density: 0.5,
+            steps: 32,
+        }
+    }
+}
+
+impl Emission {
+    /// Heat of the volume at a point, between 0 for the coldest and 1 for the hottest
+    pub fn heat_at(&self, point: Vec3) -> f64 {
+        fractal_noise(self.frequency * point, self.octaves)
+    }
+
+    /// Light emitted along the segment going from `entry` to `exit`, with the part of
+    /// the light from behind still going through the volume
+    pub fn integrate(&self, entry: Vec3, exit: Vec3) -> (Color, f64) {
+        let steps = self.steps.max(1);
+        let step = (1.0 / steps as f64) * Vec3::between_points(entry, exit);
+        let step_length = step.norm();
+        let mut emitted = Color::BLACK;
+        let mut transmittance = 1.0;
+        for index in 0..steps {
+            let point = entry + (index as f64 + 0.5) * step;
+            let heat = self.heat_at(point);
+            let temperature =
+                self.min_temperature + heat * (self.max_temperature - self.min_temperature);
+            emitted += (transmittance * heat * self.intensity * step_length)
+                * Color::from_temperature(temperature);
+            transmittance *= (-self.density * heat * step_length).exp();
+        }
+        (emitted, transmittance)
+    }
 }
 
-impl Default for TextureEffects {
+/// Metallic paint, a base tinted by the texture color sparkling with flakes, under a
+/// clear coat reflecting more at grazing angles
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct CarPaint {
+    /// Size of the tinted highlight of the metallic base, higher being sharper
+    pub metallic_size: u32,
+    /// Part of the surface covered by flakes
+    pub flake_density: UnitInterval,
+    /// Width of a flake
+    pub flake_size: f64,
+    /// Maximum tilt of a flake compared to the surface
+    pub flake_tilt_degree: f64,
+    pub flake_brightness: f64,
+    /// Reflectivity of the clear coat when seen edge-on
+    pub clearcoat: UnitInterval,
+}
+
+impl Default for CarPaint {
     fn default() -> Self {
-        TextureEffects {
-            phong: None,
-            transparency: None,
-            mirror: None,
+        CarPaint {
+            metallic_size: 20,
+            flake_density: 0.2,
+            flake_size: 0.05,
+            flake_tilt_degree: 20.0,
+            flake_brightness: 1.0,
+            clearcoat: 0.5,
         }
     }
 }
 
-#[derive(Deserialize, Debug)]
+/// Reflectance of a varnish with a refractive index of 1.5, for a ray along the normal
+const CLEARCOAT_NORMAL_REFLECTANCE: f64 = 0.04;
+
+impl CarPaint {
+    /// Normal of the flake at the point, if there is one, the same for all the points it covers
+    pub fn flake_normal_at(&self, context: &ShadingContext) -> Option<Vec3> {
+        let ShadingContext { point, normal, .. } = *context;
+        let cell = |value: f64| (value / self.flake_size).floor() as i64;
+        let seed = cell_seed(cell(point.x), cell(point.y), cell(point.z));
+        if seeded_unit_interval(seed, 0) >= self.flake_density {
+            return None;
+        }
+        let random = |stream| 2.0 * seeded_unit_interval(seed, stream) - 1.0;
+        let direction = Vec3::new(random(1), random(2), random(3));
+        let tangent = direction - direction.dot_product(normal) * normal;
+        if tangent.norm() == 0.0 {
+            return Some(normal);
+        }
+        let tilt = (seeded_unit_interval(seed, 4) * self.flake_tilt_degree).to_radians();
+        Some((normal + tilt.tan() * tangent.normalize()).normalize())
+    }
+
+    /// Schlick approximation of the part of the light the clear coat reflects
+    pub fn clearcoat_reflectance(&self, cos_incident: f64) -> f64 {
+        let r0 = CLEARCOAT_NORMAL_REFLECTANCE;
+        self.clearcoat * (r0 + (1.0 - r0) * (1.0 - cos_incident.abs()).powi(5))
+    }
+}
+
+/// Light scattered back by fibers, brightening the silhouette of fabrics like velvet
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct Sheen {
+    pub color: Color,
+    pub coeff: UnitInterval,
+    /// Higher values keep the brightening closer to the silhouette
+    pub power: f64,
+}
+
+impl Default for Sheen {
+    fn default() -> Self {
+        Sheen {
+            color: Color::WHITE,
+            coeff: 0.5,
+            power: 5.0,
+        }
+    }
+}
+
+/// Procedural bumps, tilting the normal used for lighting without changing the geometry
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct Bump {
+    /// Number of bumps per unit of length
+    pub frequency: f64,
+    /// How much the normal is tilted, 0 keeping the surface flat
+    pub strength: f64,
+}
+
+impl Default for Bump {
+    fn default() -> Self {
+        Bump {
+            frequency: 1.0,
+            strength: 0.5,
+        }
+    }
+}
+
+impl Bump {
+    /// Normal tilted by the slope of a noise field around the point, normalized
+    pub fn perturb(&self, context: &ShadingContext) -> Vec3 {
+        const DELTA: f64 = 1e-3;
+        let normal = context.normal;
+        let point = self.frequency * context.point;
+        let slope = |axis: Vec3| {
+            (value_noise(point + DELTA * axis) - value_noise(point - DELTA * axis)) / (2.0 * DELTA)
+        };
+        let gradient = Vec3::new(
+            slope(Vec3::new(1.0, 0.0, 0.0)),
+            slope(Vec3::new(0.0, 1.0, 0.0)),
+            slope(Vec3::new(0.0, 0.0, 1.0)),
+        );
+        // Only the part along the surface tilts the normal
+        let tangent_gradient = gradient - gradient.dot_product(normal) * normal;
+        (normal - self.strength * tangent_gradient).normalize()
+    }
+}
+
+/// Acceleration of gravity, in m/s², driving the speed of the water waves
+const GRAVITY: f64 = 9.81;
+
+/// Angles of the waves around the wind direction, in radians, with their phase
+const WAVES: [(f64, f64); 4] = [(0.0, 0.0), (0.5, 1.7), (-0.4, 3.1), (0.9, 4.6)];
+
+/// Sea or lake surface, refracting and reflecting the light depending on the Fresnel
+/// equations, its normal tilted by waves rolling with the time
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct Water {
+    /// Speed of the wind in m/s, stronger winds giving longer waves
+    pub wind_speed: f64,
+    /// Direction the waves go to on the surface, in degrees
+    pub wind_direction_degree: f64,
+    /// Slope of the waves, 0 keeping the water flat
+    pub steepness: f64,
+    /// Meters in a unit of the scene, to scale the waves
+    pub meters_per_unit: f64,
+    pub refractive_index: f64,
+    /// Tint of the light going through the water
+    pub color: Color,
+    /// Time of the waves in seconds, the one of the scene if not set, the rays seeing them
+    /// later while the shutter is open
+    pub time: Option<f64>,
+}
+
+impl Default for Water {
+    fn default() -> Self {
+        Water {
+            wind_speed: 5.0,
+            wind_direction_degree: 0.0,
+            steepness: 0.3,
+            meters_per_unit: 1.0,
+            refractive_index: 1.33,
+            color: Color::new(0.6, 0.85, 0.9),
+            time: None,
+        }
+    }
+}
+
+impl Water {
+    /// Normal tilted by the slope of the waves at the point, normalized
+    pub fn perturb(&self, context: &ShadingContext) -> Vec3 {
+        let ShadingContext { point, normal, .. } = *context;
+        if self.wind_speed <= 0.0 || self.steepness == 0.0 {
+            return normal;
+        }
+        let (u_axis, v_axis) = plane_axes(normal);
+        let (u, v) = (
+            self.meters_per_unit * point.dot_product(u_axis),
+            self.meters_per_unit * point.dot_product(v_axis),
+        );
+        // The longest waves go as fast as the wind, shorter ones follow the
+        // dispersion relation of deep water
+        let mut wavelength = 2.0 * PI * self.wind_speed.powi(2) / GRAVITY;
+        let slope = self.steepness / WAVES.len() as f64;
+        let time = self.time.unwrap_or(context.scene_time) + context.time;
+        let (mut slope_u, mut slope_v) = (0.0, 0.0);
+        for &(angle, phase) in WAVES.iter() {
+            let wave_number = 2.0 * PI / wavelength;
+            let pulsation = (GRAVITY * wave_number).sqrt();
+            let (sin, cos) = (self.wind_direction_degree.to_radians() + angle).sin_cos();
+            let wave = (wave_number * (cos * u + sin * v) - pulsation * time + phase).cos();
+            slope_u += slope * wave * cos;
+            slope_v += slope * wave * sin;
+            wavelength /= 1.8;
+        }
+        (normal - slope_u * u_axis - slope_v * v_axis).normalize()
+    }
+
+    /// Schlick approximation of the part of the light reflected by the surface, for
+    /// light coming from a medium of some refractive index
+    pub fn reflectance(&self, cos_incident: f64, outside_refractive_index: f64) -> f64 {
+        schlick_reflectance(
+            cos_incident,
+            outside_refractive_index,
+            self.refractive_index,
+        )
+    }
+}
+
+/// Procedural variation of the texture, different for each seed, so copies of an
+/// object do not look all the same
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct Variation {
+    /// Maximum change of each color component
+    pub color_jitter: UnitInterval,
+    /// Shift the texture coordinates by a random offset
+    pub offset: bool,
+}
+
+impl Variation {
+    pub fn color_in(&self, texture: &dyn Texture, context: &ShadingContext, seed: u64) -> Color {
+        let (u, v) = if self.offset {
+            (
+                (context.u + seeded_unit_interval(seed, 0)).fract(),
+                (context.v + seeded_unit_interval(seed, 1)).fract(),
+            )
+        } else {
+            (context.u, context.v)
+        };
+        let color = texture.color_in(&context.with_uv(u, v));
+        let jitter = |component: f64, stream| {
+            component + self.color_jitter * (2.0 * seeded_unit_interval(seed, stream) - 1.0)
+        };
+        Color::new(
+            jitter(color.red(), 2),
+            jitter(color.green(), 3),
+            jitter(color.blue(), 4),
+        )
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(default)]
 pub struct Mirror {
+    /// Part of the color coming from the reflected ray, the rest being the surface color
+    #[serde(alias = "reflectivity")]
     pub coeff: UnitInterval,
 }
 
@@ -127,11 +806,23 @@ impl Default for Mirror {
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(default)]
 pub struct Transparency {
+    /// Index for green light
+    #[serde(alias = "index")]
     pub refractive_index: f64,
+    /// Part of the light going through, unless given by the Fresnel proportions
     pub alpha: UnitInterval,
+    /// Split the light between the reflected and refracted rays with the Fresnel
+    /// proportions given by the refractive indices, reflecting more at grazing angles
+    pub fresnel: bool,
+    /// B coefficient of the Cauchy equation, in µm², making the index higher for shorter
+    /// wavelengths so colors are split apart, 0 for no dispersion
+    pub cauchy_b: f64,
+    /// Let the part `alpha` of the light through to the surfaces behind, tinted by the
+    /// color of the object, instead of casting an opaque shadow, as stained glass does
+    pub colored_shadow: bool,
 }
 
 impl Default for Transparency {
@@ -139,11 +830,42 @@ impl Default for Transparency {
         Transparency {
             refractive_index: 1.0,
             alpha: 0.5,
+            fresnel: false,
+            cauchy_b: 0.0,
+            colored_shadow: false,
         }
     }
 }
 
-#[derive(Deserialize, Debug)]
+/// Wavelength, in µm, where the Cauchy equation gives the nominal refractive index
+pub const REFERENCE_WAVELENGTH: f64 = 0.55;
+
+impl Transparency {
+    pub fn refractive_index_at(&self, wavelength: f64) -> f64 {
+        self.refractive_index
+            + self.cauchy_b * (1.0 / wavelength.powi(2) - 1.0 / REFERENCE_WAVELENGTH.powi(2))
+    }
+}
+
+/// Schlick approximation of the part of the light reflected at the boundary between two
+/// media, all of it beyond the critical angle of a total internal reflection
+pub fn schlick_reflectance(cos_incident: f64, from_index: f64, to_index: f64) -> f64 {
+    let r0 = ((from_index - to_index) / (from_index + to_index)).powi(2);
+    let cos_incident = cos_incident.abs().min(1.0);
+    // Going to a lower index, the angle of the refracted ray is the largest one
+    let cos_angle = if from_index > to_index {
+        let sin_square = (from_index / to_index).powi(2) * (1.0 - cos_incident * cos_incident);
+        if sin_square >= 1.0 {
+            return 1.0;
+        }
+        (1.0 - sin_square).sqrt()
+    } else {
+        cos_incident
+    };
+    r0 + (1.0 - r0) * (1.0 - cos_angle).powi(5)
+}
+
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(default)]
 pub struct Phong {
     pub size: u32,
@@ -158,3 +880,298 @@ impl Default for Phong {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::f64_eq;
+
+    #[test]
+    fn texture_transform_tiles_and_rotates_the_coordinates() {
+        let tiled = TextureTransform {
+            scale: TextureScale::ByAxis([2.0, 1.0]),
+            ..Default::default()
+        };
+        let (u, v) = tiled.apply(0.75, 0.25);
+        assert!((u - 0.5).abs() < 1e-9 && (v - 0.25).abs() < 1e-9);
+        let rotated = TextureTransform {
+            rotation_degree: 90.0,
+            offset: [0.0, 0.5],
+            ..Default::default()
+        };
+        let (u, v) = rotated.apply(0.25, 0.0);
+        assert!(u.abs() < 1e-9 && (v - 0.75).abs() < 1e-9);
+        let (u, _) = rotated.apply(0.0, 1e-17);
+        assert!(u < 1.0);
+        assert!(TextureTransform::default().is_identity() && !rotated.is_identity());
+    }
+
+    /// Bright where the surface faces the ray, dark where it is seen edge-on
+    struct FacingTexture;
+
+    impl Texture for FacingTexture {
+        fn color_at(&self, _: f64, _: f64) -> Color {
+            Color::BLACK
+        }
+
+        fn color_in(&self, context: &ShadingContext) -> Color {
+            context.incoming.dot_product(context.normal).abs() * &Color::WHITE
+        }
+    }
+
+    #[test]
+    fn transformed_texture_keeps_the_shading_context() {
+        let texture = TransformedTexture {
+            texture: Box::new(FacingTexture),
+            transform: TextureTransform {
+                scale: TextureScale::Uniform(2.0),
+                ..Default::default()
+            },
+        };
+        let normal = Vec3::new(0.0, 0.0, -1.0);
+        let facing = ShadingContext {
+            incoming: Vec3::new(0.0, 0.0, 1.0),
+            ..ShadingContext::at(Vec3::default(), normal)
+        };
+        let grazing = ShadingContext {
+            incoming: Vec3::new(1.0, 0.0, 0.0),
+            ..facing
+        };
+        assert!(f64_eq(texture.color_in(&facing).red(), 1.0));
+        assert!(f64_eq(texture.color_in(&grazing).red(), 0.0));
+        assert!(f64_eq(texture.color_at(0.5, 0.5).red(), 0.0));
+    }
+
+    #[test]
+    fn bump_tilts_normal_but_keeps_it_normalized() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let bump = Bump {
+            frequency: 3.0,
+            strength: 0.8,
+        };
+        let tilted = (0..20)
+            .map(|i| Vec3::new(0.37 * i as f64, 0.0, 0.11 * i as f64))
+            .map(|point| bump.perturb(&ShadingContext::at(point, normal)))
+            .inspect(|perturbed| assert!((perturbed.norm() - 1.0).abs() < 1e-9))
+            .filter(|perturbed| perturbed.dot_product(normal) < 1.0 - 1e-6)
+            .count();
+        assert!(tilted > 0);
+    }
+
+    /// Texture coordinates of a grid over the sphere, away from the seam and the poles
+    fn sphere_grid() -> impl Iterator<Item = (f64, f64)> {
+        (1..40).flat_map(|i| (1..20).map(move |j| (i as f64 / 40.0, j as f64 / 20.0)))
+    }
+
+    #[test]
+    fn sphere_direction_follows_sphere_mapping() {
+        use crate::primitives::{Shape, Sphere};
+        let sphere = Sphere::default();
+        for (u, v) in sphere_grid() {
+            let (mapped_u, mapped_v) = sphere.surface_mapping_at(sphere_direction(u, v)).unwrap();
+            assert!((mapped_u - u).abs() < 1e-9 && (mapped_v - v).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn planet_has_oceans_continents_and_ice_caps() {
+        let planet = PlanetTexture::default();
+        let is = |color: &Color, expected: &Color| (color.blue() - expected.blue()).abs() < 1e-9;
+        let colors = sphere_grid()
+            .map(|(u, v)| planet.color_at(u, v))
+            .collect::<Vec<_>>();
+        let oceans = colors
+            .iter()
+            .filter(|color| color.blue() > color.green())
+            .count();
+        let lands = colors
+            .iter()
+            .filter(|color| color.blue() < color.green())
+            .count();
+        assert!(oceans > colors.len() / 10 && lands > colors.len() / 10);
+        assert!(is(&planet.color_at(0.3, 0.01), &planet.ice_color));
+        let other_seed = PlanetTexture {
+            seed: 1,
+            ..Default::default()
+        };
+        assert!(sphere_grid().any(|(u, v)| !is(&planet.color_at(u, v), &other_seed.color_at(u, v))));
+    }
+
+    #[test]
+    fn cloud_coverage_moves_from_clear_to_overcast() {
+        let clouds = |coverage| CloudTexture {
+            coverage,
+            ..Default::default()
+        };
+        let cloudiness = |coverage| {
+            sphere_grid()
+                .map(|(u, v)| clouds(coverage).color_at(u, v).red())
+                .sum::<f64>()
+        };
+        assert_eq!(cloudiness(0.0), 0.0);
+        assert!(cloudiness(0.5) > 0.0);
+        assert!(cloudiness(1.0) > cloudiness(0.5));
+    }
+
+    #[test]
+    fn clouds_drift_around_the_poles_with_the_time() {
+        let clouds = CloudTexture {
+            drift_degree: 36.0,
+            ..Default::default()
+        };
+        let red_in = |clouds: &CloudTexture, u, v, scene_time| {
+            let context = ShadingContext {
+                scene_time,
+                time: 0.5,
+                ..ShadingContext::default().with_uv(u, v)
+            };
+            clouds.color_in(&context).red()
+        };
+        // A quarter of a turn in two seconds and a half
+        let drifted = |(u, v)| red_in(&clouds, u, v, 2.0) - clouds.color_at(u - 0.25, v).red();
+        assert!(sphere_grid().all(|(u, v)| u < 0.25 || drifted((u, v)).abs() < 1e-9));
+        let moved = |(u, v)| red_in(&clouds, u, v, 2.0) != red_in(&clouds, u, v, -0.5);
+        assert!(sphere_grid().any(moved));
+        let still = CloudTexture::default();
+        let kept = |(u, v)| red_in(&still, u, v, 2.0) == still.color_at(u, v).red();
+        assert!(sphere_grid().all(kept));
+    }
+
+    #[test]
+    fn emission_glows_more_and_absorbs_more_through_thicker_volumes() {
+        let emission = Emission::default();
+        let entry = Vec3::new(0.0, 0.0, 0.0);
+        let (thin_glow, thin_transmittance) = emission.integrate(entry, Vec3::new(0.0, 0.0, 1.0));
+        let (thick_glow, thick_transmittance) = emission.integrate(entry, Vec3::new(0.0, 0.0, 4.0));
+        assert!(thin_glow.red() > 0.0 && thick_glow.red() > thin_glow.red());
+        assert!(thick_transmittance < thin_transmittance && thin_transmittance < 1.0);
+        let (no_glow, transmittance) = emission.integrate(entry, entry);
+        assert_eq!((no_glow.red(), transmittance), (0.0, 1.0));
+    }
+
+    #[test]
+    fn water_waves_roll_with_time() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let point = Vec3::new(1.3, 0.0, 2.1);
+        let water_at = |time| Water {
+            time: Some(time),
+            ..Default::default()
+        };
+        let context = ShadingContext::at(point, normal);
+        let tilted = water_at(0.0).perturb(&context);
+        assert!((tilted.norm() - 1.0).abs() < 1e-9);
+        assert!(tilted.dot_product(normal) < 1.0 - 1e-6);
+        let later = water_at(0.5).perturb(&context);
+        assert!(later.distance(tilted) > 1e-6);
+        let seen_later = ShadingContext {
+            time: 0.5,
+            ..context
+        };
+        assert!(water_at(0.0).perturb(&seen_later).distance(later) < 1e-12);
+        let in_scene_later = ShadingContext {
+            scene_time: 0.5,
+            ..context
+        };
+        assert_eq!(Water::default().perturb(&in_scene_later), later);
+        let calm = Water {
+            wind_speed: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(calm.perturb(&context), normal);
+    }
+
+    #[test]
+    fn water_reflects_more_at_grazing_angles() {
+        let water = Water::default();
+        let facing = water.reflectance(1.0, 1.0);
+        assert!((facing - 0.02).abs() < 1e-3);
+        assert!(water.reflectance(0.1, 1.0) > 0.5);
+        assert_eq!(water.reflectance(0.0, 1.0), 1.0);
+    }
+
+    #[test]
+    fn preset_only_fills_missing_effects() {
+        let glass = TextureEffects {
+            preset: Some(EffectPreset::Glass),
+            phong: Some(Phong {
+                size: 10,
+                lum_coeff: 0.1,
+            }),
+            ..Default::default()
+        }
+        .with_preset();
+        assert_eq!(glass.transparency.unwrap().refractive_index, 1.52);
+        assert_eq!(glass.phong.unwrap().size, 10);
+        assert!(TextureEffects::default()
+            .with_preset()
+            .transparency
+            .is_none());
+    }
+
+    #[test]
+    fn schlick_reflectance_grows_at_grazing_angles() {
+        let normal_incidence = schlick_reflectance(1.0, 1.0, 1.5);
+        assert!((normal_incidence - 0.04).abs() < 1e-9);
+        assert!((schlick_reflectance(1.0, 1.5, 1.0) - normal_incidence).abs() < 1e-9);
+        assert!(schlick_reflectance(0.2, 1.0, 1.5) > 0.2);
+        assert!((schlick_reflectance(0.0, 1.0, 1.5) - 1.0).abs() < 1e-9);
+        // Beyond the critical angle of glass, about 42 degrees
+        assert_eq!(schlick_reflectance(0.5, 1.5, 1.0), 1.0);
+    }
+
+    #[test]
+    fn cauchy_dispersion_bends_blue_more_than_red() {
+        let glass = Transparency {
+            refractive_index: 1.5,
+            cauchy_b: 0.004,
+            ..Default::default()
+        };
+        assert!((glass.refractive_index_at(REFERENCE_WAVELENGTH) - 1.5).abs() < 1e-12);
+        assert!(glass.refractive_index_at(0.45) > glass.refractive_index_at(0.65));
+    }
+
+    #[test]
+    fn car_paint_flakes_follow_density() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let points: Vec<_> = (0..50)
+            .map(|i| Vec3::new(0.13 * i as f64, 0.0, 0.07 * i as f64))
+            .collect();
+        let no_flake = CarPaint {
+            flake_density: 0.0,
+            ..Default::default()
+        };
+        let context = |point| ShadingContext::at(point, normal);
+        assert!(points
+            .iter()
+            .all(|&p| no_flake.flake_normal_at(&context(p)).is_none()));
+        let full_of_flakes = CarPaint {
+            flake_density: 1.0,
+            ..Default::default()
+        };
+        let max_cos_tilt = full_of_flakes.flake_tilt_degree.to_radians().cos();
+        for &point in points.iter() {
+            let flake_normal = full_of_flakes.flake_normal_at(&context(point)).unwrap();
+            assert!((flake_normal.norm() - 1.0).abs() < 1e-9);
+            assert!(flake_normal.dot_product(normal) >= max_cos_tilt - 1e-9);
+        }
+    }
+
+    #[test]
+    fn clearcoat_reflects_more_at_grazing_angles() {
+        let paint = CarPaint::default();
+        let along_normal = paint.clearcoat_reflectance(1.0);
+        assert!((along_normal - 0.5 * CLEARCOAT_NORMAL_REFLECTANCE).abs() < 1e-12);
+        assert!(paint.clearcoat_reflectance(0.1) > paint.clearcoat_reflectance(0.9));
+    }
+
+    #[test]
+    fn flat_bump_keeps_normal() {
+        let normal = Vec3::new(0.0, 0.0, -1.0);
+        let bump = Bump {
+            frequency: 2.0,
+            strength: 0.0,
+        };
+        let perturbed = bump.perturb(&ShadingContext::at(Vec3::new(0.4, 1.3, 0.0), normal));
+        assert!(perturbed.distance(normal) < 1e-12);
+    }
+}
@@ -0,0 +1,137 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Small previews of scenes rendered quickly, for file browsers and galleries, trading
+//! the quality of the image for time.
+
+use crate::cameras::PerspectiveCamera;
+use crate::interpolation::Frame;
+use crate::ray_algorithm::camera_ray_hit;
+use crate::ray_algorithm::strategy::StandardRenderStrategy;
+use crate::renderer::{render_scene, RenderConfiguration, RendererKind};
+use crate::result::Result;
+use crate::scene::Scene;
+use crate::vector::Vec3;
+use std::f64::consts::PI;
+
+/// Reflections and refractions followed by the rays of a thumbnail
+pub const THUMBNAIL_LIGHT_RECURSION: u8 = 2;
+
+/// Camera rays per side of the grid looking for an object before the camera is reframed
+const FRAMING_PROBES: u32 = 8;
+
+/// Half of the angle under which a reframed camera sees the objects, on the shorter side
+const FRAMING_HALF_ANGLE: f64 = PI / 6.0;
+
+/// Scene rendered fast into a frame whose longer side is `size` pixels, the other one
+/// following the ratio of the camera. The rays follow few reflections, one sample per
+/// pixel and one shadow ray per area light. A camera seeing none of the bounded objects is
+/// turned into one looking the same way at all of them, from as far as they fit.
+pub fn render_thumbnail(mut scene: Scene, size: u32) -> Result<Frame> {
+    let (width, height) = thumbnail_size(scene.camera.size_ratio(), size);
+    let config = &mut scene.config;
+    config.maximum_light_recursion = config
+        .maximum_light_recursion
+        .min(THUMBNAIL_LIGHT_RECURSION);
+    config.russian_roulette_depth = config.russian_roulette_depth.min(THUMBNAIL_LIGHT_RECURSION);
+    config.area_light_samples = 1;
+    config.ambient_occlusion.samples = config.ambient_occlusion.samples.min(1);
+    if !sees_bounded_object(&scene) {
+        frame_bounded_objects(&mut scene);
+    }
+    let config = RenderConfiguration {
+        canvas_width: width,
+        canvas_height: height,
+        render_strategy: Box::new(StandardRenderStrategy),
+        renderer: match cfg!(target_arch = "wasm32") {
+            true => RendererKind::Sequential,
+            false => RendererKind::Tiled,
+        },
+        ..Default::default()
+    };
+    Frame::from_pixels(width, height, render_scene(scene, config)?)
+}
+
+/// Canvas of a thumbnail, at least one pixel on each side
+pub fn thumbnail_size(size_ratio: f64, size: u32) -> (u32, u32) {
+    let size = size.max(1);
+    let shorter = |side: f64| (side.round() as u32).clamp(1, size);
+    match size_ratio >= 1.0 {
+        true => (size, shorter(size as f64 / size_ratio)),
+        false => (shorter(size as f64 * size_ratio), size),
+    }
+}
+
+fn sees_bounded_object(scene: &Scene) -> bool {
+    let step = 1.0 / FRAMING_PROBES as f64;
+    (0..FRAMING_PROBES * FRAMING_PROBES).any(|probe| {
+        let canvas_x = (probe % FRAMING_PROBES) as f64 * step + 0.5 * step;
+        let canvas_y = (probe / FRAMING_PROBES) as f64 * step + 0.5 * step;
+        let ray = scene.camera.generate_ray(canvas_x, canvas_y);
+        camera_ray_hit(&ray, scene).is_some_and(|(index, _)| scene.objects[index].aabb().is_some())
+    })
+}
+
+/// Camera looking the way of the scene camera at the bounding sphere of the bounded
+/// objects, the scene being left as it is when there are none
+fn frame_bounded_objects(scene: &mut Scene) {
+    let bounds = scene
+        .objects
+        .iter()
+        .filter_map(|object| object.aabb())
+        .reduce(|bounds, aabb| bounds.union(&aabb));
+    let bounds = match bounds {
+        Some(bounds) => bounds,
+        None => return,
+    };
+    let center = bounds.center();
+    let radius = (0.5 * Vec3::between_points(bounds.min, bounds.max).norm()).max(1e-9);
+    let direction = scene.camera.generate_ray(0.5, 0.5).direction.normalize();
+    let size_ratio = scene.camera.size_ratio();
+    // The vertical angle is widened for the sphere to fit a canvas higher than wide
+    let half_angle = (FRAMING_HALF_ANGLE.tan() / size_ratio.min(1.0)).atan();
+    let eye = center - radius / FRAMING_HALF_ANGLE.sin() * direction;
+    let screen_center = eye + direction;
+    let height = 2.0 * half_angle.tan();
+    scene.camera = Box::new(PerspectiveCamera::new(
+        screen_center,
+        center,
+        height * size_ratio,
+        height,
+        half_angle,
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thumbnail_keeps_the_ratio_of_the_camera() {
+        assert_eq!(thumbnail_size(16.0 / 9.0, 128), (128, 72));
+        assert_eq!(thumbnail_size(0.5, 128), (64, 128));
+        assert_eq!(thumbnail_size(1000.0, 128), (128, 1));
+        assert_eq!(thumbnail_size(1.0, 0), (1, 1));
+    }
+}
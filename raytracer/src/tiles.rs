@@ -0,0 +1,226 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Square areas of the canvas rendered one at a time, and the order they are given in. A
+//! rendering by tiles keeps the rays of a task close, and starting from the center shows
+//! first where the subject of the image usually is.
+
+use std::str::FromStr;
+
+/// Area of the canvas, smaller than the tile size on the right and bottom borders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    /// Pixels of the tile, row after row
+    pub fn pixels(self) -> impl Iterator<Item = (u32, u32)> {
+        (self.y..self.y + self.height)
+            .flat_map(move |y| (self.x..self.x + self.width).map(move |x| (x, y)))
+    }
+
+    pub fn pixel_count(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+}
+
+/// Order the tiles of a canvas are rendered in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Rows of tiles, from the top left corner
+    Scanline,
+    /// Square spiral turning around the center tile
+    Spiral,
+    /// Tiles nearer to the center of the canvas first
+    #[default]
+    CenterOut,
+    /// Along a Hilbert curve, each tile being next to the previous one
+    Hilbert,
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_ref() {
+            "scanline" => TileOrder::Scanline,
+            "spiral" => TileOrder::Spiral,
+            "center_out" | "center-out" => TileOrder::CenterOut,
+            "hilbert" => TileOrder::Hilbert,
+            other => return Err(format!("{} is not a valid tile order", other)),
+        })
+    }
+}
+
+impl TileOrder {
+    /// Tiles of side `tile_size` covering the canvas, each once, in this order
+    pub fn tiles(self, canvas_width: u32, canvas_height: u32, tile_size: u32) -> Vec<Tile> {
+        let tile_size = tile_size.max(1);
+        let columns = canvas_width.div_ceil(tile_size);
+        let rows = canvas_height.div_ceil(tile_size);
+        let tile_at = |(column, row): (u32, u32)| {
+            let (x, y) = (column * tile_size, row * tile_size);
+            Tile {
+                x,
+                y,
+                width: tile_size.min(canvas_width - x),
+                height: tile_size.min(canvas_height - y),
+            }
+        };
+        let scanline = (0..rows).flat_map(|row| (0..columns).map(move |column| (column, row)));
+        let cells: Vec<(u32, u32)> = match self {
+            TileOrder::Scanline => scanline.collect(),
+            TileOrder::Spiral => spiral_cells(columns, rows),
+            TileOrder::CenterOut => {
+                let mut cells: Vec<_> = scanline.collect();
+                // Twice the distances, so the centers of the tiles and of the canvas are whole
+                let distance = |&(column, row): &(u32, u32)| {
+                    let dx = (2 * column + 1) as i64 - columns as i64;
+                    let dy = (2 * row + 1) as i64 - rows as i64;
+                    dx * dx + dy * dy
+                };
+                cells.sort_by_key(distance);
+                cells
+            }
+            TileOrder::Hilbert => hilbert_cells(columns, rows),
+        };
+        cells.into_iter().map(tile_at).collect()
+    }
+}
+
+/// Cells of a grid visited by a square spiral from the center, those outside being skipped
+fn spiral_cells(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let count = columns as usize * rows as usize;
+    let inside = |(column, row): (i64, i64)| {
+        (0..columns as i64).contains(&column) && (0..rows as i64).contains(&row)
+    };
+    let mut cell = ((columns as i64 - 1) / 2, (rows as i64 - 1) / 2);
+    let mut cells = Vec::with_capacity(count);
+    if inside(cell) {
+        cells.push((cell.0 as u32, cell.1 as u32));
+    }
+    // Sides of 1, 1, 2, 2, 3, 3... cells, turning after each one
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut side = 1;
+    let mut turn = 0;
+    while cells.len() < count {
+        let (dx, dy) = directions[turn % 4];
+        for _ in 0..side {
+            cell = (cell.0 + dx, cell.1 + dy);
+            if inside(cell) {
+                cells.push((cell.0 as u32, cell.1 as u32));
+            }
+        }
+        if turn % 2 == 1 {
+            side += 1;
+        }
+        turn += 1;
+    }
+    cells
+}
+
+/// Cells of a grid along the Hilbert curve of the smallest power of two square holding it,
+/// those outside being skipped
+fn hilbert_cells(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let side = columns.max(rows).max(1).next_power_of_two() as u64;
+    (0..side * side)
+        .map(|distance| hilbert_cell(side, distance))
+        .filter(|&(column, row)| column < columns && row < rows)
+        .collect()
+}
+
+/// Cell at some distance along the Hilbert curve filling a square of `side` cells
+fn hilbert_cell(side: u64, distance: u64) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    let mut remaining = distance;
+    let mut size = 1;
+    while size < side {
+        let rx = 1 & (remaining / 2);
+        let ry = 1 & (remaining ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = size - 1 - x;
+                y = size - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += size * rx;
+        y += size * ry;
+        remaining /= 4;
+        size *= 2;
+    }
+    (x as u32, y as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const ORDERS: [TileOrder; 4] = [
+        TileOrder::Scanline,
+        TileOrder::Spiral,
+        TileOrder::CenterOut,
+        TileOrder::Hilbert,
+    ];
+
+    #[test]
+    fn tiles_cover_each_pixel_once_in_any_order() {
+        for &order in ORDERS.iter() {
+            let tiles = order.tiles(37, 23, 8);
+            assert_eq!(tiles.len(), 5 * 3);
+            let pixels: HashSet<_> = tiles.iter().flat_map(|tile| tile.pixels()).collect();
+            let count: usize = tiles.iter().map(Tile::pixel_count).sum();
+            assert_eq!((pixels.len(), count), (37 * 23, 37 * 23));
+        }
+    }
+
+    #[test]
+    fn spiral_and_center_out_start_from_the_center() {
+        let center = Tile {
+            x: 16,
+            y: 8,
+            width: 8,
+            height: 8,
+        };
+        assert_eq!(TileOrder::Spiral.tiles(40, 24, 8)[0], center);
+        assert_eq!(TileOrder::CenterOut.tiles(40, 24, 8)[0], center);
+        let scanline = TileOrder::Scanline.tiles(40, 24, 8);
+        assert_eq!((scanline[0].x, scanline[0].y), (0, 0));
+    }
+
+    #[test]
+    fn hilbert_tiles_follow_each_other() {
+        let tiles = TileOrder::Hilbert.tiles(64, 64, 8);
+        for pair in tiles.windows(2) {
+            let gap = (pair[0].x as i64 - pair[1].x as i64).abs()
+                + (pair[0].y as i64 - pair[1].y as i64).abs();
+            assert_eq!(gap, 8);
+        }
+    }
+}
@@ -22,29 +22,169 @@ OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE.
 */
 
+use crate::vector::Vec3;
 use crate::UnitInterval;
 
 pub fn unit_interval_clamp(val: f64) -> UnitInterval {
-    match val {
-        x if x < 0.0 => 0.0,
-        x if x > 1.0 => 1.0,
-        x => x,
-    }
+    val.clamp(0.0, 1.0)
 }
 
 // Not using generics, because it would require a "float" trait which does not
 // exist in the std library, and adding a 3rd party lib only for that is excessive.
 #[inline]
 pub(crate) fn f64_eq(a: f64, b: f64) -> bool {
-    (a - b).abs() <= std::f64::EPSILON
+    (a - b).abs() <= f64::EPSILON
 }
 
 #[inline]
 pub(crate) fn f64_lt(a: f64, b: f64) -> bool {
-    a <= b + std::f64::EPSILON
+    a <= b + f64::EPSILON
 }
 
 #[inline]
 pub(crate) fn f64_gt(a: f64, b: f64) -> bool {
-    a >= b - std::f64::EPSILON
+    a >= b - f64::EPSILON
+}
+
+/// SplitMix64 step, to derive well distributed values from a small seed
+pub(crate) fn mix_seed(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Deterministic value in [0, 1) for a seed, each stream giving another value
+pub(crate) fn seeded_unit_interval(seed: u64, stream: u64) -> UnitInterval {
+    (mix_seed(mix_seed(seed) ^ stream) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Seed of a cell of a grid with integer coordinates
+pub(crate) fn cell_seed(x: i64, y: i64, z: i64) -> u64 {
+    mix_seed(mix_seed(mix_seed(x as u64) ^ y as u64) ^ z as u64)
+}
+
+/// Smooth value noise in [0, 1), interpolated between random values at integer coordinates
+pub(crate) fn value_noise(point: Vec3) -> f64 {
+    let lattice_value =
+        |x: i64, y: i64, z: i64| (cell_seed(x, y, z) >> 11) as f64 / (1u64 << 53) as f64;
+    let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+    let (x, y, z) = (point.x.floor(), point.y.floor(), point.z.floor());
+    let (tx, ty, tz) = (
+        smooth(point.x - x),
+        smooth(point.y - y),
+        smooth(point.z - z),
+    );
+    let (x, y, z) = (x as i64, y as i64, z as i64);
+    let lerp = |a: f64, b: f64, t: f64| a + t * (b - a);
+    let plane = |z: i64| {
+        lerp(
+            lerp(lattice_value(x, y, z), lattice_value(x + 1, y, z), tx),
+            lerp(
+                lattice_value(x, y + 1, z),
+                lattice_value(x + 1, y + 1, z),
+                tx,
+            ),
+            ty,
+        )
+    };
+    lerp(plane(z), plane(z + 1), tz)
+}
+
+/// Value noise in [0, 1) summed over octaves, each one twice as detailed and half as
+/// strong as the previous one
+pub(crate) fn fractal_noise(point: Vec3, octaves: u32) -> f64 {
+    let mut noise = 0.0;
+    let mut weight = 1.0;
+    let mut total_weight = 0.0;
+    let mut frequency = 1.0;
+    for octave in 0..octaves.max(1) {
+        let offset = 17.0 * octave as f64;
+        noise += weight * value_noise(frequency * point + Vec3::new(offset, offset, offset));
+        total_weight += weight;
+        weight /= 2.0;
+        frequency *= 2.0;
+    }
+    noise / total_weight
+}
+
+/// Real roots in [min, max] of the polynomial with the given coefficients, from the
+/// constant term up, sorted. Roots are isolated between the roots of the derivative then
+/// refined by bisection, which keeps its precision for the quartics of torus intersections
+/// where the closed form solutions cancel out digits. Double roots are not found.
+pub(crate) fn polynomial_roots(coefficients: &[f64], min: f64, max: f64) -> Vec<f64> {
+    const BISECTION_STEPS: u32 = 64;
+    let evaluate = |x: f64| coefficients.iter().rev().fold(0.0, |acc, &c| acc * x + c);
+    match coefficients.len() {
+        0 | 1 => return Vec::new(),
+        2 => {
+            let root = -coefficients[0] / coefficients[1];
+            return if root >= min && root <= max {
+                vec![root]
+            } else {
+                Vec::new()
+            };
+        }
+        _ => {}
+    }
+    let derivative: Vec<f64> = coefficients
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(power, &c)| power as f64 * c)
+        .collect();
+    let mut bounds = vec![min];
+    bounds.extend(polynomial_roots(&derivative, min, max));
+    bounds.push(max);
+    let mut roots = Vec::new();
+    for window in bounds.windows(2) {
+        let (mut low, mut high) = (window[0], window[1]);
+        let (value_low, value_high) = (evaluate(low), evaluate(high));
+        if value_low == 0.0 {
+            roots.push(low);
+            continue;
+        }
+        if value_low.signum() == value_high.signum() {
+            continue;
+        }
+        for _ in 0..BISECTION_STEPS {
+            let middle = (low + high) / 2.0;
+            if evaluate(middle).signum() == value_low.signum() {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        roots.push((low + high) / 2.0);
+    }
+    if evaluate(max) == 0.0 {
+        roots.push(max);
+    }
+    roots.dedup_by(|a, b| (*a - *b).abs() <= f64::EPSILON * b.abs().max(1.0));
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quartic_roots_are_found_and_sorted() {
+        // (x - 1)(x - 2)(x + 3)(x - 10)
+        let roots = polynomial_roots(&[-60.0, 76.0, -7.0, -10.0, 1.0], -100.0, 100.0);
+        let expected = [-3.0, 1.0, 2.0, 10.0];
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip(expected.iter()) {
+            assert!((root - expected).abs() < 1e-9);
+        }
+        assert_eq!(
+            polynomial_roots(&[-60.0, 76.0, -7.0, -10.0, 1.0], 0.0, 5.0).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn polynomial_without_real_roots() {
+        assert!(polynomial_roots(&[1.0, 0.0, 1.0, 0.0, 1.0], -100.0, 100.0).is_empty());
+    }
 }
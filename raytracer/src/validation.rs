@@ -0,0 +1,330 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Errors of a scene description located in its TOML text, and detection of the keys
+//! which are not known, most often typos silently ignored otherwise.
+
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Error of a scene description, with where it is in the text when known
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneError {
+    pub message: String,
+    /// Path of the table or key in error, like `object[2].texture`
+    pub key: Option<String>,
+    /// Line and column, counted from 1
+    pub position: Option<(usize, usize)>,
+}
+
+impl SceneError {
+    /// Error of the TOML parser, its message split from the key and position
+    pub(crate) fn of_toml(err: &toml::de::Error) -> Self {
+        let mut message = err.to_string();
+        if let Some(position_start) = message.rfind(" at line ") {
+            message.truncate(position_start);
+        }
+        let (message, key) = split_key(message);
+        SceneError {
+            message,
+            key,
+            position: err.line_col().map(|(line, column)| (line + 1, column + 1)),
+        }
+    }
+}
+
+impl SceneError {
+    /// Error of the JSON parser, its message split from the position
+    pub(crate) fn of_json(err: &serde_json::Error) -> Self {
+        let mut message = err.to_string();
+        if let Some(position_start) = message.rfind(" at line ") {
+            message.truncate(position_start);
+        }
+        SceneError {
+            message,
+            key: None,
+            position: Some((err.line(), err.column())).filter(|&(line, _)| line > 0),
+        }
+    }
+
+    /// Error whose place in the description is not known
+    pub(crate) fn unlocated(message: String) -> Self {
+        SceneError {
+            message,
+            key: None,
+            position: None,
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{}", self.message)?;
+        if let Some(key) = &self.key {
+            write!(formatter, " for key `{}`", key)?;
+        }
+        if let Some((line, column)) = self.position {
+            write!(formatter, " at line {} column {}", line, column)?;
+        }
+        Ok(())
+    }
+}
+
+/// Message of a TOML error, and the key it ends with if any
+pub(crate) fn split_key(mut message: String) -> (String, Option<String>) {
+    const KEY_START: &str = " for key `";
+    let mut key = None;
+    if let Some(key_start) = message.find(KEY_START) {
+        key = Some(
+            message[key_start + KEY_START.len()..]
+                .trim_end_matches('`')
+                .to_string(),
+        );
+        message.truncate(key_start);
+    }
+    (message, key)
+}
+
+/// Old names of keys, still read
+const KEY_ALIASES: [(&str, &str); 2] = [("reflectivity", "coeff"), ("index", "refractive_index")];
+
+/// Paths of the keys of a description missing from the same description written back
+/// from what was understood of it, these keys having been ignored
+pub(crate) fn unknown_keys(
+    given: &toml::Value,
+    understood: &toml::Value,
+    path: &str,
+) -> Vec<String> {
+    let mut unknown = Vec::new();
+    collect_unknown_keys(given, understood, path, &mut unknown);
+    unknown
+}
+
+fn collect_unknown_keys(
+    given: &toml::Value,
+    understood: &toml::Value,
+    path: &str,
+    unknown: &mut Vec<String>,
+) {
+    match (given, understood) {
+        (toml::Value::Table(given), toml::Value::Table(understood)) => {
+            for (key, value) in given {
+                let key_path = child_path(path, key);
+                let understood_value = understood.get(key).or_else(|| {
+                    KEY_ALIASES
+                        .iter()
+                        .find(|(alias, _)| alias == key)
+                        .and_then(|(_, name)| understood.get(*name))
+                });
+                match understood_value {
+                    Some(understood) => collect_unknown_keys(value, understood, &key_path, unknown),
+                    None => unknown.push(key_path),
+                }
+            }
+        }
+        (toml::Value::Array(given), toml::Value::Array(understood)) => {
+            for (index, (value, understood)) in given.iter().zip(understood).enumerate() {
+                collect_unknown_keys(value, understood, &format!("{}[{}]", path, index), unknown);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Position in the text of each key of a TOML description
+pub(crate) struct KeyLocations<'a> {
+    text: &'a str,
+    offsets: HashMap<String, usize>,
+}
+
+impl<'a> KeyLocations<'a> {
+    /// Locations of the keys, none if the text is not valid TOML
+    pub(crate) fn of(text: &'a str) -> Self {
+        let mut offsets = HashMap::new();
+        let mut deserializer = toml::Deserializer::new(text);
+        let locator = Locator {
+            path: String::new(),
+            offsets: &mut offsets,
+        };
+        if locator.deserialize(&mut deserializer).is_err() {
+            offsets.clear();
+        }
+        KeyLocations { text, offsets }
+    }
+
+    /// Line and column of a key, counted from 1. A table without key of its own, like an
+    /// element of an array of tables, is at its first key, else at its parent one.
+    pub(crate) fn position(&self, path: &str) -> Option<(usize, usize)> {
+        let mut path = path;
+        loop {
+            let offset = self.offsets.get(path).copied().or_else(|| {
+                self.offsets
+                    .iter()
+                    .filter(|(key, _)| key.starts_with(path) && key[path.len()..].starts_with('.'))
+                    .map(|(_, offset)| *offset)
+                    .min()
+            });
+            if let Some(offset) = offset {
+                return Some(self.line_column(offset));
+            }
+            path = &path[..path.rfind(['.', '['])?];
+        }
+    }
+
+    fn line_column(&self, offset: usize) -> (usize, usize) {
+        let before = &self.text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        (
+            before.matches('\n').count() + 1,
+            before[line_start..].chars().count() + 1,
+        )
+    }
+
+    /// Error at a key of the description
+    pub(crate) fn error(&self, path: &str, message: String) -> SceneError {
+        SceneError {
+            message,
+            key: Some(path.to_string()),
+            position: self.position(path),
+        }
+    }
+}
+
+/// Walks a document, recording where each key starts
+struct Locator<'a> {
+    path: String,
+    offsets: &'a mut HashMap<String, usize>,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for Locator<'a> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for Locator<'a> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a TOML value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<toml::Spanned<String>>()? {
+            let path = child_path(&self.path, key.get_ref());
+            self.offsets.entry(path.clone()).or_insert(key.start());
+            map.next_value_seed(Locator {
+                path,
+                offsets: &mut *self.offsets,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut index = 0;
+        while seq
+            .next_element_seed(Locator {
+                path: format!("{}[{}]", self.path, index),
+                offsets: &mut *self.offsets,
+            })?
+            .is_some()
+        {
+            index += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCRIPTION: &str = "[config]\nframe = 2\n\n[[object]]\ntype = \"sphere\"\n\
+                               texture = { type = \"plain\", colour = \"red\" }\n\n\
+                               [[object]]\ntype = \"cube\"\n";
+
+    #[test]
+    fn keys_are_located() {
+        let locations = KeyLocations::of(DESCRIPTION);
+        assert_eq!(locations.position("config.frame"), Some((2, 1)));
+        assert_eq!(
+            locations.position("object[0].texture.colour"),
+            Some((6, 29))
+        );
+        // Tables of an array are at their first key
+        assert_eq!(locations.position("object[1]"), Some((9, 1)));
+        assert_eq!(locations.position("object[1].radius"), Some((9, 1)));
+    }
+
+    #[test]
+    fn unknown_keys_are_found() {
+        let given = toml::from_str::<toml::Value>(DESCRIPTION).unwrap();
+        let mut understood = given.clone();
+        let texture = understood["object"][0]["texture"].as_table_mut().unwrap();
+        texture.remove("colour");
+        texture.insert("color".to_string(), toml::Value::from("red"));
+        let unknown = unknown_keys(&given, &understood, "");
+        assert_eq!(unknown, vec!["object[0].texture.colour".to_string()]);
+    }
+
+    #[test]
+    fn toml_errors_are_split() {
+        let err = toml::from_str::<toml::Value>("[config]\nframe = \n").unwrap_err();
+        let error = SceneError::of_toml(&err);
+        assert_eq!(error.position, Some((2, 9)));
+        assert!(!error.message.contains(" at line "));
+    }
+}
@@ -24,10 +24,10 @@ SOFTWARE.
 
 use crate::parser::ModelVector;
 use crate::utils::f64_eq;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default, Copy, Clone, Deserialize)]
-#[serde(from = "ModelVector")]
+#[derive(Debug, Default, Copy, Clone, Deserialize, Serialize)]
+#[serde(from = "ModelVector", into = "ModelVector")]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -165,6 +165,40 @@ impl Mat3 {
         let ssc = Mat3([[0.0, -v.z, v.y], [v.z, 0.0, -v.x], [-v.y, v.x, 0.0]]);
         Mat3::ID + ssc + ((1.0 - from.dot_product(to)) / (v.norm().powi(2))) * ssc * ssc
     }
+
+    /// Rotation around the X, then Y, then Z axis, with angles in radians
+    #[rustfmt::skip]
+    pub fn rotation(angles: Vec3) -> Self {
+        let (sin_x, cos_x) = angles.x.sin_cos();
+        let (sin_y, cos_y) = angles.y.sin_cos();
+        let (sin_z, cos_z) = angles.z.sin_cos();
+        let rotation_x = Mat3([
+            [1.0, 0.0, 0.0],
+            [0.0, cos_x, -sin_x],
+            [0.0, sin_x, cos_x]
+        ]);
+        let rotation_y = Mat3([
+            [cos_y, 0.0, sin_y],
+            [0.0, 1.0, 0.0],
+            [-sin_y, 0.0, cos_y]
+        ]);
+        let rotation_z = Mat3([
+            [cos_z, -sin_z, 0.0],
+            [sin_z, cos_z, 0.0],
+            [0.0, 0.0, 1.0]
+        ]);
+        rotation_z * rotation_y * rotation_x
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut result = Mat3::new();
+        for (i, row) in self.0.iter().enumerate() {
+            for (j, &value) in row.iter().enumerate() {
+                result.0[j][i] = value;
+            }
+        }
+        result
+    }
 }
 
 impl std::cmp::PartialEq for Mat3 {
@@ -372,5 +406,18 @@ mod tests {
             let vec = Vec3::new(1.1, 2.2, 2.2);
             assert_eq!(Mat3::ID, Mat3::transformation_between(vec, vec) );
         }
+
+        #[test]
+        fn quarter_rotation_around_y() {
+            let rotation = Mat3::rotation(Vec3::new(0.0, std::f64::consts::FRAC_PI_2, 0.0));
+            let result = rotation * Vec3::new(1.0, 0.0, 0.0);
+            assert!(result.distance(Vec3::new(0.0, 0.0, -1.0)) < 1e-12);
+        }
+
+        #[test]
+        fn rotation_transpose_is_inverse() {
+            let rotation = Mat3::rotation(Vec3::new(0.3, -1.2, 2.5));
+            assert_eq!(Mat3::ID, rotation * rotation.transpose());
+        }
     }
 }
@@ -24,16 +24,24 @@ SOFTWARE.
 
 #![cfg(target_arch = "wasm32")]
 
+use crate::animation::AnimationFrame;
+use crate::memory::{format_bytes, MemoryUsage};
+use crate::progress::RenderProgress;
 use crate::ray_algorithm::strategy::{RandomAntiAliasingRenderStrategy, StandardRenderStrategy};
 use crate::ray_algorithm::AnyPixelRenderStrategy;
-use crate::renderer::{render_scene, Pixel, RenderConfiguration};
-use crate::result::Result;
-use crate::scene::Scene;
+use crate::renderer::{
+    render_scene, render_tile, CancellationToken, Pixel, RenderConfiguration, RendererKind,
+};
+use crate::result::{RaytracerError, Result};
+use crate::scene::{Scene, SceneFormat};
+use crate::tiles::Tile;
+use crate::validation::SceneError;
+use instant::Instant;
 use log::*;
-use serde::de::Unexpected::Str;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
-use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use wasm_bindgen::prelude::*;
 
 #[cfg(target_arch = "wasm32")]
@@ -46,12 +54,16 @@ pub fn wasm_init() {
 }
 
 #[wasm_bindgen]
-#[serde(default)]
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct JsConfig {
     pub canvas_width: u32,
     pub ray_number: u32,
     pub strategy: Strategy,
+    /// Coarse image first, refined pass after pass
+    pub progressive: bool,
+    /// Time of the scene in seconds, replacing the one of its frame
+    pub time: Option<f64>,
 }
 
 impl JsConfig {
@@ -71,6 +83,8 @@ impl Default for JsConfig {
             canvas_width: 1024,
             ray_number: 50,
             strategy: Strategy::Normal,
+            progressive: false,
+            time: None,
         }
     }
 }
@@ -97,41 +111,282 @@ impl TryFrom<&str> for Strategy {
     }
 }
 
+/// True when the page is cross-origin isolated, so a `SharedArrayBuffer` can be given to
+/// `Renderer::share_buffer`
+#[wasm_bindgen]
+pub fn shared_buffer_available() -> bool {
+    js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("crossOriginIsolated"))
+        .is_ok_and(|isolated| isolated.as_bool() == Some(true))
+}
+
+/// RGBA pixels of the canvas, in the wasm memory or in a buffer shared with other threads
+enum Framebuffer {
+    Local(Vec<u8>),
+    Shared(js_sys::Uint8Array),
+}
+
+impl Framebuffer {
+    fn write(&mut self, index: usize, rgba: [u8; 4]) {
+        match self {
+            Framebuffer::Local(buffer) => buffer[index..index + 4].copy_from_slice(&rgba),
+            Framebuffer::Shared(view) => {
+                for (offset, &value) in rgba.iter().enumerate() {
+                    view.set_index((index + offset) as u32, value);
+                }
+            }
+        }
+    }
+}
+
+fn rgba(pixel: &Pixel, srgb_output: bool) -> [u8; 4] {
+    let [red, green, blue] = pixel.color.to_rgb8(srgb_output);
+    [red, green, blue, (pixel.alpha * 255.0) as u8]
+}
+
 #[wasm_bindgen]
 pub struct Renderer {
     render_iterator: Box<dyn Iterator<Item = Result<Pixel>>>,
-    img_buffer: Vec<u8>,
+    img_buffer: Framebuffer,
     width: u32,
     height: u32,
+    srgb_output: bool,
+    /// Last progress reported by the rendering
+    progress: Arc<Mutex<Option<RenderProgress>>>,
+    cancellation: CancellationToken,
+    /// No pixel left to give, the rendering being over or stopped
+    finished: bool,
+}
+
+/// Pixels rendered by `render_for_ms` between two readings of the clock
+const PIXELS_PER_CLOCK_READ: u32 = 64;
+
+/// Largest memory of a wasm32 module, 65536 pages of 64 KiB
+const WASM_MEMORY_LIMIT: u64 = 65536 * 65536;
+
+/// Memory a rendering needs, told before it is started
+#[derive(Serialize)]
+struct MemoryEstimate {
+    /// RGBA pixels written by the renderer
+    framebuffer_bytes: u64,
+    /// Scene and pixels being rendered, as `MemoryUsage` estimates them
+    render_bytes: u64,
+    total_bytes: u64,
+    limit_bytes: u64,
+    fits: bool,
+}
+
+impl MemoryEstimate {
+    fn new(scene: &Scene, config: &RenderConfiguration) -> MemoryEstimate {
+        let renderer = config.renderer.renderer();
+        let usage = MemoryUsage::estimate(scene, config, renderer.batch_pixels(config));
+        let framebuffer_bytes = 4 * config.canvas_width as u64 * config.canvas_height as u64;
+        let render_bytes = usage.total() as u64;
+        let total_bytes = framebuffer_bytes + render_bytes;
+        MemoryEstimate {
+            framebuffer_bytes,
+            render_bytes,
+            total_bytes,
+            limit_bytes: WASM_MEMORY_LIMIT,
+            fits: total_bytes <= WASM_MEMORY_LIMIT,
+        }
+    }
+
+    /// Error given to JS instead of the allocation failing, with the sizes to pick a
+    /// smaller canvas
+    fn to_error(&self) -> JsValue {
+        let error = serde_json::json!({
+            "kind": "memory_limit",
+            "message": format!(
+                "Rendering needs {}, more than the {} of the wasm memory",
+                format_bytes(self.total_bytes as usize),
+                format_bytes(self.limit_bytes as usize)
+            ),
+            "estimate": self,
+        });
+        to_js(&error).unwrap_or_else(|e| e)
+    }
+}
+
+/// Value as a JS object, through JSON like the deprecated `JsValue::from_serde`
+fn to_js<T: Serialize>(value: &T) -> std::result::Result<JsValue, JsValue> {
+    let json = serde_json::to_string(value).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    js_sys::JSON::parse(&json)
+}
+
+/// Value of a JS object, through JSON like the deprecated `JsValue::into_serde`
+fn from_js<T: DeserializeOwned>(value: &JsValue) -> std::result::Result<T, JsValue> {
+    let json = js_sys::JSON::stringify(value)?
+        .as_string()
+        .unwrap_or_else(|| "null".to_string());
+    serde_json::from_str(&json).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Error of a scene description as JS is given it, to be shown where it is in the editor
+#[derive(Serialize)]
+struct JsSceneError {
+    message: String,
+    /// Path of the table or key in error, like `object[2].texture`
+    field: Option<String>,
+    /// Counted from 1, like the column
+    line: Option<usize>,
+    column: Option<usize>,
+}
+
+impl From<&SceneError> for JsSceneError {
+    fn from(error: &SceneError) -> Self {
+        JsSceneError {
+            message: error.message.clone(),
+            field: error.key.clone(),
+            line: error.position.map(|(line, _)| line),
+            column: error.position.map(|(_, column)| column),
+        }
+    }
+}
+
+fn scene_errors(scene_description: &str) -> Vec<JsSceneError> {
+    Scene::validate(scene_description, SceneFormat::Toml)
+        .iter()
+        .map(JsSceneError::from)
+        .collect()
+}
+
+/// Every error of a TOML scene description, parsing and meaning alike, as an array of
+/// objects of `message`, `field`, `line` and `column`, empty for a valid scene. The files
+/// the scene refers to are not loaded.
+#[wasm_bindgen]
+pub fn validate_scene(scene_description: &str) -> JsValue {
+    to_js(&scene_errors(scene_description)).unwrap_or_else(|e| e)
+}
+
+/// Scene and configuration described by JS, a scene which cannot be built being refused
+/// with an error object of `kind` `invalid_scene`, its `message` and the `errors` of
+/// `validate_scene`
+fn load(
+    scene_description: &str,
+    js_config: JsValue,
+) -> std::result::Result<(Scene, RenderConfiguration), JsValue> {
+    let js_config: JsConfig = from_js(&js_config)?;
+    let frame = match js_config.time {
+        Some(time) => AnimationFrame::default().with_time(time),
+        None => AnimationFrame::default(),
+    };
+    let scene =
+        Scene::from_description(scene_description, SceneFormat::Toml, frame).map_err(|e| {
+            let error = serde_json::json!({
+                "kind": "invalid_scene",
+                "message": e.to_string(),
+                "errors": scene_errors(scene_description),
+            });
+            to_js(&error).unwrap_or_else(|e| e)
+        })?;
+    let config = RenderConfiguration {
+        canvas_width: js_config.canvas_width,
+        canvas_height: (js_config.canvas_width as f64 / scene.camera.size_ratio()) as u32,
+        render_strategy: js_config.generate_strategy(),
+        renderer: match js_config.progressive {
+            true => RendererKind::Progressive,
+            false => RendererKind::Sequential,
+        },
+        ..Default::default()
+    };
+    Ok((scene, config))
+}
+
+/// Memory the rendering of the scene would need, to check it fits before starting it
+#[wasm_bindgen]
+pub fn estimate_memory(
+    scene_description: &str,
+    js_config: JsValue,
+) -> std::result::Result<JsValue, JsValue> {
+    let (scene, config) = load(scene_description, js_config)?;
+    to_js(&MemoryEstimate::new(&scene, &config))
 }
 
 #[wasm_bindgen]
 impl Renderer {
+    /// Renderer of the scene, refused with a `memory_limit` error object when it would not
+    /// fit in the wasm memory
     pub fn new(
         scene_description: &str,
         js_config: JsValue,
     ) -> std::result::Result<Renderer, JsValue> {
-        let scene = Scene::from_str(scene_description).map_err(|e| e.to_string())?;
-        let js_config: JsConfig = js_config.into_serde().map_err(|e| e.to_string())?;
-        let config = RenderConfiguration {
-            canvas_width: js_config.canvas_width,
-            canvas_height: (js_config.canvas_width as f64 / scene.camera.size_ratio()) as u32,
-            render_strategy: js_config.generate_strategy(),
-        };
+        let (scene, mut config) = load(scene_description, js_config)?;
+        let memory = MemoryEstimate::new(&scene, &config);
+        if !memory.fits {
+            return Err(memory.to_error());
+        }
+        let progress = Arc::new(Mutex::new(None));
+        let reported_progress = progress.clone();
+        config.progress = Some(Box::new(move |progress: &RenderProgress| {
+            *reported_progress.lock().unwrap() = Some(progress.clone());
+        }));
         let width = config.canvas_width;
         let height = config.canvas_height;
-        let img_buffer = vec![0; (config.canvas_width * config.canvas_height * 4) as usize];
-        let render_iterator = Box::new(render_scene(scene, config, false).unwrap());
+        let cancellation = config.cancellation.clone();
+        let srgb_output = scene.config.srgb_output;
+        let img_buffer = Framebuffer::Local(vec![0; memory.framebuffer_bytes as usize]);
+        let render_iterator =
+            Box::new(render_scene(scene, config).map_err(|e| JsValue::from_str(&e.to_string()))?);
         Ok(Renderer {
             render_iterator,
             img_buffer,
             width,
             height,
+            srgb_output,
+            progress,
+            cancellation,
+            finished: false,
         })
     }
 
+    /// Stop the rendering, `next` then giving the pixels already computed before it is false
+    pub fn cancel(&self) {
+        self.cancellation.cancel();
+    }
+
+    /// Percent of the pixels done, the progressive passes included
+    pub fn progress_percent(&self) -> u32 {
+        let progress = self.progress.lock().unwrap();
+        progress
+            .as_ref()
+            .map_or(0, |progress| progress.percent() as u32)
+    }
+
+    /// Seconds left at the mean speed so far, unknown before the first percent
+    pub fn eta_seconds(&self) -> Option<f64> {
+        let progress = self.progress.lock().unwrap();
+        progress.as_ref()?.eta.map(|eta| eta.as_secs_f64())
+    }
+
+    /// Start of the pixels in the wasm memory, null once they are written to a shared buffer
     pub fn buffer_ptr(&self) -> *const u8 {
-        self.img_buffer.as_ptr()
+        match &self.img_buffer {
+            Framebuffer::Local(buffer) => buffer.as_ptr(),
+            Framebuffer::Shared(_) => std::ptr::null(),
+        }
+    }
+
+    /// Write the next pixels straight into `buffer`, of 4 bytes per pixel, so the main thread
+    /// paints from it what a worker rendered without the framebuffer being copied out of
+    /// the wasm memory at each update. The pixels already written are copied over once.
+    pub fn share_buffer(
+        &mut self,
+        buffer: js_sys::SharedArrayBuffer,
+    ) -> std::result::Result<(), JsValue> {
+        let expected_length = self.width * self.height * 4;
+        if buffer.byte_length() != expected_length {
+            return Err(JsValue::from_str(&format!(
+                "Shared buffer of {} bytes instead of {}",
+                buffer.byte_length(),
+                expected_length
+            )));
+        }
+        let view = js_sys::Uint8Array::new(&buffer);
+        if let Framebuffer::Local(pixels) = &self.img_buffer {
+            view.copy_from(pixels);
+        }
+        self.img_buffer = Framebuffer::Shared(view);
+        Ok(())
     }
 
     pub fn width(&self) -> u32 {
@@ -142,21 +397,127 @@ impl Renderer {
         self.height
     }
 
+    /// Render up to `count` pixels in a single call, giving how many were rendered, fewer
+    /// than asked once the rendering is finished
+    pub fn next_n(&mut self, count: u32) -> u32 {
+        (0..count).take_while(|_| self.next()).count() as u32
+    }
+
+    /// Render pixels for about `budget_ms` milliseconds, giving how many were rendered, so
+    /// each animation frame of the page renders what its budget allows in a single call
+    pub fn render_for_ms(&mut self, budget_ms: f64) -> u32 {
+        let start = Instant::now();
+        let mut rendered = 0;
+        while !self.finished && start.elapsed().as_secs_f64() * 1000.0 < budget_ms {
+            rendered += self.next_n(PIXELS_PER_CLOCK_READ);
+        }
+        rendered
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // Name of the JS API, the renderer not being a Rust iterator
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> bool {
+        let rendered = self.next_pixel();
+        self.finished = !rendered;
+        rendered
+    }
+
+    fn next_pixel(&mut self) -> bool {
         match self.render_iterator.next() {
             None => false,
             Some(Ok(pixel)) => {
                 let index = 4 * (pixel.x + pixel.y * self.width) as usize;
-                self.img_buffer[index] = (pixel.color.red() * 255.0) as u8;
-                self.img_buffer[index + 1] = (pixel.color.green() * 255.0) as u8;
-                self.img_buffer[index + 2] = (pixel.color.blue() * 255.0) as u8;
-                self.img_buffer[index + 3] = 0xFF;
+                self.img_buffer.write(index, rgba(&pixel, self.srgb_output));
                 true
             }
+            Some(Err(RaytracerError::Cancelled)) => false,
             Some(Err(err)) => {
                 warn!("{}", err);
                 false
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Renderer of some of the tiles of a canvas, one per web worker so that a rendering uses
+/// every core of the browser. Each worker builds its own from the same scene and
+/// configuration, the page then giving every worker ranges of tiles to render and painting
+/// the RGBA pixels they send back.
+#[wasm_bindgen]
+pub struct TileWorker {
+    scene: Scene,
+    config: RenderConfiguration,
+    tiles: Vec<Tile>,
+    srgb_output: bool,
+}
+
+#[wasm_bindgen]
+impl TileWorker {
+    /// Worker of the scene, refused with a `memory_limit` error object as `Renderer` is
+    pub fn new(
+        scene_description: &str,
+        js_config: JsValue,
+    ) -> std::result::Result<TileWorker, JsValue> {
+        let (scene, mut config) = load(scene_description, js_config)?;
+        // Tiles are rendered once, the progressive passes being left out
+        config.renderer = RendererKind::Sequential;
+        let memory = MemoryEstimate::new(&scene, &config);
+        if !memory.fits {
+            return Err(memory.to_error());
+        }
+        let tiles = config.tiles();
+        let srgb_output = scene.config.srgb_output;
+        Ok(TileWorker {
+            scene,
+            config,
+            tiles,
+            srgb_output,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.config.canvas_width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.config.canvas_height
+    }
+
+    /// Tiles of the canvas, in the order they should be rendered
+    pub fn tile_count(&self) -> u32 {
+        self.tiles.len() as u32
+    }
+
+    /// X, Y, width and height of a tile, in pixels
+    pub fn tile_bounds(&self, index: u32) -> std::result::Result<Vec<u32>, JsValue> {
+        let tile = self.tile(index)?;
+        Ok(vec![tile.x, tile.y, tile.width, tile.height])
+    }
+
+    /// RGBA pixels of the tiles from `start` to `end` excluded, tile after tile and each
+    /// one row after row, to be cut with `tile_bounds`
+    pub fn render_tiles(&self, start: u32, end: u32) -> std::result::Result<Vec<u8>, JsValue> {
+        let mut buffer = Vec::new();
+        for index in start..end {
+            let pixels = render_tile(&self.scene, &self.config, self.tile(index)?)
+                .map_err(|e| JsValue::from_str(&e.to_string()))?;
+            buffer.extend(
+                pixels
+                    .iter()
+                    .flat_map(|pixel| rgba(pixel, self.srgb_output)),
+            );
+        }
+        Ok(buffer)
+    }
+
+    fn tile(&self, index: u32) -> std::result::Result<Tile, JsValue> {
+        self.tiles
+            .get(index as usize)
+            .copied()
+            .ok_or_else(|| JsValue::from_str(&format!("No tile {} of {}", index, self.tiles.len())))
+    }
+}
@@ -22,12 +22,17 @@ OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE.
 */
 
-use raytracer::cameras::PerspectiveCamera;
+// Shared by several test crates, each using only a part of it
+#![allow(dead_code)]
+
+use raytracer::cameras::{OrthogonalCamera, PerspectiveCamera};
 use raytracer::colors::Color;
+use raytracer::io::obj::load_obj;
 use raytracer::lights::LightPoint;
-use raytracer::primitives::Sphere;
+use raytracer::primitives::{Sphere, TriangleMesh};
+use raytracer::renderer::RenderConfiguration;
 use raytracer::scene::{Scene, SceneObject};
-use raytracer::textures::CheckedPattern;
+use raytracer::textures::{CheckedPattern, PlainColorTexture, TextureEffects};
 use raytracer::vector::Vec3;
 use std::f64::consts::PI;
 use std::fs;
@@ -37,26 +42,38 @@ use std::path::PathBuf;
 
 
 pub enum SampleScene {
-    OkBasic
+    OkBasic,
+    OkMesh,
+    OkScatter,
+    OkSvg,
+    ErrMissingMesh,
 }
 
 impl SampleScene {
     const SAMPLES_ROOT_DIR: [&'static str; 2] = ["tests", "samples"];
 
+    #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self) -> String {
         let sample = match self {
             SampleScene::OkBasic => "ok_basic.toml",
+            SampleScene::OkMesh => "ok_mesh.toml",
+            SampleScene::OkScatter => "ok_scatter.toml",
+            SampleScene::OkSvg => "ok_svg.toml",
+            SampleScene::ErrMissingMesh => "err_missing_mesh.toml",
         };
         Self::load_sample_file(sample)
     }
 
     fn load_sample_file<T: AsRef<str>>(sample_file: T) -> String {
+        fs::read_to_string(Self::sample_path(sample_file)).unwrap()
+    }
+
+    fn sample_path<T: AsRef<str>>(sample_file: T) -> PathBuf {
         let sample_file = sample_file.as_ref();
-        let scene_path: PathBuf = SampleScene::SAMPLES_ROOT_DIR
+        SampleScene::SAMPLES_ROOT_DIR
             .iter()
             .chain(iter::once(&sample_file))
-            .collect();
-        fs::read_to_string(scene_path).unwrap()
+            .collect()
     }
 
 }
@@ -71,7 +88,7 @@ pub fn generate_test_scene() -> Scene {
         Vec3::new(0.0, 0.0, 30.0),
         16.0 * 2.0,
         9.0 * 2.0,
-        (PI / 8.0) as f64,
+        PI / 8.0,
     );
     let light = LightPoint::with_color(Vec3::new(50.0, 100.0, -50.0), Color::new(0.8, 0.8, 0.8));
     let primitive: Sphere = Sphere {
@@ -87,7 +104,41 @@ pub fn generate_test_scene() -> Scene {
     Scene {
         camera: Box::new(camera),
         lights: vec![Box::new(light)],
-        objects: vec![object],
+        objects: vec![object].into(),
         config: Default::default(),
+        warnings: Vec::new(),
+        description: None,
+    }
+}
+
+/// Cube of side 2 centered on the origin, of `cube.obj`
+pub fn cube_mesh() -> TriangleMesh {
+    load_obj(SampleScene::sample_path("cube.obj")).unwrap()
+}
+
+/// White sphere with some effects
+pub fn sphere_object(center: Vec3, radius: f64, effects: TextureEffects) -> SceneObject {
+    SceneObject {
+        shape: Box::new(Sphere { center, radius }),
+        texture: Box::new(PlainColorTexture::default()),
+        effects,
+    }
+}
+
+/// Test scene with other objects, seen from the front by an orthogonal camera
+pub fn orthogonal_scene(objects: Vec<SceneObject>) -> Scene {
+    Scene {
+        camera: Box::new(OrthogonalCamera::default()),
+        objects: objects.into(),
+        ..generate_test_scene()
+    }
+}
+
+/// Canvas small enough for quick renderings, with the 16:9 ratio of the test cameras
+pub fn small_config() -> RenderConfiguration {
+    RenderConfiguration {
+        canvas_width: 32,
+        canvas_height: 18,
+        ..Default::default()
     }
 }
@@ -130,11 +130,13 @@ pub(crate) fn generate_test_scene() -> Scene {
     Scene {
         camera: Box::new(camera),
         lights: vec![Box::new(light_1), Box::new(light_2)],
-        objects: vec![object_1, object_2, object_3, object_4, object_5],
+        objects: vec![object_1, object_2, object_3, object_4, object_5].into(),
         config: SceneConfiguration {
             ambient_light: Some(Color::new(0.0, 0.0, 0.2)),
             maximum_light_recursion: 2,
             ..Default::default()
         },
+        warnings: Vec::new(),
+        description: None,
     }
 }
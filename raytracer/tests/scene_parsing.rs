@@ -24,11 +24,13 @@ SOFTWARE.
 
 mod samples;
 
-use raytracer::scene::Scene;
-use std::fs;
-use std::iter;
-use std::str::FromStr;
+use raytracer::animation::AnimationFrame;
+use raytracer::primitives::Ray;
+use raytracer::result::RaytracerError;
+use raytracer::scene::{dump_scene_description, Scene, SceneDumpFormat, SceneFormat};
+use raytracer::vector::Vec3;
 use samples::SampleScene;
+use std::str::FromStr;
 
 #[test]
 fn load_basic_scene() {
@@ -36,3 +38,798 @@ fn load_basic_scene() {
     let scene_result = Scene::from_str(&scene_string);
     assert!(scene_result.is_ok());
 }
+
+#[test]
+fn load_scene_with_obj_mesh() {
+    let scene_string = SampleScene::OkMesh.to_string();
+    let scene = Scene::from_str(&scene_string).unwrap();
+    assert_eq!(scene.objects.len(), 2);
+}
+
+#[test]
+fn load_scene_with_svg_extrusion() {
+    let scene_string = SampleScene::OkSvg.to_string();
+    let scene = Scene::from_str(&scene_string).unwrap();
+    assert_eq!(scene.objects.len(), 1);
+    let aabb = scene.objects[0].shape.aabb().unwrap();
+    assert!((aabb.min.x + 4.5).abs() < 1e-6 && (aabb.max.x - 4.5).abs() < 1e-6);
+    assert!((aabb.max.y - 4.5).abs() < 1e-6 && (aabb.max.z - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn cached_scene_is_the_parsed_one() {
+    let scene_string = SampleScene::OkMesh.to_string();
+    let cache = raytracer::scene::scene_cache(&scene_string, SceneFormat::Toml).unwrap();
+    let scene = Scene::from_cache(&cache, &scene_string, Some(3));
+    let scene = scene.unwrap().unwrap();
+    let parsed = Scene::from_str(&scene_string).unwrap();
+    assert_eq!(scene.objects.len(), parsed.objects.len());
+    assert_eq!(scene.config.frame, 3);
+    let cached_box = scene.objects[0].aabb().unwrap();
+    let parsed_box = parsed.objects[0].aabb().unwrap();
+    assert_eq!(
+        (cached_box.min, cached_box.max),
+        (parsed_box.min, parsed_box.max)
+    );
+    // The hierarchy of the mesh is read from the cache instead of being built again
+    assert!(String::from_utf8_lossy(&cache).contains("bvh"));
+    let cached_bvh = scene.objects[0].shape.bottom_level_bvh().unwrap();
+    let parsed_bvh = parsed.objects[0].shape.bottom_level_bvh().unwrap();
+    assert_eq!(cached_bvh.memory_size(), parsed_bvh.memory_size());
+    // Made from another description
+    let other = format!("{}\n# Comment\n", scene_string);
+    assert!(Scene::from_cache(&cache, &other, None).unwrap().is_none());
+}
+
+#[test]
+fn cache_is_stale_when_a_file_changes() {
+    let path = std::env::temp_dir().join("raytracer_cache_test.obj");
+    std::fs::write(&path, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+    let scene_string = format!(
+        "{}\n[[object]]\ntype = \"mesh\"\nmesh = {:?}\ntexture.type = \"plain\"\n",
+        SampleScene::OkBasic.to_string(),
+        path
+    );
+    let cache = raytracer::scene::scene_cache(&scene_string, SceneFormat::Toml).unwrap();
+    assert!(Scene::from_cache(&cache, &scene_string, None)
+        .unwrap()
+        .is_some());
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap();
+    assert!(Scene::from_cache(&cache, &scene_string, None)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn included_files_are_merged() {
+    let lights = std::env::temp_dir().join("raytracer_include_lights.toml");
+    let objects = std::env::temp_dir().join("raytracer_include_objects.json");
+    std::fs::write(
+        &lights,
+        "[config]\narea_light_samples = 4\nsrgb_output = false\n\n\
+         [[light]]\ntype = \"point\"\nsource = [0, 10, 0]\ncolor = \"white\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        &objects,
+        r#"{"object": [{"type": "sphere", "center": [0, 0, 0], "radius": 1,
+                        "texture": {"type": "plain", "color": "red"}}]}"#,
+    )
+    .unwrap();
+    let scene_string = format!(
+        r#"
+        include = [{:?}, {:?}]
+
+        [config]
+        area_light_samples = 2
+
+        [camera]
+        type = "orthogonal"
+        eye = [0, 0, -10]
+        look_at = [0, 0, 0]
+        width = 16
+        height = 9
+
+        [[object]]
+        type = "sphere"
+        center = [5, 0, 0]
+        radius = 1
+        texture.type = "plain"
+        "#,
+        lights, objects
+    );
+    let scene = Scene::from_str(&scene_string).unwrap();
+    assert_eq!(scene.lights.len(), 1);
+    assert_eq!(scene.objects.len(), 2);
+    // The including scene overriding the included values
+    assert_eq!(scene.config.area_light_samples, 2);
+    assert!(!scene.config.srgb_output);
+    assert!(scene.objects[0].aabb().unwrap().center().x.abs() < 1e-9);
+    assert!(Scene::validate(&scene_string, SceneFormat::Toml).is_empty());
+}
+
+#[test]
+fn objects_share_named_materials() {
+    let objects = r#"
+        [materials.mirror]
+        texture.type = "plain"
+        effect.mirror.coeff = 0.5
+
+        [[object]]
+        type = "sphere"
+        center = [0, 0, 0]
+        radius = 1
+        material = "mirror"
+
+        [[object]]
+        type = "sphere"
+        center = [5, 0, 0]
+        radius = 1
+        material = "mirror"
+        effect.phong = {}
+        "#;
+    let scene_string = format!("{}\n{}", SampleScene::OkBasic.to_string(), objects);
+    let scene = Scene::from_str(&scene_string).unwrap();
+    let count = scene.objects.len();
+    let mirror = |index: usize| {
+        scene.objects[index]
+            .effects
+            .mirror
+            .as_ref()
+            .map(|m| m.coeff)
+    };
+    assert_eq!(mirror(count - 2), Some(0.5));
+    // The effects of the object replace those of its material
+    assert_eq!(mirror(count - 1), None);
+    assert!(Scene::validate(&scene_string, SceneFormat::Toml).is_empty());
+    let written = Scene::from_str(&scene.to_toml().unwrap()).unwrap();
+    assert_eq!(written.objects.len(), count);
+}
+
+#[test]
+fn unknown_material_is_error() {
+    let object = "[[object]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\n\
+                  material = \"gold\"\n";
+    let scene_string = format!("{}\n{}", SampleScene::OkBasic.to_string(), object);
+    match Scene::from_str(&scene_string) {
+        Err(RaytracerError::InvalidScene(error)) => {
+            assert_eq!(error.message, "unknown material `gold`")
+        }
+        _ => panic!("Unknown material accepted"),
+    }
+    let errors = Scene::validate(&scene_string, SceneFormat::Toml);
+    assert_eq!(errors.len(), 1);
+    let line = scene_string
+        .lines()
+        .position(|line| line.contains("gold"))
+        .unwrap();
+    assert_eq!(errors[0].position, Some((line + 1, 1)));
+}
+
+#[test]
+fn instances_place_copies_of_their_objects() {
+    let instances = r#"
+        [[instances]]
+        description = "Snowmen"
+
+        [[instances.object]]
+        type = "sphere"
+        center = [0, 1, 0]
+        radius = 1
+        texture.type = "plain"
+
+        [[instances.object]]
+        type = "sphere"
+        center = [0, 2.5, 0]
+        radius = 0.5
+        texture.type = "plain"
+        texture.color = "red"
+
+        [[instances.placement]]
+        position = [-10, 0, 0]
+
+        [[instances.placement]]
+        position = [10, 0, 0]
+        rotation_degree = [0, 0, 180]
+        scale = 2
+        "#;
+    let scene_string = format!("{}\n{}", SampleScene::OkBasic.to_string(), instances);
+    let count = Scene::from_str(&SampleScene::OkBasic.to_string())
+        .unwrap()
+        .objects
+        .len();
+    let scene = Scene::from_str(&scene_string).unwrap();
+    assert_eq!(scene.objects.len(), count + 4);
+    let center = |index: usize| scene.objects[count + index].aabb().unwrap().center();
+    assert!(center(1).distance(Vec3::new(-10.0, 2.5, 0.0)) < 1e-9);
+    // Upside down and twice as large
+    assert!(center(3).distance(Vec3::new(10.0, -5.0, 0.0)) < 1e-9);
+    assert!(Scene::validate(&scene_string, SceneFormat::Toml).is_empty());
+    let written = Scene::from_str(&scene.to_toml().unwrap()).unwrap();
+    assert_eq!(written.objects.len(), count + 4);
+}
+
+#[test]
+fn include_cycle_is_error() {
+    let first = std::env::temp_dir().join("raytracer_include_cycle_1.toml");
+    let second = std::env::temp_dir().join("raytracer_include_cycle_2.toml");
+    std::fs::write(&first, format!("include = {:?}\n", second)).unwrap();
+    std::fs::write(&second, format!("include = {:?}\n", first)).unwrap();
+    let scene_string = format!("include = [{:?}]\n", first);
+    match Scene::from_str(&scene_string) {
+        Err(RaytracerError::InvalidScene(error)) => {
+            assert!(
+                error.message.contains("cycle of included files"),
+                "{}",
+                error
+            )
+        }
+        _ => panic!("Include cycle not found"),
+    }
+}
+
+#[test]
+fn load_scene_with_scattered_objects() {
+    let scene_string = SampleScene::OkScatter.to_string();
+    let scene = Scene::from_str(&scene_string).unwrap();
+    assert_eq!(scene.objects.len(), 8 + 5);
+    let seeds: Vec<_> = scene.objects.iter().map(|o| o.effects.seed).collect();
+    assert!(seeds.windows(2).all(|pair| pair[0] != pair[1]));
+}
+
+#[test]
+fn scattered_objects_are_reproducible() {
+    let scene_string = SampleScene::OkScatter.to_string();
+    let first = Scene::from_str(&scene_string).unwrap();
+    let second = Scene::from_str(&scene_string).unwrap();
+    let centers = |scene: &Scene| -> Vec<_> {
+        scene
+            .objects
+            .iter()
+            .map(|o| o.shape.aabb().unwrap().center())
+            .collect()
+    };
+    assert_eq!(centers(&first), centers(&second));
+}
+
+#[test]
+fn scatter_scale_must_be_positive() {
+    for scale in &["[0, 0]", "[-1, 1]", "[1, nan]"] {
+        let scene_string = SampleScene::OkScatter
+            .to_string()
+            .replace("scale = [0.5, 1.5]", &format!("scale = {}", scale));
+        match Scene::from_str(&scene_string) {
+            Err(RaytracerError::InvalidScene(error)) => {
+                assert_eq!(error.key.as_deref(), Some("scatter[0].scale"))
+            }
+            _ => panic!("Scale {} must fail", scale),
+        }
+    }
+}
+
+#[test]
+fn scatter_surface_normal_is_normalized() {
+    let centers = |normal: &str| -> Vec<_> {
+        let scene_string = SampleScene::OkScatter
+            .to_string()
+            .replace("normal = [0, 1, 0]", &format!("normal = {}", normal));
+        let scene = Scene::from_str(&scene_string).unwrap();
+        scene
+            .objects
+            .iter()
+            .map(|o| o.shape.aabb().unwrap().center())
+            .collect()
+    };
+    let unit = centers("[0, 1, 1]");
+    let long = centers("[0, 3, 3]");
+    assert!(unit.iter().zip(&long).all(|(a, b)| a.distance(*b) < 1e-9));
+    match Scene::from_str(
+        &SampleScene::OkScatter
+            .to_string()
+            .replace("normal = [0, 1, 0]", "normal = [0, 0, 0]"),
+    ) {
+        Err(RaytracerError::InvalidScene(error)) => {
+            assert_eq!(error.key.as_deref(), Some("scatter[1].region.normal"))
+        }
+        _ => panic!("A null normal must fail"),
+    }
+}
+
+#[test]
+fn missing_obj_mesh_is_error() {
+    let scene_string = SampleScene::ErrMissingMesh.to_string();
+    let scene_result = Scene::from_str(&scene_string);
+    assert!(scene_result.is_err());
+}
+
+#[test]
+fn missing_obj_mesh_is_replaced_by_placeholder() {
+    let scene_string = SampleScene::ErrMissingMesh.to_string();
+    let scene_string = format!("{}\n[config]\nasset_placeholders = true\n", scene_string);
+    let scene = Scene::from_str(&scene_string).unwrap();
+    assert_eq!(scene.objects.len(), 1);
+    assert_eq!(scene.warnings.len(), 1);
+}
+
+#[test]
+fn hdr_environment_map_is_the_background() {
+    // Uniform light of intensity 2, in a 1 by 1 Radiance HDR file
+    let path = std::env::temp_dir().join("raytracer_environment_test.hdr");
+    let mut content = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 1\n".to_vec();
+    content.extend_from_slice(&[128, 128, 128, 130]);
+    std::fs::write(&path, content).unwrap();
+    let scene_string = format!(
+        "{}\n[config.world]\ntype = \"image\"\npath = {:?}\nintensity = 0.5\n",
+        SampleScene::OkBasic.to_string(),
+        path
+    );
+    let scene = Scene::from_str(&scene_string).unwrap();
+    let color = scene.config.background_color(Vec3::new(0.3, 0.2, 1.0));
+    assert!((color.red() - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn missing_environment_map_is_replaced_by_placeholder() {
+    let world = "[config.world]\ntype = \"image\"\npath = \"missing.hdr\"\n";
+    let scene_string = format!("{}\n{}", SampleScene::OkBasic.to_string(), world);
+    assert!(Scene::from_str(&scene_string).is_err());
+    let scene_string = format!(
+        "{}\n[config]\nasset_placeholders = true\n{}",
+        SampleScene::OkBasic.to_string(),
+        world
+    );
+    let scene = Scene::from_str(&scene_string).unwrap();
+    assert_eq!(scene.warnings.len(), 1);
+}
+
+#[test]
+fn terrain_is_raised_by_the_heightmap() {
+    // Black then white pixel, the terrain going up towards +X
+    let path = std::env::temp_dir().join("raytracer_terrain_test.png");
+    image::GrayImage::from_raw(2, 1, vec![0, 255])
+        .unwrap()
+        .save(&path)
+        .unwrap();
+    let terrain = format!(
+        "[[object]]\ntype = \"terrain\"\nheightmap = {:?}\nwidth = 10\nlength = 4\n\
+         height = 3\nresolution = [8, 2]\ntexture.type = \"plain\"\n",
+        path
+    );
+    let scene_string = format!("{}\n{}", SampleScene::OkBasic.to_string(), terrain);
+    let scene = Scene::from_str(&scene_string).unwrap();
+    let aabb = scene.objects.last().unwrap().shape.aabb().unwrap();
+    assert!((aabb.min.x + 5.0).abs() < 1e-9 && (aabb.max.z - 2.0).abs() < 1e-9);
+    assert!(aabb.min.y.abs() < 1e-9 && (aabb.max.y - 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn missing_font_is_replaced_by_placeholder() {
+    let text = "[[object]]\ntype = \"text\"\ntext = \"Title\"\nfont = \"missing.ttf\"\n\
+                depth = 0.2\ntexture.type = \"plain\"\n";
+    let scene_string = format!("{}\n{}", SampleScene::OkBasic.to_string(), text);
+    assert!(Scene::from_str(&scene_string).is_err());
+    let scene_string = format!(
+        "{}\n{}\n[config]\nasset_placeholders = true\n",
+        SampleScene::OkBasic.to_string(),
+        text
+    );
+    let scene = Scene::from_str(&scene_string).unwrap();
+    assert_eq!(scene.warnings.len(), 1);
+}
+
+#[test]
+fn texture_coordinates_are_transformed() {
+    let object = "[[object]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\n\
+                  texture = { type = \"checked\", count = 2, scale = [2, 1], offset = [0.5, 0] }\n";
+    let scene_string = format!("{}\n{}", SampleScene::OkBasic.to_string(), object);
+    let scene = Scene::from_str(&scene_string).unwrap();
+    let texture = &scene.objects.last().unwrap().texture;
+    // Four squares along u instead of two, shifted by half the texture
+    let (first, second) = (texture.color_at(0.26, 0.1), texture.color_at(0.01, 0.1));
+    assert!(first.red() > 0.5 && second.red() < 0.5);
+}
+
+#[test]
+fn camera_and_objects_follow_their_keyframes() {
+    let scene_string = format!(
+        "{}\n[[object]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\n\
+         texture.type = \"plain\"\n\
+         [[animation.camera]]\ntime = 0\n[[animation.camera]]\ntime = 2\nposition = [0, 0, 4]\n\
+         [[animation.object]]\nindex = 2\n\
+         keys = [{{ time = 0 }}, {{ time = 2, position = [10, 0, 0], scale = 2 }}]\n",
+        SampleScene::OkBasic.to_string()
+    );
+    let still = Scene::from_str(&scene_string).unwrap();
+    let moved = Scene::from_description(&scene_string, SceneFormat::Toml, 24).unwrap();
+    let (still_eye, moved_eye) = (
+        still.camera.generate_ray(0.5, 0.5).source,
+        moved.camera.generate_ray(0.5, 0.5).source,
+    );
+    assert!((moved_eye - still_eye - Vec3::new(0.0, 0.0, 2.0)).norm() < 1e-9);
+    let aabb = moved.objects.last().unwrap().aabb().unwrap();
+    assert!((aabb.center().x - 5.0).abs() < 1e-6);
+    assert!((aabb.max.y - 1.5).abs() < 1e-6);
+
+    // Twice as many frames per second reach the same time at twice the frame
+    let frame = AnimationFrame::from(24).with_frames_per_second(12.0);
+    let held = Scene::from_description(&scene_string, SceneFormat::Toml, frame).unwrap();
+    let aabb = held.objects.last().unwrap().aabb().unwrap();
+    assert!((aabb.center().x - 10.0).abs() < 1e-6);
+}
+
+#[test]
+fn shutter_blurs_the_keyframed_motion() {
+    let scene_string = |shutter: &str| {
+        format!(
+            "{}\n[[object]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\n\
+             texture.type = \"plain\"\n\
+             [[animation.camera]]\ntime = 0\n\
+             [[animation.camera]]\ntime = 1\nposition = [24, 0, 0]\n\
+             [[animation.object]]\nindex = 2\n\
+             keys = [{{ time = 0 }}, {{ time = 1, position = [0, 48, 0] }}]\n",
+            SampleScene::OkBasic
+                .to_string()
+                .replace("[camera]", &format!("[camera]\n{}", shutter))
+        )
+    };
+    let still = Scene::from_str(&scene_string("")).unwrap();
+    let aabb = still.objects.last().unwrap().aabb().unwrap();
+    assert!((aabb.max.y - 1.0).abs() < 1e-6);
+    let ray_at = |scene: &Scene, time| scene.camera.generate_ray_at(0.5, 0.5, time).source;
+    assert_eq!(ray_at(&still, 0.0), ray_at(&still, 0.5));
+
+    // Half a frame at 24 frames per second, the sphere going up by 1 meanwhile
+    let blurred = Scene::from_str(&scene_string("shutter = { open = 0, close = 0.5 }")).unwrap();
+    assert!(blurred.camera.shutter().is_open());
+    let aabb = blurred.objects.last().unwrap().aabb().unwrap();
+    assert!((aabb.min.y + 1.0).abs() < 1e-6 && (aabb.max.y - 2.0).abs() < 1e-6);
+    let moved = ray_at(&blurred, 1.0 / 48.0) - ray_at(&blurred, 0.0);
+    assert!((moved - Vec3::new(0.5, 0.0, 0.0)).norm() < 1e-9);
+}
+
+#[test]
+fn unknown_shutter_field_is_error() {
+    let scene_string = SampleScene::OkBasic
+        .to_string()
+        .replace("[camera]", "[camera]\nshutter = { open = 0, clsoe = 1 }");
+    assert!(Scene::from_str(&scene_string).is_err());
+    let scene_string = SampleScene::OkBasic
+        .to_string()
+        .replace("[camera]", "[camera]\nshuter = { open = 0, close = 1 }");
+    assert!(Scene::from_str(&scene_string).is_err());
+}
+
+#[test]
+fn animating_missing_object_is_error() {
+    let scene_string = format!(
+        "{}\n[[animation.object]]\nindex = 7\nkeys = [{{ time = 0 }}]\n",
+        SampleScene::OkBasic.to_string()
+    );
+    match Scene::from_str(&scene_string) {
+        Err(RaytracerError::InvalidScene(error)) => {
+            assert_eq!(error.key.as_deref(), Some("animation.object[0].index"))
+        }
+        _ => panic!("Animating a missing object must fail"),
+    }
+}
+
+#[test]
+fn keyframes_are_sorted_by_time() {
+    let scene_string = format!(
+        "{}\n[[object]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\n\
+         texture.type = \"plain\"\n\
+         [[animation.camera]]\ntime = 2\nposition = [0, 0, 4]\n[[animation.camera]]\ntime = 0\n\
+         [[animation.object]]\nindex = 2\n\
+         keys = [{{ time = 2, position = [10, 0, 0] }}, {{ time = 0 }}]\n",
+        SampleScene::OkBasic.to_string()
+    );
+    let still = Scene::from_str(&scene_string).unwrap();
+    let moved = Scene::from_description(&scene_string, SceneFormat::Toml, 24).unwrap();
+    let (still_eye, moved_eye) = (
+        still.camera.generate_ray(0.5, 0.5).source,
+        moved.camera.generate_ray(0.5, 0.5).source,
+    );
+    assert!((moved_eye - still_eye - Vec3::new(0.0, 0.0, 2.0)).norm() < 1e-9);
+    let aabb = moved.objects.last().unwrap().aabb().unwrap();
+    assert!((aabb.center().x - 5.0).abs() < 1e-6);
+
+    let scene_string = scene_string.replace("time = 0 }", "time = nan }");
+    match Scene::from_str(&scene_string) {
+        Err(RaytracerError::InvalidScene(error)) => {
+            assert_eq!(error.key.as_deref(), Some("animation.object[0].keys"))
+        }
+        _ => panic!("A key at no time must fail"),
+    }
+}
+
+#[test]
+fn frames_per_second_must_be_positive() {
+    for frames_per_second in &["0", "-24", "nan", "inf"] {
+        let scene_string = format!(
+            "{}\n[config]\nframes_per_second = {}\n",
+            SampleScene::OkBasic.to_string(),
+            frames_per_second
+        );
+        match Scene::from_str(&scene_string) {
+            Err(RaytracerError::InvalidScene(error)) => {
+                assert_eq!(error.key.as_deref(), Some("config.frames_per_second"))
+            }
+            _ => panic!("{} frames per second must fail", frames_per_second),
+        }
+    }
+    let frame = AnimationFrame::from(24).with_frames_per_second(0.0);
+    let scene_string = SampleScene::OkBasic.to_string();
+    assert!(Scene::from_description(&scene_string, SceneFormat::Toml, frame).is_err());
+}
+
+#[test]
+fn next_frame_refits_moved_objects() {
+    let sphere_at = |x: f64| {
+        format!(
+            "{}\n[[object]]\ntype = \"sphere\"\ncenter = [{}, 0, 0]\nradius = 1\n\
+             texture.type = \"plain\"\n",
+            SampleScene::OkBasic.to_string(),
+            x
+        )
+    };
+    let scene = Scene::from_str(&sphere_at(0.0)).unwrap();
+    let scene = scene
+        .next_frame(&sphere_at(50.0), SceneFormat::Toml, 2)
+        .unwrap();
+    assert_eq!(scene.config.frame, 2);
+    let ray = Ray::new(Vec3::new(50.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0));
+    let mut visited = Vec::new();
+    scene
+        .objects
+        .bvh()
+        .visit(&ray, f64::MAX, |index, distance| {
+            visited.push(index);
+            distance
+        });
+    assert!(visited.contains(&(scene.objects.len() - 1)));
+}
+
+#[test]
+fn every_error_is_located() {
+    let objects = "[[object]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\n\
+                   texture = { type = \"plain\", colour = \"red\" }\n\n\
+                   [[object]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\n\
+                   texture.type = \"plain\"\n";
+    let scene_string = SampleScene::OkBasic.to_string();
+    let full_string = format!("{}\n{}", scene_string, objects);
+    let lines = full_string.lines().collect::<Vec<_>>();
+    let line_of = |text: &str| lines.iter().rposition(|line| line.contains(text)).unwrap();
+    let errors = Scene::validate(&full_string, SceneFormat::Toml);
+    assert_eq!(errors.len(), 2);
+    let object_count = Scene::from_str(&scene_string).unwrap().objects.len();
+    let typo = &errors[0];
+    assert_eq!(typo.message, "unknown field `colour`");
+    assert_eq!(
+        typo.key.as_deref(),
+        Some(format!("object[{}].texture.colour", object_count).as_str())
+    );
+    assert_eq!(typo.position, Some((line_of("colour") + 1, 29)));
+    assert!(errors[1].message.contains("missing field `radius`"));
+    // Tables of an array of tables are located at their first key
+    assert_eq!(errors[1].position, Some((line_of("[[object]]") + 2, 1)));
+}
+
+#[test]
+fn unknown_color_name_is_located() {
+    let object = "[[object]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\n\
+                  texture.type = \"plain\"\ntexture.color = \"bleu\"\n";
+    let scene_string = format!("{}\n{}", SampleScene::OkBasic.to_string(), object);
+    match Scene::from_str(&scene_string) {
+        Err(RaytracerError::InvalidScene(error)) => {
+            assert!(error
+                .message
+                .contains("bleu is not a valid color reference"))
+        }
+        _ => panic!("Unknown color name accepted"),
+    }
+    let errors = Scene::validate(&scene_string, SceneFormat::Toml);
+    assert_eq!(errors.len(), 1);
+    let object_count = Scene::from_str(&SampleScene::OkBasic.to_string())
+        .unwrap()
+        .objects
+        .len();
+    assert_eq!(errors[0].message, "bleu is not a valid color reference");
+    assert_eq!(
+        errors[0].key.as_deref(),
+        Some(format!("object[{}].texture", object_count).as_str())
+    );
+    // At the first key of the texture
+    let lines = scene_string.lines().collect::<Vec<_>>();
+    let line = lines
+        .iter()
+        .rposition(|line| line.contains("texture.type"))
+        .unwrap();
+    assert_eq!(errors[0].position, Some((line + 1, 1)));
+}
+
+#[test]
+fn unknown_fields_are_warned_or_denied() {
+    let object = "[[object]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\n\
+                  texture.type = \"plain\"\nmaterail = \"glass\"\n";
+    let scene_string = format!("{}\n{}", SampleScene::OkBasic.to_string(), object);
+    let scene = Scene::from_str(&scene_string).unwrap();
+    assert_eq!(scene.warnings.len(), 1);
+    let with_mode = |mode: &str| {
+        format!(
+            "{}\n[config]\nunknown_fields = \"{}\"\n",
+            scene_string, mode
+        )
+    };
+    let scene = Scene::from_str(&with_mode("ignore")).unwrap();
+    assert!(scene.warnings.is_empty());
+    match Scene::from_str(&with_mode("deny")) {
+        Err(RaytracerError::InvalidScene(error)) => {
+            assert_eq!(error.message, "unknown field `materail`")
+        }
+        _ => panic!("unknown field not denied"),
+    }
+}
+
+#[test]
+fn syntax_error_is_located() {
+    match Scene::from_str("[camera]\ntype = \"perspective\"\nscreen_center = [0, 0,\n") {
+        Err(RaytracerError::InvalidScene(error)) => assert_eq!(error.position.unwrap().0, 4),
+        _ => panic!("invalid TOML accepted"),
+    }
+}
+
+#[test]
+fn load_cornell_box_sample() {
+    let scene_string = std::fs::read_to_string("../samples/cornell_box.toml").unwrap();
+    let scene = Scene::from_str(&scene_string).unwrap();
+    assert_eq!(scene.objects.len(), 7);
+}
+
+#[test]
+fn load_glass_presets_sample() {
+    let scene_string = std::fs::read_to_string("../samples/glass_presets.toml").unwrap();
+    let scene = Scene::from_str(&scene_string).unwrap();
+    assert_eq!(scene.objects.len(), 3);
+    let diamond = scene.objects[2].effects().transparency.as_ref().unwrap();
+    assert_eq!(diamond.refractive_index, 2.42);
+}
+
+#[test]
+fn load_asteroids_sample() {
+    let scene_string = std::fs::read_to_string("../samples/asteroids.toml").unwrap();
+    let scene = Scene::from_str(&scene_string).unwrap();
+    assert_eq!(scene.objects.len(), 3);
+}
+
+#[test]
+fn load_planet_sample() {
+    let scene_string = std::fs::read_to_string("../samples/planet.toml").unwrap();
+    let scene = Scene::from_str(&scene_string).unwrap();
+    assert_eq!(scene.objects.len(), 2);
+    assert!(scene.objects[1].effects().shadowless);
+}
+
+#[test]
+fn dumped_scene_fills_defaults_and_reloads() {
+    let scene_string = SampleScene::OkScatter.to_string();
+    let dump =
+        dump_scene_description(&scene_string, SceneFormat::Toml, SceneDumpFormat::Toml).unwrap();
+    assert!(dump.contains("area_light_samples = 16"));
+    let scene = Scene::from_str(&dump).unwrap();
+    assert_eq!(scene.objects.len(), 8 + 5);
+}
+
+#[test]
+fn written_scene_reads_back_the_same() {
+    for entry in std::fs::read_dir("../samples").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().unwrap() != "toml" {
+            continue;
+        }
+        let scene = Scene::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let written = scene.to_toml().unwrap();
+        let scene_again = Scene::from_str(&written).unwrap();
+        assert_eq!(
+            scene_again.objects.len(),
+            scene.objects.len(),
+            "{}",
+            path.display()
+        );
+        assert_eq!(
+            scene_again.to_toml().unwrap(),
+            written,
+            "{}",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn written_scene_has_its_current_configuration() {
+    let mut scene = Scene::from_str(&SampleScene::OkScatter.to_string()).unwrap();
+    scene.config.area_light_samples = 3;
+    let json: serde_json::Value = serde_json::from_str(&scene.to_json().unwrap()).unwrap();
+    assert_eq!(json["config"]["area_light_samples"], 3);
+    assert!(json["scatter"][0]["object"]["texture"].is_object());
+    assert!(samples::generate_test_scene().to_toml().is_err());
+}
+
+#[test]
+fn dumped_scene_as_json() {
+    let scene_string = SampleScene::OkBasic.to_string();
+    let dump =
+        dump_scene_description(&scene_string, SceneFormat::Toml, SceneDumpFormat::Json).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&dump).unwrap();
+    assert_eq!(json["config"]["tone_mapping"]["operator"], "clamp");
+}
+
+#[test]
+fn json_scene_is_the_toml_one() {
+    let scene_string = SampleScene::OkScatter.to_string();
+    let json =
+        dump_scene_description(&scene_string, SceneFormat::Toml, SceneDumpFormat::Json).unwrap();
+    assert!(Scene::validate(&json, SceneFormat::Json).is_empty());
+    let scene = Scene::from_json(&json).unwrap();
+    assert_eq!(scene.objects.len(), 8 + 5);
+    assert!(scene.warnings.is_empty());
+    match Scene::from_json("{\"camera\": [1,}") {
+        Err(RaytracerError::InvalidScene(error)) => assert_eq!(error.position, Some((1, 15))),
+        _ => panic!("invalid JSON accepted"),
+    }
+}
+
+#[test]
+fn load_yaml_sample() {
+    assert_eq!(
+        SceneFormat::from_path("scenes/basic_1.YML"),
+        Some(SceneFormat::Yaml)
+    );
+    let scene_string = std::fs::read_to_string("../samples/basic_1.yaml").unwrap();
+    let scene = Scene::from_yaml(&scene_string).unwrap();
+    let toml_scene = Scene::from_str(&std::fs::read_to_string("../samples/basic_1.toml").unwrap());
+    assert_eq!(scene.objects.len(), toml_scene.unwrap().objects.len());
+    assert!(scene.warnings.is_empty());
+    let typos = scene_string
+        .replace("look_at", "look_to")
+        .replace("color: white", "colour: white");
+    let errors = Scene::validate(&typos, SceneFormat::Yaml);
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].key.as_deref(), Some("camera"));
+    assert_eq!(errors[1].key.as_deref(), Some("object[1].texture.colour"));
+    assert_eq!(errors[1].position, None);
+}
+
+#[test]
+fn empty_descriptions_are_not_tables() {
+    let scenes = [
+        ("", SceneFormat::Yaml),
+        ("# Nothing yet\n", SceneFormat::Yaml),
+        ("null", SceneFormat::Json),
+    ];
+    for (scene_string, format) in &scenes {
+        match Scene::from_description(scene_string, *format, AnimationFrame::default()) {
+            Err(RaytracerError::InvalidScene(error)) => {
+                assert_eq!(error.message, "the description is not a table")
+            }
+            _ => panic!("empty description accepted: {:?}", scene_string),
+        }
+    }
+    assert!(!Scene::validate("", SceneFormat::Json).is_empty());
+}
+
+#[test]
+fn null_items_of_arrays_are_left_out() {
+    let scene_string = SampleScene::OkBasic.to_string();
+    let dump =
+        dump_scene_description(&scene_string, SceneFormat::Toml, SceneDumpFormat::Json).unwrap();
+    let mut json: serde_json::Value = serde_json::from_str(&dump).unwrap();
+    let object_count = json["object"].as_array().unwrap().len();
+    json["object"]
+        .as_array_mut()
+        .unwrap()
+        .push(serde_json::Value::Null);
+    let scene = Scene::from_json(&json.to_string()).unwrap();
+    assert_eq!(scene.objects.len(), object_count);
+}
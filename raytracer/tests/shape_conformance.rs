@@ -0,0 +1,313 @@
+/*
+MIT License
+
+Copyright (c) 2019, 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+//! Checks every shape must pass, whatever its geometry, so the ray algorithm can rely on them.
+//!
+//! A new primitive only needs a `ShapeFixture` and a `shape_conformance!` line.
+
+mod samples;
+
+use raytracer::colors::Color;
+use raytracer::primitives::{
+    AABox, DisplacedSphere, Heightmap, InfinitePlan, Ray, Shape, Sphere, SquarePlan, Torus,
+    TransformedShape,
+};
+use raytracer::textures::PlainColorTexture;
+use raytracer::vector::{Mat3, Vec3};
+use std::f64::consts::FRAC_PI_2;
+use std::sync::Arc;
+
+const TOLERANCE: f64 = 1e-9;
+
+struct ShapeFixture {
+    shape: Box<dyn Shape>,
+    /// Point outside of the shape, on the side its normals point to
+    outside: Vec3,
+    /// Point of the shape surface visible from `outside`
+    target: Vec3,
+    /// Point inside the shape, only for closed shapes
+    inside: Option<Vec3>,
+    /// Ray touching the shape only at its border, or parallel to it
+    grazing: Ray,
+}
+
+fn sphere_fixture() -> ShapeFixture {
+    ShapeFixture {
+        shape: Box::new(Sphere {
+            center: Vec3::new(1.0, 2.0, 3.0),
+            radius: 2.0,
+        }),
+        outside: Vec3::new(1.0, 2.0, -10.0),
+        target: Vec3::new(1.0, 2.0, 1.0),
+        inside: Some(Vec3::new(1.5, 2.5, 3.5)),
+        grazing: Ray::new(Vec3::new(3.0, 2.0, -10.0), Vec3::new(0.0, 0.0, 1.0)),
+    }
+}
+
+fn infinite_plan_fixture() -> ShapeFixture {
+    ShapeFixture {
+        shape: Box::new(InfinitePlan::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        )),
+        outside: Vec3::new(3.0, 5.0, -2.0),
+        target: Vec3::new(-2.0, -1.0, 7.0),
+        inside: None,
+        grazing: Ray::new(Vec3::new(0.0, -1.0, -5.0), Vec3::new(0.0, 0.0, 1.0)),
+    }
+}
+
+fn square_plan_fixture() -> ShapeFixture {
+    ShapeFixture {
+        shape: Box::new(SquarePlan::new(
+            Vec3::new(0.0, 0.0, 5.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            4.0,
+        )),
+        outside: Vec3::new(0.0, 0.0, -5.0),
+        target: Vec3::new(1.0, -1.0, 5.0),
+        inside: None,
+        grazing: Ray::new(Vec3::new(-10.0, 0.0, 5.0), Vec3::new(1.0, 0.0, 0.0)),
+    }
+}
+
+fn aa_box_fixture() -> ShapeFixture {
+    ShapeFixture {
+        shape: Box::new(AABox::new(
+            Vec3::new(2.0, 1.0, 3.0),
+            Vec3::new(-2.0, -1.0, -3.0),
+        )),
+        outside: Vec3::new(0.5, 10.0, 1.0),
+        target: Vec3::new(0.5, 1.0, 1.0),
+        inside: Some(Vec3::new(0.1, 0.2, 0.3)),
+        grazing: Ray::new(Vec3::new(2.0, 1.0, -10.0), Vec3::new(0.0, 0.0, 1.0)),
+    }
+}
+
+fn torus_fixture() -> ShapeFixture {
+    ShapeFixture {
+        shape: Box::new(Torus::new(
+            Vec3::new(0.0, 0.0, 5.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+            0.5,
+        )),
+        outside: Vec3::new(2.0, 0.0, -10.0),
+        target: Vec3::new(2.0, 0.0, 3.5),
+        inside: Some(Vec3::new(2.0, 0.1, 5.0)),
+        grazing: Ray::new(Vec3::new(2.0, 0.5, -10.0), Vec3::new(0.0, 0.0, 1.0)),
+    }
+}
+
+/// Evenly raised by a plain heightmap, so the hits are known
+fn displaced_sphere_fixture() -> ShapeFixture {
+    let heightmap = Heightmap::Texture(Box::new(PlainColorTexture {
+        color: Color::WHITE,
+    }));
+    let sphere = Sphere {
+        center: Vec3::new(0.0, 0.0, 5.0),
+        radius: 1.5,
+    };
+    ShapeFixture {
+        shape: Box::new(DisplacedSphere::new(sphere, 0.5, heightmap)),
+        outside: Vec3::new(0.0, 0.0, -10.0),
+        target: Vec3::new(0.0, 0.0, 3.0),
+        inside: Some(Vec3::new(0.5, 0.5, 5.5)),
+        grazing: Ray::new(Vec3::new(2.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0)),
+    }
+}
+
+fn transformed_box_fixture() -> ShapeFixture {
+    let unit_box = AABox::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+    ShapeFixture {
+        shape: Box::new(TransformedShape::new(
+            Arc::new(unit_box),
+            Vec3::new(0.0, 0.0, 10.0),
+            Mat3::rotation(Vec3::new(0.0, FRAC_PI_2, 0.0)),
+            2.0,
+        )),
+        outside: Vec3::new(0.5, 0.3, 0.0),
+        target: Vec3::new(0.5, 0.3, 8.0),
+        inside: Some(Vec3::new(0.1, 0.2, 10.3)),
+        grazing: Ray::new(Vec3::new(2.0, 2.0, 0.0), Vec3::new(0.0, 0.0, 1.0)),
+    }
+}
+
+fn cube_mesh_fixture() -> ShapeFixture {
+    ShapeFixture {
+        shape: Box::new(samples::cube_mesh()),
+        outside: Vec3::new(0.3, 0.2, -10.0),
+        target: Vec3::new(0.3, 0.2, -1.0),
+        inside: Some(Vec3::new(0.1, 0.2, 0.3)),
+        grazing: Ray::new(Vec3::new(1.0, 1.0, -10.0), Vec3::new(0.0, 0.0, 1.0)),
+    }
+}
+
+fn assert_valid_surface_point(shape: &dyn Shape, point: Vec3) {
+    assert!(
+        point.x.is_finite() && point.y.is_finite() && point.z.is_finite(),
+        "Hit point is not finite: {:?}",
+        point
+    );
+    let normal = shape.normal_at(point).expect("No normal at hit point");
+    assert!(
+        (normal.norm() - 1.0).abs() < TOLERANCE,
+        "Normal is not normalized: {:?}",
+        normal
+    );
+    let (u, v) = shape
+        .surface_mapping_at(point)
+        .expect("No surface mapping at hit point");
+    assert!((0.0..=1.0).contains(&u), "u out of range: {}", u);
+    assert!((0.0..=1.0).contains(&v), "v out of range: {}", v);
+    if let Some(aabb) = shape.aabb() {
+        let inside =
+            |value: f64, min: f64, max: f64| value >= min - TOLERANCE && value <= max + TOLERANCE;
+        assert!(
+            inside(point.x, aabb.min.x, aabb.max.x)
+                && inside(point.y, aabb.min.y, aabb.max.y)
+                && inside(point.z, aabb.min.z, aabb.max.z),
+            "Point {:?} is out of the bounding box {:?}",
+            point,
+            aabb
+        );
+    }
+}
+
+fn assert_in_front_of(ray: &Ray, point: Vec3) {
+    let distance = Vec3::between_points(ray.source, point).dot_product(ray.direction);
+    assert!(distance > 0.0, "Hit point {:?} is behind the ray", point);
+}
+
+fn check_hit_from_outside(fixture: &ShapeFixture) {
+    let ray = Ray::ray_from_to(fixture.outside, fixture.target);
+    let hit = fixture
+        .shape
+        .check_collision(&ray)
+        .expect("No hit from outside");
+    assert_in_front_of(&ray, hit);
+    assert!(
+        hit.distance(fixture.target) < 1e-6,
+        "Hit at {:?} instead of {:?}",
+        hit,
+        fixture.target
+    );
+    assert_valid_surface_point(fixture.shape.as_ref(), hit);
+}
+
+fn check_hit_from_inside(fixture: &ShapeFixture) {
+    let inside = match fixture.inside {
+        Some(inside) => inside,
+        None => return,
+    };
+    let directions = [
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec3::new(-1.0, 1.0, -1.0),
+    ];
+    for &direction in directions.iter() {
+        let ray = Ray::new(inside, direction);
+        let hit = fixture
+            .shape
+            .check_collision(&ray)
+            .expect("No hit from inside");
+        assert_in_front_of(&ray, hit);
+        assert_valid_surface_point(fixture.shape.as_ref(), hit);
+    }
+}
+
+fn check_no_hit_behind_origin(fixture: &ShapeFixture) {
+    let toward_target = Ray::ray_from_to(fixture.outside, fixture.target);
+    let away = Ray::new(fixture.outside, -toward_target.direction);
+    assert!(fixture.shape.check_collision(&away).is_none());
+}
+
+fn check_grazing_ray(fixture: &ShapeFixture) {
+    if let Some(hit) = fixture.shape.check_collision(&fixture.grazing) {
+        assert_in_front_of(&fixture.grazing, hit);
+        assert_valid_surface_point(fixture.shape.as_ref(), hit);
+    }
+}
+
+fn check_normal_orientation(fixture: &ShapeFixture) {
+    let ray = Ray::ray_from_to(fixture.outside, fixture.target);
+    let hit = fixture.shape.check_collision(&ray).unwrap();
+    let normal = fixture.shape.normal_at(hit).unwrap();
+    assert!(
+        normal.dot_product(ray.direction) < 0.0,
+        "Normal must face rays coming from outside"
+    );
+    if let Some(inside) = fixture.inside {
+        let ray = Ray::ray_from_to(inside, fixture.target);
+        let hit = fixture.shape.check_collision(&ray).unwrap();
+        let normal = fixture.shape.normal_at(hit).unwrap();
+        assert!(
+            normal.dot_product(ray.direction) > 0.0,
+            "Normal must point outside of closed shapes"
+        );
+    }
+}
+
+macro_rules! shape_conformance {
+    ($name:ident, $fixture:expr) => {
+        mod $name {
+            use super::*;
+
+            #[test]
+            fn hit_from_outside() {
+                check_hit_from_outside(&$fixture);
+            }
+
+            #[test]
+            fn hit_from_inside() {
+                check_hit_from_inside(&$fixture);
+            }
+
+            #[test]
+            fn no_hit_behind_origin() {
+                check_no_hit_behind_origin(&$fixture);
+            }
+
+            #[test]
+            fn grazing_ray() {
+                check_grazing_ray(&$fixture);
+            }
+
+            #[test]
+            fn normal_orientation() {
+                check_normal_orientation(&$fixture);
+            }
+        }
+    };
+}
+
+shape_conformance!(sphere, sphere_fixture());
+shape_conformance!(infinite_plan, infinite_plan_fixture());
+shape_conformance!(square_plan, square_plan_fixture());
+shape_conformance!(aa_box, aa_box_fixture());
+shape_conformance!(torus, torus_fixture());
+shape_conformance!(displaced_sphere, displaced_sphere_fixture());
+shape_conformance!(transformed_box, transformed_box_fixture());
+shape_conformance!(cube_mesh, cube_mesh_fixture());
@@ -24,8 +24,33 @@ SOFTWARE.
 
 mod samples;
 
-use raytracer::renderer::{render_scene, RenderConfiguration};
-use raytracer::scene::Scene;
+use raytracer::animation::render_animation;
+use raytracer::cameras::{OrthogonalCamera, PerspectiveCamera};
+use raytracer::checkpoint::Checkpoint;
+use raytracer::colors::Color;
+use raytracer::interpolation::Frame;
+use raytracer::io::hdr::parse_hdr;
+use raytracer::lights::{LightDirectional, LightRectangle};
+use raytracer::panorama::PanoramaGrid;
+use raytracer::progress::RenderProgress;
+use raytracer::ray_algorithm::strategy::RandomAntiAliasingRenderStrategy;
+use raytracer::ray_algorithm::RayKind;
+use raytracer::renderer::{
+    render_environment, render_motion_vectors, render_scene, render_scene_with, render_tile,
+    resume_scene_with_finally, CancellationToken, Pixel, ProgressiveRenderer, RenderConfiguration,
+    Renderer, RendererKind, SequentialRenderer, PROGRESSIVE_COARSE_STEP,
+};
+use raytracer::result::RaytracerError;
+use raytracer::scene::{BackgroundVisibility, Scene, SceneConfiguration, SceneFormat, SceneObject};
+use raytracer::service::{JobStatus, RenderJob, RenderService};
+use raytracer::textures::{Mirror, PlainColorTexture, Sheen, TextureEffects, Transparency};
+use raytracer::thumbnail::render_thumbnail;
+use raytracer::tiles::TileOrder;
+use raytracer::vector::Vec3;
+use std::collections::HashSet;
+use std::f64::consts::PI;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
 
 
@@ -37,15 +62,18 @@ fn scene_without_lights_is_error() {
         ..samples::generate_test_scene()
     };
     let config = <RenderConfiguration as Default>::default();
-    render_scene(scene, config, false).unwrap().count();
+    render_scene(scene, config).unwrap().count();
 }
 
 #[test]
 fn smoke_sequential_rendering() {
     let scene = samples::generate_test_scene();
-    let config = <RenderConfiguration as Default>::default();
+    let config = RenderConfiguration {
+        renderer: RendererKind::Sequential,
+        ..Default::default()
+    };
     let expected_count = (config.canvas_height * config.canvas_width) as usize;
-    let render_iter = render_scene(scene, config, false).unwrap();
+    let render_iter = render_scene(scene, config).unwrap();
     let count = render_iter.count();
     assert_eq!(count, expected_count);
 }
@@ -53,9 +81,1295 @@ fn smoke_sequential_rendering() {
 #[test]
 fn smoke_parallel_rendering() {
     let scene = samples::generate_test_scene();
-    let config = <RenderConfiguration as Default>::default();
+    let config = RenderConfiguration {
+        renderer: RendererKind::Tiled,
+        ..Default::default()
+    };
     let expected_count = (config.canvas_height * config.canvas_width) as usize;
-    let render_iter = render_scene(scene, config, true).unwrap();
+    let render_iter = render_scene(scene, config).unwrap();
     let count = render_iter.count();
     assert_eq!(count, expected_count);
 }
+
+#[test]
+fn renderers_give_the_same_pixels() {
+    let render = |renderer| {
+        let config = RenderConfiguration {
+            canvas_width: 40,
+            canvas_height: 30,
+            renderer,
+            ..Default::default()
+        };
+        let mut pixels: Vec<_> = render_scene(samples::generate_test_scene(), config)
+            .unwrap()
+            .map(|pixel| {
+                let pixel = pixel.unwrap();
+                (pixel.y, pixel.x, pixel.color.to_linear8())
+            })
+            .collect();
+        pixels.sort_unstable();
+        pixels
+    };
+    let sequential = render(RendererKind::Sequential);
+    assert_eq!(sequential.len(), 40 * 30);
+    assert_eq!(render(RendererKind::Threaded), sequential);
+    assert_eq!(render(RendererKind::Tiled), sequential);
+}
+
+#[test]
+fn random_samples_only_depend_on_the_seed() {
+    let render = |renderer, seed| {
+        let config = RenderConfiguration {
+            canvas_width: 40,
+            canvas_height: 30,
+            render_strategy: Box::new(RandomAntiAliasingRenderStrategy { rays_per_pixel: 4 }),
+            renderer,
+            ..Default::default()
+        };
+        let mut scene = samples::generate_test_scene();
+        scene.config.seed = seed;
+        let mut pixels: Vec<_> = render_scene(scene, config)
+            .unwrap()
+            .map(|pixel| {
+                let pixel = pixel.unwrap();
+                (pixel.y, pixel.x, pixel.color.to_linear8())
+            })
+            .collect();
+        pixels.sort_unstable();
+        pixels
+    };
+    let sequential = render(RendererKind::Sequential, 7);
+    assert_eq!(render(RendererKind::Sequential, 7), sequential);
+    assert_eq!(render(RendererKind::Threaded, 7), sequential);
+    assert_eq!(render(RendererKind::Tiled, 7), sequential);
+    assert_ne!(render(RendererKind::Sequential, 8), sequential);
+}
+
+#[test]
+fn tiles_of_any_size_and_order_give_the_same_pixels() {
+    let render = |renderer, tile_size, tile_order| {
+        let config = RenderConfiguration {
+            canvas_width: 40,
+            canvas_height: 30,
+            renderer,
+            tile_size,
+            tile_order,
+            ..Default::default()
+        };
+        render_scene(samples::generate_test_scene(), config)
+            .unwrap()
+            .map(|pixel| {
+                let pixel = pixel.unwrap();
+                (pixel.x, pixel.y, pixel.color.to_linear8())
+            })
+            .collect::<Vec<_>>()
+    };
+    let sorted = |mut pixels: Vec<_>| {
+        pixels.sort_unstable();
+        pixels
+    };
+    let scanline = render(RendererKind::Sequential, 40, TileOrder::Scanline);
+    assert_eq!((scanline[0].0, scanline[0].1, scanline[1].0), (0, 0, 1));
+    let center_out = render(RendererKind::Sequential, 8, TileOrder::CenterOut);
+    assert_eq!((center_out[0].0, center_out[0].1), (16, 8));
+    let expected = sorted(scanline);
+    assert_eq!(sorted(center_out), expected);
+    for &order in &[TileOrder::Spiral, TileOrder::Hilbert] {
+        assert_eq!(sorted(render(RendererKind::Sequential, 7, order)), expected);
+        assert_eq!(sorted(render(RendererKind::Tiled, 7, order)), expected);
+    }
+}
+
+#[test]
+fn resumed_rendering_gives_the_same_pixels() {
+    let config = |renderer| RenderConfiguration {
+        canvas_width: 40,
+        canvas_height: 30,
+        renderer,
+        tile_size: 8,
+        ..Default::default()
+    };
+    let sorted = |render_iter: &mut dyn Iterator<Item = raytracer::result::Result<Pixel>>| {
+        let mut pixels: Vec<_> = render_iter
+            .map(|pixel| {
+                let pixel = pixel.unwrap();
+                (pixel.x, pixel.y, pixel.color.to_linear8())
+            })
+            .collect();
+        pixels.sort_unstable();
+        pixels
+    };
+    let sequential = config(RendererKind::Sequential);
+    let mut checkpoint = Checkpoint::new(&sequential, "test scene");
+    let interrupted = render_scene(samples::generate_test_scene(), sequential).unwrap();
+    for pixel in interrupted.take(500) {
+        checkpoint.record(&pixel.unwrap());
+    }
+    let sequential = config(RendererKind::Sequential);
+    let expected = sorted(&mut render_scene(samples::generate_test_scene(), sequential).unwrap());
+    let checkpoint = Checkpoint::decode(&checkpoint.encode()).unwrap();
+    assert_eq!(checkpoint.completed_tiles().len(), 8);
+    for &renderer in &[RendererKind::Sequential, RendererKind::Tiled] {
+        let scene = samples::generate_test_scene();
+        let resumed = resume_scene_with_finally(scene, config(renderer), &checkpoint, || {});
+        assert_eq!(sorted(&mut resumed.unwrap()), expected);
+    }
+}
+
+#[test]
+fn panorama_parts_give_the_pixels_of_the_whole_image() {
+    let render = |scene, canvas_width, canvas_height| {
+        let config = RenderConfiguration {
+            canvas_width,
+            canvas_height,
+            ..Default::default()
+        };
+        render_scene(scene, config)
+            .unwrap()
+            .map(|pixel| pixel.unwrap())
+            .collect::<Vec<_>>()
+    };
+    let mut whole = vec![None; 40 * 30];
+    for pixel in render(samples::generate_test_scene(), 40, 30) {
+        whole[(pixel.y * 40 + pixel.x) as usize] = Some(pixel.color.to_linear8());
+    }
+    let grid = PanoramaGrid::new(40, 30, 3, 2).unwrap();
+    let mut stitched = vec![None; 40 * 30];
+    for row in 0..2 {
+        for column in 0..3 {
+            let part = grid.part(column, row).unwrap();
+            let scene = grid
+                .part_scene(samples::generate_test_scene(), column, row)
+                .unwrap();
+            for pixel in render(scene, part.width, part.height) {
+                let (x, y) = (part.x + pixel.x, part.y + pixel.y);
+                stitched[(y * 40 + x) as usize] = Some(pixel.color.to_linear8());
+            }
+        }
+    }
+    assert!(whole.iter().all(Option::is_some));
+    // Rays only go through the same canvas points up to rounding errors
+    let close = |a: [u8; 3], b: [u8; 3]| a.iter().zip(&b).all(|(a, b)| a.max(b) - a.min(b) <= 8);
+    assert!(stitched
+        .iter()
+        .zip(&whole)
+        .all(|(part, whole)| close(part.unwrap(), whole.unwrap())));
+}
+
+#[test]
+fn progressive_rendering_ends_on_the_sequential_pixels() {
+    let config = || RenderConfiguration {
+        canvas_width: 37,
+        canvas_height: 23,
+        ..Default::default()
+    };
+    let final_pixels = |renderer: &dyn Renderer| {
+        let mut count = 0;
+        let mut pixels = vec![None; 37 * 23];
+        for pixel in
+            render_scene_with(renderer, samples::generate_test_scene(), config(), || {}).unwrap()
+        {
+            let pixel = pixel.unwrap();
+            pixels[(pixel.x + pixel.y * 37) as usize] = Some(pixel.color.to_linear8());
+            count += 1;
+        }
+        assert_eq!(count, renderer.pixel_count(37, 23));
+        pixels
+    };
+    let sequential = final_pixels(&SequentialRenderer);
+    assert!(sequential.iter().all(Option::is_some));
+    for &threaded in &[false, true] {
+        let renderer = ProgressiveRenderer {
+            coarse_step: PROGRESSIVE_COARSE_STEP,
+            threaded,
+        };
+        assert_eq!(final_pixels(&renderer), sequential);
+    }
+}
+
+#[test]
+fn smoke_animation_rendering() {
+    let scene_string = format!(
+        "{}\n[[animation.camera]]\ntime = 0\n\
+         [[animation.camera]]\ntime = 1\nposition = [1, 0, 0]\n",
+        samples::SampleScene::OkBasic.to_string()
+    );
+    let config = RenderConfiguration {
+        canvas_width: 8,
+        canvas_height: 6,
+        ..Default::default()
+    };
+    let mut rendered = Vec::new();
+    let frame_done = |frame, image: Frame| {
+        assert_eq!((image.width(), image.height()), (8, 6));
+        rendered.push(frame);
+        Ok(())
+    };
+    render_animation(
+        &scene_string,
+        SceneFormat::Toml,
+        3..6,
+        Some(4.0),
+        &config,
+        frame_done,
+    )
+    .unwrap();
+    assert_eq!(rendered, vec![3, 4, 5]);
+}
+
+#[test]
+fn smoke_pixel_ray_tracing() {
+    let scene = samples::generate_test_scene();
+    let ray_tree = raytracer::debug::trace_pixel(&scene, 0.5, 0.7).unwrap();
+    let camera_ray = &ray_tree.rays[0];
+    assert_eq!(camera_ray.kind, RayKind::Camera);
+    assert!(camera_ray.end.is_some());
+    assert!(ray_tree.rays.iter().any(|ray| ray.kind == RayKind::Shadow));
+}
+
+#[test]
+fn parallel_rendering_covers_partial_tiles() {
+    let scene = samples::generate_test_scene();
+    let config = RenderConfiguration {
+        canvas_width: 37,
+        canvas_height: 21,
+        ..Default::default()
+    };
+    let pixels: HashSet<(u32, u32)> = render_scene(scene, config)
+        .unwrap()
+        .map(|pixel| {
+            let pixel = pixel.unwrap();
+            (pixel.x, pixel.y)
+        })
+        .collect();
+    assert_eq!(pixels.len(), 37 * 21);
+    assert!(pixels.iter().all(|&(x, y)| x < 37 && y < 21));
+}
+
+#[test]
+fn far_clip_hides_distant_objects() {
+    let camera = PerspectiveCamera::new(
+        Vec3::new(0.0, 10.0, -10.0),
+        Vec3::new(0.0, 0.0, 30.0),
+        16.0 * 2.0,
+        9.0 * 2.0,
+        PI / 8.0,
+    )
+    .with_far_clip(1.0);
+    let scene = Scene {
+        camera: Box::new(camera),
+        ..samples::generate_test_scene()
+    };
+    let config = samples::small_config();
+    let all_background = render_scene(scene, config)
+        .unwrap()
+        .map(Result::unwrap)
+        .all(|pixel| pixel.color.red() + pixel.color.green() + pixel.color.blue() == 0.0);
+    assert!(all_background);
+}
+
+#[test]
+fn near_clip_hides_objects_around_the_eye() {
+    let camera = || {
+        PerspectiveCamera::new(
+            Vec3::new(0.0, 10.0, -10.0),
+            Vec3::new(0.0, 0.0, 30.0),
+            16.0 * 2.0,
+            9.0 * 2.0,
+            PI / 8.0,
+        )
+        .with_near_clip(25.0)
+    };
+    let occluder = samples::sphere_object(Vec3::new(0.0, 10.0, -10.0), 2.0, Default::default());
+    let reference_scene = Scene {
+        camera: Box::new(camera()),
+        ..samples::generate_test_scene()
+    };
+    let mut occluded_scene = Scene {
+        camera: Box::new(camera()),
+        ..samples::generate_test_scene()
+    };
+    occluded_scene.objects.push(occluder);
+    let render = |scene| {
+        let mut pixels: Vec<_> = render_scene(scene, samples::small_config())
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|pixel| {
+                let color = (pixel.color.red(), pixel.color.green(), pixel.color.blue());
+                (pixel.x, pixel.y, color)
+            })
+            .collect();
+        pixels.sort_by_key(|&(x, y, _)| (y, x));
+        pixels
+    };
+    assert_eq!(render(reference_scene), render(occluded_scene));
+}
+
+#[test]
+fn refraction_uses_the_surrounding_medium() {
+    let glass_sphere = |radius| {
+        let effects = TextureEffects {
+            transparency: Some(Transparency {
+                refractive_index: 1.3,
+                alpha: 0.5,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        samples::sphere_object(Vec3::new(0.0, 0.0, 0.0), radius, effects)
+    };
+    let scene = samples::orthogonal_scene(vec![glass_sphere(4.0), glass_sphere(2.0)]);
+    // Ray going through both spheres, one unit away from their center
+    let ray_tree = raytracer::debug::trace_pixel(&scene, 0.5 + 1.0 / 16.0, 0.5).unwrap();
+    let camera_direction = ray_tree.rays[0].direction;
+    let refractions: Vec<_> = ray_tree
+        .rays
+        .iter()
+        .filter(|ray| ray.kind == RayKind::Refraction)
+        .collect();
+    assert!(refractions.len() >= 3);
+    // Bent when entering the outer sphere, but not between two media of same index
+    assert!(refractions[0].direction.distance(camera_direction) > 1e-3);
+    assert!(refractions[1].direction.distance(refractions[0].direction) < 1e-9);
+    assert!(refractions[2].direction.distance(refractions[1].direction) < 1e-9);
+}
+
+#[test]
+fn fresnel_glass_also_reflects_the_light() {
+    let reflections = |fresnel| {
+        let effects = TextureEffects {
+            transparency: Some(Transparency {
+                refractive_index: 1.5,
+                fresnel,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let glass_sphere = samples::sphere_object(Vec3::new(0.0, 0.0, 0.0), 4.0, effects);
+        let scene = samples::orthogonal_scene(vec![glass_sphere]);
+        let ray_tree = raytracer::debug::trace_pixel(&scene, 0.5 + 1.0 / 16.0, 0.5).unwrap();
+        ray_tree
+            .rays
+            .iter()
+            .filter(|ray| ray.kind == RayKind::Reflection)
+            .count()
+    };
+    assert_eq!(reflections(false), 0);
+    assert!(reflections(true) >= 2);
+}
+
+#[test]
+fn total_internal_reflection_in_glass_cube() {
+    let glass_cube = SceneObject {
+        shape: Box::new(samples::cube_mesh()),
+        texture: Box::new(PlainColorTexture::default()),
+        effects: TextureEffects {
+            transparency: Some(Transparency {
+                refractive_index: 1.5,
+                alpha: 0.5,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    };
+    // Ray entering the front face at 45 degrees, then reaching the side face
+    // with an angle beyond the critical one
+    let camera = OrthogonalCamera::new(
+        Vec3::new(-10.0, 0.0, -11.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        1.0,
+        1.0,
+    );
+    let scene = Scene {
+        camera: Box::new(camera),
+        objects: vec![glass_cube].into(),
+        ..samples::generate_test_scene()
+    };
+    let ray_tree = raytracer::debug::trace_pixel(&scene, 0.5, 0.5).unwrap();
+    let internal_reflection = ray_tree
+        .rays
+        .iter()
+        .find(|ray| ray.kind == RayKind::Reflection)
+        .expect("No internal reflection");
+    assert!((internal_reflection.source.x - 1.0).abs() < 1e-6);
+    assert!(internal_reflection.direction.x < 0.0);
+}
+
+#[test]
+fn perfect_mirror_only_shows_reflections() {
+    let mut scene = samples::generate_test_scene();
+    let effects = TextureEffects {
+        mirror: Some(Mirror { coeff: 1.0 }),
+        ..Default::default()
+    };
+    let mirror_sphere = samples::sphere_object(Vec3::new(0.0, 0.0, 0.0), 5.0, effects);
+    scene.objects = vec![mirror_sphere].into();
+    let config = samples::small_config();
+    // Nothing to reflect but the black world, so the white sphere is not seen
+    let all_black = render_scene(scene, config)
+        .unwrap()
+        .map(Result::unwrap)
+        .all(|pixel| pixel.color.red() + pixel.color.green() + pixel.color.blue() == 0.0);
+    assert!(all_black);
+}
+
+#[test]
+fn holdout_object_cuts_a_black_matte() {
+    let effects = TextureEffects {
+        holdout: true,
+        ..Default::default()
+    };
+    let holdout_sphere = samples::sphere_object(Vec3::new(0.0, 0.0, 0.0), 2.0, effects);
+    let scene = samples::orthogonal_scene(vec![holdout_sphere]);
+    let config = samples::small_config();
+    let pixels: Vec<_> = render_scene(scene, config)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let center = pixels.iter().find(|p| p.x == 16 && p.y == 9).unwrap();
+    let corner = pixels.iter().find(|p| p.x == 0 && p.y == 0).unwrap();
+    assert_eq!(center.alpha, 0.0);
+    assert_eq!(
+        center.color.red() + center.color.green() + center.color.blue(),
+        0.0
+    );
+    assert_eq!(corner.alpha, 1.0);
+}
+
+#[test]
+fn background_seen_by_camera_and_reflections_is_configurable() {
+    let render_center_and_corner = |config: SceneConfiguration| {
+        let effects = TextureEffects {
+            mirror: Some(Mirror { coeff: 1.0 }),
+            ..Default::default()
+        };
+        let mirror_sphere = samples::sphere_object(Vec3::new(0.0, 0.0, 0.0), 2.0, effects);
+        let scene = Scene {
+            config,
+            ..samples::orthogonal_scene(vec![mirror_sphere])
+        };
+        let config = samples::small_config();
+        let pixels: Vec<_> = render_scene(scene, config)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let center = pixels
+            .iter()
+            .find(|p| p.x == 16 && p.y == 9)
+            .unwrap()
+            .clone();
+        let corner = pixels
+            .iter()
+            .find(|p| p.x == 0 && p.y == 0)
+            .unwrap()
+            .clone();
+        (center, corner)
+    };
+
+    // The red world is only seen in the mirror, around it the pixels are transparent
+    let (center, corner) = render_center_and_corner(SceneConfiguration {
+        world_color: Color::RED,
+        camera_background: BackgroundVisibility::Transparent,
+        ..Default::default()
+    });
+    assert!(center.color.red() > 0.0);
+    assert_eq!(center.alpha, 1.0);
+    assert_eq!(corner.alpha, 0.0);
+    assert_eq!(corner.color.red(), 0.0);
+
+    // And the other way around
+    let (center, corner) = render_center_and_corner(SceneConfiguration {
+        world_color: Color::RED,
+        reflection_background: BackgroundVisibility::Transparent,
+        ..Default::default()
+    });
+    assert_eq!(center.color.red(), 0.0);
+    assert_eq!(corner.alpha, 1.0);
+    assert!(corner.color.red() > 0.0);
+}
+
+#[test]
+fn transparent_object_casts_a_colored_shadow() {
+    let scene_toml = |colored_shadow: bool| {
+        format!(
+            r##"
+            [config]
+            ambient_light = [0, 0, 0]
+
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 9
+
+            [[light]]
+            type = "directional"
+            direction = [1, 0, 1]
+            color = "white"
+
+            [[object]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            texture.type = "plain"
+            texture.color = "red"
+            effect.transparency = {{ alpha = 0.5, colored_shadow = {} }}
+
+            [[object]]
+            type = "infinite_plan"
+            center = [0, 0, 5]
+            normal = [0, 0, -1]
+            texture.type = "plain"
+            "##,
+            colored_shadow
+        )
+    };
+    let shadow_color = |colored_shadow: bool| {
+        let scene = Scene::from_str(&scene_toml(colored_shadow)).unwrap();
+        let config = samples::small_config();
+        render_scene(scene, config)
+            .unwrap()
+            .map(Result::unwrap)
+            .find(|p| p.x == 26 && p.y == 9)
+            .unwrap()
+            .color
+    };
+    let opaque = shadow_color(false);
+    assert_eq!(opaque.red() + opaque.green() + opaque.blue(), 0.0);
+    let colored = shadow_color(true);
+    assert!(colored.red() > 0.0);
+    assert_eq!(colored.green() + colored.blue(), 0.0);
+}
+
+#[test]
+fn moving_object_is_blurred_while_the_shutter_is_open() {
+    let scene_toml = |shutter: &str| {
+        format!(
+            r##"
+            [config]
+            ambient_light = [0, 0, 0]
+            frames_per_second = 24
+
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 9
+            {}
+
+            [[light]]
+            type = "directional"
+            direction = [0, 0, 1]
+            color = "white"
+
+            [[object]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            velocity = [48, 0, 0]
+            texture.type = "plain"
+            "##,
+            shutter
+        )
+    };
+    let red_at = |shutter: &str, x: u32| {
+        let scene = Scene::from_str(&scene_toml(shutter)).unwrap();
+        let config = RenderConfiguration {
+            render_strategy: Box::new(RandomAntiAliasingRenderStrategy { rays_per_pixel: 64 }),
+            ..samples::small_config()
+        };
+        render_scene(scene, config)
+            .unwrap()
+            .map(Result::unwrap)
+            .find(|p| p.x == x && p.y == 9)
+            .unwrap()
+            .color
+            .red()
+    };
+    // The sphere goes 2 units to the right during the frame, seen by part of the rays
+    // of a pixel after its right edge
+    assert_eq!(red_at("", 20), 0.0);
+    let blurred = red_at("shutter = { open = 0, close = 1 }", 20);
+    assert!(blurred > 0.0 && blurred < red_at("", 16));
+}
+
+#[test]
+fn sheen_adds_its_color_to_the_surface() {
+    let red_of_sphere = |sheen: Option<Sheen>| -> f64 {
+        let effects = TextureEffects {
+            sheen,
+            ..Default::default()
+        };
+        let blue_sphere = SceneObject {
+            texture: Box::new(PlainColorTexture {
+                color: Color::new(0.0, 0.0, 1.0),
+            }),
+            ..samples::sphere_object(Vec3::new(0.0, 0.0, 0.0), 4.0, effects)
+        };
+        let scene = samples::orthogonal_scene(vec![blue_sphere]);
+        let config = samples::small_config();
+        render_scene(scene, config)
+            .unwrap()
+            .map(|pixel| pixel.unwrap().color.red())
+            .sum()
+    };
+    let red_sheen = Sheen {
+        color: Color::new(1.0, 0.0, 0.0),
+        ..Default::default()
+    };
+    assert_eq!(red_of_sphere(None), 0.0);
+    assert!(red_of_sphere(Some(red_sheen)) > 0.0);
+}
+
+#[test]
+fn path_tracing_lights_surfaces_facing_away_from_lights() {
+    let scene_toml = |integrator: &str| {
+        format!(
+            r##"
+            [config]
+            integrator = "{}"
+            ambient_light = [0, 0, 0]
+
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 9
+
+            [[light]]
+            type = "directional"
+            direction = [1, -1, 0]
+            color = "white"
+
+            [[object]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 2
+            texture.type = "plain"
+
+            [[object]]
+            type = "infinite_plan"
+            center = [0, -2, 0]
+            normal = [0, 1, 0]
+            texture.type = "plain"
+            "##,
+            integrator
+        )
+    };
+    let brightness_under_sphere = |integrator: &str| {
+        let scene = Scene::from_str(&scene_toml(integrator)).unwrap();
+        let config = RenderConfiguration {
+            render_strategy: Box::new(RandomAntiAliasingRenderStrategy { rays_per_pixel: 32 }),
+            ..samples::small_config()
+        };
+        let pixel = render_scene(scene, config)
+            .unwrap()
+            .map(Result::unwrap)
+            .find(|p| p.x == 16 && p.y == 12)
+            .unwrap();
+        pixel.color.red() + pixel.color.green() + pixel.color.blue()
+    };
+    assert_eq!(brightness_under_sphere("whitted"), 0.0);
+    assert!(brightness_under_sphere("path") > 0.0);
+}
+
+#[test]
+fn ambient_occlusion_darkens_corners() {
+    let scene_toml = r##"
+        [config]
+        integrator = "ambient_occlusion"
+        ambient_occlusion.samples = 64
+        ambient_occlusion.distance = 3
+
+        [camera]
+        type = "orthogonal"
+        eye = [0, 0, -10]
+        look_at = [0, 0, 0]
+        width = 16
+        height = 9
+
+        [[object]]
+        type = "sphere"
+        center = [0, 0, 0]
+        radius = 2
+        texture.type = "plain"
+
+        [[object]]
+        type = "infinite_plan"
+        center = [0, 0, 2.1]
+        normal = [0, 0, -1]
+        texture.type = "plain"
+    "##;
+    let scene = Scene::from_str(scene_toml).unwrap();
+    let config = samples::small_config();
+    let pixels: Vec<_> = render_scene(scene, config)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let red_at = |x, y| {
+        pixels
+            .iter()
+            .find(|p| p.x == x && p.y == y)
+            .unwrap()
+            .color
+            .red()
+    };
+    assert_eq!(red_at(16, 9), 1.0);
+    assert!(red_at(20, 9) < 1.0);
+}
+
+#[test]
+fn emissive_volume_glows_and_lets_light_through() {
+    let scene_toml = r##"
+        [config]
+        ambient_light = [0, 0, 0]
+        world_color = [0, 0, 1]
+
+        [camera]
+        type = "orthogonal"
+        eye = [0, 0, -10]
+        look_at = [0, 0, 0]
+        width = 16
+        height = 9
+
+        [[light]]
+        type = "point"
+        source = [0, 0, -10]
+        color = "white"
+
+        [[object]]
+        type = "sphere"
+        center = [0, 0, 0]
+        radius = 3
+        texture.type = "plain"
+        effect.emission = { max_temperature = 2000, density = 0.2 }
+    "##;
+    let scene = Scene::from_str(scene_toml).unwrap();
+    let config = samples::small_config();
+    let pixels: Vec<_> = render_scene(scene, config)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let center = &pixels.iter().find(|p| p.x == 16 && p.y == 9).unwrap().color;
+    assert!(center.red() > center.green());
+    assert!(center.blue() > 0.0 && center.blue() < 1.0);
+}
+
+#[test]
+fn atmosphere_gives_a_blue_sky_above_the_ground() {
+    let scene_toml = r##"
+        [config.atmosphere]
+        sun_direction = [0, 1, 1]
+
+        [camera]
+        type = "perspective"
+        screen_center = [0, 0, 1]
+        look_at = [0, 0, 10]
+        width = 2
+        height = 2
+
+        [[light]]
+        type = "directional"
+        direction = [0, -1, -1]
+        color = "white"
+
+        [[object]]
+        type = "infinite_plan"
+        center = [0, -1, 0]
+        normal = [0, 1, 0]
+        texture.type = "plain"
+    "##;
+    let scene = Scene::from_str(scene_toml).unwrap();
+    let config = RenderConfiguration {
+        canvas_width: 8,
+        canvas_height: 8,
+        ..Default::default()
+    };
+    let pixels: Vec<_> = render_scene(scene, config)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let sky = &pixels.iter().find(|p| p.x == 4 && p.y == 0).unwrap().color;
+    assert!(sky.blue() > sky.red());
+    let ground = &pixels.iter().find(|p| p.x == 4 && p.y == 7).unwrap().color;
+    assert!(ground.red() > 0.0);
+}
+
+#[test]
+fn water_shows_the_bottom_and_reflects_the_sky() {
+    let scene_toml = r##"
+        [config]
+        world_color = [0, 0, 1]
+        ambient_light = [1, 1, 1]
+
+        [camera]
+        type = "orthogonal"
+        eye = [0, 10, -10]
+        look_at = [0, 0, 0]
+        width = 4
+        height = 4
+
+        [[light]]
+        type = "point"
+        source = [0, 10, -10]
+        color = "white"
+
+        [[object]]
+        type = "infinite_plan"
+        center = [0, 0, 0]
+        normal = [0, 1, 0]
+        texture.type = "plain"
+        texture.color = [0, 0, 0]
+        effect.water = { steepness = 0.1 }
+
+        [[object]]
+        type = "infinite_plan"
+        center = [0, -2, 0]
+        normal = [0, 1, 0]
+        texture.type = "plain"
+        texture.color = "red"
+    "##;
+    let scene = Scene::from_str(scene_toml).unwrap();
+    let config = RenderConfiguration {
+        canvas_width: 8,
+        canvas_height: 8,
+        ..Default::default()
+    };
+    let pixels: Vec<_> = render_scene(scene, config)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let center = &pixels.iter().find(|p| p.x == 4 && p.y == 4).unwrap().color;
+    assert!(center.red() > 0.0);
+    assert!(center.blue() > 0.0);
+}
+
+#[test]
+fn glass_presets_sample_refracts_and_totally_reflects() {
+    let scene_string = std::fs::read_to_string("../samples/glass_presets.toml").unwrap();
+    let scene = Scene::from_str(&scene_string).unwrap();
+    // Through the diamond, on the right of the image
+    let ray_tree = raytracer::debug::trace_pixel(&scene, 0.55, 0.45).unwrap();
+    let count = |kind| ray_tree.rays.iter().filter(|ray| ray.kind == kind).count();
+    assert!(count(RayKind::Refraction) >= 2);
+    assert!(count(RayKind::Reflection) >= 1);
+    let config = samples::small_config();
+    let pixels = render_scene(scene, config).unwrap();
+    assert_eq!(pixels.filter(Result::is_ok).count(), 32 * 18);
+}
+
+#[test]
+fn area_light_sends_one_shadow_ray_per_sample() {
+    let base_scene = samples::generate_test_scene();
+    let scene = Scene {
+        camera: Box::new(OrthogonalCamera::default()),
+        lights: vec![Box::new(LightRectangle::new(
+            Vec3::new(0.0, 20.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            10.0,
+            10.0,
+            Color::WHITE,
+        ))],
+        config: SceneConfiguration {
+            area_light_samples: 9,
+            maximum_light_recursion: 0,
+            ..Default::default()
+        },
+        ..base_scene
+    };
+    let ray_tree = raytracer::debug::trace_pixel(&scene, 0.5, 0.5).unwrap();
+    let shadow_rays: Vec<_> = ray_tree
+        .rays
+        .iter()
+        .filter(|ray| ray.kind == RayKind::Shadow)
+        .collect();
+    assert_eq!(shadow_rays.len(), 9);
+    assert!(shadow_rays[0].direction.distance(shadow_rays[1].direction) > 1e-6);
+}
+
+#[test]
+fn directional_light_shadow_rays_are_parallel() {
+    let direction = Vec3::new(1.0, -2.0, 0.5);
+    let scene = Scene {
+        camera: Box::new(OrthogonalCamera::default()),
+        lights: vec![Box::new(LightDirectional::new(direction, Color::WHITE))],
+        ..samples::generate_test_scene()
+    };
+    for &(x, y) in &[(0.5, 0.5), (0.48, 0.52), (0.53, 0.47)] {
+        let ray_tree = raytracer::debug::trace_pixel(&scene, x, y).unwrap();
+        let shadow_ray = ray_tree
+            .rays
+            .iter()
+            .find(|ray| ray.kind == RayKind::Shadow)
+            .expect("No shadow ray");
+        assert!(shadow_ray.direction.distance(-direction.normalize()) < 1e-9);
+    }
+}
+
+#[test]
+fn motion_vectors_follow_moved_objects() {
+    let scene_at = |position: &str| {
+        Scene::from_str(&format!(
+            r#"
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -10]
+            look_at = [0, 0, 0]
+            width = 16
+            height = 16
+
+            [[light]]
+            type = "point"
+            source = [0, 10, -10]
+            color = "white"
+
+            [[object]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 2
+            position = {}
+            texture.type = "plain"
+            texture.color = "red"
+            "#,
+            position
+        ))
+        .unwrap()
+    };
+    let scene = scene_at("[0, 0, 0]");
+    let next_scene = scene_at("[2, 1, 0]");
+    let motion = render_motion_vectors(&scene, &next_scene, 32, 32);
+    // Two pixels by unit, y going down on the canvas
+    let (dx, dy) = motion.at(16, 16);
+    assert!((dx - 4.0).abs() < 1e-6 && (dy + 2.0).abs() < 1e-6);
+    assert_eq!(motion.at(0, 0), (0.0, 0.0));
+    let still = render_motion_vectors(&scene, &scene, 32, 32);
+    assert!((0..32).all(|x| still.at(x, 16).0.abs() < 1e-9));
+}
+
+#[test]
+fn environment_capture_sees_all_around_the_eye() {
+    let scene = Scene::from_str(
+        r#"
+        [config]
+        world_color = [0, 0, 1]
+
+        [camera]
+        type = "orthogonal"
+        eye = [0, 0, -10]
+        look_at = [0, 0, 0]
+        width = 16
+        height = 16
+
+        [[light]]
+        type = "point"
+        source = [0, 0, 0]
+        color = "white"
+
+        [[object]]
+        type = "sphere"
+        center = [0, 0, 10]
+        radius = 3
+        texture.type = "plain"
+        texture.color = "red"
+        "#,
+    )
+    .unwrap();
+    let config = RenderConfiguration {
+        canvas_width: 64,
+        canvas_height: 32,
+        ..Default::default()
+    };
+    let map = render_environment(scene, Vec3::new(0.0, 0.0, 0.0), &config).unwrap();
+    let ahead = map.color_in(Vec3::new(0.0, 0.0, 1.0), 0.0);
+    assert!(ahead.red() > 0.5 && ahead.blue() < 0.1);
+    let behind = map.color_in(Vec3::new(0.0, 0.0, -1.0), 0.0);
+    assert!(behind.red() < 1e-9 && (behind.blue() - 1.0).abs() < 1e-9);
+    let read_back = parse_hdr(&map.to_hdr()).unwrap();
+    let ahead_read_back = read_back.color_in(Vec3::new(0.0, 0.0, 1.0), 0.0);
+    assert!((ahead_read_back.red() - ahead.red()).abs() < 0.01);
+}
+
+#[test]
+fn render_stats_count_the_work_of_each_pixel() {
+    let scene = Scene::from_str(
+        r#"
+        [camera]
+        type = "orthogonal"
+        eye = [0, 0, -10]
+        look_at = [0, 0, 0]
+        width = 4
+        height = 4
+
+        [[light]]
+        type = "point"
+        source = [0, 5, -5]
+        color = "white"
+
+        [[object]]
+        type = "triangle_mesh"
+        vertices = [[-10, -10, 0], [10, -10, 0], [0, 10, 0]]
+        triangles = [[0, 1, 2]]
+        texture.type = "plain"
+        texture.color = "white"
+        "#,
+    )
+    .unwrap();
+    let config = RenderConfiguration {
+        canvas_width: 8,
+        canvas_height: 8,
+        ..Default::default()
+    };
+    let mut pixels = render_scene(scene, config).unwrap();
+    assert_eq!(pixels.by_ref().count(), 64);
+    let stats = pixels.stats();
+    assert_eq!(stats.pixels, 64);
+    assert_eq!(stats.rays, 64);
+    // Every pixel sees the triangle, lit by the light
+    assert_eq!(stats.shadow_rays, 64);
+    assert!(stats.triangle_tests >= 128);
+    assert!(stats.bvh_node_visits >= 128);
+    assert_eq!(stats.phases.len(), 1);
+    assert_eq!(stats.phases[0].0, "Rendering");
+}
+
+#[test]
+fn progress_is_reported_until_every_tile_is_done() {
+    for &renderer in [RendererKind::Sequential, RendererKind::Tiled].iter() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let shared = reports.clone();
+        let config = RenderConfiguration {
+            canvas_width: 40,
+            canvas_height: 30,
+            renderer,
+            progress: Some(Box::new(move |progress: &RenderProgress| {
+                shared.lock().unwrap().push(progress.clone());
+            })),
+            ..Default::default()
+        };
+        render_scene(samples::generate_test_scene(), config)
+            .unwrap()
+            .count();
+        let reports = reports.lock().unwrap();
+        assert!(reports
+            .windows(2)
+            .all(|pair| pair[0].done_pixels < pair[1].done_pixels));
+        let last = reports.last().unwrap();
+        assert_eq!((last.percent(), last.done_pixels), (100, 1200));
+        // 3 by 2 tiles of 16 pixels
+        assert_eq!((last.done_tiles, last.total_tiles), (6, Some(6)));
+    }
+}
+
+#[test]
+fn cancelled_rendering_ends_early_with_its_status() {
+    let renderers = [
+        RendererKind::Sequential,
+        RendererKind::Threaded,
+        RendererKind::Tiled,
+        RendererKind::Progressive,
+    ];
+    for &renderer in renderers.iter() {
+        let cancellation = CancellationToken::default();
+        let config = RenderConfiguration {
+            renderer,
+            cancellation: cancellation.clone(),
+            ..Default::default()
+        };
+        let total = (config.canvas_width * config.canvas_height) as usize;
+        let mut pixels = render_scene(samples::generate_test_scene(), config).unwrap();
+        assert!(pixels.next().unwrap().is_ok());
+        cancellation.cancel();
+        let rest: Vec<_> = pixels.collect();
+        assert!(rest.len() < total, "{:?} was not cancelled", renderer);
+        assert!(matches!(rest.last(), Some(Err(RaytracerError::Cancelled))));
+        assert!(rest[..rest.len() - 1].iter().all(|pixel| pixel.is_ok()));
+    }
+}
+
+#[test]
+fn thumbnail_reframes_a_camera_seeing_no_object() {
+    let scene = Scene::from_str(
+        r#"
+        [config]
+        world_color = [0, 0, 1]
+
+        [camera]
+        type = "perspective"
+        screen_center = [0, 0, 0]
+        look_at = [0, 0, 1]
+        width = 16
+        height = 9
+
+        [[light]]
+        type = "point"
+        source = [0, 50, -50]
+        color = "white"
+
+        [[object]]
+        type = "sphere"
+        center = [0, 0, -20]
+        radius = 2
+        texture.type = "plain"
+        texture.color = "red"
+        "#,
+    )
+    .unwrap();
+    let frame = render_thumbnail(scene, 128).unwrap();
+    assert_eq!((frame.width(), frame.height()), (128, 72));
+    // The sphere behind the camera is in the middle of the view, with some sky around it
+    let corner = frame.pixel(0, 0).color.clone();
+    assert!(corner.blue() > 0.5 && corner.red() < 0.1, "{:?}", corner);
+    let center = frame.pixel(64, 36).color.clone();
+    assert!(center.red() > 0.5, "{:?}", center);
+}
+
+#[test]
+fn tiles_rendered_apart_match_the_whole_rendering() {
+    let config = RenderConfiguration {
+        canvas_width: 40,
+        canvas_height: 30,
+        renderer: RendererKind::Sequential,
+        ..Default::default()
+    };
+    let scene = samples::generate_test_scene();
+    let mut tile_pixels = Vec::new();
+    for tile in config.tiles() {
+        let pixels = render_tile(&scene, &config, tile).unwrap();
+        assert_eq!(pixels.len(), tile.pixel_count());
+        tile_pixels.extend(pixels);
+    }
+    let whole_frame = Frame::from_pixels(40, 30, render_scene(scene, config).unwrap()).unwrap();
+    assert_eq!(tile_pixels.len(), 1200);
+    for pixel in tile_pixels {
+        let whole_pixel = whole_frame.pixel(pixel.x, pixel.y);
+        assert_eq!(pixel.color.to_rgb8(true), whole_pixel.color.to_rgb8(true));
+    }
+}
+
+#[test]
+fn render_service_starts_the_jobs_of_higher_priority_first() {
+    let service = RenderService::new(1);
+    let started = Arc::new(Mutex::new(Vec::new()));
+    let job = |name: &'static str, priority: i32| {
+        let started = started.clone();
+        RenderJob {
+            name: String::from(name),
+            priority,
+            scene: samples::generate_test_scene(),
+            config: RenderConfiguration {
+                canvas_width: 64,
+                canvas_height: 36,
+                progress: Some(Box::new(move |_: &RenderProgress| {
+                    let mut started = started.lock().unwrap();
+                    if !started.contains(&name) {
+                        started.push(name);
+                    }
+                })),
+                ..Default::default()
+            },
+        }
+    };
+    let first = service.submit(job("first", 0));
+    let low = service.submit(job("low", 0));
+    let high = service.submit(job("high", 5));
+    for &id in [first, low, high].iter() {
+        assert!(matches!(service.wait(id), Some(JobStatus::Done(_))));
+    }
+    let started = started.lock().unwrap();
+    let position = |name| started.iter().position(|started| *started == name).unwrap();
+    assert!(position("high") < position("low"));
+    let frame = service.take_frame(high).unwrap();
+    assert_eq!((frame.width(), frame.height()), (64, 36));
+    assert!(service.take_frame(high).is_none());
+    assert_eq!(service.reports().len(), 3);
+    assert!(!service.cancel(low));
+    service.shutdown();
+}
+
+#[test]
+fn render_service_cancels_queued_and_rendering_jobs() {
+    let service = RenderService::new(1);
+    let job = || RenderJob {
+        name: String::from("job"),
+        priority: 0,
+        scene: samples::generate_test_scene(),
+        config: Default::default(),
+    };
+    let rendering = service.submit(job());
+    let queued = service.submit(job());
+    assert!(service.cancel(queued));
+    assert_eq!(service.status(queued), Some(JobStatus::Cancelled));
+    assert!(service.cancel(rendering));
+    assert_eq!(service.wait(rendering), Some(JobStatus::Cancelled));
+    assert!(service.take_frame(rendering).is_none());
+    assert_eq!(service.status(42), None);
+}
+
+#[test]
+fn composited_transparency_sees_through_more_layers_than_the_recursion() {
+    let scene_toml = |transparency: &str, maximum_light_recursion: u8| {
+        let mut description = format!(
+            r#"
+            [config]
+            transparency = "{}"
+            maximum_light_recursion = {}
+
+            [camera]
+            type = "orthogonal"
+            eye = [0, 0, -20]
+            look_at = [0, 0, 0]
+            width = 4
+            height = 4
+
+            [[light]]
+            type = "directional"
+            direction = [0, -1, 1]
+            color = "white"
+
+            [[object]]
+            type = "sphere"
+            center = [0, 0, 12]
+            radius = 1
+            texture.type = "plain"
+            texture.color = "red"
+            "#,
+            transparency, maximum_light_recursion
+        );
+        for z in 0..4 {
+            description += &format!(
+                r#"
+                [[object]]
+                type = "sphere"
+                center = [0, 0, {}]
+                radius = 1
+                texture.type = "plain"
+                texture.color = [0.1, 0.1, 0.1]
+                effect.transparency = {{ alpha = 0.8, refractive_index = 1 }}
+                "#,
+                2 * z
+            );
+        }
+        Scene::from_str(&description).unwrap()
+    };
+    let center_color = |scene: Scene| {
+        let config = RenderConfiguration {
+            canvas_width: 9,
+            canvas_height: 9,
+            ..Default::default()
+        };
+        let frame = Frame::from_pixels(9, 9, render_scene(scene, config).unwrap()).unwrap();
+        frame.pixel(4, 4).color.clone()
+    };
+    // With refractive indices of 1, compositing gives the light of the refracted rays
+    let refracted = center_color(scene_toml("refracted", 20));
+    let composited = center_color(scene_toml("composited", 20));
+    assert!((refracted.red() - composited.red()).abs() < 1e-6);
+    assert!((refracted.green() - composited.green()).abs() < 1e-6);
+    // But without the recursion needed to see through every sphere
+    let refracted = center_color(scene_toml("refracted", 2));
+    let composited = center_color(scene_toml("composited", 2));
+    assert!(composited.red() > refracted.red() + 0.1);
+}
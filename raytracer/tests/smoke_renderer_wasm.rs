@@ -30,18 +30,28 @@ mod samples;
 
 extern crate wasm_bindgen_test;
 use wasm_bindgen_test::*;
-use raytracer::renderer::RenderConfiguration;
 use raytracer::wasm::JsConfig;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use wasm_bindgen::JsValue;
 
 
 wasm_bindgen_test_configure!(run_in_browser);
 
+fn to_js<T: Serialize>(value: &T) -> JsValue {
+    js_sys::JSON::parse(&serde_json::to_string(value).unwrap()).unwrap()
+}
+
+fn from_js<T: DeserializeOwned>(value: &JsValue) -> T {
+    let json: String = js_sys::JSON::stringify(value).unwrap().into();
+    serde_json::from_str(&json).unwrap()
+}
+
 #[wasm_bindgen_test]
 fn smoke_wasm_rendering() {
     let scene_toml = include_str!("samples/ok_basic.toml");
-    let config = JsValue::from_serde(&<JsConfig as Default>::default()).unwrap();
-    let mut renderer = raytracer::wasm::Renderer::new(&scene_toml, config).unwrap();
+    let config = to_js(&<JsConfig as Default>::default());
+    let mut renderer = raytracer::wasm::Renderer::new(scene_toml, config).unwrap();
     let expected_count = (renderer.width() * renderer.height()) as usize;
     let count = {
         let mut i = 0;
@@ -54,4 +64,74 @@ fn smoke_wasm_rendering() {
         i
     };
     assert_eq!(count, expected_count);
-}
\ No newline at end of file
+}
+
+#[wasm_bindgen_test]
+fn canvas_beyond_the_wasm_memory_is_refused() {
+    let scene_toml = include_str!("samples/ok_basic.toml");
+    let js_config = JsConfig {
+        canvas_width: 100_000,
+        ..Default::default()
+    };
+    let config = || to_js(&js_config);
+    let estimate = raytracer::wasm::estimate_memory(scene_toml, config()).unwrap();
+    let estimate: serde_json::Value = from_js(&estimate);
+    assert_eq!(estimate["fits"], false);
+    let error = raytracer::wasm::Renderer::new(scene_toml, config())
+        .err()
+        .unwrap();
+    let error: serde_json::Value = from_js(&error);
+    assert_eq!(error["kind"], "memory_limit");
+}
+
+#[wasm_bindgen_test]
+fn pixels_are_rendered_many_per_call() {
+    let scene_toml = include_str!("samples/ok_basic.toml");
+    let config = to_js(&<JsConfig as Default>::default());
+    let mut renderer = raytracer::wasm::Renderer::new(scene_toml, config).unwrap();
+    let expected_count = renderer.width() * renderer.height();
+    let mut count = renderer.next_n(100);
+    assert_eq!(count, 100);
+    while !renderer.is_finished() {
+        count += renderer.render_for_ms(5.0);
+    }
+    assert_eq!(count, expected_count);
+}
+
+#[wasm_bindgen_test]
+fn tile_workers_render_every_pixel_once() {
+    let scene_toml = include_str!("samples/ok_basic.toml");
+    let config = to_js(&<JsConfig as Default>::default());
+    let worker = raytracer::wasm::TileWorker::new(scene_toml, config).unwrap();
+    let tile_count = worker.tile_count();
+    let covered_pixels: u32 = (0..tile_count)
+        .map(|index| {
+            let bounds = worker.tile_bounds(index).unwrap();
+            bounds[2] * bounds[3]
+        })
+        .sum();
+    assert_eq!(covered_pixels, worker.width() * worker.height());
+    let buffer = worker.render_tiles(0, tile_count.min(2)).unwrap();
+    let first_bounds = worker.tile_bounds(0).unwrap();
+    assert!(buffer.len() as u32 >= 4 * first_bounds[2] * first_bounds[3]);
+    assert!(worker.render_tiles(tile_count, tile_count + 1).is_err());
+}
+
+#[wasm_bindgen_test]
+fn scene_errors_are_given_with_their_line_and_field() {
+    let scene_toml = include_str!("samples/ok_basic.toml");
+    let errors: Vec<serde_json::Value> = from_js(&raytracer::wasm::validate_scene(scene_toml));
+    assert!(errors.is_empty());
+    let broken_toml = "[camera]\ntype = \"perspective\"\nscreen_center = [0, 0, \"far\"]\n";
+    let errors: Vec<serde_json::Value> = from_js(&raytracer::wasm::validate_scene(broken_toml));
+    assert!(!errors.is_empty());
+    assert!(errors[0]["message"].is_string());
+    assert!(errors[0]["line"].as_u64().is_some());
+    let config = to_js(&<JsConfig as Default>::default());
+    let error = raytracer::wasm::Renderer::new(broken_toml, config)
+        .err()
+        .unwrap();
+    let error: serde_json::Value = from_js(&error);
+    assert_eq!(error["kind"], "invalid_scene");
+    assert!(!error["errors"].as_array().unwrap().is_empty());
+}
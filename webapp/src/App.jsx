@@ -29,6 +29,7 @@ import { Layout, Input, message, notification } from 'antd';
 import { TitleBar } from "./TitleBar";
 import { Config } from "./Config";
 import { Renderer } from "./Renderer";
+import raytracer from '../../raytracer/Cargo.toml';
 
 const { Header, Footer, Sider, Content } = Layout;
 const { TextArea } = Input;
@@ -43,6 +44,7 @@ export class App extends React.Component {
     this.state = {
       showConfigPanel: false,
       sceneDescription: sample_scene,
+      sceneErrors: [],
       percentProgression: 0,
       isRendering: false,
       config: null,
@@ -67,10 +69,23 @@ export class App extends React.Component {
     this.onEditorChange = (e) => {
       e.persist();
       this.setState(state => ({
-        sceneDescription: e.target.value
+        sceneDescription: e.target.value,
+        sceneErrors: raytracer.validate_scene(e.target.value),
       }));
     }
 
+    // Put the cursor of the editor at the start of the line of an error
+    this.onSceneErrorClick = (error) => {
+      if (!error.line) {
+        return;
+      }
+      const editor = document.getElementsByClassName("editor")[0];
+      const lines = this.state.sceneDescription.split("\n");
+      const offset = lines.slice(0, error.line - 1).reduce((sum, line) => sum + line.length + 1, 0);
+      editor.focus();
+      editor.setSelectionRange(offset, offset + lines[error.line - 1].length);
+    }
+
     this.onRenderingError = (msg) => {
       this.openNotification(msg);
     }
@@ -127,6 +142,17 @@ export class App extends React.Component {
                   autoSize={false}
                   value={this.state.sceneDescription}
                   onChange={this.onEditorChange} />
+                {this.state.sceneErrors.length > 0 &&
+                  <ul className="editor__errors">
+                    {this.state.sceneErrors.map((error, index) =>
+                      <li key={index} onClick={() => this.onSceneErrorClick(error)}>
+                        {error.line ? `Line ${error.line}: ` : ""}
+                        {error.message}
+                        {error.field ? ` (${error.field})` : ""}
+                      </li>
+                    )}
+                  </ul>
+                }
               </div>
               <Renderer
                 ref={this.rendererRef}
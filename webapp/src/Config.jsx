@@ -26,7 +26,7 @@ SOFTWARE.
 
 import React from 'react';
 import ReactDOM from 'react-dom';
-import { Input, Form, Select, InputNumber } from 'antd';
+import { Input, Form, Select, InputNumber, Switch } from 'antd';
 
 export class Config extends React.Component {
 
@@ -41,6 +41,9 @@ export class Config extends React.Component {
       canvas_width: this.state.canvas_width,
       strategy: this.state.strategy,
       ray_number: this.state.ray_number,
+      progressive: this.state.progressive,
+      time: this.state.time,
+      workers: this.state.workers,
     }
     this.props.onConfigChange(config);
   }
@@ -52,6 +55,9 @@ export class Config extends React.Component {
       strategy: "normal",
       ray_number: 50,
       canvas_width: 1024,
+      progressive: false,
+      time: null,
+      workers: false,
     };
 
     this.onStrategySelect = (strategy) => {
@@ -71,6 +77,27 @@ export class Config extends React.Component {
       this.updateConfig);
     };
 
+    this.onProgressiveChange = (progressive) => {
+      this.setState(state => ({
+        progressive
+      }),
+      this.updateConfig);
+    };
+
+    this.onWorkersChange = (workers) => {
+      this.setState(state => ({
+        workers
+      }),
+      this.updateConfig);
+    };
+
+    this.onTimeChange = (time) => {
+      this.setState(state => ({
+        time: typeof time === "number" ? time : null
+      }),
+      this.updateConfig);
+    };
+
     this.onCanvasWidthChange = (canvas_width) => {
       if (!canvas_width) {
         return;
@@ -102,6 +129,15 @@ export class Config extends React.Component {
             <InputNumber min={1} value={this.state.ray_number} onChange={this.onRayNumberChange} />
           </Form.Item>
         }
+        <Form.Item className="config__item" label="Coarse image first, then refined">
+          <Switch checked={this.state.progressive} onChange={this.onProgressiveChange} />
+        </Form.Item>
+        <Form.Item className="config__item" label="Tiles shared between web workers, one per core">
+          <Switch checked={this.state.workers} onChange={this.onWorkersChange} />
+        </Form.Item>
+        <Form.Item className="config__item" label="Time in seconds, the one of the frame if empty">
+          <InputNumber step={0.1} value={this.state.time} onChange={this.onTimeChange} />
+        </Form.Item>
 
       </Form>
     );
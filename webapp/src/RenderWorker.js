@@ -0,0 +1,48 @@
+/*
+MIT License
+
+Copyright (c) 2020 Vincent Hiribarren
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+
+// Web worker rendering the ranges of tiles the page gives it, each worker having its own
+// wasm module so that a rendering uses every core of the browser
+
+import raytracer from '../../raytracer/Cargo.toml';
+
+let worker = null;
+
+self.onmessage = (event) => {
+  const message = event.data;
+  try {
+    if (message.type === "init") {
+      raytracer.wasm_init();
+      worker = raytracer.TileWorker.new(message.sceneDescription, message.config);
+      self.postMessage({type: "ready"});
+    }
+    else if (message.type === "render") {
+      const pixels = worker.render_tiles(message.start, message.end);
+      self.postMessage({type: "tiles", start: message.start, end: message.end, pixels}, [pixels.buffer]);
+    }
+  }
+  catch(err) {
+    self.postMessage({type: "error", message: err.message || err});
+  }
+};
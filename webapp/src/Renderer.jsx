@@ -33,8 +33,6 @@ export class Renderer extends React.Component {
     raytracer.wasm_init();
     this.shouldStop = false;
     this.progressPercent = 0;
-    this.progressCurrent = 0;
-    this.progressMax = 0;
   }
 
   static get defaultProps() {
@@ -50,11 +48,19 @@ export class Renderer extends React.Component {
 
   stop() {
     this.shouldStop = true;
+    if (this.workers) {
+      this.endWorkers();
+      this.props.onChange(false);
+    }
+  }
+
+  endWorkers() {
+    this.workers.forEach(worker => worker.terminate());
+    this.workers = null;
   }
 
-  updateProgression() {
-    this.progressCurrent += 1;
-    const percentProgress = Math.trunc((100*(this.progressCurrent/this.progressMax)));
+  updateProgression(renderer) {
+    const percentProgress = renderer.progress_percent();
     if (percentProgress != this.progressPercent) {
       this.progressPercent = percentProgress;
       this.props.onPercentProgression(percentProgress);
@@ -64,18 +70,20 @@ export class Renderer extends React.Component {
   renderScene(sceneDescription, config) {
     console.log("Config for rendering: ", config);
     this.shouldStop = false;
+    if (config && config.workers && window.Worker) {
+      this.renderSceneInWorkers(sceneDescription, config);
+      return;
+    }
     let renderer;
     try {
       renderer = raytracer.Renderer.new(sceneDescription, config ? config : {});
     }
     catch(err) {
       console.log(err);
-      this.props.onError(err);
+      this.props.onError(err.message || err);
       return;
     }
-    this.progressMax = renderer.width()*renderer.height();
     this.progressPercent = 0;
-    this.progressCurrent = 0;
     const canvas_width = renderer.width();
     const canvas_height = renderer.height();
     const video_buffer_size = canvas_width * canvas_height * 4;
@@ -93,18 +101,16 @@ export class Renderer extends React.Component {
     }
 
     const renderLoop = () => {
-      const loop_start = Date.now()
-      let hasNext;
-      while (hasNext = renderer.next()) {
-        this.updateProgression();
-        if (Date.now() - loop_start > 20) {
-          if (this.shouldStop) {
-            hasNext = false;
-          }
-          else {
-            requestAnimationFrame(renderLoop);
-          }
-          break;
+      renderer.render_for_ms(20);
+      this.updateProgression(renderer);
+      let hasNext = !renderer.is_finished();
+      if (hasNext) {
+        if (this.shouldStop) {
+          renderer.cancel();
+          hasNext = false;
+        }
+        else {
+          requestAnimationFrame(renderLoop);
         }
       }
       if (!hasNext) {
@@ -119,6 +125,84 @@ export class Renderer extends React.Component {
     renderLoop();
   }
 
+  // Tiles shared between a web worker per core, each worker being given the next tile as
+  // soon as it sends back the pixels of the previous one
+  renderSceneInWorkers(sceneDescription, config) {
+    let tiles;
+    try {
+      tiles = raytracer.TileWorker.new(sceneDescription, config);
+    }
+    catch(err) {
+      console.log(err);
+      this.props.onError(err.message || err);
+      return;
+    }
+    this.progressPercent = 0;
+    const canvas = document.getElementById("canvas");
+    canvas.width = tiles.width();
+    canvas.height = tiles.height();
+    const ctx = canvas.getContext('2d');
+    const tileCount = tiles.tile_count();
+    const tileBounds = [...Array(tileCount).keys()].map(index => tiles.tile_bounds(index));
+    tiles.free();
+
+    let nextTile = 0;
+    let doneTiles = 0;
+    const startDate = Date.now();
+    const giveNextTile = (worker) => {
+      if (nextTile < tileCount) {
+        worker.postMessage({type: "render", start: nextTile, end: nextTile + 1});
+        nextTile += 1;
+      }
+    };
+    const drawTiles = (start, end, pixels) => {
+      let offset = 0;
+      for (let index = start; index < end; index++) {
+        const [x, y, width, height] = tileBounds[index];
+        const size = width * height * 4;
+        const data = new Uint8ClampedArray(pixels.buffer, pixels.byteOffset + offset, size);
+        ctx.putImageData(new ImageData(data, width, height), x, y);
+        offset += size;
+      }
+    };
+    const onMessage = (worker, message) => {
+      if (!this.workers) {
+        return;
+      }
+      if (message.type === "error") {
+        this.endWorkers();
+        this.props.onError(message.message);
+        this.props.onChange(false);
+        return;
+      }
+      if (message.type === "tiles") {
+        drawTiles(message.start, message.end, message.pixels);
+        doneTiles += message.end - message.start;
+        const percentProgress = Math.floor(100 * doneTiles / tileCount);
+        if (percentProgress != this.progressPercent) {
+          this.progressPercent = percentProgress;
+          this.props.onPercentProgression(percentProgress);
+        }
+        if (doneTiles === tileCount) {
+          this.endWorkers();
+          this.props.onSuccess(Date.now() - startDate);
+          this.props.onChange(false);
+          return;
+        }
+      }
+      giveNextTile(worker);
+    };
+
+    const workerCount = Math.min(navigator.hardwareConcurrency || 4, tileCount);
+    this.workers = [...Array(workerCount).keys()].map(() => {
+      const worker = new Worker("./RenderWorker.js");
+      worker.onmessage = (event) => onMessage(worker, event.data);
+      worker.postMessage({type: "init", sceneDescription, config});
+      return worker;
+    });
+    this.props.onChange(true);
+  }
+
   render() {
     return (
       <div className="renderer">